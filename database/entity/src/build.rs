@@ -12,6 +12,12 @@ pub struct Model {
     pub commit_sha: String,
     pub status: String,
     pub created_at: DateTime,
+    pub attempt: i32,
+    pub retry_at: Option<DateTime>,
+    pub pr_head_sha: Option<String>,
+    pub parent_sha: Option<String>,
+    pub pr_commit_sha: Option<String>,
+    pub jobs: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,6 +2,11 @@
 
 pub mod prelude;
 
+pub mod api_token;
 pub mod build;
+pub mod event_log;
+pub mod job_log;
 pub mod pull_request;
+pub mod repository;
+pub mod webhook_delivery;
 pub mod workflow;
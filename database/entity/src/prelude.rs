@@ -1,5 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
+pub use super::api_token::Entity as ApiToken;
 pub use super::build::Entity as Build;
+pub use super::event_log::Entity as EventLog;
+pub use super::job_log::Entity as JobLog;
 pub use super::pull_request::Entity as PullRequest;
+pub use super::repository::Entity as Repository;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
 pub use super::workflow::Entity as Workflow;
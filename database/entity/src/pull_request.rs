@@ -11,6 +11,15 @@ pub struct Model {
     pub number: i32,
     pub try_build: Option<i32>,
     pub created_at: DateTime,
+    pub status_comment: Option<String>,
+    pub approved_by: Option<String>,
+    pub summary_comment: Option<String>,
+    pub approved_at: Option<DateTime>,
+    pub priority: i32,
+    pub rollup: Option<String>,
+    pub delegated_to: Option<String>,
+    pub updated_at: Option<DateTime>,
+    pub approved_sha: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -14,6 +14,8 @@ pub struct Model {
     pub status: String,
     pub r#type: String,
     pub created_at: DateTime,
+    pub finished_at: Option<DateTime>,
+    pub artifacts: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -3,6 +3,26 @@ pub use sea_orm_migration::prelude::*;
 mod m20230505_165859_create_build;
 mod m20230506_075859_create_pr;
 mod m20230506_102008_create_workflow;
+mod m20230604_091512_add_pr_status_comment;
+mod m20230611_120000_add_pr_approval;
+mod m20230618_090000_add_pr_summary_comment;
+mod m20230625_100000_add_workflow_finished_at;
+mod m20230702_100000_add_pr_state;
+mod m20230709_100000_create_event_log;
+mod m20230716_100000_add_pr_queue_claim;
+mod m20230723_100000_create_job_log;
+mod m20230730_100000_add_build_retry;
+mod m20230806_100000_create_api_token;
+mod m20230813_100000_create_repository;
+mod m20230820_100000_create_webhook_delivery;
+mod m20230827_100000_add_workflow_artifacts;
+mod m20230903_100000_add_repository_pause;
+mod m20230910_100000_add_repository_lease;
+mod m20230917_100000_add_pr_approved_sha;
+mod m20230924_100000_drop_pr_queue_claim;
+mod m20231001_100000_add_api_token_repositories;
+mod m20231008_100000_add_event_log_kind;
+mod m20231015_100000_add_build_arguments;
 
 pub struct Migrator;
 
@@ -13,6 +33,26 @@ impl MigratorTrait for Migrator {
             Box::new(m20230505_165859_create_build::Migration),
             Box::new(m20230506_075859_create_pr::Migration),
             Box::new(m20230506_102008_create_workflow::Migration),
+            Box::new(m20230604_091512_add_pr_status_comment::Migration),
+            Box::new(m20230611_120000_add_pr_approval::Migration),
+            Box::new(m20230618_090000_add_pr_summary_comment::Migration),
+            Box::new(m20230625_100000_add_workflow_finished_at::Migration),
+            Box::new(m20230702_100000_add_pr_state::Migration),
+            Box::new(m20230709_100000_create_event_log::Migration),
+            Box::new(m20230716_100000_add_pr_queue_claim::Migration),
+            Box::new(m20230723_100000_create_job_log::Migration),
+            Box::new(m20230730_100000_add_build_retry::Migration),
+            Box::new(m20230806_100000_create_api_token::Migration),
+            Box::new(m20230813_100000_create_repository::Migration),
+            Box::new(m20230820_100000_create_webhook_delivery::Migration),
+            Box::new(m20230827_100000_add_workflow_artifacts::Migration),
+            Box::new(m20230903_100000_add_repository_pause::Migration),
+            Box::new(m20230910_100000_add_repository_lease::Migration),
+            Box::new(m20230917_100000_add_pr_approved_sha::Migration),
+            Box::new(m20230924_100000_drop_pr_queue_claim::Migration),
+            Box::new(m20231001_100000_add_api_token_repositories::Migration),
+            Box::new(m20231008_100000_add_event_log_kind::Migration),
+            Box::new(m20231015_100000_add_build_arguments::Migration),
         ]
     }
 }
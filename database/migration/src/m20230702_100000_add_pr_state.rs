@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single column change per `ALTER TABLE` statement
+        for column in [
+            ColumnDef::new(PullRequest::ApprovedAt)
+                .timestamp()
+                .null()
+                .to_owned(),
+            ColumnDef::new(PullRequest::Priority)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+            ColumnDef::new(PullRequest::Rollup)
+                .string()
+                .null()
+                .to_owned(),
+            ColumnDef::new(PullRequest::DelegatedTo)
+                .string()
+                .null()
+                .to_owned(),
+            ColumnDef::new(PullRequest::UpdatedAt)
+                .timestamp()
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PullRequest::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            PullRequest::ApprovedAt,
+            PullRequest::Priority,
+            PullRequest::Rollup,
+            PullRequest::DelegatedTo,
+            PullRequest::UpdatedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PullRequest::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum PullRequest {
+    Table,
+    ApprovedAt,
+    Priority,
+    Rollup,
+    DelegatedTo,
+    UpdatedAt,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EventLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EventLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(EventLog::Repository).string().not_null())
+                    .col(ColumnDef::new(EventLog::PrNumber).integer().null())
+                    .col(ColumnDef::new(EventLog::Data).text().not_null())
+                    .col(
+                        ColumnDef::new(EventLog::CreatedAt)
+                            .timestamp()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("index-event-log-repo-pr")
+                    .table(EventLog::Table)
+                    .col(EventLog::Repository)
+                    .col(EventLog::PrNumber)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EventLog::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum EventLog {
+    Table,
+    Id,
+    Repository,
+    PrNumber,
+    Data,
+    CreatedAt,
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(JobLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(JobLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(JobLog::Repository).string().not_null())
+                    .col(ColumnDef::new(JobLog::RunId).big_unsigned().not_null())
+                    .col(ColumnDef::new(JobLog::JobName).string().not_null())
+                    .col(ColumnDef::new(JobLog::Excerpt).text().not_null())
+                    .col(
+                        ColumnDef::new(JobLog::CreatedAt)
+                            .timestamp()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(JobLog::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum JobLog {
+    Table,
+    Id,
+    Repository,
+    RunId,
+    JobName,
+    Excerpt,
+    CreatedAt,
+}
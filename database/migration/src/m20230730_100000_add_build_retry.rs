@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single column change per `ALTER TABLE` statement
+        for column in [
+            ColumnDef::new(Build::Attempt)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+            ColumnDef::new(Build::RetryAt).timestamp().null().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Build::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [Build::Attempt, Build::RetryAt] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Build::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Build {
+    Table,
+    Attempt,
+    RetryAt,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiToken::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(ApiToken::TokenHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ApiToken::Scopes).string().not_null())
+                    .col(
+                        ColumnDef::new(ApiToken::CreatedAt)
+                            .timestamp()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiToken::ExpiresAt).timestamp().null())
+                    .col(ColumnDef::new(ApiToken::RevokedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum ApiToken {
+    Table,
+    Id,
+    Name,
+    TokenHash,
+    Scopes,
+    CreatedAt,
+    ExpiresAt,
+    RevokedAt,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Repository::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Repository::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Repository::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Repository::TreeClosedPriority)
+                            .integer()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(Repository::TreeClosedBy).string().null())
+                    .col(ColumnDef::new(Repository::TreeClosedReason).text().null())
+                    .col(ColumnDef::new(Repository::TreeClosedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Repository::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Repository {
+    Table,
+    Id,
+    Name,
+    TreeClosedPriority,
+    TreeClosedBy,
+    TreeClosedReason,
+    TreeClosedAt,
+}
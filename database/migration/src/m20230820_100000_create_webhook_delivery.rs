@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::DeliveryId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::EventType)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::PayloadHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::Payload).text().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::Result).string().not_null())
+                    .col(
+                        ColumnDef::new(WebhookDelivery::ReceivedAt)
+                            .timestamp()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("webhook_delivery_delivery_id")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::DeliveryId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    DeliveryId,
+    EventType,
+    PayloadHash,
+    Payload,
+    Result,
+    ReceivedAt,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single column change per `ALTER TABLE` statement
+        for column in [
+            ColumnDef::new(Repository::PausedBy)
+                .string()
+                .null()
+                .to_owned(),
+            ColumnDef::new(Repository::PauseReason)
+                .text()
+                .null()
+                .to_owned(),
+            ColumnDef::new(Repository::PausedAt)
+                .timestamp()
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Repository::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Repository::PausedBy,
+            Repository::PauseReason,
+            Repository::PausedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Repository::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Repository {
+    Table,
+    PausedBy,
+    PauseReason,
+    PausedAt,
+}
@@ -0,0 +1,94 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Kinds of events that were logged before the kind was stored in its own column.
+const KINDS: &[&str] = &[
+    "command_received",
+    "approved",
+    "unapproved",
+    "priority_changed",
+    "rollup_changed",
+    "delegation_changed",
+    "build_started",
+    "workflow_completed",
+    "build_succeeded",
+    "build_failed",
+    "build_cancelled",
+    "build_timed_out",
+    "merged",
+    "digest_posted",
+    "stale_reminder",
+    "tree_closed",
+    "tree_opened",
+    "paused",
+    "resumed",
+    "duration_regression_reported",
+    "permission_changed",
+    "delegation_revoked",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventLog::Table)
+                    .add_column(ColumnDef::new(EventLog::Kind).string().null())
+                    .to_owned(),
+            )
+            .await?;
+        // The kind is the tag of the serialized event, which is its first field
+        for kind in KINDS {
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(EventLog::Table)
+                        .value(EventLog::Kind, *kind)
+                        .and_where(Expr::col(EventLog::Data).like(format!(r#"{{"type":"{kind}"%"#)))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("index-event-log-repo-kind")
+                    .table(EventLog::Table)
+                    .col(EventLog::Repository)
+                    .col(EventLog::Kind)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("index-event-log-repo-kind")
+                    .table(EventLog::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventLog::Table)
+                    .drop_column(EventLog::Kind)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum EventLog {
+    Table,
+    Repository,
+    Data,
+    Kind,
+}
@@ -0,0 +1,58 @@
+use axum::async_trait;
+use std::time::Duration;
+
+use crate::config::AgreementConfig;
+use crate::http::SendLimited;
+
+/// Decides if a GitHub user has signed the contributor agreement (e.g. a CLA) required by
+/// a repository.
+#[async_trait]
+pub trait AgreementChecker {
+    async fn has_signed(&self, username: &str) -> anyhow::Result<bool>;
+}
+
+/// How long should we wait for the agreement service to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Queries an external agreement service over HTTP.
+///
+/// The service receives a `GET {url}?user={username}` request and it should respond with
+/// a JSON object `{"signed": <bool>}`.
+pub struct ServiceAgreementChecker {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl ServiceAgreementChecker {
+    pub fn new(config: &AgreementConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            client: crate::http::client(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct AgreementResponse {
+    signed: bool,
+}
+
+#[async_trait]
+impl AgreementChecker for ServiceAgreementChecker {
+    async fn has_signed(&self, username: &str) -> anyhow::Result<bool> {
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[("user", username)])
+            .timeout(REQUEST_TIMEOUT)
+            .send_limited()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let response = serde_json::from_str::<AgreementResponse>(&response).map_err(|error| {
+            anyhow::anyhow!("Cannot parse agreement service response {response}: {error:?}")
+        })?;
+        Ok(response.signed)
+    }
+}
@@ -0,0 +1,257 @@
+//! JSON API that exposes the state of the bot stored in the database to external tools
+//! (e.g. dashboards or release scripts).
+use chrono::{DateTime, Utc};
+
+use crate::dashboard::{build_status_name, load_queue, QueueEntry};
+use crate::database::{BuildModel, DbClient, PrimaryKey, PullRequestModel, WorkflowStatus};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ApiQueueEntry {
+    pub number: u64,
+    pub status: &'static str,
+    pub priority: u32,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub try_build: Option<&'static str>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ApiRepository {
+    pub name: String,
+    pub paused: bool,
+    /// Replica that currently drives the repository, if several replicas share the database.
+    pub leader: Option<String>,
+    pub queue: Vec<ApiQueueEntry>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ApiPullRequest {
+    pub number: u64,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub priority: u32,
+    pub delegated_to: Option<String>,
+    pub try_build: Option<ApiBuild>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ApiBuild {
+    pub id: PrimaryKey,
+    pub branch: String,
+    pub commit_sha: String,
+    pub status: &'static str,
+    pub created_at: DateTime<Utc>,
+    pub attempt: u32,
+    pub workflows: Vec<ApiWorkflow>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct ApiWorkflow {
+    pub name: String,
+    pub url: String,
+    pub run_id: u64,
+    pub status: &'static str,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub artifacts: Vec<String>,
+}
+
+/// Returns the merge queue of the repository, see [`load_queue`].
+pub async fn get_queue(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+) -> anyhow::Result<Vec<ApiQueueEntry>> {
+    Ok(load_queue(db, repo)
+        .await?
+        .into_iter()
+        .map(queue_entry_to_api)
+        .collect())
+}
+
+/// Returns all repositories known to the bot with their merge queues.
+pub async fn get_repositories(
+    db: &(dyn DbClient + Send + Sync),
+) -> anyhow::Result<Vec<ApiRepository>> {
+    let mut repositories = vec![];
+    for repo in db.get_repositories().await? {
+        repositories.push(ApiRepository {
+            paused: db.get_pause(&repo).await?.is_some(),
+            leader: db
+                .get_repository_lease(&repo)
+                .await?
+                .filter(|lease| lease.expires_at > Utc::now())
+                .map(|lease| lease.holder),
+            queue: get_queue(db, &repo).await?,
+            name: repo.to_string(),
+        });
+    }
+    Ok(repositories)
+}
+
+/// Returns the state of a PR, or `None` if the bot does not know the PR.
+pub async fn get_pull_request(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+    number: PullRequestNumber,
+) -> anyhow::Result<Option<ApiPullRequest>> {
+    let Some(pr) = db.find_pull_request(repo, number).await? else {
+        return Ok(None);
+    };
+    let PullRequestModel {
+        number,
+        approved_by,
+        approved_at,
+        priority,
+        delegated_to,
+        try_build,
+        ..
+    } = pr;
+    let try_build = match try_build {
+        Some(build) => Some(build_to_api(db, build).await?),
+        None => None,
+    };
+    Ok(Some(ApiPullRequest {
+        number: number.0,
+        approved_by,
+        approved_at,
+        priority,
+        delegated_to,
+        try_build,
+    }))
+}
+
+/// Returns a build with its workflows, or `None` if the repository has no such build.
+pub async fn get_build(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+    id: PrimaryKey,
+) -> anyhow::Result<Option<ApiBuild>> {
+    match db.find_build_by_id(repo, id).await? {
+        Some(build) => Ok(Some(build_to_api(db, build).await?)),
+        None => Ok(None),
+    }
+}
+
+async fn build_to_api(
+    db: &(dyn DbClient + Send + Sync),
+    build: BuildModel,
+) -> anyhow::Result<ApiBuild> {
+    let workflows = db
+        .get_workflows_for_build(&build)
+        .await?
+        .into_iter()
+        .map(|workflow| ApiWorkflow {
+            name: workflow.name,
+            url: workflow.url,
+            run_id: workflow.run_id.0,
+            status: match workflow.status {
+                WorkflowStatus::Pending => "pending",
+                WorkflowStatus::Success => "success",
+                WorkflowStatus::Failure => "failure",
+            },
+            finished_at: workflow.finished_at,
+            artifacts: workflow.artifacts,
+        })
+        .collect();
+    Ok(ApiBuild {
+        id: build.id,
+        status: build_status_name(&build.status),
+        branch: build.branch,
+        commit_sha: build.commit_sha,
+        created_at: build.created_at,
+        attempt: build.attempt,
+        workflows,
+    })
+}
+
+fn queue_entry_to_api(entry: QueueEntry) -> ApiQueueEntry {
+    ApiQueueEntry {
+        number: entry.number,
+        status: entry.status.name(),
+        priority: entry.priority,
+        approved_by: entry.approved_by,
+        approved_at: entry.approved_at,
+        try_build: entry.try_build,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octocrab::models::RunId;
+
+    use crate::api::{get_build, get_pull_request, get_queue, get_repositories};
+    use crate::database::{DbClient, TryBuildArgs, WorkflowStatus, WorkflowType};
+    use crate::github::{CommitSha, PullRequestNumber};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn pull_request_with_build() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.approve(&pr, "foo", None).await.unwrap();
+        db.attach_try_build(
+            pr,
+            "automation/bors/try".to_string(),
+            CommitSha("sha".to_string()),
+            0,
+            TryBuildArgs::default(),
+        )
+        .await
+        .unwrap();
+        let build = db
+            .find_build(
+                &repo,
+                "automation/bors/try".to_string(),
+                CommitSha("sha".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        db.create_workflow(
+            &build,
+            "CI".to_string(),
+            "https://ci".to_string(),
+            RunId(5),
+            WorkflowType::Github,
+            WorkflowStatus::Pending,
+        )
+        .await
+        .unwrap();
+
+        let pr = get_pull_request(&db, &repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.approved_by.as_deref(), Some("foo"));
+        let try_build = pr.try_build.unwrap();
+        assert_eq!(try_build.status, "pending");
+        assert_eq!(try_build.workflows[0].run_id, 5);
+
+        let build = get_build(&db, &repo, try_build.id).await.unwrap().unwrap();
+        assert_eq!(build, try_build);
+        assert_eq!(get_queue(&db, &repo).await.unwrap()[0].status, "approved");
+
+        let repositories = get_repositories(&db).await.unwrap();
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].name, repo.to_string());
+        assert!(!repositories[0].paused);
+        assert_eq!(repositories[0].leader, None);
+        assert_eq!(repositories[0].queue[0].number, 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_objects() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        assert!(get_pull_request(&db, &repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(get_build(&db, &repo, 1).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,244 @@
+//! Export of the audit trail of a repository (commands, approvals and merges), computed from the
+//! event log, for compliance reviews that need to show who has authorized each merged change.
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::database::{DbClient, LoggedEvent};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+/// A single entry of the audit trail.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    pub repository: String,
+    pub pr: Option<u64>,
+    /// `command`, `approved`, `unapproved`, `merged`, `permission_granted`,
+    /// `permission_revoked` or `delegation_revoked`.
+    pub action: &'static str,
+    /// Who has sent the command or approved the PR, or whose permission or delegation has
+    /// changed.
+    pub actor: Option<String>,
+    /// The name of a command (e.g. `approve`), or the changed permission (`review` or `try`).
+    pub command: Option<String>,
+    /// For merges, who has approved the merged PR.
+    pub approved_by: Option<String>,
+}
+
+/// Format of an exported audit trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditFormat {
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl AuditFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(AuditFormat::Csv),
+            "jsonl" => Some(AuditFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            AuditFormat::Csv => "text/csv",
+            AuditFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+/// Loads the audit trail of the repository between `since` (inclusive) and `until` (exclusive),
+/// the oldest records first.
+pub async fn load_audit_trail(
+    db: &dyn DbClient,
+    repo: &GithubRepoName,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<Vec<AuditRecord>> {
+    // Approvals before the period are needed to find out who has approved PRs merged in it
+    let mut approvals: Vec<(PullRequestNumber, Option<String>)> = vec![];
+    let mut records = vec![];
+    for event in db.get_events(repo, None).await? {
+        if event.created_at >= until {
+            break;
+        }
+        let (action, actor, command, approved_by) = match event.event {
+            LoggedEvent::CommandReceived { author, command } => {
+                ("command", Some(author), Some(command), None)
+            }
+            LoggedEvent::Approved { approver } => {
+                approvals.extend(event.pr.map(|pr| (pr, Some(approver.clone()))));
+                ("approved", Some(approver), None, None)
+            }
+            LoggedEvent::Unapproved => {
+                approvals.extend(event.pr.map(|pr| (pr, None)));
+                ("unapproved", None, None, None)
+            }
+            LoggedEvent::Merged => {
+                let approved_by = approvals
+                    .iter()
+                    .rev()
+                    .find(|(pr, _)| Some(*pr) == event.pr)
+                    .and_then(|(_, approver)| approver.clone());
+                ("merged", None, None, approved_by)
+            }
+            LoggedEvent::PermissionChanged {
+                user,
+                permission,
+                granted,
+            } => {
+                let action = if granted {
+                    "permission_granted"
+                } else {
+                    "permission_revoked"
+                };
+                (action, Some(user), Some(permission), None)
+            }
+            LoggedEvent::DelegationRevoked { delegate } => {
+                ("delegation_revoked", Some(delegate), None, None)
+            }
+            _ => continue,
+        };
+        if event.created_at < since {
+            continue;
+        }
+        records.push(AuditRecord {
+            time: event.created_at,
+            repository: event.repository,
+            pr: event.pr.map(|pr| pr.0),
+            action,
+            actor,
+            command,
+            approved_by,
+        });
+    }
+    Ok(records)
+}
+
+/// Renders the audit trail in the given format.
+pub fn render_audit_trail(records: &[AuditRecord], format: AuditFormat) -> String {
+    let mut text = String::new();
+    match format {
+        AuditFormat::Csv => {
+            text.push_str("time,repository,pr,action,actor,command,approved_by\n");
+            for record in records {
+                let fields = [
+                    record.time.to_rfc3339(),
+                    record.repository.clone(),
+                    record.pr.map(|pr| pr.to_string()).unwrap_or_default(),
+                    record.action.to_string(),
+                    record.actor.clone().unwrap_or_default(),
+                    record.command.clone().unwrap_or_default(),
+                    record.approved_by.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| escape_csv(field)).collect();
+                let _ = writeln!(text, "{}", fields.join(","));
+            }
+        }
+        AuditFormat::Jsonl => {
+            for record in records {
+                let line = serde_json::to_string(record).expect("Cannot serialize audit record");
+                let _ = writeln!(text, "{line}");
+            }
+        }
+    }
+    text
+}
+
+/// Parses the period of an export from dates in the `YYYY-MM-DD` format. Both dates are
+/// inclusive. The period starts at the beginning of the event log and ends now by default.
+pub fn parse_period(
+    since: Option<&str>,
+    until: Option<&str>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|date| DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc))
+            .map_err(|_| anyhow::anyhow!("Invalid date `{date}`, expected YYYY-MM-DD"))
+    };
+    let since = match since {
+        Some(since) => parse(since)?,
+        None => DateTime::<Utc>::MIN_UTC,
+    };
+    let until = match until {
+        Some(until) => parse(until)? + Duration::days(1),
+        None => now,
+    };
+    Ok((since, until))
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::audit::{load_audit_trail, parse_period, render_audit_trail, AuditFormat};
+    use crate::database::{DbClient, LoggedEvent};
+    use crate::github::PullRequestNumber;
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn audit_trail_of_merged_pr() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        let command = LoggedEvent::CommandReceived {
+            author: "reviewer".to_string(),
+            command: "approve".to_string(),
+        };
+        db.record_event(&repo, Some(pr.number), command)
+            .await
+            .unwrap();
+        db.approve(&pr, "reviewer", None).await.unwrap();
+        db.record_event(&repo, Some(pr.number), LoggedEvent::Merged)
+            .await
+            .unwrap();
+
+        let now = Utc::now() + Duration::minutes(1);
+        let records = load_audit_trail(&db, &repo, now - Duration::days(1), now)
+            .await
+            .unwrap();
+        let actions: Vec<&str> = records.iter().map(|record| record.action).collect();
+        assert_eq!(actions, ["command", "approved", "merged"]);
+        assert_eq!(records[2].approved_by.as_deref(), Some("reviewer"));
+
+        let csv = render_audit_trail(&records, AuditFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "time,repository,pr,action,actor,command,approved_by"
+        );
+        assert!(lines[3].ends_with(",owner/name,1,merged,,,reviewer"));
+        let jsonl = render_audit_trail(&records, AuditFormat::Jsonl);
+        assert_eq!(jsonl.lines().count(), 3);
+
+        let records = load_audit_trail(&db, &repo, now, now + Duration::days(1))
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn period_includes_until_date() {
+        let (since, until) =
+            parse_period(Some("2023-08-01"), Some("2023-08-31"), Utc::now()).unwrap();
+        assert_eq!(since.to_rfc3339(), "2023-08-01T00:00:00+00:00");
+        assert_eq!(until.to_rfc3339(), "2023-09-01T00:00:00+00:00");
+        assert!(parse_period(Some("08/01/2023"), None, Utc::now()).is_err());
+    }
+}
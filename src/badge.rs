@@ -0,0 +1,202 @@
+//! SVG badges with the state of the merge queue of a repository or of a single PR, which can be
+//! embedded e.g. in a README.
+use crate::dashboard::{is_merge_build_running, load_queue, QueueEntryStatus};
+use crate::database::{BuildStatus, DbClient};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+const GREEN: &str = "#4c1";
+const YELLOW: &str = "#dfb317";
+const RED: &str = "#e05d44";
+const GREY: &str = "#9f9f9f";
+
+/// Approximate width of a character of the badge font in pixels.
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+
+/// A badge with a label on the left and a colored message on the right.
+#[derive(Debug, PartialEq)]
+pub struct Badge {
+    pub label: String,
+    pub message: String,
+    pub color: &'static str,
+}
+
+impl Badge {
+    /// Renders the badge as an SVG image in the style of shields.io badges.
+    pub fn render(&self) -> String {
+        let label = escape_xml(&self.label);
+        let message = escape_xml(&self.message);
+        let label_width = self.label.chars().count() * CHAR_WIDTH + PADDING;
+        let message_width = self.message.chars().count() * CHAR_WIDTH + PADDING;
+        let width = label_width + message_width;
+        let label_x = label_width / 2;
+        let message_x = label_width + message_width / 2;
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+<title>{label}: {message}</title>
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="14">{label}</text>
+<text x="{message_x}" y="14">{message}</text>
+</g>
+</svg>
+"##,
+            color = self.color,
+        )
+    }
+}
+
+/// Creates a badge with the state of the merge queue of the repository: whether the tree is
+/// closed or a PR is being merged, and how many approved PRs wait in the queue.
+pub async fn repository_badge(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+) -> anyhow::Result<Badge> {
+    let entries = load_queue(db, repo).await?;
+    let building = entries
+        .iter()
+        .any(|entry| entry.status == QueueEntryStatus::Building);
+    let queued = entries
+        .iter()
+        .filter(|entry| entry.status == QueueEntryStatus::Approved)
+        .count();
+    let tree_closed = db.get_tree_closure(repo).await?.is_some();
+    let (message, color) = match (tree_closed, building, queued) {
+        (true, _, queued) => (format!("tree closed, {queued} queued"), RED),
+        (false, true, queued) => (format!("merging, {queued} queued"), YELLOW),
+        (false, false, 0) => ("queue empty".to_string(), GREEN),
+        (false, false, queued) => (format!("{queued} queued"), GREEN),
+    };
+    Ok(Badge {
+        label: "bors".to_string(),
+        message,
+        color,
+    })
+}
+
+/// Creates a badge with the state of a PR, or returns `None` if the bot does not know the PR.
+pub async fn pull_request_badge(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+    pr_number: PullRequestNumber,
+) -> anyhow::Result<Option<Badge>> {
+    let Some(pr) = db.find_pull_request(repo, pr_number).await? else {
+        return Ok(None);
+    };
+    let try_status = pr.try_build.as_ref().map(|build| &build.status);
+    let (message, color) = if is_merge_build_running(&pr) {
+        ("merging", YELLOW)
+    } else if pr.is_approved() {
+        ("approved", GREEN)
+    } else {
+        match try_status {
+            Some(BuildStatus::Pending) => ("try running", YELLOW),
+            Some(BuildStatus::Success) => ("try passed", GREEN),
+            Some(BuildStatus::Failure | BuildStatus::Timeouted) => ("try failed", RED),
+            Some(BuildStatus::Cancelled) | None => ("not approved", GREY),
+        }
+    };
+    Ok(Some(Badge {
+        label: "bors".to_string(),
+        message: message.to_string(),
+        color,
+    }))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::badge::{pull_request_badge, repository_badge, Badge, GREEN, RED};
+    use crate::database::{BuildStatus, DbClient, TryBuildArgs};
+    use crate::github::{CommitSha, PullRequestNumber};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn render_badge() {
+        let badge = Badge {
+            label: "bors".to_string(),
+            message: "<1> queued".to_string(),
+            color: GREEN,
+        };
+        let svg = badge.render();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="118""#));
+        assert!(svg.contains("<title>bors: &lt;1&gt; queued</title>"));
+    }
+
+    #[tokio::test]
+    async fn queue_length() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        assert_eq!(
+            repository_badge(&db, &repo).await.unwrap().message,
+            "queue empty"
+        );
+
+        for number in 1..=2 {
+            let pr = db
+                .get_or_create_pull_request(&repo, PullRequestNumber(number))
+                .await
+                .unwrap();
+            db.approve(&pr, "foo", None).await.unwrap();
+        }
+        assert_eq!(
+            repository_badge(&db, &repo).await.unwrap().message,
+            "2 queued"
+        );
+
+        db.close_tree(&repo, 100, Some("foo"), "CI is broken")
+            .await
+            .unwrap();
+        let badge = repository_badge(&db, &repo).await.unwrap();
+        assert_eq!(badge.message, "tree closed, 2 queued");
+        assert_eq!(badge.color, RED);
+    }
+
+    #[tokio::test]
+    async fn pull_request_state() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        assert_eq!(
+            pull_request_badge(&db, &repo, PullRequestNumber(1))
+                .await
+                .unwrap(),
+            None
+        );
+
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.attach_try_build(
+            pr,
+            "automation/bors/try".to_string(),
+            CommitSha("sha".to_string()),
+            0,
+            TryBuildArgs::default(),
+        )
+        .await
+        .unwrap();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.update_build_status(pr.try_build.as_ref().unwrap(), BuildStatus::Failure)
+            .await
+            .unwrap();
+
+        let badge = pull_request_badge(&db, &repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(badge.message, "try failed");
+        assert_eq!(badge.color, RED);
+    }
+}
@@ -1,140 +1,1363 @@
-use std::net::{Ipv4Addr, SocketAddr};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::Router;
 use bors::bors::{BorsContext, CommandParser};
 use clap::Parser;
 use dotenv::dotenv;
-use sea_orm::Database;
+use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use tokio::sync::{broadcast, oneshot, watch};
 use tokio::task::LocalSet;
 use tower::limit::ConcurrencyLimitLayer;
-use tracing_subscriber::EnvFilter;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
+use bors::audit::{load_audit_trail, parse_period, render_audit_trail, AuditFormat};
 use bors::bors::event::BorsEvent;
-use bors::database::SeaORMClient;
-use bors::github::server::{create_bors_process, github_webhook_handler, ServerState};
-use bors::github::{GithubAppState, WebhookSecret};
-use migration::{Migrator, MigratorTrait};
+use bors::bors::lease::LeaseConfig;
+use bors::ci::azure::AzurePipelinesClient;
+use bors::ci::buildkite::{BuildkiteClient, BuildkiteForge};
+use bors::ci::circleci::{CircleCiClient, CircleCiForge};
+use bors::ci::jenkins::{JenkinsClient, JenkinsForge};
+use bors::ci::CiServices;
+use bors::config::{config_warnings, validate_config};
+use bors::consistency::ConsistencyReports;
+use bors::database::{normalize_connection_string, DbClient, SeaORMClient};
+use bors::error_reporting::{self, ErrorContext};
+use bors::fixtures::FixtureRecorder;
+use bors::forge::Forge;
+use bors::gitea::{GiteaForge, GiteaState};
+use bors::github::oauth::OAuthConfig;
+use bors::github::server::{
+    admin_consistency_handler, admin_dequeue_handler, admin_pause_handler,
+    admin_repositories_handler, admin_resume_handler, admin_resync_handler,
+    admin_rotate_token_handler, admin_validate_config_handler, api_audit_handler,
+    api_build_handler, api_build_result_handler, api_builds_handler,
+    api_pull_request_builds_handler, api_pull_request_handler, api_queue_handler,
+    api_statistics_handler, buildkite_webhook_handler, circleci_webhook_handler,
+    create_bors_process, dashboard_action_handler, gitea_webhook_handler, github_webhook_handler,
+    gitlab_webhook_handler, health_handler, history_page_handler, jenkins_webhook_handler,
+    log_filter_handler, login_handler, metrics_handler, oauth_callback_handler, openapi_handler,
+    protect_branches_handler, pull_request_badge_handler, pull_request_history_page_handler,
+    queue_events_handler, queue_page_handler, replay_delivery_handler, repository_badge_handler,
+    set_log_filter_handler, ServerState, ServerStateRef,
+};
+use bors::github::{GithubAppState, GithubRepoName, WebhookSecret};
+use bors::gitlab::{GitlabForge, GitlabState};
+use bors::global_config::{DatabaseConfig, GlobalConfig, PartialGlobalConfig};
+use bors::health::{heartbeat, Heartbeat, HEARTBEATS};
+use bors::homu::{import_homu_pulls, load_homu_pulls, parse_repo_mapping};
+use bors::http::SendLimited;
+use bors::metrics::CommandMetrics;
+use bors::notifications::{EmailCredentials, NotificationCredentials, ZulipCredentials};
+use bors::secrets::SecretSource;
+use bors::snapshot::{export_snapshot, import_snapshot, Snapshot};
+use bors::stats::repository_statistics;
+use bors::tokens::{create_token, parse_scopes};
+use bors::utils::logging::LogFilter;
+use migration::{MigrationName, Migrator, MigratorTrait};
+use secrecy::ExposeSecret;
 
 /// How often should the bot check DB state, e.g. for handling timeouts.
 const PERIODIC_REFRESH: Duration = Duration::from_secs(120);
 
+/// How often should the bot cross-check its state with GitHub, to repair drift caused by lost
+/// webhooks.
+const CONSISTENCY_SWEEP: Duration = Duration::from_secs(30 * 60);
+
+/// Number of periodic refreshes that can be missed by a replica before it loses its leases.
+const LEASE_REFRESHES: u32 = 3;
+
+/// How long should we wait for a database connection.
+const DB_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many dashboard updates can be buffered for a slow dashboard page.
+const DASHBOARD_UPDATES_CAPACITY: usize = 64;
+
+/// How often should the bot check whether the webhook secret has been rotated.
+const SECRET_REFRESH: Duration = Duration::from_secs(300);
+
+/// How often should the watchdog check that the loops of the bot are making progress.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long can open connections (e.g. of dashboard pages) delay the shutdown of the server.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Command line flags and environment variables override the values from the configuration file.
 #[derive(clap::Parser)]
 struct Opts {
-    /// Secret used to authenticate webhooks.
+    /// Path to a TOML configuration file of the bot.
+    #[arg(long, env = "BORS_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Secret used to authenticate webhooks. Secrets can also be referenced using
+    /// `file:<path>`, `env:<variable>` or `vault:<path>#<key>`.
     #[arg(long, env = "WEBHOOK_SECRET")]
-    webhook_secret: String,
+    webhook_secret: Option<String>,
 
     /// Github App ID.
     #[arg(long, env = "APP_ID")]
-    app_id: u64,
+    app_id: Option<u64>,
 
-    /// Private key used to authenticate as a Github App.
+    /// Path to the private key used to authenticate as a Github App, or a secret reference.
     #[arg(long, env = "PRIVATE_KEY")]
-    private_key: String,
+    private_key: Option<String>,
 
-    /// Database connection string.
+    /// Database connection string, either `sqlite://<path>` or `postgres://<url>`.
     #[arg(long, env = "DATABASE")]
-    db: String,
+    db: Option<String>,
 
-    /// Prefix used for bot commands in PR comments.
-    #[arg(long, env = "CMD_PREFIX", default_value = "@bors")]
-    cmd_prefix: String,
+    /// Prefix used for bot commands in PR comments [default: @bors].
+    #[arg(long, env = "CMD_PREFIX")]
+    cmd_prefix: Option<String>,
 
+    /// Port on which the web server listens.
     #[arg(long, env = "BORS_PORT")]
-    port: u16,
+    port: Option<u16>,
+
+    /// Address on which the web server listens [default: 127.0.0.1].
+    #[arg(long, env = "BORS_HOST")]
+    host: Option<String>,
+
+    /// Maximum number of connections in the database connection pool [default: 10].
+    #[arg(long, env = "DATABASE_MAX_CONNECTIONS")]
+    db_max_connections: Option<u32>,
+
+    /// Maximum size of a webhook payload in bytes, larger webhooks are rejected
+    /// [default: 26214400].
+    #[arg(long, env = "MAX_WEBHOOK_SIZE")]
+    max_webhook_size: Option<usize>,
+
+    /// Number of days after which received webhooks are deleted from the delivery log
+    /// [default: 14].
+    #[arg(long, env = "WEBHOOK_DELIVERY_RETENTION_DAYS")]
+    webhook_delivery_retention_days: Option<u64>,
+
+    /// Notify repositories (the `restarting` notification) when the bot shuts down.
+    #[arg(long, env = "RESTART_NOTICE")]
+    restart_notice: bool,
+
+    /// Only report what the bot would do (in PR comments and logs), without pushing branches or
+    /// merging anything. Can also be enabled for single repositories in their configuration.
+    #[arg(long, env = "DRY_RUN")]
+    dry_run: bool,
+
+    /// Only apply database migrations and exit. Only the database has to be configured.
+    #[arg(long)]
+    migrate_only: bool,
+
+    /// Print CI statistics of the given repository (`<owner>/<name>`) and exit.
+    #[arg(long)]
+    stats: Option<String>,
+
+    /// Token used to authenticate requests to admin endpoints, in addition to API tokens with the
+    /// `admin` scope.
+    #[arg(long, env = "ADMIN_TOKEN")]
+    admin_token: Option<String>,
+
+    /// Token used to authenticate requests to the JSON API, in addition to API tokens with the
+    /// `read` scope.
+    #[arg(long, env = "API_TOKEN")]
+    api_token: Option<String>,
+
+    /// Create an API token with the given name (describing who uses it), print it and exit.
+    #[arg(long)]
+    create_api_token: Option<String>,
+
+    /// Comma-separated scopes of the created API token (`read`, `admin`, `report`) [default: read].
+    #[arg(long, requires = "create_api_token")]
+    token_scopes: Option<String>,
+
+    /// Comma-separated repositories (`owner/name`) in which the created API token can be used
+    /// [default: all repositories].
+    #[arg(long, requires = "create_api_token")]
+    token_repositories: Option<String>,
+
+    /// Number of days after which the created API token expires [default: never].
+    #[arg(long, requires = "create_api_token")]
+    token_expires_in_days: Option<i64>,
+
+    /// Print all API tokens (without their values) and exit.
+    #[arg(long)]
+    list_api_tokens: bool,
+
+    /// Revoke the API token with the given ID and exit.
+    #[arg(long)]
+    revoke_api_token: Option<i32>,
+
+    /// Print the audit trail (commands, approvals and merges) of the given repository
+    /// (`<owner>/<name>`) and exit.
+    #[arg(long)]
+    export_audit: Option<String>,
+
+    /// First day (`YYYY-MM-DD`) of the exported audit trail [default: the first recorded event].
+    #[arg(long, requires = "export_audit")]
+    audit_since: Option<String>,
+
+    /// Last day (`YYYY-MM-DD`) of the exported audit trail [default: today].
+    #[arg(long, requires = "export_audit")]
+    audit_until: Option<String>,
+
+    /// Format of the exported audit trail (`csv`, `jsonl`) [default: csv].
+    #[arg(long, requires = "export_audit")]
+    audit_format: Option<String>,
+
+    /// Print the given number of most recently received webhook deliveries and exit.
+    #[arg(long)]
+    list_webhook_deliveries: Option<u64>,
+
+    /// Import the approvals, priorities, rollup modes and delegations of open PRs from the given
+    /// SQLite database of homu and exit.
+    #[arg(long)]
+    import_homu: Option<PathBuf>,
+
+    /// Maps a repository label of the homu configuration to a GitHub repository
+    /// (`<label>=<owner>/<name>`). Can be repeated.
+    #[arg(long, requires = "import_homu")]
+    homu_repo: Vec<String>,
+
+    /// Write all persistent state of the bot (PRs, approvals, builds, ...) to the given JSON file
+    /// and exit.
+    #[arg(long, conflicts_with = "import_snapshot")]
+    export_snapshot: Option<PathBuf>,
+
+    /// Import a snapshot written by `--export-snapshot` into an empty database and exit.
+    #[arg(long)]
+    import_snapshot: Option<PathBuf>,
+
+    /// Change the log filter of the running bot to the given directives (in the `RUST_LOG`
+    /// syntax) using its admin endpoint and exit. Requires the admin token.
+    #[arg(long)]
+    set_log_filter: Option<String>,
+
+    /// Client ID of the GitHub OAuth App used to log in to the dashboard.
+    /// Admin actions of the dashboard are disabled if it is not set.
+    #[arg(long, env = "OAUTH_CLIENT_ID")]
+    oauth_client_id: Option<String>,
+
+    /// Client secret of the GitHub OAuth App, or a secret reference.
+    #[arg(long, env = "OAUTH_CLIENT_SECRET")]
+    oauth_client_secret: Option<String>,
+
+    /// URL of the Zulip organization where notifications are sent.
+    /// Zulip notifications are disabled if it is not set.
+    #[arg(long, env = "ZULIP_URL")]
+    zulip_url: Option<String>,
+
+    /// Email of the Zulip bot that sends notifications.
+    #[arg(long, env = "ZULIP_EMAIL")]
+    zulip_email: Option<String>,
+
+    /// API key of the Zulip bot, or a secret reference.
+    #[arg(long, env = "ZULIP_API_KEY")]
+    zulip_api_key: Option<String>,
+
+    /// URL of the SMTP server used to send email notifications (including the credentials),
+    /// or a secret reference. Email notifications are disabled if it is not set.
+    #[arg(long, env = "SMTP_URL")]
+    smtp_url: Option<String>,
+
+    /// Sender of email notifications, e.g. `Bors <bors@example.com>`.
+    #[arg(long, env = "SMTP_FROM")]
+    smtp_from: Option<String>,
+
+    /// DSN of the Sentry (or compatible) project that receives errors and panics of the bot,
+    /// or a secret reference. Errors are not reported if it is not set.
+    #[arg(long, env = "SENTRY_DSN")]
+    sentry_dsn: Option<String>,
+
+    /// URL of an OpenTelemetry collector that receives the tracing spans of the bot using OTLP
+    /// (gRPC), e.g. `http://localhost:4317`. Spans are not exported if it is not set.
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Store sanitized payloads of received webhooks as JSON files in this directory, so that they
+    /// can be replayed in tests.
+    #[arg(long, env = "RECORD_WEBHOOKS")]
+    record_webhooks: Option<PathBuf>,
+
+    /// Identifier of this replica, unique among all replicas of the bot that share the database.
+    /// Every replica handles webhooks, but each repository is only refreshed and reconciled by
+    /// the replica that holds its lease.
+    #[arg(long, env = "REPLICA_ID")]
+    replica_id: Option<String>,
+
+    /// Duration of the leases of repositories in seconds [default: 360].
+    #[arg(long, env = "LEASE_DURATION")]
+    lease_duration: Option<u64>,
+
+    /// URL of a GitLab instance whose projects (`gitlab_projects` in the configuration file) are
+    /// managed by the bot, e.g. `https://gitlab.com`.
+    #[arg(long, env = "GITLAB_URL")]
+    gitlab_url: Option<String>,
+
+    /// Access token of the GitLab bot user (with the `api` scope), or a secret reference.
+    #[arg(long, env = "GITLAB_TOKEN")]
+    gitlab_token: Option<String>,
+
+    /// URL of a Gitea (or Forgejo) instance whose repositories (`gitea_repositories` in the
+    /// configuration file) are managed by the bot, e.g. `https://codeberg.org`.
+    #[arg(long, env = "GITEA_URL")]
+    gitea_url: Option<String>,
+
+    /// Access token of the Gitea bot user, or a secret reference.
+    #[arg(long, env = "GITEA_TOKEN")]
+    gitea_token: Option<String>,
+
+    /// Buildkite API access token (with the `write_builds` scope) used to start try builds, or a
+    /// secret reference.
+    #[arg(long, env = "BUILDKITE_TOKEN")]
+    buildkite_token: Option<String>,
+
+    /// Token of the Buildkite webhooks that report finished builds, or a secret reference.
+    #[arg(long, env = "BUILDKITE_WEBHOOK_TOKEN")]
+    buildkite_webhook_token: Option<String>,
+
+    /// Azure DevOps personal access token (with the `Build (Read & execute)` scope) used to queue
+    /// try builds in Azure Pipelines, or a secret reference.
+    #[arg(long, env = "AZURE_PIPELINES_TOKEN")]
+    azure_pipelines_token: Option<String>,
 
-    #[arg(long, env = "BORS_HOST", default_value = "127.0.0.1")]
-    host: String,
+    /// URL of a Jenkins server whose jobs run try builds, e.g. `https://ci.example.com`.
+    #[arg(long, env = "JENKINS_URL")]
+    jenkins_url: Option<String>,
+
+    /// Jenkins user that triggers the jobs.
+    #[arg(long, env = "JENKINS_USER")]
+    jenkins_user: Option<String>,
+
+    /// API token of the Jenkins user, or a secret reference.
+    #[arg(long, env = "JENKINS_TOKEN")]
+    jenkins_token: Option<String>,
+
+    /// Secret used by Jenkins jobs to sign their result callbacks, or a secret reference.
+    #[arg(long, env = "JENKINS_CALLBACK_SECRET")]
+    jenkins_callback_secret: Option<String>,
+
+    /// CircleCI personal API token used to trigger pipelines of try builds, or a secret reference.
+    #[arg(long, env = "CIRCLECI_TOKEN")]
+    circleci_token: Option<String>,
+
+    /// Secret of the CircleCI webhooks that report completed workflows, or a secret reference.
+    #[arg(long, env = "CIRCLECI_WEBHOOK_SECRET")]
+    circleci_webhook_secret: Option<String>,
+
+    /// Check the given repository configuration file (`rust-bors.toml`) and exit.
+    #[arg(long)]
+    validate_config: Option<PathBuf>,
 }
 
-async fn server(state: ServerState) -> anyhow::Result<()> {
-    let state = Arc::new(state);
+impl Opts {
+    fn into_config(self) -> anyhow::Result<GlobalConfig> {
+        let (file, overrides) = self.into_overrides();
+        GlobalConfig::load(file.as_deref(), overrides)
+    }
+
+    /// Returns the path of the configuration file and the values set by flags and environment
+    /// variables.
+    fn into_overrides(self) -> (Option<PathBuf>, PartialGlobalConfig) {
+        let overrides = PartialGlobalConfig {
+            webhook_secret: self.webhook_secret,
+            app_id: self.app_id,
+            private_key: self.private_key,
+            db: self.db,
+            db_max_connections: self.db_max_connections,
+            cmd_prefix: self.cmd_prefix,
+            host: self.host,
+            port: self.port,
+            max_webhook_size: self.max_webhook_size,
+            webhook_delivery_retention_days: self.webhook_delivery_retention_days,
+            restart_notice: self.restart_notice.then_some(true),
+            dry_run: self.dry_run.then_some(true),
+            admin_token: self.admin_token,
+            api_token: self.api_token,
+            oauth_client_id: self.oauth_client_id,
+            oauth_client_secret: self.oauth_client_secret,
+            zulip_url: self.zulip_url,
+            zulip_email: self.zulip_email,
+            zulip_api_key: self.zulip_api_key,
+            notification_webhooks: None,
+            smtp_url: self.smtp_url,
+            smtp_from: self.smtp_from,
+            sentry_dsn: self.sentry_dsn,
+            otlp_endpoint: self.otlp_endpoint,
+            record_webhooks: self.record_webhooks,
+            replica_id: self.replica_id,
+            lease_duration: self.lease_duration,
+            gitlab_url: self.gitlab_url,
+            gitlab_token: self.gitlab_token,
+            gitlab_projects: None,
+            gitea_url: self.gitea_url,
+            gitea_token: self.gitea_token,
+            gitea_repositories: None,
+            buildkite_token: self.buildkite_token,
+            buildkite_webhook_token: self.buildkite_webhook_token,
+            azure_pipelines_token: self.azure_pipelines_token,
+            jenkins_url: self.jenkins_url,
+            jenkins_user: self.jenkins_user,
+            jenkins_token: self.jenkins_token,
+            jenkins_callback_secret: self.jenkins_callback_secret,
+            circleci_token: self.circleci_token,
+            circleci_webhook_secret: self.circleci_webhook_secret,
+        };
+        (self.config, overrides)
+    }
+}
 
+async fn server(state: ServerStateRef, addr: SocketAddr) -> anyhow::Result<()> {
     let app = Router::new()
         .route("/github", post(github_webhook_handler))
+        .route("/gitlab", post(gitlab_webhook_handler))
+        .route("/gitea", post(gitea_webhook_handler))
+        .route("/buildkite", post(buildkite_webhook_handler))
+        .route("/jenkins", post(jenkins_webhook_handler))
+        .route("/circleci", post(circleci_webhook_handler))
+        .route("/health", get(health_handler))
+        .route(
+            "/admin/protect-branches/:owner/:name",
+            post(protect_branches_handler),
+        )
+        .route(
+            "/admin/deliveries/:id/replay",
+            post(replay_delivery_handler),
+        )
+        .route("/admin/repos", get(admin_repositories_handler))
+        .route("/admin/consistency", get(admin_consistency_handler))
+        .route(
+            "/admin/repos/:owner/:name/prs/:number/dequeue",
+            post(admin_dequeue_handler),
+        )
+        .route(
+            "/admin/repos/:owner/:name/resync",
+            post(admin_resync_handler),
+        )
+        .route("/admin/repos/:owner/:name/pause", post(admin_pause_handler))
+        .route(
+            "/admin/repos/:owner/:name/resume",
+            post(admin_resume_handler),
+        )
+        .route("/admin/tokens/:id/rotate", post(admin_rotate_token_handler))
+        .route(
+            "/admin/validate-config",
+            post(admin_validate_config_handler),
+        )
+        .route(
+            "/admin/log-filter",
+            get(log_filter_handler).put(set_log_filter_handler),
+        )
+        .route("/queue/:owner/:name", get(queue_page_handler))
+        .route("/queue/:owner/:name/events", get(queue_events_handler))
+        .route(
+            "/queue/:owner/:name/prs/:number/:action",
+            post(dashboard_action_handler),
+        )
+        .route("/history/:owner/:name", get(history_page_handler))
+        .route(
+            "/history/:owner/:name/pr/:number",
+            get(pull_request_history_page_handler),
+        )
+        .route("/badge/:owner/:name", get(repository_badge_handler))
+        .route(
+            "/badge/:owner/:name/pr/:file",
+            get(pull_request_badge_handler),
+        )
+        .route("/login", get(login_handler))
+        .route("/oauth/callback", get(oauth_callback_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/api/v1/openapi.json", get(openapi_handler))
+        .route("/api/v1/repos/:owner/:name/queue", get(api_queue_handler))
+        .route("/api/v1/repos/:owner/:name/builds", get(api_builds_handler))
+        .route(
+            "/api/v1/repos/:owner/:name/stats",
+            get(api_statistics_handler),
+        )
+        .route("/api/v1/repos/:owner/:name/audit", get(api_audit_handler))
+        .route(
+            "/api/v1/repos/:owner/:name/prs/:number/builds",
+            get(api_pull_request_builds_handler),
+        )
+        .route(
+            "/api/v1/repos/:owner/:name/builds/:id",
+            get(api_build_handler),
+        )
+        .route(
+            "/api/v1/repos/:owner/:name/prs/:number",
+            get(api_pull_request_handler),
+        )
+        .route(
+            "/api/v1/repos/:owner/:name/builds/:id/result",
+            post(api_build_result_handler),
+        )
         .layer(ConcurrencyLimitLayer::new(100))
-        .with_state(state);
+        .with_state(state.clone());
 
-    let opts = Opts::parse();
-    let addr = SocketAddr::from((opts.host.parse::<Ipv4Addr>().unwrap(), opts.port));
-
-    axum::Server::bind(&addr)
+    let (draining_tx, draining_rx) = oneshot::channel();
+    let shutdown = async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down, webhooks are no longer accepted");
+        state.begin_shutdown();
+        let _ = draining_tx.send(());
+    };
+    let server = axum::Server::bind(&addr)
         .serve(app.into_make_service())
-        .await?;
+        .with_graceful_shutdown(shutdown);
+    tokio::select! {
+        res = server => res?,
+        // Streams of dashboard pages are never finished by the clients
+        () = async {
+            match draining_rx.await {
+                Ok(()) => tokio::time::sleep(SHUTDOWN_TIMEOUT).await,
+                Err(_) => std::future::pending().await,
+            }
+        } => {
+            tracing::warn!("Closing connections that are still open");
+        }
+    }
     Ok(())
 }
 
-async fn initialize_db(connection_string: &str) -> anyhow::Result<SeaORMClient> {
-    let db = Database::connect(connection_string).await?;
+/// Waits until the bot is asked to stop, using `SIGTERM` (e.g. by Kubernetes during a rollout) or
+/// Ctrl+C.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(error) => tracing::error!("Cannot listen for SIGTERM: {error:?}"),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Periodically checks that the loops of the bot are making progress, and reports an error once a
+/// loop stalls.
+async fn watchdog() {
+    let mut stalled: Vec<&str> = vec![];
+    loop {
+        tokio::time::sleep(WATCHDOG_INTERVAL).await;
+        let report = HEARTBEATS.check(chrono::Utc::now());
+        if report.stalled != stalled {
+            if report.healthy {
+                tracing::info!("All loops of the bot are making progress again");
+            } else {
+                let error = anyhow::anyhow!(
+                    "Loops of the bot have stalled: {} ({report:?})",
+                    report.stalled.join(", ")
+                );
+                error_reporting::report_error(
+                    &error,
+                    ErrorContext {
+                        event: "Watchdog",
+                        repository: None,
+                        pr: None,
+                    },
+                );
+                tracing::error!("{error}");
+            }
+            stalled = report.stalled;
+        }
+    }
+}
+
+/// Periodically loads the webhook secret from its source and starts to use it once it changes.
+async fn rotate_webhook_secret(state: ServerStateRef, source: SecretSource) -> anyhow::Result<()> {
+    if !source.can_rotate() {
+        return std::future::pending().await;
+    }
+    loop {
+        tokio::time::sleep(SECRET_REFRESH).await;
+        match source.load().await {
+            Ok(secret) => {
+                let current = state.get_webhook_secrets().swap_remove(0);
+                if current.expose() != secret.expose_secret() {
+                    tracing::info!("Webhook secret has been rotated");
+                    state.rotate_webhook_secret(WebhookSecret::new(secret.expose_secret().clone()));
+                }
+            }
+            Err(error) => tracing::error!("Cannot load webhook secret: {error:?}"),
+        }
+    }
+}
+
+async fn initialize_db(
+    connection_string: &str,
+    max_connections: u32,
+) -> anyhow::Result<SeaORMClient> {
+    let mut options = ConnectOptions::new(normalize_connection_string(connection_string));
+    options
+        .max_connections(max_connections)
+        .connect_timeout(DB_CONNECT_TIMEOUT)
+        .acquire_timeout(DB_CONNECT_TIMEOUT)
+        // Connections that have been broken (e.g. by a database restart) are replaced
+        .idle_timeout(Duration::from_secs(600));
+    let db = Database::connect(options).await?;
+    check_migrations(&db).await?;
     Migrator::up(&db, None).await?;
     Ok(SeaORMClient::new(db))
 }
 
-fn try_main(opts: Opts) -> anyhow::Result<()> {
+/// Refuses to use a database that has been migrated by a newer version of the bot, which happens
+/// when the bot is downgraded.
+async fn check_migrations(db: &DatabaseConnection) -> anyhow::Result<()> {
+    let known: HashSet<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+    let unknown: Vec<String> = Migrator::get_migration_models(db)
+        .await
+        .context("Cannot load applied migrations")?
+        .into_iter()
+        .map(|migration| migration.version)
+        .filter(|version| !known.contains(version))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The database contains migrations unknown to this version of bors ({}). Was the bot downgraded?",
+            unknown.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Administration of API tokens requested using the command line.
+enum TokenCommand {
+    Create {
+        name: String,
+        scopes: String,
+        repositories: Option<String>,
+        expires_in_days: Option<i64>,
+    },
+    List,
+    Revoke(i32),
+}
+
+impl TokenCommand {
+    fn from_opts(opts: &mut Opts) -> Option<Self> {
+        if let Some(name) = opts.create_api_token.take() {
+            return Some(TokenCommand::Create {
+                name,
+                scopes: opts
+                    .token_scopes
+                    .take()
+                    .unwrap_or_else(|| "read".to_string()),
+                repositories: opts.token_repositories.take(),
+                expires_in_days: opts.token_expires_in_days,
+            });
+        }
+        if opts.list_api_tokens {
+            return Some(TokenCommand::List);
+        }
+        opts.revoke_api_token.map(TokenCommand::Revoke)
+    }
+
+    async fn execute(self, db: &SeaORMClient) -> anyhow::Result<()> {
+        match self {
+            TokenCommand::Create {
+                name,
+                scopes,
+                repositories,
+                expires_in_days,
+            } => {
+                let scopes = parse_scopes(&scopes)?;
+                let repositories: Vec<String> = repositories
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|repo| repo.trim().to_string())
+                    .filter(|repo| !repo.is_empty())
+                    .collect();
+                if let Some(repo) = repositories.iter().find(|repo| !repo.contains('/')) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid repository `{repo}`, expected `owner/name`"
+                    ));
+                }
+                let expires_at =
+                    expires_in_days.map(|days| chrono::Utc::now() + chrono::Duration::days(days));
+                let (id, token) =
+                    create_token(db, &name, &scopes, &repositories, expires_at).await?;
+                println!("Created API token {id} ({name}):\n{token}");
+                println!("Store it now, it cannot be shown again.");
+            }
+            TokenCommand::List => {
+                for token in db.get_api_tokens().await? {
+                    let scopes: Vec<&str> = token.scopes.iter().map(|scope| scope.name()).collect();
+                    let repositories = if token.repositories.is_empty() {
+                        "all repositories".to_string()
+                    } else {
+                        token.repositories.join(",")
+                    };
+                    let state = match (token.revoked_at, token.expires_at) {
+                        (Some(revoked_at), _) => format!("revoked at {revoked_at}"),
+                        (None, Some(expires_at)) => format!("expires at {expires_at}"),
+                        (None, None) => "does not expire".to_string(),
+                    };
+                    println!(
+                        "{}\t{}\t{}\t{repositories}\tcreated at {}, {state}",
+                        token.id,
+                        token.name,
+                        scopes.join(","),
+                        token.created_at
+                    );
+                }
+            }
+            TokenCommand::Revoke(id) => {
+                if !db.revoke_api_token(id).await? {
+                    return Err(anyhow::anyhow!("API token {id} does not exist"));
+                }
+                println!("API token {id} has been revoked");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Export of the audit trail of a repository requested using the command line.
+struct AuditExport {
+    repository: String,
+    since: Option<String>,
+    until: Option<String>,
+    format: String,
+}
+
+impl AuditExport {
+    fn from_opts(opts: &mut Opts) -> Option<Self> {
+        let repository = opts.export_audit.take()?;
+        Some(AuditExport {
+            repository,
+            since: opts.audit_since.take(),
+            until: opts.audit_until.take(),
+            format: opts
+                .audit_format
+                .take()
+                .unwrap_or_else(|| "csv".to_string()),
+        })
+    }
+
+    async fn execute(self, db: &SeaORMClient) -> anyhow::Result<()> {
+        let repo = GithubRepoName::parse(&self.repository)?;
+        let format = AuditFormat::parse(&self.format).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown audit format `{}`, expected `csv` or `jsonl`",
+                self.format
+            )
+        })?;
+        let (since, until) = parse_period(
+            self.since.as_deref(),
+            self.until.as_deref(),
+            chrono::Utc::now(),
+        )?;
+        let records = load_audit_trail(db, &repo, since, until).await?;
+        print!("{}", render_audit_trail(&records, format));
+        Ok(())
+    }
+}
+
+/// Import of the state of a homu instance requested using the command line.
+struct HomuImport {
+    path: PathBuf,
+    repos: Vec<String>,
+}
+
+impl HomuImport {
+    fn from_opts(opts: &mut Opts) -> Option<Self> {
+        let path = opts.import_homu.take()?;
+        Some(HomuImport {
+            path,
+            repos: std::mem::take(&mut opts.homu_repo),
+        })
+    }
+
+    async fn execute(self, db: &SeaORMClient) -> anyhow::Result<()> {
+        let repos = parse_repo_mapping(&self.repos)?;
+        if repos.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Map the repositories of homu using `--homu-repo <label>=<owner>/<name>`"
+            ));
+        }
+        // The database of homu is only read
+        let homu = Database::connect(format!("sqlite://{}?mode=ro", self.path.display()))
+            .await
+            .with_context(|| format!("Cannot open homu database {}", self.path.display()))?;
+        let pulls = load_homu_pulls(&homu).await?;
+        let summary = import_homu_pulls(db, &pulls, &repos).await?;
+        for repo in &summary.skipped_repos {
+            println!("Skipped PRs of unmapped homu repository `{repo}`");
+        }
+        println!(
+            "Imported {} PR(s), {} of them newly approved",
+            summary.pull_requests, summary.approvals
+        );
+        Ok(())
+    }
+}
+
+/// Export or import of a snapshot of the state of the bot requested using the command line.
+enum SnapshotCommand {
+    Export(PathBuf),
+    Import(PathBuf),
+}
+
+impl SnapshotCommand {
+    fn from_opts(opts: &mut Opts) -> Option<Self> {
+        if let Some(path) = opts.export_snapshot.take() {
+            return Some(SnapshotCommand::Export(path));
+        }
+        opts.import_snapshot.take().map(SnapshotCommand::Import)
+    }
+
+    async fn execute(self, mut db: SeaORMClient) -> anyhow::Result<()> {
+        match self {
+            SnapshotCommand::Export(path) => {
+                let snapshot = export_snapshot(db.connection()).await?;
+                let json = serde_json::to_string(&snapshot)?;
+                std::fs::write(&path, json)
+                    .with_context(|| format!("Cannot write snapshot to {}", path.display()))?;
+                print_row_counts("Exported", &snapshot);
+            }
+            SnapshotCommand::Import(path) => {
+                let json = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot read snapshot {}", path.display()))?;
+                let snapshot: Snapshot = serde_json::from_str(&json)
+                    .with_context(|| format!("Cannot parse snapshot {}", path.display()))?;
+                print_row_counts("Importing", &snapshot);
+                import_snapshot(db.connection(), snapshot).await?;
+                println!("The snapshot has been imported");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_row_counts(action: &str, snapshot: &Snapshot) {
+    let counts: Vec<String> = snapshot
+        .row_counts()
+        .into_iter()
+        .map(|(table, count)| format!("{count} {table}"))
+        .collect();
+    println!("{action} {}", counts.join(", "));
+}
+
+/// Changes the log filter of the bot running with the given configuration.
+async fn set_remote_log_filter(config: &GlobalConfig, directives: String) -> anyhow::Result<()> {
+    let token = config
+        .admin_token
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("An admin token is required to change the log filter"))?;
+    let url = format!("http://{}:{}/admin/log-filter", config.host, config.port);
+    let response = bors::http::client()
+        .put(&url)
+        .bearer_auth(token)
+        .body(directives)
+        .send_limited()
+        .await
+        .with_context(|| format!("Cannot connect to {url}"))?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!(
+            "Cannot change the log filter ({status}): {text}"
+        ));
+    }
+    println!("Log filter changed to `{text}`");
+    Ok(())
+}
+
+/// Prints the most recently received webhook deliveries, newest first.
+async fn print_webhook_deliveries(db: &SeaORMClient, limit: u64) -> anyhow::Result<()> {
+    for delivery in db.get_webhook_deliveries(limit).await? {
+        println!(
+            "{}\t{}\treceived at {}\tsha256:{}\t{}",
+            delivery.delivery_id,
+            delivery.event_type,
+            delivery.received_at,
+            delivery.payload_hash,
+            delivery.result
+        );
+    }
+    Ok(())
+}
+
+/// Prints the problems found in a repository configuration file.
+/// Fails if there are any problems.
+fn validate_repository_config(path: &Path) -> anyhow::Result<()> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    for warning in config_warnings(&content) {
+        println!("warning (line {}): {}", warning.line, warning.message);
+    }
+    let problems = validate_config(&content);
+    if problems.is_empty() {
+        println!("{} is valid", path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("- {problem}");
+    }
+    Err(anyhow::anyhow!(
+        "Found {} problem(s) in {}",
+        problems.len(),
+        path.display()
+    ))
+}
+
+/// Applies database migrations. Only the configuration of the database is needed.
+fn migrate(opts: Opts) -> anyhow::Result<()> {
+    let (file, overrides) = opts.into_overrides();
+    let config = DatabaseConfig::load(file.as_deref(), overrides)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Cannot build tokio runtime")?;
+    {
+        let _guard = runtime.enter();
+        init_tracing(None)?;
+    }
+    let db_url = runtime
+        .block_on(config.db.load())
+        .context("Cannot load database connection string")?;
+    runtime
+        .block_on(initialize_db(
+            db_url.expose_secret(),
+            config.db_max_connections,
+        ))
+        .context("Cannot initialize database")?;
+    tracing::info!("Database migrations applied");
+    Ok(())
+}
+
+fn try_main(mut opts: Opts) -> anyhow::Result<()> {
+    if let Some(path) = &opts.validate_config {
+        return validate_repository_config(path);
+    }
+    if opts.migrate_only {
+        return migrate(opts);
+    }
+    let stats = opts.stats.take();
+    let token_command = TokenCommand::from_opts(&mut opts);
+    let list_deliveries = opts.list_webhook_deliveries;
+    let audit_export = AuditExport::from_opts(&mut opts);
+    let homu_import = HomuImport::from_opts(&mut opts);
+    let snapshot_command = SnapshotCommand::from_opts(&mut opts);
+    let set_log_filter = opts.set_log_filter.take();
+    let config = opts.into_config()?;
+
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .context("Cannot build tokio runtime")?;
+    let log_filter = {
+        let _guard = runtime.enter();
+        init_tracing(config.otlp_endpoint.as_deref())?
+    };
+    if let Some(directives) = set_log_filter {
+        return runtime.block_on(set_remote_log_filter(&config, directives));
+    }
+
+    let _sentry = match &config.sentry_dsn {
+        Some(dsn) => {
+            let dsn = runtime
+                .block_on(dsn.load())
+                .context("Cannot load Sentry DSN")?;
+            Some(error_reporting::init(dsn.expose_secret())?)
+        }
+        None => None,
+    };
 
+    let db_url = runtime
+        .block_on(config.db.load())
+        .context("Cannot load database connection string")?;
     let db = runtime
-        .block_on(initialize_db(&opts.db))
+        .block_on(initialize_db(
+            db_url.expose_secret(),
+            config.db_max_connections,
+        ))
         .context("Cannot initialize database")?;
+    if let Some(command) = token_command {
+        return runtime.block_on(command.execute(&db));
+    }
+    if let Some(export) = audit_export {
+        return runtime.block_on(export.execute(&db));
+    }
+    if let Some(import) = homu_import {
+        return runtime.block_on(import.execute(&db));
+    }
+    if let Some(command) = snapshot_command {
+        return runtime.block_on(command.execute(db));
+    }
+    if let Some(limit) = list_deliveries {
+        return runtime.block_on(print_webhook_deliveries(&db, limit));
+    }
+    if let Some(repo) = stats {
+        let repo = GithubRepoName::parse(&repo)?;
+        let stats = runtime
+            .block_on(repository_statistics(&db, &repo))
+            .context("Cannot compute statistics")?;
+        print!("{stats}");
+        return Ok(());
+    }
 
+    let private_key = runtime
+        .block_on(config.private_key.load())
+        .context("Cannot load private key")?;
+    let zulip = match (config.zulip_url, config.zulip_email, &config.zulip_api_key) {
+        (Some(url), Some(email), Some(api_key)) => {
+            let api_key = runtime
+                .block_on(api_key.load())
+                .context("Cannot load Zulip API key")?;
+            Some(ZulipCredentials {
+                url,
+                email,
+                api_key,
+            })
+        }
+        _ => None,
+    };
+    let mut webhooks = HashMap::new();
+    for (name, url) in &config.notification_webhooks {
+        let url = runtime
+            .block_on(url.load())
+            .with_context(|| format!("Cannot load URL of notification webhook `{name}`"))?;
+        webhooks.insert(name.clone(), url);
+    }
+    let email = match (&config.smtp_url, config.smtp_from) {
+        (Some(smtp_url), Some(from)) => {
+            let smtp_url = runtime
+                .block_on(smtp_url.load())
+                .context("Cannot load SMTP URL")?;
+            Some(EmailCredentials { smtp_url, from })
+        }
+        _ => None,
+    };
+    let notification_credentials = NotificationCredentials {
+        zulip,
+        webhooks,
+        email,
+    };
+    let dashboard_db = Arc::new(db.clone());
+    let gitlab_state = match &config.gitlab {
+        Some(gitlab) => {
+            let token = runtime
+                .block_on(gitlab.token.load())
+                .context("Cannot load GitLab token")?;
+            Some(runtime.block_on(GitlabState::load(
+                &gitlab.url,
+                token,
+                gitlab.projects.clone(),
+                db.clone(),
+                notification_credentials.clone(),
+            ))?)
+        }
+        None => None,
+    };
+    let gitea_state = match &config.gitea {
+        Some(gitea) => {
+            let token = runtime
+                .block_on(gitea.token.load())
+                .context("Cannot load Gitea token")?;
+            Some(runtime.block_on(GiteaState::load(
+                &gitea.url,
+                token,
+                gitea.repositories.clone(),
+                db.clone(),
+                notification_credentials.clone(),
+            ))?)
+        }
+        None => None,
+    };
     let state = runtime.block_on(GithubAppState::load(
-        opts.app_id.into(),
-        std::fs::read(opts.private_key)?.into(),
+        config.app_id.into(),
+        private_key.expose_secret().as_bytes().to_vec().into(),
         db,
+        notification_credentials,
     ))?;
-    let ctx = BorsContext::new(CommandParser::new(opts.cmd_prefix));
-    let (tx, gh_process) = create_bors_process(state, ctx);
+    let mut ci = CiServices::default();
+    // Secrets of the webhooks (or callbacks) of the CI services that report results
+    let mut ci_webhook_secrets: Vec<(&dyn Forge, WebhookSecret)> = vec![];
+    if let Some(buildkite) = &config.buildkite {
+        let token = runtime
+            .block_on(buildkite.token.load())
+            .context("Cannot load Buildkite token")?;
+        ci = ci.with_service(Arc::new(BuildkiteClient::new(token)));
+        let webhook_token = runtime
+            .block_on(buildkite.webhook_token.load())
+            .context("Cannot load Buildkite webhook token")?;
+        ci_webhook_secrets.push((
+            &BuildkiteForge,
+            WebhookSecret::new(webhook_token.expose_secret().clone()),
+        ));
+    }
+    if let Some(token) = &config.azure_pipelines_token {
+        let token = runtime
+            .block_on(token.load())
+            .context("Cannot load Azure Pipelines token")?;
+        ci = ci.with_service(Arc::new(AzurePipelinesClient::new(token)));
+    }
+    if let Some(jenkins) = &config.jenkins {
+        let token = runtime
+            .block_on(jenkins.token.load())
+            .context("Cannot load Jenkins token")?;
+        ci = ci.with_service(Arc::new(JenkinsClient::new(
+            &jenkins.url,
+            jenkins.user.clone(),
+            token,
+        )?));
+        let callback_secret = runtime
+            .block_on(jenkins.callback_secret.load())
+            .context("Cannot load Jenkins callback secret")?;
+        ci_webhook_secrets.push((
+            &JenkinsForge,
+            WebhookSecret::new(callback_secret.expose_secret().clone()),
+        ));
+    }
+    if let Some(circleci) = &config.circleci {
+        let token = runtime
+            .block_on(circleci.token.load())
+            .context("Cannot load CircleCI token")?;
+        ci = ci.with_service(Arc::new(CircleCiClient::new(token)));
+        let webhook_secret = runtime
+            .block_on(circleci.webhook_secret.load())
+            .context("Cannot load CircleCI webhook secret")?;
+        ci_webhook_secrets.push((
+            &CircleCiForge,
+            WebhookSecret::new(webhook_secret.expose_secret().clone()),
+        ));
+    }
+    let ci = Arc::new(ci);
+    let metrics = Arc::new(CommandMetrics::default());
+    let consistency = Arc::new(ConsistencyReports::default());
+    if config.dry_run {
+        tracing::warn!("Running in dry-run mode, no branches are pushed and nothing is merged");
+    }
+    let lease = match config.replica_id.clone() {
+        Some(holder) => {
+            let duration = config
+                .lease_duration
+                .map_or(PERIODIC_REFRESH * LEASE_REFRESHES, Duration::from_secs);
+            tracing::info!("Running as replica {holder}, repositories are leased for {duration:?}");
+            Some(LeaseConfig {
+                holder,
+                duration: chrono::Duration::from_std(duration).context("Invalid lease duration")?,
+            })
+        }
+        None => None,
+    };
+    let create_ctx = || {
+        BorsContext::new(CommandParser::new(config.cmd_prefix.clone()))
+            .with_metrics(metrics.clone())
+            .with_consistency(consistency.clone())
+            .with_ci(ci.clone())
+            .with_restart_notice(config.restart_notice)
+            .with_dry_run(config.dry_run)
+            .with_lease(lease.clone())
+            .with_webhook_delivery_retention_days(config.webhook_delivery_retention_days)
+    };
+    let (updates, _) = broadcast::channel(DASHBOARD_UPDATES_CAPACITY);
+    // Stops the event loops once the server has stopped accepting webhooks
+    let (stop_tx, stop_rx) = watch::channel(());
+    let stopped = || {
+        let mut stop_rx = stop_rx.clone();
+        async move {
+            let _ = stop_rx.changed().await;
+        }
+    };
+    let (tx, gh_process) =
+        create_bors_process("github", state, create_ctx(), updates.clone(), stopped());
+    let gitlab = gitlab_state.map(|state| {
+        create_bors_process("gitlab", state, create_ctx(), updates.clone(), stopped())
+    });
+    let gitea = gitea_state
+        .map(|state| create_bors_process("gitea", state, create_ctx(), updates.clone(), stopped()));
 
-    let refresh_tx = tx.clone();
+    let mut refresh_txs = vec![tx.clone()];
+    refresh_txs.extend(gitlab.as_ref().map(|(tx, _)| tx.clone()));
+    refresh_txs.extend(gitea.as_ref().map(|(tx, _)| tx.clone()));
+    heartbeat(Heartbeat::Started);
     let refresh_process = async move {
+        for refresh_tx in &refresh_txs {
+            refresh_tx.send(BorsEvent::Reconcile).await?;
+        }
+        let mut last_sweep = Instant::now();
         loop {
             tokio::time::sleep(PERIODIC_REFRESH).await;
-            refresh_tx.send(BorsEvent::Refresh).await?;
+            for refresh_tx in &refresh_txs {
+                refresh_tx.send(BorsEvent::Refresh).await?;
+            }
+            heartbeat(Heartbeat::RefreshScheduled);
+            if last_sweep.elapsed() >= CONSISTENCY_SWEEP {
+                for refresh_tx in &refresh_txs {
+                    refresh_tx.send(BorsEvent::Sweep).await?;
+                }
+                last_sweep = Instant::now();
+            }
         }
     };
 
-    let state = ServerState::new(tx, WebhookSecret::new(opts.webhook_secret));
-    let server_process = server(state);
+    let webhook_secret = runtime
+        .block_on(config.webhook_secret.load())
+        .context("Cannot load webhook secret")?;
+    let oauth = match (config.oauth_client_id, &config.oauth_client_secret) {
+        (Some(client_id), Some(client_secret)) => {
+            let client_secret = runtime
+                .block_on(client_secret.load())
+                .context("Cannot load OAuth client secret")?;
+            Some(OAuthConfig::new(client_id, client_secret))
+        }
+        _ => None,
+    };
+    let fixture_recorder = config
+        .record_webhooks
+        .map(FixtureRecorder::new)
+        .transpose()
+        .context("Cannot record webhooks")?;
+    let mut server_state = ServerState::new(
+        tx,
+        WebhookSecret::new(webhook_secret.expose_secret().clone()),
+        config.admin_token,
+    )
+    .with_dashboard(dashboard_db, updates)
+    .with_api_token(config.api_token)
+    .with_oauth(oauth)
+    .with_metrics(metrics)
+    .with_consistency(consistency)
+    .with_log_filter(Arc::new(log_filter))
+    .with_max_webhook_size(config.max_webhook_size)
+    .with_fixture_recorder(fixture_recorder);
+    for (service, secret) in ci_webhook_secrets {
+        server_state = server_state.with_ci_webhook_secret(service, secret);
+    }
+    let gitlab_process = match gitlab {
+        Some((gitlab_tx, process)) => {
+            server_state = server_state.with_forge(&GitlabForge, gitlab_tx);
+            Some(process)
+        }
+        None => None,
+    };
+    let gitea_process = match gitea {
+        Some((gitea_tx, process)) => {
+            server_state = server_state.with_forge(&GiteaForge, gitea_tx);
+            Some(process)
+        }
+        None => None,
+    };
+    let state = Arc::new(server_state);
+    let secret_process = rotate_webhook_secret(state.clone(), config.webhook_secret);
+    let server_process = server(state, SocketAddr::from((config.host, config.port)));
 
     let fut = async move {
-        tokio::select! {
-            () = gh_process => {
+        let mut event_loops = vec![];
+        if let Some(gitlab_process) = gitlab_process {
+            event_loops.push(tokio::task::spawn_local(gitlab_process));
+        }
+        if let Some(gitea_process) = gitea_process {
+            event_loops.push(tokio::task::spawn_local(gitea_process));
+        }
+        tokio::pin!(gh_process);
+        let result = tokio::select! {
+            () = &mut gh_process => {
                 tracing::warn!("Github webhook process has ended");
-                Ok(())
+                return Ok(());
             },
             res = refresh_process => {
                 tracing::warn!("Refresh generator has ended");
                 res
             }
             res = server_process => {
-                tracing::warn!("Server has ended: {res:?}");
+                match &res {
+                    Ok(()) => tracing::info!("Server has stopped"),
+                    Err(error) => tracing::warn!("Server has ended: {error:?}"),
+                }
+                res
+            }
+            res = secret_process => {
+                tracing::warn!("Webhook secret rotation has ended: {res:?}");
                 res
             }
+            () = watchdog() => {
+                tracing::warn!("Watchdog has ended");
+                Ok(())
+            }
+        };
+
+        // Let the event loops handle the events they have already received
+        let _ = stop_tx.send(());
+        gh_process.await;
+        for event_loop in event_loops {
+            let _ = event_loop.await;
         }
+        result
     };
 
     runtime.block_on(async move {
         let set = LocalSet::new();
         set.run_until(fut).await.unwrap();
     });
+    // Sends the remaining spans
+    opentelemetry::global::shutdown_tracer_provider();
 
     Ok(())
 }
 
+/// Initializes the log output to stderr, filtered by the `RUST_LOG` environment variable, and, if
+/// `otlp_endpoint` is set, the export of the spans of the bot (up to the debug level) to an
+/// OpenTelemetry collector. Returns the filter of the log output, which can be changed while the
+/// bot is running; it does not apply to the exported spans. It has to be called in the context of
+/// the tokio runtime, which sends the spans.
+fn init_tracing(otlp_endpoint: Option<&str>) -> anyhow::Result<LogFilter> {
+    let otlp = match otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(
+                    opentelemetry::sdk::trace::config()
+                        .with_resource(Resource::new([KeyValue::new("service.name", "bors")])),
+                )
+                .install_batch(opentelemetry::runtime::Tokio)
+                .context("Cannot create OTLP exporter")?;
+            // API calls and DB queries are traced at the debug level
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(Targets::new().with_target("bors", Level::DEBUG)),
+            )
+        }
+        None => None,
+    };
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| "error".to_string());
+    let (log_filter, filter_layer) = LogFilter::new(&directives)?;
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_filter(filter_layer),
+        )
+        .with(otlp)
+        .init();
+    Ok(log_filter)
+}
+
 fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .with_target(false)
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
 
     let opts = Opts::parse();
     if let Err(error) = try_main(opts) {
@@ -0,0 +1,347 @@
+//! Command line client for operators of a running bot. Every command is a request to an admin
+//! endpoint of the bot, authenticated with a token with the `admin` scope.
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use dotenv::dotenv;
+use reqwest::{Method, StatusCode};
+
+use bors::http::SendLimited;
+
+/// Manages a running bors instance using its admin endpoints.
+#[derive(clap::Parser)]
+struct Opts {
+    /// URL of the bot, e.g. `https://bors.example.com`.
+    #[arg(long, env = "BORS_URL")]
+    url: String,
+
+    /// The admin token of the bot or an API token with the `admin` scope.
+    #[arg(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    token: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// List the repositories known to the bot and their merge queues.
+    Repos,
+    /// Show the discrepancies with the forge repaired by the last consistency sweep of each
+    /// repository.
+    Consistency,
+    /// Remove a PR from the merge queue, regardless of who has approved it.
+    Dequeue {
+        /// Repository of the PR (`<owner>/<name>`).
+        repository: String,
+        number: u64,
+    },
+    /// Reconcile the state of a repository with its forge (running builds, the GitHub merge
+    /// queue), like when the bot starts.
+    Resync {
+        /// Repository (`<owner>/<name>`).
+        repository: String,
+    },
+    /// Pause a repository: commands are still acknowledged, but no builds are started and nothing
+    /// is merged until it is resumed.
+    Pause {
+        /// Repository (`<owner>/<name>`).
+        repository: String,
+        /// Reason of the pause, shown in comments of the bot.
+        #[arg(default_value = "")]
+        reason: String,
+    },
+    /// Resume a paused repository.
+    Resume {
+        /// Repository (`<owner>/<name>`).
+        repository: String,
+    },
+    /// Replace an API token with a new token with the same name and scopes, and revoke it.
+    RotateToken { id: i32 },
+    /// Process a stored webhook delivery again.
+    Replay { delivery_id: String },
+    /// Check a repository configuration file (`rust-bors.toml`) using the version of the running
+    /// bot.
+    ValidateConfig { path: PathBuf },
+}
+
+#[derive(serde::Deserialize)]
+struct Repository {
+    name: String,
+    paused: bool,
+    #[serde(default)]
+    leader: Option<String>,
+    queue: Vec<QueueEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct SweepReport {
+    repository: String,
+    swept_at: String,
+    discrepancies: Vec<Discrepancy>,
+}
+
+#[derive(serde::Deserialize)]
+struct Discrepancy {
+    kind: String,
+    pr: Option<u64>,
+    description: String,
+}
+
+#[derive(serde::Deserialize)]
+struct QueueEntry {
+    number: u64,
+    status: String,
+    priority: u32,
+    approved_by: Option<String>,
+    try_build: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RotatedToken {
+    id: i32,
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ConfigValidation {
+    problems: Vec<String>,
+    warnings: Vec<ConfigWarning>,
+}
+
+#[derive(serde::Deserialize)]
+struct ConfigWarning {
+    line: usize,
+    message: String,
+}
+
+/// Sends requests to the admin endpoints of the bot.
+struct AdminClient {
+    url: String,
+    token: String,
+}
+
+impl AdminClient {
+    /// Sends a request and returns the status and the body of its successful response.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let url = format!("{}{path}", self.url.trim_end_matches('/'));
+        let mut request = bors::http::client()
+            .request(method, &url)
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        let response = request
+            .send_limited()
+            .await
+            .with_context(|| format!("Cannot connect to {url}"))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(anyhow::anyhow!(
+                "The token cannot be used for admin endpoints ({status})"
+            )),
+            status if !status.is_success() => Err(anyhow::anyhow!(
+                "Request to {url} failed ({status}): {text}"
+            )),
+            status => Ok((status, text)),
+        }
+    }
+
+    async fn request_json<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> anyhow::Result<T> {
+        let (_, text) = self.request(method, path, body).await?;
+        serde_json::from_str(&text).with_context(|| format!("Cannot parse the response of {path}"))
+    }
+}
+
+impl Command {
+    async fn execute(self, client: &AdminClient) -> anyhow::Result<()> {
+        match self {
+            Command::Repos => {
+                let repositories: Vec<Repository> = client
+                    .request_json(Method::GET, "/admin/repos", None)
+                    .await?;
+                for repository in repositories {
+                    let paused = if repository.paused { ", paused" } else { "" };
+                    let leader = repository
+                        .leader
+                        .map(|leader| format!(", driven by {leader}"))
+                        .unwrap_or_default();
+                    println!(
+                        "{} ({} in queue{paused}{leader})",
+                        repository.name,
+                        repository.queue.len()
+                    );
+                    for entry in repository.queue {
+                        let mut line = format!(
+                            "  #{}\t{}\tp={}",
+                            entry.number, entry.status, entry.priority
+                        );
+                        if let Some(approver) = entry.approved_by {
+                            line.push_str(&format!("\tapproved by {approver}"));
+                        }
+                        if let Some(status) = entry.try_build {
+                            line.push_str(&format!("\ttry build {status}"));
+                        }
+                        println!("{line}");
+                    }
+                }
+            }
+            Command::Consistency => {
+                let reports: Vec<SweepReport> = client
+                    .request_json(Method::GET, "/admin/consistency", None)
+                    .await?;
+                for report in reports {
+                    println!(
+                        "{} (swept at {}, {} discrepancies)",
+                        report.repository,
+                        report.swept_at,
+                        report.discrepancies.len()
+                    );
+                    for discrepancy in report.discrepancies {
+                        let pr = discrepancy
+                            .pr
+                            .map(|pr| format!("#{pr}"))
+                            .unwrap_or_else(|| "-".to_string());
+                        println!("  {pr}\t{}\t{}", discrepancy.kind, discrepancy.description);
+                    }
+                }
+            }
+            Command::Dequeue { repository, number } => {
+                check_repository(&repository)?;
+                client
+                    .request(
+                        Method::POST,
+                        &format!("/admin/repos/{repository}/prs/{number}/dequeue"),
+                        None,
+                    )
+                    .await?;
+                println!("{repository}#{number} will be removed from the queue");
+            }
+            Command::Resync { repository } => {
+                check_repository(&repository)?;
+                client
+                    .request(
+                        Method::POST,
+                        &format!("/admin/repos/{repository}/resync"),
+                        None,
+                    )
+                    .await?;
+                println!("{repository} will be reconciled");
+            }
+            Command::Pause { repository, reason } => {
+                check_repository(&repository)?;
+                client
+                    .request(
+                        Method::POST,
+                        &format!("/admin/repos/{repository}/pause"),
+                        Some(reason),
+                    )
+                    .await?;
+                println!("{repository} will be paused");
+            }
+            Command::Resume { repository } => {
+                check_repository(&repository)?;
+                client
+                    .request(
+                        Method::POST,
+                        &format!("/admin/repos/{repository}/resume"),
+                        None,
+                    )
+                    .await?;
+                println!("{repository} will be resumed");
+            }
+            Command::RotateToken { id } => {
+                let rotated: RotatedToken = client
+                    .request_json(Method::POST, &format!("/admin/tokens/{id}/rotate"), None)
+                    .await?;
+                println!(
+                    "API token {id} has been revoked and replaced by API token {}:\n{}",
+                    rotated.id, rotated.token
+                );
+                println!("Store it now, it cannot be shown again.");
+            }
+            Command::Replay { delivery_id } => {
+                let (status, text) = client
+                    .request(
+                        Method::POST,
+                        &format!("/admin/deliveries/{delivery_id}/replay"),
+                        None,
+                    )
+                    .await?;
+                if status == StatusCode::ACCEPTED {
+                    println!("Delivery {delivery_id} has been replayed");
+                } else {
+                    println!("{text}");
+                }
+            }
+            Command::ValidateConfig { path } => {
+                let content = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot read {}", path.display()))?;
+                let validation: ConfigValidation = client
+                    .request_json(Method::POST, "/admin/validate-config", Some(content))
+                    .await?;
+                for warning in validation.warnings {
+                    println!("warning (line {}): {}", warning.line, warning.message);
+                }
+                if validation.problems.is_empty() {
+                    println!("{} is valid", path.display());
+                    return Ok(());
+                }
+                for problem in &validation.problems {
+                    println!("- {problem}");
+                }
+                return Err(anyhow::anyhow!(
+                    "Found {} problem(s) in {}",
+                    validation.problems.len(),
+                    path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_repository(repository: &str) -> anyhow::Result<()> {
+    match repository.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!(
+            "Repository must be in the format <owner>/<name>"
+        )),
+    }
+}
+
+fn try_main(opts: Opts) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Cannot build tokio runtime")?;
+    let client = AdminClient {
+        url: opts.url,
+        token: opts.token,
+    };
+    runtime.block_on(opts.command.execute(&client))
+}
+
+fn main() {
+    dotenv().ok();
+
+    let opts = Opts::parse();
+    if let Err(error) = try_main(opts) {
+        eprintln!("Error: {error:?}");
+        std::process::exit(1);
+    }
+}
@@ -1,17 +1,99 @@
 mod parser;
+use crate::database::RollupMode;
 use crate::github::CommitSha;
+use crate::permissions::PermissionType;
 pub use parser::{CommandParseError, CommandParser};
 
+/// Who should be marked as the approver of a PR.
+#[derive(Debug, PartialEq)]
+pub enum Approver {
+    /// The author of the command (`r+`).
+    Myself,
+    /// A specific user (`r=<user>`).
+    Specified(String),
+}
+
 /// Bors command specified by a user.
 #[derive(Debug, PartialEq)]
 pub enum BorsCommand {
     /// Ping the bot.
     Ping,
+    /// Approve a PR, which queues it for being merged.
+    Approve {
+        approver: Approver,
+        /// Approve the PR even if it exceeds the size limits of the repository
+        /// (`ignore-limits`).
+        ignore_limits: bool,
+        /// Head commit (or its prefix) that is being approved (`r+ <sha>`). The approval is
+        /// refused if the PR has a different head, and it is pinned to the head otherwise.
+        sha: Option<String>,
+    },
+    /// Remove the approval of a PR.
+    Unapprove,
     /// Perform a try build.
     Try {
         /// Parent commit which should be used as the merge base.
         parent: Option<CommitSha>,
+        /// Commit of the PR that should be tested instead of its head, e.g. to bisect the history
+        /// of the PR.
+        commit: Option<CommitSha>,
+        /// Filter of CI jobs that should be executed.
+        /// It is passed to the CI workflow when the try build is started using a workflow dispatch.
+        jobs: Option<String>,
     },
     /// Cancel a try build.
     TryCancel,
+    /// Set the priority of a PR in the merge queue (`p=<priority>`).
+    SetPriority { priority: u32 },
+    /// Set the rollup mode of a PR (`rollup=<mode>`, or `rollup` for `always`).
+    SetRollup { mode: RollupMode },
+    /// Remove the rollup mode of a PR, so that it is no longer considered for rollups
+    /// (`rollup-`).
+    ClearRollup,
+    /// Close the tree for PRs below the given priority (`treeclosed=<priority> <reason>`).
+    TreeClosed { priority: u32, reason: String },
+    /// Reopen the tree (`treeclosed-`).
+    TreeOpen,
+    /// Pause the repository, so that no builds are started and nothing is merged
+    /// (`pause <reason>`).
+    Pause { reason: String },
+    /// Resume a paused repository (`resume`).
+    Resume,
+}
+
+impl BorsCommand {
+    /// Name of the command used in metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BorsCommand::Ping => "ping",
+            BorsCommand::Approve { .. } => "approve",
+            BorsCommand::Unapprove => "unapprove",
+            BorsCommand::Try { .. } => "try",
+            BorsCommand::TryCancel => "try_cancel",
+            BorsCommand::SetPriority { .. } => "set_priority",
+            BorsCommand::SetRollup { .. } => "set_rollup",
+            BorsCommand::ClearRollup => "clear_rollup",
+            BorsCommand::TreeClosed { .. } => "tree_closed",
+            BorsCommand::TreeOpen => "tree_open",
+            BorsCommand::Pause { .. } => "pause",
+            BorsCommand::Resume => "resume",
+        }
+    }
+
+    /// Permission that the author of the command has to have, if any.
+    pub fn required_permission(&self) -> Option<PermissionType> {
+        match self {
+            BorsCommand::Ping => None,
+            BorsCommand::Try { .. } | BorsCommand::TryCancel => Some(PermissionType::Try),
+            BorsCommand::Approve { .. }
+            | BorsCommand::Unapprove
+            | BorsCommand::SetPriority { .. }
+            | BorsCommand::SetRollup { .. }
+            | BorsCommand::ClearRollup
+            | BorsCommand::TreeClosed { .. }
+            | BorsCommand::TreeOpen
+            | BorsCommand::Pause { .. }
+            | BorsCommand::Resume => Some(PermissionType::Review),
+        }
+    }
 }
@@ -2,7 +2,8 @@
 
 use std::collections::HashSet;
 
-use crate::bors::command::BorsCommand;
+use crate::bors::command::{Approver, BorsCommand};
+use crate::database::RollupMode;
 use crate::github::CommitSha;
 
 #[derive(Debug, PartialEq)]
@@ -31,6 +32,10 @@ impl CommandParser {
         Self { prefix }
     }
 
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
     /// Parses bors commands from the given string.
     ///
     /// Assumes that each command spands at most one line and that there are not more commands on
@@ -40,8 +45,18 @@ impl CommandParser {
         text: &'a str,
     ) -> Vec<Result<BorsCommand, CommandParseError<'a>>> {
         // The order of the parsers in the vector is important
-        let parsers: Vec<for<'b> fn(&'b str, &[CommandPart<'b>]) -> ParseResult<'b>> =
-            vec![parser_ping, parser_try_cancel, parser_try];
+        let parsers: Vec<for<'b> fn(&'b str, &[CommandPart<'b>]) -> ParseResult<'b>> = vec![
+            parser_ping,
+            parser_approve,
+            parser_unapprove,
+            parser_rollup,
+            parser_rollup_unset,
+            parser_try_cancel,
+            parser_try,
+            parser_tree_open,
+            parser_pause,
+            parser_resume,
+        ];
 
         text.lines()
             .filter_map(|line| match line.find(&self.prefix) {
@@ -62,8 +77,17 @@ impl CommandParser {
                                         }
                                         Some(Err(CommandParseError::UnknownCommand(command)))
                                     }
-                                    CommandPart::KeyValue { .. } => {
-                                        Some(Err(CommandParseError::MissingCommand))
+                                    CommandPart::KeyValue { key, value } => {
+                                        if let Some(result) = parser_priority(key, value, rest) {
+                                            return Some(result);
+                                        }
+                                        if let Some(result) = parser_tree_closed(key, value, rest) {
+                                            return Some(result);
+                                        }
+                                        if let Some(result) = parser_rollup_mode(key, value, rest) {
+                                            return Some(result);
+                                        }
+                                        Some(parser_approve_specified(key, value, rest))
                                     }
                                 }
                             }
@@ -117,6 +141,220 @@ fn parser_ping<'a>(command: &'a str, _parts: &[CommandPart<'a>]) -> ParseResult<
     }
 }
 
+/// Parses "@bors r+ <sha> <ignore-limits>".
+fn parser_approve<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "r+" {
+        return None;
+    }
+    Some(
+        parse_approve_args(parts).map(|(ignore_limits, sha)| BorsCommand::Approve {
+            approver: Approver::Myself,
+            ignore_limits,
+            sha,
+        }),
+    )
+}
+
+/// Parses "@bors r=<user> <sha> <ignore-limits>".
+fn parser_approve_specified<'a>(
+    key: &'a str,
+    value: &'a str,
+    parts: &[CommandPart<'a>],
+) -> Result<BorsCommand, CommandParseError<'a>> {
+    if key != "r" {
+        return Err(CommandParseError::MissingCommand);
+    }
+    let (ignore_limits, sha) = parse_approve_args(parts)?;
+    Ok(BorsCommand::Approve {
+        approver: Approver::Specified(value.trim_start_matches('@').to_string()),
+        ignore_limits,
+        sha,
+    })
+}
+
+/// Parses the arguments of an approval, returns whether the size limits should be ignored and
+/// the approved head commit.
+fn parse_approve_args<'a>(
+    parts: &[CommandPart<'a>],
+) -> Result<(bool, Option<String>), CommandParseError<'a>> {
+    let mut ignore_limits = false;
+    let mut sha = None;
+    for part in parts {
+        match part {
+            CommandPart::Bare("ignore-limits") if !ignore_limits => ignore_limits = true,
+            CommandPart::Bare("ignore-limits") => {
+                return Err(CommandParseError::DuplicateArg("ignore-limits"))
+            }
+            CommandPart::Bare(value) if is_sha_prefix(value) => {
+                if sha.is_some() {
+                    return Err(CommandParseError::DuplicateArg("sha"));
+                }
+                sha = Some(value.to_lowercase());
+            }
+            part => return Err(unknown_arg(part)),
+        }
+    }
+    Ok((ignore_limits, sha))
+}
+
+/// Is the value an (abbreviated) commit SHA, like the ones shown by GitHub?
+fn is_sha_prefix(value: &str) -> bool {
+    (7..=40).contains(&value.len()) && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Parses "@bors r-".
+fn parser_unapprove<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "r-" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(Ok(BorsCommand::Unapprove))
+}
+
+/// Parses "@bors rollup", which marks the PR to be always included in rollups, like in homu.
+fn parser_rollup<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "rollup" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(Ok(BorsCommand::SetRollup {
+        mode: RollupMode::Always,
+    }))
+}
+
+/// Parses "@bors rollup=<always|maybe|iffy|never>".
+fn parser_rollup_mode<'a>(
+    key: &'a str,
+    value: &'a str,
+    parts: &[CommandPart<'a>],
+) -> ParseResult<'a> {
+    if key != "rollup" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(
+        RollupMode::from_name(value)
+            .map(|mode| BorsCommand::SetRollup { mode })
+            .ok_or_else(|| {
+                CommandParseError::ValidationError(format!(
+                    "Rollup mode has to be one of `always`, `maybe`, `iffy` or `never`, found `{value}`"
+                ))
+            }),
+    )
+}
+
+/// Parses "@bors rollup-".
+fn parser_rollup_unset<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "rollup-" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(Ok(BorsCommand::ClearRollup))
+}
+
+/// Parses "@bors p=<priority>" or "@bors priority=<priority>".
+fn parser_priority<'a>(key: &'a str, value: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if key != "p" && key != "priority" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(
+        value
+            .parse::<u32>()
+            .map(|priority| BorsCommand::SetPriority { priority })
+            .map_err(|_| {
+                CommandParseError::ValidationError(format!(
+                    "Priority has to be a non-negative integer, found `{value}`"
+                ))
+            }),
+    )
+}
+
+/// Parses "@bors treeclosed=<priority> <reason>". The reason is the rest of the line.
+fn parser_tree_closed<'a>(
+    key: &'a str,
+    value: &'a str,
+    parts: &[CommandPart<'a>],
+) -> ParseResult<'a> {
+    if key != "treeclosed" {
+        return None;
+    }
+    let priority = match value.parse::<u32>() {
+        Ok(priority) => priority,
+        Err(_) => {
+            return Some(Err(CommandParseError::ValidationError(format!(
+                "Tree closure priority has to be a non-negative integer, found `{value}`"
+            ))))
+        }
+    };
+    Some(Ok(BorsCommand::TreeClosed {
+        priority,
+        reason: join_parts(parts),
+    }))
+}
+
+/// Parses "@bors pause <reason>". The reason is the rest of the line.
+fn parser_pause<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "pause" {
+        return None;
+    }
+    Some(Ok(BorsCommand::Pause {
+        reason: join_parts(parts),
+    }))
+}
+
+/// Parses "@bors resume".
+fn parser_resume<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "resume" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(Ok(BorsCommand::Resume))
+}
+
+/// Joins the parts of a command back into free text, e.g. a reason.
+fn join_parts(parts: &[CommandPart]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            CommandPart::Bare(word) => word.to_string(),
+            CommandPart::KeyValue { key, value } => format!("{key}={value}"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses "@bors treeclosed-".
+fn parser_tree_open<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
+    if command != "treeclosed-" {
+        return None;
+    }
+    if let Some(part) = parts.first() {
+        return Some(Err(unknown_arg(part)));
+    }
+    Some(Ok(BorsCommand::TreeOpen))
+}
+
+fn unknown_arg<'a>(part: &CommandPart<'a>) -> CommandParseError<'a> {
+    match part {
+        CommandPart::Bare(key) | CommandPart::KeyValue { key, .. } => {
+            CommandParseError::UnknownArg(*key)
+        }
+    }
+}
+
 fn parse_sha(input: &str) -> Result<CommitSha, String> {
     if input.len() != 40 {
         return Err("SHA must have exactly 40 characters".to_string());
@@ -124,13 +362,15 @@ fn parse_sha(input: &str) -> Result<CommitSha, String> {
     Ok(CommitSha(input.to_string()))
 }
 
-/// Parses "@bors try <parent=sha>".
+/// Parses "@bors try <parent=sha> <commit=sha> <jobs=filter>".
 fn parser_try<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a> {
     if command != "try" {
         return None;
     }
 
     let mut parent = None;
+    let mut commit = None;
+    let mut jobs = None;
 
     for part in parts {
         match part {
@@ -147,13 +387,28 @@ fn parser_try<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseResult<'a
                             ))));
                         }
                     };
+                } else if *key == "commit" {
+                    commit = match parse_sha(value) {
+                        Ok(sha) => Some(sha),
+                        Err(error) => {
+                            return Some(Err(CommandParseError::ValidationError(format!(
+                                "Try commit has to be a valid commit SHA: {error}"
+                            ))));
+                        }
+                    };
+                } else if *key == "jobs" {
+                    jobs = Some(value.to_string());
                 } else {
                     return Some(Err(CommandParseError::UnknownArg(key)));
                 }
             }
         }
     }
-    Some(Ok(BorsCommand::Try { parent }))
+    Some(Ok(BorsCommand::Try {
+        parent,
+        commit,
+        jobs,
+    }))
 }
 
 /// Parses "@bors try cancel".
@@ -168,12 +423,13 @@ fn parser_try_cancel<'a>(command: &'a str, parts: &[CommandPart<'a>]) -> ParseRe
 #[cfg(test)]
 mod tests {
     use crate::bors::command::parser::{CommandParseError, CommandParser};
-    use crate::bors::command::BorsCommand;
+    use crate::bors::command::{Approver, BorsCommand};
+    use crate::database::RollupMode;
     use crate::github::CommitSha;
+    use crate::global_config::DEFAULT_CMD_PREFIX;
 
     fn get_command_prefix() -> String {
-        dotenv::dotenv().ok();
-        std::env::var("CMD_PREFIX").unwrap()
+        DEFAULT_CMD_PREFIX.to_string()
     }
 
     #[test]
@@ -248,7 +504,128 @@ line two"#,
         );
         let cmds = parse_commands(&command);
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Try { parent: None })));
+        assert!(matches!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: None,
+                commit: None,
+                jobs: None
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_approve() {
+        let cmds = parse_commands(&format!("{} r+", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Myself,
+                ignore_limits: false,
+                sha: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_approve_ignore_limits() {
+        let cmds = parse_commands(&format!("{} r+ ignore-limits", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Myself,
+                ignore_limits: true,
+                sha: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_approve_specified_ignore_limits() {
+        let cmds = parse_commands(&format!("{} r=foo ignore-limits", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Specified("foo".to_string()),
+                ignore_limits: true,
+                sha: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_approve_specified() {
+        let cmds = parse_commands(&format!("{} r=@foo", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Specified("foo".to_string()),
+                ignore_limits: false,
+                sha: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_approve_sha() {
+        let cmds = parse_commands(&format!(
+            "{} r=foo 1a2B3c4 ignore-limits",
+            get_command_prefix()
+        ));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Approve {
+                approver: Approver::Specified("foo".to_string()),
+                ignore_limits: true,
+                sha: Some("1a2b3c4".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn parse_approve_duplicate_sha() {
+        let cmds = parse_commands(&format!("{} r+ 1a2b3c4 5d6e7f8", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Err(CommandParseError::DuplicateArg("sha")));
+    }
+
+    #[test]
+    fn parse_approve_unknown_arg() {
+        let cmds = parse_commands(&format!("{} r+ foo", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Err(CommandParseError::UnknownArg("foo")));
+    }
+
+    #[test]
+    fn parse_unapprove() {
+        let cmds = parse_commands(&format!("{} r-", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Unapprove));
+    }
+
+    #[test]
+    fn parse_priority() {
+        let cmds = parse_commands(&format!("{} p=5", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::SetPriority { priority: 5 }));
+
+        let cmds = parse_commands(&format!("{} priority=10", get_command_prefix()));
+        assert_eq!(cmds[0], Ok(BorsCommand::SetPriority { priority: 10 }));
+    }
+
+    #[test]
+    fn parse_priority_invalid() {
+        let cmds = parse_commands(&format!("{} p=high", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(
+            cmds[0],
+            Err(CommandParseError::ValidationError(_))
+        ));
     }
 
     #[test]
@@ -256,7 +633,14 @@ line two"#,
         let command = format!("{} try", get_command_prefix());
         let cmds = parse_commands(&command);
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Try { parent: None })));
+        assert!(matches!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: None,
+                commit: None,
+                jobs: None
+            })
+        ));
     }
 
     #[test]
@@ -272,7 +656,58 @@ line two"#,
             Ok(BorsCommand::Try {
                 parent: Some(CommitSha(
                     "ea9c1b050cc8b420c2c211d2177811e564a4dc60".to_string()
-                ))
+                )),
+                commit: None,
+                jobs: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_try_commit() {
+        let command = format!(
+            "{} try commit=ea9c1b050cc8b420c2c211d2177811e564a4dc60",
+            get_command_prefix()
+        );
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: None,
+                commit: Some(CommitSha(
+                    "ea9c1b050cc8b420c2c211d2177811e564a4dc60".to_string()
+                )),
+                jobs: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_try_commit_invalid() {
+        let command = format!("{} try commit=foo", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        insta::assert_debug_snapshot!(cmds[0], @r###"
+        Err(
+            ValidationError(
+                "Try commit has to be a valid commit SHA: SHA must have exactly 40 characters",
+            ),
+        )
+        "###);
+    }
+
+    #[test]
+    fn parse_try_jobs() {
+        let command = format!("{} try jobs=x86_64-linux,aarch64-*", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: None,
+                commit: None,
+                jobs: Some("x86_64-linux,aarch64-*".to_string())
             })
         );
     }
@@ -317,7 +752,14 @@ line two"#,
         );
         let cmds = parse_commands(&command);
         assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], Ok(BorsCommand::Try { parent: None })));
+        assert!(matches!(
+            cmds[0],
+            Ok(BorsCommand::Try {
+                parent: None,
+                commit: None,
+                jobs: None
+            })
+        ));
     }
 
     #[test]
@@ -328,6 +770,114 @@ line two"#,
         assert!(matches!(cmds[0], Ok(BorsCommand::TryCancel)));
     }
 
+    #[test]
+    fn parse_tree_closed() {
+        let command = format!(
+            "{} treeclosed=100 CI is broken, see #123",
+            get_command_prefix()
+        );
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::TreeClosed {
+                priority: 100,
+                reason: "CI is broken, see #123".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_tree_closed_invalid_priority() {
+        let command = format!("{} treeclosed=foo", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(
+            cmds[0],
+            Err(CommandParseError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn parse_tree_open() {
+        let command = format!("{} treeclosed-", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::TreeOpen));
+    }
+
+    #[test]
+    fn parse_rollup() {
+        let cmds = parse_commands(&format!("{} rollup", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::SetRollup {
+                mode: RollupMode::Always
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rollup_mode() {
+        let cmds = parse_commands(&format!("{} rollup=iffy", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::SetRollup {
+                mode: RollupMode::Iffy
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rollup_invalid_mode() {
+        let cmds = parse_commands(&format!("{} rollup=sometimes", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        insta::assert_debug_snapshot!(cmds[0], @r###"
+        Err(
+            ValidationError(
+                "Rollup mode has to be one of `always`, `maybe`, `iffy` or `never`, found `sometimes`",
+            ),
+        )
+        "###);
+    }
+
+    #[test]
+    fn parse_rollup_unset() {
+        let cmds = parse_commands(&format!("{} rollup-", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::ClearRollup));
+    }
+
+    #[test]
+    fn parse_rollup_unset_unknown_arg() {
+        let cmds = parse_commands(&format!("{} rollup- always", get_command_prefix()));
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Err(CommandParseError::UnknownArg("always")));
+    }
+
+    #[test]
+    fn parse_pause() {
+        let command = format!("{} pause Release freeze until v1.0", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(
+            cmds[0],
+            Ok(BorsCommand::Pause {
+                reason: "Release freeze until v1.0".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_resume() {
+        let command = format!("{} resume", get_command_prefix());
+        let cmds = parse_commands(&command);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0], Ok(BorsCommand::Resume));
+    }
+
     fn parse_commands(text: &str) -> Vec<Result<BorsCommand, CommandParseError>> {
         CommandParser::new(get_command_prefix()).parse_commands(text)
     }
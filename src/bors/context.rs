@@ -1,11 +1,101 @@
+use std::sync::Arc;
+
 use crate::bors::command::CommandParser;
+use crate::bors::lease::LeaseConfig;
+use crate::ci::CiServices;
+use crate::config::RepositoryConfig;
+use crate::consistency::ConsistencyReports;
+use crate::faults::Faults;
+use crate::global_config::DEFAULT_WEBHOOK_DELIVERY_RETENTION_DAYS;
+use crate::metrics::CommandMetrics;
 
 pub struct BorsContext {
     pub parser: CommandParser,
+    /// Usage of commands, shared with the `/metrics` endpoint.
+    pub metrics: Arc<CommandMetrics>,
+    /// External CI services that can run try builds.
+    pub ci: Arc<CiServices>,
+    /// Results of consistency sweeps, shared with the `/admin/consistency` endpoint.
+    pub consistency: Arc<ConsistencyReports>,
+    /// Should repositories be notified when the bot is restarting?
+    pub restart_notice: bool,
+    /// Should the bot only report what it would do in all repositories, without pushing branches
+    /// or merging anything?
+    pub dry_run: bool,
+    /// Failures injected by tests.
+    pub faults: Arc<Faults>,
+    /// Leases of repositories, if several replicas of the bot share the database.
+    pub lease: Option<LeaseConfig>,
+    /// Number of days after which received webhooks are deleted from the delivery log.
+    pub webhook_delivery_retention_days: u64,
 }
 
 impl BorsContext {
     pub fn new(parser: CommandParser) -> Self {
-        Self { parser }
+        Self {
+            parser,
+            metrics: Arc::default(),
+            ci: Arc::default(),
+            consistency: Arc::default(),
+            restart_notice: false,
+            dry_run: false,
+            faults: Arc::default(),
+            lease: None,
+            webhook_delivery_retention_days: DEFAULT_WEBHOOK_DELIVERY_RETENTION_DAYS,
+        }
+    }
+
+    /// Records the usage of commands into the given metrics.
+    pub fn with_metrics(mut self, metrics: Arc<CommandMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Allows starting try builds using the given CI services.
+    pub fn with_ci(mut self, ci: Arc<CiServices>) -> Self {
+        self.ci = ci;
+        self
+    }
+
+    /// Records the results of consistency sweeps into the given reports.
+    pub fn with_consistency(mut self, consistency: Arc<ConsistencyReports>) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Sends the `restarting` notification to repositories when the bot shuts down.
+    pub fn with_restart_notice(mut self, restart_notice: bool) -> Self {
+        self.restart_notice = restart_notice;
+        self
+    }
+
+    /// Runs the bot in dry-run mode in all repositories.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Injects the given failures into the bot.
+    pub fn with_faults(mut self, faults: Arc<Faults>) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// Only drives the repositories whose lease is held by this replica.
+    pub fn with_lease(mut self, lease: Option<LeaseConfig>) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Deletes received webhooks from the delivery log after the given number of days.
+    pub fn with_webhook_delivery_retention_days(mut self, days: u64) -> Self {
+        self.webhook_delivery_retention_days = days;
+        self
+    }
+
+    /// Is the repository with the given configuration in dry-run mode, either because the whole
+    /// bot or the repository itself is?
+    pub fn is_dry_run(&self, config: &RepositoryConfig) -> bool {
+        self.dry_run || config.dry_run
     }
 }
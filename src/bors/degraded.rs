@@ -0,0 +1,159 @@
+//! Keeps the bot responsive while its database is unavailable.
+use std::collections::VecDeque;
+
+use crate::bors::event::BorsEvent;
+use crate::bors::{handle_bors_event, BorsContext, BorsState, CommandParser, RepositoryClient};
+use crate::database::DbClient;
+use crate::messages::MessageKind;
+
+/// Maximum number of events that are kept in memory while the database is unavailable.
+const MAX_PENDING_EVENTS: usize = 1000;
+
+/// Handles bors events, but instead of failing them while the database is unavailable, it keeps
+/// them in memory and handles them once the database becomes available again.
+#[derive(Default)]
+pub struct EventProcessor {
+    pending: VecDeque<BorsEvent>,
+}
+
+impl EventProcessor {
+    /// Number of events postponed until the database becomes available again.
+    pub fn postponed_events(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub async fn process<Client: RepositoryClient>(
+        &mut self,
+        event: BorsEvent,
+        state: &mut dyn BorsState<Client>,
+        ctx: &BorsContext,
+    ) -> anyhow::Result<()> {
+        if !requires_db(&event) {
+            return handle_bors_event(event, state, ctx).await;
+        }
+
+        let (_, db) = state.get_all_repos_mut();
+        if let Err(error) = ping(db, ctx).await {
+            tracing::warn!("Database is unavailable: {error:?}");
+            self.postpone(event, state, ctx).await;
+            return Ok(());
+        }
+
+        if !self.pending.is_empty() {
+            tracing::info!(
+                "Database is available again, handling {} postponed event(s)",
+                self.pending.len()
+            );
+            while let Some(pending) = self.pending.pop_front() {
+                if let Err(error) = handle_bors_event(pending, state, ctx).await {
+                    tracing::error!("Cannot handle postponed event: {error:?}");
+                }
+            }
+        }
+        handle_bors_event(event, state, ctx).await
+    }
+
+    async fn postpone<Client: RepositoryClient>(
+        &mut self,
+        event: BorsEvent,
+        state: &mut dyn BorsState<Client>,
+        ctx: &BorsContext,
+    ) {
+        match &event {
+            // Refresh happens periodically, so it does not have to be remembered
+            BorsEvent::Refresh => return,
+            BorsEvent::Comment(comment) if !state.is_comment_internal(comment) => {
+                if let Some((repo, _)) = state.get_repo_state_mut(&comment.repository) {
+                    let repo_parser = repo.config.command_prefix.clone().map(CommandParser::new);
+                    let parser = repo_parser.as_ref().unwrap_or(&ctx.parser);
+                    if parser.parse_commands(&comment.text).is_empty() {
+                        return self.push(event);
+                    }
+                    let message = repo.config.messages.render(
+                        MessageKind::DatabaseUnavailable,
+                        &repo.repository,
+                        comment.pr_number,
+                        &[],
+                    );
+                    if let Err(error) = repo.client.post_comment(comment.pr_number, &message).await
+                    {
+                        tracing::warn!("Cannot acknowledge postponed command: {error:?}");
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.push(event);
+    }
+
+    fn push(&mut self, event: BorsEvent) {
+        if self.pending.len() >= MAX_PENDING_EVENTS {
+            tracing::error!("Too many postponed events, dropping {event:?}");
+            return;
+        }
+        self.pending.push_back(event);
+    }
+}
+
+/// Checks that the database is available, unless a test has injected a timeout.
+async fn ping(db: &dyn DbClient, ctx: &BorsContext) -> anyhow::Result<()> {
+    if ctx.faults.take_db_timeout() {
+        return Err(anyhow::anyhow!("Injected timeout of the database"));
+    }
+    db.ping().await
+}
+
+fn requires_db(event: &BorsEvent) -> bool {
+    !matches!(
+        event,
+        BorsEvent::InstallationsChanged
+            | BorsEvent::ProtectBranches(_)
+            | BorsEvent::ConfigChanged(_)
+            | BorsEvent::PullRequestPushed(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::degraded::EventProcessor;
+    use crate::bors::event::BorsEvent;
+    use crate::bors::{BorsContext, CommandParser};
+    use crate::tests::database::create_test_db;
+    use crate::tests::event::{comment, default_pr_number};
+    use crate::tests::state::ClientBuilder;
+
+    #[tokio::test]
+    async fn postpone_commands_while_db_is_unavailable() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let ctx = BorsContext::new(CommandParser::new("@bors".to_string()));
+        let mut processor = EventProcessor::default();
+
+        state.db.connection().clone().close().await.unwrap();
+        processor
+            .process(
+                BorsEvent::Comment(comment("@bors ping").create()),
+                &mut state,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        state.client().check_comments(
+            default_pr_number(),
+            &[":warning: The database of the bot is currently unavailable. The command will be executed once it becomes available again."],
+        );
+
+        state.db = create_test_db().await;
+        processor
+            .process(BorsEvent::Refresh, &mut state, &ctx)
+            .await
+            .unwrap();
+        state.client().check_comments(
+            default_pr_number(),
+            &[
+                ":warning: The database of the bot is currently unavailable. The command will be executed once it becomes available again.",
+                "Pong 🏓!",
+            ],
+        );
+    }
+}
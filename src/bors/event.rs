@@ -1,4 +1,5 @@
-use crate::database::{WorkflowStatus, WorkflowType};
+use crate::bors::command::BorsCommand;
+use crate::database::{PrimaryKey, WorkflowStatus, WorkflowType};
 use crate::github::{CommitSha, GithubRepoName, GithubUser, PullRequestNumber};
 use octocrab::models::RunId;
 
@@ -10,13 +11,105 @@ pub enum BorsEvent {
     WorkflowStarted(WorkflowStarted),
     /// A workflow run on Github Actions or a check run from external CI system has been completed.
     WorkflowCompleted(WorkflowCompleted),
+    /// An external system has reported the result of its build of a candidate commit using the
+    /// JSON API.
+    BuildResultReported(BuildResultReported),
     /// A check suite has been completed, either as a workflow run on Github Actions, or as a
     /// workflow from some external CI system.
     CheckSuiteCompleted(CheckSuiteCompleted),
+    /// A pull request has been approved using a GitHub review.
+    /// The comment contains the text of the review.
+    ReviewApproved(PullRequestComment),
+    /// A GitHub review of a pull request has been dismissed.
+    ReviewDismissed(ReviewDismissed),
+    /// A pull request has been closed, either by merging it or by abandoning it.
+    PullRequestClosed(PullRequestClosed),
+    /// A label has been added to a pull request.
+    PullRequestLabeled(PullRequestLabelChanged),
+    /// A label has been removed from a pull request.
+    PullRequestUnlabeled(PullRequestLabelChanged),
+    /// New commits have been pushed to a pull request, or it has been opened.
+    PullRequestPushed(PullRequestPushed),
+    /// A pull request has been converted to a draft.
+    PullRequestConvertedToDraft(PullRequestChanged),
+    /// A branch of a repository has been force-pushed or deleted.
+    BranchRewritten(BranchRewritten),
+    /// The configuration file of a repository has been changed in its default branch.
+    ConfigChanged(ConfigChanged),
+    /// A user logged in to the dashboard has requested executing a command on a pull request.
+    DashboardCommand(DashboardCommand),
+    /// An administrator has requested configuring branch protection of a repository.
+    ProtectBranches(GithubRepoName),
+    /// An administrator has removed a pull request from the merge queue.
+    ForceDequeue(PullRequestChanged),
+    /// An administrator has requested reconciling the persisted state of a repository with the
+    /// state of GitHub.
+    Resync(GithubRepoName),
+    /// An administrator has paused a repository.
+    Pause(PauseRepository),
+    /// An administrator has resumed a paused repository.
+    Resume(GithubRepoName),
+    /// The delay of postponed updates of summary comments of a repository has elapsed.
+    FlushUpdates(GithubRepoName),
     /// The configuration of some repository has been changed for the bot's Github App.
     InstallationsChanged,
     /// Periodic event that serves for checking e.g. timeouts.
     Refresh,
+    /// Sent once when the bot starts, serves for reconciling the persisted state with the
+    /// state of GitHub, which might have changed while the bot was not running.
+    Reconcile,
+    /// Periodic event that serves for cross-checking the persisted state with the state of GitHub
+    /// and repairing differences caused by lost webhooks.
+    Sweep,
+}
+
+impl BorsEvent {
+    /// Can the event change the configuration of its repository? A force-push to the default
+    /// branch can replace the configuration file without listing the commits that changed it.
+    pub fn may_change_config(&self) -> bool {
+        match self {
+            BorsEvent::ConfigChanged(_) => true,
+            BorsEvent::BranchRewritten(payload) => {
+                payload.default_branch
+                    && matches!(payload.rewrite, BranchRewrite::ForcePushed { .. })
+            }
+            _ => false,
+        }
+    }
+
+    /// Repository to which the event relates, or `None` if the event can affect all
+    /// repositories.
+    pub fn repository(&self) -> Option<&GithubRepoName> {
+        match self {
+            BorsEvent::Comment(comment) | BorsEvent::ReviewApproved(comment) => {
+                Some(&comment.repository)
+            }
+            BorsEvent::WorkflowStarted(payload) => Some(&payload.repository),
+            BorsEvent::WorkflowCompleted(payload) => Some(&payload.repository),
+            BorsEvent::BuildResultReported(payload) => Some(&payload.repository),
+            BorsEvent::CheckSuiteCompleted(payload) => Some(&payload.repository),
+            BorsEvent::ReviewDismissed(payload) => Some(&payload.repository),
+            BorsEvent::PullRequestClosed(payload) => Some(&payload.repository),
+            BorsEvent::PullRequestLabeled(payload) | BorsEvent::PullRequestUnlabeled(payload) => {
+                Some(&payload.repository)
+            }
+            BorsEvent::PullRequestPushed(payload) => Some(&payload.repository),
+            BorsEvent::PullRequestConvertedToDraft(payload) => Some(&payload.repository),
+            BorsEvent::BranchRewritten(payload) => Some(&payload.repository),
+            BorsEvent::ConfigChanged(payload) => Some(&payload.repository),
+            BorsEvent::DashboardCommand(payload) => Some(&payload.repository),
+            BorsEvent::ForceDequeue(payload) => Some(&payload.repository),
+            BorsEvent::Pause(payload) => Some(&payload.repository),
+            BorsEvent::ProtectBranches(repository)
+            | BorsEvent::FlushUpdates(repository)
+            | BorsEvent::Resync(repository)
+            | BorsEvent::Resume(repository) => Some(repository),
+            BorsEvent::InstallationsChanged
+            | BorsEvent::Refresh
+            | BorsEvent::Reconcile
+            | BorsEvent::Sweep => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -27,6 +120,83 @@ pub struct PullRequestComment {
     pub text: String,
 }
 
+/// A command executed using a button of the dashboard. It is handled in the same way as if it was
+/// posted by `author` in a comment.
+#[derive(Debug)]
+pub struct DashboardCommand {
+    pub repository: GithubRepoName,
+    pub author: GithubUser,
+    pub pr_number: PullRequestNumber,
+    pub command: BorsCommand,
+}
+
+#[derive(Debug)]
+pub struct PullRequestChanged {
+    pub repository: GithubRepoName,
+    pub pr_number: PullRequestNumber,
+}
+
+#[derive(Debug)]
+pub struct PauseRepository {
+    pub repository: GithubRepoName,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct PullRequestPushed {
+    pub repository: GithubRepoName,
+    pub pr_number: PullRequestNumber,
+    pub head_sha: CommitSha,
+}
+
+#[derive(Debug)]
+pub struct PullRequestLabelChanged {
+    pub repository: GithubRepoName,
+    pub pr_number: PullRequestNumber,
+    pub label: String,
+}
+
+#[derive(Debug)]
+pub enum BranchRewrite {
+    /// The branch was force-pushed from `before` to `after`.
+    ForcePushed { before: CommitSha, after: CommitSha },
+    /// The branch was deleted, its last commit was `before`.
+    Deleted { before: CommitSha },
+}
+
+#[derive(Debug)]
+pub struct BranchRewritten {
+    pub repository: GithubRepoName,
+    pub branch: String,
+    /// Is this the default branch of the repository?
+    pub default_branch: bool,
+    pub rewrite: BranchRewrite,
+}
+
+#[derive(Debug)]
+pub struct ConfigChanged {
+    pub repository: GithubRepoName,
+    /// The last pushed commit, which contains the new configuration.
+    pub commit_sha: CommitSha,
+}
+
+#[derive(Debug)]
+pub struct ReviewDismissed {
+    pub repository: GithubRepoName,
+    pub pr_number: PullRequestNumber,
+    /// Author of the dismissed review.
+    pub reviewer: GithubUser,
+}
+
+#[derive(Debug)]
+pub struct PullRequestClosed {
+    pub repository: GithubRepoName,
+    pub pr_number: PullRequestNumber,
+    /// Was the PR merged or abandoned?
+    pub merged: bool,
+    pub base_branch: String,
+}
+
 #[derive(Debug)]
 pub struct WorkflowStarted {
     pub repository: GithubRepoName,
@@ -38,7 +208,7 @@ pub struct WorkflowStarted {
     pub url: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WorkflowCompleted {
     pub repository: GithubRepoName,
     pub branch: String,
@@ -47,6 +217,18 @@ pub struct WorkflowCompleted {
     pub status: WorkflowStatus,
 }
 
+/// Result of a build of a candidate commit created by bors, reported by an external system.
+/// Each system is identified by its `name`, which is the name of its workflow.
+#[derive(Debug, Clone)]
+pub struct BuildResultReported {
+    pub repository: GithubRepoName,
+    pub build_id: PrimaryKey,
+    pub name: String,
+    pub url: String,
+    pub status: WorkflowStatus,
+    pub artifacts: Vec<String>,
+}
+
 #[derive(Debug)]
 pub struct CheckSuiteCompleted {
     pub repository: GithubRepoName,
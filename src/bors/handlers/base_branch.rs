@@ -0,0 +1,169 @@
+use crate::bors::event::{BranchRewrite, BranchRewritten};
+use crate::bors::handlers::branches::is_bors_branch;
+use crate::bors::handlers::comments::{post_status_comment, update_summary_comment};
+use crate::bors::handlers::trybuild::cancel_build_workflows;
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::database::{BuildStatus, DbClient};
+use crate::messages::MessageKind;
+
+/// Handles a force-push or a deletion of a base branch.
+///
+/// Running builds of PRs that target the branch were created on top of its previous history, so
+/// their results are meaningless and they are cancelled. Since a rewrite of a base branch usually
+/// means that someone has modified it manually, the operators of the bot are alerted.
+pub(super) async fn handle_branch_rewritten<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: BranchRewritten,
+) -> anyhow::Result<()> {
+    if is_bors_branch(&payload.branch) {
+        return Ok(());
+    }
+
+    let mut cancelled = vec![];
+    for build in db.get_running_builds(&repo.repository).await? {
+        let Some(pr_model) = db.find_pr_by_build(&build).await? else {
+            continue;
+        };
+        let pr = repo.client.get_pull_request(pr_model.number).await?;
+        if pr.base.name != payload.branch {
+            continue;
+        }
+
+        tracing::info!("Cancelling build {} of PR {}", build.commit_sha, pr.number);
+        if let Err(error) = cancel_build_workflows(repo, db, &build).await {
+            tracing::error!(
+                "Could not cancel workflows for SHA {}: {error:?}",
+                build.commit_sha
+            );
+        }
+        db.update_build_status(&build, BuildStatus::Cancelled)
+            .await?;
+        let message = match &payload.rewrite {
+            BranchRewrite::ForcePushed { before, after } => repo.config.messages.render(
+                MessageKind::BaseBranchForcePushed,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("branch", &payload.branch),
+                    ("before", &before.0),
+                    ("after", &after.0),
+                ],
+            ),
+            BranchRewrite::Deleted { before } => repo.config.messages.render(
+                MessageKind::BaseBranchDeleted,
+                &repo.repository,
+                pr.number,
+                &[("branch", &payload.branch), ("before", &before.0)],
+            ),
+        };
+        post_status_comment(repo, db, pr.number, &message).await?;
+        update_summary_comment(repo, db, pr.number).await?;
+        cancelled.push(pr.number);
+    }
+
+    // Force-pushes to other (e.g. feature) branches are not interesting for operators
+    if !payload.default_branch && cancelled.is_empty() {
+        return Ok(());
+    }
+
+    let mut alert = format!(
+        ":rotating_light: The base branch `{}` was {}. This usually means that it was modified manually.",
+        payload.branch,
+        rewrite_description(&payload.rewrite)
+    );
+    if !cancelled.is_empty() {
+        alert.push_str(&format!(
+            " The following try build(s) were based on its previous history and were cancelled: {}",
+            cancelled
+                .iter()
+                .map(|pr| format!("#{pr}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    tracing::warn!("{alert}");
+    if let Some(issue) = repo.config.operator_issue {
+        repo.client.post_comment(issue.into(), &alert).await?;
+    }
+    Ok(())
+}
+
+fn rewrite_description(rewrite: &BranchRewrite) -> String {
+    match rewrite {
+        BranchRewrite::ForcePushed { before, after } => {
+            format!("force-pushed from `{before}` to `{after}`")
+        }
+        BranchRewrite::Deleted { before } => format!("deleted (its last commit was `{before}`)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::event::BranchRewrite;
+    use crate::github::CommitSha;
+    use crate::tests::event::default_pr_number;
+    use crate::tests::state::{ClientBuilder, RepoConfigBuilder};
+
+    fn force_push() -> BranchRewrite {
+        BranchRewrite::ForcePushed {
+            before: CommitSha("old-sha".to_string()),
+            after: CommitSha("new-sha".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_base_force_push_cancels_build() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().operator_issue(Some(100)))
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        state
+            .branch_rewritten("main-branch", false, force_push())
+            .await;
+
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":warning: The base branch `main-branch` was force-pushed from `old-sha` to `new-sha`, so this try build was cancelled, because its merge commit is no longer based on the history of the branch. Please start it again."
+        );
+        insta::assert_snapshot!(
+            state.client().get_last_comment(100),
+            @":rotating_light: The base branch `main-branch` was force-pushed from `old-sha` to `new-sha`. This usually means that it was modified manually. The following try build(s) were based on its previous history and were cancelled: #1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_branch_force_push() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().operator_issue(Some(100)))
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        state.branch_rewritten("feature", false, force_push()).await;
+
+        state.client().check_comment_count(default_pr_number(), 1);
+        state.client().check_comment_count(100, 0);
+    }
+
+    #[tokio::test]
+    async fn test_default_branch_deleted_alert() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().operator_issue(Some(100)))
+            .create_state()
+            .await;
+        state
+            .branch_rewritten(
+                "main",
+                true,
+                BranchRewrite::Deleted {
+                    before: CommitSha("old-sha".to_string()),
+                },
+            )
+            .await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(100),
+            @":rotating_light: The base branch `main` was deleted (its last commit was `old-sha`). This usually means that it was modified manually."
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Management of the branches that are used by bors to test candidate merge commits.
+//!
+//! A candidate merge commit is prepared on a temporary, per-PR merge branch: the branch is reset
+//! to the base commit and the PR head is merged into it. The resulting commit is then
+//! force-pushed to the branch on which CI runs and the temporary branch is deleted. Try builds
+//! started by a workflow dispatch do not need a CI branch, their merge commit stays on the
+//! temporary branch until the next try build of the PR or until the PR is closed.
+//! All branches are created on demand if they do not exist yet.
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::config::TryTrigger;
+use crate::database::{BuildModel, DbClient};
+use crate::github::{BranchUpdateError, CommitSha, MergeError, PullRequestNumber};
+use crate::messages::MessageKind;
+
+// This branch should run CI checks.
+pub(super) const TRY_BRANCH_NAME: &str = "automation/bors/try";
+
+// This branch runs CI checks of the merge commits of approved PRs before they are merged into the
+// base branch.
+pub const AUTO_BRANCH_NAME: &str = "automation/bors/auto";
+
+// Branches with this prefix serve for preparing the final commit.
+// They will be reset to master and merged with the branch that should be tested.
+// Because this action (reset + merge) is not atomic, these branches should not run CI checks to
+// avoid starting them twice.
+const TRY_MERGE_BRANCH_PREFIX: &str = "automation/bors/try-merge";
+
+/// Matches all branches managed by bors (the try and auto branches and the temporary merge
+/// branches), so that they can be protected before they are created.
+pub(super) const BORS_BRANCH_PATTERN: &str = "automation/bors/*";
+
+/// Name of the temporary branch used to prepare try merge commits for the given PR.
+pub(super) fn try_merge_branch_name(pr: PullRequestNumber) -> String {
+    format!("{TRY_MERGE_BRANCH_PREFIX}-{pr}")
+}
+
+/// Returns the branch that should point to the commit of a running build. The try branch is not
+/// updated when the repository starts try builds using a workflow dispatch, the merge commit is
+/// kept on the merge branch of the PR instead.
+pub(super) fn build_commit_branch<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    build: &BuildModel,
+    pr: Option<PullRequestNumber>,
+) -> String {
+    match (&repo.config.try_trigger, pr) {
+        (TryTrigger::WorkflowDispatch { .. }, Some(pr)) if build.branch == TRY_BRANCH_NAME => {
+            try_merge_branch_name(pr)
+        }
+        _ => build.branch.clone(),
+    }
+}
+
+/// Has the branch of a running build been changed by someone else than the bot?
+///
+/// Try builds of all PRs share the try branch, so a try build of another PR moves the branch
+/// while the previous build keeps running on its own commit. The branch has therefore only been
+/// changed if it was deleted or if it points to a commit that is not tested by a newer build.
+pub(super) async fn build_branch_changed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    build: &BuildModel,
+    pr: Option<PullRequestNumber>,
+) -> anyhow::Result<bool> {
+    let branch = build_commit_branch(repo, build, pr);
+    let Some(sha) = repo.client.get_branch_sha(&branch).await? else {
+        return Ok(true);
+    };
+    if sha.0 == build.commit_sha {
+        return Ok(false);
+    }
+    let newer_build = db
+        .find_build(&repo.repository, build.branch.clone(), sha)
+        .await?
+        .map_or(false, |tip| tip.id > build.id);
+    Ok(!newer_build)
+}
+
+/// Is this a branch managed by bors?
+pub(super) fn is_bors_branch(branch: &str) -> bool {
+    branch == TRY_BRANCH_NAME
+        || branch == AUTO_BRANCH_NAME
+        || branch.starts_with(TRY_MERGE_BRANCH_PREFIX)
+}
+
+pub(super) enum CandidateMergeError {
+    /// The PR cannot be cleanly merged into the base commit.
+    Conflict,
+    /// The bot is not allowed to modify the given branch.
+    PermissionDenied(String),
+    /// The merge commit does not have a verified signature, which the repository requires.
+    Unverified(CommitSha),
+    Other(anyhow::Error),
+}
+
+/// Merges `head` on top of `base` using the temporary `merge_branch` and force-pushes the
+/// resulting merge commit to `ci_branch`.
+///
+/// The temporary branch is deleted afterwards, regardless of the result. If there is no
+/// `ci_branch`, the temporary branch is kept when the merge succeeds, so that the merge commit
+/// remains reachable.
+pub(super) async fn create_candidate_merge<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    merge_branch: &str,
+    ci_branch: Option<&str>,
+    base: &CommitSha,
+    head: &CommitSha,
+    commit_message: &str,
+) -> Result<CommitSha, CandidateMergeError> {
+    let result = prepare_merge(repo, merge_branch, ci_branch, base, head, commit_message).await;
+
+    if ci_branch.is_some() || result.is_err() {
+        if let Err(error) = repo.client.delete_branch(merge_branch).await {
+            tracing::warn!("Could not delete branch {merge_branch}: {error:?}");
+        }
+    }
+    result
+}
+
+async fn prepare_merge<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    merge_branch: &str,
+    ci_branch: Option<&str>,
+    base: &CommitSha,
+    head: &CommitSha,
+    commit_message: &str,
+) -> Result<CommitSha, CandidateMergeError> {
+    // First set the merge branch to the base commit
+    repo.client
+        .set_branch_to_sha(merge_branch, base)
+        .await
+        .map_err(|error| branch_error(merge_branch, error))?;
+
+    // Then merge the PR commit into the merge branch
+    let merge_sha = match repo
+        .client
+        .merge_branches(merge_branch, head, commit_message)
+        .await
+    {
+        Ok(sha) => sha,
+        Err(MergeError::Conflict) => return Err(CandidateMergeError::Conflict),
+        Err(error) => return Err(CandidateMergeError::Other(error.into())),
+    };
+    tracing::debug!("Merge successful, SHA: {merge_sha}");
+
+    // The merge commit is created by the forge, so it is not checked when the PR is approved
+    if repo.config.require_verified_commits
+        && !repo
+            .client
+            .is_commit_verified(&merge_sha)
+            .await
+            .map_err(CandidateMergeError::Other)?
+    {
+        return Err(CandidateMergeError::Unverified(merge_sha));
+    }
+
+    // If the merge was successful, then set the branch that will run CI to the merged commit.
+    if let Some(ci_branch) = ci_branch {
+        repo.client
+            .set_branch_to_sha(ci_branch, &merge_sha)
+            .await
+            .map_err(|error| branch_error(ci_branch, error))?;
+    }
+    Ok(merge_sha)
+}
+
+fn branch_error(branch: &str, error: BranchUpdateError) -> CandidateMergeError {
+    match error {
+        BranchUpdateError::PermissionDenied(_) => {
+            CandidateMergeError::PermissionDenied(branch.to_string())
+        }
+        error => {
+            CandidateMergeError::Other(anyhow::anyhow!("Cannot set branch {branch}: {error:?}"))
+        }
+    }
+}
+
+/// Informs the PR about a failed candidate merge.
+/// Errors that cannot be explained to the user are returned back to the caller.
+pub(super) async fn report_candidate_merge_error<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: PullRequestNumber,
+    pr_branch: &str,
+    error: CandidateMergeError,
+) -> anyhow::Result<()> {
+    match error {
+        CandidateMergeError::Conflict => {
+            tracing::warn!("Merge conflict");
+            repo.client
+                .post_comment(pr, &merge_conflict_message(pr_branch))
+                .await?;
+        }
+        CandidateMergeError::PermissionDenied(branch) => {
+            tracing::warn!("Permission denied when updating branch {branch}");
+            repo.client
+                .post_comment(
+                    pr,
+                    &repo.config.messages.render(
+                        MessageKind::BranchPermissionDenied,
+                        &repo.repository,
+                        pr,
+                        &[("branch", &branch)],
+                    ),
+                )
+                .await?;
+        }
+        CandidateMergeError::Unverified(sha) => {
+            tracing::warn!("Merge commit {sha} is not verified");
+            repo.client
+                .post_comment(
+                    pr,
+                    &repo.config.messages.render(
+                        MessageKind::MergeCommitUnverified,
+                        &repo.repository,
+                        pr,
+                        &[("sha", &sha.to_string())],
+                    ),
+                )
+                .await?;
+        }
+        CandidateMergeError::Other(error) => return Err(error),
+    }
+    Ok(())
+}
+
+fn merge_conflict_message(branch: &str) -> String {
+    format!(
+        r#":lock: Merge conflict
+
+This pull request and the master branch diverged in a way that cannot
+ be automatically merged. Please rebase on top of the latest master
+ branch, and let the reviewer approve again.
+
+<details><summary>How do I rebase?</summary>
+
+Assuming `self` is your fork and `upstream` is this repository,
+ you can resolve the conflict following these steps:
+
+1. `git checkout {branch}` *(switch to your branch)*
+2. `git fetch upstream master` *(retrieve the latest master)*
+3. `git rebase upstream/master -p` *(rebase on top of it)*
+4. Follow the on-screen instruction to resolve conflicts (check `git status` if you got lost).
+5. `git push self {branch} --force-with-lease` *(update this PR)*
+
+You may also read
+ [*Git Rebasing to Resolve Conflicts* by Drew Blessing](http://blessing.io/git/git-rebase/open-source/2015/08/23/git-rebasing-to-resolve-conflicts.html)
+ for a short tutorial.
+
+Please avoid the ["**Resolve conflicts**" button](https://help.github.com/articles/resolving-a-merge-conflict-on-github/) on GitHub.
+ It uses `git merge` instead of `git rebase` which makes the PR commit history more difficult to read.
+
+Sometimes step 4 will complete without asking for resolution. This is usually due to difference between how `Cargo.lock` conflict is
+handled during merge and rebase. This is normal, and you should still perform step 5 to update this PR.
+
+</details>  
+"#
+    )
+}
@@ -0,0 +1,400 @@
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::config::OutdatedCommentsMode;
+use crate::database::{BuildStatus, DbClient, PullRequestModel, WorkflowStatus};
+use crate::github::PullRequestNumber;
+
+/// Posts a status comment (e.g. about a started or a finished build) to the given PR.
+///
+/// The previous status comment of the PR is edited, minimized or kept as it is, based on the
+/// configuration of the repository. If `summary_comment_delay` is set, the comment is postponed
+/// and posted by [`flush_deferred_comments`], unless a later status comment replaces it.
+pub(super) async fn post_status_comment<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    pr: PullRequestNumber,
+    text: &str,
+) -> anyhow::Result<()> {
+    if !repo.config.summary_comment_delay.is_zero() {
+        repo.deferred_updates.add_status_comment(pr, text);
+        return Ok(());
+    }
+    write_status_comment(repo, db, pr, text).await
+}
+
+async fn write_status_comment<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    pr: PullRequestNumber,
+    text: &str,
+) -> anyhow::Result<()> {
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr)
+        .await?;
+
+    if let Some(ref previous) = pr_model.status_comment {
+        match repo.config.outdated_comments {
+            OutdatedCommentsMode::Append => {}
+            OutdatedCommentsMode::Minimize => {
+                if let Err(error) = repo.client.minimize_comment(previous).await {
+                    tracing::warn!("Could not minimize comment {previous}: {error:?}");
+                }
+            }
+            OutdatedCommentsMode::Edit => match repo.client.edit_comment(previous, text).await {
+                Ok(()) => return Ok(()),
+                // The comment might have been deleted, post a new one instead
+                Err(error) => tracing::warn!("Could not edit comment {previous}: {error:?}"),
+            },
+        }
+    }
+
+    let comment = repo.client.post_comment(pr, text).await?;
+    db.set_status_comment(&pr_model, &comment).await
+}
+
+/// Creates or updates the summary comment of the given PR, if the repository has enabled it.
+///
+/// The summary comment is a single comment that is edited in place whenever the state of the PR
+/// (its approval or its try build) changes. If `summary_comment_delay` is set, the update is
+/// postponed and posted by [`flush_deferred_comments`] together with later changes of the PR.
+pub(super) async fn update_summary_comment<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    pr: PullRequestNumber,
+) -> anyhow::Result<()> {
+    if !repo.config.summary_comment {
+        return Ok(());
+    }
+    if !repo.config.summary_comment_delay.is_zero() {
+        repo.deferred_updates.add(pr);
+        return Ok(());
+    }
+    write_summary_comment(repo, db, pr).await
+}
+
+/// Posts the postponed status comments and updates of summary comments of the repository.
+pub(super) async fn flush_deferred_comments<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+) {
+    let (prs, status_comments) = repo.deferred_updates.take();
+    for (pr, text) in status_comments {
+        if let Err(error) = write_status_comment(repo, db, pr, &text).await {
+            tracing::error!("Could not post status comment to PR {pr}: {error:?}");
+        }
+    }
+    for pr in prs {
+        if let Err(error) = write_summary_comment(repo, db, pr).await {
+            tracing::error!("Could not update summary comment of PR {pr}: {error:?}");
+        }
+    }
+}
+
+async fn write_summary_comment<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    pr: PullRequestNumber,
+) -> anyhow::Result<()> {
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr)
+        .await?;
+    let text = format_summary(db, &pr_model).await?;
+
+    if let Some(ref previous) = pr_model.summary_comment {
+        match repo.client.edit_comment(previous, &text).await {
+            Ok(()) => return Ok(()),
+            // The comment might have been deleted, post a new one instead
+            Err(error) => tracing::warn!("Could not edit summary comment {previous}: {error:?}"),
+        }
+    }
+
+    let comment = repo.client.post_comment(pr, &text).await?;
+    db.set_summary_comment(&pr_model, &comment).await
+}
+
+async fn format_summary(db: &dyn DbClient, pr: &PullRequestModel) -> anyhow::Result<String> {
+    let mut lines = vec![":clipboard: **Status of this pull request**".to_string()];
+
+    lines.push(match pr.approved_by {
+        Some(ref approver) => format!("- [x] Approved by `{approver}`"),
+        None => "- [ ] Approved".to_string(),
+    });
+    if pr.priority != 0 {
+        lines.push(format!("- Priority: `{}`", pr.priority));
+    }
+    if let Some(rollup) = pr.rollup {
+        lines.push(format!("- Rollup: `{}`", rollup.name()));
+    }
+
+    match pr.try_build {
+        Some(ref build) => {
+            let (done, status) = match build.status {
+                BuildStatus::Pending => (' ', ":hourglass: in progress"),
+                BuildStatus::Success => ('x', ":sunny: successful"),
+                BuildStatus::Failure => (' ', ":broken_heart: failed"),
+                BuildStatus::Cancelled => (' ', "cancelled"),
+                BuildStatus::Timeouted => (' ', ":boom: timed out"),
+            };
+            lines.push(format!(
+                "- [{done}] Try build {status} (`{}`)",
+                build.commit_sha
+            ));
+
+            let mut workflows = db.get_workflows_for_build(build).await?;
+            workflows.sort_by(|a, b| a.name.cmp(&b.name));
+            lines.extend(workflows.into_iter().map(|workflow| {
+                let status = match workflow.status {
+                    WorkflowStatus::Pending => ":hourglass:",
+                    WorkflowStatus::Success => ":white_check_mark:",
+                    WorkflowStatus::Failure => ":x:",
+                };
+                format!("  - [{}]({}) {status}", workflow.name, workflow.url)
+            }));
+        }
+        None => lines.push("- [ ] Try build".to_string()),
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bors::event::BorsEvent;
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
+    use crate::config::OutdatedCommentsMode;
+    use crate::database::{DbClient, RollupMode, WorkflowStatus};
+    use crate::tests::event::{default_pr_number, suite_success};
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder,
+    };
+
+    #[tokio::test]
+    async fn status_comment_append() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.comment("@bors try cancel").await;
+        state.client().check_comment_count(default_pr_number(), 2);
+        state
+            .client()
+            .check_minimized_comments(default_pr_number(), &[]);
+    }
+
+    #[tokio::test]
+    async fn status_comment_minimize() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().outdated_comments(OutdatedCommentsMode::Minimize))
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        state.comment("@bors try cancel").await;
+        state.comment("@bors try").await;
+        state.client().check_comment_count(default_pr_number(), 3);
+        state
+            .client()
+            .check_minimized_comments(default_pr_number(), &[0, 1]);
+    }
+
+    #[tokio::test]
+    async fn status_comment_edit() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().outdated_comments(OutdatedCommentsMode::Edit))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :sunny: Try build successful
+        - [workflow-1](https://workflow-1.com) :white_check_mark:
+        Build commit: sha-merged (`sha-merged`)
+        "###);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_disabled() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.client().check_comment_count(default_pr_number(), 1);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_approve() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().summary_comment(true))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [x] Approved by `<user>`
+        - [ ] Try build
+        "###);
+
+        // The summary comment is edited instead of posting a new one
+        state.comment("@bors r-").await;
+        state.client().check_comment_count(default_pr_number(), 3);
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [ ] Approved
+        - [ ] Try build
+        "###);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_clear_rollup() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .summary_comment(true)
+                    .features(toml::from_str("rollups = true").unwrap()),
+            )
+            .create_state()
+            .await;
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        state
+            .db
+            .set_rollup(&pr, Some(RollupMode::Iffy))
+            .await
+            .unwrap();
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [x] Approved by `<user>`
+        - Rollup: `iffy`
+        - [ ] Try build
+        "###);
+
+        state.comment("@bors rollup-").await;
+        state.client().check_comment_count(default_pr_number(), 2);
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [x] Approved by `<user>`
+        - [ ] Try build
+        "###);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_set_priority() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().summary_comment(true))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.comment("@bors p=5").await;
+        state.client().check_comment_count(default_pr_number(), 2);
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [x] Approved by `<user>`
+        - Priority: `5`
+        - [ ] Try build
+        "###);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_try_build() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().summary_comment(true))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [ ] Approved
+        - [ ] Try build :hourglass: in progress (`sha-merged`)
+        "###);
+
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [ ] Approved
+        - [x] Try build :sunny: successful (`sha-merged`)
+          - [workflow-1](https://workflow-1.com) :white_check_mark:
+        "###);
+    }
+
+    #[tokio::test]
+    async fn summary_comment_delay() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .summary_comment(true)
+                    .summary_comment_delay(Duration::from_secs(10)),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r-").await;
+        state.client().check_comment_count(default_pr_number(), 2);
+
+        // Both changes are posted at once
+        state
+            .event(BorsEvent::FlushUpdates(default_repo_name()))
+            .await;
+        state.client().check_comment_count(default_pr_number(), 3);
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 2), @r###"
+        :clipboard: **Status of this pull request**
+        - [ ] Approved
+        - [ ] Try build
+        "###);
+    }
+
+    #[tokio::test]
+    async fn status_comment_delay() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().summary_comment_delay(Duration::from_secs(10)))
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        state.comment("@bors try cancel").await;
+        state.client().check_comment_count(default_pr_number(), 0);
+
+        // Only the latest status comment is posted
+        state
+            .event(BorsEvent::FlushUpdates(default_repo_name()))
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        let comment = state.client().get_last_comment(default_pr_number());
+        insta::assert_snapshot!(comment, @"Try build cancelled.");
+    }
+
+    #[tokio::test]
+    async fn summary_comment_draft() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().summary_comment(true))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.converted_to_draft(default_pr_number()).await;
+        insta::assert_snapshot!(state.client().get_comment(default_pr_number(), 1), @r###"
+        :clipboard: **Status of this pull request**
+        - [ ] Approved
+        - [ ] Try build
+        "###);
+    }
+}
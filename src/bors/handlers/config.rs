@@ -0,0 +1,311 @@
+use crate::agreement::{AgreementChecker, ServiceAgreementChecker};
+use crate::bors::event::{ConfigChanged, PullRequestPushed};
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::bors_ng::{parse_bors_ng_config, BORS_NG_CONFIG_FILE_PATH};
+use crate::config::{config_warnings, validate_config, RepositoryConfig, CONFIG_FILE_PATH};
+use crate::github::{CheckAnnotation, CommitSha};
+
+/// Name of the check run that reports problems in the configuration file changed by a PR.
+pub(super) const CONFIG_CHECK_NAME: &str = "bors/config";
+
+/// Reloads the configuration of the repository after its configuration file has been changed in
+/// the default branch. The result is reported as a comment on the commit that changed the file.
+/// Without a `rust-bors.toml` file, the `bors.toml` file of bors-ng is used.
+pub(super) async fn handle_config_changed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    payload: ConfigChanged,
+) -> anyhow::Result<()> {
+    let mut path = CONFIG_FILE_PATH;
+    let mut config = repo
+        .client
+        .get_file_content(&payload.commit_sha, CONFIG_FILE_PATH)
+        .await?
+        .map(|content| {
+            toml::from_str::<RepositoryConfig>(&content).map_err(|error| error.to_string())
+        });
+    if config.is_none() {
+        path = BORS_NG_CONFIG_FILE_PATH;
+        config = repo
+            .client
+            .get_file_content(&payload.commit_sha, BORS_NG_CONFIG_FILE_PATH)
+            .await?
+            .map(|content| {
+                parse_bors_ng_config(&content)
+                    .map(|translated| translated.config)
+                    .map_err(|error| format!("{error:#}"))
+            });
+    }
+
+    let message = match config {
+        None => format!(
+            ":warning: `{CONFIG_FILE_PATH}` has been removed. The previous configuration stays in effect until bors is restarted."
+        ),
+        Some(Err(error)) => {
+            tracing::warn!("Invalid configuration of {}: {error}", repo.repository);
+            format!(
+                r#":x: The configuration was not reloaded, because `{path}` is invalid:
+```
+{error}
+```"#
+            )
+        }
+        Some(Ok(config)) => {
+            tracing::info!("Reloaded repository config for {}: {config:#?}", repo.repository);
+            if config.agreement != repo.config.agreement {
+                repo.agreement_checker = config.agreement.as_ref().map(|agreement| {
+                    Box::new(ServiceAgreementChecker::new(agreement)) as Box<dyn AgreementChecker>
+                });
+            }
+            repo.config = config;
+            ":white_check_mark: The configuration has been reloaded.".to_string()
+        }
+    };
+    repo.client
+        .post_commit_comment(&payload.commit_sha, &message)
+        .await
+}
+
+/// Reloads the configuration of the repository after its default branch has been force-pushed,
+/// if the push has changed the configuration file. The commits of a force-push do not tell
+/// whether they have changed the file, so its content before and after the push is compared.
+pub(super) async fn handle_default_branch_force_pushed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    before: &CommitSha,
+    after: &CommitSha,
+) -> anyhow::Result<()> {
+    for path in [CONFIG_FILE_PATH, BORS_NG_CONFIG_FILE_PATH] {
+        let previous = repo.client.get_file_content(before, path).await?;
+        let current = repo.client.get_file_content(after, path).await?;
+        if previous != current {
+            let payload = ConfigChanged {
+                repository: repo.repository.clone(),
+                commit_sha: after.clone(),
+            };
+            return handle_config_changed(repo, payload).await;
+        }
+    }
+    Ok(())
+}
+
+/// Validates the configuration file of a PR that modifies it, and reports the result as a check
+/// run, so that an invalid configuration is caught before it is merged. Deprecated settings are
+/// reported as warning annotations of the check run.
+pub(super) async fn handle_pull_request_pushed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    payload: PullRequestPushed,
+) -> anyhow::Result<()> {
+    let files = repo
+        .client
+        .get_pull_request_files(payload.pr_number)
+        .await?;
+    if !files.iter().any(|file| file == CONFIG_FILE_PATH) {
+        return Ok(());
+    }
+
+    let mut annotations = vec![];
+    let (success, summary) = match repo
+        .client
+        .get_file_content(&payload.head_sha, CONFIG_FILE_PATH)
+        .await?
+    {
+        None => (
+            false,
+            format!("`{CONFIG_FILE_PATH}` has been removed, bors will not operate on the repository without it."),
+        ),
+        Some(content) => {
+            annotations = config_warnings(&content)
+                .into_iter()
+                .map(|warning| CheckAnnotation {
+                    path: CONFIG_FILE_PATH.to_string(),
+                    line: warning.line,
+                    message: warning.message,
+                })
+                .collect();
+            let problems = validate_config(&content);
+            if problems.is_empty() {
+                (true, format!("`{CONFIG_FILE_PATH}` is valid."))
+            } else {
+                (
+                    false,
+                    problems
+                        .iter()
+                        .map(|problem| format!("- {problem}"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                )
+            }
+        }
+    };
+    repo.client
+        .create_check_run(
+            &payload.head_sha,
+            CONFIG_CHECK_NAME,
+            success,
+            &summary,
+            &annotations,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::event::{BorsEvent, BranchRewrite, ConfigChanged, PullRequestPushed};
+    use crate::bors_ng::BORS_NG_CONFIG_FILE_PATH;
+    use crate::config::CONFIG_FILE_PATH;
+    use crate::github::{CheckAnnotation, CommitSha, PullRequestNumber};
+    use crate::tests::event::default_pr_number;
+    use crate::tests::state::{default_repo_name, ClientBuilder};
+
+    #[tokio::test]
+    async fn reload_config() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .files
+            .insert(CONFIG_FILE_PATH.to_string(), "timeout = 100".to_string());
+        state.event(config_changed()).await;
+        assert_eq!(state.config().timeout.as_secs(), 100);
+        state.client().check_commit_comments(
+            "sha-config",
+            &[":white_check_mark: The configuration has been reloaded."],
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_config_after_force_push() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().commit_files.insert(
+            ("new-sha".to_string(), CONFIG_FILE_PATH.to_string()),
+            "timeout = 100".to_string(),
+        );
+        state
+            .branch_rewritten("main", true, force_push_of_default_branch())
+            .await;
+        assert_eq!(state.config().timeout.as_secs(), 100);
+        state.client().check_commit_comments(
+            "new-sha",
+            &[":white_check_mark: The configuration has been reloaded."],
+        );
+    }
+
+    #[tokio::test]
+    async fn force_push_without_config_change() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .files
+            .insert(CONFIG_FILE_PATH.to_string(), "timeout = 100".to_string());
+        state
+            .branch_rewritten("main", true, force_push_of_default_branch())
+            .await;
+        assert!(state.client().commit_comments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reload_bors_ng_config() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().files.insert(
+            BORS_NG_CONFIG_FILE_PATH.to_string(),
+            "timeout_sec = 100\nblock_labels = [\"S-blocked\"]".to_string(),
+        );
+        state.event(config_changed()).await;
+        assert_eq!(state.config().timeout.as_secs(), 100);
+        assert_eq!(
+            state.config().blocking_labels,
+            vec!["S-blocked".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_invalid_config() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let timeout = state.config().timeout;
+        state.client().files.insert(
+            CONFIG_FILE_PATH.to_string(),
+            "timeout = \"foo\"".to_string(),
+        );
+        state.event(config_changed()).await;
+        assert_eq!(state.config().timeout, timeout);
+        assert!(state.client().commit_comments["sha-config"][0]
+            .starts_with(":x: The configuration was not reloaded"));
+    }
+
+    #[tokio::test]
+    async fn check_config_of_pr() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .pr_files
+            .insert(default_pr_number(), vec![CONFIG_FILE_PATH.to_string()]);
+        state.client().files.insert(
+            CONFIG_FILE_PATH.to_string(),
+            "timeout = 0\ntimout = 10".to_string(),
+        );
+        state.event(pr_pushed()).await;
+        assert_eq!(
+            state.client().check_runs,
+            vec![(
+                "sha-pr".to_string(),
+                "bors/config".to_string(),
+                false,
+                "- Unknown key `timout`\n- `build_timeout` must be larger than zero".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn check_deprecated_config_of_pr() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .pr_files
+            .insert(default_pr_number(), vec![CONFIG_FILE_PATH.to_string()]);
+        state.client().files.insert(
+            CONFIG_FILE_PATH.to_string(),
+            "summary_comment = true\ntimeout = 600".to_string(),
+        );
+        state.event(pr_pushed()).await;
+        assert!(state.client().check_runs[0].2);
+        assert_eq!(
+            state.client().check_annotations,
+            vec![CheckAnnotation {
+                path: CONFIG_FILE_PATH.to_string(),
+                line: 2,
+                message: "`timeout` is deprecated, rename it to `build_timeout` and set `schema_version = 2`".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignore_pr_without_config_changes() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .pr_files
+            .insert(default_pr_number(), vec!["src/lib.rs".to_string()]);
+        state.event(pr_pushed()).await;
+        assert!(state.client().check_runs.is_empty());
+    }
+
+    fn pr_pushed() -> BorsEvent {
+        BorsEvent::PullRequestPushed(PullRequestPushed {
+            repository: default_repo_name(),
+            pr_number: PullRequestNumber(default_pr_number()),
+            head_sha: CommitSha("sha-pr".to_string()),
+        })
+    }
+
+    fn config_changed() -> BorsEvent {
+        BorsEvent::ConfigChanged(ConfigChanged {
+            repository: default_repo_name(),
+            commit_sha: CommitSha("sha-config".to_string()),
+        })
+    }
+
+    fn force_push_of_default_branch() -> BranchRewrite {
+        BranchRewrite::ForcePushed {
+            before: CommitSha("old-sha".to_string()),
+            after: CommitSha("new-sha".to_string()),
+        }
+    }
+}
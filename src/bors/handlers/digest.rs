@@ -0,0 +1,107 @@
+use chrono::{DateTime, Utc};
+
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::database::{DbClient, LoggedEvent};
+use crate::digest::compute_digest;
+use crate::notifications::{notify, Notification, NotificationEvent};
+
+/// Posts the digest of the repository if it is enabled and the previous digest was posted at
+/// least `interval_days` ago. The digest covers the period since the previous digest.
+///
+/// Posted digests are recorded in the event log before they are sent, so that neither a restart of
+/// the bot nor a failure to post the comment cause the digest to be sent again.
+pub(super) async fn post_digest_if_due<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let Some(config) = repo.config.digest.clone() else {
+        return Ok(());
+    };
+    let interval = chrono::Duration::days(config.interval_days as i64);
+    let last_digest = db
+        .get_last_event(&repo.repository, None, LoggedEvent::DigestPosted.kind())
+        .await?
+        .map(|event| event.created_at);
+    if last_digest.map_or(false, |posted_at| now - posted_at < interval) {
+        return Ok(());
+    }
+
+    let since = last_digest.unwrap_or(now - interval);
+    let digest = compute_digest(db, &repo.repository, since, now).await?;
+    tracing::info!("Posting digest of the repository");
+    db.record_event(&repo.repository, None, LoggedEvent::DigestPosted)
+        .await?;
+    let text = digest.to_string();
+    notify(
+        repo,
+        Notification {
+            event: NotificationEvent::Digest,
+            repository: repo.repository.clone(),
+            pr: None,
+            message: format!("\n{text}"),
+        },
+    )
+    .await;
+    if let Some(issue) = config.issue {
+        if let Err(error) = repo.client.post_comment(issue.into(), &text).await {
+            tracing::error!("Could not post digest to issue {issue}: {error:?}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::bors::handlers::digest::post_digest_if_due;
+    use crate::bors::BorsState;
+    use crate::config::DigestConfig;
+    use crate::notifications::{NotificationEvent, NotificationRoute};
+    use crate::tests::notifications::RecordingNotifier;
+    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn post_digest_once_per_interval() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().digest(Some(DigestConfig {
+                interval_days: 7,
+                issue: Some(100),
+            })))
+            .notifications(vec![NotificationRoute {
+                events: vec![NotificationEvent::Digest],
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+
+        let now = Utc::now();
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        post_digest_if_due(repo, db, now).await.unwrap();
+        post_digest_if_due(repo, db, now + Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(notifier.events(), vec![NotificationEvent::Digest]);
+        state.client().check_comment_count(100, 1);
+        assert!(state
+            .client()
+            .get_last_comment(100)
+            .starts_with("**Digest of owner/name from"));
+
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        post_digest_if_due(repo, db, now + Duration::days(8))
+            .await
+            .unwrap();
+        state.client().check_comment_count(100, 2);
+    }
+
+    #[tokio::test]
+    async fn no_digest_without_config() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        post_digest_if_due(repo, db, Utc::now()).await.unwrap();
+        state.client().check_comment_count(100, 0);
+    }
+}
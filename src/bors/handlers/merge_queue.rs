@@ -0,0 +1,139 @@
+//! Interoperation with the native merge queue of GitHub, which lands approved PRs if the
+//! repository uses [`LandingMode::GithubMergeQueue`].
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::LandingMode;
+use crate::database::DbClient;
+use crate::github::PullRequestNumber;
+use crate::messages::MessageKind;
+
+/// Adds an approved PR to the merge queue of GitHub, if the repository lands PRs using it.
+/// PRs of a paused repository are added once it is resumed, PRs below the priority of a closed
+/// tree once the tree is reopened.
+pub(super) async fn enqueue_pull_request<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+    pr: PullRequestNumber,
+) -> anyhow::Result<()> {
+    if repo.config.landing != LandingMode::GithubMergeQueue {
+        return Ok(());
+    }
+    if db.get_pause(&repo.repository).await?.is_some() {
+        tracing::info!("Repository is paused, the PR is not added to the GitHub merge queue");
+        return Ok(());
+    }
+    if let Some(closure) = db.get_tree_closure(&repo.repository).await? {
+        let priority = db
+            .find_pull_request(&repo.repository, pr)
+            .await?
+            .map_or(0, |pr| pr.priority);
+        if priority < closure.priority {
+            tracing::info!("Tree is closed, the PR is not added to the GitHub merge queue");
+            return Ok(());
+        }
+    }
+    if ctx.is_dry_run(&repo.config) {
+        tracing::info!("Dry run, the PR is not added to the GitHub merge queue");
+        let message =
+            repo.config
+                .messages
+                .render(MessageKind::DryRunEnqueue, &repo.repository, pr, &[]);
+        return repo.client.post_comment(pr, &message).await;
+    }
+    tracing::info!("Adding PR to the GitHub merge queue");
+    repo.client.add_to_merge_queue(pr).await
+}
+
+/// Removes a PR from the merge queue of GitHub, if the repository lands PRs using it.
+pub(super) async fn dequeue_pull_request<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: PullRequestNumber,
+) -> anyhow::Result<()> {
+    if repo.config.landing != LandingMode::GithubMergeQueue {
+        return Ok(());
+    }
+    tracing::info!("Removing PR from the GitHub merge queue");
+    // GitHub might have already removed the PR from the queue on its own (e.g. after a failed
+    // merge), so this is not an error.
+    if let Err(error) = repo.client.remove_from_merge_queue(pr).await {
+        tracing::warn!("Could not remove PR {pr} from the merge queue: {error:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::LandingMode;
+    use crate::tests::event::default_pr_number;
+    use crate::tests::state::{ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn test_bors_landing_does_not_use_merge_queue() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[]);
+    }
+
+    #[tokio::test]
+    async fn test_approve_enqueues() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().landing(LandingMode::GithubMergeQueue))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_enqueue() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .landing(LandingMode::GithubMergeQueue)
+                    .dry_run(true),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[]);
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":test_tube: **Dry run**: this pull request would now be added to the merge queue."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unapprove_dequeues() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().landing(LandingMode::GithubMergeQueue))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r-").await;
+        state.client().check_merge_queue(&[]);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_label_holds_pr_out_of_merge_queue() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .landing(LandingMode::GithubMergeQueue)
+                    .blocking_labels(vec!["do-not-merge".to_string()]),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+
+        state
+            .pull_request_labeled(default_pr_number(), "do-not-merge")
+            .await;
+        state.client().check_merge_queue(&[]);
+
+        state
+            .pull_request_unlabeled(default_pr_number(), "do-not-merge")
+            .await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+    }
+}
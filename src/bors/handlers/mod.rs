@@ -1,23 +1,69 @@
 use anyhow::Context;
-use tracing::Instrument;
+use tracing::{Instrument, Span};
 
 use crate::bors::command::BorsCommand;
 use crate::bors::command::CommandParseError;
-use crate::bors::event::{BorsEvent, PullRequestComment};
+use crate::bors::command::CommandParser;
+use crate::bors::event::{BorsEvent, BranchRewrite, DashboardCommand, PullRequestComment};
+use crate::bors::handlers::base_branch::handle_branch_rewritten;
+use crate::bors::handlers::branches::{AUTO_BRANCH_NAME, TRY_BRANCH_NAME};
+use crate::bors::handlers::comments::{flush_deferred_comments, update_summary_comment};
+use crate::bors::handlers::config::{
+    handle_config_changed, handle_default_branch_force_pushed, handle_pull_request_pushed,
+};
+use crate::bors::handlers::pause::{command_pause, command_resume, handle_pause, handle_resume};
 use crate::bors::handlers::ping::command_ping;
-use crate::bors::handlers::refresh::refresh_repository;
-use crate::bors::handlers::trybuild::{command_try_build, command_try_cancel, TRY_BRANCH_NAME};
+use crate::bors::handlers::protection::protect_branches;
+use crate::bors::handlers::pull_request::handle_pull_request_closed;
+use crate::bors::handlers::reconcile::reconcile_repository;
+use crate::bors::handlers::refresh::{prune_webhook_deliveries, refresh_repository};
+use crate::bors::handlers::review::{
+    command_approve, command_set_priority, command_set_rollup, command_unapprove,
+    handle_converted_to_draft, handle_force_dequeue, handle_pull_request_labeled,
+    handle_pull_request_unlabeled, handle_review_approved, handle_review_dismissed,
+    revoke_outdated_approval,
+};
+use crate::bors::handlers::sweep::sweep_repository;
+use crate::bors::handlers::tree::{command_tree_closed, command_tree_open};
+use crate::bors::handlers::trybuild::{command_try_build, command_try_cancel};
 use crate::bors::handlers::workflow::{
-    handle_check_suite_completed, handle_workflow_completed, handle_workflow_started,
+    handle_build_result_reported, handle_check_suite_completed, handle_workflow_completed,
+    handle_workflow_started, poll_external_builds,
 };
+use crate::bors::lease::holds_lease;
 use crate::bors::{BorsContext, BorsState, RepositoryClient, RepositoryState};
-use crate::database::DbClient;
-use crate::github::GithubRepoName;
-use crate::utils::logging::LogError;
+use crate::config::Feature;
+use crate::database::{DbClient, LoggedEvent};
+use crate::error_reporting::{self, ErrorContext};
+use crate::github::{GithubRepoName, GithubUser, PullRequest, PullRequestNumber};
+use crate::messages::MessageKind;
+use crate::metrics::CommandOutcome;
+use crate::notifications::{notify, Notification, NotificationEvent};
+use crate::utils::logging::{CorrelationId, LogError};
+
+pub use branches::AUTO_BRANCH_NAME;
+pub use shutdown::handle_shutdown;
 
+mod base_branch;
+mod branches;
+mod comments;
+mod config;
+mod digest;
 mod labels;
+mod merge_queue;
+mod pause;
+mod permissions;
 mod ping;
+mod protection;
+mod pull_request;
+mod reconcile;
 mod refresh;
+mod regression;
+mod review;
+mod shutdown;
+mod stale;
+mod sweep;
+mod tree;
 mod trybuild;
 mod workflow;
 
@@ -46,17 +92,258 @@ pub async fn handle_bors_event<Client: RepositoryClient>(
                     .instrument(span.clone())
                     .await
                 {
-                    span.log_error(error);
+                    report_error(repo, &span, Some(pr_number), error).await;
+                    repo.client
+                        .post_comment(pr_number, &command_error_comment())
+                        .await
+                        .context("Cannot send comment reacting to an error")?;
+                }
+            }
+        }
+        BorsEvent::ReviewApproved(review) => {
+            if state.is_comment_internal(&review) {
+                return Ok(());
+            }
+
+            if let Some((repo, db)) = get_repo_state(state, &review.repository) {
+                let span = tracing::info_span!(
+                    "Review approved",
+                    pr = format!("{}#{}", review.repository, review.pr_number),
+                    author = review.author.username
+                );
+                let pr_number = review.pr_number;
+                if let Err(error) = handle_review_approved(repo, db, ctx, review)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
                     repo.client
-                        .post_comment(
-                            pr_number,
-                            ":x: Encountered an error while executing command",
-                        )
+                        .post_comment(pr_number, &command_error_comment())
                         .await
                         .context("Cannot send comment reacting to an error")?;
                 }
             }
         }
+        BorsEvent::ReviewDismissed(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Review dismissed",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_review_dismissed(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::PullRequestClosed(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Pull request closed",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_pull_request_closed(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::PullRequestLabeled(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Pull request labeled",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                    label = %payload.label,
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_pull_request_labeled(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::PullRequestUnlabeled(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Pull request unlabeled",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                    label = %payload.label,
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_pull_request_unlabeled(repo, db, ctx, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::PullRequestConvertedToDraft(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Pull request converted to draft",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_converted_to_draft(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::BranchRewritten(payload) => {
+            let force_pushed = match &payload.rewrite {
+                BranchRewrite::ForcePushed { before, after } if payload.default_branch => {
+                    Some((before.clone(), after.clone()))
+                }
+                _ => None,
+            };
+            if state.get_repo_state_mut(&payload.repository).is_none() && force_pushed.is_some() {
+                return reload_skipped_repository(state, &payload.repository).await;
+            }
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Branch rewritten",
+                    repo = payload.repository.to_string(),
+                    branch = %payload.branch,
+                );
+                if let Err(error) = handle_branch_rewritten(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, None, error).await;
+                }
+                if let Some((before, after)) = force_pushed {
+                    if let Err(error) = handle_default_branch_force_pushed(repo, &before, &after)
+                        .instrument(span.clone())
+                        .await
+                    {
+                        report_error(repo, &span, None, error).await;
+                    }
+                }
+            }
+        }
+        BorsEvent::PullRequestPushed(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Pull request pushed",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = revoke_outdated_approval(repo, db, pr_number, &payload.head_sha)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+                if let Err(error) = handle_pull_request_pushed(repo, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::ConfigChanged(payload) => {
+            if state.get_repo_state_mut(&payload.repository).is_none() {
+                return reload_skipped_repository(state, &payload.repository).await;
+            }
+            if let Some((repo, _)) = get_repo_state(state, &payload.repository) {
+                let span =
+                    tracing::info_span!("Config changed", repo = payload.repository.to_string());
+                if let Err(error) = handle_config_changed(repo, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::DashboardCommand(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Dashboard command",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number),
+                    author = payload.author.username
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_dashboard_command(repo, db, ctx, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::ProtectBranches(repository) => {
+            if let Some((repo, _)) = get_repo_state(state, &repository) {
+                let span = tracing::info_span!("Protect branches", repo = repository.to_string());
+                if let Err(error) = protect_branches(repo).instrument(span.clone()).await {
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::ForceDequeue(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Force dequeue",
+                    pr = format!("{}#{}", payload.repository, payload.pr_number)
+                );
+                let pr_number = payload.pr_number;
+                if let Err(error) = handle_force_dequeue(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, Some(pr_number), error).await;
+                }
+            }
+        }
+        BorsEvent::Resync(repository) => {
+            if let Some((repo, db)) = get_repo_state(state, &repository) {
+                let span = tracing::info_span!("Resync", repo = repository.to_string());
+                if let Err(error) = reconcile_repository(repo, db, ctx)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::Pause(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!("Pause", repo = payload.repository.to_string());
+                if let Err(error) = handle_pause(repo, db, payload.reason)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::Resume(repository) => {
+            if let Some((repo, db)) = get_repo_state(state, &repository) {
+                let span = tracing::info_span!("Resume", repo = repository.to_string());
+                if let Err(error) = handle_resume(repo, db, ctx).instrument(span.clone()).await {
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::FlushUpdates(repository) => {
+            if let Some((repo, db)) = get_repo_state(state, &repository) {
+                let span = tracing::info_span!("Flush updates", repo = repository.to_string());
+                flush_deferred_comments(repo, db).instrument(span).await;
+            }
+        }
         BorsEvent::InstallationsChanged => {
             let span = tracing::info_span!("Repository reload");
             if let Err(error) = state.reload_repositories().instrument(span.clone()).await {
@@ -64,17 +351,17 @@ pub async fn handle_bors_event<Client: RepositoryClient>(
             }
         }
         BorsEvent::WorkflowStarted(payload) => {
-            if let Some((_, db)) = get_repo_state(state, &payload.repository) {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
                 let span = tracing::info_span!(
                     "Workflow started",
                     repo = payload.repository.to_string(),
                     id = payload.run_id.into_inner()
                 );
-                if let Err(error) = handle_workflow_started(db, payload)
+                if let Err(error) = handle_workflow_started(repo, db, payload)
                     .instrument(span.clone())
                     .await
                 {
-                    span.log_error(error);
+                    report_error(repo, &span, None, error).await;
                 }
             }
         }
@@ -89,7 +376,23 @@ pub async fn handle_bors_event<Client: RepositoryClient>(
                     .instrument(span.clone())
                     .await
                 {
-                    span.log_error(error);
+                    report_error(repo, &span, None, error).await;
+                }
+            }
+        }
+        BorsEvent::BuildResultReported(payload) => {
+            if let Some((repo, db)) = get_repo_state(state, &payload.repository) {
+                let span = tracing::info_span!(
+                    "Build result reported",
+                    repo = payload.repository.to_string(),
+                    build = payload.build_id,
+                    name = payload.name.as_str()
+                );
+                if let Err(error) = handle_build_result_reported(repo, db, payload)
+                    .instrument(span.clone())
+                    .await
+                {
+                    report_error(repo, &span, None, error).await;
                 }
             }
         }
@@ -103,20 +406,164 @@ pub async fn handle_bors_event<Client: RepositoryClient>(
                     .instrument(span.clone())
                     .await
                 {
-                    span.log_error(error);
+                    report_error(repo, &span, None, error).await;
                 }
             }
         }
         BorsEvent::Refresh => {
             let span = tracing::info_span!("Refresh");
             let (repos, db) = state.get_all_repos_mut();
-            futures::future::join_all(repos.into_iter().map(|repo| async {
+            let mut repos = leased_repos(repos, db, ctx).instrument(span.clone()).await;
+            prune_webhook_deliveries(db, ctx)
+                .instrument(span.clone())
+                .await;
+            futures::future::join_all(repos.iter_mut().map(|repo| async {
                 let subspan = tracing::info_span!("Repo", repo = repo.repository.to_string());
-                refresh_repository(repo, db).instrument(subspan).await
+                refresh_repository(repo, db, ctx).instrument(subspan).await
             }))
+            .instrument(span.clone())
+            .await;
+            // Finished builds are completed one by one, because that requires exclusive access
+            // to the database client.
+            async {
+                for repo in repos {
+                    let subspan = tracing::info_span!("Repo", repo = repo.repository.to_string());
+                    if let Err(error) = poll_external_builds(repo, db, ctx)
+                        .instrument(subspan.clone())
+                        .await
+                    {
+                        report_error(repo, &subspan, None, error).await;
+                    }
+                }
+            }
             .instrument(span)
             .await;
         }
+        BorsEvent::Reconcile => {
+            let span = tracing::info_span!("Reconcile");
+            let (repos, db) = state.get_all_repos_mut();
+            let repos = leased_repos(repos, db, ctx).instrument(span.clone()).await;
+            // Repositories are reconciled one by one, because finishing builds requires
+            // exclusive access to the database client.
+            async {
+                for repo in repos {
+                    let subspan = tracing::info_span!("Repo", repo = repo.repository.to_string());
+                    if let Err(error) = reconcile_repository(repo, db, ctx)
+                        .instrument(subspan.clone())
+                        .await
+                    {
+                        report_error(repo, &subspan, None, error).await;
+                    }
+                }
+            }
+            .instrument(span)
+            .await;
+        }
+        BorsEvent::Sweep => {
+            let span = tracing::info_span!("Sweep");
+            let (repos, db) = state.get_all_repos_mut();
+            let repos = leased_repos(repos, db, ctx).instrument(span.clone()).await;
+            // Repositories are swept one by one, because finishing builds requires exclusive
+            // access to the database client.
+            async {
+                for repo in repos {
+                    let subspan = tracing::info_span!("Repo", repo = repo.repository.to_string());
+                    if let Err(error) = sweep_repository(repo, db, ctx)
+                        .instrument(subspan.clone())
+                        .await
+                    {
+                        report_error(repo, &subspan, None, error).await;
+                    }
+                }
+            }
+            .instrument(span)
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the repositories whose lease is held by this replica, which should be periodically
+/// refreshed, reconciled and swept by it.
+async fn leased_repos<'a, Client: RepositoryClient>(
+    repos: Vec<&'a mut RepositoryState<Client>>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+) -> Vec<&'a mut RepositoryState<Client>> {
+    let mut leased = Vec::with_capacity(repos.len());
+    for repo in repos {
+        let span = tracing::info_span!("Repo", repo = repo.repository.to_string());
+        if holds_lease(repo, db, ctx).instrument(span).await {
+            leased.push(repo);
+        }
+    }
+    leased
+}
+
+/// Logs an error that has happened while handling an event and sends it to the notifiers of the
+/// repository.
+async fn report_error<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    span: &Span,
+    pr: Option<PullRequestNumber>,
+    error: anyhow::Error,
+) {
+    let event = span.metadata().map_or("event", |metadata| metadata.name());
+    error_reporting::report_error(
+        &error,
+        ErrorContext {
+            event,
+            repository: Some(&repo.repository),
+            pr,
+        },
+    );
+    let mut message = format!("Error while handling `{event}`: {error:#}");
+    if let Some(id) = CorrelationId::current() {
+        message.push_str(&format!(" (error ID: `{id}`)"));
+    }
+    span.log_error(error);
+    notify(
+        repo,
+        Notification {
+            event: NotificationEvent::BotError,
+            repository: repo.repository.clone(),
+            pr: None,
+            message,
+        },
+    )
+    .await;
+}
+
+/// Reply to a comment whose commands could not be executed because of an error. It includes the
+/// correlation ID of the event, which can be used to find the error in the logs.
+fn command_error_comment() -> String {
+    match CorrelationId::current() {
+        Some(id) => format!(":x: Encountered an error while executing command (error ID: `{id}`)"),
+        None => ":x: Encountered an error while executing command".to_string(),
+    }
+}
+
+/// Prefix of the commands of the repository, which is used in replies that refer to commands.
+pub(super) fn command_prefix<'a, Client: RepositoryClient>(
+    repo: &'a RepositoryState<Client>,
+    ctx: &'a BorsContext,
+) -> &'a str {
+    repo.config
+        .command_prefix
+        .as_deref()
+        .unwrap_or_else(|| ctx.parser.prefix())
+}
+
+/// Reloads all repositories after the configuration of a repository that is not loaded may have
+/// changed. The repository could have been skipped because its configuration was missing or
+/// invalid, so it can start being used once its configuration is fixed.
+async fn reload_skipped_repository<Client: RepositoryClient>(
+    state: &mut dyn BorsState<Client>,
+    repo: &GithubRepoName,
+) -> anyhow::Result<()> {
+    let span = tracing::info_span!("Repository reload", repo = repo.to_string());
+    if let Err(error) = state.reload_repositories().instrument(span.clone()).await {
+        span.log_error(error);
     }
     Ok(())
 }
@@ -141,36 +588,27 @@ async fn handle_comment<Client: RepositoryClient>(
     comment: PullRequestComment,
 ) -> anyhow::Result<()> {
     let pr_number = comment.pr_number;
-    let commands = ctx.parser.parse_commands(&comment.text);
+    let repo_parser = repo.config.command_prefix.clone().map(CommandParser::new);
+    let parser = repo_parser.as_ref().unwrap_or(&ctx.parser);
+    let commands = parser.parse_commands(&comment.text);
     let pull_request = repo.client.get_pull_request(pr_number).await?;
 
     tracing::debug!("Commands: {commands:?}");
     tracing::trace!("Text: {}", comment.text);
 
+    // Only commands that can change the state of the PR are reflected in its summary comment
+    let mut state_changed = false;
     for command in commands {
         match command {
             Ok(command) => {
-                let result = match command {
-                    BorsCommand::Ping => {
-                        let span = tracing::info_span!("Ping");
-                        command_ping(repo, &pull_request).instrument(span).await
-                    }
-                    BorsCommand::Try { parent } => {
-                        let span = tracing::info_span!("Try");
-                        command_try_build(repo, database, &pull_request, &comment.author, parent)
-                            .instrument(span)
-                            .await
-                    }
-                    BorsCommand::TryCancel => {
-                        let span = tracing::info_span!("Cancel try");
-                        command_try_cancel(repo, database, &pull_request, &comment.author)
-                            .instrument(span)
-                            .await
-                    }
-                };
-                if result.is_err() {
-                    return result.context("Cannot execute Bors command");
-                }
+                state_changed |= !matches!(command, BorsCommand::Ping);
+                log_command(repo, database, pr_number, &comment.author, &command).await;
+                let name = command.name();
+                let result =
+                    execute_command(repo, database, ctx, &pull_request, &comment.author, command)
+                        .await;
+                record_command(repo, ctx, name, &result);
+                result.context("Cannot execute Bors command")?;
             }
             Err(error) => {
                 let error_msg = match error {
@@ -193,26 +631,276 @@ async fn handle_comment<Client: RepositoryClient>(
                 };
 
                 tracing::warn!("{error_msg}");
+                ctx.metrics
+                    .record(&repo.repository, "invalid", CommandOutcome::ParseError);
 
+                let message = repo.config.messages.render(
+                    MessageKind::ParseError,
+                    &repo.repository,
+                    pull_request.number,
+                    &[("error", &error_msg)],
+                );
                 repo.client
-                    .post_comment(pull_request.number, &error_msg)
+                    .post_comment(pull_request.number, &message)
                     .await
                     .context("Could not reply to PR comment")?;
             }
         }
     }
+    if state_changed {
+        update_summary_comment(repo, database, pr_number).await?;
+    }
+    Ok(())
+}
+
+/// Records the outcome of an executed command in the metrics of the bot.
+fn record_command<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    ctx: &BorsContext,
+    command: &'static str,
+    result: &anyhow::Result<CommandOutcome>,
+) {
+    let outcome = match result {
+        Ok(outcome) => *outcome,
+        Err(_) => CommandOutcome::Failed,
+    };
+    ctx.metrics.record(&repo.repository, command, outcome);
+}
+
+/// Checks that `author` is allowed to use the command. Review commands that only affect the PR
+/// (e.g. `r+` or `p=`) can also be used by the user that review rights of the PR were delegated
+/// to, if delegation is enabled. If the author is not allowed to use the command, they are told
+/// so in a comment.
+async fn check_command_permission<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    database: &dyn DbClient,
+    pull_request: &PullRequest,
+    author: &GithubUser,
+    command: &BorsCommand,
+) -> anyhow::Result<bool> {
+    let Some(permission) = command.required_permission() else {
+        return Ok(true);
+    };
+    if repo
+        .permissions_resolver
+        .has_permission(&author.username, permission)
+        .await
+    {
+        return Ok(true);
+    }
+
+    let delegable = matches!(
+        command,
+        BorsCommand::Approve { .. }
+            | BorsCommand::Unapprove
+            | BorsCommand::SetPriority { .. }
+            | BorsCommand::SetRollup { .. }
+            | BorsCommand::ClearRollup
+    );
+    if delegable && repo.config.features.is_enabled(Feature::Delegation) {
+        let delegate = database
+            .find_pull_request(&repo.repository, pull_request.number)
+            .await?
+            .and_then(|pr| pr.delegated_to);
+        if delegate.map_or(false, |delegate| {
+            delegate.eq_ignore_ascii_case(&author.username)
+        }) {
+            return Ok(true);
+        }
+    }
+
+    tracing::info!("Permission denied");
+    repo.client
+        .post_comment(
+            pull_request.number,
+            &repo.config.messages.render(
+                MessageKind::InsufficientPrivileges,
+                &repo.repository,
+                pull_request.number,
+                &[("user", &author.username), ("role", permission.name())],
+            ),
+        )
+        .await?;
+    Ok(false)
+}
+
+/// Appends the received command to the event log. Failing to record it does not prevent the
+/// command from being executed.
+async fn log_command<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    database: &mut dyn DbClient,
+    pr_number: PullRequestNumber,
+    author: &GithubUser,
+    command: &BorsCommand,
+) {
+    let event = LoggedEvent::CommandReceived {
+        author: author.username.clone(),
+        command: command.name().to_string(),
+    };
+    if let Err(error) = database
+        .record_event(&repo.repository, Some(pr_number), event)
+        .await
+    {
+        tracing::error!("Cannot record command {}: {error:?}", command.name());
+    }
+}
+
+/// Executes a command on behalf of `author`, no matter whether it was posted in a comment or
+/// requested using the dashboard. Returns whether the command was executed or denied.
+async fn execute_command<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    database: &mut dyn DbClient,
+    ctx: &BorsContext,
+    pull_request: &PullRequest,
+    author: &GithubUser,
+    command: BorsCommand,
+) -> anyhow::Result<CommandOutcome> {
+    if !check_command_permission(repo, database, pull_request, author, &command).await? {
+        return Ok(CommandOutcome::PermissionDenied);
+    }
+
+    match command {
+        BorsCommand::Ping => {
+            let span = tracing::info_span!("Ping");
+            command_ping(repo, pull_request).instrument(span).await
+        }
+        BorsCommand::Approve {
+            approver,
+            ignore_limits,
+            sha,
+        } => {
+            let span = tracing::info_span!("Approve");
+            command_approve(
+                repo,
+                database,
+                ctx,
+                pull_request,
+                author,
+                approver,
+                ignore_limits,
+                sha,
+            )
+            .instrument(span)
+            .await
+        }
+        BorsCommand::Unapprove => {
+            let span = tracing::info_span!("Unapprove");
+            command_unapprove(repo, database, pull_request)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::Try {
+            parent,
+            commit,
+            jobs,
+        } => {
+            let span = tracing::info_span!("Try");
+            command_try_build(repo, database, ctx, pull_request, parent, commit, jobs)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::TryCancel => {
+            let span = tracing::info_span!("Cancel try");
+            command_try_cancel(repo, database, pull_request)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::SetPriority { priority } => {
+            let span = tracing::info_span!("Set priority");
+            command_set_priority(repo, database, pull_request, priority)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::SetRollup { mode } => {
+            let span = tracing::info_span!("Set rollup");
+            command_set_rollup(repo, database, pull_request, Some(mode))
+                .instrument(span)
+                .await
+        }
+        BorsCommand::ClearRollup => {
+            let span = tracing::info_span!("Clear rollup");
+            command_set_rollup(repo, database, pull_request, None)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::TreeClosed { priority, reason } => {
+            let span = tracing::info_span!("Tree closed");
+            command_tree_closed(repo, database, pull_request, author, priority, reason)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::TreeOpen => {
+            let span = tracing::info_span!("Tree open");
+            command_tree_open(repo, database, ctx, pull_request, author)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::Pause { reason } => {
+            let span = tracing::info_span!("Pause");
+            command_pause(repo, database, pull_request, author, reason)
+                .instrument(span)
+                .await
+        }
+        BorsCommand::Resume => {
+            let span = tracing::info_span!("Resume");
+            command_resume(repo, database, ctx, pull_request, author)
+                .instrument(span)
+                .await
+        }
+    }?;
+    Ok(CommandOutcome::Executed)
+}
+
+/// Executes a command requested using the dashboard. It is recorded in the event log and it
+/// performs the same permission checks as a command posted in a comment.
+async fn handle_dashboard_command<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    database: &mut dyn DbClient,
+    ctx: &BorsContext,
+    payload: DashboardCommand,
+) -> anyhow::Result<()> {
+    let pull_request = repo.client.get_pull_request(payload.pr_number).await?;
+    log_command(
+        repo,
+        database,
+        payload.pr_number,
+        &payload.author,
+        &payload.command,
+    )
+    .await;
+    let state_changed = !matches!(payload.command, BorsCommand::Ping);
+    let name = payload.command.name();
+    let result = execute_command(
+        repo,
+        database,
+        ctx,
+        &pull_request,
+        &payload.author,
+        payload.command,
+    )
+    .await;
+    record_command(repo, ctx, name, &result);
+    result.context("Cannot execute dashboard command")?;
+    if state_changed {
+        update_summary_comment(repo, database, payload.pr_number).await?;
+    }
     Ok(())
 }
 
 /// Is this branch interesting for the bot?
 fn is_bors_observed_branch(branch: &str) -> bool {
-    branch == TRY_BRANCH_NAME
+    branch == TRY_BRANCH_NAME || branch == AUTO_BRANCH_NAME
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tests::event::{comment, default_pr_number};
-    use crate::tests::state::{test_bot_user, ClientBuilder};
+    use crate::bors::command::BorsCommand;
+    use crate::bors::event::{BorsEvent, DashboardCommand};
+    use crate::database::{DbClient, LoggedEvent, ReplayedPullRequest};
+    use crate::metrics::CommandOutcome;
+    use crate::tests::event::{comment, default_pr_number, default_user};
+    use crate::tests::permissions::NoPermissions;
+    use crate::tests::state::{default_repo_name, test_bot_user, ClientBuilder, RepoConfigBuilder};
 
     #[tokio::test]
     async fn test_ignore_bot_comment() {
@@ -222,4 +910,210 @@ mod tests {
             .await;
         state.client().check_comments(default_pr_number(), &[]);
     }
+
+    #[tokio::test]
+    async fn test_command_metrics() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.comment("@bors ping").await;
+        state.comment("@bors foo").await;
+
+        let repo = default_repo_name();
+        assert_eq!(
+            state
+                .metrics
+                .count(&repo, "approve", CommandOutcome::Executed),
+            1
+        );
+        assert_eq!(
+            state.metrics.count(&repo, "ping", CommandOutcome::Executed),
+            1
+        );
+        assert_eq!(
+            state
+                .metrics
+                .count(&repo, "invalid", CommandOutcome::ParseError),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_metrics_permission_denied() {
+        let mut state = ClientBuilder::default()
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        assert_eq!(
+            state.metrics.count(
+                &default_repo_name(),
+                "try",
+                CommandOutcome::PermissionDenied
+            ),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delegated_review_commands() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().features(toml::from_str("delegation = true").unwrap()),
+            )
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        let repo = default_repo_name();
+        let pr = state
+            .db
+            .get_or_create_pull_request(&repo, default_pr_number())
+            .await
+            .unwrap();
+        state
+            .db
+            .set_delegate(&pr, Some(&default_user().username))
+            .await
+            .unwrap();
+
+        state.comment("@bors r+").await;
+        state.comment("@bors try").await;
+        let pr = state
+            .db
+            .find_pull_request(&repo, default_pr_number())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(pr.is_approved());
+        assert_eq!(
+            state
+                .metrics
+                .count(&repo, "approve", CommandOutcome::Executed),
+            1
+        );
+        assert_eq!(
+            state
+                .metrics
+                .count(&repo, "try", CommandOutcome::PermissionDenied),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delegated_review_commands_disabled() {
+        let mut state = ClientBuilder::default()
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        let repo = default_repo_name();
+        let pr = state
+            .db
+            .get_or_create_pull_request(&repo, default_pr_number())
+            .await
+            .unwrap();
+        state
+            .db
+            .set_delegate(&pr, Some(&default_user().username))
+            .await
+            .unwrap();
+
+        state.comment("@bors r+").await;
+        assert_eq!(
+            state
+                .metrics
+                .count(&repo, "approve", CommandOutcome::PermissionDenied),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_log() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r-").await;
+
+        let events: Vec<LoggedEvent> = state
+            .db
+            .get_events(&default_repo_name(), Some(default_pr_number().into()))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[0], LoggedEvent::CommandReceived { .. }));
+        assert_eq!(
+            events[1],
+            LoggedEvent::Approved {
+                approver: "<user>".to_string()
+            }
+        );
+        assert!(matches!(events[2], LoggedEvent::CommandReceived { .. }));
+        assert_eq!(events[3], LoggedEvent::Unapproved);
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_command() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .event(BorsEvent::DashboardCommand(DashboardCommand {
+                repository: default_repo_name(),
+                author: default_user(),
+                pr_number: default_pr_number().into(),
+                command: BorsCommand::SetPriority { priority: 3 },
+            }))
+            .await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.priority, 3);
+        let events = state
+            .db
+            .get_events(&default_repo_name(), Some(default_pr_number().into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            events[0].event,
+            LoggedEvent::CommandReceived {
+                author: "<user>".to_string(),
+                command: "set_priority".to_string()
+            }
+        );
+        assert_eq!(
+            events[1].event,
+            LoggedEvent::PriorityChanged { priority: 3 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_event_log() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors p=2").await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r-").await;
+        state.comment("@bors r=foo").await;
+
+        let repo = default_repo_name();
+        let events: Vec<LoggedEvent> = state
+            .db
+            .get_events(&repo, Some(default_pr_number().into()))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        let pr = state
+            .db
+            .find_pull_request(&repo, default_pr_number().into())
+            .await
+            .unwrap()
+            .unwrap();
+        let replayed = ReplayedPullRequest::replay(&events);
+        assert_eq!(replayed.approved_by, pr.approved_by);
+        assert_eq!(replayed.approved_by.as_deref(), Some("foo"));
+        assert_eq!(replayed.priority, pr.priority);
+        assert_eq!(replayed.priority, 2);
+    }
 }
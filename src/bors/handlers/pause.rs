@@ -0,0 +1,289 @@
+//! Pausing of a repository, e.g. during an incident of the CI infrastructure or a release freeze.
+//! While a repository is paused, commands are still acknowledged (approvals and priorities are
+//! recorded), but no try builds are started and no PRs are merged. PRs are taken out of the merge
+//! queue of GitHub and they are put back once the repository is resumed.
+//!
+//! The pause is stored in the database, so it survives restarts of the bot.
+use crate::bors::handlers::merge_queue::dequeue_pull_request;
+use crate::bors::handlers::reconcile::requeue_pull_request;
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::LandingMode;
+use crate::database::{DbClient, RepositoryPause};
+use crate::github::{GithubUser, PullRequest};
+use crate::messages::MessageKind;
+use crate::notifications::{notify, Notification, NotificationEvent};
+
+/// Pauses the repository on behalf of the author of a command.
+pub(super) async fn command_pause<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+    author: &GithubUser,
+    reason: String,
+) -> anyhow::Result<()> {
+    let description = pause_repository(repo, db, Some(&author.username), reason.clone()).await?;
+    let message = repo.config.messages.render(
+        MessageKind::Paused,
+        &repo.repository,
+        pr.number,
+        &[
+            ("user", &author.username),
+            ("reason", &reason),
+            ("description", &description),
+        ],
+    );
+    repo.client.post_comment(pr.number, &message).await
+}
+
+/// Resumes the repository on behalf of the author of a command.
+pub(super) async fn command_resume<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+    pr: &PullRequest,
+    author: &GithubUser,
+) -> anyhow::Result<()> {
+    if db.get_pause(&repo.repository).await?.is_none() {
+        let message =
+            repo.config
+                .messages
+                .render(MessageKind::NotPaused, &repo.repository, pr.number, &[]);
+        repo.client.post_comment(pr.number, &message).await?;
+        return Ok(());
+    }
+    resume_repository(repo, db, ctx, Some(&author.username)).await?;
+    let message = repo.config.messages.render(
+        MessageKind::Resumed,
+        &repo.repository,
+        pr.number,
+        &[("user", &author.username)],
+    );
+    repo.client.post_comment(pr.number, &message).await
+}
+
+/// Pauses the repository, as requested using the admin API.
+pub(super) async fn handle_pause<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    reason: String,
+) -> anyhow::Result<()> {
+    pause_repository(repo, db, None, reason).await?;
+    Ok(())
+}
+
+/// Resumes the repository, as requested using the admin API.
+pub(super) async fn handle_resume<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+) -> anyhow::Result<()> {
+    if db.get_pause(&repo.repository).await?.is_none() {
+        tracing::info!("Repository is not paused");
+        return Ok(());
+    }
+    resume_repository(repo, db, ctx, None).await?;
+    Ok(())
+}
+
+/// Describes who has paused the repository and why.
+pub fn describe_pause(pause: &RepositoryPause) -> String {
+    let who = match &pause.paused_by {
+        Some(user) => format!("by `{user}`"),
+        None => "by an administrator".to_string(),
+    };
+    let mut text = format!("The repository has been paused {who}");
+    if pause.reason.is_empty() {
+        text.push('.');
+    } else {
+        text.push_str(&format!(": {}", pause.reason));
+    }
+    text
+}
+
+async fn pause_repository<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    paused_by: Option<&str>,
+    reason: String,
+) -> anyhow::Result<String> {
+    db.pause_repository(&repo.repository, paused_by, &reason)
+        .await?;
+    tracing::info!("Repository paused");
+
+    // GitHub would keep merging the PRs that are in its merge queue
+    if repo.config.landing == LandingMode::GithubMergeQueue {
+        for pr in db.get_approved_pull_requests(&repo.repository).await? {
+            dequeue_pull_request(repo, pr.number).await?;
+        }
+    }
+
+    let message = describe_pause(&RepositoryPause {
+        paused_by: paused_by.map(|user| user.to_string()),
+        reason,
+        paused_at: chrono::Utc::now(),
+    });
+    announce(repo, NotificationEvent::RepositoryPaused, message.clone()).await;
+    Ok(message)
+}
+
+async fn resume_repository<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+    resumed_by: Option<&str>,
+) -> anyhow::Result<String> {
+    db.resume_repository(&repo.repository, resumed_by).await?;
+    tracing::info!("Repository resumed");
+
+    if repo.config.landing == LandingMode::GithubMergeQueue {
+        for pr in db.get_approved_pull_requests(&repo.repository).await? {
+            if let Err(error) = requeue_pull_request(repo, db, ctx, pr.number).await {
+                tracing::error!("Could not re-enqueue PR {}: {error:?}", pr.number);
+            }
+        }
+    }
+
+    let message = match resumed_by {
+        Some(user) => format!("The repository has been resumed by `{user}`."),
+        None => "The repository has been resumed by an administrator.".to_string(),
+    };
+    announce(repo, NotificationEvent::RepositoryResumed, message.clone()).await;
+    Ok(message)
+}
+
+async fn announce<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    event: NotificationEvent,
+    message: String,
+) {
+    notify(
+        repo,
+        Notification {
+            event,
+            repository: repo.repository.clone(),
+            pr: None,
+            message,
+        },
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::LandingMode;
+    use crate::database::DbClient;
+    use crate::notifications::{NotificationEvent, NotificationRoute};
+    use crate::tests::event::default_pr_number;
+    use crate::tests::notifications::RecordingNotifier;
+    use crate::tests::permissions::NoPermissions;
+    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .notifications(vec![NotificationRoute {
+                events: vec![
+                    NotificationEvent::RepositoryPaused,
+                    NotificationEvent::RepositoryResumed,
+                ],
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state.comment("@bors pause CI is down").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":pause_button: The repository has been paused by `<user>`: CI is down"
+        );
+        assert!(state
+            .db
+            .get_pause(&default_repo_name())
+            .await
+            .unwrap()
+            .is_some());
+
+        state.comment("@bors resume").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":arrow_forward: The repository has been resumed by `<user>`."
+        );
+        assert!(state
+            .db
+            .get_pause(&default_repo_name())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            notifier.events(),
+            vec![
+                NotificationEvent::RepositoryPaused,
+                NotificationEvent::RepositoryResumed
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_not_paused() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors resume").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":exclamation: The repository is not paused."],
+        );
+    }
+
+    #[tokio::test]
+    async fn pause_no_permission() {
+        let mut state = ClientBuilder::default()
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        state.comment("@bors pause").await;
+        assert!(state
+            .db
+            .get_pause(&default_repo_name())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn no_try_build_while_paused() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors pause").await;
+        state.comment("@bors try").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":pause_button: Try builds are not started while the repository is paused. The repository has been paused by `<user>`."
+        );
+        let pr = state
+            .db
+            .find_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(pr.try_build.is_none());
+    }
+
+    #[tokio::test]
+    async fn hold_approved_prs_out_of_merge_queue() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().landing(LandingMode::GithubMergeQueue))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+
+        state.comment("@bors pause").await;
+        state.client().check_merge_queue(&[]);
+
+        // Approvals are still recorded, but the PR waits until the repository is resumed
+        state.comment("@bors r-").await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[]);
+
+        state.comment("@bors resume").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Reactions to changes of the permissions of users, which are found when the permissions of a
+//! repository are reloaded. Every change is recorded in the event log (and so in the audit trail)
+//! and sent as the `permissions_changed` notification, and users that have lost a permission lose
+//! the review rights delegated to them (if the `delegation` feature is enabled).
+use std::collections::HashSet;
+
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::config::Feature;
+use crate::database::{DbClient, LoggedEvent};
+use crate::notifications::{notify, Notification, NotificationEvent};
+use crate::permissions::PermissionChange;
+
+pub(super) async fn handle_permission_changes<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+) -> anyhow::Result<()> {
+    let changes = repo.permissions_resolver.take_changes();
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut removed = HashSet::new();
+    for change in &changes {
+        for (users, granted) in [(&change.added, true), (&change.removed, false)] {
+            for user in users {
+                let event = LoggedEvent::PermissionChanged {
+                    user: user.clone(),
+                    permission: change.permission.name().to_string(),
+                    granted,
+                };
+                db.record_event(&repo.repository, None, event).await?;
+            }
+        }
+        removed.extend(change.removed.iter().map(|user| user.to_lowercase()));
+    }
+    notify(
+        repo,
+        Notification {
+            event: NotificationEvent::PermissionsChanged,
+            repository: repo.repository.clone(),
+            pr: None,
+            message: changes
+                .iter()
+                .map(format_change)
+                .collect::<Vec<_>>()
+                .join("; "),
+        },
+    )
+    .await;
+
+    if removed.is_empty() || !repo.config.features.is_enabled(Feature::Delegation) {
+        return Ok(());
+    }
+    for pr in db.get_pull_requests(&repo.repository).await? {
+        let Some(delegate) = pr.delegated_to.clone() else {
+            continue;
+        };
+        if removed.contains(&delegate.to_lowercase()) {
+            tracing::info!("Revoking delegation of PR {} to {delegate}", pr.number);
+            db.set_delegate(&pr, None).await?;
+            db.record_event(
+                &repo.repository,
+                Some(pr.number),
+                LoggedEvent::DelegationRevoked { delegate },
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+fn format_change(change: &PermissionChange) -> String {
+    let users = |users: &[String]| {
+        users
+            .iter()
+            .map(|user| format!("`{user}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut parts = vec![];
+    if !change.added.is_empty() {
+        parts.push(format!("granted to {}", users(&change.added)));
+    }
+    if !change.removed.is_empty() {
+        parts.push(format!("revoked from {}", users(&change.removed)));
+    }
+    format!(
+        "Permission `{}` {}",
+        change.permission.name(),
+        parts.join(" and ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::async_trait;
+
+    use crate::database::{DbClient, LoggedEvent};
+    use crate::permissions::{PermissionChange, PermissionResolver, PermissionType};
+    use crate::tests::event::default_pr_number;
+    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+
+    struct ChangedPermissions(Mutex<Vec<PermissionChange>>);
+
+    #[async_trait]
+    impl PermissionResolver for ChangedPermissions {
+        async fn has_permission(&self, _username: &str, _permission: PermissionType) -> bool {
+            true
+        }
+
+        fn take_changes(&self) -> Vec<PermissionChange> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_delegation_of_removed_user() {
+        let resolver = ChangedPermissions(Mutex::new(vec![PermissionChange {
+            permission: PermissionType::Try,
+            added: vec!["alice".to_string()],
+            removed: vec!["bob".to_string()],
+        }]));
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().features(toml::from_str("delegation = true").unwrap()),
+            )
+            .permission_resolver(Box::new(resolver))
+            .create_state()
+            .await;
+        let repo = default_repo_name();
+        let pr = state
+            .db
+            .get_or_create_pull_request(&repo, default_pr_number())
+            .await
+            .unwrap();
+        state.db.set_delegate(&pr, Some("Bob")).await.unwrap();
+
+        state.refresh().await;
+        let pr = state
+            .db
+            .find_pull_request(&repo, default_pr_number())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pr.delegated_to, None);
+
+        let events: Vec<LoggedEvent> = state
+            .db
+            .get_events(&repo, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                LoggedEvent::DelegationChanged {
+                    delegate: Some("Bob".to_string())
+                },
+                LoggedEvent::PermissionChanged {
+                    user: "alice".to_string(),
+                    permission: "try".to_string(),
+                    granted: true
+                },
+                LoggedEvent::PermissionChanged {
+                    user: "bob".to_string(),
+                    permission: "try".to_string(),
+                    granted: false
+                },
+                LoggedEvent::DelegationChanged { delegate: None },
+                LoggedEvent::DelegationRevoked {
+                    delegate: "Bob".to_string()
+                },
+            ]
+        );
+    }
+}
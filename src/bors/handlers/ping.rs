@@ -1,19 +1,24 @@
 use crate::bors::RepositoryClient;
 use crate::bors::RepositoryState;
 use crate::github::PullRequest;
+use crate::messages::MessageKind;
 
 pub(super) async fn command_ping<Client: RepositoryClient>(
     repo: &mut RepositoryState<Client>,
     pr: &PullRequest,
 ) -> anyhow::Result<()> {
-    repo.client.post_comment(pr.number, "Pong 🏓!").await?;
+    let message = repo
+        .config
+        .messages
+        .render(MessageKind::Pong, &repo.repository, pr.number, &[]);
+    repo.client.post_comment(pr.number, &message).await?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::tests::event::default_pr_number;
-    use crate::tests::state::ClientBuilder;
+    use crate::tests::state::{ClientBuilder, RepoConfigBuilder};
 
     #[tokio::test]
     async fn test_ping() {
@@ -23,4 +28,32 @@ mod tests {
             .client()
             .check_comments(default_pr_number(), &["Pong 🏓!"]);
     }
+
+    #[tokio::test]
+    async fn test_ping_repository_prefix() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().command_prefix(Some("@ci".to_string())))
+            .create_state()
+            .await;
+        state.comment("@bors ping").await;
+        state.comment("@ci ping").await;
+        state
+            .client()
+            .check_comments(default_pr_number(), &["Pong 🏓!"]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_custom_message() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .messages(toml::from_str(r#"pong = "Here I am""#).unwrap()),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors ping").await;
+        state
+            .client()
+            .check_comments(default_pr_number(), &["Here I am"]);
+    }
 }
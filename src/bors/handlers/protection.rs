@@ -0,0 +1,92 @@
+use crate::bors::handlers::branches::BORS_BRANCH_PATTERN;
+use crate::bors::{BranchProtection, RepositoryClient, RepositoryState};
+
+/// Configures branch protection of the branches managed by bors, so that a new repository does
+/// not have to be set up manually.
+///
+/// The configured base branches require the configured checks and only the bot can push to them.
+/// Only the bot can push to the branches used for testing (the whole `automation/bors/` namespace,
+/// including the try, auto and temporary merge branches), but it can reset them using force-pushes
+/// and delete them.
+pub(super) async fn protect_branches<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+) -> anyhow::Result<()> {
+    let config = repo.config.branch_protection.clone();
+    let base_protection = BranchProtection {
+        required_checks: config.required_checks,
+        allow_force_pushes: false,
+        allow_deletions: false,
+    };
+    let bors_protection = BranchProtection {
+        required_checks: vec![],
+        allow_force_pushes: true,
+        allow_deletions: true,
+    };
+
+    let branches = config
+        .branches
+        .iter()
+        .map(|branch| (branch.as_str(), &base_protection))
+        .chain(std::iter::once((BORS_BRANCH_PATTERN, &bors_protection)));
+
+    let mut failed = vec![];
+    for (branch, protection) in branches {
+        match repo.client.protect_branch(branch, protection).await {
+            Ok(()) => tracing::info!("Branch {branch} protected"),
+            Err(error) => {
+                tracing::error!("Could not protect branch {branch}: {error:?}");
+                failed.push(branch.to_string());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Could not protect branch(es) {}",
+            failed.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::branches::BORS_BRANCH_PATTERN;
+    use crate::bors::BranchProtection;
+    use crate::config::BranchProtectionConfig;
+    use crate::tests::state::{ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn test_protect_branches() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().branch_protection(BranchProtectionConfig {
+                    branches: vec!["main".to_string()],
+                    required_checks: vec!["ci".to_string()],
+                }),
+            )
+            .create_state()
+            .await;
+        state.protect_branches().await;
+
+        let protected = &state.client().protected_branches;
+        assert_eq!(protected.len(), 2);
+        assert_eq!(
+            protected["main"],
+            BranchProtection {
+                required_checks: vec!["ci".to_string()],
+                allow_force_pushes: false,
+                allow_deletions: false,
+            }
+        );
+        assert_eq!(
+            protected[BORS_BRANCH_PATTERN],
+            BranchProtection {
+                required_checks: vec![],
+                allow_force_pushes: true,
+                allow_deletions: true,
+            }
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use crate::bors::event::PullRequestClosed;
+use crate::bors::handlers::branches::try_merge_branch_name;
+use crate::bors::handlers::trybuild::cancel_build_workflows;
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::database::{BuildStatus, DbClient, LoggedEvent};
+use crate::github::{Milestone, PullRequestNumber};
+
+/// Releases all resources held by a PR that has been closed (either merged or abandoned).
+///
+/// Its running build is cancelled, its temporary branch is deleted, its delegation is revoked and
+/// it is removed from the queue. If the PR was merged from the queue, the configured post-merge
+/// actions are performed.
+pub(super) async fn handle_pull_request_closed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: PullRequestClosed,
+) -> anyhow::Result<()> {
+    let pr = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+
+    if let Some(ref build) = pr.try_build {
+        if build.status == BuildStatus::Pending {
+            tracing::info!("Cancelling try build of a closed PR");
+            if let Err(error) = cancel_build_workflows(repo, db, build).await {
+                tracing::error!(
+                    "Could not cancel workflows for SHA {}: {error:?}",
+                    build.commit_sha
+                );
+            }
+            db.update_build_status(build, BuildStatus::Cancelled)
+                .await?;
+        }
+    }
+
+    let merge_branch = try_merge_branch_name(payload.pr_number);
+    if let Err(error) = repo.client.delete_branch(&merge_branch).await {
+        tracing::warn!("Could not delete branch {merge_branch}: {error:?}");
+    }
+
+    if payload.merged {
+        db.record_event(
+            repo.client.repository(),
+            Some(payload.pr_number),
+            LoggedEvent::Merged,
+        )
+        .await?;
+    }
+
+    if pr.delegated_to.is_some() {
+        tracing::info!("Revoking delegation of closed PR");
+        db.set_delegate(&pr, None).await?;
+    }
+
+    // Approved PRs have been landed by bors, the other ones have been merged outside of it
+    let landed = pr.is_approved();
+    if landed {
+        tracing::info!("Removing closed PR from the queue");
+        db.unapprove(&pr).await?;
+    }
+
+    if payload.merged && (landed || repo.config.post_merge.all_merged_prs) {
+        handle_post_merge(repo, payload.pr_number, &payload.base_branch).await?;
+    }
+    Ok(())
+}
+
+/// Assigns a merged PR to the current milestone and adds labels to it, based on the
+/// configuration of the repository.
+async fn handle_post_merge<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: PullRequestNumber,
+    base_branch: &str,
+) -> anyhow::Result<()> {
+    let config = repo.config.post_merge.clone();
+
+    if config.assign_milestone {
+        let milestones = repo.client.get_open_milestones().await?;
+        match current_milestone(milestones) {
+            Some(milestone) => {
+                tracing::info!("Assigning PR to milestone {}", milestone.title);
+                repo.client.set_milestone(pr, milestone.number).await?;
+            }
+            None => tracing::warn!("Cannot assign PR to a milestone, there is no open milestone"),
+        }
+    }
+
+    let labels = config
+        .labels
+        .into_iter()
+        .chain(
+            config
+                .branch_labels
+                .get(base_branch)
+                .cloned()
+                .unwrap_or_default(),
+        )
+        .collect::<Vec<_>>();
+    if !labels.is_empty() {
+        tracing::info!("Adding post-merge label(s) {labels:?}");
+        repo.client.add_labels(pr, &labels).await?;
+    }
+    Ok(())
+}
+
+/// The current milestone is the open milestone with the nearest due date.
+/// If no milestone has a due date, the most recently created one is used.
+fn current_milestone(milestones: Vec<Milestone>) -> Option<Milestone> {
+    let (with_due_date, without_due_date): (Vec<_>, Vec<_>) = milestones
+        .into_iter()
+        .partition(|milestone| milestone.due_on.is_some());
+    with_due_date
+        .into_iter()
+        .min_by_key(|milestone| milestone.due_on)
+        .or_else(|| {
+            without_due_date
+                .into_iter()
+                .max_by_key(|milestone| milestone.number)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::bors::handlers::branches::{try_merge_branch_name, TRY_BRANCH_NAME};
+    use crate::config::PostMergeConfig;
+    use crate::database::{BuildStatus, DbClient, LoggedEvent};
+    use crate::github::Milestone;
+    use crate::tests::event::{default_pr_number, WorkflowStartedBuilder};
+    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn test_closed_cancel_try_build() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state
+            .workflow_started(
+                WorkflowStartedBuilder::default()
+                    .branch(TRY_BRANCH_NAME.to_string())
+                    .run_id(123),
+            )
+            .await;
+        state.pull_request_closed(default_pr_number()).await;
+
+        state.client().check_cancelled_workflows(&[123]);
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.try_build.unwrap().status, BuildStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_closed_delete_merge_branch() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.pull_request_closed(default_pr_number()).await;
+        let branch = try_merge_branch_name(default_pr_number().into());
+        state.client().check_deleted_branches(&[branch.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_closed_remove_approval() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.pull_request_closed(default_pr_number()).await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+        state.client().check_comment_count(default_pr_number(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_closed_remove_delegation() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        state.db.set_delegate(&pr, Some("Bob")).await.unwrap();
+        state.pull_request_closed(default_pr_number()).await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.delegated_to, None);
+        let events: Vec<LoggedEvent> = state
+            .db
+            .get_events(&default_repo_name(), Some(default_pr_number().into()))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(
+            events.last(),
+            Some(&LoggedEvent::DelegationChanged { delegate: None })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merged_post_merge_actions() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().post_merge(PostMergeConfig {
+                    assign_milestone: true,
+                    labels: vec!["merged-by-bors".to_string()],
+                    branch_labels: [("beta".to_string(), vec!["beta-nominated".to_string()])]
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                }),
+            )
+            .create_state()
+            .await;
+        state.client().milestones = vec![
+            milestone(1, Some(3)),
+            milestone(2, Some(1)),
+            milestone(3, None),
+        ];
+        state.comment("@bors r+").await;
+        state.pull_request_merged(default_pr_number(), "beta").await;
+
+        state.client().check_milestone(default_pr_number(), Some(2));
+        state
+            .client()
+            .check_added_labels(default_pr_number(), &["merged-by-bors", "beta-nominated"]);
+    }
+
+    #[tokio::test]
+    async fn test_merged_milestone_without_due_date() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().post_merge(PostMergeConfig {
+                assign_milestone: true,
+                ..Default::default()
+            }))
+            .create_state()
+            .await;
+        state.client().milestones = vec![milestone(1, None), milestone(2, None)];
+        state.comment("@bors r+").await;
+        state.pull_request_merged(default_pr_number(), "main").await;
+        state.client().check_milestone(default_pr_number(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_merged_outside_of_bors_no_post_merge_actions() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().post_merge(PostMergeConfig {
+                assign_milestone: true,
+                ..Default::default()
+            }))
+            .create_state()
+            .await;
+        state.client().milestones = vec![milestone(1, None)];
+        state.pull_request_merged(default_pr_number(), "main").await;
+        state.client().check_milestone(default_pr_number(), None);
+    }
+
+    #[tokio::test]
+    async fn test_merged_outside_of_bors_all_merged_prs() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().post_merge(PostMergeConfig {
+                assign_milestone: true,
+                all_merged_prs: true,
+                ..Default::default()
+            }))
+            .create_state()
+            .await;
+        state.client().milestones = vec![milestone(1, None)];
+        state.pull_request_merged(default_pr_number(), "main").await;
+        state.client().check_milestone(default_pr_number(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_no_post_merge_actions() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().post_merge(PostMergeConfig {
+                assign_milestone: true,
+                labels: vec!["merged-by-bors".to_string()],
+                ..Default::default()
+            }))
+            .create_state()
+            .await;
+        state.client().milestones = vec![milestone(1, None)];
+        state.pull_request_closed(default_pr_number()).await;
+        state.client().check_milestone(default_pr_number(), None);
+    }
+
+    fn milestone(number: u64, due_in_days: Option<u32>) -> Milestone {
+        Milestone {
+            number,
+            title: format!("milestone-{number}"),
+            due_on: due_in_days.map(|days| Utc.with_ymd_and_hms(2023, 7, days, 0, 0, 0).unwrap()),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Reconciles the persisted state of a repository with GitHub when the bot starts, because
+//! webhooks that were sent while the bot was not running are lost.
+use crate::bors::event::CheckSuiteCompleted;
+use crate::bors::handlers::branches::build_branch_changed;
+use crate::bors::handlers::comments::{post_status_comment, update_summary_comment};
+use crate::bors::handlers::merge_queue::enqueue_pull_request;
+use crate::bors::handlers::review::{blocking_labels, revoke_outdated_approval};
+use crate::bors::handlers::workflow::try_complete_build;
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::LandingMode;
+use crate::database::{BuildModel, BuildStatus, DbClient};
+use crate::github::{CommitSha, PullRequestNumber};
+use crate::messages::MessageKind;
+
+pub(super) async fn reconcile_repository<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+) -> anyhow::Result<()> {
+    let running_builds = db.get_running_builds(&repo.repository).await?;
+    tracing::info!("Reconciling {} running build(s)", running_builds.len());
+    for build in running_builds {
+        if let Err(error) = reconcile_build(repo, db, build).await {
+            tracing::error!("Could not reconcile build: {error:?}");
+        }
+    }
+
+    if repo.config.landing == LandingMode::GithubMergeQueue {
+        let approved = db.get_approved_pull_requests(&repo.repository).await?;
+        tracing::info!("Re-enqueuing {} approved PR(s)", approved.len());
+        for pr in approved {
+            if let Err(error) = requeue_pull_request(repo, db, ctx, pr.number).await {
+                tracing::error!("Could not re-enqueue PR {}: {error:?}", pr.number);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A build whose branch has been deleted or moved to a commit that is not tested by a newer build
+/// cannot finish anymore, so it is marked as cancelled. Other builds are adopted again, including
+/// try builds that were superseded on the shared try branch by a try build of another PR; if
+/// their workflows have finished in the meantime, the build is completed.
+async fn reconcile_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    build: BuildModel,
+) -> anyhow::Result<()> {
+    let pr = db.find_pr_by_build(&build).await?;
+    if !build_branch_changed(repo, db, &build, pr.as_ref().map(|pr| pr.number)).await? {
+        tracing::info!("Adopting running build {}", build.commit_sha);
+        // The workflows of the build might not have been created yet
+        if db.get_workflows_for_build(&build).await?.is_empty() {
+            return Ok(());
+        }
+        let event = CheckSuiteCompleted {
+            repository: repo.repository.clone(),
+            branch: build.branch,
+            commit_sha: CommitSha(build.commit_sha),
+        };
+        return try_complete_build(repo, db, event).await;
+    }
+
+    tracing::info!("Build {} was interrupted", build.commit_sha);
+    db.update_build_status(&build, BuildStatus::Cancelled)
+        .await?;
+    let Some(pr) = pr else {
+        tracing::warn!("No PR found for build {}", build.commit_sha);
+        return Ok(());
+    };
+    let message = repo.config.messages.render(
+        MessageKind::BuildInterrupted,
+        &repo.repository,
+        pr.number,
+        &[("sha", &build.commit_sha)],
+    );
+    post_status_comment(repo, db, pr.number, &message).await?;
+    update_summary_comment(repo, db, pr.number).await
+}
+
+/// Adds an approved PR to the merge queue of GitHub again, unless it is held out of the queue.
+/// An approval pinned to a commit that is no longer the head of the PR is removed instead.
+pub(super) async fn requeue_pull_request<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+    pr_number: PullRequestNumber,
+) -> anyhow::Result<()> {
+    let pr = repo.client.get_pull_request(pr_number).await?;
+    if revoke_outdated_approval(repo, db, pr_number, &pr.head.sha).await? {
+        return Ok(());
+    }
+    if pr.draft || !blocking_labels(repo, &pr.labels).is_empty() {
+        return Ok(());
+    }
+    enqueue_pull_request(repo, db, ctx, pr_number).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
+    use crate::bors::RepositoryClient;
+    use crate::config::LandingMode;
+    use crate::database::{DbClient, WorkflowStatus};
+    use crate::github::CommitSha;
+    use crate::tests::event::{
+        comment, default_pr_number, suite_pending, suite_success, WorkflowCompletedBuilder,
+        WorkflowStartedBuilder,
+    };
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder,
+    };
+
+    #[tokio::test]
+    async fn reconcile_keep_running_build() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.reconcile().await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        assert_eq!(
+            state
+                .db
+                .get_running_builds(&default_repo_name())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_keep_overlapping_try_builds() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.client().merge_branches_fn = Box::new(|| Ok(CommitSha("sha-merged-2".to_string())));
+        state.comment(comment("@bors try").pr_number(2)).await;
+        state.reconcile().await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        state.client().check_comment_count(2, 1);
+        assert_eq!(
+            state
+                .db
+                .get_running_builds(&default_repo_name())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_interrupted_build() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.client().delete_branch(TRY_BRANCH_NAME).await.unwrap();
+        state.reconcile().await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":warning: The build of commit sha-merged was interrupted while bors was not running. Please start it again."
+        );
+        assert!(state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn reconcile_complete_finished_build() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_pending()]);
+        state.comment("@bors try").await;
+        state
+            .workflow_started(WorkflowStartedBuilder::default().branch(TRY_BRANCH_NAME.to_string()))
+            .await;
+        state
+            .workflow_completed(
+                WorkflowCompletedBuilder::default()
+                    .branch(TRY_BRANCH_NAME.to_string())
+                    .status(WorkflowStatus::Success),
+            )
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+
+        // The check suite has finished while the bot was not running
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+        state.reconcile().await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :sunny: Try build successful
+        - [workflow-name](https://workflow-name-1) :white_check_mark:
+        Build commit: sha-merged (`sha-merged`)
+        "###);
+    }
+
+    #[tokio::test]
+    async fn reconcile_requeue_approved_pr() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().landing(LandingMode::GithubMergeQueue))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().merge_queue.clear();
+        state.reconcile().await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+    }
+}
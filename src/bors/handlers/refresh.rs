@@ -2,21 +2,46 @@ use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 
-use crate::bors::handlers::trybuild::cancel_build_workflows;
-use crate::bors::{RepositoryClient, RepositoryState};
+use crate::bors::handlers::command_prefix;
+use crate::bors::handlers::comments::{
+    flush_deferred_comments, post_status_comment, update_summary_comment,
+};
+use crate::bors::handlers::digest::post_digest_if_due;
+use crate::bors::handlers::permissions::handle_permission_changes;
+use crate::bors::handlers::regression::report_duration_regressions;
+use crate::bors::handlers::stale::remind_stale_pull_requests;
+use crate::bors::handlers::trybuild::{cancel_build_workflows, retry_try_build};
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::Feature;
 use crate::database::{BuildStatus, DbClient};
+use crate::messages::MessageKind;
+use crate::notifications::{notify, Notification, NotificationEvent};
 
 pub async fn refresh_repository<Client: RepositoryClient>(
     repo: &mut RepositoryState<Client>,
     db: &dyn DbClient,
+    ctx: &BorsContext,
 ) -> anyhow::Result<()> {
     let timeout = repo.config.timeout;
 
+    // Postponed updates of summary comments are posted at the latest during the refresh
+    flush_deferred_comments(repo, db).await;
+
+    if let Some(config) = &repo.config.log_excerpts {
+        let expiration = now(ctx) - chrono::Duration::days(config.retention_days as i64);
+        let deleted = db
+            .delete_job_logs_before(&repo.repository, expiration)
+            .await?;
+        if deleted > 0 {
+            tracing::info!("Deleted {deleted} expired log excerpt(s)");
+        }
+    }
+
     let running_builds = db.get_running_builds(&repo.repository).await?;
     tracing::info!("Found {} running build(s)", running_builds.len());
 
     for build in running_builds {
-        if elapsed_time(build.created_at) >= timeout {
+        if elapsed_time(ctx, build.created_at) >= timeout {
             tracing::info!("Cancelling build {}", build.commit_sha);
 
             db.update_build_status(&build, BuildStatus::Cancelled)
@@ -29,24 +54,90 @@ pub async fn refresh_repository<Client: RepositoryClient>(
                     );
                 }
 
-                if let Err(error) = repo
-                    .client
-                    .post_comment(pr.number, ":boom: Test timed out")
-                    .await
-                {
+                notify(
+                    repo,
+                    Notification {
+                        event: NotificationEvent::BuildTimedOut,
+                        repository: repo.repository.clone(),
+                        pr: Some(pr.number),
+                        message: format!(
+                            "Try build `{}` was cancelled after running for more than {} minute(s)",
+                            build.commit_sha,
+                            timeout.as_secs() / 60
+                        ),
+                    },
+                )
+                .await;
+
+                let message = repo.config.messages.render(
+                    MessageKind::TimedOut,
+                    &repo.repository,
+                    pr.number,
+                    &[],
+                );
+                if let Err(error) = post_status_comment(repo, db, pr.number, &message).await {
                     tracing::error!("Could not send comment to PR {}: {error:?}", pr.number);
                 }
+                if let Err(error) = update_summary_comment(repo, db, pr.number).await {
+                    tracing::error!(
+                        "Could not update summary comment of PR {}: {error:?}",
+                        pr.number
+                    );
+                }
             } else {
                 tracing::warn!("No PR found for build {}", build.commit_sha);
             }
         }
     }
 
+    if let Err(error) = handle_permission_changes(repo, db).await {
+        tracing::error!("Could not handle changes of permissions: {error:?}");
+    }
+    if let Err(error) = post_digest_if_due(repo, db, now(ctx)).await {
+        tracing::error!("Could not post digest: {error:?}");
+    }
+    let prefix = command_prefix(repo, ctx).to_string();
+    if let Err(error) = remind_stale_pull_requests(repo, db, &prefix, now(ctx)).await {
+        tracing::error!("Could not remind stale PRs: {error:?}");
+    }
+    if let Err(error) = report_duration_regressions(repo, db, now(ctx)).await {
+        tracing::error!("Could not report duration regressions: {error:?}");
+    }
+
+    if !repo.config.features.is_enabled(Feature::AutoRetry) {
+        return Ok(());
+    }
+    // Builds due for a retry are retried once the repository is resumed
+    if db.get_pause(&repo.repository).await?.is_some() {
+        return Ok(());
+    }
+    for build in db.get_builds_to_retry(&repo.repository, now(ctx)).await? {
+        if let Err(error) = retry_try_build(repo, db, ctx, build).await {
+            tracing::error!("Could not retry build: {error:?}");
+        }
+    }
+
     Ok(())
 }
 
+/// Deletes webhooks received before the retention period of the delivery log.
+/// The delivery log is shared by all repositories, so it is pruned once per refresh.
+pub async fn prune_webhook_deliveries(db: &dyn DbClient, ctx: &BorsContext) {
+    let expiration = now(ctx) - chrono::Duration::days(ctx.webhook_delivery_retention_days as i64);
+    match db.delete_webhook_deliveries_before(expiration).await {
+        Ok(0) => {}
+        Ok(deleted) => tracing::info!("Deleted {deleted} expired webhook deliveries"),
+        Err(error) => tracing::error!("Could not delete expired webhook deliveries: {error:?}"),
+    }
+}
+
+/// The current time, shifted by the clock skew injected by tests.
+fn now(ctx: &BorsContext) -> DateTime<Utc> {
+    current_time() + ctx.faults.clock_skew()
+}
+
 #[cfg(not(test))]
-fn now() -> DateTime<Utc> {
+fn current_time() -> DateTime<Utc> {
     Utc::now()
 }
 
@@ -56,12 +147,12 @@ thread_local! {
 }
 
 #[cfg(test)]
-fn now() -> DateTime<Utc> {
+fn current_time() -> DateTime<Utc> {
     MOCK_TIME.with(|time| time.borrow_mut().unwrap_or_else(Utc::now))
 }
 
-fn elapsed_time(date: DateTime<Utc>) -> Duration {
-    let time: DateTime<Utc> = now();
+fn elapsed_time(ctx: &BorsContext, date: DateTime<Utc>) -> Duration {
+    let time: DateTime<Utc> = now(ctx);
     (time - date).to_std().unwrap_or(Duration::ZERO)
 }
 
@@ -71,13 +162,21 @@ mod tests {
     use std::time::Duration;
 
     use chrono::Utc;
+    use octocrab::models::RunId;
     use tokio::runtime::RuntimeFlavor;
 
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
     use crate::bors::handlers::refresh::MOCK_TIME;
-    use crate::bors::handlers::trybuild::TRY_BRANCH_NAME;
-    use crate::database::DbClient;
-    use crate::tests::event::{default_pr_number, WorkflowStartedBuilder};
-    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+    use crate::config::{LogExcerptsConfig, RetryPolicy, TryTrigger};
+    use crate::database::{BuildStatus, DbClient, WorkflowStatus};
+    use crate::github::PullRequestNumber;
+    use crate::tests::event::{
+        default_pr_number, suite_failure, WorkflowCompletedBuilder, WorkflowStartedBuilder,
+    };
+    use crate::tests::github::{BranchBuilder, PRBuilder};
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder,
+    };
 
     #[tokio::test(flavor = "current_thread")]
     async fn refresh_no_builds() {
@@ -85,6 +184,35 @@ mod tests {
         state.refresh().await;
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_poll_external_build() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().try_trigger(TryTrigger::Buildkite {
+                    pipeline: "acme/app".to_string(),
+                }),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        state.refresh().await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":hourglass: Trying commit pr-sha with merge sha-merged…"],
+        );
+
+        state.ci.results.lock().unwrap().insert(
+            "https://buildkite.com/acme/app/builds/2001".to_string(),
+            WorkflowStatus::Success,
+        );
+        state.refresh().await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :sunny: Try build successful
+        - [buildkite](https://buildkite.com/acme/app/builds/2001) :white_check_mark:
+        Build commit: sha-merged (`sha-merged`)
+        "###);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn refresh_do_nothing_before_timeout() {
         let mut state = ClientBuilder::default()
@@ -137,6 +265,208 @@ mod tests {
         .await;
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_delete_expired_log_excerpts() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().log_excerpts(Some(LogExcerptsConfig {
+                    max_size: 1024,
+                    retention_days: 30,
+                    ..Default::default()
+                })),
+            )
+            .create_state()
+            .await;
+        state
+            .db
+            .store_job_log(&default_repo_name(), RunId(1), "test", "error")
+            .await
+            .unwrap();
+
+        with_mocked_time(Duration::from_secs(10 * 24 * 3600), async {
+            state.refresh().await;
+            assert_eq!(state.db.get_job_logs(RunId(1)).await.unwrap().len(), 1);
+        })
+        .await;
+        with_mocked_time(Duration::from_secs(31 * 24 * 3600), async {
+            state.refresh().await;
+            assert!(state.db.get_job_logs(RunId(1)).await.unwrap().is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_delete_expired_webhook_deliveries() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .db
+            .record_webhook_delivery("delivery-1", "issue_comment", "hash", "{}", "accepted")
+            .await
+            .unwrap();
+
+        with_mocked_time(Duration::from_secs(10 * 24 * 3600), async {
+            state.refresh().await;
+            assert!(state
+                .db
+                .find_webhook_delivery("delivery-1")
+                .await
+                .unwrap()
+                .is_some());
+        })
+        .await;
+        with_mocked_time(Duration::from_secs(15 * 24 * 3600), async {
+            state.refresh().await;
+            assert!(state
+                .db
+                .find_webhook_delivery("delivery-1")
+                .await
+                .unwrap()
+                .is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_retry_failed_build() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().retry(RetryPolicy {
+                max_retries: 1,
+                backoff: Duration::from_secs(60),
+                backoff_multiplier: 2,
+            }))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :broken_heart: Test failed
+        - [workflow-1](https://workflow-1.com) :x:
+        :repeat: The build will be retried automatically in 1 minute(s) (retry 1 of 1).
+        "###);
+
+        with_mocked_time(Duration::from_secs(30), async {
+            state.refresh().await;
+            assert!(state
+                .db
+                .get_running_builds(&default_repo_name())
+                .await
+                .unwrap()
+                .is_empty());
+        })
+        .await;
+        with_mocked_time(Duration::from_secs(120), async {
+            state.refresh().await;
+            let pr = state
+                .db
+                .get_or_create_pull_request(
+                    &default_repo_name(),
+                    PullRequestNumber(default_pr_number()),
+                )
+                .await
+                .unwrap();
+            let build = pr.try_build.unwrap();
+            assert_eq!(build.status, BuildStatus::Pending);
+            assert_eq!(build.attempt, 1);
+        })
+        .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_retry_with_build_arguments() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .try_trigger(TryTrigger::WorkflowDispatch {
+                        workflow: "try.yml".to_string(),
+                    })
+                    .retry(retry_once()),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors try jobs=linux").await;
+        state
+            .workflow_completed(
+                WorkflowCompletedBuilder::default()
+                    .branch("main-branch".to_string())
+                    .commit_sha("main-sha".to_string())
+                    .run_id(1001)
+                    .status(WorkflowStatus::Failure),
+            )
+            .await;
+
+        with_mocked_time(Duration::from_secs(120), async {
+            state.refresh().await;
+            let dispatched = &state.client().dispatched_workflows;
+            assert_eq!(dispatched.len(), 2);
+            assert_eq!(dispatched[1].2.get("jobs"), Some(&"linux".to_string()));
+        })
+        .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn refresh_do_not_retry_updated_pr() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().retry(retry_once()))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .head(
+                    BranchBuilder::default()
+                        .sha("new-head".to_string())
+                        .create(),
+                )
+                .create())
+        });
+
+        with_mocked_time(Duration::from_secs(120), async {
+            state.refresh().await;
+            let pr = state
+                .db
+                .get_or_create_pull_request(
+                    &default_repo_name(),
+                    PullRequestNumber(default_pr_number()),
+                )
+                .await
+                .unwrap();
+            let build = pr.try_build.unwrap();
+            assert_eq!(build.status, BuildStatus::Failure);
+            assert_eq!(build.attempt, 0);
+        })
+        .await;
+    }
+
+    fn retry_once() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 1,
+            backoff: Duration::from_secs(60),
+            backoff_multiplier: 2,
+        }
+    }
+
     async fn with_mocked_time<Fut: Future<Output = ()>>(in_future: Duration, future: Fut) {
         // It is important to use this function only with a single threaded runtime,
         // otherwise the `MOCK_TIME` variable might get mixed up between different threads.
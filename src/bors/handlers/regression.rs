@@ -0,0 +1,51 @@
+//! Notifications about workflows that have become significantly slower, e.g. because of a change
+//! in a recently merged PR.
+use chrono::{DateTime, Duration, Utc};
+
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::database::{DbClient, LoggedEvent};
+use crate::notifications::{notify, Notification, NotificationEvent};
+use crate::stats::duration_regressions;
+
+/// Sends a notification about each workflow whose median duration has increased significantly in
+/// the week before `now`. Each workflow is reported at most once a week. It is checked during the
+/// periodic refresh of the repository.
+pub(super) async fn report_duration_regressions<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let regressions = duration_regressions(db, &repo.repository, now).await?;
+    if regressions.is_empty() {
+        return Ok(());
+    }
+
+    let events = db
+        .get_events_of_kinds(&repo.repository, None, &["duration_regression_reported"])
+        .await?;
+    for regression in regressions {
+        let reported = LoggedEvent::DurationRegressionReported {
+            workflow: regression.workflow.clone(),
+        };
+        if events
+            .iter()
+            .any(|event| event.event == reported && event.created_at >= now - Duration::days(7))
+        {
+            continue;
+        }
+
+        tracing::info!("Reporting slowdown of workflow {}", regression.workflow);
+        notify(
+            repo,
+            Notification {
+                event: NotificationEvent::DurationRegression,
+                repository: repo.repository.clone(),
+                pr: None,
+                message: regression.to_string(),
+            },
+        )
+        .await;
+        db.record_event(&repo.repository, None, reported).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,1591 @@
+use anyhow::Context;
+
+use crate::bors::command::Approver;
+use crate::bors::event::{
+    PullRequestChanged, PullRequestComment, PullRequestLabelChanged, ReviewDismissed,
+};
+use crate::bors::handlers::comments::update_summary_comment;
+use crate::bors::handlers::handle_comment;
+use crate::bors::handlers::merge_queue::{dequeue_pull_request, enqueue_pull_request};
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::Feature;
+use crate::database::{DbClient, RollupMode};
+use crate::github::{Commit, CommitSha, GithubUser, PullRequest, PullRequestNumber};
+use crate::messages::MessageKind;
+use crate::permissions::PermissionType;
+
+/// Approves the PR, which queues it for being merged.
+///
+/// Draft PRs can only be approved if the repository allows it. All labels required by the
+/// repository have to be present on the PR, the PR has to satisfy the size limits of the
+/// repository (unless `ignore_limits` is set) and its author has to have signed the contributor
+/// agreement of the repository, if there is one. If the repository requires it, all commits have
+/// to be signed off by their authors and have a verified signature. A PR carrying a blocking label
+/// cannot be approved.
+///
+/// If the approved commit is given (`r+ <sha>`), it has to be the head of the PR and the approval
+/// is pinned to it, so that it is removed if the head changes.
+///
+/// A PR that is already approved by the same approver (and pinned to the same commit) is left
+/// as it is, the bot only replies that it is already approved.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn command_approve<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+    pr: &PullRequest,
+    author: &GithubUser,
+    approver: Approver,
+    ignore_limits: bool,
+    sha: Option<String>,
+) -> anyhow::Result<()> {
+    if let Some(sha) = &sha {
+        if !pr.head.sha.0.starts_with(sha.as_str()) {
+            tracing::info!(
+                "Refusing to approve commit {sha}, the head is {}",
+                pr.head.sha
+            );
+            repo.client
+                .post_comment(
+                    pr.number,
+                    &repo.config.messages.render(
+                        MessageKind::ApproveNotHead,
+                        &repo.repository,
+                        pr.number,
+                        &[("sha", sha), ("head_sha", &pr.head.sha.to_string())],
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let approver = match approver {
+        Approver::Myself => author.username.clone(),
+        Approver::Specified(approver) => approver,
+    };
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr.number)
+        .await?;
+    let approved_sha = sha.as_ref().map(|_| &pr.head.sha);
+    if pr_model.approved_by.as_deref() == Some(approver.as_str())
+        && pr_model.approved_sha.as_ref() == approved_sha
+    {
+        tracing::info!("PR is already approved by {approver}");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::AlreadyApproved,
+                    &repo.repository,
+                    pr.number,
+                    &[("approver", &approver)],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if pr.draft && !repo.config.drafts.allows_approval() {
+        tracing::info!("Refusing to approve a draft PR");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::ApproveDraft,
+                    &repo.repository,
+                    pr.number,
+                    &[],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let missing_labels = repo
+        .config
+        .required_labels
+        .iter()
+        .filter(|label| !pr.labels.contains(label))
+        .map(|label| format!("`{label}`"))
+        .collect::<Vec<_>>();
+    if !missing_labels.is_empty() {
+        tracing::info!("Refusing to approve a PR with missing labels {missing_labels:?}");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::MissingRequiredLabels,
+                    &repo.repository,
+                    pr.number,
+                    &[("labels", &missing_labels.join(", "))],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let blocking_labels = blocking_labels(repo, &pr.labels);
+    if !blocking_labels.is_empty() {
+        tracing::info!("Refusing to approve a PR with blocking labels {blocking_labels:?}");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::ApproveBlocked,
+                    &repo.repository,
+                    pr.number,
+                    &[("labels", &blocking_labels.join(", "))],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if ignore_limits {
+        tracing::info!("Ignoring PR limits");
+    } else if !check_limits(repo, pr).await? {
+        return Ok(());
+    }
+
+    if !check_agreement(repo, pr).await? {
+        return Ok(());
+    }
+
+    if (repo.config.require_sign_off || repo.config.require_verified_commits)
+        && !check_commits(repo, pr).await?
+    {
+        return Ok(());
+    }
+
+    db.approve(&pr_model, &approver, approved_sha).await?;
+    tracing::info!("PR approved by {approver}");
+
+    let message = repo.config.messages.render(
+        MessageKind::Approved,
+        &repo.repository,
+        pr.number,
+        &[("sha", &pr.head.sha.to_string()), ("approver", &approver)],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+
+    enqueue_pull_request(repo, db, ctx, pr.number).await?;
+    Ok(())
+}
+
+/// Removes the approval of the PR, which also removes it from the queue.
+pub(super) async fn command_unapprove<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+) -> anyhow::Result<()> {
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr.number)
+        .await?;
+    db.unapprove(&pr_model).await?;
+    tracing::info!("PR unapproved");
+
+    repo.client
+        .post_comment(
+            pr.number,
+            &repo.config.messages.render(
+                MessageKind::Unapproved,
+                &repo.repository,
+                pr.number,
+                &[("sha", &pr.head.sha.to_string())],
+            ),
+        )
+        .await?;
+    dequeue_pull_request(repo, pr.number).await
+}
+
+/// Removes the approval of a PR that was pinned to a commit (`r+ <sha>`) which is no longer its
+/// head, so that changes that were not reviewed are never merged. Returns whether the approval
+/// was removed.
+pub(super) async fn revoke_outdated_approval<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    pr_number: PullRequestNumber,
+    head_sha: &CommitSha,
+) -> anyhow::Result<bool> {
+    let Some(pr_model) = db.find_pull_request(&repo.repository, pr_number).await? else {
+        return Ok(false);
+    };
+    let Some(approved_sha) = pr_model.approved_sha.clone() else {
+        return Ok(false);
+    };
+    if !pr_model.is_approved() || &approved_sha == head_sha {
+        return Ok(false);
+    }
+
+    db.unapprove(&pr_model).await?;
+    tracing::info!("Head changed from approved commit {approved_sha} to {head_sha}");
+    dequeue_pull_request(repo, pr_number).await?;
+
+    repo.client
+        .post_comment(
+            pr_number,
+            &repo.config.messages.render(
+                MessageKind::ApprovalOutdated,
+                &repo.repository,
+                pr_number,
+                &[
+                    ("approved_sha", &approved_sha.to_string()),
+                    ("head_sha", &head_sha.to_string()),
+                ],
+            ),
+        )
+        .await?;
+    update_summary_comment(repo, db, pr_number).await?;
+    Ok(true)
+}
+
+/// Sets the priority of the PR, PRs with a higher priority are merged first.
+///
+/// The change is shown in the summary comment, or acknowledged by a comment if the repository
+/// does not use summary comments.
+pub(super) async fn command_set_priority<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+    priority: u32,
+) -> anyhow::Result<()> {
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr.number)
+        .await?;
+    db.set_priority(&pr_model, priority).await?;
+    tracing::info!("Priority set to {priority}");
+
+    if repo.config.summary_comment {
+        update_summary_comment(repo, db, pr.number).await
+    } else {
+        let reply = repo.config.messages.render(
+            MessageKind::PrioritySet,
+            &repo.repository,
+            pr.number,
+            &[("priority", &priority.to_string())],
+        );
+        repo.client.post_comment(pr.number, &reply).await?;
+        Ok(())
+    }
+}
+
+/// Sets the rollup mode of the PR, or removes it (e.g. one imported from homu) if `mode` is
+/// `None`, so that the PR is no longer considered for rollups. The approval of the PR is kept.
+///
+/// The change is shown in the summary comment, or acknowledged by a comment if the repository
+/// does not use summary comments.
+pub(super) async fn command_set_rollup<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+    mode: Option<RollupMode>,
+) -> anyhow::Result<()> {
+    if !repo.config.features.is_enabled(Feature::Rollups) {
+        return report_feature_disabled(repo, pr.number, Feature::Rollups).await;
+    }
+
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr.number)
+        .await?;
+    let reply = match mode {
+        None if pr_model.rollup.is_none() => {
+            let reply = repo.config.messages.render(
+                MessageKind::NotMarkedForRollup,
+                &repo.repository,
+                pr.number,
+                &[],
+            );
+            repo.client.post_comment(pr.number, &reply).await?;
+            return Ok(());
+        }
+        None => repo.config.messages.render(
+            MessageKind::RollupCleared,
+            &repo.repository,
+            pr.number,
+            &[],
+        ),
+        Some(mode) => repo.config.messages.render(
+            MessageKind::RollupSet,
+            &repo.repository,
+            pr.number,
+            &[("mode", mode.name())],
+        ),
+    };
+    db.set_rollup(&pr_model, mode).await?;
+    tracing::info!("Rollup mode set to {mode:?}");
+
+    if repo.config.summary_comment {
+        update_summary_comment(repo, db, pr.number).await
+    } else {
+        repo.client.post_comment(pr.number, &reply).await?;
+        Ok(())
+    }
+}
+
+/// Tells the author of a command that it cannot be used, because its feature is disabled.
+pub(super) async fn report_feature_disabled<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: PullRequestNumber,
+    feature: Feature,
+) -> anyhow::Result<()> {
+    tracing::info!("Feature {} is disabled", feature.name());
+    repo.client
+        .post_comment(
+            pr,
+            &repo.config.messages.render(
+                MessageKind::FeatureDisabled,
+                &repo.repository,
+                pr,
+                &[("feature", feature.name())],
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes a PR that was converted to a draft from the queue, unless the repository allows
+/// approving drafts.
+pub(super) async fn handle_converted_to_draft<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: PullRequestChanged,
+) -> anyhow::Result<()> {
+    if repo.config.drafts.allows_approval() {
+        return Ok(());
+    }
+
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+    if !pr_model.is_approved() {
+        return Ok(());
+    }
+
+    db.unapprove(&pr_model).await?;
+    tracing::info!("Draft PR removed from the queue");
+    dequeue_pull_request(repo, payload.pr_number).await?;
+
+    repo.client
+        .post_comment(
+            payload.pr_number,
+            &repo.config.messages.render(
+                MessageKind::ConvertedToDraft,
+                &repo.repository,
+                payload.pr_number,
+                &[],
+            ),
+        )
+        .await?;
+    update_summary_comment(repo, db, payload.pr_number).await
+}
+
+/// Informs an approved PR that it is held out of the queue because a blocking label was added to it.
+pub(super) async fn handle_pull_request_labeled<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: PullRequestLabelChanged,
+) -> anyhow::Result<()> {
+    if !repo.config.blocking_labels.contains(&payload.label) {
+        return Ok(());
+    }
+
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+    if !pr_model.is_approved() {
+        return Ok(());
+    }
+
+    tracing::info!("Approved PR held by label {}", payload.label);
+    dequeue_pull_request(repo, payload.pr_number).await?;
+    repo.client
+        .post_comment(
+            payload.pr_number,
+            &repo.config.messages.render(
+                MessageKind::HeldByLabel,
+                &repo.repository,
+                payload.pr_number,
+                &[("label", &payload.label)],
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns an approved PR back to the queue once the last blocking label was removed from it.
+pub(super) async fn handle_pull_request_unlabeled<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+    payload: PullRequestLabelChanged,
+) -> anyhow::Result<()> {
+    if !repo.config.blocking_labels.contains(&payload.label) {
+        return Ok(());
+    }
+
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+    if !pr_model.is_approved() {
+        return Ok(());
+    }
+
+    let pr = repo.client.get_pull_request(payload.pr_number).await?;
+    if !blocking_labels(repo, &pr.labels).is_empty() {
+        tracing::debug!("PR is still held by other blocking labels");
+        return Ok(());
+    }
+
+    tracing::info!("Approved PR released back to the queue");
+    enqueue_pull_request(repo, db, ctx, payload.pr_number).await?;
+    repo.client
+        .post_comment(
+            payload.pr_number,
+            &repo.config.messages.render(
+                MessageKind::BlockingLabelsRemoved,
+                &repo.repository,
+                payload.pr_number,
+                &[],
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the (formatted) blocking labels of the repository that are present in `labels`.
+pub(super) fn blocking_labels<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    labels: &[String],
+) -> Vec<String> {
+    repo.config
+        .blocking_labels
+        .iter()
+        .filter(|label| labels.contains(label))
+        .map(|label| format!("`{label}`"))
+        .collect()
+}
+
+/// Handles an approving GitHub review. Commands in the text of the review are executed like in a
+/// comment and, if the repository opted in, the PR is approved by the reviewer.
+pub(super) async fn handle_review_approved<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+    review: PullRequestComment,
+) -> anyhow::Result<()> {
+    let reviewer = review.author.clone();
+    let pr_number = review.pr_number;
+    handle_comment(repo, db, ctx, review).await?;
+
+    if !repo.config.review_approvals {
+        return Ok(());
+    }
+    // Reviews from users that cannot approve PRs are normal reviews, do not complain about them
+    if !repo
+        .permissions_resolver
+        .has_permission(&reviewer.username, PermissionType::Review)
+        .await
+    {
+        return Ok(());
+    }
+
+    // The review could have approved the PR using a command (`@bors r+`)
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), pr_number)
+        .await?;
+    if pr_model.approved_by.as_deref() == Some(reviewer.username.as_str()) {
+        return Ok(());
+    }
+
+    let pr = repo.client.get_pull_request(pr_number).await?;
+    command_approve(repo, db, ctx, &pr, &reviewer, Approver::Myself, false, None).await?;
+    update_summary_comment(repo, db, pr_number).await
+}
+
+/// Revokes the approval of a PR removed from the queue by an administrator (e.g. because its
+/// merge keeps breaking the queue), without checking any permissions on GitHub.
+pub(super) async fn handle_force_dequeue<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: PullRequestChanged,
+) -> anyhow::Result<()> {
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+    if pr_model.approved_by.is_none() {
+        tracing::info!("PR is not in the queue");
+        return Ok(());
+    }
+
+    db.unapprove(&pr_model).await?;
+    tracing::info!("PR removed from the queue by an administrator");
+    dequeue_pull_request(repo, payload.pr_number).await?;
+
+    repo.client
+        .post_comment(
+            payload.pr_number,
+            &repo.config.messages.render(
+                MessageKind::ForceDequeued,
+                &repo.repository,
+                payload.pr_number,
+                &[],
+            ),
+        )
+        .await?;
+    update_summary_comment(repo, db, payload.pr_number).await
+}
+
+/// Revokes the approval of a PR if it was given by the author of the dismissed review.
+pub(super) async fn handle_review_dismissed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: ReviewDismissed,
+) -> anyhow::Result<()> {
+    if !repo.config.review_approvals {
+        return Ok(());
+    }
+
+    let pr_model = db
+        .get_or_create_pull_request(repo.client.repository(), payload.pr_number)
+        .await?;
+    if pr_model.approved_by.as_deref() != Some(payload.reviewer.username.as_str()) {
+        return Ok(());
+    }
+
+    db.unapprove(&pr_model).await?;
+    tracing::info!("Approval revoked by a dismissed review");
+    dequeue_pull_request(repo, payload.pr_number).await?;
+
+    repo.client
+        .post_comment(
+            payload.pr_number,
+            &repo.config.messages.render(
+                MessageKind::ReviewDismissed,
+                &repo.repository,
+                payload.pr_number,
+                &[("reviewer", &payload.reviewer.username)],
+            ),
+        )
+        .await?;
+    update_summary_comment(repo, db, payload.pr_number).await
+}
+
+/// Checks that the PR satisfies the size limits of the repository and that it does not modify
+/// forbidden paths. If not, the violated limits are listed on the PR.
+async fn check_limits<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: &PullRequest,
+) -> anyhow::Result<bool> {
+    let limits = &repo.config.limits;
+    let messages = &repo.config.messages;
+    let mut violations = vec![];
+    if let Some(max_lines) = limits.max_changed_lines {
+        if pr.changed_lines > max_lines {
+            violations.push(messages.render(
+                MessageKind::TooManyChangedLines,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("lines", &pr.changed_lines.to_string()),
+                    ("max", &max_lines.to_string()),
+                ],
+            ));
+        }
+    }
+    if let Some(max_files) = limits.max_changed_files {
+        if pr.changed_files > max_files {
+            violations.push(messages.render(
+                MessageKind::TooManyChangedFiles,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("files", &pr.changed_files.to_string()),
+                    ("max", &max_files.to_string()),
+                ],
+            ));
+        }
+    }
+    if !limits.forbidden_paths.is_empty() {
+        let forbidden_paths = limits.forbidden_paths.clone();
+        let forbidden_files = repo
+            .client
+            .get_pull_request_files(pr.number)
+            .await?
+            .into_iter()
+            .filter(|file| forbidden_paths.iter().any(|path| file.starts_with(path)))
+            .map(|file| format!("`{file}`"))
+            .collect::<Vec<_>>();
+        if !forbidden_files.is_empty() {
+            violations.push(repo.config.messages.render(
+                MessageKind::ForbiddenFilesChanged,
+                &repo.repository,
+                pr.number,
+                &[("files", &forbidden_files.join(", "))],
+            ));
+        }
+    }
+    if violations.is_empty() {
+        return Ok(true);
+    }
+
+    tracing::info!("Refusing to approve a PR that exceeds limits: {violations:?}");
+    let message = repo.config.messages.render(
+        MessageKind::LimitsExceeded,
+        &repo.repository,
+        pr.number,
+        &[("violations", &violations.join("\n"))],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+    Ok(false)
+}
+
+/// Checks that the author of the PR has signed the contributor agreement of the repository.
+/// If not, the PR is informed about where the agreement can be signed.
+async fn check_agreement<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: &PullRequest,
+) -> anyhow::Result<bool> {
+    let (Some(checker), Some(config)) = (&repo.agreement_checker, &repo.config.agreement) else {
+        return Ok(true);
+    };
+    let signed = checker
+        .has_signed(&pr.author)
+        .await
+        .with_context(|| format!("Cannot check the agreement of {}", pr.author))?;
+    if signed {
+        return Ok(true);
+    }
+
+    tracing::info!("Author {} has not signed the agreement", pr.author);
+    let message = repo.config.messages.render(
+        MessageKind::AgreementMissing,
+        &repo.repository,
+        pr.number,
+        &[("author", &pr.author), ("sign_url", &config.sign_url)],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+    Ok(false)
+}
+
+/// Checks that all commits of the PR are signed off by their authors and that their signatures
+/// are verified, based on the configuration of the repository.
+/// If not, the offending commits are listed on the PR.
+async fn check_commits<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: &PullRequest,
+) -> anyhow::Result<bool> {
+    let commits = repo.client.get_pull_request_commits(pr.number).await?;
+
+    let mut problems = vec![];
+    if repo.config.require_sign_off {
+        let unsigned = format_commits(commits.iter().filter(|commit| !is_signed_off(commit)));
+        if !unsigned.is_empty() {
+            problems.push(repo.config.messages.render(
+                MessageKind::CommitsNotSignedOff,
+                &repo.repository,
+                pr.number,
+                &[("commits", &unsigned)],
+            ));
+        }
+    }
+    if repo.config.require_verified_commits {
+        let unverified = format_commits(commits.iter().filter(|commit| !commit.verified));
+        if !unverified.is_empty() {
+            problems.push(repo.config.messages.render(
+                MessageKind::CommitsUnverified,
+                &repo.repository,
+                pr.number,
+                &[("commits", &unverified)],
+            ));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(true);
+    }
+
+    tracing::info!("Refusing to approve a PR with invalid commits");
+    let message = repo.config.messages.render(
+        MessageKind::InvalidCommits,
+        &repo.repository,
+        pr.number,
+        &[("problems", &problems.join("\n\n"))],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+    Ok(false)
+}
+
+fn format_commits<'a>(commits: impl Iterator<Item = &'a Commit>) -> String {
+    commits
+        .map(|commit| {
+            format!(
+                "- {} {}",
+                commit.sha,
+                commit.message.lines().next().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Does the commit message contain a `Signed-off-by: Name <email>` trailer matching the author of
+/// the commit?
+fn is_signed_off(commit: &Commit) -> bool {
+    commit
+        .message
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Signed-off-by:"))
+        .filter_map(|signature| signature.trim().strip_suffix('>')?.split_once('<'))
+        .any(|(name, email)| {
+            name.trim() == commit.author_name.trim()
+                && email
+                    .trim()
+                    .eq_ignore_ascii_case(commit.author_email.trim())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::event::{BorsEvent, PullRequestPushed};
+    use crate::config::{AgreementConfig, DraftPolicy, PullRequestLimits};
+    use crate::database::{DbClient, QueueStatus, RollupMode};
+    use crate::github::{Commit, CommitSha, GithubUser, PullRequest, PullRequestNumber};
+    use crate::tests::agreement::SignedBy;
+    use crate::tests::event::{default_pr_number, default_user};
+    use crate::tests::github::{BranchBuilder, PRBuilder};
+    use crate::tests::permissions::NoPermissions;
+    use crate::tests::state::{default_repo_name, ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn test_approve() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.approved_by, Some("<user>".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_approve_pinned_sha() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn = Box::new(|pr| Ok(pr_with_head(pr.0, HEAD_SHA)));
+        state.comment("@bors r+ 0123abc").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(pr.is_approved());
+        assert_eq!(pr.approved_sha, Some(CommitSha(HEAD_SHA.to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_approve_pinned_sha_not_head() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn = Box::new(|pr| Ok(pr_with_head(pr.0, HEAD_SHA)));
+        state.comment("@bors r+ abcdef1").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":no_entry: Commit `abcdef1` is not the head of this pull request (0123abcdef), it cannot be approved."
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_push_revokes_pinned_approval() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn = Box::new(|pr| Ok(pr_with_head(pr.0, HEAD_SHA)));
+        state.comment("@bors r+ 0123abc").await;
+        state.event(pr_pushed("fedcba9876")).await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":warning: The head of this pull request has changed from the approved commit 0123abcdef to fedcba9876, so its approval was removed. Please review the new changes and approve it again."
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+        assert_eq!(pr.approved_sha, None);
+    }
+
+    #[tokio::test]
+    async fn test_push_keeps_unpinned_approval() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.event(pr_pushed("fedcba9876")).await;
+        state.client().check_comment_count(default_pr_number(), 1);
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_unapprove_resets_queue_status() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(matches!(
+            pr.queue_status(),
+            QueueStatus::Approved { approver, .. } if approver == "<user>"
+        ));
+        assert!(pr.approved_at.is_some());
+
+        state.comment("@bors r-").await;
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.queue_status(), QueueStatus::NotApproved);
+        assert_eq!(pr.approved_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_approve_specified() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r=foo").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.approved_by, Some("foo".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_approve_no_permission() {
+        let mut state = ClientBuilder::default()
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &["@<user>: :key: Insufficient privileges: not in review users"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_missing_required_labels() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().required_labels(vec![
+                "relnotes-reviewed".to_string(),
+                "qa-approved".to_string(),
+            ]))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .labels(vec!["qa-approved".to_string()])
+                .create())
+        });
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":no_entry: This pull request cannot be approved, it is missing the following required label(s): `relnotes-reviewed`"],
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_approve_required_labels_present() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().required_labels(vec!["qa-approved".to_string()]))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .labels(vec!["qa-approved".to_string()])
+                .create())
+        });
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_with_blocking_label() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .labels(vec!["do-not-merge".to_string()])
+                .create())
+        });
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":hourglass: This pull request cannot be approved until the following blocking label(s) are removed: `do-not-merge`"
+        );
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_label_added() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state
+            .pull_request_labeled(default_pr_number(), "do-not-merge")
+            .await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":hourglass: This pull request is held out of the queue until the `do-not-merge` label is removed."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_blocking_label_added() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state
+            .pull_request_labeled(default_pr_number(), "T-compiler")
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_label_removed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state
+            .pull_request_unlabeled(default_pr_number(), "do-not-merge")
+            .await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":arrow_forward: All blocking labels were removed, this pull request is back in the queue."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_label_removed_other_remaining() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec![
+                "do-not-merge".to_string(),
+                "waiting-on-crater".to_string(),
+            ]))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .labels(vec!["waiting-on-crater".to_string()])
+                .create())
+        });
+        state
+            .pull_request_unlabeled(default_pr_number(), "do-not-merge")
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_label_removed_not_approved() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state
+            .pull_request_unlabeled(default_pr_number(), "do-not-merge")
+            .await;
+        state.client().check_comment_count(default_pr_number(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_approve_exceeds_limits() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().limits(PullRequestLimits {
+                max_changed_lines: Some(100),
+                max_changed_files: Some(10),
+                forbidden_paths: vec!["src/generated/".to_string()],
+            }))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .changed_lines(500)
+                .changed_files(5)
+                .create())
+        });
+        state.client().pr_files.insert(
+            default_pr_number(),
+            vec![
+                "src/lib.rs".to_string(),
+                "src/generated/bindings.rs".to_string(),
+            ],
+        );
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :no_entry: This pull request cannot be approved:
+        - it changes 500 lines, but at most 100 are allowed
+        - it modifies files that must not be changed: `src/generated/bindings.rs`
+
+        Please consider splitting it into smaller pull requests. In an emergency, a reviewer can approve it anyway using `r+ ignore-limits`.
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_approve_exceeds_limits_custom_message() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .limits(PullRequestLimits {
+                        max_changed_lines: Some(100),
+                        max_changed_files: None,
+                        forbidden_paths: vec![],
+                    })
+                    .messages(
+                        toml::from_str(
+                            r#"
+limits_exceeded = "Too large:\n{violations}"
+too_many_changed_lines = "- {lines}/{max} lines"
+"#,
+                        )
+                        .unwrap(),
+                    ),
+            )
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .changed_lines(500)
+                .create())
+        });
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        Too large:
+        - 500/100 lines
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_approve_ignore_limits() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().limits(PullRequestLimits {
+                max_changed_lines: Some(100),
+                max_changed_files: None,
+                forbidden_paths: vec![],
+            }))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .changed_lines(500)
+                .create())
+        });
+        state.comment("@bors r+ ignore-limits").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_agreement_not_signed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().agreement(Some(agreement_config())))
+            .agreement_checker(Some(Box::new(SignedBy(vec![]))))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":pen: @<user>, you have not signed the contributor agreement of this repository yet, so this pull request cannot be approved. You can sign it [here](https://cla.com/sign)."
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_approve_agreement_signed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().agreement(Some(agreement_config())))
+            .agreement_checker(Some(Box::new(SignedBy(vec!["<user>".to_string()]))))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    fn agreement_config() -> AgreementConfig {
+        AgreementConfig {
+            url: "https://cla.com/check".to_string(),
+            sign_url: "https://cla.com/sign".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approve_unsigned_commits() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().require_sign_off(true))
+            .create_state()
+            .await;
+        state.client().pr_commits.insert(
+            default_pr_number(),
+            vec![
+                commit("sha1", "Fix bug\n\nSigned-off-by: Foo Bar <foo@bar.com>"),
+                commit("sha2", "Add test"),
+                commit(
+                    "sha3",
+                    "Fix test\n\nSigned-off-by: Someone Else <foo@bar.com>",
+                ),
+            ],
+        );
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :no_entry: This pull request cannot be approved. The following commit(s) do not contain a `Signed-off-by` trailer matching their author:
+        - sha2 Add test
+        - sha3 Fix test
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_approve_signed_commits() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().require_sign_off(true))
+            .create_state()
+            .await;
+        state.client().pr_commits.insert(
+            default_pr_number(),
+            vec![commit(
+                "sha1",
+                "Fix bug\n\nSigned-off-by: Foo Bar <FOO@bar.com>",
+            )],
+        );
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_unverified_commits() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().require_verified_commits(true))
+            .create_state()
+            .await;
+        let mut unverified = commit("sha2", "Add test");
+        unverified.verified = false;
+        state.client().pr_commits.insert(
+            default_pr_number(),
+            vec![commit("sha1", "Fix bug"), unverified],
+        );
+        state.comment("@bors r+").await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :no_entry: This pull request cannot be approved. The following commit(s) do not have a verified signature:
+        - sha2 Add test
+        "###);
+    }
+
+    fn commit(sha: &str, message: &str) -> Commit {
+        Commit {
+            sha: CommitSha(sha.to_string()),
+            message: message.to_string(),
+            author_name: "Foo Bar".to_string(),
+            author_email: "foo@bar.com".to_string(),
+            verified: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_priority() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors p=7").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.priority, 7);
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":ok: The priority of this pull request is now 7."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear_rollup() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().features(toml::from_str("rollups = true").unwrap()),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        state
+            .db
+            .set_rollup(&pr, Some(RollupMode::Always))
+            .await
+            .unwrap();
+        state.comment("@bors rollup-").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.rollup, None);
+        assert!(pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_set_rollup() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().features(toml::from_str("rollups = true").unwrap()),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors rollup=iffy").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":ok: The rollup mode of this pull request is now `iffy`."
+        );
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.rollup, Some(RollupMode::Iffy));
+
+        state.comment("@bors rollup-").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":ok: This pull request is no longer marked for rollup."
+        );
+        state.comment("@bors rollup-").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":exclamation: This pull request is not marked for rollup."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear_rollup_disabled() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        state
+            .db
+            .set_rollup(&pr, Some(RollupMode::Always))
+            .await
+            .unwrap();
+        state.comment("@bors rollup-").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":no_entry_sign: The `rollups` feature is not enabled in this repository."
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert_eq!(pr.rollup, Some(RollupMode::Always));
+    }
+
+    #[tokio::test]
+    async fn test_unapprove() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r-").await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_approve_draft() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).draft(true).create()));
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":construction: This pull request is a draft, so it cannot be approved. Mark it as ready for review first."],
+        );
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_approve_draft_allowed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().drafts(DraftPolicy::Allow))
+            .create_state()
+            .await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).draft(true).create()));
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_converted_to_draft_removes_approval() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.converted_to_draft(default_pr_number()).await;
+
+        state.client().check_comment_count(default_pr_number(), 2);
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_converted_to_draft_not_approved() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.converted_to_draft(default_pr_number()).await;
+        state.client().check_comment_count(default_pr_number(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_review_approval_disabled() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.review_approved("").await;
+        state.client().check_comment_count(default_pr_number(), 0);
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_review_approval() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().review_approvals(true))
+            .create_state()
+            .await;
+        state.review_approved("").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_review_approval_with_command() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().review_approvals(true))
+            .create_state()
+            .await;
+        state.review_approved("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":pushpin: Commit pr-sha has been approved by `<user>`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve_already_approved() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.comment("@bors r+").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[
+                ":pushpin: Commit pr-sha has been approved by `<user>`",
+                ":information_source: This pull request is already approved by `<user>`.",
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_review_approval_no_permission() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().review_approvals(true))
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        state.review_approved("").await;
+        state.client().check_comment_count(default_pr_number(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_review_dismissed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().review_approvals(true))
+            .create_state()
+            .await;
+        state.review_approved("").await;
+        state
+            .review_dismissed(default_pr_number(), default_user())
+            .await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @"The review of `<user>` was dismissed, so the approval of this pull request was removed.");
+    }
+
+    #[tokio::test]
+    async fn test_review_dismissed_other_reviewer() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().review_approvals(true))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state
+            .review_dismissed(
+                default_pr_number(),
+                GithubUser {
+                    username: "other".to_string(),
+                    html_url: "https://other.com".parse().unwrap(),
+                },
+            )
+            .await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(pr.is_approved());
+    }
+
+    #[tokio::test]
+    async fn test_force_dequeue() {
+        let mut state = ClientBuilder::default()
+            .permission_resolver(Box::new(NoPermissions))
+            .create_state()
+            .await;
+        state.force_dequeue(default_pr_number()).await;
+        state.client().check_comment_count(default_pr_number(), 0);
+
+        state
+            .db
+            .approve(
+                &state
+                    .db
+                    .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+                    .await
+                    .unwrap(),
+                "alice",
+                None,
+            )
+            .await
+            .unwrap();
+        state.force_dequeue(default_pr_number()).await;
+
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        assert!(!pr.is_approved());
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":warning: This PR has been removed from the queue by an administrator.");
+    }
+
+    const HEAD_SHA: &str = "0123abcdef";
+
+    fn pr_with_head(number: u64, sha: &str) -> PullRequest {
+        PRBuilder::default()
+            .number(number)
+            .head(BranchBuilder::default().sha(sha.to_string()).create())
+            .create()
+    }
+
+    fn pr_pushed(sha: &str) -> BorsEvent {
+        BorsEvent::PullRequestPushed(PullRequestPushed {
+            repository: default_repo_name(),
+            pr_number: PullRequestNumber(default_pr_number()),
+            head_sha: CommitSha(sha.to_string()),
+        })
+    }
+}
@@ -0,0 +1,78 @@
+//! Work done by an event loop before the bot stops, once it has handled all events it has
+//! received. Builds and the merge queue are stored in the database, so only the state that is
+//! kept in memory has to be written out.
+use tracing::Instrument;
+
+use crate::bors::handlers::comments::flush_deferred_comments;
+use crate::bors::lease::release_lease;
+use crate::bors::{BorsContext, BorsState, RepositoryClient};
+use crate::notifications::{notify, Notification, NotificationEvent};
+
+const RESTART_MESSAGE: &str =
+    "Bors is restarting. Webhooks received in the meantime will be handled once it is back.";
+
+/// Posts postponed updates of summary comments, releases the leases of repositories and, if
+/// enabled, notifies every repository that the bot is restarting.
+pub async fn handle_shutdown<Client: RepositoryClient>(
+    state: &mut dyn BorsState<Client>,
+    ctx: &BorsContext,
+) {
+    let (repos, db) = state.get_all_repos_mut();
+    for repo in repos {
+        let span = tracing::info_span!("Shutdown", repo = repo.repository.to_string());
+        async {
+            flush_deferred_comments(repo, db).await;
+            release_lease(repo, db, ctx).await;
+            if ctx.restart_notice {
+                notify(
+                    repo,
+                    Notification {
+                        event: NotificationEvent::Restarting,
+                        repository: repo.repository.clone(),
+                        pr: None,
+                        message: RESTART_MESSAGE.to_string(),
+                    },
+                )
+                .await;
+            }
+        }
+        .instrument(span)
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bors::{handle_shutdown, BorsContext, CommandParser};
+    use crate::notifications::{NotificationEvent, NotificationRoute};
+    use crate::tests::event::default_pr_number;
+    use crate::tests::notifications::RecordingNotifier;
+    use crate::tests::state::{ClientBuilder, RepoConfigBuilder};
+
+    #[tokio::test]
+    async fn flush_updates_on_shutdown() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .summary_comment(true)
+                    .summary_comment_delay(Duration::from_secs(10)),
+            )
+            .notifications(vec![NotificationRoute {
+                events: vec![NotificationEvent::Restarting],
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_comment_count(default_pr_number(), 1);
+
+        let ctx =
+            BorsContext::new(CommandParser::new("@bors".to_string())).with_restart_notice(true);
+        handle_shutdown(&mut state, &ctx).await;
+        state.client().check_comment_count(default_pr_number(), 2);
+        assert_eq!(notifier.events(), vec![NotificationEvent::Restarting]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Reminders about PRs that are stuck in the same state for too long (e.g. an approved PR whose
+//! build has failed), so that they do not silently rot in the queue.
+use chrono::{DateTime, Utc};
+
+use crate::bors::{RepositoryClient, RepositoryState};
+use crate::config::Feature;
+use crate::database::{BuildStatus, DbClient, LoggedEvent, PullRequestModel};
+use crate::messages::MessageKind;
+
+/// A state in which a PR should not stay for long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StaleState {
+    ApprovedFailing,
+    Delegated,
+    FailedTry,
+}
+
+impl StaleState {
+    fn reason(self) -> &'static str {
+        match self {
+            StaleState::ApprovedFailing => "approved_failing",
+            StaleState::Delegated => "delegated",
+            StaleState::FailedTry => "failed_try",
+        }
+    }
+}
+
+/// When did the bors state of the PR change for the last time.
+fn last_activity(pr: &PullRequestModel) -> DateTime<Utc> {
+    let updated_at = pr.updated_at.unwrap_or(pr.created_at);
+    match &pr.try_build {
+        Some(build) => updated_at.max(build.created_at),
+        None => updated_at,
+    }
+}
+
+/// Delegated PRs are only reminded if the `delegation` feature is enabled.
+fn stale_state(pr: &PullRequestModel, delegation: bool) -> Option<StaleState> {
+    let failed = pr
+        .try_build
+        .as_ref()
+        .map_or(false, |build| build.status == BuildStatus::Failure);
+    if pr.is_approved() {
+        failed.then_some(StaleState::ApprovedFailing)
+    } else if delegation && pr.delegated_to.is_some() {
+        Some(StaleState::Delegated)
+    } else if failed
+        && pr
+            .try_build
+            .as_ref()
+            .map_or(false, |build| build.retry_at.is_none())
+    {
+        Some(StaleState::FailedTry)
+    } else {
+        None
+    }
+}
+
+/// Posts a reminder to each PR that has been in a stale state for longer than the threshold
+/// configured for the state. Each PR is reminded only once, until its state changes again.
+/// The reminders refer to the commands of the repository using `prefix`.
+pub(super) async fn remind_stale_pull_requests<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    prefix: &str,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let config = repo.config.stale_reminders.clone();
+    if config == Default::default() {
+        return Ok(());
+    }
+
+    let delegation = repo.config.features.is_enabled(Feature::Delegation);
+    for pr in db.get_pull_requests(&repo.repository).await? {
+        let Some(state) = stale_state(&pr, delegation) else {
+            continue;
+        };
+        let threshold = match state {
+            StaleState::ApprovedFailing => config.approved_failing_days,
+            StaleState::Delegated => config.delegated_days,
+            StaleState::FailedTry => config.failed_try_days,
+        };
+        let Some(threshold) = threshold else {
+            continue;
+        };
+        let since = last_activity(&pr);
+        let days = (now - since).num_days();
+        if days < threshold as i64 {
+            continue;
+        }
+        let reminder = LoggedEvent::StaleReminder {
+            reason: state.reason().to_string(),
+            since: since.timestamp(),
+        };
+        let reminded = db
+            .get_events_of_kinds(&repo.repository, Some(pr.number), &[reminder.kind()])
+            .await?
+            .into_iter()
+            .any(|event| event.event == reminder);
+        if reminded {
+            continue;
+        }
+
+        let github_pr = repo.client.get_pull_request(pr.number).await?;
+        if !github_pr.open {
+            // The reminder is recorded without being posted, so that the closed PR is not
+            // fetched again until its state changes
+            db.record_event(&repo.repository, Some(pr.number), reminder)
+                .await?;
+            continue;
+        }
+        tracing::info!("Reminding PR {} in state {state:?}", pr.number);
+        let days = days.to_string();
+        let message = match state {
+            StaleState::ApprovedFailing => repo.config.messages.render(
+                MessageKind::StaleApprovedFailing,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("author", &github_pr.author),
+                    ("approver", pr.approved_by.as_deref().unwrap_or_default()),
+                    ("days", &days),
+                    ("prefix", prefix),
+                ],
+            ),
+            StaleState::Delegated => repo.config.messages.render(
+                MessageKind::StaleDelegation,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("delegate", pr.delegated_to.as_deref().unwrap_or_default()),
+                    ("days", &days),
+                    ("prefix", prefix),
+                ],
+            ),
+            StaleState::FailedTry => repo.config.messages.render(
+                MessageKind::StaleTryFailure,
+                &repo.repository,
+                pr.number,
+                &[("author", &github_pr.author), ("days", &days)],
+            ),
+        };
+        repo.client.post_comment(pr.number, &message).await?;
+        db.record_event(&repo.repository, Some(pr.number), reminder)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
+    use crate::bors::handlers::stale::remind_stale_pull_requests;
+    use crate::bors::BorsState;
+    use crate::config::StaleRemindersConfig;
+    use crate::database::WorkflowStatus;
+    use crate::tests::event::{default_pr_number, suite_failure};
+    use crate::tests::github::PRBuilder;
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder,
+    };
+
+    fn config() -> RepoConfigBuilder {
+        RepoConfigBuilder::default().stale_reminders(StaleRemindersConfig {
+            approved_failing_days: Some(3),
+            delegated_days: None,
+            failed_try_days: Some(7),
+        })
+    }
+
+    #[tokio::test]
+    async fn remind_approved_failing_pr_once() {
+        let mut state = ClientBuilder::default()
+            .config(config())
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_comment_count(default_pr_number(), 3);
+
+        let now = Utc::now();
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        remind_stale_pull_requests(repo, db, "@bors", now + Duration::days(1))
+            .await
+            .unwrap();
+        state.client().check_comment_count(default_pr_number(), 3);
+
+        for day in [4, 5] {
+            let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+            remind_stale_pull_requests(repo, db, "@bors", now + Duration::days(day))
+                .await
+                .unwrap();
+        }
+        state.client().check_comment_count(default_pr_number(), 4);
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":hourglass: @<user> @<user>, this pull request is approved, but its build has been failing for 4 day(s). Please fix the failure, or unapprove the pull request using @bors r-."
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_closed_pr() {
+        let mut state = ClientBuilder::default()
+            .config(config())
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).open(false).create()));
+
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        remind_stale_pull_requests(repo, db, "@bors", Utc::now() + Duration::days(10))
+            .await
+            .unwrap();
+        state.client().check_comment_count(default_pr_number(), 2);
+
+        // The closed PR is not checked again
+        state.client().get_pr_fn = Box::new(|_| Err(anyhow::anyhow!("PR fetched again")));
+        let (repo, db) = state.get_repo_state_mut(&default_repo_name()).unwrap();
+        remind_stale_pull_requests(repo, db, "@bors", Utc::now() + Duration::days(11))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+//! Periodically cross-checks the persisted state of a repository with GitHub and repairs drift
+//! caused by lost webhooks: running builds whose branch has changed or whose checks have finished,
+//! and PRs in the merge queue that have been closed.
+use std::collections::HashSet;
+
+use crate::bors::event::{CheckSuiteCompleted, PullRequestClosed};
+use crate::bors::handlers::branches::{build_branch_changed, build_commit_branch};
+use crate::bors::handlers::comments::{post_status_comment, update_summary_comment};
+use crate::bors::handlers::pull_request::handle_pull_request_closed;
+use crate::bors::handlers::workflow::try_complete_build;
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::consistency::{Discrepancy, DiscrepancyKind};
+use crate::database::{BuildModel, BuildStatus, DbClient};
+use crate::github::{CommitSha, PullRequestNumber};
+use crate::messages::MessageKind;
+
+pub(super) async fn sweep_repository<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+) -> anyhow::Result<()> {
+    let mut discrepancies = vec![];
+    // PRs that have already been checked while sweeping their running build
+    let mut checked = HashSet::new();
+    for build in db.get_running_builds(&repo.repository).await? {
+        match sweep_build(repo, db, &build, &mut checked).await {
+            Ok(Some(discrepancy)) => discrepancies.push(discrepancy),
+            Ok(None) => {}
+            Err(error) => tracing::error!("Could not sweep build {}: {error:?}", build.commit_sha),
+        }
+    }
+    for pr in db.get_approved_pull_requests(&repo.repository).await? {
+        if checked.contains(&pr.number.0) {
+            continue;
+        }
+        match sweep_closed_pull_request(repo, db, pr.number).await {
+            Ok(Some(discrepancy)) => discrepancies.push(discrepancy),
+            Ok(None) => {}
+            Err(error) => tracing::error!("Could not sweep PR {}: {error:?}", pr.number),
+        }
+    }
+
+    if discrepancies.is_empty() {
+        tracing::debug!("No discrepancies found");
+    } else {
+        tracing::warn!("Repaired {} discrepancies", discrepancies.len());
+    }
+    ctx.consistency.record(&repo.repository, discrepancies);
+    Ok(())
+}
+
+async fn sweep_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    build: &BuildModel,
+    checked: &mut HashSet<u64>,
+) -> anyhow::Result<Option<Discrepancy>> {
+    let pr = db.find_pr_by_build(build).await?.map(|pr| pr.number);
+    if let Some(pr) = pr {
+        checked.insert(pr.0);
+        if let Some(discrepancy) = sweep_closed_pull_request(repo, db, pr).await? {
+            return Ok(Some(discrepancy));
+        }
+    }
+
+    if build_branch_changed(repo, db, build, pr).await? {
+        let branch = build_commit_branch(repo, build, pr);
+        tracing::warn!("Branch of build {} has changed", build.commit_sha);
+        db.update_build_status(build, BuildStatus::Cancelled)
+            .await?;
+        if let Some(pr) = pr {
+            let message = repo.config.messages.render(
+                MessageKind::BuildBranchChanged,
+                &repo.repository,
+                pr,
+                &[("sha", &build.commit_sha), ("branch", &branch)],
+            );
+            post_status_comment(repo, db, pr, &message).await?;
+            update_summary_comment(repo, db, pr).await?;
+        }
+        return Ok(Some(Discrepancy::new(
+            DiscrepancyKind::BuildBranchChanged,
+            pr,
+            format!(
+                "Branch `{}` does not point to commit {} of a running build",
+                branch, build.commit_sha
+            ),
+        )));
+    }
+
+    // The workflows of the build might not have been created yet
+    if db.get_workflows_for_build(build).await?.is_empty() {
+        return Ok(None);
+    }
+    let event = CheckSuiteCompleted {
+        repository: repo.repository.clone(),
+        branch: build.branch.clone(),
+        commit_sha: CommitSha(build.commit_sha.clone()),
+    };
+    try_complete_build(repo, db, event).await?;
+    let finished = db
+        .find_build_by_key(build.id)
+        .await?
+        .map_or(false, |build| build.status != BuildStatus::Pending);
+    if !finished {
+        return Ok(None);
+    }
+    tracing::warn!("Checks of build {} have finished", build.commit_sha);
+    Ok(Some(Discrepancy::new(
+        DiscrepancyKind::BuildFinished,
+        pr,
+        format!(
+            "Checks of commit {} have finished, but the build was running",
+            build.commit_sha
+        ),
+    )))
+}
+
+/// Handles a PR that has been closed without the bot noticing it, like if the webhook had been
+/// received.
+async fn sweep_closed_pull_request<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr_number: PullRequestNumber,
+) -> anyhow::Result<Option<Discrepancy>> {
+    let pr = repo.client.get_pull_request(pr_number).await?;
+    if pr.open {
+        return Ok(None);
+    }
+    tracing::warn!("PR {pr_number} has been closed (merged: {})", pr.merged);
+    let payload = PullRequestClosed {
+        repository: repo.repository.clone(),
+        pr_number,
+        merged: pr.merged,
+        base_branch: pr.base.name,
+    };
+    handle_pull_request_closed(repo, db, payload).await?;
+    Ok(Some(Discrepancy::new(
+        DiscrepancyKind::PullRequestClosed,
+        Some(pr_number),
+        "PR has been closed, but it was still tracked",
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
+    use crate::bors::RepositoryClient;
+    use crate::consistency::DiscrepancyKind;
+    use crate::database::{DbClient, LoggedEvent, WorkflowStatus};
+    use crate::github::CommitSha;
+    use crate::tests::event::{
+        comment, default_pr_number, suite_pending, suite_success, WorkflowCompletedBuilder,
+        WorkflowStartedBuilder,
+    };
+    use crate::tests::github::PRBuilder;
+    use crate::tests::state::{default_merge_sha, default_repo_name, ClientBuilder};
+
+    #[tokio::test]
+    async fn sweep_consistent_state() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.sweep().await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        let reports = state.consistency.reports();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].discrepancies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_changed_build_branch() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.client().delete_branch(TRY_BRANCH_NAME).await.unwrap();
+        state.sweep().await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":warning: The build of commit sha-merged was cancelled, because its branch `automation/bors/try` has been changed. Please start it again."
+        );
+        assert!(state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            state
+                .consistency
+                .count(&default_repo_name(), DiscrepancyKind::BuildBranchChanged),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn sweep_overlapping_try_builds() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
+        state.client().merge_branches_fn = Box::new(|| Ok(CommitSha("sha-merged-2".to_string())));
+        state.comment(comment("@bors try").pr_number(2)).await;
+        state.sweep().await;
+        state.client().check_comment_count(default_pr_number(), 1);
+        assert_eq!(
+            state
+                .db
+                .get_running_builds(&default_repo_name())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(state.consistency.reports()[0].discrepancies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_finished_build() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_pending()]);
+        state.comment("@bors try").await;
+        state
+            .workflow_started(WorkflowStartedBuilder::default().branch(TRY_BRANCH_NAME.to_string()))
+            .await;
+        state
+            .workflow_completed(
+                WorkflowCompletedBuilder::default()
+                    .branch(TRY_BRANCH_NAME.to_string())
+                    .status(WorkflowStatus::Success),
+            )
+            .await;
+
+        // The webhook about the finished check suite has been lost
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+        state.sweep().await;
+        assert!(state
+            .client()
+            .get_last_comment(default_pr_number())
+            .starts_with(":sunny: Try build successful"));
+        assert_eq!(
+            state
+                .consistency
+                .count(&default_repo_name(), DiscrepancyKind::BuildFinished),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn sweep_closed_pull_request() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).open(false).create()));
+        state.sweep().await;
+        assert!(state
+            .db
+            .get_approved_pull_requests(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
+        let report = &state.consistency.reports()[0];
+        assert_eq!(report.discrepancies.len(), 1);
+        assert_eq!(
+            report.discrepancies[0].kind,
+            DiscrepancyKind::PullRequestClosed
+        );
+        assert_eq!(report.discrepancies[0].pr, Some(default_pr_number()));
+    }
+
+    #[tokio::test]
+    async fn sweep_merged_pull_request() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .open(false)
+                .merged(true)
+                .create())
+        });
+        state.sweep().await;
+        let events: Vec<LoggedEvent> = state
+            .db
+            .get_events(&default_repo_name(), Some(default_pr_number().into()))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert!(events.contains(&LoggedEvent::Merged));
+    }
+
+    #[tokio::test]
+    async fn sweep_approved_pull_request_with_build_once() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors r+").await;
+        state.comment("@bors try").await;
+        let fetched = Arc::new(AtomicUsize::new(0));
+        let counter = fetched.clone();
+        state.client().get_pr_fn = Box::new(move |pr| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(PRBuilder::default().number(pr.0).create())
+        });
+        state.sweep().await;
+        assert_eq!(fetched.load(Ordering::SeqCst), 1);
+    }
+}
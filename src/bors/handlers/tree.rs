@@ -0,0 +1,435 @@
+//! Closing and reopening of the tree of a repository. While the tree is closed (e.g. because the
+//! base branch is broken), only PRs with at least the priority of the closure are merged. PRs
+//! below the priority are held out of the GitHub merge queue until the tree is reopened.
+//!
+//! Every change of the state of the tree is announced to the notification routes of the
+//! repository, and the current closure is shown on the dashboard.
+use crate::bors::handlers::branches::AUTO_BRANCH_NAME;
+use crate::bors::handlers::merge_queue::dequeue_pull_request;
+use crate::bors::handlers::reconcile::requeue_pull_request;
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::config::LandingMode;
+use crate::database::{BuildModel, BuildStatus, DbClient, TreeClosure};
+use crate::github::{GithubUser, PullRequest};
+use crate::messages::MessageKind;
+use crate::notifications::{notify, Notification, NotificationEvent};
+
+/// Closes the tree for PRs below `priority`.
+pub(super) async fn command_tree_closed<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+    author: &GithubUser,
+    priority: u32,
+    reason: String,
+) -> anyhow::Result<()> {
+    db.close_tree(&repo.repository, priority, Some(&author.username), &reason)
+        .await?;
+    tracing::info!("Tree closed below priority {priority}");
+
+    let closure = TreeClosure {
+        priority,
+        closed_by: Some(author.username.clone()),
+        reason,
+        closed_at: chrono::Utc::now(),
+    };
+    dequeue_held_pull_requests(repo, db, priority).await?;
+    let message = repo.config.messages.render(
+        MessageKind::TreeClosed,
+        &repo.repository,
+        pr.number,
+        &[
+            ("user", &author.username),
+            ("priority", &priority.to_string()),
+            ("reason", &closure.reason),
+            ("description", &describe_closure(&closure)),
+        ],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+    announce_closure(repo, &closure).await;
+    Ok(())
+}
+
+/// Reopens the tree.
+pub(super) async fn command_tree_open<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+    pr: &PullRequest,
+    author: &GithubUser,
+) -> anyhow::Result<()> {
+    if db.get_tree_closure(&repo.repository).await?.is_none() {
+        let message = repo.config.messages.render(
+            MessageKind::TreeNotClosed,
+            &repo.repository,
+            pr.number,
+            &[],
+        );
+        repo.client.post_comment(pr.number, &message).await?;
+        return Ok(());
+    }
+
+    db.open_tree(&repo.repository, Some(&author.username))
+        .await?;
+    tracing::info!("Tree reopened");
+    requeue_held_pull_requests(repo, db, ctx).await?;
+
+    let message = repo.config.messages.render(
+        MessageKind::TreeOpened,
+        &repo.repository,
+        pr.number,
+        &[("user", &author.username)],
+    );
+    repo.client.post_comment(pr.number, &message).await?;
+    announce_opening(
+        repo,
+        format!("The tree has been reopened by `{}`.", author.username),
+    )
+    .await;
+    Ok(())
+}
+
+/// Closes the tree automatically once the configured number of builds of approved PRs in a row
+/// have failed, and reopens a tree that was closed automatically once such a build succeeds.
+/// Try builds are ignored, they test changes that have not been reviewed yet.
+pub(super) async fn update_tree_after_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    build: &BuildModel,
+    success: bool,
+) -> anyhow::Result<()> {
+    let Some(threshold) = repo.config.tree.close_after_failures else {
+        return Ok(());
+    };
+    if build.branch != AUTO_BRANCH_NAME {
+        return Ok(());
+    }
+    let closure = db.get_tree_closure(&repo.repository).await?;
+
+    if success {
+        if closure.map_or(false, |closure| closure.closed_by.is_none()) {
+            db.open_tree(&repo.repository, None).await?;
+            tracing::info!("Tree reopened automatically");
+            announce_opening(
+                repo,
+                "The tree has been reopened automatically, because a build has succeeded."
+                    .to_string(),
+            )
+            .await;
+        }
+        return Ok(());
+    }
+    if closure.is_some() {
+        return Ok(());
+    }
+
+    // Builds that are still running are skipped, they have not failed (yet)
+    let running = db
+        .get_running_builds(&repo.repository)
+        .await?
+        .into_iter()
+        .filter(|build| build.branch == AUTO_BRANCH_NAME)
+        .count() as u64;
+    let finished: Vec<BuildStatus> = db
+        .get_branch_builds(
+            &repo.repository,
+            AUTO_BRANCH_NAME,
+            threshold as u64 + running,
+        )
+        .await?
+        .into_iter()
+        .map(|build| build.status)
+        .filter(|status| *status != BuildStatus::Pending)
+        .take(threshold as usize)
+        .collect();
+    if finished.len() < threshold as usize
+        || finished
+            .iter()
+            .any(|status| *status != BuildStatus::Failure)
+    {
+        return Ok(());
+    }
+
+    let priority = repo.config.tree.closed_priority;
+    let reason = format!("{threshold} build(s) in a row have failed");
+    db.close_tree(&repo.repository, priority, None, &reason)
+        .await?;
+    tracing::info!("Tree closed automatically below priority {priority}");
+    dequeue_held_pull_requests(repo, db, priority).await?;
+    announce_closure(
+        repo,
+        &TreeClosure {
+            priority,
+            closed_by: None,
+            reason,
+            closed_at: chrono::Utc::now(),
+        },
+    )
+    .await;
+    Ok(())
+}
+
+/// Removes approved PRs below the priority of a closed tree from the GitHub merge queue, which
+/// would merge them otherwise.
+async fn dequeue_held_pull_requests<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    priority: u32,
+) -> anyhow::Result<()> {
+    if repo.config.landing != LandingMode::GithubMergeQueue {
+        return Ok(());
+    }
+    for pr in db.get_approved_pull_requests(&repo.repository).await? {
+        if pr.priority < priority {
+            dequeue_pull_request(repo, pr.number).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the approved PRs that were held out of the GitHub merge queue while the tree was closed
+/// back to the queue.
+async fn requeue_held_pull_requests<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+) -> anyhow::Result<()> {
+    if repo.config.landing != LandingMode::GithubMergeQueue {
+        return Ok(());
+    }
+    for pr in db.get_approved_pull_requests(&repo.repository).await? {
+        if let Err(error) = requeue_pull_request(repo, db, ctx, pr.number).await {
+            tracing::error!("Could not re-enqueue PR {}: {error:?}", pr.number);
+        }
+    }
+    Ok(())
+}
+
+/// Describes who has closed the tree, for which PRs and why.
+pub fn describe_closure(closure: &TreeClosure) -> String {
+    let who = match &closure.closed_by {
+        Some(user) => format!("by `{user}`"),
+        None => "automatically".to_string(),
+    };
+    let mut text = format!(
+        "The tree has been closed {who} for pull requests below priority {}",
+        closure.priority
+    );
+    if closure.reason.is_empty() {
+        text.push('.');
+    } else {
+        text.push_str(&format!(": {}", closure.reason));
+    }
+    text
+}
+
+async fn announce_closure<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    closure: &TreeClosure,
+) {
+    notify(
+        repo,
+        Notification {
+            event: NotificationEvent::TreeClosed,
+            repository: repo.repository.clone(),
+            pr: None,
+            message: describe_closure(closure),
+        },
+    )
+    .await;
+}
+
+async fn announce_opening<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    message: String,
+) {
+    notify(
+        repo,
+        Notification {
+            event: NotificationEvent::TreeOpened,
+            repository: repo.repository.clone(),
+            pr: None,
+            message,
+        },
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::handlers::branches::{AUTO_BRANCH_NAME, TRY_BRANCH_NAME};
+    use crate::config::{LandingMode, TreeConfig};
+    use crate::database::{DbClient, TryBuildArgs, WorkflowStatus};
+    use crate::github::CommitSha;
+    use crate::notifications::{NotificationEvent, NotificationRoute};
+    use crate::tests::event::{default_pr_number, suite_failure, suite_success};
+    use crate::tests::notifications::RecordingNotifier;
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder, TestBorsState,
+    };
+
+    #[tokio::test]
+    async fn test_close_and_open_tree() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .notifications(vec![NotificationRoute {
+                events: vec![NotificationEvent::TreeClosed, NotificationEvent::TreeOpened],
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state.comment("@bors treeclosed=100 CI is broken").await;
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @":evergreen_tree: The tree has been closed by `<user>` for pull requests below priority 100: CI is broken"
+        );
+        let closure = state
+            .db
+            .get_tree_closure(&default_repo_name())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(closure.priority, 100);
+        assert_eq!(closure.closed_by.as_deref(), Some("<user>"));
+
+        state.comment("@bors treeclosed-").await;
+        assert!(state
+            .db
+            .get_tree_closure(&default_repo_name())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            notifier.events(),
+            vec![NotificationEvent::TreeClosed, NotificationEvent::TreeOpened]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_tree_not_closed() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors treeclosed-").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":exclamation: The tree is not closed."],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_tree_after_failures() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().tree(TreeConfig {
+                close_after_failures: Some(2),
+                closed_priority: 50,
+            }))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        for run_id in [1, 2] {
+            start_auto_build(&mut state).await;
+            state
+                .perform_workflow_events(
+                    run_id,
+                    AUTO_BRANCH_NAME,
+                    &default_merge_sha(),
+                    WorkflowStatus::Failure,
+                )
+                .await;
+        }
+        let closure = state
+            .db
+            .get_tree_closure(&default_repo_name())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(closure.priority, 50);
+        assert_eq!(closure.closed_by, None);
+
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+        start_auto_build(&mut state).await;
+        state
+            .perform_workflow_events(
+                3,
+                AUTO_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        assert!(state
+            .db
+            .get_tree_closure(&default_repo_name())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_try_builds_do_not_close_tree() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().tree(TreeConfig {
+                close_after_failures: Some(1),
+                closed_priority: 50,
+            }))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        assert!(state
+            .db
+            .get_tree_closure(&default_repo_name())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_closed_tree_holds_pr_out_of_merge_queue() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().landing(LandingMode::GithubMergeQueue))
+            .create_state()
+            .await;
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+
+        state.comment("@bors treeclosed=10 CI is broken").await;
+        state.client().check_merge_queue(&[]);
+        state.comment("@bors r+").await;
+        state.client().check_merge_queue(&[]);
+
+        state.comment("@bors treeclosed-").await;
+        state.client().check_merge_queue(&[default_pr_number()]);
+    }
+
+    async fn start_auto_build(state: &mut TestBorsState) {
+        let pr = state
+            .db
+            .get_or_create_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap();
+        state
+            .db
+            .attach_try_build(
+                pr,
+                AUTO_BRANCH_NAME.to_string(),
+                CommitSha(default_merge_sha()),
+                0,
+                TryBuildArgs::default(),
+            )
+            .await
+            .unwrap();
+    }
+}
@@ -1,41 +1,162 @@
-use anyhow::anyhow;
+use std::collections::HashMap;
 
+use crate::bors::handlers::branches::{
+    create_candidate_merge, report_candidate_merge_error, try_merge_branch_name, TRY_BRANCH_NAME,
+};
+use crate::bors::handlers::command_prefix;
+use crate::bors::handlers::comments::post_status_comment;
 use crate::bors::handlers::labels::handle_label_trigger;
+use crate::bors::handlers::pause::describe_pause;
+use crate::bors::handlers::review::blocking_labels;
 use crate::bors::RepositoryClient;
 use crate::bors::RepositoryState;
+use crate::bors::{BorsContext, DispatchedWorkflow};
+use crate::ci::CiBuildRequest;
+use crate::config::{Feature, TryTrigger};
 use crate::database::{
-    BuildModel, BuildStatus, DbClient, PullRequestModel, WorkflowStatus, WorkflowType,
-};
-use crate::github::{
-    CommitSha, GithubUser, LabelTrigger, MergeError, PullRequest, PullRequestNumber,
+    BuildModel, BuildStatus, DbClient, PullRequestModel, TryBuildArgs, WorkflowStatus, WorkflowType,
 };
-use crate::permissions::PermissionType;
-
-// This branch serves for preparing the final commit.
-// It will be reset to master and merged with the branch that should be tested.
-// Because this action (reset + merge) is not atomic, this branch should not run CI checks to avoid
-// starting them twice.
-const TRY_MERGE_BRANCH_NAME: &str = "automation/bors/try-merge";
-
-// This branch should run CI checks.
-pub(super) const TRY_BRANCH_NAME: &str = "automation/bors/try";
+use crate::github::{Branch, CommitSha, LabelTrigger, PullRequest, PullRequestNumber};
+use crate::messages::MessageKind;
+use crate::notifications::{notify, Notification, NotificationEvent};
 
 /// Performs a so-called try build - merges the PR branch into a special branch designed
 /// for running CI checks.
 ///
 /// If `parent` is set, it will use it as a base commit for the merge.
 /// Otherwise, it will use the latest commit on the main repository branch.
+///
+/// If `commit` is set, that commit of the PR is merged instead of the head of the PR. It has to
+/// be one of the commits of the PR.
+///
+/// If the repository starts try builds using a workflow dispatch, the configured workflow is
+/// dispatched with the merge commit and the `jobs` filter as its inputs. If it uses an external
+/// CI service, a build of the configured pipeline is started instead.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn command_try_build<Client: RepositoryClient>(
     repo: &mut RepositoryState<Client>,
     db: &mut dyn DbClient,
+    ctx: &BorsContext,
     pr: &PullRequest,
-    author: &GithubUser,
     parent: Option<CommitSha>,
+    commit: Option<CommitSha>,
+    jobs: Option<String>,
 ) -> anyhow::Result<()> {
-    if !check_try_permissions(repo, pr, author).await? {
+    if let Some(pause) = db.get_pause(&repo.repository).await? {
+        tracing::info!("Refusing to start a try build in a paused repository");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::TryPaused,
+                    &repo.repository,
+                    pr.number,
+                    &[("description", &describe_pause(&pause))],
+                ),
+            )
+            .await?;
         return Ok(());
     }
 
+    if !repo.config.features.is_enabled(Feature::TryOnForks)
+        && pr.is_from_fork(repo.client.repository())
+    {
+        tracing::info!("Refusing to test a PR from a fork");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::TryFromFork,
+                    &repo.repository,
+                    pr.number,
+                    &[],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if pr.draft && !repo.config.drafts.allows_try() {
+        tracing::info!("Refusing to test a draft PR");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::TryDraft,
+                    &repo.repository,
+                    pr.number,
+                    &[],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let blocking_labels = blocking_labels(repo, &pr.labels);
+    if !blocking_labels.is_empty() {
+        tracing::info!("Refusing to test a PR with blocking labels {blocking_labels:?}");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::TryBlocked,
+                    &repo.repository,
+                    pr.number,
+                    &[("labels", &blocking_labels.join(", "))],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if jobs.is_some() && !matches!(repo.config.try_trigger, TryTrigger::WorkflowDispatch { .. }) {
+        tracing::warn!("Job filter used without a workflow dispatch");
+        repo.client
+            .post_comment(
+                pr.number,
+                &repo.config.messages.render(
+                    MessageKind::JobFilterUnsupported,
+                    &repo.repository,
+                    pr.number,
+                    &[],
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let pr_head_sha = pr.head.sha.clone();
+    let tested_pr;
+    let pr = match &commit {
+        Some(commit) => {
+            let commits = repo.client.get_pull_request_commits(pr.number).await?;
+            if !commits.iter().any(|pr_commit| pr_commit.sha == *commit) {
+                tracing::warn!("Commit {commit} is not a part of the PR");
+                repo.client
+                    .post_comment(
+                        pr.number,
+                        &repo.config.messages.render(
+                            MessageKind::CommitNotInPullRequest,
+                            &repo.repository,
+                            pr.number,
+                            &[("sha", &commit.to_string())],
+                        ),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            tested_pr = PullRequest {
+                head: Branch {
+                    sha: commit.clone(),
+                    ..pr.head.clone()
+                },
+                ..pr.clone()
+            };
+            &tested_pr
+        }
+        None => pr,
+    };
+
     let pr_model = db
         .get_or_create_pull_request(repo.client.repository(), pr.number)
         .await?;
@@ -43,88 +164,294 @@ pub(super) async fn command_try_build<Client: RepositoryClient>(
     if let Some(ref build) = pr_model.try_build {
         if build.status == BuildStatus::Pending {
             tracing::warn!("Try build already in progress");
-            repo.client
-                .post_comment(
-                    pr.number,
-                    ":exclamation: A try build is currently in progress. You can cancel it using @bors try cancel.",
-                )
-                .await?;
+            let message = repo.config.messages.render(
+                MessageKind::TryInProgress,
+                &repo.repository,
+                pr.number,
+                &[("prefix", command_prefix(repo, ctx))],
+            );
+            repo.client.post_comment(pr.number, &message).await?;
             return Ok(());
         }
     }
 
-    let base_sha = parent.as_ref().unwrap_or(&pr.base.sha);
+    let args = TryBuildArgs {
+        pr_head_sha: Some(pr_head_sha),
+        parent,
+        commit,
+        jobs,
+    };
+    start_try_build(repo, db, ctx, pr, pr_model, args, 0).await
+}
 
-    // First set the try branch to our base commit (either the selected parent or the main branch).
-    repo.client
-        .set_branch_to_sha(TRY_MERGE_BRANCH_NAME, base_sha)
-        .await
-        .map_err(|error| {
-            let base = if let Some(ref parent) = parent {
-                parent.0.as_str()
-            } else {
-                "main branch"
-            };
-            anyhow!("Cannot set try merge branch to {base}: {error:?}")
-        })?;
+/// Merges the PR into the `parent` of `args` (or its base branch) on the try branch and starts CI
+/// on the merge commit. `attempt` is the number of automatic retries that preceded this build.
+async fn start_try_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+    pr: &PullRequest,
+    pr_model: PullRequestModel,
+    args: TryBuildArgs,
+    attempt: u32,
+) -> anyhow::Result<()> {
+    let base_sha = args.parent.as_ref().unwrap_or(&pr.base.sha);
+    let dispatch_workflow = match repo.config.try_trigger {
+        TryTrigger::WorkflowDispatch { ref workflow } => Some(workflow.clone()),
+        _ => None,
+    };
+    let message = auto_merge_commit_message(pr, "<try>");
+
+    if ctx.is_dry_run(&repo.config) {
+        tracing::info!("Dry run, the try build is not started");
+        let head_sha = pr.head.sha.to_string();
+        let base_sha = base_sha.to_string();
+        let message = match dispatch_workflow {
+            Some(ref workflow) => repo.config.messages.render(
+                MessageKind::DryRunTryDispatch,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("head_sha", &head_sha),
+                    ("base_sha", &base_sha),
+                    ("workflow", workflow),
+                ],
+            ),
+            None => repo.config.messages.render(
+                MessageKind::DryRunTry,
+                &repo.repository,
+                pr.number,
+                &[
+                    ("head_sha", &head_sha),
+                    ("base_sha", &base_sha),
+                    ("branch", TRY_BRANCH_NAME),
+                ],
+            ),
+        };
+        return repo.client.post_comment(pr.number, &message).await;
+    }
 
-    // Then merge the PR commit into the try branch
-    match repo
-        .client
-        .merge_branches(
-            TRY_MERGE_BRANCH_NAME,
-            &pr.head.sha,
-            &auto_merge_commit_message(pr, "<try>"),
-        )
-        .await
+    // A dispatched workflow checks out the merge commit itself, so it does not need the try branch
+    let ci_branch = match dispatch_workflow {
+        Some(_) => None,
+        None => Some(TRY_BRANCH_NAME),
+    };
+    match create_candidate_merge(
+        repo,
+        &try_merge_branch_name(pr.number),
+        ci_branch,
+        base_sha,
+        &pr.head.sha,
+        &message,
+    )
+    .await
     {
         Ok(merge_sha) => {
-            tracing::debug!("Merge successful, SHA: {merge_sha}");
-            // If the merge was succesful, then set the actual try branch that will run CI to the
-            // merged commit.
-            repo.client
-                .set_branch_to_sha(TRY_BRANCH_NAME, &merge_sha)
-                .await
-                .map_err(|error| anyhow!("Cannot set try branch to main branch: {error:?}"))?;
-
-            db.attach_try_build(pr_model, TRY_BRANCH_NAME.to_string(), merge_sha.clone())
-                .await?;
+            db.attach_try_build(
+                pr_model,
+                TRY_BRANCH_NAME.to_string(),
+                merge_sha.clone(),
+                attempt,
+                args.clone(),
+            )
+            .await?;
             tracing::info!("Try build started");
 
+            if let Some(workflow) = dispatch_workflow {
+                dispatch_try_workflow(repo, db, pr, &workflow, &merge_sha, args.jobs).await?;
+            } else if let Some((service, pipeline)) = repo.config.try_trigger.ci_pipeline() {
+                let request = CiBuildRequest {
+                    repository: &repo.repository,
+                    pr: pr.number,
+                    branch: TRY_BRANCH_NAME,
+                    commit_sha: &merge_sha,
+                    message: &message,
+                };
+                let started = match ctx.ci.get(service) {
+                    Some(ci) => ci.start_build(pipeline, &request).await,
+                    None => Err(anyhow::anyhow!("CI service {service} is not configured")),
+                };
+                attach_external_build(repo, db, service, &merge_sha, started).await?;
+            }
+
             handle_label_trigger(repo, pr.number, LabelTrigger::TryBuildStarted).await?;
 
-            repo.client
-                .post_comment(
+            notify(
+                repo,
+                Notification {
+                    event: NotificationEvent::BuildStarted,
+                    repository: repo.repository.clone(),
+                    pr: Some(pr.number),
+                    message: format!("Try build `{merge_sha}` started"),
+                },
+            )
+            .await;
+
+            if repo.config.verbosity.posts_progress_comments() {
+                let message = repo.config.messages.render(
+                    MessageKind::TryStarted,
+                    &repo.repository,
                     pr.number,
-                    &format!(
-                        ":hourglass: Trying commit {} with merge {merge_sha}…",
-                        pr.head.sha
-                    ),
-                )
-                .await?;
-            Ok(())
-        }
-        Err(MergeError::Conflict) => {
-            tracing::warn!("Merge conflict");
-            repo.client
-                .post_comment(pr.number, &merge_conflict_message(&pr.head.name))
-                .await?;
+                    &[
+                        ("head_sha", &pr.head.sha.to_string()),
+                        ("merge_sha", &merge_sha.to_string()),
+                    ],
+                );
+                post_status_comment(repo, db, pr.number, &message).await?;
+            }
             Ok(())
         }
-        Err(error) => Err(error.into()),
+        Err(error) => report_candidate_merge_error(repo, pr.number, &pr.head.name, error).await,
     }
 }
 
-pub(super) async fn command_try_cancel<Client: RepositoryClient>(
+/// Starts the given workflow for a try build and attaches its run to the build, so that the
+/// result of the run can be correlated with the build using its run ID.
+async fn dispatch_try_workflow<Client: RepositoryClient>(
     repo: &mut RepositoryState<Client>,
-    db: &mut dyn DbClient,
+    db: &dyn DbClient,
     pr: &PullRequest,
-    author: &GithubUser,
+    workflow: &str,
+    merge_sha: &CommitSha,
+    jobs: Option<String>,
+) -> anyhow::Result<()> {
+    let Some(build) = db
+        .find_build(
+            repo.client.repository(),
+            TRY_BRANCH_NAME.to_string(),
+            merge_sha.clone(),
+        )
+        .await?
+    else {
+        return Err(anyhow::anyhow!("Cannot find try build {merge_sha}"));
+    };
+
+    let mut inputs = HashMap::new();
+    inputs.insert("pr".to_string(), pr.number.to_string());
+    inputs.insert("merge_sha".to_string(), merge_sha.to_string());
+    if let Some(jobs) = jobs {
+        inputs.insert("jobs".to_string(), jobs);
+    }
+
+    // The workflow definition is taken from the base branch, the workflow itself should check out
+    // the merge commit.
+    match repo
+        .client
+        .dispatch_workflow(workflow, &pr.base.name, &inputs)
+        .await
+    {
+        Ok(run) => {
+            tracing::info!("Dispatched workflow {workflow} (run ID {})", run.run_id);
+            db.create_workflow(
+                &build,
+                workflow.to_string(),
+                run.url,
+                run.run_id,
+                WorkflowType::Github,
+                WorkflowStatus::Pending,
+            )
+            .await
+        }
+        Err(error) => {
+            db.update_build_status(&build, BuildStatus::Failure).await?;
+            Err(error)
+        }
+    }
+}
+
+/// Attaches a build started in an external CI service to the try build. If the build could not
+/// be started, the try build fails.
+async fn attach_external_build<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+    service: &str,
+    merge_sha: &CommitSha,
+    started: anyhow::Result<DispatchedWorkflow>,
 ) -> anyhow::Result<()> {
-    if !check_try_permissions(repo, pr, author).await? {
+    let Some(build) = db
+        .find_build(
+            repo.client.repository(),
+            TRY_BRANCH_NAME.to_string(),
+            merge_sha.clone(),
+        )
+        .await?
+    else {
+        return Err(anyhow::anyhow!("Cannot find try build {merge_sha}"));
+    };
+
+    match started {
+        Ok(run) => {
+            tracing::info!(
+                "Started {service} build {} (run ID {})",
+                run.url,
+                run.run_id
+            );
+            db.create_workflow(
+                &build,
+                service.to_string(),
+                run.url,
+                run.run_id,
+                WorkflowType::External,
+                WorkflowStatus::Pending,
+            )
+            .await
+        }
+        Err(error) => {
+            db.update_build_status(&build, BuildStatus::Failure).await?;
+            Err(error)
+        }
+    }
+}
+
+/// Starts a failed try build again with the same arguments, once the delay of its automatic retry
+/// has passed. Unless the build had an explicit parent, the PR is merged into the current state of
+/// its base branch again. The build is not retried if the PR has been closed or its head has
+/// changed since the build was started.
+pub(super) async fn retry_try_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+    build: BuildModel,
+) -> anyhow::Result<()> {
+    db.set_build_retry(&build, None).await?;
+
+    // If another try build has been started in the meantime, the PR is not found
+    let Some(pr_model) = db.find_pr_by_build(&build).await? else {
+        return Ok(());
+    };
+    let pr = repo.client.get_pull_request(pr_model.number).await?;
+    if !pr.open || build.args.pr_head_sha.as_ref() != Some(&pr.head.sha) {
+        tracing::info!(
+            "PR {} has been closed or updated since try build {}, it is not retried",
+            pr.number,
+            build.commit_sha
+        );
         return Ok(());
     }
+    tracing::info!(
+        "Retrying try build {} of PR {} (attempt {})",
+        build.commit_sha,
+        pr.number,
+        build.attempt + 1
+    );
+    let pr = match build.args.commit {
+        Some(ref commit) => PullRequest {
+            head: Branch {
+                sha: commit.clone(),
+                ..pr.head
+            },
+            ..pr
+        },
+        None => pr,
+    };
+    start_try_build(repo, db, ctx, &pr, pr_model, build.args, build.attempt + 1).await
+}
 
+pub(super) async fn command_try_cancel<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    pr: &PullRequest,
+) -> anyhow::Result<()> {
     let pr_number: PullRequestNumber = pr.number;
     let pr = db
         .get_or_create_pull_request(repo.client.repository(), pr_number)
@@ -132,7 +459,17 @@ pub(super) async fn command_try_cancel<Client: RepositoryClient>(
 
     let Some(build) = get_pending_build(pr) else {
         tracing::warn!("No build found");
-        repo.client.post_comment(pr_number, ":exclamation: There is currently no try build in progress.").await?;
+        repo.client
+            .post_comment(
+                pr_number,
+                &repo.config.messages.render(
+                    MessageKind::NoTryInProgress,
+                    &repo.repository,
+                    pr_number,
+                    &[],
+                ),
+            )
+            .await?;
         return Ok(());
     };
 
@@ -148,9 +485,11 @@ pub(super) async fn command_try_cancel<Client: RepositoryClient>(
 
     tracing::info!("Try build cancelled");
 
-    repo.client
-        .post_comment(pr_number, "Try build cancelled.")
-        .await?;
+    let message =
+        repo.config
+            .messages
+            .render(MessageKind::TryCancelled, &repo.repository, pr_number, &[]);
+    post_status_comment(repo, db, pr_number, &message).await?;
 
     Ok(())
 }
@@ -190,78 +529,22 @@ fn auto_merge_commit_message(pr: &PullRequest, reviewer: &str) -> String {
     )
 }
 
-fn merge_conflict_message(branch: &str) -> String {
-    format!(
-        r#":lock: Merge conflict
-
-This pull request and the master branch diverged in a way that cannot
- be automatically merged. Please rebase on top of the latest master
- branch, and let the reviewer approve again.
-
-<details><summary>How do I rebase?</summary>
-
-Assuming `self` is your fork and `upstream` is this repository,
- you can resolve the conflict following these steps:
-
-1. `git checkout {branch}` *(switch to your branch)*
-2. `git fetch upstream master` *(retrieve the latest master)*
-3. `git rebase upstream/master -p` *(rebase on top of it)*
-4. Follow the on-screen instruction to resolve conflicts (check `git status` if you got lost).
-5. `git push self {branch} --force-with-lease` *(update this PR)*
-
-You may also read
- [*Git Rebasing to Resolve Conflicts* by Drew Blessing](http://blessing.io/git/git-rebase/open-source/2015/08/23/git-rebasing-to-resolve-conflicts.html)
- for a short tutorial.
-
-Please avoid the ["**Resolve conflicts**" button](https://help.github.com/articles/resolving-a-merge-conflict-on-github/) on GitHub.
- It uses `git merge` instead of `git rebase` which makes the PR commit history more difficult to read.
-
-Sometimes step 4 will complete without asking for resolution. This is usually due to difference between how `Cargo.lock` conflict is
-handled during merge and rebase. This is normal, and you should still perform step 5 to update this PR.
-
-</details>  
-"#
-    )
-}
-
-async fn check_try_permissions<Client: RepositoryClient>(
-    repo: &mut RepositoryState<Client>,
-    pr: &PullRequest,
-    author: &GithubUser,
-) -> anyhow::Result<bool> {
-    let result = if !repo
-        .permissions_resolver
-        .has_permission(&author.username, PermissionType::Try)
-        .await
-    {
-        tracing::info!("Permission denied");
-        repo.client
-            .post_comment(
-                pr.number,
-                &format!(
-                    "@{}: :key: Insufficient privileges: not in try users",
-                    author.username
-                ),
-            )
-            .await?;
-        false
-    } else {
-        true
-    };
-    Ok(result)
-}
-
 #[cfg(test)]
 mod tests {
     use sea_orm::EntityTrait;
 
     use entity::workflow;
 
-    use crate::bors::handlers::trybuild::{TRY_BRANCH_NAME, TRY_MERGE_BRANCH_NAME};
+    use std::collections::HashMap;
+
+    use crate::bors::handlers::branches::{try_merge_branch_name, TRY_BRANCH_NAME};
+    use crate::bors::RepositoryClient;
+    use crate::config::{DraftPolicy, Feature, TryTrigger, Verbosity};
     use crate::database::{BuildStatus, DbClient, WorkflowStatus, WorkflowType};
-    use crate::github::{CommitSha, LabelTrigger, MergeError};
+    use crate::github::{BranchUpdateError, Commit, CommitSha, LabelTrigger, MergeError};
     use crate::tests::event::{
-        default_pr_number, suite_failure, suite_pending, suite_success, WorkflowStartedBuilder,
+        default_pr_number, suite_failure, suite_pending, suite_success, CheckSuiteCompletedBuilder,
+        WorkflowCompletedBuilder, WorkflowStartedBuilder,
     };
     use crate::tests::github::{BranchBuilder, PRBuilder};
     use crate::tests::permissions::NoPermissions;
@@ -300,6 +583,81 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_try_dry_run() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().dry_run(true))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .head(BranchBuilder::default().sha("head1".to_string()).create())
+                .base(BranchBuilder::default().sha("base1".to_string()).create())
+                .create())
+        });
+
+        state.comment("@bors try").await;
+
+        state.client().check_comments(
+            default_pr_number(),
+            &[":test_tube: **Dry run**: `head1` would now be merged into `base1` and tested on `automation/bors/try`, but no branches are changed in dry-run mode."],
+        );
+        let pr = state
+            .db
+            .find_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(pr.try_build.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_try_dry_run_workflow_dispatch() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().dry_run(true).try_trigger(
+                TryTrigger::WorkflowDispatch {
+                    workflow: "try.yml".to_string(),
+                },
+            ))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .head(BranchBuilder::default().sha("head1".to_string()).create())
+                .base(BranchBuilder::default().sha("base1".to_string()).create())
+                .create())
+        });
+
+        state.comment("@bors try").await;
+
+        state.client().check_comments(
+            default_pr_number(),
+            &[":test_tube: **Dry run**: `head1` would now be merged into `base1` and tested by the `try.yml` workflow, but no branches are changed and no workflows are started in dry-run mode."],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_quiet_skips_started_comment() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().verbosity(Verbosity::Quiet))
+            .create_state()
+            .await;
+
+        state.comment("@bors try").await;
+
+        state.client().check_comment_count(default_pr_number(), 0);
+        assert!(state
+            .db
+            .find_pull_request(&default_repo_name(), default_pr_number().into())
+            .await
+            .unwrap()
+            .unwrap()
+            .try_build
+            .is_some());
+    }
+
     #[tokio::test]
     async fn test_try_merge_branch_history() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -312,12 +670,52 @@ mod tests {
         });
 
         state.comment("@bors try").await;
+        state.client().check_branch_history(
+            &try_merge_branch_name(default_pr_number().into()),
+            &["main1", &default_merge_sha()],
+        );
         state
             .client()
-            .check_branch_history(TRY_MERGE_BRANCH_NAME, &["main1", &default_merge_sha()]);
+            .check_branch_history(TRY_BRANCH_NAME, &[&default_merge_sha()]);
+    }
+
+    #[tokio::test]
+    async fn test_try_merge_delete_merge_branch() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try").await;
         state
             .client()
-            .check_branch_history(TRY_BRANCH_NAME, &[&default_merge_sha()]);
+            .check_deleted_branches(&[try_merge_branch_name(default_pr_number().into()).as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_try_merge_conflict_delete_merge_branch() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().merge_branches_fn = Box::new(|| Err(MergeError::Conflict));
+        state.comment("@bors try").await;
+        state
+            .client()
+            .check_deleted_branches(&[try_merge_branch_name(default_pr_number().into()).as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_try_merge_branch_permission_denied() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().set_branch_fn = Box::new(|branch| {
+            if branch == TRY_BRANCH_NAME {
+                Err(BranchUpdateError::PermissionDenied(branch.to_string()))
+            } else {
+                Ok(())
+            }
+        });
+        state.comment("@bors try").await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":no_entry: Cannot update branch `automation/bors/try`, the bot does not have permission to push to it. Please check the branch protection settings of this repository.");
+        assert!(state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
     }
 
     #[tokio::test]
@@ -327,7 +725,7 @@ mod tests {
             .comment("@bors try parent=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
             .await;
         state.client().check_branch_history(
-            TRY_MERGE_BRANCH_NAME,
+            &try_merge_branch_name(default_pr_number().into()),
             &[
                 "ea9c1b050cc8b420c2c211d2177811e564a4dc60",
                 &default_merge_sha(),
@@ -335,6 +733,75 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_try_merge_explicit_commit() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().pr_commits.insert(
+            default_pr_number(),
+            vec![
+                pr_commit("ea9c1b050cc8b420c2c211d2177811e564a4dc60"),
+                pr_commit("pr-sha"),
+            ],
+        );
+        state
+            .comment("@bors try commit=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
+            .await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":hourglass: Trying commit ea9c1b050cc8b420c2c211d2177811e564a4dc60 with merge sha-merged…"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_merge_commit_outside_pr() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .pr_commits
+            .insert(default_pr_number(), vec![pr_commit("pr-sha")]);
+        state
+            .comment("@bors try commit=ea9c1b050cc8b420c2c211d2177811e564a4dc60")
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":exclamation: Commit ea9c1b050cc8b420c2c211d2177811e564a4dc60 is not a part of this pull request, only its commits can be tested.");
+        assert!(state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    fn pr_commit(sha: &str) -> Commit {
+        Commit {
+            sha: CommitSha(sha.to_string()),
+            message: "Commit message".to_string(),
+            author_name: "Foo Bar".to_string(),
+            author_email: "foo@bar.com".to_string(),
+            verified: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_try_unverified_merge_commit() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().require_verified_commits(true))
+            .create_state()
+            .await;
+        state
+            .client()
+            .unverified_commits
+            .insert(default_merge_sha());
+        state.comment("@bors try").await;
+
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":no_entry: The merge commit `sha-merged` does not have a verified signature, so it cannot be tested.");
+        assert!(state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_try_merge_conflict() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -400,6 +867,19 @@ mod tests {
         insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":exclamation: A try build is currently in progress. You can cancel it using @bors try cancel.");
     }
 
+    #[tokio::test]
+    async fn test_try_merge_active_build_command_prefix() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().command_prefix(Some("@ci".to_string())))
+            .create_state()
+            .await;
+
+        state.comment("@ci try").await;
+        state.comment("@ci try").await;
+
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @":exclamation: A try build is currently in progress. You can cancel it using @ci try cancel.");
+    }
+
     #[tokio::test]
     async fn test_try_again_after_checks_finish() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -647,4 +1127,191 @@ mod tests {
             .check_added_labels(default_pr_number(), &["foo", "bar"])
             .check_removed_labels(default_pr_number(), &["baz"]);
     }
+
+    #[tokio::test]
+    async fn test_try_draft() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).draft(true).create()));
+        state.comment("@bors try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":construction: This pull request is a draft, so it cannot be tested. Mark it as ready for review first."],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_blocking_label() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().blocking_labels(vec!["do-not-merge".to_string()]))
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .labels(vec!["do-not-merge".to_string()])
+                .create())
+        });
+        state.comment("@bors try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":hourglass: This pull request cannot be tested until the following blocking label(s) are removed: `do-not-merge`"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_draft_allowed() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().drafts(DraftPolicy::AllowTry))
+            .create_state()
+            .await;
+        state.client().get_pr_fn =
+            Box::new(|pr| Ok(PRBuilder::default().number(pr.0).draft(true).create()));
+        state.comment("@bors try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":hourglass: Trying commit pr-sha with merge sha-merged…"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_fork_disabled() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default()
+                    .features(toml::from_str("try_on_forks = false").unwrap()),
+            )
+            .create_state()
+            .await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .head_label("contributor:fix".to_string())
+                .create())
+        });
+        state.comment("@bors try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":no_entry: Try builds of pull requests from forks are disabled in this repository."],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_fork_enabled_by_default() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.client().get_pr_fn = Box::new(|pr| {
+            Ok(PRBuilder::default()
+                .number(pr.0)
+                .head_label("contributor:fix".to_string())
+                .create())
+        });
+        state.comment("@bors try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":hourglass: Trying commit pr-sha with merge sha-merged…"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_jobs_without_workflow_dispatch() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state.comment("@bors try jobs=linux").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[":exclamation: A job filter can only be used if try builds are started using a workflow dispatch."],
+        );
+        assert!(state.client().dispatched_workflows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_try_workflow_dispatch() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().try_trigger(TryTrigger::WorkflowDispatch {
+                    workflow: "try.yml".to_string(),
+                }),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors try jobs=linux").await;
+
+        let inputs = HashMap::from([
+            ("pr".to_string(), default_pr_number().to_string()),
+            ("merge_sha".to_string(), default_merge_sha()),
+            ("jobs".to_string(), "linux".to_string()),
+        ]);
+        assert_eq!(
+            state.client().dispatched_workflows,
+            vec![("try.yml".to_string(), "main-branch".to_string(), inputs)]
+        );
+        // The merge commit is kept on the merge branch instead of being pushed to the try branch
+        let merge_branch = try_merge_branch_name(default_pr_number().into());
+        assert_eq!(
+            state.client().get_branch_sha(&merge_branch).await.unwrap(),
+            Some(CommitSha(default_merge_sha()))
+        );
+        assert_eq!(
+            state
+                .client()
+                .get_branch_sha(TRY_BRANCH_NAME)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // The dispatched run is executed on the base branch
+        state
+            .workflow_completed(
+                WorkflowCompletedBuilder::default()
+                    .branch("main-branch".to_string())
+                    .commit_sha("main-sha".to_string())
+                    .run_id(1001)
+                    .status(WorkflowStatus::Success),
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :sunny: Try build successful
+        - [try.yml](https://try.yml-1001.com) :white_check_mark:
+        Build commit: sha-merged (`sha-merged`)
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_try_buildkite() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().try_trigger(TryTrigger::Buildkite {
+                    pipeline: "acme/app".to_string(),
+                }),
+            )
+            .create_state()
+            .await;
+        state.comment("@bors try").await;
+        assert_eq!(
+            *state.ci.builds.lock().unwrap(),
+            vec![("acme/app".to_string(), default_merge_sha())]
+        );
+
+        // Check suites of the try branch do not decide the result of the build
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+        state
+            .check_suite_completed(
+                CheckSuiteCompletedBuilder::default().branch(TRY_BRANCH_NAME.to_string()),
+            )
+            .await;
+        state
+            .workflow_completed(
+                WorkflowCompletedBuilder::default()
+                    .branch(TRY_BRANCH_NAME.to_string())
+                    .run_id(2001)
+                    .status(WorkflowStatus::Failure),
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :broken_heart: Test failed
+        - [buildkite](https://buildkite.com/acme/app/builds/2001) :x:
+        "###);
+    }
 }
@@ -1,11 +1,28 @@
-use crate::bors::event::{CheckSuiteCompleted, WorkflowCompleted, WorkflowStarted};
+use chrono::Utc;
+use octocrab::models::RunId;
+use tracing::{Instrument, Span};
+
+use crate::bors::event::{
+    BuildResultReported, CheckSuiteCompleted, WorkflowCompleted, WorkflowStarted,
+};
+use crate::bors::handlers::comments::{post_status_comment, update_summary_comment};
 use crate::bors::handlers::is_bors_observed_branch;
 use crate::bors::handlers::labels::handle_label_trigger;
-use crate::bors::{self, RepositoryClient, RepositoryState};
-use crate::database::{BuildStatus, DbClient, WorkflowStatus};
-use crate::github::LabelTrigger;
+use crate::bors::handlers::tree::update_tree_after_build;
+use crate::bors::{self, BorsContext, JobLog, RepositoryClient, RepositoryState, WorkflowLink};
+use crate::ci::stable_run_id;
+use crate::config::{workflow_matches, CiConfig, Feature};
+use crate::database::{
+    BuildModel, BuildStatus, DbClient, PullRequestModel, WorkflowModel, WorkflowStatus,
+    WorkflowType,
+};
+use crate::github::{CommitSha, LabelTrigger};
+use crate::log_excerpt::{code_fence, compile_anchors, extract_excerpt};
+use crate::messages::MessageKind;
+use crate::notifications::{notify, Notification, NotificationEvent};
 
-pub(super) async fn handle_workflow_started(
+pub(super) async fn handle_workflow_started<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
     db: &mut dyn DbClient,
     payload: WorkflowStarted,
 ) -> anyhow::Result<()> {
@@ -43,7 +60,7 @@ pub(super) async fn handle_workflow_started(
     )
     .await?;
 
-    Ok(())
+    update_build_progress(repo, db, &build).await
 }
 
 pub(super) async fn handle_workflow_completed<Client: RepositoryClient>(
@@ -54,14 +71,151 @@ pub(super) async fn handle_workflow_completed<Client: RepositoryClient>(
     tracing::info!("Updating status of workflow to {:?}", payload.status);
     db.update_workflow_status(*payload.run_id, payload.status)
         .await?;
+    let run_id = payload.run_id;
+
+    // Workflows started by a workflow dispatch do not run on a bors branch, their build is
+    // found using the run ID instead. Builds of external CI services are found in the same way,
+    // because their result does not depend on the check suites of the commit.
+    if !is_bors_observed_branch(&payload.branch) || repo.config.try_trigger.ci_pipeline().is_some()
+    {
+        try_complete_dispatched_build(repo, db, run_id).await?;
+    } else {
+        // Try to complete the build
+        let event = CheckSuiteCompleted {
+            repository: payload.repository,
+            branch: payload.branch,
+            commit_sha: payload.commit_sha,
+        };
+        try_complete_build(repo, db, event).await?;
+    }
+
+    // The summary comment of a completed build has already been updated
+    if repo.config.summary_comment {
+        if let Some(build) = db.find_build_by_workflow_run(run_id).await? {
+            if build.status == BuildStatus::Pending {
+                update_build_progress(repo, db, &build).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shows the state of the workflows of a running build in the summary comment of its PR.
+///
+/// Without `summary_comment_delay`, each workflow event would edit the comment, so the summary
+/// is then only updated when the build starts and completes.
+async fn update_build_progress<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    build: &BuildModel,
+) -> anyhow::Result<()> {
+    if !repo.config.summary_comment || repo.config.summary_comment_delay.is_zero() {
+        return Ok(());
+    }
+    match db.find_pr_by_build(build).await? {
+        Some(pr) => update_summary_comment(repo, db, pr.number).await,
+        None => Ok(()),
+    }
+}
+
+/// Stores a result reported by an external system as a workflow of the build. The first report
+/// of the system creates the workflow, a final result (success or failure) completes it.
+pub(super) async fn handle_build_result_reported<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    payload: BuildResultReported,
+) -> anyhow::Result<()> {
+    let Some(build) = db
+        .find_build_by_id(&repo.repository, payload.build_id)
+        .await?
+    else {
+        tracing::warn!("Build for reported result not found");
+        return Ok(());
+    };
+    if build.status != BuildStatus::Pending {
+        tracing::warn!("Received result for an already completed build");
+        return Ok(());
+    }
+
+    tracing::info!(
+        "Handling reported result (name={}, url={}, status={:?})",
+        payload.name,
+        payload.url,
+        payload.status
+    );
+    // Each system has its own workflow, so that repeated reports update it
+    let run_id = stable_run_id(&format!("{}/{}", build.id, payload.name));
+    let workflows = db.get_workflows_for_build(&build).await?;
+    if !workflows.iter().any(|w| w.run_id == run_id) {
+        db.create_workflow(
+            &build,
+            payload.name,
+            payload.url,
+            run_id,
+            WorkflowType::Reported,
+            WorkflowStatus::Pending,
+        )
+        .await?;
+    }
+    if !payload.artifacts.is_empty() {
+        db.set_workflow_artifacts(run_id, &payload.artifacts)
+            .await?;
+    }
+    if payload.status == WorkflowStatus::Pending {
+        return Ok(());
+    }
 
-    // Try to complete the build
-    let event = CheckSuiteCompleted {
-        repository: payload.repository,
-        branch: payload.branch,
-        commit_sha: payload.commit_sha,
+    let payload = WorkflowCompleted {
+        repository: repo.repository.clone(),
+        branch: build.branch.clone(),
+        commit_sha: CommitSha(build.commit_sha.clone()),
+        run_id,
+        status: payload.status,
     };
-    try_complete_build(repo, db, event).await
+    handle_workflow_completed(repo, db, payload).await
+}
+
+/// Polls the results of the pending builds of an external CI service that does not report them
+/// using webhooks. Finished builds are handled like completed workflows.
+pub(super) async fn poll_external_builds<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    ctx: &BorsContext,
+) -> anyhow::Result<()> {
+    let Some((service, pipeline)) = repo.config.try_trigger.ci_pipeline() else {
+        return Ok(());
+    };
+    let Some(ci) = ctx.ci.get(service) else {
+        return Ok(());
+    };
+    let pipeline = pipeline.to_string();
+
+    for build in db.get_running_builds(&repo.repository).await? {
+        let workflows = db.get_workflows_for_build(&build).await?;
+        for workflow in workflows.into_iter().filter(|w| {
+            w.workflow_type == WorkflowType::External
+                && w.status == WorkflowStatus::Pending
+                && w.name == service
+        }) {
+            let status = match ci.poll_build(&pipeline, &workflow.url).await {
+                Ok(Some(status)) => status,
+                Ok(None) => continue,
+                Err(error) => {
+                    tracing::warn!("Cannot poll {service} build {}: {error:?}", workflow.url);
+                    continue;
+                }
+            };
+            let payload = WorkflowCompleted {
+                repository: repo.repository.clone(),
+                branch: build.branch.clone(),
+                commit_sha: CommitSha(build.commit_sha.clone()),
+                run_id: workflow.run_id,
+                status,
+            };
+            handle_workflow_completed(repo, db, payload).await?;
+        }
+    }
+    Ok(())
 }
 
 pub(super) async fn handle_check_suite_completed<Client: RepositoryClient>(
@@ -74,10 +228,14 @@ pub(super) async fn handle_check_suite_completed<Client: RepositoryClient>(
         payload.branch,
         payload.commit_sha
     );
+    if let Some((service, _)) = repo.config.try_trigger.ci_pipeline() {
+        tracing::debug!("Ignoring check suite, try builds are run by {service}");
+        return Ok(());
+    }
     try_complete_build(repo, db, payload).await
 }
 
-async fn try_complete_build<Client: RepositoryClient>(
+pub(super) async fn try_complete_build<Client: RepositoryClient>(
     repo: &mut RepositoryState<Client>,
     db: &mut dyn DbClient,
     payload: CheckSuiteCompleted,
@@ -120,10 +278,99 @@ async fn try_complete_build<Client: RepositoryClient>(
         return Ok(());
     }
 
-    let has_failure = checks
+    let workflows = db.get_workflows_for_build(&build).await?;
+    let has_failure = if repo.config.ci.filters_workflows() {
+        try_workflows_failed(&repo.config.ci, &workflows)
+    } else {
+        // Reported results are not a part of the check suites of the commit
+        checks
+            .iter()
+            .any(|check| matches!(check.status, bors::CheckSuiteStatus::Failure))
+            || workflows.iter().any(|w| {
+                w.workflow_type == WorkflowType::Reported && w.status == WorkflowStatus::Failure
+            })
+    };
+
+    let span = build_span(&pr, &build);
+    complete_build(repo, db, build, pr, has_failure)
+        .instrument(span)
+        .await
+}
+
+async fn try_complete_dispatched_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    run_id: RunId,
+) -> anyhow::Result<()> {
+    let Some(build) = db.find_build_by_workflow_run(run_id).await? else {
+        return Ok(());
+    };
+    if build.status != BuildStatus::Pending {
+        return Ok(());
+    }
+
+    let Some(pr) = db.find_pr_by_build(&build).await? else {
+        tracing::warn!("Cannot find PR for build {}", build.commit_sha);
+        return Ok(());
+    };
+
+    // There are no check suites on the try branch, the result is decided only by the workflows
+    let workflows = db.get_workflows_for_build(&build).await?;
+    let has_failure = if repo.config.ci.filters_workflows() {
+        try_workflows_failed(&repo.config.ci, &workflows)
+    } else {
+        workflows
+            .iter()
+            .any(|w| w.status == WorkflowStatus::Failure)
+    };
+
+    let span = build_span(&pr, &build);
+    complete_build(repo, db, build, pr, has_failure)
+        .instrument(span)
+        .await
+}
+
+/// Decides whether a try build has failed using only the workflows that are relevant for try
+/// builds according to the CI configuration of the repository.
+fn try_workflows_failed(config: &CiConfig, workflows: &[WorkflowModel]) -> bool {
+    let missing = missing_try_workflows(config, workflows);
+    if !missing.is_empty() {
+        tracing::warn!("Try workflows {missing:?} have not been started");
+        return true;
+    }
+    workflows
         .iter()
-        .any(|check| matches!(check.status, bors::CheckSuiteStatus::Failure));
+        .filter(|w| config.is_try_workflow(&w.name))
+        .any(|w| w.status == WorkflowStatus::Failure)
+}
 
+/// Returns the configured try workflows that have not been started for the build.
+fn missing_try_workflows<'a>(config: &'a CiConfig, workflows: &[WorkflowModel]) -> Vec<&'a String> {
+    config
+        .try_workflows
+        .iter()
+        .filter(|pattern| !workflows.iter().any(|w| workflow_matches(pattern, &w.name)))
+        .collect()
+}
+
+/// Span of the completion of a build, nested in the span of the event that has completed it.
+fn build_span(pr: &PullRequestModel, build: &BuildModel) -> Span {
+    tracing::info_span!(
+        "Build",
+        pr = pr.number.0,
+        id = build.id,
+        sha = %build.commit_sha
+    )
+}
+
+/// Posts the result of the build to its PR once all of its workflows have finished.
+async fn complete_build<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &mut dyn DbClient,
+    build: BuildModel,
+    pr: PullRequestModel,
+    has_failure: bool,
+) -> anyhow::Result<()> {
     let mut workflows = db.get_workflows_for_build(&build).await?;
     workflows.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -138,40 +385,83 @@ async fn try_complete_build<Client: RepositoryClient>(
         return Ok(());
     }
 
-    let workflow_list = workflows
-        .into_iter()
-        .map(|w| {
-            format!(
-                "- [{}]({}) {}",
-                w.name,
-                w.url,
-                if w.status == WorkflowStatus::Success {
-                    ":white_check_mark:"
-                } else {
-                    ":x:"
-                }
-            )
-        })
-        .collect::<Vec<_>>()
-        .join("\n");
+    let mut workflow_list = Vec::with_capacity(workflows.len());
+    for workflow in &workflows {
+        let mut line = format_workflow(repo, workflow).await;
+        if !repo.config.ci.is_try_workflow(&workflow.name) {
+            // The first line is the link to the workflow itself
+            let end = line.find('\n').unwrap_or(line.len());
+            line.insert_str(end, " (informational)");
+        }
+        workflow_list.push(line);
+    }
+    // Missing try workflows fail the build, so they are listed to explain the failure
+    for missing in missing_try_workflows(&repo.config.ci, &workflows) {
+        workflow_list.push(format!("- `{missing}` :x: (not started)"));
+    }
+    let workflow_list = workflow_list.join("\n");
 
     let message = if !has_failure {
         tracing::info!("Workflow succeeded");
-
-        let sha = &payload.commit_sha;
-        format!(
-            r#":sunny: Try build successful
-{workflow_list}
-Build commit: {sha} (`{sha}`)"#
+        notify(
+            repo,
+            Notification {
+                event: NotificationEvent::BuildSucceeded,
+                repository: repo.repository.clone(),
+                pr: Some(pr.number),
+                message: format!("Try build `{}` succeeded", build.commit_sha),
+            },
         )
+        .await;
+
+        if repo.config.verbosity.posts_progress_comments() {
+            Some(repo.config.messages.render(
+                MessageKind::TrySucceeded,
+                &repo.repository,
+                pr.number,
+                &[("workflows", &workflow_list), ("sha", &build.commit_sha)],
+            ))
+        } else {
+            None
+        }
     } else {
         tracing::info!("Workflow failed");
-        format!(
-            r#":broken_heart: Test failed
-{workflow_list}"#
+        let failed_workflows: Vec<String> = workflows
+            .iter()
+            .filter(|workflow| workflow.status == WorkflowStatus::Failure)
+            .map(|workflow| format!("[{}]({})", workflow.name, workflow.url))
+            .collect();
+        notify(
+            repo,
+            Notification {
+                event: NotificationEvent::BuildFailed,
+                repository: repo.repository.clone(),
+                pr: Some(pr.number),
+                message: format!(
+                    "Try build `{}` failed: {}",
+                    build.commit_sha,
+                    failed_workflows.join(", ")
+                ),
+            },
         )
+        .await;
+        let logs = store_log_excerpts(repo, db, &workflows).await;
+        let retry = schedule_retry(repo, db, &build).await?;
+        let approved = approved_failure_note(repo, &pr).await?;
+        Some(format!(
+            "{}{}{retry}{approved}",
+            repo.config.messages.render(
+                MessageKind::TryFailed,
+                &repo.repository,
+                pr.number,
+                &[("workflows", &workflow_list)]
+            ),
+            format_log_excerpts(&logs)
+        ))
     };
-    repo.client.post_comment(pr.number, &message).await?;
+    if let Some(message) = message {
+        post_status_comment(repo, db, pr.number, &message).await?;
+    }
 
     let (status, trigger) = if has_failure {
         (BuildStatus::Failure, LabelTrigger::TryBuildFailed)
@@ -179,9 +469,195 @@ Build commit: {sha} (`{sha}`)"#
         (BuildStatus::Success, LabelTrigger::TryBuildSucceeded)
     };
     db.update_build_status(&build, status).await?;
-
+    // The result of the build is already stored, closing the tree is not essential
+    if let Err(error) = update_tree_after_build(repo, db, &build, !has_failure).await {
+        tracing::error!(
+            "Could not update the tree after build {}: {error:?}",
+            build.commit_sha
+        );
+    }
     handle_label_trigger(repo, pr.number, trigger).await?;
-    Ok(())
+    if !has_failure {
+        if let Some(label) = repo.config.approved_failure_label.clone() {
+            repo.client.remove_labels(pr.number, &[label]).await?;
+        }
+    }
+    update_summary_comment(repo, db, pr.number).await
+}
+
+/// If the PR of a failed build is approved, mentions its author and approver, so that the PR does
+/// not silently stay in the queue without being merged, and adds the configured label to it.
+/// Returns the mention for the build result comment.
+async fn approved_failure_note<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    pr: &PullRequestModel,
+) -> anyhow::Result<String> {
+    let Some(approver) = &pr.approved_by else {
+        return Ok(String::new());
+    };
+    let author = repo.client.get_pull_request(pr.number).await?.author;
+    if let Some(label) = repo.config.approved_failure_label.clone() {
+        tracing::info!("Adding label {label} to failed approved PR");
+        repo.client.add_labels(pr.number, &[label]).await?;
+    }
+    Ok(format!(
+        "\n{}",
+        repo.config.messages.render(
+            MessageKind::ApprovedFailed,
+            &repo.repository,
+            pr.number,
+            &[("author", &author), ("approver", approver)],
+        )
+    ))
+}
+
+/// Schedules an automatic retry of a failed build, if the retry policy of the repository allows
+/// it. Returns a note about the retry for the build result comment.
+async fn schedule_retry<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+    build: &BuildModel,
+) -> anyhow::Result<String> {
+    let policy = &repo.config.retry;
+    if !repo.config.features.is_enabled(Feature::AutoRetry) || build.attempt >= policy.max_retries {
+        return Ok(String::new());
+    }
+    let delay = policy.delay(build.attempt);
+    let retry_at = Utc::now() + chrono::Duration::from_std(delay)?;
+    db.set_build_retry(build, Some(retry_at)).await?;
+    tracing::info!(
+        "Retry of build {} scheduled at {retry_at}",
+        build.commit_sha
+    );
+    Ok(format!(
+        "\n:repeat: The build will be retried automatically in {} minute(s) (retry {} of {}).",
+        (delay.as_secs() + 59) / 60,
+        build.attempt + 1,
+        policy.max_retries
+    ))
+}
+
+/// Formats a line with a link to the workflow, followed by links to its failed jobs and artifacts.
+async fn format_workflow<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    workflow: &WorkflowModel,
+) -> String {
+    let mut text = format!(
+        "- [{}]({}) {}",
+        workflow.name,
+        workflow.url,
+        if workflow.status == WorkflowStatus::Success {
+            ":white_check_mark:"
+        } else {
+            ":x:"
+        }
+    );
+    if workflow.workflow_type != WorkflowType::Github {
+        if !workflow.artifacts.is_empty() {
+            let artifacts: Vec<String> = workflow
+                .artifacts
+                .iter()
+                .map(|url| format!("<{url}>"))
+                .collect();
+            text.push_str(&format!("\n  - Artifacts: {}", artifacts.join(", ")));
+        }
+        return text;
+    }
+
+    // The links are only informative, so the result is posted even if they cannot be loaded
+    let details = match repo.client.get_workflow_run_details(workflow.run_id).await {
+        Ok(details) => details,
+        Err(error) => {
+            tracing::warn!(
+                "Cannot load details of workflow run {}: {error:?}",
+                workflow.run_id
+            );
+            return text;
+        }
+    };
+    if !details.failed_jobs.is_empty() {
+        text.push_str(&format!(
+            "\n  - Failed jobs: {}",
+            format_links(&details.failed_jobs)
+        ));
+    }
+    if !details.artifacts.is_empty() {
+        text.push_str(&format!(
+            "\n  - Artifacts: {}",
+            format_links(&details.artifacts)
+        ));
+    }
+    text
+}
+
+/// Stores the ends of the logs of the failed jobs of the given workflows, if the repository has
+/// enabled it, and returns them.
+async fn store_log_excerpts<Client: RepositoryClient>(
+    repo: &mut RepositoryState<Client>,
+    db: &dyn DbClient,
+    workflows: &[WorkflowModel],
+) -> Vec<JobLog> {
+    let Some(config) = repo.config.log_excerpts.clone() else {
+        return vec![];
+    };
+    let anchors = compile_anchors(&config.anchors);
+
+    let mut logs = vec![];
+    for workflow in workflows.iter().filter(|workflow| {
+        workflow.status == WorkflowStatus::Failure && workflow.workflow_type == WorkflowType::Github
+    }) {
+        let job_logs = match repo
+            .client
+            .get_failed_job_logs(workflow.run_id, config.max_size.max(config.search_size))
+            .await
+        {
+            Ok(job_logs) => job_logs,
+            Err(error) => {
+                tracing::warn!(
+                    "Cannot load job logs of workflow run {}: {error:?}",
+                    workflow.run_id
+                );
+                continue;
+            }
+        };
+        for mut log in job_logs {
+            log.excerpt = extract_excerpt(&log.excerpt, &anchors, config.max_size).to_string();
+            if let Err(error) = db
+                .store_job_log(
+                    &repo.repository,
+                    workflow.run_id,
+                    &log.job_name,
+                    &log.excerpt,
+                )
+                .await
+            {
+                tracing::warn!("Cannot store log of job {}: {error:?}", log.job_name);
+            }
+            logs.push(log);
+        }
+    }
+    logs
+}
+
+fn format_log_excerpts(logs: &[JobLog]) -> String {
+    logs.iter()
+        .map(|log| {
+            let excerpt = log.excerpt.trim_end();
+            let fence = code_fence(excerpt);
+            format!(
+                "\n<details><summary>End of the log of `{}`</summary>\n\n{fence}\n{excerpt}\n{fence}\n\n</details>",
+                log.job_name,
+            )
+        })
+        .collect()
+}
+
+fn format_links(links: &[WorkflowLink]) -> String {
+    links
+        .iter()
+        .map(|link| format!("[{}]({})", link.name, link.url))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[cfg(test)]
@@ -192,13 +668,22 @@ mod tests {
 
     use entity::workflow;
 
-    use crate::bors::handlers::trybuild::TRY_BRANCH_NAME;
-    use crate::database::WorkflowStatus;
+    use crate::bors::handlers::branches::TRY_BRANCH_NAME;
+    use octocrab::models::RunId;
+
+    use crate::bors::event::{BorsEvent, BuildResultReported};
+    use crate::bors::{JobLog, WorkflowLink, WorkflowRunDetails};
+    use crate::config::{CiConfig, LogExcerptsConfig, Verbosity};
+    use crate::database::{DbClient, WorkflowStatus};
+    use crate::notifications::{NotificationEvent, NotificationRoute};
     use crate::tests::event::{
         default_pr_number, suite_failure, suite_pending, suite_success, CheckSuiteCompletedBuilder,
         WorkflowCompletedBuilder, WorkflowStartedBuilder,
     };
-    use crate::tests::state::{default_merge_sha, ClientBuilder};
+    use crate::tests::notifications::RecordingNotifier;
+    use crate::tests::state::{
+        default_merge_sha, default_repo_name, ClientBuilder, RepoConfigBuilder,
+    };
 
     #[tokio::test]
     async fn test_unknown_build() {
@@ -299,6 +784,88 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_try_failure_log_excerpt() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().log_excerpts(Some(LogExcerptsConfig::default())))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.client().job_logs.insert(
+            1,
+            vec![JobLog {
+                job_name: "test".to_string(),
+                excerpt: "error: assertion failed\n".to_string(),
+            }],
+        );
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @r###"
+        :broken_heart: Test failed
+        - [workflow-1](https://workflow-1.com) :x:
+        <details><summary>End of the log of `test`</summary>
+
+        ```
+        error: assertion failed
+        ```
+
+        </details>
+        "###
+        );
+        let logs = state.db.get_job_logs(RunId(1)).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].job_name, "test");
+    }
+
+    #[tokio::test]
+    async fn test_try_failure_log_excerpt_anchor() {
+        let mut state = ClientBuilder::default()
+            .config(
+                RepoConfigBuilder::default().log_excerpts(Some(LogExcerptsConfig {
+                    anchors: vec!["^error".to_string()],
+                    ..Default::default()
+                })),
+            )
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.client().job_logs.insert(
+            1,
+            vec![JobLog {
+                job_name: "test".to_string(),
+                excerpt: "Compiling foo\nerror: mismatched types\nCompiling bar\nerror: could not compile `bar`\n".to_string(),
+            }],
+        );
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+
+        let logs = state.db.get_job_logs(RunId(1)).await.unwrap();
+        assert_eq!(logs[0].excerpt, "error: could not compile `bar`\n");
+    }
+
     #[tokio::test]
     async fn test_try_failure() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -325,6 +892,214 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_approved_try_failure_mentions_author_and_approver() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().approved_failure_label(Some("failed".to_string())))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+
+        state.comment("@bors r+").await;
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @r###"
+        :broken_heart: Test failed
+        - [workflow-1](https://workflow-1.com) :x:
+        :bell: @<user> @<user>, this pull request is approved, but its build has failed, so it will not be merged until the failure is fixed.
+        "###
+        );
+        state
+            .client()
+            .check_added_labels(default_pr_number(), &["failed"]);
+    }
+
+    #[tokio::test]
+    async fn test_try_success_removes_approved_failure_label() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().approved_failure_label(Some("failed".to_string())))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+
+        state
+            .client()
+            .check_removed_labels(default_pr_number(), &["failed"]);
+    }
+
+    #[tokio::test]
+    async fn test_try_success_verbose_notification() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().verbosity(Verbosity::Verbose))
+            .notifications(vec![NotificationRoute {
+                events: NotificationEvent::ALL.to_vec(),
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+
+        assert_eq!(
+            notifier.events(),
+            vec![
+                NotificationEvent::BuildStarted,
+                NotificationEvent::BuildSucceeded
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_success_quiet() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().verbosity(Verbosity::Quiet))
+            .notifications(vec![NotificationRoute {
+                events: NotificationEvent::ALL.to_vec(),
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+
+        state.client().check_comment_count(default_pr_number(), 0);
+        assert!(notifier.events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_try_failure_notification() {
+        let notifier = RecordingNotifier::default();
+        let mut state = ClientBuilder::default()
+            .notifications(vec![NotificationRoute {
+                events: vec![NotificationEvent::BuildFailed],
+                notifier: Box::new(notifier.clone()),
+            }])
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+
+        let notifications = notifier.notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(
+            notifications[0].pr.map(|pr| pr.0),
+            Some(default_pr_number())
+        );
+        assert_eq!(
+            notifications[0].message,
+            format!(
+                "Try build `{}` failed: [workflow-1](https://workflow-1.com)",
+                default_merge_sha()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_failure_job_and_artifact_links() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.client().workflow_run_details.insert(
+            1,
+            WorkflowRunDetails {
+                failed_jobs: vec![
+                    WorkflowLink {
+                        name: "test".to_string(),
+                        url: "https://job-1.com".to_string(),
+                    },
+                    WorkflowLink {
+                        name: "lint".to_string(),
+                        url: "https://job-2.com".to_string(),
+                    },
+                ],
+                artifacts: vec![WorkflowLink {
+                    name: "binaries".to_string(),
+                    url: "https://artifact-1.com".to_string(),
+                }],
+            },
+        );
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+
+        insta::assert_snapshot!(
+            state.client().get_last_comment(default_pr_number()),
+            @r###"
+        :broken_heart: Test failed
+        - [workflow-1](https://workflow-1.com) :x:
+          - Failed jobs: [test](https://job-1.com), [lint](https://job-2.com)
+          - Artifacts: [binaries](https://artifact-1.com)
+        "###
+        );
+    }
+
     #[tokio::test]
     async fn test_try_success_multiple_suites() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -362,6 +1137,76 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_try_informational_workflow_failure() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().ci(CiConfig {
+                try_workflows: vec![],
+                informational_workflows: vec!["workflow-2".to_string()],
+            }))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success(), suite_pending()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success(), suite_failure()]);
+        state
+            .perform_workflow_events(
+                2,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Failure,
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :sunny: Try build successful
+        - [workflow-1](https://workflow-1.com) :white_check_mark:
+        - [workflow-2](https://workflow-2.com) :x: (informational)
+        Build commit: sha-merged (`sha-merged`)
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_try_missing_try_workflow() {
+        let mut state = ClientBuilder::default()
+            .config(RepoConfigBuilder::default().ci(CiConfig {
+                try_workflows: vec!["workflow-1".to_string(), "workflow-3".to_string()],
+                informational_workflows: vec![],
+            }))
+            .create_state()
+            .await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+
+        state.comment("@bors try").await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :broken_heart: Test failed
+        - [workflow-1](https://workflow-1.com) :white_check_mark:
+        - `workflow-3` :x: (not started)
+        "###);
+    }
+
     #[tokio::test]
     async fn test_try_failure_multiple_suites() {
         let mut state = ClientBuilder::default().create_state().await;
@@ -448,4 +1293,54 @@ mod tests {
         state.check_suite_completed(event()).await;
         state.client().check_comment_count(default_pr_number(), 2);
     }
+
+    #[tokio::test]
+    async fn test_try_reported_result() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_success()]);
+        state.comment("@bors try").await;
+        let build = state
+            .db
+            .get_running_builds(&default_repo_name())
+            .await
+            .unwrap()
+            .remove(0);
+        let report = |status: WorkflowStatus, artifacts: Vec<String>| {
+            BorsEvent::BuildResultReported(BuildResultReported {
+                repository: default_repo_name(),
+                build_id: build.id,
+                name: "hw-lab".to_string(),
+                url: "https://lab.example.com/runs/42".to_string(),
+                status,
+                artifacts,
+            })
+        };
+
+        // The pending result holds the build after the check suites have finished
+        state.event(report(WorkflowStatus::Pending, vec![])).await;
+        state
+            .perform_workflow_events(
+                1,
+                TRY_BRANCH_NAME,
+                &default_merge_sha(),
+                WorkflowStatus::Success,
+            )
+            .await;
+        state.client().check_comment_count(default_pr_number(), 1);
+
+        state
+            .event(report(
+                WorkflowStatus::Failure,
+                vec!["https://lab.example.com/runs/42/report.html".to_string()],
+            ))
+            .await;
+        insta::assert_snapshot!(state.client().get_last_comment(default_pr_number()), @r###"
+        :broken_heart: Test failed
+        - [hw-lab](https://lab.example.com/runs/42) :x:
+          - Artifacts: <https://lab.example.com/runs/42/report.html>
+        - [workflow-1](https://workflow-1.com) :white_check_mark:
+        "###);
+    }
 }
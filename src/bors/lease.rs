@@ -0,0 +1,112 @@
+//! Allows running several replicas of the bot against a single database. Every replica handles
+//! webhooks and serves the API, but only the replica that holds the lease of a repository
+//! periodically refreshes and reconciles it, so that its merge queue is driven by a single replica.
+//! A replica that stops renewing its leases (e.g. because it has crashed) loses them once they
+//! expire, and another replica takes over its repositories.
+use crate::bors::{BorsContext, RepositoryClient, RepositoryState};
+use crate::database::DbClient;
+
+/// Identity of a replica and the duration of the leases it acquires.
+#[derive(Debug, Clone)]
+pub struct LeaseConfig {
+    /// Identifier of the replica, which has to be unique among all replicas.
+    pub holder: String,
+    /// Leases have to be renewed before they expire, so this should be a multiple of the interval
+    /// of the periodic refresh.
+    pub duration: chrono::Duration,
+}
+
+/// Acquires or renews the lease of the given repository. Returns `true` if the repository should
+/// be driven by this replica, which is always the case if leases are disabled.
+pub async fn holds_lease<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+) -> bool {
+    let Some(lease) = &ctx.lease else {
+        return true;
+    };
+    match db
+        .acquire_repository_lease(&repo.repository, &lease.holder, lease.duration)
+        .await
+    {
+        Ok(true) => true,
+        Ok(false) => {
+            tracing::debug!("Repository is leased by another replica");
+            false
+        }
+        Err(error) => {
+            tracing::warn!("Cannot acquire the lease of the repository: {error:?}");
+            false
+        }
+    }
+}
+
+/// Releases the lease of the given repository, so that another replica can take it over without
+/// waiting for the lease to expire.
+pub async fn release_lease<Client: RepositoryClient>(
+    repo: &RepositoryState<Client>,
+    db: &dyn DbClient,
+    ctx: &BorsContext,
+) {
+    let Some(lease) = &ctx.lease else {
+        return;
+    };
+    if let Err(error) = db
+        .release_repository_lease(&repo.repository, &lease.holder)
+        .await
+    {
+        tracing::warn!("Cannot release the lease of the repository: {error:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::event::BorsEvent;
+    use crate::bors::lease::LeaseConfig;
+    use crate::database::DbClient;
+    use crate::github::mock::{MockGithub, MockPullRequest};
+    use crate::github::GithubRepoName;
+    use crate::tests::database::create_test_db;
+
+    fn lease(holder: &str) -> LeaseConfig {
+        LeaseConfig {
+            holder: holder.to_string(),
+            duration: chrono::Duration::minutes(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_only_leased_repositories() {
+        let repo = GithubRepoName::new("owner", "name");
+        let mock = MockGithub::start().await.unwrap();
+        mock.add_repository(&repo, "build_timeout = 3600");
+        mock.add_pull_request(&repo, MockPullRequest::new(1, "pr-sha"));
+        let db = create_test_db().await;
+        db.acquire_repository_lease(&repo, "other", chrono::Duration::minutes(5))
+            .await
+            .unwrap();
+
+        let mut state = mock.create_state(db).await.unwrap();
+        state.set_lease(lease("replica"));
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors try"),
+            )
+            .await
+            .unwrap();
+        // Webhooks are handled by all replicas, but the build only times out in the replica that
+        // holds the lease
+        mock.faults().skew_clock(chrono::Duration::hours(2));
+        state.event(BorsEvent::Refresh).await.unwrap();
+        assert_eq!(mock.comments(&repo, 1).len(), 1);
+
+        state.set_lease(lease("other"));
+        state.event(BorsEvent::Refresh).await.unwrap();
+        assert_eq!(
+            mock.comments(&repo, 1).last().unwrap(),
+            ":boom: Test timed out"
+        );
+    }
+}
@@ -1,22 +1,31 @@
 use crate::config::RepositoryConfig;
 use axum::async_trait;
 use octocrab::models::RunId;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::future::Future;
 use std::pin::Pin;
 
-use crate::github::{CommitSha, GithubRepoName, MergeError, PullRequest, PullRequestNumber};
+use crate::agreement::AgreementChecker;
+use crate::github::{
+    BranchUpdateError, CheckAnnotation, CommentId, Commit, CommitSha, GithubRepoName, MergeError,
+    Milestone, PullRequest, PullRequestNumber,
+};
+use crate::notifications::NotificationRoute;
 use crate::permissions::PermissionResolver;
 
 mod command;
 mod context;
+pub mod degraded;
 pub mod event;
 mod handlers;
+pub mod lease;
+pub mod shard;
 
 use crate::bors::event::PullRequestComment;
 use crate::database::DbClient;
 pub use command::CommandParser;
 pub use context::BorsContext;
-pub use handlers::handle_bors_event;
+pub use handlers::{handle_bors_event, handle_shutdown, AUTO_BRANCH_NAME};
 
 /// Provides functionality for working with a remote repository.
 #[async_trait]
@@ -26,11 +35,69 @@ pub trait RepositoryClient {
     /// Resolve a pull request from this repository by it's number.
     async fn get_pull_request(&mut self, pr: PullRequestNumber) -> anyhow::Result<PullRequest>;
 
+    /// Find the paths of all files modified by a pull request.
+    async fn get_pull_request_files(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<String>>;
+
+    /// Find all commits of a pull request.
+    async fn get_pull_request_commits(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<Commit>>;
+
+    /// Does the commit with the given `sha` have a verified signature?
+    async fn is_commit_verified(&mut self, sha: &CommitSha) -> anyhow::Result<bool>;
+
     /// Post a comment to the pull request with the given number.
-    async fn post_comment(&mut self, pr: PullRequestNumber, text: &str) -> anyhow::Result<()>;
+    async fn post_comment(
+        &mut self,
+        pr: PullRequestNumber,
+        text: &str,
+    ) -> anyhow::Result<CommentId>;
+
+    /// Replace the text of an existing comment.
+    async fn edit_comment(&mut self, comment: &CommentId, text: &str) -> anyhow::Result<()>;
+
+    /// Hide an existing comment as outdated.
+    async fn minimize_comment(&mut self, comment: &CommentId) -> anyhow::Result<()>;
+
+    /// Post a comment to the commit with the given `sha`.
+    async fn post_commit_comment(&mut self, sha: &CommitSha, text: &str) -> anyhow::Result<()>;
+
+    /// Create a completed check run with the given result on the commit with the given `sha`.
+    async fn create_check_run(
+        &mut self,
+        sha: &CommitSha,
+        name: &str,
+        success: bool,
+        summary: &str,
+        annotations: &[CheckAnnotation],
+    ) -> anyhow::Result<()>;
+
+    /// Load the content of the file at `path` in the commit with the given `sha`.
+    /// Returns `None` if the file does not exist.
+    async fn get_file_content(
+        &mut self,
+        sha: &CommitSha,
+        path: &str,
+    ) -> anyhow::Result<Option<String>>;
 
     /// Set the given branch to a commit with the given `sha`.
-    async fn set_branch_to_sha(&mut self, branch: &str, sha: &CommitSha) -> anyhow::Result<()>;
+    /// If the branch does not exist yet, it will be created.
+    async fn set_branch_to_sha(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError>;
+
+    /// Delete the given branch. Deleting a branch that does not exist is not an error.
+    async fn delete_branch(&mut self, branch: &str) -> anyhow::Result<()>;
+
+    /// Find the commit to which the given branch points.
+    /// Returns `None` if the branch does not exist.
+    async fn get_branch_sha(&mut self, branch: &str) -> anyhow::Result<Option<CommitSha>>;
 
     /// Merge `head` into `base`. Returns the SHA of the merge commit.
     async fn merge_branches(
@@ -47,9 +114,32 @@ pub trait RepositoryClient {
         sha: &CommitSha,
     ) -> anyhow::Result<Vec<CheckSuite>>;
 
+    /// Find the failed jobs and uploaded artifacts of a Github Actions workflow run.
+    async fn get_workflow_run_details(
+        &mut self,
+        run_id: RunId,
+    ) -> anyhow::Result<WorkflowRunDetails>;
+
+    /// Find the logs of the failed jobs of a Github Actions workflow run, split into the failed
+    /// steps of the jobs where possible. Only the last `max_size` bytes of each log are returned.
+    async fn get_failed_job_logs(
+        &mut self,
+        run_id: RunId,
+        max_size: usize,
+    ) -> anyhow::Result<Vec<JobLog>>;
+
     /// Cancels Github Actions workflows.
     async fn cancel_workflows(&mut self, run_ids: Vec<RunId>) -> anyhow::Result<()>;
 
+    /// Start a run of the given GitHub Actions workflow on `git_ref` using a workflow dispatch
+    /// event with the given inputs.
+    async fn dispatch_workflow(
+        &mut self,
+        workflow: &str,
+        git_ref: &str,
+        inputs: &HashMap<String, String>,
+    ) -> anyhow::Result<DispatchedWorkflow>;
+
     /// Add a set of labels to a PR.
     async fn add_labels(&mut self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()>;
 
@@ -59,6 +149,27 @@ pub trait RepositoryClient {
         pr: PullRequestNumber,
         labels: &[String],
     ) -> anyhow::Result<()>;
+
+    /// Add a PR to the native merge queue of GitHub.
+    async fn add_to_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()>;
+
+    /// Remove a PR from the native merge queue of GitHub.
+    async fn remove_from_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()>;
+
+    /// Configure branch protection of the given branch, so that only the bot can push to it.
+    /// The branch can also be a pattern, in which `*` matches any part of a branch name, to protect
+    /// branches that do not exist yet.
+    async fn protect_branch(
+        &mut self,
+        branch: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()>;
+
+    /// Find all open milestones of the repository.
+    async fn get_open_milestones(&mut self) -> anyhow::Result<Vec<Milestone>>;
+
+    /// Assign a PR to the milestone with the given number.
+    async fn set_milestone(&mut self, pr: PullRequestNumber, milestone: u64) -> anyhow::Result<()>;
 }
 
 #[derive(Clone)]
@@ -75,6 +186,45 @@ pub struct CheckSuite {
     pub(crate) status: CheckSuiteStatus,
 }
 
+/// A link to some part of a workflow run (a job or an artifact).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkflowLink {
+    pub name: String,
+    pub url: String,
+}
+
+/// Information about a finished Github Actions workflow run that is useful for users.
+#[derive(Clone, Debug, Default)]
+pub struct WorkflowRunDetails {
+    pub failed_jobs: Vec<WorkflowLink>,
+    pub artifacts: Vec<WorkflowLink>,
+}
+
+/// The end of the log of a failed job of a Github Actions workflow run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobLog {
+    pub job_name: String,
+    pub excerpt: String,
+}
+
+/// Protection rules of a branch to which only the bot can push.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BranchProtection {
+    /// Checks that have to pass before a commit can be pushed to the branch.
+    pub required_checks: Vec<String>,
+    /// Branches used by bors for testing are reset using force-pushes.
+    pub allow_force_pushes: bool,
+    /// Temporary branches of bors are deleted once they are not needed anymore.
+    pub allow_deletions: bool,
+}
+
+/// A workflow run started by a workflow dispatch event.
+#[derive(Clone, Debug)]
+pub struct DispatchedWorkflow {
+    pub run_id: RunId,
+    pub url: String,
+}
+
 /// Main state holder for the bot.
 /// It is behind a trait to allow easier mocking in tests.
 pub trait BorsState<Client: RepositoryClient> {
@@ -101,5 +251,59 @@ pub struct RepositoryState<Client: RepositoryClient> {
     pub repository: GithubRepoName,
     pub client: Client,
     pub permissions_resolver: Box<dyn PermissionResolver>,
+    /// Only present if the repository requires a contributor agreement.
+    pub agreement_checker: Option<Box<dyn AgreementChecker>>,
+    /// Where are notifications about events of the repository sent.
+    pub notifications: Vec<NotificationRoute>,
     pub config: RepositoryConfig,
+    /// Updates of summary and status comments that have been postponed by
+    /// `summary_comment_delay`.
+    pub deferred_updates: DeferredUpdates,
+}
+
+/// Comments that should be updated once the delay of the repository has elapsed.
+#[derive(Default)]
+pub struct DeferredUpdates {
+    /// PRs whose summary comment should be updated.
+    prs: BTreeSet<u64>,
+    /// The latest status comment of each PR. Earlier status comments that have not been posted
+    /// yet are replaced by it.
+    status_comments: BTreeMap<u64, String>,
+    /// Has the event that posts the updates already been scheduled?
+    scheduled: bool,
+}
+
+impl DeferredUpdates {
+    pub fn add(&mut self, pr: PullRequestNumber) {
+        self.prs.insert(pr.0);
+    }
+
+    pub fn add_status_comment(&mut self, pr: PullRequestNumber, text: &str) {
+        self.status_comments.insert(pr.0, text.to_string());
+    }
+
+    /// Returns `true` if there are postponed updates for which no event has been scheduled yet,
+    /// and marks them as scheduled.
+    pub fn schedule(&mut self) -> bool {
+        if (self.prs.is_empty() && self.status_comments.is_empty()) || self.scheduled {
+            return false;
+        }
+        self.scheduled = true;
+        true
+    }
+
+    /// Takes all postponed updates out: the PRs whose summary comment should be updated and the
+    /// status comments that should be posted.
+    pub fn take(&mut self) -> (Vec<PullRequestNumber>, Vec<(PullRequestNumber, String)>) {
+        self.scheduled = false;
+        let prs = std::mem::take(&mut self.prs)
+            .into_iter()
+            .map(PullRequestNumber)
+            .collect();
+        let status_comments = std::mem::take(&mut self.status_comments)
+            .into_iter()
+            .map(|(pr, text)| (PullRequestNumber(pr), text))
+            .collect();
+        (prs, status_comments)
+    }
 }
@@ -0,0 +1,119 @@
+//! Events of different repositories are handled independently of each other, so that a repository
+//! with many events or a slow API call does not delay the events of other repositories.
+//!
+//! The event loop takes the state of a repository out of the state of its forge and lends it to
+//! a [`RepoShard`], which handles the events of the repository one by one, in the order in which
+//! they were received. Once the shard has no more events, the state is put back.
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::bors::event::PullRequestComment;
+use crate::bors::{BorsState, RepositoryClient, RepositoryState};
+use crate::database::DbClient;
+use crate::github::GithubRepoName;
+
+/// The state of a single repository taken out of the state of its forge, with its own client of
+/// the database.
+pub struct RepoShard<Client: RepositoryClient> {
+    repo: RepositoryState<Client>,
+    db: Box<dyn DbClient>,
+}
+
+impl<Client: RepositoryClient> RepoShard<Client> {
+    pub fn new(repo: RepositoryState<Client>, db: Box<dyn DbClient>) -> Self {
+        Self { repo, db }
+    }
+
+    pub fn repository(&self) -> &GithubRepoName {
+        &self.repo.repository
+    }
+
+    pub fn into_repository_state(self) -> RepositoryState<Client> {
+        self.repo
+    }
+
+    /// Returns the delay after which postponed updates of summary comments should be posted, if
+    /// there are any that have not been scheduled yet.
+    pub fn schedule_deferred_updates(&mut self) -> Option<Duration> {
+        self.repo
+            .deferred_updates
+            .schedule()
+            .then_some(self.repo.config.summary_comment_delay)
+    }
+}
+
+impl<Client: RepositoryClient> BorsState<Client> for RepoShard<Client> {
+    /// Comments of the bot are filtered out before their events are sent to a shard.
+    fn is_comment_internal(&self, _comment: &PullRequestComment) -> bool {
+        false
+    }
+
+    fn get_repo_state_mut(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<(&mut RepositoryState<Client>, &mut dyn DbClient)> {
+        if &self.repo.repository == repo {
+            Some((&mut self.repo, self.db.as_mut()))
+        } else {
+            None
+        }
+    }
+
+    fn get_all_repos_mut(&mut self) -> (Vec<&mut RepositoryState<Client>>, &mut dyn DbClient) {
+        (vec![&mut self.repo], self.db.as_mut())
+    }
+
+    /// Repositories are reloaded by the event loop once all shards have finished.
+    fn reload_repositories(&mut self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
+        Box::pin(async move {
+            Err(anyhow::anyhow!(
+                "Repositories cannot be reloaded by the shard of {}",
+                self.repo.repository
+            ))
+        })
+    }
+}
+
+/// State of a forge whose repositories can be lent to shards.
+pub trait ShardedState<Client: RepositoryClient>: BorsState<Client> {
+    /// Names of the repositories that are currently not lent to a shard.
+    fn repository_names(&self) -> Vec<GithubRepoName>;
+
+    /// Takes the state of the given repository out, so that it can be handled by a shard.
+    fn take_repository(&mut self, repo: &GithubRepoName) -> Option<RepoShard<Client>>;
+
+    /// Puts the state of a repository lent to a shard back.
+    fn return_repository(&mut self, shard: RepoShard<Client>);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::event::BorsEvent;
+    use crate::bors::shard::ShardedState;
+    use crate::bors::{handle_bors_event, BorsContext, CommandParser};
+    use crate::tests::event::{comment, default_pr_number};
+    use crate::tests::state::{default_repo_name, ClientBuilder};
+
+    #[tokio::test]
+    async fn handle_event_in_shard() {
+        let mut state = ClientBuilder::default().create_state().await;
+        let ctx = BorsContext::new(CommandParser::new("@bors".to_string()));
+
+        let mut shard = state.take_repository(&default_repo_name()).unwrap();
+        assert!(state.repository_names().is_empty());
+        handle_bors_event(
+            BorsEvent::Comment(comment("@bors ping").create()),
+            &mut shard,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        state.return_repository(shard);
+
+        assert_eq!(state.repository_names(), vec![default_repo_name()]);
+        state
+            .client()
+            .check_comments(default_pr_number(), &["Pong 🏓!"]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Compatibility with the `bors.toml` configuration files of bors-ng, so that repositories can
+//! switch from bors-ng without writing a new configuration first.
+//!
+//! The file is only used when the repository does not contain a `rust-bors.toml` file. Its keys
+//! are mapped onto [`RepositoryConfig`]; keys of bors-ng that have no equivalent are ignored with
+//! a warning.
+use anyhow::Context;
+
+use crate::config::RepositoryConfig;
+
+pub const BORS_NG_CONFIG_FILE_PATH: &str = "bors.toml";
+
+/// Keys of bors-ng that are understood, but have no equivalent in the bot.
+const UNSUPPORTED_KEYS: &[&str] = &[
+    "pr_status",
+    "required_approvals",
+    "up_to_date_approvals",
+    "use_codeowners",
+    "delete_merged_branches",
+    "use_squash_merge",
+    "update_base_for_deletes",
+    "cut_body_after",
+    "committer",
+    "prerun_timeout_sec",
+];
+
+/// Configuration of a repository translated from a bors-ng configuration file.
+#[derive(Debug)]
+pub struct BorsNgConfig {
+    pub config: RepositoryConfig,
+    /// Settings of the file that are ignored.
+    pub warnings: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BorsNgToml {
+    /// Patterns of the statuses that have to succeed, `%` matches any text.
+    #[serde(default)]
+    status: Vec<String>,
+    #[serde(default)]
+    block_labels: Vec<String>,
+    #[serde(default)]
+    timeout_sec: Option<u64>,
+    #[serde(flatten)]
+    other: toml::Table,
+}
+
+/// Parses the content of a bors-ng configuration file.
+///
+/// - `status` becomes `ci.try_workflows` (with `%` replaced by `*`).
+/// - `block_labels` becomes `blocking_labels`.
+/// - `timeout_sec` becomes `build_timeout`.
+pub fn parse_bors_ng_config(content: &str) -> anyhow::Result<BorsNgConfig> {
+    let file: BorsNgToml = toml::from_str(content)?;
+
+    let mut table = toml::Table::new();
+    if let Some(timeout) = file.timeout_sec {
+        table.insert(
+            "build_timeout".to_string(),
+            toml::Value::Integer(timeout as i64),
+        );
+    }
+    table.insert(
+        "blocking_labels".to_string(),
+        toml::Value::try_from(file.block_labels)?,
+    );
+    let workflows: Vec<String> = file
+        .status
+        .iter()
+        .map(|pattern| pattern.replace('%', "*"))
+        .collect();
+    let mut ci = toml::Table::new();
+    ci.insert(
+        "try_workflows".to_string(),
+        toml::Value::try_from(workflows)?,
+    );
+    table.insert("ci".to_string(), toml::Value::Table(ci));
+    let config: RepositoryConfig = toml::Value::Table(table)
+        .try_into()
+        .context("Cannot translate the bors-ng configuration")?;
+
+    let warnings = file
+        .other
+        .keys()
+        .map(|key| {
+            if UNSUPPORTED_KEYS.contains(&key.as_str()) {
+                format!("`{key}` is not supported and it is ignored")
+            } else {
+                format!("Unknown key `{key}`")
+            }
+        })
+        .collect();
+    Ok(BorsNgConfig { config, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bors_ng::parse_bors_ng_config;
+
+    #[test]
+    fn translate_config() {
+        let content = r#"status = ["ci/circleci%", "continuous-integration/travis-ci/push"]
+block_labels = ["S-blocked"]
+timeout_sec = 7200
+delete_merged_branches = true
+"#;
+        let translated = parse_bors_ng_config(content).unwrap();
+        let config = translated.config;
+        assert_eq!(config.timeout, Duration::from_secs(7200));
+        assert_eq!(config.blocking_labels, vec!["S-blocked".to_string()]);
+        assert!(config.ci.is_try_workflow("ci/circleci: build"));
+        assert!(config
+            .ci
+            .is_try_workflow("continuous-integration/travis-ci/push"));
+        assert!(!config.ci.is_try_workflow("docs"));
+        assert_eq!(
+            translated.warnings,
+            vec!["`delete_merged_branches` is not supported and it is ignored".to_string()]
+        );
+    }
+
+    #[test]
+    fn translate_empty_config() {
+        let translated = parse_bors_ng_config("").unwrap();
+        assert_eq!(translated.config.timeout, Duration::from_secs(3600));
+        assert!(!translated.config.ci.filters_workflows());
+        assert!(translated.warnings.is_empty());
+    }
+
+    #[test]
+    fn translate_invalid_config() {
+        assert!(parse_bors_ng_config("timeout_sec = \"foo\"").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Azure Pipelines integration. Try builds are queued using the REST API of Azure DevOps, and their
+//! results are polled during the periodic refresh of the repositories.
+use anyhow::Context;
+use axum::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+
+use crate::bors::DispatchedWorkflow;
+use crate::ci::{stable_run_id, CiBuildRequest, CiService};
+use crate::database::WorkflowStatus;
+use crate::http::SendLimited;
+
+const API_URL: &str = "https://dev.azure.com";
+const API_VERSION: &str = "7.1";
+
+/// Queues builds of Azure Pipelines, using a personal access token with the `Build (Read &
+/// execute)` scope.
+pub struct AzurePipelinesClient {
+    client: reqwest::Client,
+    token: SecretString,
+}
+
+impl AzurePipelinesClient {
+    pub fn new(token: SecretString) -> Self {
+        Self {
+            client: crate::http::client(),
+            token,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<String> {
+        let response = request
+            .basic_auth("", Some(self.token.expose_secret()))
+            .query(&[("api-version", API_VERSION)])
+            .send_limited()
+            .await
+            .context("Cannot send Azure DevOps API request")?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("Azure DevOps API error ({status}): {text}"));
+        }
+        Ok(text)
+    }
+}
+
+#[async_trait]
+impl CiService for AzurePipelinesClient {
+    fn name(&self) -> &'static str {
+        "azure_pipelines"
+    }
+
+    /// `pipeline` contains the organization, the project and the ID of the pipeline definition,
+    /// e.g. `acme/app/12`.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn start_build(
+        &self,
+        pipeline: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        #[derive(serde::Deserialize, Debug)]
+        struct WebLink {
+            href: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct Links {
+            web: WebLink,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct BuildPayload {
+            id: u64,
+            #[serde(rename = "_links")]
+            links: Links,
+        }
+
+        let pipeline = parse_pipeline(pipeline)?;
+        // Parameters are strings, passed to the build as variables
+        let parameters = json!({
+            "BORS_REPOSITORY": request.repository.to_string(),
+            "BORS_PR": request.pr.to_string(),
+            "BORS_MERGE_SHA": request.commit_sha.0,
+        });
+        let body = json!({
+            "definition": { "id": pipeline.definition },
+            "sourceBranch": format!("refs/heads/{}", request.branch),
+            "sourceVersion": request.commit_sha.0,
+            "parameters": parameters.to_string(),
+        });
+        let text = self
+            .send(
+                self.client
+                    .post(pipeline.builds_url(&[])?)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.to_string()),
+            )
+            .await
+            .with_context(|| format!("Cannot queue a build of {pipeline}"))?;
+        let build: BuildPayload =
+            serde_json::from_str(&text).context("Cannot parse Azure Pipelines build")?;
+        Ok(DispatchedWorkflow {
+            run_id: stable_run_id(&format!("{pipeline}/{}", build.id)),
+            url: build.links.web.href,
+        })
+    }
+
+    /// The ID of the build is taken from its web URL (`.../_build/results?buildId=<id>`).
+    async fn poll_build(
+        &self,
+        pipeline: &str,
+        url: &str,
+    ) -> anyhow::Result<Option<WorkflowStatus>> {
+        #[derive(serde::Deserialize, Debug)]
+        #[serde(rename_all = "camelCase")]
+        struct BuildPayload {
+            status: String,
+            result: Option<String>,
+        }
+
+        let pipeline = parse_pipeline(pipeline)?;
+        let url = Url::parse(url).with_context(|| format!("Invalid build URL {url}"))?;
+        let Some((_, build_id)) = url.query_pairs().find(|(key, _)| key == "buildId") else {
+            return Err(anyhow::anyhow!(
+                "Build URL {url} does not contain the build ID"
+            ));
+        };
+        let text = self
+            .send(self.client.get(pipeline.builds_url(&[build_id.as_ref()])?))
+            .await
+            .with_context(|| format!("Cannot load build {build_id} of {pipeline}"))?;
+        let build: BuildPayload =
+            serde_json::from_str(&text).context("Cannot parse Azure Pipelines build")?;
+        Ok(build_status(&build.status, build.result.as_deref()))
+    }
+}
+
+/// Pipeline definition of an Azure DevOps project.
+#[derive(Debug, PartialEq)]
+struct Pipeline<'a> {
+    organization: &'a str,
+    project: &'a str,
+    definition: u64,
+}
+
+impl Pipeline<'_> {
+    /// URL of the builds API of the project, with the given additional path segments.
+    fn builds_url(&self, path: &[&str]) -> anyhow::Result<Url> {
+        let mut url = Url::parse(API_URL)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid API URL"))?
+            .pop_if_empty()
+            .extend([self.organization, self.project, "_apis", "build", "builds"])
+            .extend(path);
+        Ok(url)
+    }
+}
+
+impl std::fmt::Display for Pipeline<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.organization, self.project, self.definition
+        )
+    }
+}
+
+fn parse_pipeline(pipeline: &str) -> anyhow::Result<Pipeline<'_>> {
+    let mut parts = pipeline.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(organization), Some(project), Some(definition), None) => Ok(Pipeline {
+            organization,
+            project,
+            definition: definition
+                .parse()
+                .with_context(|| format!("Invalid pipeline definition ID `{definition}`"))?,
+        }),
+        _ => Err(anyhow::anyhow!(
+            "Invalid Azure Pipelines pipeline `{pipeline}`"
+        )),
+    }
+}
+
+/// Maps the status and the result of a build to the status of its workflow, `None` if the build
+/// has not finished yet. Partially succeeded builds have failed tasks, so they are failures.
+fn build_status(status: &str, result: Option<&str>) -> Option<WorkflowStatus> {
+    if status != "completed" {
+        return None;
+    }
+    match result {
+        Some("succeeded") => Some(WorkflowStatus::Success),
+        _ => Some(WorkflowStatus::Failure),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::azure::{build_status, parse_pipeline, Pipeline};
+    use crate::database::WorkflowStatus;
+
+    #[test]
+    fn pipelines() {
+        let pipeline = parse_pipeline("acme/My Project/12").unwrap();
+        assert_eq!(
+            pipeline,
+            Pipeline {
+                organization: "acme",
+                project: "My Project",
+                definition: 12,
+            }
+        );
+        assert_eq!(
+            pipeline.builds_url(&["34"]).unwrap().as_str(),
+            "https://dev.azure.com/acme/My%20Project/_apis/build/builds/34"
+        );
+        assert!(parse_pipeline("acme/project").is_err());
+        assert!(parse_pipeline("acme/project/ci").is_err());
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(build_status("inProgress", None), None);
+        assert_eq!(
+            build_status("completed", Some("succeeded")),
+            Some(WorkflowStatus::Success)
+        );
+        assert_eq!(
+            build_status("completed", Some("partiallySucceeded")),
+            Some(WorkflowStatus::Failure)
+        );
+        assert_eq!(
+            build_status("completed", Some("canceled")),
+            Some(WorkflowStatus::Failure)
+        );
+    }
+}
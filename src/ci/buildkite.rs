@@ -0,0 +1,299 @@
+//! Buildkite integration. Try builds are started using the REST API, and their results are received
+//! from the `build.finished` webhook of a Buildkite notification service.
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use anyhow::Context;
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::{HeaderMap, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::bors::event::{BorsEvent, WorkflowCompleted};
+use crate::bors::DispatchedWorkflow;
+use crate::ci::{stable_run_id, CiBuildRequest, CiService};
+use crate::database::WorkflowStatus;
+use crate::forge::{constant_time_eq, extract_webhook, header, Forge};
+use crate::github::server::ServerStateRef;
+use crate::github::{CommitSha, GithubRepoName, WebhookSecret};
+use crate::http::SendLimited;
+
+const API_URL: &str = "https://api.buildkite.com/v2";
+
+/// Meta-data key of a build that contains the repository whose PR is tested.
+const REPOSITORY_META_DATA: &str = "bors-repository";
+
+/// Prefix of the event types of Buildkite deliveries stored in the delivery log.
+pub const EVENT_TYPE_PREFIX: &str = "buildkite:";
+
+/// Starts builds of Buildkite pipelines, using an API access token with the `write_builds` scope.
+pub struct BuildkiteClient {
+    client: reqwest::Client,
+    token: SecretString,
+}
+
+impl BuildkiteClient {
+    pub fn new(token: SecretString) -> Self {
+        Self {
+            client: crate::http::client(),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl CiService for BuildkiteClient {
+    fn name(&self) -> &'static str {
+        "buildkite"
+    }
+
+    /// `pipeline` is the slug of the organization and of the pipeline, e.g. `acme/app`.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn start_build(
+        &self,
+        pipeline: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        #[derive(serde::Deserialize, Debug)]
+        struct BuildPayload {
+            id: String,
+            web_url: String,
+        }
+
+        let (organization, pipeline) = pipeline
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("Invalid Buildkite pipeline `{pipeline}`"))?;
+        let body = json!({
+            "commit": request.commit_sha.0,
+            "branch": request.branch,
+            "message": request.message,
+            "meta_data": {
+                REPOSITORY_META_DATA: request.repository.to_string(),
+            },
+            "env": {
+                "BORS_PR": request.pr.to_string(),
+                "BORS_MERGE_SHA": request.commit_sha.0,
+            },
+        });
+        let response = self
+            .client
+            .post(format!(
+                "{API_URL}/organizations/{organization}/pipelines/{pipeline}/builds"
+            ))
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", self.token.expose_secret()),
+            )
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send_limited()
+            .await
+            .context("Cannot send Buildkite API request")?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "Cannot start Buildkite build of {organization}/{pipeline} ({status}): {text}"
+            ));
+        }
+        let build: BuildPayload =
+            serde_json::from_str(&text).context("Cannot parse Buildkite build")?;
+        Ok(DispatchedWorkflow {
+            run_id: stable_run_id(&build.id),
+            url: build.web_url,
+        })
+    }
+}
+
+/// axum extractor for Buildkite webhook events.
+#[derive(Debug)]
+pub struct BuildkiteWebhook(pub BorsEvent);
+
+#[async_trait]
+impl<B> FromRequest<ServerStateRef, B> for BuildkiteWebhook
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: Request<B>,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        extract_webhook(&BuildkiteForge, request, state)
+            .await
+            .map(BuildkiteWebhook)
+    }
+}
+
+/// Webhooks of a Buildkite notification service. They are signed using HMAC-SHA256 in the
+/// `X-Buildkite-Signature` header, or they contain the token in the `X-Buildkite-Token` header.
+pub struct BuildkiteForge;
+
+impl Forge for BuildkiteForge {
+    fn name(&self) -> &'static str {
+        "buildkite"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool {
+        if let Some(signature) = header(headers, "x-buildkite-signature") {
+            return verify_signature(signature, body, secret);
+        }
+        header(headers, "x-buildkite-token")
+            .map_or(false, |token| constant_time_eq(token, secret.expose()))
+    }
+
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-buildkite-event")
+    }
+
+    /// Buildkite does not identify its deliveries.
+    fn delivery_id<'a>(&self, _headers: &'a HeaderMap) -> Option<&'a str> {
+        None
+    }
+
+    fn recorded_event_type(&self, event_type: &str) -> String {
+        format!("{EVENT_TYPE_PREFIX}{event_type}")
+    }
+
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        let event_type = event_type
+            .strip_prefix(EVENT_TYPE_PREFIX)
+            .unwrap_or(event_type);
+        parse_buildkite_event(event_type, body)
+    }
+}
+
+/// The signature has the form `timestamp=<unix time>,signature=<hex HMAC>`, where the HMAC is
+/// computed from `<timestamp>.<body>`.
+fn verify_signature(signature: &str, body: &[u8], secret: &WebhookSecret) -> bool {
+    let mut timestamp = None;
+    let mut hmac = None;
+    for part in signature.split(',') {
+        match part.split_once('=') {
+            Some(("timestamp", value)) => timestamp = Some(value),
+            Some(("signature", value)) => hmac = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(hmac)) = (timestamp, hmac) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes()).expect("Cannot create HMAC key");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&hmac).is_ok()
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BuildPayload {
+    id: String,
+    state: String,
+    branch: String,
+    commit: String,
+    #[serde(default)]
+    meta_data: HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BuildEvent {
+    build: BuildPayload,
+}
+
+/// Parses the payload of a webhook with the given type (the `X-Buildkite-Event` header).
+/// Only finished builds started by bors are interesting for the bot.
+pub fn parse_buildkite_event(event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+    if event_type != "build.finished" {
+        tracing::debug!("Ignoring Buildkite event type {event_type:?}");
+        return Ok(None);
+    }
+    let payload: BuildEvent = serde_json::from_slice(body)?;
+    let build = payload.build;
+    let Some(repository) = build.meta_data.get(REPOSITORY_META_DATA) else {
+        return Ok(None);
+    };
+    let (owner, name) = repository
+        .split_once('/')
+        .ok_or_else(|| anyhow::anyhow!("Invalid repository {repository} in build meta-data"))?;
+    Ok(Some(BorsEvent::WorkflowCompleted(WorkflowCompleted {
+        repository: GithubRepoName::new(owner, name),
+        branch: build.branch,
+        commit_sha: CommitSha(build.commit),
+        run_id: stable_run_id(&build.id),
+        status: match build.state.as_str() {
+            "passed" => WorkflowStatus::Success,
+            _ => WorkflowStatus::Failure,
+        },
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use crate::bors::event::BorsEvent;
+    use crate::ci::buildkite::{parse_buildkite_event, BuildkiteForge};
+    use crate::ci::stable_run_id;
+    use crate::database::WorkflowStatus;
+    use crate::forge::Forge;
+    use crate::github::WebhookSecret;
+    use crate::tests::io::load_test_file;
+
+    #[test]
+    fn verify_signature() {
+        let secret = WebhookSecret::new("ABCDEF".to_string());
+        let body = br#"{"event": "ping"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"ABCDEF").unwrap();
+        mac.update(b"1700000000.");
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        assert!(!BuildkiteForge.verify_webhook(&headers, body, &secret));
+        headers.insert(
+            "x-buildkite-signature",
+            format!("timestamp=1700000000,signature={signature}")
+                .parse()
+                .unwrap(),
+        );
+        assert!(BuildkiteForge.verify_webhook(&headers, body, &secret));
+        assert!(!BuildkiteForge.verify_webhook(&headers, b"{}", &secret));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-buildkite-token", "ABCDEF".parse().unwrap());
+        assert!(BuildkiteForge.verify_webhook(&headers, body, &secret));
+    }
+
+    #[test]
+    fn build_finished() {
+        let body = load_test_file("buildkite/build-finished.json");
+        let Some(BorsEvent::WorkflowCompleted(workflow)) =
+            parse_buildkite_event("build.finished", body.as_bytes()).unwrap()
+        else {
+            panic!("Expected a completed workflow");
+        };
+        assert_eq!(workflow.repository.to_string(), "owner/name");
+        assert_eq!(workflow.branch, "automation/bors/try");
+        assert_eq!(workflow.commit_sha.0, "sha-merged");
+        assert_eq!(
+            workflow.run_id,
+            stable_run_id("0190046e-e199-453b-a302-a21a4d649d31")
+        );
+        assert_eq!(workflow.status, WorkflowStatus::Failure);
+
+        assert!(parse_buildkite_event("build.started", body.as_bytes())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! CircleCI integration. Try builds are started by triggering a pipeline of the project on the try
+//! branch using the REST API, and the result of the configured workflow of the pipeline is
+//! received from the `workflow-completed` webhook of the project.
+use std::fmt::Debug;
+
+use anyhow::Context;
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::{HeaderMap, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use octocrab::models::RunId;
+use reqwest::header::CONTENT_TYPE;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::bors::event::{BorsEvent, WorkflowCompleted};
+use crate::bors::DispatchedWorkflow;
+use crate::ci::{stable_run_id, CiBuildRequest, CiService};
+use crate::database::WorkflowStatus;
+use crate::forge::{extract_webhook, header, Forge};
+use crate::github::server::ServerStateRef;
+use crate::github::{CommitSha, GithubRepoName, WebhookSecret};
+use crate::http::SendLimited;
+
+const API_URL: &str = "https://circleci.com/api/v2";
+
+/// Prefix of the event types of CircleCI deliveries stored in the delivery log.
+pub const EVENT_TYPE_PREFIX: &str = "circleci:";
+
+/// Triggers pipelines of CircleCI projects, using a personal API token.
+pub struct CircleCiClient {
+    client: reqwest::Client,
+    token: SecretString,
+}
+
+impl CircleCiClient {
+    pub fn new(token: SecretString) -> Self {
+        Self {
+            client: crate::http::client(),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl CiService for CircleCiClient {
+    fn name(&self) -> &'static str {
+        "circleci"
+    }
+
+    /// `pipeline` contains the slug of the project and the name of the workflow that decides the
+    /// result of the build, e.g. `gh/acme/app/test`.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn start_build(
+        &self,
+        pipeline: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        #[derive(serde::Deserialize, Debug)]
+        struct PipelinePayload {
+            id: String,
+            number: u64,
+        }
+
+        let (project, workflow) = parse_pipeline(pipeline)?;
+        // The branch contains only the merge commit, so the pipeline runs on it
+        let body = json!({ "branch": request.branch });
+        let response = self
+            .client
+            .post(format!("{API_URL}/project/{project}/pipeline"))
+            .header("Circle-Token", self.token.expose_secret())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send_limited()
+            .await
+            .context("Cannot send CircleCI API request")?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "Cannot trigger CircleCI pipeline of {project} ({status}): {text}"
+            ));
+        }
+        let triggered: PipelinePayload =
+            serde_json::from_str(&text).context("Cannot parse CircleCI pipeline")?;
+        Ok(DispatchedWorkflow {
+            run_id: workflow_run_id(&triggered.id, workflow),
+            url: format!(
+                "https://app.circleci.com/pipelines/{project}/{}",
+                triggered.number
+            ),
+        })
+    }
+}
+
+/// Splits a pipeline into the slug of the project (`vcs/organization/repository`) and the name
+/// of the workflow.
+fn parse_pipeline(pipeline: &str) -> anyhow::Result<(&str, &str)> {
+    match pipeline.rsplit_once('/') {
+        Some((project, workflow))
+            if project.split('/').count() == 3 && !workflow.trim().is_empty() =>
+        {
+            Ok((project, workflow))
+        }
+        _ => Err(anyhow::anyhow!("Invalid CircleCI pipeline `{pipeline}`")),
+    }
+}
+
+/// A pipeline can run several workflows, only the configured one is attached to the build.
+/// The webhooks of the other workflows have run IDs that do not belong to any build.
+fn workflow_run_id(pipeline_id: &str, workflow: &str) -> RunId {
+    stable_run_id(&format!("{pipeline_id}/{workflow}"))
+}
+
+/// axum extractor for CircleCI webhook events.
+#[derive(Debug)]
+pub struct CircleCiWebhook(pub BorsEvent);
+
+#[async_trait]
+impl<B> FromRequest<ServerStateRef, B> for CircleCiWebhook
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: Request<B>,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        extract_webhook(&CircleCiForge, request, state)
+            .await
+            .map(CircleCiWebhook)
+    }
+}
+
+/// Webhooks of a CircleCI project. They are signed using HMAC-SHA256 in the `Circleci-Signature`
+/// header (`v1=<hex HMAC of the body>`).
+pub struct CircleCiForge;
+
+impl Forge for CircleCiForge {
+    fn name(&self) -> &'static str {
+        "circleci"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool {
+        let Some(signatures) = header(headers, "circleci-signature") else {
+            return false;
+        };
+        // More signature versions might be sent in the future, separated by commas
+        signatures
+            .split(',')
+            .filter_map(|signature| signature.trim().strip_prefix("v1="))
+            .filter_map(|signature| hex::decode(signature).ok())
+            .any(|signature| {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+                    .expect("Cannot create HMAC key");
+                mac.update(body);
+                mac.verify_slice(&signature).is_ok()
+            })
+    }
+
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "circleci-event-type")
+    }
+
+    /// CircleCI does not identify its deliveries in the headers.
+    fn delivery_id<'a>(&self, _headers: &'a HeaderMap) -> Option<&'a str> {
+        None
+    }
+
+    fn recorded_event_type(&self, event_type: &str) -> String {
+        format!("{EVENT_TYPE_PREFIX}{event_type}")
+    }
+
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        let event_type = event_type
+            .strip_prefix(EVENT_TYPE_PREFIX)
+            .unwrap_or(event_type);
+        parse_circleci_event(event_type, body)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WorkflowPayload {
+    name: String,
+    status: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct VcsPayload {
+    revision: String,
+    #[serde(default)]
+    branch: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PipelinePayload {
+    id: String,
+    vcs: VcsPayload,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ProjectPayload {
+    slug: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WorkflowEvent {
+    workflow: WorkflowPayload,
+    pipeline: PipelinePayload,
+    project: ProjectPayload,
+}
+
+/// Parses the payload of a webhook with the given type (the `Circleci-Event-Type` header).
+/// Only completed workflows are interesting for the bot.
+pub fn parse_circleci_event(event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+    if event_type != "workflow-completed" {
+        tracing::debug!("Ignoring CircleCI event type {event_type:?}");
+        return Ok(None);
+    }
+    let payload: WorkflowEvent = serde_json::from_slice(body)?;
+    // The slug of GitHub projects is `github/<owner>/<repository>` (or `gh/...`)
+    let repository = match payload.project.slug.split('/').collect::<Vec<_>>()[..] {
+        [_, owner, name] => GithubRepoName::new(owner, name),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid CircleCI project {}",
+                payload.project.slug
+            ))
+        }
+    };
+    Ok(Some(BorsEvent::WorkflowCompleted(WorkflowCompleted {
+        repository,
+        branch: payload.pipeline.vcs.branch.unwrap_or_default(),
+        commit_sha: CommitSha(payload.pipeline.vcs.revision),
+        run_id: workflow_run_id(&payload.pipeline.id, &payload.workflow.name),
+        status: match payload.workflow.status.as_str() {
+            "success" => WorkflowStatus::Success,
+            _ => WorkflowStatus::Failure,
+        },
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use crate::bors::event::BorsEvent;
+    use crate::ci::circleci::{
+        parse_circleci_event, parse_pipeline, workflow_run_id, CircleCiForge,
+    };
+    use crate::database::WorkflowStatus;
+    use crate::forge::Forge;
+    use crate::github::WebhookSecret;
+    use crate::tests::io::load_test_file;
+
+    #[test]
+    fn pipelines() {
+        assert_eq!(
+            parse_pipeline("gh/acme/app/test").unwrap(),
+            ("gh/acme/app", "test")
+        );
+        assert!(parse_pipeline("gh/acme/app").is_err());
+        assert!(parse_pipeline("gh/acme/app/").is_err());
+    }
+
+    #[test]
+    fn verify_signature() {
+        let secret = WebhookSecret::new("ABCDEF".to_string());
+        let body = br#"{"type": "ping"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"ABCDEF").unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        assert!(!CircleCiForge.verify_webhook(&headers, body, &secret));
+        headers.insert(
+            "circleci-signature",
+            format!("v2=abc,v1={signature}").parse().unwrap(),
+        );
+        assert!(CircleCiForge.verify_webhook(&headers, body, &secret));
+        assert!(!CircleCiForge.verify_webhook(&headers, b"{}", &secret));
+    }
+
+    #[test]
+    fn workflow_completed() {
+        let body = load_test_file("circleci/workflow-completed.json");
+        let Some(BorsEvent::WorkflowCompleted(workflow)) =
+            parse_circleci_event("workflow-completed", body.as_bytes()).unwrap()
+        else {
+            panic!("Expected a completed workflow");
+        };
+        assert_eq!(workflow.repository.to_string(), "owner/name");
+        assert_eq!(workflow.branch, "automation/bors/try");
+        assert_eq!(workflow.commit_sha.0, "sha-merged");
+        assert_eq!(
+            workflow.run_id,
+            workflow_run_id("1285fe1d-d3a6-44fc-8886-8979558254c4", "test")
+        );
+        assert_eq!(workflow.status, WorkflowStatus::Failure);
+
+        assert!(parse_circleci_event("job-completed", body.as_bytes())
+            .unwrap()
+            .is_none());
+    }
+}
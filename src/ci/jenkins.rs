@@ -0,0 +1,272 @@
+//! Jenkins integration. Try builds are started by triggering a parameterized job, and the job
+//! reports its result by sending a signed callback to the `/jenkins` endpoint of the bot.
+//!
+//! The callback is a JSON object with the `build_id`, `repository` and `commit` parameters that
+//! the job has received from the bot (`BORS_BUILD_ID`, `BORS_REPOSITORY` and `BORS_MERGE_SHA`)
+//! and its `status` (`success` or `failure`). It is signed using HMAC-SHA256 with the callback
+//! secret, whose hex digest is sent in the `X-Bors-Signature` header (`sha256=<digest>`).
+use std::fmt::Debug;
+
+use anyhow::Context;
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::{HeaderMap, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::Url;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+
+use crate::bors::event::{BorsEvent, WorkflowCompleted};
+use crate::bors::DispatchedWorkflow;
+use crate::ci::{stable_run_id, CiBuildRequest, CiService};
+use crate::database::WorkflowStatus;
+use crate::forge::{extract_webhook, header, Forge};
+use crate::github::server::ServerStateRef;
+use crate::github::{CommitSha, GithubRepoName, WebhookSecret};
+use crate::http::SendLimited;
+
+/// Event type of the callbacks, which do not have a type header.
+const CALLBACK_EVENT_TYPE: &str = "build_finished";
+
+/// Prefix of the event types of Jenkins callbacks stored in the delivery log.
+pub const EVENT_TYPE_PREFIX: &str = "jenkins:";
+
+/// Triggers parameterized jobs of a Jenkins server, authenticated as a user with an API token.
+pub struct JenkinsClient {
+    client: reqwest::Client,
+    /// URL of the Jenkins server, e.g. `https://ci.example.com`.
+    url: Url,
+    user: String,
+    token: SecretString,
+}
+
+impl JenkinsClient {
+    pub fn new(url: &str, user: String, token: SecretString) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::http::client(),
+            url: Url::parse(url).with_context(|| format!("Invalid Jenkins URL {url}"))?,
+            user,
+            token,
+        })
+    }
+
+    /// URL of a job, whose name can contain folders (`folder/job`).
+    fn job_url(&self, job: &str) -> anyhow::Result<Url> {
+        let mut url = self.url.clone();
+        {
+            let mut segments = url
+                .path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("Invalid Jenkins URL {}", self.url))?;
+            segments.pop_if_empty();
+            for name in job.split('/') {
+                segments.extend(["job", name]);
+            }
+        }
+        Ok(url)
+    }
+}
+
+#[async_trait]
+impl CiService for JenkinsClient {
+    fn name(&self) -> &'static str {
+        "jenkins"
+    }
+
+    /// The job receives the `BORS_BUILD_ID`, `BORS_REPOSITORY`, `BORS_PR`, `BORS_BRANCH` and
+    /// `BORS_MERGE_SHA` parameters. Jenkins only queues the build, so its number is not known;
+    /// the build is identified by `BORS_BUILD_ID` instead.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn start_build(
+        &self,
+        job: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        let mut bytes = [0; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let build_id = hex::encode(bytes);
+        let repository = request.repository.to_string();
+        let pr = request.pr.to_string();
+        let job_url = self.job_url(job)?;
+        let response = self
+            .client
+            .post(format!("{job_url}/buildWithParameters"))
+            .basic_auth(&self.user, Some(self.token.expose_secret()))
+            .form(&[
+                ("BORS_BUILD_ID", build_id.as_str()),
+                ("BORS_REPOSITORY", repository.as_str()),
+                ("BORS_PR", pr.as_str()),
+                ("BORS_BRANCH", request.branch),
+                ("BORS_MERGE_SHA", request.commit_sha.0.as_str()),
+            ])
+            .send_limited()
+            .await
+            .context("Cannot send Jenkins request")?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Cannot trigger Jenkins job {job} ({status}): {text}"
+            ));
+        }
+        Ok(DispatchedWorkflow {
+            run_id: stable_run_id(&build_id),
+            url: job_url.to_string(),
+        })
+    }
+}
+
+/// axum extractor for the result callbacks of Jenkins jobs.
+#[derive(Debug)]
+pub struct JenkinsWebhook(pub BorsEvent);
+
+#[async_trait]
+impl<B> FromRequest<ServerStateRef, B> for JenkinsWebhook
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: Request<B>,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        extract_webhook(&JenkinsForge, request, state)
+            .await
+            .map(JenkinsWebhook)
+    }
+}
+
+/// Result callbacks of Jenkins jobs, signed with the callback secret.
+pub struct JenkinsForge;
+
+impl Forge for JenkinsForge {
+    fn name(&self) -> &'static str {
+        "jenkins"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool {
+        let Some(signature) = header(headers, "x-bors-signature")
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .and_then(|signature| hex::decode(signature).ok())
+        else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+            .expect("Cannot create HMAC key");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn event_type<'a>(&self, _headers: &'a HeaderMap) -> Option<&'a str> {
+        Some(CALLBACK_EVENT_TYPE)
+    }
+
+    fn delivery_id<'a>(&self, _headers: &'a HeaderMap) -> Option<&'a str> {
+        None
+    }
+
+    fn recorded_event_type(&self, event_type: &str) -> String {
+        format!("{EVENT_TYPE_PREFIX}{event_type}")
+    }
+
+    fn parse_webhook(&self, _event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        parse_callback(body).map(Some)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CallbackStatus {
+    Success,
+    Failure,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Callback {
+    build_id: String,
+    repository: String,
+    commit: String,
+    status: CallbackStatus,
+}
+
+/// Parses the result callback of a job. The build is found using the run ID derived from its
+/// `build_id`, so the branch is not needed.
+fn parse_callback(body: &[u8]) -> anyhow::Result<BorsEvent> {
+    let callback: Callback = serde_json::from_slice(body)?;
+    let (owner, name) = callback
+        .repository
+        .split_once('/')
+        .ok_or_else(|| anyhow::anyhow!("Invalid repository {}", callback.repository))?;
+    Ok(BorsEvent::WorkflowCompleted(WorkflowCompleted {
+        repository: GithubRepoName::new(owner, name),
+        branch: String::new(),
+        commit_sha: CommitSha(callback.commit),
+        run_id: stable_run_id(&callback.build_id),
+        status: match callback.status {
+            CallbackStatus::Success => WorkflowStatus::Success,
+            CallbackStatus::Failure => WorkflowStatus::Failure,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use hmac::{Hmac, Mac};
+    use secrecy::SecretString;
+    use sha2::Sha256;
+
+    use crate::bors::event::BorsEvent;
+    use crate::ci::jenkins::{JenkinsClient, JenkinsForge};
+    use crate::ci::stable_run_id;
+    use crate::database::WorkflowStatus;
+    use crate::forge::Forge;
+    use crate::github::WebhookSecret;
+
+    #[test]
+    fn job_urls() {
+        let client = JenkinsClient::new(
+            "https://ci.example.com/",
+            "bors".to_string(),
+            SecretString::new("token".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            client.job_url("rust/try build").unwrap().as_str(),
+            "https://ci.example.com/job/rust/job/try%20build"
+        );
+    }
+
+    #[test]
+    fn signed_callback() {
+        let secret = WebhookSecret::new("ABCDEF".to_string());
+        let body =
+            br#"{"build_id": "abc", "repository": "owner/name", "commit": "sha-merged", "status": "failure"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"ABCDEF").unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        assert!(!JenkinsForge.verify_webhook(&headers, body, &secret));
+        headers.insert(
+            "x-bors-signature",
+            format!("sha256={signature}").parse().unwrap(),
+        );
+        assert!(JenkinsForge.verify_webhook(&headers, body, &secret));
+        assert!(!JenkinsForge.verify_webhook(&headers, b"{}", &secret));
+
+        let event_type = JenkinsForge.event_type(&headers).unwrap();
+        let Some(BorsEvent::WorkflowCompleted(workflow)) =
+            JenkinsForge.parse_webhook(event_type, body).unwrap()
+        else {
+            panic!("Expected a completed workflow");
+        };
+        assert_eq!(workflow.repository.to_string(), "owner/name");
+        assert_eq!(workflow.run_id, stable_run_id("abc"));
+        assert_eq!(workflow.status, WorkflowStatus::Failure);
+    }
+}
@@ -0,0 +1,100 @@
+//! Integrations of external CI services, which run try builds instead of the CI triggered by
+//! pushing to the try branch.
+//!
+//! The bot starts a build of the configured pipeline ([`crate::config::TryTrigger`]) for the merge
+//! commit using the API of the service, and stores it as an external workflow of the try build.
+//! The service reports the result of the build using a webhook, which is turned into a
+//! [`WorkflowCompleted`](crate::bors::event::WorkflowCompleted) event, or the result is polled
+//! during the periodic refresh of the repositories.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::async_trait;
+use octocrab::models::RunId;
+use sha2::{Digest, Sha256};
+
+use crate::bors::DispatchedWorkflow;
+use crate::database::WorkflowStatus;
+use crate::github::{CommitSha, GithubRepoName, PullRequestNumber};
+
+pub mod azure;
+pub mod buildkite;
+pub mod circleci;
+pub mod jenkins;
+
+/// A build of a candidate merge commit requested from a CI service.
+pub struct CiBuildRequest<'a> {
+    pub repository: &'a GithubRepoName,
+    pub pr: PullRequestNumber,
+    /// Bors branch that contains the merge commit.
+    pub branch: &'a str,
+    pub commit_sha: &'a CommitSha,
+    pub message: &'a str,
+}
+
+/// Client of an external CI service.
+#[async_trait]
+pub trait CiService: Send + Sync {
+    /// Name of the service, which is also used in the configuration of repositories, e.g.
+    /// `buildkite`.
+    fn name(&self) -> &'static str;
+
+    /// Starts a build of the given pipeline. The returned run ID identifies the build in the
+    /// webhook that reports its result.
+    async fn start_build(
+        &self,
+        pipeline: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow>;
+
+    /// Returns the result of a build started by [`CiService::start_build`] with the given URL,
+    /// or `None` if it is still running. Services that report results using webhooks are not
+    /// polled.
+    async fn poll_build(
+        &self,
+        _pipeline: &str,
+        _url: &str,
+    ) -> anyhow::Result<Option<WorkflowStatus>> {
+        Ok(None)
+    }
+}
+
+/// The configured CI services, by their name.
+#[derive(Default)]
+pub struct CiServices {
+    services: HashMap<&'static str, Arc<dyn CiService>>,
+}
+
+impl CiServices {
+    pub fn with_service(mut self, service: Arc<dyn CiService>) -> Self {
+        self.services.insert(service.name(), service);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn CiService> {
+        self.services.get(name).map(|service| service.as_ref())
+    }
+}
+
+/// Derives the run ID of a build from its unique ID in the CI service (e.g. a UUID), because
+/// build numbers are only unique within a single pipeline. The ID fits into `i64`, in which
+/// run IDs are stored.
+pub fn stable_run_id(build_id: &str) -> RunId {
+    let hash = Sha256::digest(build_id.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    RunId(u64::from_be_bytes(bytes) >> 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::stable_run_id;
+
+    #[test]
+    fn run_ids() {
+        let id = stable_run_id("0190046e-e199-453b-a302-a21a4d649d31");
+        assert_eq!(id, stable_run_id("0190046e-e199-453b-a302-a21a4d649d31"));
+        assert_ne!(id, stable_run_id("0190046e-e199-453b-a302-a21a4d649d32"));
+        assert!(id.0 <= i64::MAX as u64);
+    }
+}
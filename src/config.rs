@@ -5,20 +5,883 @@ use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 
 use crate::github::{LabelModification, LabelTrigger};
+use crate::messages::MessageTemplates;
+use crate::notifications::{NotificationEvent, WebhookFormat};
 
 pub const CONFIG_FILE_PATH: &str = "rust-bors.toml";
 
+/// Newest version of the configuration schema understood by the bot.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Top-level keys that have been renamed in a version of the configuration schema:
+/// `(old key, new key, version)`. Files with an older `schema_version` can still use the old keys,
+/// but they receive a deprecation warning.
+const RENAMED_KEYS: &[(&str, &str, u32)] = &[("timeout", "build_timeout", 2)];
+
 /// Configuration of a repository loaded from a `rust-bors.toml`
 /// file located in the root of the repository file tree.
 #[derive(serde::Deserialize, Debug)]
 pub struct RepositoryConfig {
+    /// Version of the schema of the file, which decides which keys can be used in it.
+    /// Files without a version use version 1.
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    /// How long can a build run before it is cancelled, in seconds.
     #[serde(
+        rename = "build_timeout",
+        alias = "timeout",
         default = "default_timeout",
         deserialize_with = "deserialize_duration_from_secs"
     )]
     pub timeout: Duration,
     #[serde(default, deserialize_with = "deserialize_labels")]
     pub labels: HashMap<LabelTrigger, Vec<LabelModification>>,
+    /// What should happen with a previous status comment of the bot when a new one is posted.
+    #[serde(default)]
+    pub outdated_comments: OutdatedCommentsMode,
+    /// Should the bot maintain a single comment on each PR that summarizes the current state of
+    /// the PR, and edit it in place whenever the state changes?
+    #[serde(default)]
+    pub summary_comment: bool,
+    /// How long are updates of the summary comment and status comments postponed, in seconds,
+    /// so that changes of a PR that happen shortly after each other (e.g. many finished
+    /// workflows) are posted as a single edit or comment. Updates are posted immediately by
+    /// default, and the summary comment then only shows the workflows of a build once it
+    /// completes.
+    #[serde(default, deserialize_with = "deserialize_duration_from_secs")]
+    pub summary_comment_delay: Duration,
+    /// How is CI started for try builds.
+    #[serde(default)]
+    pub try_trigger: TryTrigger,
+    /// What can be done with draft PRs.
+    #[serde(default)]
+    pub drafts: DraftPolicy,
+    /// Who lands approved PRs.
+    #[serde(default)]
+    pub landing: LandingMode,
+    /// Labels that have to be present on a PR before it can be approved.
+    #[serde(default)]
+    pub required_labels: Vec<String>,
+    /// Labels that prevent a PR from being approved or tested while they are present on it. An
+    /// approved PR that gets one of them is held out of the queue until they are removed.
+    #[serde(default)]
+    pub blocking_labels: Vec<String>,
+    /// Label added to an approved PR whose build has failed, so that failed PRs can be found
+    /// easily. It is removed when a later build of the PR succeeds.
+    #[serde(default)]
+    pub approved_failure_label: Option<String>,
+    /// Limits on the size and contents of PRs that can be approved.
+    #[serde(default)]
+    pub limits: PullRequestLimits,
+    /// Contributor agreement (e.g. a CLA) that the author of a PR has to sign before the PR can
+    /// be approved.
+    #[serde(default)]
+    pub agreement: Option<AgreementConfig>,
+    /// Does every commit of a PR have to contain a `Signed-off-by` trailer matching its author
+    /// (Developer Certificate of Origin) before the PR can be approved?
+    #[serde(default)]
+    pub require_sign_off: bool,
+    /// Does every commit of a PR have to have a signature verified by GitHub before the PR can be
+    /// approved? Merge commits created for testing have to be verified as well.
+    #[serde(default)]
+    pub require_verified_commits: bool,
+    /// Should an approving GitHub review from a user with review permissions approve the PR,
+    /// like `r+` does?
+    #[serde(default)]
+    pub review_approvals: bool,
+    /// Actions performed on a PR after it has been merged.
+    #[serde(default)]
+    pub post_merge: PostMergeConfig,
+    /// Branches that are protected by the `protect-branches` admin endpoint.
+    #[serde(default)]
+    pub branch_protection: BranchProtectionConfig,
+    /// Should excerpts of the logs of failed jobs be stored and posted with build failures?
+    #[serde(default)]
+    pub log_excerpts: Option<LogExcerptsConfig>,
+    /// Number of an issue where alerts that require the attention of the operators of the bot
+    /// (e.g. a force-pushed base branch) are posted.
+    #[serde(default)]
+    pub operator_issue: Option<u64>,
+    /// Prefix of bors commands (e.g. `@bors`) in this repository. If not set, the prefix
+    /// configured for the whole bot is used.
+    #[serde(default)]
+    pub command_prefix: Option<String>,
+    /// Which CI workflows decide the result of builds.
+    #[serde(default)]
+    pub ci: CiConfig,
+    /// Automatic retries of failed builds.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Custom texts of messages posted by the bot.
+    #[serde(default)]
+    pub messages: MessageTemplates,
+    /// Subsystems of the bot that are enabled or disabled in this repository.
+    #[serde(default)]
+    pub features: FeatureToggles,
+    /// Where are notifications about important events of the repository sent.
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// Periodic digest of the activity of the repository.
+    #[serde(default)]
+    pub digest: Option<DigestConfig>,
+    /// Reminders about PRs that are stuck in the same state for too long.
+    #[serde(default)]
+    pub stale_reminders: StaleRemindersConfig,
+    /// How much does the bot comment on PRs and send to chat services.
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    /// Automatic closing of the tree of the repository.
+    #[serde(default)]
+    pub tree: TreeConfig,
+    /// Should the bot only report what it would do, without pushing branches or merging anything?
+    /// Commands are still handled and recorded, which allows trialing the bot on a repository.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Describes how much does the bot report about builds. The state of builds is always shown on the
+/// dashboard (and in the summary comment, if it is enabled).
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    /// Only failures (and replies to commands) are posted as PR comments.
+    Quiet,
+    /// Started and successful builds are also posted as PR comments.
+    #[default]
+    Normal,
+    /// Started and successful builds are also sent as notifications to chat services.
+    Verbose,
+}
+
+impl Verbosity {
+    /// Should comments about started and successful builds be posted to PRs?
+    pub fn posts_progress_comments(self) -> bool {
+        self >= Verbosity::Normal
+    }
+}
+
+/// Describes how are outdated status comments (e.g. about a finished build) handled.
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutdatedCommentsMode {
+    /// Keep the previous comments and post a new one.
+    #[default]
+    Append,
+    /// Hide the previous comment as outdated and post a new one.
+    Minimize,
+    /// Replace the text of the previous comment.
+    Edit,
+}
+
+/// Describes how are CI workflows started for try builds.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TryTrigger {
+    /// The merge commit is pushed to the try branch, which should trigger CI.
+    #[default]
+    Push,
+    /// The given workflow (file name or ID) is started with a `workflow_dispatch` event
+    /// on the base branch of the PR. It receives the `pr`, `merge_sha` and `jobs` inputs. The try
+    /// branch is not updated, the merge commit is kept on the temporary merge branch of the PR.
+    WorkflowDispatch { workflow: String },
+    /// A build of the given pipeline (`organization/pipeline`) is started in Buildkite for the
+    /// merge commit on the try branch. The result of the build is the result of the try build.
+    Buildkite { pipeline: String },
+    /// A build of the given pipeline definition (`organization/project/definition ID`) is queued
+    /// in Azure Pipelines for the merge commit on the try branch. Its result is polled.
+    AzurePipelines { pipeline: String },
+    /// The given parameterized Jenkins job (`folder/job`) is triggered for the merge commit on
+    /// the try branch. The job reports its result using a signed callback.
+    Jenkins { job: String },
+    /// A pipeline of the given CircleCI project is triggered on the try branch. The result of the
+    /// given workflow of the pipeline (`vcs/organization/repository/workflow`) is the result of
+    /// the try build.
+    #[serde(rename = "circleci")]
+    CircleCi { pipeline: String },
+}
+
+impl TryTrigger {
+    /// Returns the name of the external CI service that runs try builds and the pipeline that is
+    /// started, if try builds are not run by the CI of the forge.
+    pub fn ci_pipeline(&self) -> Option<(&'static str, &str)> {
+        match self {
+            TryTrigger::Push | TryTrigger::WorkflowDispatch { .. } => None,
+            TryTrigger::Buildkite { pipeline } => Some(("buildkite", pipeline)),
+            TryTrigger::AzurePipelines { pipeline } => Some(("azure_pipelines", pipeline)),
+            TryTrigger::Jenkins { job } => Some(("jenkins", job)),
+            TryTrigger::CircleCi { pipeline } => Some(("circleci", pipeline)),
+        }
+    }
+}
+
+/// Describes how are approved PRs landed.
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LandingMode {
+    /// Bors merges approved PRs itself.
+    #[default]
+    Bors,
+    /// Approved PRs are added to the native merge queue of GitHub, which merges them.
+    /// Bors still manages approvals and try builds.
+    GithubMergeQueue,
+}
+
+/// Describes how are draft PRs handled.
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DraftPolicy {
+    /// Draft PRs can neither be approved nor tested.
+    #[default]
+    Block,
+    /// Draft PRs can be tested using try builds, but they cannot be approved.
+    AllowTry,
+    /// Draft PRs are handled like any other PR.
+    Allow,
+}
+
+impl DraftPolicy {
+    pub fn allows_approval(&self) -> bool {
+        matches!(self, DraftPolicy::Allow)
+    }
+
+    pub fn allows_try(&self) -> bool {
+        matches!(self, DraftPolicy::Allow | DraftPolicy::AllowTry)
+    }
+}
+
+/// Limits that a PR has to satisfy to be approved, unless the approval explicitly ignores them.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PullRequestLimits {
+    /// Maximum number of added and removed lines.
+    #[serde(default)]
+    pub max_changed_lines: Option<u64>,
+    /// Maximum number of modified files.
+    #[serde(default)]
+    pub max_changed_files: Option<u64>,
+    /// Path prefixes (e.g. directories with generated files) that must not be modified.
+    #[serde(default)]
+    pub forbidden_paths: Vec<String>,
+}
+
+/// Maximum number of automatic retries that can be configured.
+const MAX_RETRIES: u32 = 10;
+
+/// Describes how are failed builds retried automatically.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times is a failed build started again. Builds are not retried by default.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Delay before the first retry, in seconds.
+    #[serde(
+        default = "default_retry_backoff",
+        deserialize_with = "deserialize_duration_from_secs"
+    )]
+    pub backoff: Duration,
+    /// Each following retry waits this many times longer than the previous one.
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: default_retry_backoff(),
+            backoff_multiplier: default_backoff_multiplier(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before retrying a build that has already been retried `attempt` times.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(self.backoff_multiplier.saturating_pow(attempt))
+    }
+}
+
+/// A subsystem of the bot that can be enabled or disabled per repository, so that it can be rolled
+/// out gradually.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Testing several approved PRs together in a single build.
+    Rollups,
+    /// Testing the merge of several approved PRs on top of each other at the same time.
+    /// Merge trains are not implemented yet, so the toggle has no effect.
+    MergeTrains,
+    /// Retrying failed builds according to the [`RetryPolicy`].
+    AutoRetry,
+    /// Delegating review rights of a PR to other users.
+    Delegation,
+    /// Try builds of PRs opened from forks of the repository.
+    TryOnForks,
+}
+
+impl Feature {
+    /// Name of the feature in the `[features]` section of the configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Rollups => "rollups",
+            Feature::MergeTrains => "merge_trains",
+            Feature::AutoRetry => "auto_retry",
+            Feature::Delegation => "delegation",
+            Feature::TryOnForks => "try_on_forks",
+        }
+    }
+
+    /// Features that already existed before they could be toggled are enabled by default,
+    /// new features have to be enabled explicitly.
+    fn enabled_by_default(self) -> bool {
+        match self {
+            Feature::AutoRetry | Feature::TryOnForks => true,
+            Feature::Rollups | Feature::MergeTrains | Feature::Delegation => false,
+        }
+    }
+}
+
+/// Features that are explicitly enabled (`true`) or disabled (`false`) in a repository.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct FeatureToggles(HashMap<Feature, bool>);
+
+impl FeatureToggles {
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.0
+            .get(&feature)
+            .copied()
+            .unwrap_or_else(|| feature.enabled_by_default())
+    }
+}
+
+/// Describes which CI workflows (by their names) decide the result of builds.
+/// If nothing is configured, the result of a build is decided by all check suites of its commit.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CiConfig {
+    /// Workflows that make up the try pipeline. A try build fails if any of them fails or does not
+    /// run at all. If empty, all workflows that are not informational are used. A `*` in a name
+    /// matches any text.
+    #[serde(default)]
+    pub try_workflows: Vec<String>,
+    /// Workflows whose results are shown in the build result, but do not affect it.
+    #[serde(default)]
+    pub informational_workflows: Vec<String>,
+}
+
+impl CiConfig {
+    /// Is the result of builds decided only by some of the workflows?
+    pub fn filters_workflows(&self) -> bool {
+        !self.try_workflows.is_empty() || !self.informational_workflows.is_empty()
+    }
+
+    /// Does the result of the workflow with the given name affect the result of try builds?
+    pub fn is_try_workflow(&self, name: &str) -> bool {
+        !self.informational_workflows.iter().any(|w| w == name)
+            && (self.try_workflows.is_empty()
+                || self
+                    .try_workflows
+                    .iter()
+                    .any(|pattern| workflow_matches(pattern, name)))
+    }
+}
+
+/// Does the name of a workflow match a pattern, in which `*` matches any text?
+pub fn workflow_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // There is no wildcard
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Branch protection that is configured for the managed branches of a repository.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct BranchProtectionConfig {
+    /// Base branches (e.g. `main`) into which PRs are merged.
+    #[serde(default)]
+    pub branches: Vec<String>,
+    /// Checks that have to pass before a commit can be pushed to the base branches.
+    #[serde(default)]
+    pub required_checks: Vec<String>,
+}
+
+/// An external service that tracks which users have signed the contributor agreement.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AgreementConfig {
+    /// Endpoint of the service, see [`crate::agreement::ServiceAgreementChecker`].
+    pub url: String,
+    /// Page where authors can sign the agreement.
+    pub sign_url: String,
+}
+
+/// Chat services where notifications about events of the repository are sent.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct NotificationsConfig {
+    /// Zulip stream that receives notifications. The credentials of the Zulip bot are configured
+    /// for the whole bot.
+    #[serde(default)]
+    pub zulip: Option<ZulipNotificationConfig>,
+    /// Incoming webhooks (e.g. of Slack) that receive notifications.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookNotificationConfig>,
+    /// Email addresses that receive notifications. The SMTP server is configured for the whole
+    /// bot.
+    #[serde(default)]
+    pub email: Option<EmailNotificationConfig>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ZulipNotificationConfig {
+    pub stream: String,
+    #[serde(default = "default_zulip_topic")]
+    pub topic: String,
+    /// Events that are sent to the stream, all events by default.
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationEvent>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookNotificationConfig {
+    /// Name of the webhook, its URL is configured for the whole bot (`notification_webhooks`).
+    pub webhook: String,
+    pub format: WebhookFormat,
+    /// Events that are sent to the webhook, all events by default.
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationEvent>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmailNotificationConfig {
+    pub recipients: Vec<String>,
+    /// Events that are sent by email, all events by default.
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationEvent>,
+    /// At most this many emails are sent in an hour, further notifications are dropped, so that
+    /// e.g. a broken CI does not flood the inboxes of the recipients.
+    #[serde(default = "default_email_max_per_hour")]
+    pub max_per_hour: u32,
+}
+
+/// Release management actions that are performed on a PR after it has been merged.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PostMergeConfig {
+    /// Assign the PR to the current milestone, which is the open milestone with the nearest
+    /// due date.
+    #[serde(default)]
+    pub assign_milestone: bool,
+    /// Labels added to every merged PR.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Labels added to PRs merged into a specific base branch (e.g. `beta-nominated` for
+    /// a release branch).
+    #[serde(default)]
+    pub branch_labels: HashMap<String, Vec<String>>,
+    /// Perform the actions also for PRs merged outside of bors. By default, they are performed
+    /// only for PRs that were approved in the queue of bors when they were merged.
+    #[serde(default)]
+    pub all_merged_prs: bool,
+}
+
+/// Describes how are excerpts of the logs of failed jobs stored.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogExcerptsConfig {
+    /// Maximum size of the excerpt of each failed job, in bytes.
+    #[serde(default = "default_log_excerpt_size")]
+    pub max_size: usize,
+    /// For how many days are the excerpts kept in the database.
+    #[serde(default = "default_log_retention_days")]
+    pub retention_days: u64,
+    /// Regular expressions matching the first line of an error (e.g. `^error(\[E\d+\])?:`).
+    /// The excerpt starts at the first matching line from which the rest of the log fits into the
+    /// excerpt, or it is the end of the log if no line matches, see [`crate::log_excerpt`].
+    #[serde(default)]
+    pub anchors: Vec<String>,
+    /// How many bytes at the end of the log are searched for the anchors.
+    #[serde(default = "default_log_search_size")]
+    pub search_size: usize,
+}
+
+impl Default for LogExcerptsConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_log_excerpt_size(),
+            retention_days: default_log_retention_days(),
+            anchors: vec![],
+            search_size: default_log_search_size(),
+        }
+    }
+}
+
+/// Describes how often and where is the digest of the repository posted. The digest is always
+/// sent to the notification routes that accept the `digest` event.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DigestConfig {
+    /// How many days are covered by a single digest.
+    #[serde(default = "default_digest_interval_days")]
+    pub interval_days: u64,
+    /// Number of a tracking issue where the digest is also posted.
+    #[serde(default)]
+    pub issue: Option<u64>,
+}
+
+fn default_digest_interval_days() -> u64 {
+    7
+}
+
+/// After how many days without a change is a PR in each of the states reminded about.
+/// Reminders about a state are disabled if its threshold is not set.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct StaleRemindersConfig {
+    /// The PR is approved, but its last build has failed.
+    #[serde(default)]
+    pub approved_failing_days: Option<u64>,
+    /// Review rights have been delegated, but the PR has not been approved.
+    #[serde(default)]
+    pub delegated_days: Option<u64>,
+    /// The last try build of the PR has failed and it has not been retried.
+    #[serde(default)]
+    pub failed_try_days: Option<u64>,
+}
+
+/// Describes when is the tree of the repository closed automatically. A tree that has been closed
+/// automatically is reopened by the next successful build.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TreeConfig {
+    /// The tree is closed once this many builds in a row have failed. It is never closed
+    /// automatically if this is not set.
+    #[serde(default)]
+    pub close_after_failures: Option<u32>,
+    /// PRs with at least this priority are still merged while the tree is closed automatically.
+    #[serde(default = "default_tree_closed_priority")]
+    pub closed_priority: u32,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        Self {
+            close_after_failures: None,
+            closed_priority: default_tree_closed_priority(),
+        }
+    }
+}
+
+fn default_tree_closed_priority() -> u32 {
+    1000
+}
+
+/// Top-level keys of [`RepositoryConfig`], including keys that were renamed.
+const CONFIG_KEYS: &[&str] = &[
+    "schema_version",
+    "build_timeout",
+    "timeout",
+    "labels",
+    "outdated_comments",
+    "summary_comment",
+    "summary_comment_delay",
+    "try_trigger",
+    "drafts",
+    "landing",
+    "required_labels",
+    "blocking_labels",
+    "approved_failure_label",
+    "limits",
+    "agreement",
+    "require_sign_off",
+    "require_verified_commits",
+    "review_approvals",
+    "post_merge",
+    "branch_protection",
+    "log_excerpts",
+    "operator_issue",
+    "command_prefix",
+    "ci",
+    "retry",
+    "messages",
+    "features",
+    "notifications",
+    "digest",
+    "stale_reminders",
+    "verbosity",
+    "tree",
+    "dry_run",
+];
+
+/// Checks the content of a repository configuration file.
+/// Returns a description of each found problem, the list is empty if the configuration is valid.
+pub fn validate_config(content: &str) -> Vec<String> {
+    let table: toml::Table = match toml::from_str(content) {
+        Ok(table) => table,
+        Err(error) => return vec![format!("Invalid TOML: {error}")],
+    };
+    let mut problems: Vec<String> = table
+        .keys()
+        .filter(|key| !CONFIG_KEYS.contains(&key.as_str()))
+        .map(|key| format!("Unknown key `{key}`"))
+        .collect();
+
+    let version = schema_version(&table);
+    for (old, new, since) in RENAMED_KEYS {
+        if version >= *since && table.contains_key(*old) {
+            problems.push(format!(
+                "`{old}` has been renamed to `{new}` in schema version {since}"
+            ));
+        }
+    }
+
+    match toml::from_str::<RepositoryConfig>(content) {
+        Ok(config) => problems.extend(config.inconsistencies()),
+        Err(error) => problems.push(format!("Invalid configuration: {error}")),
+    }
+    problems
+}
+
+/// A setting that still works, but should be updated, because it is deprecated.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigWarning {
+    /// Line of the configuration file (starting at 1) where the setting is located.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Finds deprecated settings in the content of a repository configuration file.
+pub fn config_warnings(content: &str) -> Vec<ConfigWarning> {
+    let Ok(table) = toml::from_str::<toml::Table>(content) else {
+        return vec![];
+    };
+    let version = schema_version(&table);
+    RENAMED_KEYS
+        .iter()
+        .filter(|(old, _, since)| version < *since && table.contains_key(*old))
+        .map(|(old, new, since)| ConfigWarning {
+            line: key_line(content, old),
+            message: format!(
+                "`{old}` is deprecated, rename it to `{new}` and set `schema_version = {since}`"
+            ),
+        })
+        .collect()
+}
+
+fn schema_version(table: &toml::Table) -> u32 {
+    table
+        .get("schema_version")
+        .and_then(|version| version.as_integer())
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or_else(default_schema_version)
+}
+
+/// Finds the line where a top-level key is set, i.e. before the first table header.
+fn key_line(content: &str, key: &str) -> usize {
+    content
+        .lines()
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .map_or(false, |rest| rest.trim_start().starts_with('='))
+        })
+        .map_or(1, |index| index + 1)
+}
+
+impl RepositoryConfig {
+    /// Finds settings that are valid on their own, but do not make sense together.
+    pub fn inconsistencies(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.schema_version == 0 || self.schema_version > CURRENT_SCHEMA_VERSION {
+            problems.push(format!(
+                "`schema_version` {} is not supported, the newest supported version is {CURRENT_SCHEMA_VERSION}",
+                self.schema_version
+            ));
+        }
+        if self.timeout.is_zero() {
+            problems.push("`build_timeout` must be larger than zero".to_string());
+        }
+        for label in &self.required_labels {
+            if self.blocking_labels.contains(label) {
+                problems.push(format!(
+                    "Label `{label}` is both required and blocking, so no PR could ever be merged"
+                ));
+            }
+        }
+        if let Some(label) = &self.approved_failure_label {
+            if label.trim().is_empty() {
+                problems.push("`approved_failure_label` must not be empty".to_string());
+            }
+        }
+        match &self.try_trigger {
+            TryTrigger::WorkflowDispatch { workflow } if workflow.trim().is_empty() => {
+                problems.push("`try_trigger.workflow` must not be empty".to_string());
+            }
+            TryTrigger::Buildkite { pipeline } if pipeline.split('/').count() != 2 => {
+                problems.push(format!(
+                    "`try_trigger.pipeline` must have the form `organization/pipeline`, found `{pipeline}`"
+                ));
+            }
+            TryTrigger::AzurePipelines { pipeline }
+                if !matches!(
+                    pipeline.split('/').collect::<Vec<_>>()[..],
+                    [_, _, definition] if definition.parse::<u64>().is_ok()
+                ) =>
+            {
+                problems.push(format!(
+                    "`try_trigger.pipeline` must have the form `organization/project/definition ID`, found `{pipeline}`"
+                ));
+            }
+            TryTrigger::Jenkins { job } if job.split('/').any(|name| name.trim().is_empty()) => {
+                problems.push(format!(
+                    "`try_trigger.job` is not a valid job name: `{job}`"
+                ));
+            }
+            TryTrigger::CircleCi { pipeline }
+                if pipeline.split('/').count() != 4
+                    || pipeline.split('/').any(|part| part.trim().is_empty()) =>
+            {
+                problems.push(format!(
+                    "`try_trigger.pipeline` must have the form `vcs/organization/repository/workflow`, found `{pipeline}`"
+                ));
+            }
+            _ => {}
+        }
+        if !self.branch_protection.required_checks.is_empty()
+            && self.branch_protection.branches.is_empty()
+        {
+            problems.push(
+                "`branch_protection.required_checks` have no effect without `branch_protection.branches`"
+                    .to_string(),
+            );
+        }
+        if let Some(log_excerpts) = &self.log_excerpts {
+            if log_excerpts.max_size == 0 {
+                problems.push("`log_excerpts.max_size` must be larger than zero".to_string());
+            }
+            for anchor in &log_excerpts.anchors {
+                if let Err(error) = regex::Regex::new(anchor) {
+                    problems.push(format!(
+                        "`log_excerpts.anchors` contains an invalid regular expression `{anchor}`: {error}"
+                    ));
+                }
+            }
+        }
+        for workflow in &self.ci.try_workflows {
+            if self.ci.informational_workflows.contains(workflow) {
+                problems.push(format!(
+                    "Workflow `{workflow}` is both a try workflow and an informational workflow"
+                ));
+            }
+        }
+        if self.retry.max_retries > MAX_RETRIES {
+            problems.push(format!(
+                "`retry.max_retries` must be at most {MAX_RETRIES}, found {}",
+                self.retry.max_retries
+            ));
+        }
+        if self.retry.backoff_multiplier == 0 {
+            problems.push("`retry.backoff_multiplier` must be at least 1".to_string());
+        }
+        if self.retry.max_retries > 0 && !self.features.is_enabled(Feature::AutoRetry) {
+            problems.push(
+                "`retry` has no effect while the `auto_retry` feature is disabled".to_string(),
+            );
+        }
+        problems.extend(self.messages.unknown_placeholders());
+        if let Some(prefix) = &self.command_prefix {
+            if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+                problems.push(format!(
+                    "`command_prefix` must be non-empty and must not contain whitespace, found `{prefix}`"
+                ));
+            }
+        }
+        if let Some(digest) = &self.digest {
+            if digest.interval_days == 0 {
+                problems.push("`digest.interval_days` must be larger than zero".to_string());
+            }
+        }
+        if self.tree.close_after_failures == Some(0) {
+            problems.push("`tree.close_after_failures` must be larger than zero".to_string());
+        }
+        if let Some(zulip) = &self.notifications.zulip {
+            if zulip.events.is_empty() {
+                problems.push("`notifications.zulip.events` must not be empty".to_string());
+            }
+        }
+        if let Some(email) = &self.notifications.email {
+            if email.recipients.is_empty() {
+                problems.push("`notifications.email.recipients` must not be empty".to_string());
+            }
+            for recipient in &email.recipients {
+                if !recipient.contains('@') {
+                    problems.push(format!(
+                        "`notifications.email.recipients` contains an invalid address `{recipient}`"
+                    ));
+                }
+            }
+            if email.events.is_empty() {
+                problems.push("`notifications.email.events` must not be empty".to_string());
+            }
+            if email.max_per_hour == 0 {
+                problems.push(
+                    "`notifications.email.max_per_hour` must be larger than zero".to_string(),
+                );
+            }
+        }
+        for webhook in &self.notifications.webhooks {
+            if webhook.events.is_empty() {
+                problems.push(format!(
+                    "`events` of notification webhook `{}` must not be empty",
+                    webhook.webhook
+                ));
+            }
+        }
+        problems
+    }
+}
+
+fn default_retry_backoff() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_backoff_multiplier() -> u32 {
+    2
+}
+
+fn default_zulip_topic() -> String {
+    "bors".to_string()
+}
+
+fn default_notification_events() -> Vec<NotificationEvent> {
+    NotificationEvent::ALL.to_vec()
+}
+
+fn default_email_max_per_hour() -> u32 {
+    10
+}
+
+fn default_log_excerpt_size() -> usize {
+    4096
+}
+
+fn default_log_retention_days() -> u64 {
+    30
+}
+
+fn default_log_search_size() -> usize {
+    64 * 1024
+}
+
+fn default_schema_version() -> u32 {
+    1
 }
 
 fn default_timeout() -> Duration {
@@ -109,7 +972,13 @@ where
 mod tests {
     use std::collections::BTreeMap;
 
-    use crate::config::{default_timeout, RepositoryConfig};
+    use crate::config::{
+        config_warnings, default_timeout, validate_config, workflow_matches, AgreementConfig,
+        ConfigWarning, DraftPolicy, EmailNotificationConfig, Feature, LandingMode,
+        OutdatedCommentsMode, PostMergeConfig, PullRequestLimits, RepositoryConfig, TryTrigger,
+        WebhookNotificationConfig, ZulipNotificationConfig,
+    };
+    use crate::notifications::{NotificationEvent, WebhookFormat};
 
     #[test]
     fn deserialize_empty() {
@@ -125,6 +994,227 @@ mod tests {
         assert_eq!(config.timeout.as_secs(), 3600);
     }
 
+    #[test]
+    fn deserialize_outdated_comments() {
+        let config = load_config("");
+        assert_eq!(config.outdated_comments, OutdatedCommentsMode::Append);
+
+        let config = load_config(r#"outdated_comments = "minimize""#);
+        assert_eq!(config.outdated_comments, OutdatedCommentsMode::Minimize);
+    }
+
+    #[test]
+    fn deserialize_try_trigger() {
+        let config = load_config("");
+        assert_eq!(config.try_trigger, TryTrigger::Push);
+
+        let content = r#"[try_trigger]
+workflow_dispatch = { workflow = "try.yml" }
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.try_trigger,
+            TryTrigger::WorkflowDispatch {
+                workflow: "try.yml".to_string()
+            }
+        );
+
+        let content = r#"[try_trigger]
+buildkite = { pipeline = "acme/app" }
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.try_trigger.ci_pipeline(),
+            Some(("buildkite", "acme/app"))
+        );
+
+        let content = r#"[try_trigger]
+azure_pipelines = { pipeline = "acme/app/12" }
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.try_trigger.ci_pipeline(),
+            Some(("azure_pipelines", "acme/app/12"))
+        );
+
+        let content = r#"[try_trigger]
+jenkins = { job = "rust/try" }
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.try_trigger.ci_pipeline(),
+            Some(("jenkins", "rust/try"))
+        );
+
+        let content = r#"[try_trigger]
+circleci = { pipeline = "gh/acme/app/test" }
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.try_trigger.ci_pipeline(),
+            Some(("circleci", "gh/acme/app/test"))
+        );
+    }
+
+    #[test]
+    fn deserialize_drafts() {
+        let config = load_config("");
+        assert_eq!(config.drafts, DraftPolicy::Block);
+
+        let config = load_config(r#"drafts = "allow_try""#);
+        assert_eq!(config.drafts, DraftPolicy::AllowTry);
+    }
+
+    #[test]
+    fn deserialize_landing() {
+        let config = load_config("");
+        assert_eq!(config.landing, LandingMode::Bors);
+
+        let config = load_config(r#"landing = "github_merge_queue""#);
+        assert_eq!(config.landing, LandingMode::GithubMergeQueue);
+    }
+
+    #[test]
+    fn deserialize_post_merge() {
+        let content = r#"[post_merge]
+assign_milestone = true
+labels = ["merged-by-bors"]
+branch_labels = { beta = ["beta-nominated"] }
+all_merged_prs = true
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.post_merge,
+            PostMergeConfig {
+                assign_milestone: true,
+                labels: vec!["merged-by-bors".to_string()],
+                branch_labels: [("beta".to_string(), vec!["beta-nominated".to_string()])]
+                    .into_iter()
+                    .collect(),
+                all_merged_prs: true,
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_limits() {
+        let content = r#"[limits]
+max_changed_lines = 1000
+forbidden_paths = ["src/generated/"]
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.limits,
+            PullRequestLimits {
+                max_changed_lines: Some(1000),
+                max_changed_files: None,
+                forbidden_paths: vec!["src/generated/".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_log_excerpt_anchor() {
+        let problems = validate_config("[log_excerpts]\nanchors = [\"(\"]");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0]
+            .starts_with("`log_excerpts.anchors` contains an invalid regular expression `(`"));
+    }
+
+    #[test]
+    fn deserialize_zulip_notifications() {
+        let content = r#"[notifications.zulip]
+stream = "t-infra"
+events = ["build_failed", "bot_error"]
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.notifications.zulip,
+            Some(ZulipNotificationConfig {
+                stream: "t-infra".to_string(),
+                topic: "bors".to_string(),
+                events: vec![NotificationEvent::BuildFailed, NotificationEvent::BotError],
+            })
+        );
+        assert!(validate_config(content).is_empty());
+        assert_eq!(
+            validate_config("[notifications.zulip]\nstream = \"t-infra\"\nevents = []"),
+            vec!["`notifications.zulip.events` must not be empty".to_string()]
+        );
+    }
+
+    #[test]
+    fn deserialize_email_notifications() {
+        let content = r#"[notifications.email]
+recipients = ["maintainers@example.com"]
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.notifications.email,
+            Some(EmailNotificationConfig {
+                recipients: vec!["maintainers@example.com".to_string()],
+                events: NotificationEvent::ALL.to_vec(),
+                max_per_hour: 10,
+            })
+        );
+        assert!(validate_config(content).is_empty());
+        assert_eq!(
+            validate_config(
+                "[notifications.email]\nrecipients = [\"maintainers\"]\nmax_per_hour = 0"
+            ),
+            vec![
+                "`notifications.email.recipients` contains an invalid address `maintainers`"
+                    .to_string(),
+                "`notifications.email.max_per_hour` must be larger than zero".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_notification_webhooks() {
+        let content = r#"[[notifications.webhooks]]
+webhook = "infra-slack"
+format = "slack"
+events = ["build_failed"]
+
+[[notifications.webhooks]]
+webhook = "ops-teams"
+format = "teams"
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.notifications.webhooks,
+            vec![
+                WebhookNotificationConfig {
+                    webhook: "infra-slack".to_string(),
+                    format: WebhookFormat::Slack,
+                    events: vec![NotificationEvent::BuildFailed],
+                },
+                WebhookNotificationConfig {
+                    webhook: "ops-teams".to_string(),
+                    format: WebhookFormat::Teams,
+                    events: NotificationEvent::ALL.to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_agreement() {
+        let content = r#"[agreement]
+url = "https://cla.example.com/api/check"
+sign_url = "https://cla.example.com/sign"
+"#;
+        let config = load_config(content);
+        assert_eq!(
+            config.agreement,
+            Some(AgreementConfig {
+                url: "https://cla.example.com/api/check".to_string(),
+                sign_url: "https://cla.example.com/sign".to_string(),
+            })
+        );
+    }
+
     #[test]
     fn deserialize_labels() {
         let content = r#"[labels]
@@ -159,6 +1249,155 @@ try_failed = []
         "###);
     }
 
+    #[test]
+    fn deserialize_command_prefix() {
+        let config = load_config("");
+        assert_eq!(config.command_prefix, None);
+        let config = load_config(r#"command_prefix = "@ci""#);
+        assert_eq!(config.command_prefix.as_deref(), Some("@ci"));
+    }
+
+    #[test]
+    fn deserialize_ci() {
+        let content = r#"[ci]
+try_workflows = ["CI"]
+informational_workflows = ["Docs"]
+"#;
+        let config = load_config(content);
+        assert!(config.ci.is_try_workflow("CI"));
+        assert!(!config.ci.is_try_workflow("Docs"));
+        assert!(!config.ci.is_try_workflow("Other"));
+    }
+
+    #[test]
+    fn workflow_patterns() {
+        assert!(workflow_matches("CI", "CI"));
+        assert!(!workflow_matches("CI", "CI (push)"));
+        assert!(workflow_matches("ci/*", "ci/linux"));
+        assert!(workflow_matches("*linux*", "ci/linux (x64)"));
+        assert!(workflow_matches("a*b*b", "abb"));
+        assert!(!workflow_matches("a*b*b", "ab"));
+        assert!(!workflow_matches("ci/*", "docs"));
+    }
+
+    #[test]
+    fn deserialize_retry() {
+        let config = load_config("");
+        assert_eq!(config.retry.max_retries, 0);
+
+        let content = r#"[retry]
+max_retries = 3
+backoff = 60
+"#;
+        let config = load_config(content);
+        assert_eq!(config.retry.max_retries, 3);
+        assert_eq!(config.retry.delay(0).as_secs(), 60);
+        assert_eq!(config.retry.delay(2).as_secs(), 240);
+    }
+
+    #[test]
+    fn deserialize_features() {
+        let config = load_config("");
+        assert!(config.features.is_enabled(Feature::AutoRetry));
+        assert!(!config.features.is_enabled(Feature::Rollups));
+
+        let content = r#"[features]
+auto_retry = false
+rollups = true
+"#;
+        let config = load_config(content);
+        assert!(!config.features.is_enabled(Feature::AutoRetry));
+        assert!(config.features.is_enabled(Feature::Rollups));
+        assert!(config.features.is_enabled(Feature::TryOnForks));
+    }
+
+    #[test]
+    fn validate_valid_config() {
+        let content = r#"
+timeout = 3600
+required_labels = ["S-waiting-on-bors"]
+blocking_labels = ["S-blocked"]
+command_prefix = "@ci"
+
+[branch_protection]
+branches = ["main"]
+required_checks = ["ci"]
+"#;
+        assert!(validate_config(content).is_empty());
+    }
+
+    #[test]
+    fn validate_unknown_keys() {
+        let content = r#"
+timeout = 3600
+timout = 7200
+"#;
+        assert_eq!(validate_config(content), vec!["Unknown key `timout`"]);
+    }
+
+    #[test]
+    fn validate_inconsistent_config() {
+        let content = r#"
+timeout = 0
+required_labels = ["foo"]
+blocking_labels = ["foo"]
+"#;
+        insta::assert_debug_snapshot!(validate_config(content), @r###"
+        [
+            "`build_timeout` must be larger than zero",
+            "Label `foo` is both required and blocking, so no PR could ever be merged",
+        ]
+        "###);
+    }
+
+    #[test]
+    fn deprecated_key_in_old_schema() {
+        let content = r#"
+required_labels = ["foo"]
+timeout = 600
+
+[retry]
+max_retries = 1
+"#;
+        assert_eq!(load_config(content).timeout.as_secs(), 600);
+        assert!(validate_config(content).is_empty());
+        assert_eq!(
+            config_warnings(content),
+            vec![ConfigWarning {
+                line: 3,
+                message:
+                    "`timeout` is deprecated, rename it to `build_timeout` and set `schema_version = 2`"
+                        .to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn deprecated_key_in_new_schema() {
+        let content = r#"
+schema_version = 2
+timeout = 600
+"#;
+        assert!(config_warnings(content).is_empty());
+        assert_eq!(
+            validate_config(content),
+            vec!["`timeout` has been renamed to `build_timeout` in schema version 2"]
+        );
+
+        let content = "schema_version = 2\nbuild_timeout = 600";
+        assert!(config_warnings(content).is_empty());
+        assert!(validate_config(content).is_empty());
+        assert_eq!(load_config(content).timeout.as_secs(), 600);
+    }
+
+    #[test]
+    fn unsupported_schema_version() {
+        assert_eq!(
+            validate_config("schema_version = 3"),
+            vec!["`schema_version` 3 is not supported, the newest supported version is 2"]
+        );
+    }
+
     #[test]
     #[should_panic(expected = "Label modification must start with `+` or `-`")]
     fn deserialize_labels_missing_prefix() {
@@ -0,0 +1,150 @@
+//! Discrepancies between the persisted state of the bot and the state of GitHub, found by the
+//! periodic consistency sweep (see [`crate::bors::event::BorsEvent::Sweep`]). They are usually
+//! caused by lost webhooks, and the sweep repairs them.
+//!
+//! The report of the last sweep of each repository is returned by `/admin/consistency` and the
+//! number of discrepancies is exposed at `/metrics`. Both are kept in memory, so they are reset
+//! when the bot is restarted.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::github::{GithubRepoName, PullRequestNumber};
+use crate::metrics::escape_label;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    /// The branch of a running build has been deleted or moved to a different commit, so the
+    /// build has been cancelled.
+    BuildBranchChanged,
+    /// All checks of a running build have finished, so the build has been completed.
+    BuildFinished,
+    /// An approved PR has been closed or merged, so it has been removed from the merge queue.
+    PullRequestClosed,
+}
+
+impl DiscrepancyKind {
+    fn name(self) -> &'static str {
+        match self {
+            DiscrepancyKind::BuildBranchChanged => "build_branch_changed",
+            DiscrepancyKind::BuildFinished => "build_finished",
+            DiscrepancyKind::PullRequestClosed => "pull_request_closed",
+        }
+    }
+}
+
+/// A difference between the persisted state and GitHub, which has been repaired by the sweep.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub kind: DiscrepancyKind,
+    pub pr: Option<u64>,
+    pub description: String,
+}
+
+impl Discrepancy {
+    pub fn new(
+        kind: DiscrepancyKind,
+        pr: Option<PullRequestNumber>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind,
+            pr: pr.map(|pr| pr.0),
+            description: description.into(),
+        }
+    }
+}
+
+/// Result of the last sweep of a repository.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct SweepReport {
+    pub repository: String,
+    pub swept_at: DateTime<Utc>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Reports of the sweeps of all repositories, shared by the event loops and the server.
+#[derive(Debug, Default)]
+pub struct ConsistencyReports {
+    reports: Mutex<BTreeMap<String, SweepReport>>,
+    counts: Mutex<BTreeMap<(String, DiscrepancyKind), u64>>,
+}
+
+impl ConsistencyReports {
+    /// Replaces the report of the repository with the result of its latest sweep.
+    pub fn record(&self, repo: &GithubRepoName, discrepancies: Vec<Discrepancy>) {
+        let mut counts = self.counts.lock().unwrap();
+        for discrepancy in &discrepancies {
+            *counts
+                .entry((repo.to_string(), discrepancy.kind))
+                .or_default() += 1;
+        }
+        self.reports.lock().unwrap().insert(
+            repo.to_string(),
+            SweepReport {
+                repository: repo.to_string(),
+                swept_at: Utc::now(),
+                discrepancies,
+            },
+        );
+    }
+
+    /// Returns the report of the last sweep of every repository.
+    pub fn reports(&self) -> Vec<SweepReport> {
+        self.reports.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Returns the number of discrepancies of the given kind found in the repository since the
+    /// bot has started.
+    pub fn count(&self, repo: &GithubRepoName, kind: DiscrepancyKind) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        counts.get(&(repo.to_string(), kind)).copied().unwrap_or(0)
+    }
+
+    /// Renders the counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP bors_consistency_discrepancies_total Number of discrepancies between the state of the bot and GitHub repaired by the consistency sweep.\n# TYPE bors_consistency_discrepancies_total counter\n",
+        );
+        for ((repo, kind), count) in self.counts.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_consistency_discrepancies_total{{repository="{}",kind="{}"}} {count}"#,
+                escape_label(repo),
+                kind.name()
+            );
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consistency::{ConsistencyReports, Discrepancy, DiscrepancyKind};
+    use crate::github::PullRequestNumber;
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn record_reports() {
+        let reports = ConsistencyReports::default();
+        let repo = default_repo_name();
+        let closed = Discrepancy::new(
+            DiscrepancyKind::PullRequestClosed,
+            Some(PullRequestNumber(1)),
+            "PR was closed",
+        );
+        reports.record(&repo, vec![closed]);
+        reports.record(&repo, vec![]);
+        // Only the last sweep is reported, but all discrepancies are counted
+        assert!(reports.reports()[0].discrepancies.is_empty());
+        assert_eq!(reports.count(&repo, DiscrepancyKind::PullRequestClosed), 1);
+        insta::assert_snapshot!(reports.render(), @r###"
+        # HELP bors_consistency_discrepancies_total Number of discrepancies between the state of the bot and GitHub repaired by the consistency sweep.
+        # TYPE bors_consistency_discrepancies_total counter
+        bors_consistency_discrepancies_total{repository="owner/name",kind="pull_request_closed"} 1
+        "###);
+    }
+}
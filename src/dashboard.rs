@@ -0,0 +1,412 @@
+//! Web pages that show the state of the bot to the maintainers of a repository.
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+
+use crate::bors::AUTO_BRANCH_NAME;
+use crate::database::{BuildStatus, DbClient, PullRequestModel, TreeClosure};
+use crate::github::GithubRepoName;
+
+/// Notifies open dashboard pages that the state shown by them might have changed.
+pub type DashboardUpdates = broadcast::Sender<DashboardUpdate>;
+
+/// Sent after the bot has processed an event that might have changed the state of a repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashboardUpdate {
+    /// `None` if the state of all repositories might have changed.
+    pub repository: Option<GithubRepoName>,
+}
+
+impl DashboardUpdate {
+    pub fn affects(&self, repo: &GithubRepoName) -> bool {
+        self.repository
+            .as_ref()
+            .map_or(true, |repository| repository == repo)
+    }
+}
+
+/// A PR shown on the queue page.
+#[derive(Debug, PartialEq)]
+pub struct QueueEntry {
+    pub number: u64,
+    pub status: QueueEntryStatus,
+    pub priority: u32,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+    /// Status of the last try build of the PR.
+    pub try_build: Option<&'static str>,
+}
+
+/// Describes what is happening with a PR on the queue page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueEntryStatus {
+    /// The PR is being built before being merged.
+    Building,
+    /// The PR is approved and waits in the queue.
+    Approved,
+    /// The PR is not approved, but it has a running try build.
+    Testing,
+}
+
+impl QueueEntryStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            QueueEntryStatus::Building => "building",
+            QueueEntryStatus::Approved => "approved",
+            QueueEntryStatus::Testing => "testing",
+        }
+    }
+}
+
+impl QueueEntry {
+    fn new(pr: &PullRequestModel) -> Self {
+        let status = if is_merge_build_running(pr) {
+            QueueEntryStatus::Building
+        } else if pr.is_approved() {
+            QueueEntryStatus::Approved
+        } else {
+            QueueEntryStatus::Testing
+        };
+        Self {
+            number: pr.number.0,
+            status,
+            priority: pr.priority,
+            approved_by: pr.approved_by.clone(),
+            approved_at: pr.approved_at,
+            try_build: pr
+                .try_build
+                .as_ref()
+                .map(|build| build_status_name(&build.status)),
+        }
+    }
+}
+
+/// Is the PR being built before being merged? Merge builds run on the auto branch.
+pub fn is_merge_build_running(pr: &PullRequestModel) -> bool {
+    pr.try_build.as_ref().map_or(false, |build| {
+        build.branch == AUTO_BRANCH_NAME && build.status == BuildStatus::Pending
+    })
+}
+
+/// Loads the PRs of the repository that are interesting for its maintainers: approved PRs in the
+/// order of the merge queue, followed by PRs with a running try build.
+pub async fn load_queue(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+) -> anyhow::Result<Vec<QueueEntry>> {
+    let mut entries: Vec<QueueEntry> = db
+        .get_approved_pull_requests(repo)
+        .await?
+        .iter()
+        .map(QueueEntry::new)
+        .collect();
+    for build in db.get_running_builds(repo).await? {
+        if let Some(pr) = db.find_pr_by_build(&build).await? {
+            if !entries.iter().any(|entry| entry.number == pr.number.0) {
+                entries.push(QueueEntry::new(&pr));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Loads the queue of the repository (see [`load_queue`]) together with the closure of its tree,
+/// if the tree is closed.
+pub async fn load_queue_and_tree(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+) -> anyhow::Result<(Vec<QueueEntry>, Option<TreeClosure>)> {
+    Ok((
+        load_queue(db, repo).await?,
+        db.get_tree_closure(repo).await?,
+    ))
+}
+
+/// Who is looking at a dashboard page.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Viewer {
+    /// Login to the dashboard is not configured.
+    Anonymous,
+    /// Login is configured, but the user has not logged in.
+    LoggedOut,
+    /// A logged in user, who can use the admin actions of the dashboard.
+    LoggedIn(String),
+}
+
+/// Renders an HTML page with the queue of the given repository.
+/// The page replaces the queue with new versions received from the `events` endpoint.
+pub fn render_queue_page(
+    repo: &GithubRepoName,
+    entries: &[QueueEntry],
+    tree: Option<&TreeClosure>,
+    viewer: &Viewer,
+) -> String {
+    let repo_name = escape_html(&repo.to_string());
+    let login = match viewer {
+        Viewer::Anonymous => String::new(),
+        Viewer::LoggedOut => {
+            format!(
+                r#"<p><a href="/login?return_to=/queue/{repo_name}">Log in with GitHub</a></p>"#
+            )
+        }
+        Viewer::LoggedIn(user) => format!("<p>Logged in as {}</p>", escape_html(user)),
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Queue of {repo_name}</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}
+tr.building {{ background: #fff3c4; }}
+tr.testing {{ color: #666; }}
+.tree-closed {{ background: #ffd7d5; border: 1px solid #cf222e; padding: 4px 8px; }}
+</style>
+</head>
+<body>
+<h1>Queue of {repo_name}</h1>
+{login}
+<div id="queue">
+{queue}</div>
+<script>
+new EventSource("/queue/{repo_name}/events").onmessage = (event) => {{
+    document.getElementById("queue").innerHTML = event.data;
+}};
+</script>
+</body>
+</html>
+"#,
+        queue = render_queue(repo, entries, tree, viewer),
+    )
+}
+
+/// Renders the summary and the table of the queue, which are updated live on the queue page.
+/// If the tree is closed, a banner describing the closure is pinned above them.
+/// Logged in users also see buttons that retry the try build of a PR, change its priority or
+/// remove it from the queue.
+pub fn render_queue(
+    repo: &GithubRepoName,
+    entries: &[QueueEntry],
+    tree: Option<&TreeClosure>,
+    viewer: &Viewer,
+) -> String {
+    let actions = matches!(viewer, Viewer::LoggedIn(_));
+    let repo_name = escape_html(&repo.to_string());
+    let approved = entries
+        .iter()
+        .filter(|entry| entry.status != QueueEntryStatus::Testing)
+        .count();
+    let building = entries
+        .iter()
+        .any(|entry| entry.status == QueueEntryStatus::Building);
+
+    let mut rows = String::new();
+    for entry in entries {
+        let _ = write!(
+            rows,
+            r#"<tr class="{status}"><td><a href="https://github.com/{repo_name}/pull/{number}">#{number}</a></td><td>{status}</td><td>{priority}</td><td>{approver}</td><td>{approved_at}</td><td>{try_build}</td>"#,
+            status = entry.status.name(),
+            number = entry.number,
+            priority = entry.priority,
+            approver = escape_html(entry.approved_by.as_deref().unwrap_or("")),
+            approved_at = entry
+                .approved_at
+                .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default(),
+            try_build = entry.try_build.unwrap_or(""),
+        );
+        if actions {
+            let _ = write!(
+                rows,
+                r#"<td><form method="post" action="/queue/{repo_name}/prs/{number}/retry"><button>Retry</button></form><form method="post" action="/queue/{repo_name}/prs/{number}/priority"><input name="priority" type="number" min="0" value="{priority}"><button>Set priority</button></form><form method="post" action="/queue/{repo_name}/prs/{number}/dequeue"><button>Remove</button></form></td>"#,
+                number = entry.number,
+                priority = entry.priority,
+            );
+        }
+        rows.push_str("</tr>\n");
+    }
+
+    let banner = match tree {
+        Some(closure) => format!(
+            r#"<p class="tree-closed">Tree closed for PRs below priority {priority} {who} at {closed_at}: {reason}</p>
+"#,
+            priority = closure.priority,
+            who = match &closure.closed_by {
+                Some(user) => format!("by {}", escape_html(user)),
+                None => "automatically".to_string(),
+            },
+            closed_at = closure.closed_at.format("%Y-%m-%d %H:%M UTC"),
+            reason = escape_html(&closure.reason),
+        ),
+        None => String::new(),
+    };
+
+    format!(
+        r#"{banner}<p>{approved} approved PR(s), merge build: {merge_build}</p>
+<table>
+<tr><th>PR</th><th>Status</th><th>Priority</th><th>Approved by</th><th>Approved at</th><th>Try build</th>{actions_header}</tr>
+{rows}</table>
+"#,
+        merge_build = if building { "running" } else { "idle" },
+        actions_header = if actions { "<th>Actions</th>" } else { "" },
+    )
+}
+
+pub(crate) fn build_status_name(status: &BuildStatus) -> &'static str {
+    match status {
+        BuildStatus::Pending => "pending",
+        BuildStatus::Success => "success",
+        BuildStatus::Failure => "failure",
+        BuildStatus::Cancelled => "cancelled",
+        BuildStatus::Timeouted => "timed out",
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::AUTO_BRANCH_NAME;
+    use crate::dashboard::{
+        load_queue, render_queue, render_queue_page, DashboardUpdate, QueueEntryStatus, Viewer,
+    };
+    use crate::database::{DbClient, TryBuildArgs};
+    use crate::github::GithubRepoName;
+    use crate::github::{CommitSha, PullRequestNumber};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn queue_order_and_status() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr1 = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        let pr2 = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap();
+        let pr3 = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(3))
+            .await
+            .unwrap();
+        db.approve(&pr1, "foo", None).await.unwrap();
+        db.approve(&pr2, "<bar>", None).await.unwrap();
+        db.set_priority(&pr2, 5).await.unwrap();
+        db.attach_try_build(
+            pr3,
+            "automation/bors/try".to_string(),
+            CommitSha("sha".to_string()),
+            0,
+            TryBuildArgs::default(),
+        )
+        .await
+        .unwrap();
+
+        let entries = load_queue(&db, &repo).await.unwrap();
+        let order: Vec<(u64, QueueEntryStatus)> = entries
+            .iter()
+            .map(|entry| (entry.number, entry.status))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (2, QueueEntryStatus::Approved),
+                (1, QueueEntryStatus::Approved),
+                (3, QueueEntryStatus::Testing)
+            ]
+        );
+        assert_eq!(entries[2].try_build, Some("pending"));
+
+        let page = render_queue_page(&repo, &entries, None, &Viewer::Anonymous);
+        assert!(page.contains("<title>Queue of owner/name</title>"));
+        assert!(page.contains("2 approved PR(s), merge build: idle"));
+        assert!(page.contains("&lt;bar&gt;"));
+        assert!(page.contains(r#"new EventSource("/queue/owner/name/events")"#));
+        assert!(!page.contains("<form"));
+        assert!(!page.contains(r#"<p class="tree-closed">"#));
+    }
+
+    #[tokio::test]
+    async fn merge_build_status() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.approve(&pr, "foo", None).await.unwrap();
+        db.attach_try_build(
+            pr,
+            AUTO_BRANCH_NAME.to_string(),
+            CommitSha("sha".to_string()),
+            0,
+            TryBuildArgs::default(),
+        )
+        .await
+        .unwrap();
+
+        let entries = load_queue(&db, &repo).await.unwrap();
+        assert_eq!(entries[0].status, QueueEntryStatus::Building);
+        let page = render_queue_page(&repo, &entries, None, &Viewer::Anonymous);
+        assert!(page.contains("1 approved PR(s), merge build: running"));
+    }
+
+    #[tokio::test]
+    async fn actions_for_logged_in_users() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.approve(&pr, "foo", None).await.unwrap();
+        let entries = load_queue(&db, &repo).await.unwrap();
+
+        let page = render_queue_page(&repo, &entries, None, &Viewer::LoggedOut);
+        assert!(page.contains(r#"<a href="/login?return_to=/queue/owner/name">"#));
+        assert!(!page.contains("<form"));
+
+        let queue = render_queue(&repo, &entries, None, &Viewer::LoggedIn("foo".to_string()));
+        assert!(queue.contains(r#"<form method="post" action="/queue/owner/name/prs/1/dequeue">"#));
+    }
+
+    #[tokio::test]
+    async fn tree_closure_banner() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        db.close_tree(&repo, 100, Some("<foo>"), "CI is broken")
+            .await
+            .unwrap();
+        let tree = db.get_tree_closure(&repo).await.unwrap();
+
+        let queue = render_queue(&repo, &[], tree.as_ref(), &Viewer::Anonymous);
+        assert!(queue.starts_with(
+            r#"<p class="tree-closed">Tree closed for PRs below priority 100 by &lt;foo&gt; at "#
+        ));
+        assert!(queue.contains(": CI is broken</p>"));
+    }
+
+    #[test]
+    fn update_affects_repository() {
+        let repo = default_repo_name();
+        let other = GithubRepoName::new("owner", "other");
+        let update = DashboardUpdate {
+            repository: Some(repo.clone()),
+        };
+        assert!(update.affects(&repo));
+        assert!(!update.affects(&other));
+        assert!(DashboardUpdate { repository: None }.affects(&other));
+    }
+}
@@ -10,12 +10,33 @@ use octocrab::models::RunId;
 
 pub use sea_orm_client::SeaORMClient;
 
-use crate::github::PullRequestNumber;
+use crate::github::{CommentId, PullRequestNumber};
 use crate::github::{CommitSha, GithubRepoName};
 
 mod sea_orm_client;
 
-type PrimaryKey = i32;
+pub type PrimaryKey = i32;
+
+/// Prepares a database connection string for connecting to the database.
+///
+/// SQLite databases (used for small deployments) are created if they do not exist yet, so that
+/// the bot can be started without any manual setup. Postgres databases have to be created by
+/// the operator.
+pub fn normalize_connection_string(connection_string: &str) -> String {
+    if connection_string.starts_with("sqlite:")
+        && connection_string != "sqlite::memory:"
+        && !connection_string.contains("mode=")
+    {
+        let separator = if connection_string.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!("{connection_string}{separator}mode=rwc")
+    } else {
+        connection_string.to_string()
+    }
+}
 
 /// Status of a GitHub build.
 #[derive(Debug, PartialEq)]
@@ -40,6 +61,26 @@ pub struct BuildModel {
     pub commit_sha: String,
     pub status: BuildStatus,
     pub created_at: DateTime<Utc>,
+    /// How many times has the build been retried automatically (0 for the first run).
+    pub attempt: u32,
+    /// When should the failed build be retried automatically, if at all.
+    pub retry_at: Option<DateTime<Utc>>,
+    /// Arguments of the try build, used when the build is retried.
+    pub args: TryBuildArgs,
+}
+
+/// Arguments with which a try build was started, so that an automatic retry tests the same
+/// commits in the same way.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TryBuildArgs {
+    /// Head of the PR when the build was started. The build is not retried once the head changes.
+    pub pr_head_sha: Option<CommitSha>,
+    /// Base commit of the merge given by `parent=`.
+    pub parent: Option<CommitSha>,
+    /// Commit of the PR tested instead of its head, given by `commit=`.
+    pub commit: Option<CommitSha>,
+    /// Job filter given by `jobs=`.
+    pub jobs: Option<String>,
 }
 
 /// Represents a pull request.
@@ -49,18 +90,140 @@ pub struct PullRequestModel {
     pub number: PullRequestNumber,
     pub try_build: Option<BuildModel>,
     pub created_at: DateTime<Utc>,
+    /// The last status comment posted by the bot to this PR.
+    pub status_comment: Option<CommentId>,
+    /// Username of the reviewer that has approved this PR.
+    /// Approved PRs are queued for being merged.
+    pub approved_by: Option<String>,
+    /// The comment of the bot that summarizes the current state of this PR.
+    pub summary_comment: Option<CommentId>,
+    /// When was this PR approved.
+    pub approved_at: Option<DateTime<Utc>>,
+    /// Priority of the PR in the merge queue. PRs with a higher priority are merged first.
+    pub priority: u32,
+    /// How should the PR be handled when creating rollups.
+    pub rollup: Option<RollupMode>,
+    /// Username of the user that has been delegated review rights for this PR.
+    pub delegated_to: Option<String>,
+    /// When did the bors state of this PR change for the last time.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Head commit of the PR pinned by its approval (`r+ <sha>`). The PR is not merged if its head
+    /// has changed since.
+    pub approved_sha: Option<CommitSha>,
+}
+
+impl PullRequestModel {
+    pub fn is_approved(&self) -> bool {
+        self.approved_by.is_some()
+    }
+
+    pub fn queue_status(&self) -> QueueStatus {
+        match &self.approved_by {
+            Some(approver) => QueueStatus::Approved {
+                approver: approver.clone(),
+                // PRs approved before the approval time was stored
+                approved_at: self.approved_at.unwrap_or(self.created_at),
+            },
+            None => QueueStatus::NotApproved,
+        }
+    }
+}
+
+/// State of a PR with regards to the merge queue.
+/// Transitions between the states are performed by [`DbClient::approve`] and
+/// [`DbClient::unapprove`].
+#[derive(Debug, PartialEq)]
+pub enum QueueStatus {
+    /// The PR is not in the queue.
+    NotApproved,
+    /// The PR has been approved and it waits in the queue.
+    Approved {
+        approver: String,
+        approved_at: DateTime<Utc>,
+    },
+}
+
+/// Describes how should a PR be handled when creating rollups.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RollupMode {
+    /// The PR is trivial and it should always be included in a rollup.
+    Always,
+    /// The PR can be included in a rollup.
+    Maybe,
+    /// The PR can be included in a rollup, but it has a higher risk of failing.
+    Iffy,
+    /// The PR should never be included in a rollup.
+    Never,
+}
+
+impl RollupMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "always" => Some(RollupMode::Always),
+            "maybe" => Some(RollupMode::Maybe),
+            "iffy" => Some(RollupMode::Iffy),
+            "never" => Some(RollupMode::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RollupMode::Always => "always",
+            RollupMode::Maybe => "maybe",
+            RollupMode::Iffy => "iffy",
+            RollupMode::Never => "never",
+        }
+    }
+}
+
+/// The tree of a repository is closed, so that only PRs with at least the given priority are
+/// merged, e.g. while the base branch is broken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeClosure {
+    /// PRs with a lower priority are not merged until the tree is reopened.
+    pub priority: u32,
+    /// User that has closed the tree, `None` if the bot has closed it automatically.
+    pub closed_by: Option<String>,
+    pub reason: String,
+    pub closed_at: DateTime<Utc>,
+}
+
+/// A repository is paused (e.g. during an incident of the CI infrastructure or a release freeze),
+/// so that no builds are started and no PRs are merged. Commands are still accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepositoryPause {
+    /// User that has paused the repository, `None` if it has been paused using the admin API.
+    pub paused_by: Option<String>,
+    pub reason: String,
+    pub paused_at: DateTime<Utc>,
+}
+
+/// The lease of a repository, which allows a single replica of the bot to drive its merge queue
+/// when several replicas share the same database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepositoryLease {
+    /// Identifier of the replica that holds the lease.
+    pub holder: String,
+    /// The lease can be acquired by another replica after this time, unless it is renewed.
+    pub expires_at: DateTime<Utc>,
 }
 
 /// Describes whether a workflow is a Github Actions workflow or if it's a job from some external
 /// CI.
 #[derive(Debug, PartialEq)]
 pub enum WorkflowType {
+    /// A workflow of the CI of the forge, which can be inspected and cancelled using the
+    /// repository client (a GitLab CI pipeline for GitLab projects).
     Github,
     External,
+    /// A result reported by an external system using the JSON API, which is not part of the check
+    /// suites of the commit.
+    Reported,
 }
 
 /// Status of a workflow.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WorkflowStatus {
     /// Workflow is running.
     Pending,
@@ -80,11 +243,274 @@ pub struct WorkflowModel {
     pub workflow_type: WorkflowType,
     pub status: WorkflowStatus,
     pub created_at: DateTime<Utc>,
+    /// When did the workflow finish (either successfully or unsuccessfully).
+    pub finished_at: Option<DateTime<Utc>>,
+    /// URLs of artifacts reported together with the result of the workflow.
+    pub artifacts: Vec<String>,
+}
+
+impl WorkflowModel {
+    /// How long did the workflow run. Returns `None` if the workflow has not finished yet.
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.finished_at
+            .map(|finished_at| finished_at - self.created_at)
+    }
+}
+
+/// What can be done using an API token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Reading the state of the bot using the JSON API.
+    Read,
+    /// Using the admin endpoints (e.g. configuring branch protection).
+    Admin,
+    /// Reporting results of builds from external systems.
+    Report,
+}
+
+impl TokenScope {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Admin => "admin",
+            TokenScope::Report => "report",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "read" => Some(TokenScope::Read),
+            "admin" => Some(TokenScope::Admin),
+            "report" => Some(TokenScope::Report),
+            _ => None,
+        }
+    }
+}
+
+/// A token used by an integration to access the API of the bot.
+/// Only a hash of the token is stored.
+pub struct ApiTokenModel {
+    pub id: PrimaryKey,
+    /// Describes who uses the token.
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    /// Repositories (`owner/name`) in which the token can be used. If it is empty, the token can
+    /// be used in all repositories, and also for endpoints that do not belong to a repository.
+    pub repositories: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// An excerpt of the log of a failed job, stored so that it is available even after GitHub
+/// deletes the log.
+pub struct JobLogModel {
+    pub id: PrimaryKey,
+    pub repository: String,
+    pub run_id: RunId,
+    pub job_name: String,
+    pub excerpt: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A webhook received from GitHub, stored so that it can be replayed when debugging missed events.
+pub struct WebhookDeliveryModel {
+    pub id: PrimaryKey,
+    /// ID assigned to the delivery by GitHub (the `X-GitHub-Delivery` header).
+    /// Redeliveries from GitHub share the ID of the original delivery.
+    pub delivery_id: String,
+    pub event_type: String,
+    /// Hex-encoded SHA-256 hash of the payload.
+    pub payload_hash: String,
+    pub payload: String,
+    /// What has the bot done with the webhook (e.g. `accepted` or `ignored`).
+    pub result: String,
+    pub received_at: DateTime<Utc>,
+}
+
+/// A significant action performed by bors or by its users.
+/// Events are stored in an append-only log, which explains how did a PR get into its current state.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoggedEvent {
+    /// A bors command has been received in a PR comment.
+    CommandReceived {
+        author: String,
+        /// Name of the command, e.g. `approve`. Changes of the state of the PR caused by the
+        /// command are recorded as separate events.
+        command: String,
+    },
+    Approved {
+        approver: String,
+    },
+    Unapproved,
+    /// The priority of a PR has been changed.
+    PriorityChanged {
+        priority: u32,
+    },
+    /// The rollup mode of a PR has been changed. `None` if the rollup mode has been cleared.
+    RollupChanged {
+        rollup: Option<String>,
+    },
+    /// Review rights of a PR have been delegated. `None` if the delegation has been removed.
+    DelegationChanged {
+        delegate: Option<String>,
+    },
+    BuildStarted {
+        branch: String,
+        commit_sha: String,
+    },
+    WorkflowCompleted {
+        run_id: u64,
+        status: String,
+    },
+    BuildSucceeded {
+        commit_sha: String,
+    },
+    BuildFailed {
+        commit_sha: String,
+    },
+    /// A build has been cancelled, either by a user or because it has become outdated.
+    BuildCancelled {
+        commit_sha: String,
+    },
+    /// A build has been cancelled by the bot, because it ran for too long.
+    BuildTimedOut {
+        commit_sha: String,
+    },
+    /// A PR has been merged.
+    Merged,
+    /// The periodic digest of the repository has been posted.
+    DigestPosted,
+    /// A reminder about a PR that has been stuck in the same state for too long has been posted.
+    StaleReminder {
+        reason: String,
+        /// Unix timestamp of the last change of the state of the PR, which identifies the state
+        /// that has been reminded about.
+        since: i64,
+    },
+    /// The tree of the repository has been closed for PRs below the given priority.
+    TreeClosed {
+        /// `None` if the tree has been closed automatically.
+        closed_by: Option<String>,
+        priority: u32,
+        reason: String,
+    },
+    /// The tree of the repository has been reopened.
+    TreeOpened {
+        /// `None` if the tree has been reopened automatically.
+        opened_by: Option<String>,
+    },
+    /// The repository has been paused.
+    Paused {
+        /// `None` if the repository has been paused using the admin API.
+        paused_by: Option<String>,
+        reason: String,
+    },
+    /// The repository has been resumed.
+    Resumed {
+        /// `None` if the repository has been resumed using the admin API.
+        resumed_by: Option<String>,
+    },
+    /// A slowdown of a workflow has been reported.
+    DurationRegressionReported {
+        workflow: String,
+    },
+    /// A user has gained or lost a permission (`review` or `try`) in the repository.
+    PermissionChanged {
+        user: String,
+        permission: String,
+        granted: bool,
+    },
+    /// Review rights delegated to a user have been revoked, because the user has lost a
+    /// permission.
+    DelegationRevoked {
+        delegate: String,
+    },
+}
+
+impl LoggedEvent {
+    /// Kind of the event, which is stored alongside it so that events can be filtered by it.
+    /// It is the same as the `type` field of the serialized event.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LoggedEvent::CommandReceived { .. } => "command_received",
+            LoggedEvent::Approved { .. } => "approved",
+            LoggedEvent::Unapproved => "unapproved",
+            LoggedEvent::PriorityChanged { .. } => "priority_changed",
+            LoggedEvent::RollupChanged { .. } => "rollup_changed",
+            LoggedEvent::DelegationChanged { .. } => "delegation_changed",
+            LoggedEvent::BuildStarted { .. } => "build_started",
+            LoggedEvent::WorkflowCompleted { .. } => "workflow_completed",
+            LoggedEvent::BuildSucceeded { .. } => "build_succeeded",
+            LoggedEvent::BuildFailed { .. } => "build_failed",
+            LoggedEvent::BuildCancelled { .. } => "build_cancelled",
+            LoggedEvent::BuildTimedOut { .. } => "build_timed_out",
+            LoggedEvent::Merged => "merged",
+            LoggedEvent::DigestPosted => "digest_posted",
+            LoggedEvent::StaleReminder { .. } => "stale_reminder",
+            LoggedEvent::TreeClosed { .. } => "tree_closed",
+            LoggedEvent::TreeOpened { .. } => "tree_opened",
+            LoggedEvent::Paused { .. } => "paused",
+            LoggedEvent::Resumed { .. } => "resumed",
+            LoggedEvent::DurationRegressionReported { .. } => "duration_regression_reported",
+            LoggedEvent::PermissionChanged { .. } => "permission_changed",
+            LoggedEvent::DelegationRevoked { .. } => "delegation_revoked",
+        }
+    }
+}
+
+/// An entry of the event log of a repository.
+pub struct EventModel {
+    pub id: PrimaryKey,
+    pub repository: String,
+    /// PR that the event relates to, if any.
+    pub pr: Option<PullRequestNumber>,
+    pub event: LoggedEvent,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Bors state of a PR derived from its events in the event log.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplayedPullRequest {
+    pub approved_by: Option<String>,
+    pub priority: u32,
+    pub rollup: Option<String>,
+    pub delegated_to: Option<String>,
+    pub merged: bool,
+}
+
+impl ReplayedPullRequest {
+    /// Replays the events of a single PR, in the order in which they were recorded.
+    pub fn replay<'a>(events: impl IntoIterator<Item = &'a LoggedEvent>) -> Self {
+        let mut state = Self::default();
+        for event in events {
+            match event {
+                LoggedEvent::Approved { approver } => state.approved_by = Some(approver.clone()),
+                LoggedEvent::Unapproved => state.approved_by = None,
+                LoggedEvent::PriorityChanged { priority } => state.priority = *priority,
+                LoggedEvent::RollupChanged { rollup } => state.rollup = rollup.clone(),
+                LoggedEvent::DelegationChanged { delegate } => {
+                    state.delegated_to = delegate.clone()
+                }
+                LoggedEvent::Merged => state.merged = true,
+                _ => {}
+            }
+        }
+        state
+    }
 }
 
 /// Provides access to a database.
+///
+/// Methods that change the bors state of a PR (approval, priority, rollup mode or delegation) or
+/// the status of a build or a workflow also append the change to the event log, so that the state
+/// of a PR can be derived from it using [`ReplayedPullRequest`].
 #[async_trait]
 pub trait DbClient {
+    /// Checks that the database is reachable.
+    async fn ping(&self) -> anyhow::Result<()>;
+
     /// Finds a Pull request row for the given repository and PR number.
     /// If it doesn't exist, a new row is created.
     async fn get_or_create_pull_request(
@@ -93,18 +519,177 @@ pub trait DbClient {
         pr_number: PullRequestNumber,
     ) -> anyhow::Result<PullRequestModel>;
 
+    /// Finds a Pull request row for the given repository and PR number, without creating it.
+    async fn find_pull_request(
+        &self,
+        repo: &GithubRepoName,
+        pr_number: PullRequestNumber,
+    ) -> anyhow::Result<Option<PullRequestModel>>;
+
+    /// Returns all PRs of the given repository that bors knows about.
+    async fn get_pull_requests(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Vec<PullRequestModel>>;
+
+    /// Returns all repositories that have a PR that bors knows about, sorted by their name.
+    async fn get_repositories(&self) -> anyhow::Result<Vec<GithubRepoName>>;
+
+    /// Returns all approved PRs of the given repository, in the order of the merge queue.
+    /// PRs with a higher priority go first, PRs with the same priority are ordered by the time of
+    /// their approval.
+    async fn get_approved_pull_requests(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Vec<PullRequestModel>>;
+
     /// Finds a Pull request by a build (either a try or merge one).
     async fn find_pr_by_build(
         &self,
         build: &BuildModel,
     ) -> anyhow::Result<Option<PullRequestModel>>;
 
+    /// Remembers the last status comment posted by the bot to the given PR.
+    async fn set_status_comment(
+        &self,
+        pr: &PullRequestModel,
+        comment: &CommentId,
+    ) -> anyhow::Result<()>;
+
+    /// Remembers the summary comment of the given PR.
+    async fn set_summary_comment(
+        &self,
+        pr: &PullRequestModel,
+        comment: &CommentId,
+    ) -> anyhow::Result<()>;
+
+    /// Appends an event to the event log of the given repository.
+    async fn record_event(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        event: LoggedEvent,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the logged events of the given repository (or only of a single PR of the
+    /// repository), in the order in which they were recorded.
+    async fn get_events(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+    ) -> anyhow::Result<Vec<EventModel>>;
+
+    /// Returns the logged events of the given kinds (see [`LoggedEvent::kind`]) of the given
+    /// repository (or only of a single PR of the repository), in the order in which they were
+    /// recorded.
+    async fn get_events_of_kinds(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        kinds: &[&str],
+    ) -> anyhow::Result<Vec<EventModel>>;
+
+    /// Returns the most recently logged event of the given kind (see [`LoggedEvent::kind`]) of
+    /// the given repository (or only of a single PR of the repository).
+    async fn get_last_event(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        kind: &str,
+    ) -> anyhow::Result<Option<EventModel>>;
+
+    /// Marks the given PR as approved by the given reviewer. If `sha` is set, the approval is
+    /// pinned to that head commit.
+    async fn approve(
+        &self,
+        pr: &PullRequestModel,
+        approver: &str,
+        sha: Option<&CommitSha>,
+    ) -> anyhow::Result<()>;
+
+    /// Removes the approval of the given PR, which also removes it from the merge queue.
+    async fn unapprove(&self, pr: &PullRequestModel) -> anyhow::Result<()>;
+
+    /// Sets the merge queue priority of the given PR.
+    async fn set_priority(&self, pr: &PullRequestModel, priority: u32) -> anyhow::Result<()>;
+
+    /// Sets (or clears) the rollup mode of the given PR.
+    async fn set_rollup(
+        &self,
+        pr: &PullRequestModel,
+        rollup: Option<RollupMode>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the closure of the tree of the given repository, or `None` if the tree is open.
+    async fn get_tree_closure(&self, repo: &GithubRepoName) -> anyhow::Result<Option<TreeClosure>>;
+
+    /// Closes the tree of the given repository for PRs below `priority`. Closing an already closed
+    /// tree replaces its closure.
+    async fn close_tree(
+        &self,
+        repo: &GithubRepoName,
+        priority: u32,
+        closed_by: Option<&str>,
+        reason: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Reopens the tree of the given repository.
+    async fn open_tree(&self, repo: &GithubRepoName, opened_by: Option<&str>)
+        -> anyhow::Result<()>;
+
+    /// Returns the pause of the given repository, or `None` if it is not paused.
+    async fn get_pause(&self, repo: &GithubRepoName) -> anyhow::Result<Option<RepositoryPause>>;
+
+    /// Pauses the given repository. Pausing an already paused repository replaces its pause.
+    async fn pause_repository(
+        &self,
+        repo: &GithubRepoName,
+        paused_by: Option<&str>,
+        reason: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Resumes the given repository.
+    async fn resume_repository(
+        &self,
+        repo: &GithubRepoName,
+        resumed_by: Option<&str>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the lease of the given repository, or `None` if no replica has ever leased it.
+    /// The returned lease may be expired.
+    async fn get_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Option<RepositoryLease>>;
+
+    /// Acquires or renews the lease of the given repository for the given replica.
+    /// Returns `false` if another replica holds an unexpired lease.
+    async fn acquire_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+        holder: &str,
+        duration: chrono::Duration,
+    ) -> anyhow::Result<bool>;
+
+    /// Releases the lease of the given repository, if it is held by the given replica.
+    async fn release_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+        holder: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Delegates review rights of the given PR to a user, or removes the delegation.
+    async fn set_delegate(&self, pr: &PullRequestModel, user: Option<&str>) -> anyhow::Result<()>;
+
     /// Attaches an existing build to the given PR.
+    /// `attempt` is the number of automatic retries that preceded this build.
     async fn attach_try_build(
         &self,
         pr: PullRequestModel,
         branch: String,
         commit_sha: CommitSha,
+        attempt: u32,
+        args: TryBuildArgs,
     ) -> anyhow::Result<()>;
 
     /// Finds a build row by its repository, commit SHA and branch.
@@ -115,6 +700,52 @@ pub trait DbClient {
         commit_sha: CommitSha,
     ) -> anyhow::Result<Option<BuildModel>>;
 
+    /// Finds a build of the given repository by its ID.
+    async fn find_build_by_id(
+        &self,
+        repo: &GithubRepoName,
+        id: PrimaryKey,
+    ) -> anyhow::Result<Option<BuildModel>>;
+
+    /// Finds a build of any repository by its ID.
+    async fn find_build_by_key(&self, id: PrimaryKey) -> anyhow::Result<Option<BuildModel>>;
+
+    /// Finds a build to which is attached a workflow with the given run ID.
+    async fn find_build_by_workflow_run(&self, run_id: RunId)
+        -> anyhow::Result<Option<BuildModel>>;
+
+    /// Schedules an automatic retry of a failed build, or cancels it if `retry_at` is `None`.
+    async fn set_build_retry(
+        &self,
+        build: &BuildModel,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns failed builds whose automatic retry was scheduled before `time`.
+    async fn get_builds_to_retry(
+        &self,
+        repo: &GithubRepoName,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<BuildModel>>;
+
+    /// Returns builds of the repository, the newest first. Skips `offset` builds and returns at
+    /// most `limit` builds. If `commits` is set, only builds of these commits are returned.
+    async fn get_builds(
+        &self,
+        repo: &GithubRepoName,
+        commits: Option<&[String]>,
+        offset: u64,
+        limit: u64,
+    ) -> anyhow::Result<Vec<BuildModel>>;
+
+    /// Returns at most `limit` builds of the given branch of the repository, the newest first.
+    async fn get_branch_builds(
+        &self,
+        repo: &GithubRepoName,
+        branch: &str,
+        limit: u64,
+    ) -> anyhow::Result<Vec<BuildModel>>;
+
     /// Returns all builds that have not been completed yet.
     async fn get_running_builds(&self, repo: &GithubRepoName) -> anyhow::Result<Vec<BuildModel>>;
 
@@ -137,12 +768,92 @@ pub trait DbClient {
     ) -> anyhow::Result<()>;
 
     /// Updates the status of a workflow with the given run ID in the DB.
+    /// When the workflow is finished, its completion time is also recorded.
     async fn update_workflow_status(
         &self,
         run_id: u64,
         status: WorkflowStatus,
     ) -> anyhow::Result<()>;
 
+    /// Stores the URLs of artifacts of a workflow with the given run ID.
+    async fn set_workflow_artifacts(
+        &self,
+        run_id: RunId,
+        artifacts: &[String],
+    ) -> anyhow::Result<()>;
+
+    /// Stores an excerpt of the log of a failed job of a workflow run.
+    async fn store_job_log(
+        &self,
+        repo: &GithubRepoName,
+        run_id: RunId,
+        job_name: &str,
+        excerpt: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Get the stored log excerpts of the jobs of a workflow run.
+    async fn get_job_logs(&self, run_id: RunId) -> anyhow::Result<Vec<JobLogModel>>;
+
+    /// Deletes log excerpts of the given repository that were stored before the given time.
+    /// Returns the number of deleted excerpts.
+    async fn delete_job_logs_before(
+        &self,
+        repo: &GithubRepoName,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<u64>;
+
+    /// Stores a new API token, identified by the hash of its value. Returns the ID of the token.
+    async fn create_api_token(
+        &self,
+        name: &str,
+        token_hash: &str,
+        scopes: &[TokenScope],
+        repositories: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<PrimaryKey>;
+
+    /// Finds an API token by the hash of its value.
+    async fn find_api_token(&self, token_hash: &str) -> anyhow::Result<Option<ApiTokenModel>>;
+
+    /// Returns all API tokens, including expired and revoked ones.
+    async fn get_api_tokens(&self) -> anyhow::Result<Vec<ApiTokenModel>>;
+
+    /// Revokes an API token, so that it can no longer be used.
+    /// Returns `false` if there is no such token.
+    async fn revoke_api_token(&self, id: PrimaryKey) -> anyhow::Result<bool>;
+
+    /// Stores a received webhook. Returns the ID of the stored delivery.
+    async fn record_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        event_type: &str,
+        payload_hash: &str,
+        payload: &str,
+        result: &str,
+    ) -> anyhow::Result<PrimaryKey>;
+
+    /// Finds the most recently received webhook with the given GitHub delivery ID.
+    async fn find_webhook_delivery(
+        &self,
+        delivery_id: &str,
+    ) -> anyhow::Result<Option<WebhookDeliveryModel>>;
+
+    /// Returns the most recently received webhooks, newest first.
+    async fn get_webhook_deliveries(&self, limit: u64)
+        -> anyhow::Result<Vec<WebhookDeliveryModel>>;
+
+    /// Deletes webhooks that were received before the given time.
+    /// Returns the number of deleted deliveries.
+    async fn delete_webhook_deliveries_before(&self, time: DateTime<Utc>) -> anyhow::Result<u64>;
+
+    /// Get all workflows attached to builds of the given repository, or only the workflows
+    /// created since the given time.
+    async fn get_workflows_for_repository(
+        &self,
+        repo: &GithubRepoName,
+        since: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Vec<WorkflowModel>>;
+
     /// Get all workflows attached to a build.
     async fn get_workflows_for_build(
         &self,
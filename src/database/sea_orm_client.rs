@@ -4,19 +4,26 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use octocrab::models::RunId;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveValue::{Set, Unchanged};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DbErr, EntityTrait, QueryFilter, TransactionTrait};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, SelectTwo, Statement, TransactionTrait,
+};
 
-use entity::{build, pull_request, workflow};
+use entity::{
+    api_token, build, event_log, job_log, pull_request, repository, webhook_delivery, workflow,
+};
 use migration::sea_orm::DatabaseConnection;
 
 use crate::database::{
-    BuildModel, BuildStatus, DbClient, PullRequestModel, WorkflowModel, WorkflowStatus,
-    WorkflowType,
+    ApiTokenModel, BuildModel, BuildStatus, DbClient, EventModel, JobLogModel, LoggedEvent,
+    PrimaryKey, PullRequestModel, RepositoryLease, RepositoryPause, RollupMode, TokenScope,
+    TreeClosure, TryBuildArgs, WebhookDeliveryModel, WorkflowModel, WorkflowStatus, WorkflowType,
 };
-use crate::github::PullRequestNumber;
+use crate::github::{CommentId, PullRequestNumber};
 use crate::github::{CommitSha, GithubRepoName};
 
 /// Provides access to a database using SeaORM mapping.
+#[derive(Clone)]
 pub struct SeaORMClient {
     db: DatabaseConnection,
 }
@@ -31,8 +38,21 @@ impl SeaORMClient {
     }
 }
 
+// Each query has its own span, nested in the span of the event that has made it.
 #[async_trait]
 impl DbClient for SeaORMClient {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.db
+            .execute(Statement::from_string(
+                self.db.get_database_backend(),
+                "SELECT 1".to_string(),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_or_create_pull_request(
         &self,
         repo: &GithubRepoName,
@@ -72,6 +92,70 @@ impl DbClient for SeaORMClient {
         Ok(pr_from_db(pr, build))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_pull_request(
+        &self,
+        repo: &GithubRepoName,
+        pr_number: PullRequestNumber,
+    ) -> anyhow::Result<Option<PullRequestModel>> {
+        let result = pull_request::Entity::find()
+            .filter(
+                pull_request::Column::Repository
+                    .eq(full_repo_name(repo))
+                    .and(pull_request::Column::Number.eq(pr_number.0)),
+            )
+            .find_also_related(build::Entity)
+            .one(&self.db)
+            .await?;
+        Ok(result.map(|(pr, build)| pr_from_db(pr, build)))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_requests(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Vec<PullRequestModel>> {
+        let prs = pull_request::Entity::find()
+            .filter(pull_request::Column::Repository.eq(full_repo_name(repo)))
+            .order_by_asc(pull_request::Column::Number)
+            .find_also_related(build::Entity)
+            .all(&self.db)
+            .await?;
+        Ok(prs
+            .into_iter()
+            .map(|(pr, build)| pr_from_db(pr, build))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_repositories(&self) -> anyhow::Result<Vec<GithubRepoName>> {
+        let names: Vec<String> = pull_request::Entity::find()
+            .select_only()
+            .column(pull_request::Column::Repository)
+            .distinct()
+            .order_by_asc(pull_request::Column::Repository)
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        names
+            .into_iter()
+            .map(|name| GithubRepoName::parse(&name))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_approved_pull_requests(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Vec<PullRequestModel>> {
+        let prs = merge_queue(repo).all(&self.db).await?;
+        Ok(prs
+            .into_iter()
+            .map(|(pr, build)| pr_from_db(pr, build))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn find_pr_by_build(
         &self,
         build: &BuildModel,
@@ -84,17 +168,481 @@ impl DbClient for SeaORMClient {
         Ok(result.map(|(pr, build)| pr_from_db(pr, build)))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_status_comment(
+        &self,
+        pr: &PullRequestModel,
+        comment: &CommentId,
+    ) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            status_comment: Set(Some(comment.0.clone())),
+            ..Default::default()
+        };
+        pr_model.update(&self.db).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_summary_comment(
+        &self,
+        pr: &PullRequestModel,
+        comment: &CommentId,
+    ) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            summary_comment: Set(Some(comment.0.clone())),
+            ..Default::default()
+        };
+        pr_model.update(&self.db).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn record_event(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        event: LoggedEvent,
+    ) -> anyhow::Result<()> {
+        insert_event(&self.db, full_repo_name(repo), pr, &event).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_events(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+    ) -> anyhow::Result<Vec<EventModel>> {
+        let mut query = event_log::Entity::find()
+            .filter(event_log::Column::Repository.eq(full_repo_name(repo)));
+        if let Some(pr) = pr {
+            query = query.filter(event_log::Column::PrNumber.eq(pr.0));
+        }
+        let events = query
+            .order_by_asc(event_log::Column::Id)
+            .all(&self.db)
+            .await?;
+        events.into_iter().map(event_from_db).collect()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_events_of_kinds(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        kinds: &[&str],
+    ) -> anyhow::Result<Vec<EventModel>> {
+        let mut query = event_log::Entity::find()
+            .filter(event_log::Column::Repository.eq(full_repo_name(repo)))
+            .filter(event_log::Column::Kind.is_in(kinds.iter().copied()));
+        if let Some(pr) = pr {
+            query = query.filter(event_log::Column::PrNumber.eq(pr.0));
+        }
+        let events = query
+            .order_by_asc(event_log::Column::Id)
+            .all(&self.db)
+            .await?;
+        events.into_iter().map(event_from_db).collect()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_last_event(
+        &self,
+        repo: &GithubRepoName,
+        pr: Option<PullRequestNumber>,
+        kind: &str,
+    ) -> anyhow::Result<Option<EventModel>> {
+        let mut query = event_log::Entity::find()
+            .filter(event_log::Column::Repository.eq(full_repo_name(repo)))
+            .filter(event_log::Column::Kind.eq(kind));
+        if let Some(pr) = pr {
+            query = query.filter(event_log::Column::PrNumber.eq(pr.0));
+        }
+        let event = query
+            .order_by_desc(event_log::Column::Id)
+            .one(&self.db)
+            .await?;
+        event.map(event_from_db).transpose()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn approve(
+        &self,
+        pr: &PullRequestModel,
+        approver: &str,
+        sha: Option<&CommitSha>,
+    ) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            approved_by: Set(Some(approver.to_string())),
+            approved_at: Set(Some(now())),
+            approved_sha: Set(sha.map(|sha| sha.0.clone())),
+            updated_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        pr_model.update(&tx).await?;
+        insert_event(
+            &tx,
+            pr.repository.clone(),
+            Some(pr.number),
+            &LoggedEvent::Approved {
+                approver: approver.to_string(),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn unapprove(&self, pr: &PullRequestModel) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            approved_by: Set(None),
+            approved_at: Set(None),
+            approved_sha: Set(None),
+            updated_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        pr_model.update(&tx).await?;
+        insert_event(
+            &tx,
+            pr.repository.clone(),
+            Some(pr.number),
+            &LoggedEvent::Unapproved,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_priority(&self, pr: &PullRequestModel, priority: u32) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            priority: Set(priority as i32),
+            updated_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        pr_model.update(&tx).await?;
+        insert_event(
+            &tx,
+            pr.repository.clone(),
+            Some(pr.number),
+            &LoggedEvent::PriorityChanged { priority },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_rollup(
+        &self,
+        pr: &PullRequestModel,
+        rollup: Option<RollupMode>,
+    ) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            rollup: Set(rollup.map(|rollup| rollup.name().to_string())),
+            updated_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        pr_model.update(&tx).await?;
+        insert_event(
+            &tx,
+            pr.repository.clone(),
+            Some(pr.number),
+            &LoggedEvent::RollupChanged {
+                rollup: rollup.map(|rollup| rollup.name().to_string()),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_tree_closure(&self, repo: &GithubRepoName) -> anyhow::Result<Option<TreeClosure>> {
+        let repository = repository::Entity::find()
+            .filter(repository::Column::Name.eq(full_repo_name(repo)))
+            .one(&self.db)
+            .await?;
+        Ok(repository.and_then(|repository| {
+            Some(TreeClosure {
+                priority: repository.tree_closed_priority?.max(0) as u32,
+                closed_by: repository.tree_closed_by,
+                reason: repository.tree_closed_reason.unwrap_or_default(),
+                closed_at: datetime_from_db(repository.tree_closed_at?),
+            })
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn close_tree(
+        &self,
+        repo: &GithubRepoName,
+        priority: u32,
+        closed_by: Option<&str>,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let model = repository::ActiveModel {
+            name: Set(full_repo_name(repo)),
+            tree_closed_priority: Set(Some(priority as i32)),
+            tree_closed_by: Set(closed_by.map(|user| user.to_string())),
+            tree_closed_reason: Set(Some(reason.to_string())),
+            tree_closed_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        upsert_repository(&tx, model).await?;
+        insert_event(
+            &tx,
+            full_repo_name(repo),
+            None,
+            &LoggedEvent::TreeClosed {
+                closed_by: closed_by.map(|user| user.to_string()),
+                priority,
+                reason: reason.to_string(),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn open_tree(
+        &self,
+        repo: &GithubRepoName,
+        opened_by: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let model = repository::ActiveModel {
+            name: Set(full_repo_name(repo)),
+            tree_closed_priority: Set(None),
+            tree_closed_by: Set(None),
+            tree_closed_reason: Set(None),
+            tree_closed_at: Set(None),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        upsert_repository(&tx, model).await?;
+        insert_event(
+            &tx,
+            full_repo_name(repo),
+            None,
+            &LoggedEvent::TreeOpened {
+                opened_by: opened_by.map(|user| user.to_string()),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pause(&self, repo: &GithubRepoName) -> anyhow::Result<Option<RepositoryPause>> {
+        let repository = repository::Entity::find()
+            .filter(repository::Column::Name.eq(full_repo_name(repo)))
+            .one(&self.db)
+            .await?;
+        Ok(repository.and_then(|repository| {
+            Some(RepositoryPause {
+                paused_by: repository.paused_by,
+                reason: repository.pause_reason.unwrap_or_default(),
+                paused_at: datetime_from_db(repository.paused_at?),
+            })
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn pause_repository(
+        &self,
+        repo: &GithubRepoName,
+        paused_by: Option<&str>,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let model = repository::ActiveModel {
+            name: Set(full_repo_name(repo)),
+            paused_by: Set(paused_by.map(|user| user.to_string())),
+            pause_reason: Set(Some(reason.to_string())),
+            paused_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        upsert_repository(&tx, model).await?;
+        insert_event(
+            &tx,
+            full_repo_name(repo),
+            None,
+            &LoggedEvent::Paused {
+                paused_by: paused_by.map(|user| user.to_string()),
+                reason: reason.to_string(),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn resume_repository(
+        &self,
+        repo: &GithubRepoName,
+        resumed_by: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let model = repository::ActiveModel {
+            name: Set(full_repo_name(repo)),
+            paused_by: Set(None),
+            pause_reason: Set(None),
+            paused_at: Set(None),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        upsert_repository(&tx, model).await?;
+        insert_event(
+            &tx,
+            full_repo_name(repo),
+            None,
+            &LoggedEvent::Resumed {
+                resumed_by: resumed_by.map(|user| user.to_string()),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<Option<RepositoryLease>> {
+        let repository = repository::Entity::find()
+            .filter(repository::Column::Name.eq(full_repo_name(repo)))
+            .one(&self.db)
+            .await?;
+        Ok(repository.and_then(|repository| {
+            Some(RepositoryLease {
+                holder: repository.lease_holder?,
+                expires_at: datetime_from_db(repository.lease_expires_at?),
+            })
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn acquire_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+        holder: &str,
+        duration: chrono::Duration,
+    ) -> anyhow::Result<bool> {
+        // Makes sure that the repository has a row that can be leased
+        repository::Entity::insert(repository::ActiveModel {
+            name: Set(full_repo_name(repo)),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(repository::Column::Name)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+
+        // The lease is only acquired if no other replica holds it, or if its lease has expired
+        let now = now();
+        let result = repository::Entity::update_many()
+            .set(repository::ActiveModel {
+                lease_holder: Set(Some(holder.to_string())),
+                lease_expires_at: Set(Some(now + duration)),
+                ..Default::default()
+            })
+            .filter(repository::Column::Name.eq(full_repo_name(repo)))
+            .filter(
+                Condition::any()
+                    .add(repository::Column::LeaseHolder.is_null())
+                    .add(repository::Column::LeaseHolder.eq(holder))
+                    .add(repository::Column::LeaseExpiresAt.lt(now)),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn release_repository_lease(
+        &self,
+        repo: &GithubRepoName,
+        holder: &str,
+    ) -> anyhow::Result<()> {
+        repository::Entity::update_many()
+            .set(repository::ActiveModel {
+                lease_holder: Set(None),
+                lease_expires_at: Set(None),
+                ..Default::default()
+            })
+            .filter(
+                repository::Column::Name
+                    .eq(full_repo_name(repo))
+                    .and(repository::Column::LeaseHolder.eq(holder)),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_delegate(&self, pr: &PullRequestModel, user: Option<&str>) -> anyhow::Result<()> {
+        let pr_model = pull_request::ActiveModel {
+            id: Unchanged(pr.id),
+            delegated_to: Set(user.map(|user| user.to_string())),
+            updated_at: Set(Some(now())),
+            ..Default::default()
+        };
+        let tx = self.db.begin().await?;
+        pr_model.update(&tx).await?;
+        insert_event(
+            &tx,
+            pr.repository.clone(),
+            Some(pr.number),
+            &LoggedEvent::DelegationChanged {
+                delegate: user.map(|user| user.to_string()),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn attach_try_build(
         &self,
         pr: PullRequestModel,
         branch: String,
         commit_sha: CommitSha,
+        attempt: u32,
+        args: TryBuildArgs,
     ) -> anyhow::Result<()> {
+        let event = LoggedEvent::BuildStarted {
+            branch: branch.clone(),
+            commit_sha: commit_sha.0.clone(),
+        };
         let build = build::ActiveModel {
             repository: Set(pr.repository.clone()),
             branch: Set(branch),
             commit_sha: Set(commit_sha.0),
             status: Set(build_status_to_db(BuildStatus::Pending).to_string()),
+            attempt: Set(attempt as i32),
+            pr_head_sha: Set(args.pr_head_sha.map(|sha| sha.0)),
+            parent_sha: Set(args.parent.map(|sha| sha.0)),
+            pr_commit_sha: Set(args.commit.map(|sha| sha.0)),
+            jobs: Set(args.jobs),
             ..Default::default()
         };
 
@@ -110,11 +658,13 @@ impl DbClient for SeaORMClient {
             ..Default::default()
         };
         pr_model.update(&tx).await?;
+        insert_event(&tx, pr.repository, Some(pr.number), &event).await?;
         tx.commit().await?;
 
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn find_build(
         &self,
         repo: &GithubRepoName,
@@ -133,6 +683,114 @@ impl DbClient for SeaORMClient {
         Ok(build.map(build_from_db))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_build_by_id(
+        &self,
+        repo: &GithubRepoName,
+        id: PrimaryKey,
+    ) -> anyhow::Result<Option<BuildModel>> {
+        let build = build::Entity::find_by_id(id)
+            .filter(build::Column::Repository.eq(full_repo_name(repo)))
+            .one(&self.db)
+            .await?;
+        Ok(build.map(build_from_db))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_build_by_key(&self, id: PrimaryKey) -> anyhow::Result<Option<BuildModel>> {
+        let build = build::Entity::find_by_id(id).one(&self.db).await?;
+        Ok(build.map(build_from_db))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_build_by_workflow_run(
+        &self,
+        run_id: RunId,
+    ) -> anyhow::Result<Option<BuildModel>> {
+        let workflow = workflow::Entity::find()
+            .filter(workflow::Column::RunId.eq(run_id.0))
+            .find_also_related(build::Entity)
+            .one(&self.db)
+            .await?;
+        Ok(workflow.and_then(|(_, build)| build).map(build_from_db))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_build_retry(
+        &self,
+        build: &BuildModel,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let model = build::ActiveModel {
+            id: Unchanged(build.id),
+            retry_at: Set(retry_at.map(|time| time.naive_utc())),
+            ..Default::default()
+        };
+        model.update(&self.db).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_builds_to_retry(
+        &self,
+        repo: &GithubRepoName,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<BuildModel>> {
+        let builds = build::Entity::find()
+            .filter(
+                build::Column::Repository
+                    .eq(full_repo_name(repo))
+                    .and(build::Column::Status.eq(build_status_to_db(BuildStatus::Failure)))
+                    .and(build::Column::RetryAt.lte(time.naive_utc())),
+            )
+            .all(&self.db)
+            .await?;
+        Ok(builds.into_iter().map(build_from_db).collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_builds(
+        &self,
+        repo: &GithubRepoName,
+        commits: Option<&[String]>,
+        offset: u64,
+        limit: u64,
+    ) -> anyhow::Result<Vec<BuildModel>> {
+        let mut query =
+            build::Entity::find().filter(build::Column::Repository.eq(full_repo_name(repo)));
+        if let Some(commits) = commits {
+            query = query.filter(build::Column::CommitSha.is_in(commits.iter().cloned()));
+        }
+        let builds = query
+            .order_by_desc(build::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+        Ok(builds.into_iter().map(build_from_db).collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_branch_builds(
+        &self,
+        repo: &GithubRepoName,
+        branch: &str,
+        limit: u64,
+    ) -> anyhow::Result<Vec<BuildModel>> {
+        let builds = build::Entity::find()
+            .filter(
+                build::Column::Repository
+                    .eq(full_repo_name(repo))
+                    .and(build::Column::Branch.eq(branch)),
+            )
+            .order_by_desc(build::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+        Ok(builds.into_iter().map(build_from_db).collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_running_builds(&self, repo: &GithubRepoName) -> anyhow::Result<Vec<BuildModel>> {
         let builds = build::Entity::find()
             .filter(
@@ -145,20 +803,45 @@ impl DbClient for SeaORMClient {
         Ok(builds.into_iter().map(build_from_db).collect())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn update_build_status(
         &self,
         build: &BuildModel,
         status: BuildStatus,
     ) -> anyhow::Result<()> {
+        let commit_sha = build.commit_sha.clone();
+        let event = match status {
+            BuildStatus::Pending => None,
+            BuildStatus::Success => Some(LoggedEvent::BuildSucceeded { commit_sha }),
+            BuildStatus::Failure => Some(LoggedEvent::BuildFailed { commit_sha }),
+            BuildStatus::Cancelled => Some(LoggedEvent::BuildCancelled { commit_sha }),
+            BuildStatus::Timeouted => Some(LoggedEvent::BuildTimedOut { commit_sha }),
+        };
         let model = build::ActiveModel {
             id: Unchanged(build.id),
             status: Set(build_status_to_db(status).to_string()),
             ..Default::default()
         };
-        model.update(&self.db).await?;
+        let tx = self.db.begin().await?;
+        model.update(&tx).await?;
+        if let Some(event) = event {
+            let pr = pull_request::Entity::find()
+                .filter(pull_request::Column::TryBuild.eq(build.id))
+                .one(&tx)
+                .await?;
+            insert_event(
+                &tx,
+                build.repository.clone(),
+                pr.map(|pr| PullRequestNumber(pr.number as u64)),
+                &event,
+            )
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn create_workflow(
         &self,
         build: &BuildModel,
@@ -181,23 +864,265 @@ impl DbClient for SeaORMClient {
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn update_workflow_status(
         &self,
         run_id: u64,
         status: WorkflowStatus,
     ) -> anyhow::Result<()> {
+        let finished_at = match status {
+            WorkflowStatus::Pending => None,
+            WorkflowStatus::Success | WorkflowStatus::Failure => Some(now()),
+        };
         let model = workflow::ActiveModel {
             status: Set(workflow_status_to_db(&status).to_string()),
+            finished_at: Set(finished_at),
             ..Default::default()
         };
+        let tx = self.db.begin().await?;
         workflow::Entity::update_many()
             .set(model)
             .filter(workflow::Column::RunId.eq(run_id))
+            .exec(&tx)
+            .await?;
+        let build = workflow::Entity::find()
+            .filter(workflow::Column::RunId.eq(run_id))
+            .find_also_related(build::Entity)
+            .one(&tx)
+            .await?
+            .and_then(|(_, build)| build)
+            // Only finished workflows are recorded
+            .filter(|_| status != WorkflowStatus::Pending);
+        if let Some(build) = build {
+            let pr = pull_request::Entity::find()
+                .filter(pull_request::Column::TryBuild.eq(build.id))
+                .one(&tx)
+                .await?;
+            insert_event(
+                &tx,
+                build.repository,
+                pr.map(|pr| PullRequestNumber(pr.number as u64)),
+                &LoggedEvent::WorkflowCompleted {
+                    run_id,
+                    status: workflow_status_to_db(&status).to_string(),
+                },
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_workflow_artifacts(
+        &self,
+        run_id: RunId,
+        artifacts: &[String],
+    ) -> anyhow::Result<()> {
+        // URLs cannot contain line breaks, so the artifacts are stored one per line
+        let model = workflow::ActiveModel {
+            artifacts: Set(Some(artifacts.join("\n"))),
+            ..Default::default()
+        };
+        workflow::Entity::update_many()
+            .set(model)
+            .filter(workflow::Column::RunId.eq(run_id.0))
             .exec(&self.db)
             .await?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn store_job_log(
+        &self,
+        repo: &GithubRepoName,
+        run_id: RunId,
+        job_name: &str,
+        excerpt: &str,
+    ) -> anyhow::Result<()> {
+        let model = job_log::ActiveModel {
+            repository: Set(full_repo_name(repo)),
+            run_id: Set(run_id.0 as i64),
+            job_name: Set(job_name.to_string()),
+            excerpt: Set(excerpt.to_string()),
+            ..Default::default()
+        };
+        model.insert(&self.db).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_job_logs(&self, run_id: RunId) -> anyhow::Result<Vec<JobLogModel>> {
+        let logs = job_log::Entity::find()
+            .filter(job_log::Column::RunId.eq(run_id.0))
+            .order_by_asc(job_log::Column::Id)
+            .all(&self.db)
+            .await?;
+        Ok(logs
+            .into_iter()
+            .map(|log| JobLogModel {
+                id: log.id,
+                repository: log.repository,
+                run_id: RunId(log.run_id as u64),
+                job_name: log.job_name,
+                excerpt: log.excerpt,
+                created_at: datetime_from_db(log.created_at),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn delete_job_logs_before(
+        &self,
+        repo: &GithubRepoName,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = job_log::Entity::delete_many()
+            .filter(
+                job_log::Column::Repository
+                    .eq(full_repo_name(repo))
+                    .and(job_log::Column::CreatedAt.lt(time.naive_utc())),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn create_api_token(
+        &self,
+        name: &str,
+        token_hash: &str,
+        scopes: &[TokenScope],
+        repositories: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<PrimaryKey> {
+        let model = api_token::ActiveModel {
+            name: Set(name.to_string()),
+            token_hash: Set(token_hash.to_string()),
+            scopes: Set(scopes
+                .iter()
+                .map(|scope| scope.name())
+                .collect::<Vec<_>>()
+                .join(",")),
+            repositories: Set(Some(repositories.join(",")).filter(|value| !value.is_empty())),
+            expires_at: Set(expires_at.map(|time| time.naive_utc())),
+            ..Default::default()
+        };
+        let model = model.insert(&self.db).await?;
+        Ok(model.id)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_api_token(&self, token_hash: &str) -> anyhow::Result<Option<ApiTokenModel>> {
+        let token = api_token::Entity::find()
+            .filter(api_token::Column::TokenHash.eq(token_hash))
+            .one(&self.db)
+            .await?;
+        token.map(api_token_from_db).transpose()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_api_tokens(&self) -> anyhow::Result<Vec<ApiTokenModel>> {
+        let tokens = api_token::Entity::find()
+            .order_by_asc(api_token::Column::Id)
+            .all(&self.db)
+            .await?;
+        tokens.into_iter().map(api_token_from_db).collect()
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revoke_api_token(&self, id: PrimaryKey) -> anyhow::Result<bool> {
+        let result = api_token::Entity::update_many()
+            .set(api_token::ActiveModel {
+                revoked_at: Set(Some(now())),
+                ..Default::default()
+            })
+            .filter(api_token::Column::Id.eq(id))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn record_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        event_type: &str,
+        payload_hash: &str,
+        payload: &str,
+        result: &str,
+    ) -> anyhow::Result<PrimaryKey> {
+        let model = webhook_delivery::ActiveModel {
+            delivery_id: Set(delivery_id.to_string()),
+            event_type: Set(event_type.to_string()),
+            payload_hash: Set(payload_hash.to_string()),
+            payload: Set(payload.to_string()),
+            result: Set(result.to_string()),
+            ..Default::default()
+        };
+        let model = model.insert(&self.db).await?;
+        Ok(model.id)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn find_webhook_delivery(
+        &self,
+        delivery_id: &str,
+    ) -> anyhow::Result<Option<WebhookDeliveryModel>> {
+        let delivery = webhook_delivery::Entity::find()
+            .filter(webhook_delivery::Column::DeliveryId.eq(delivery_id))
+            .order_by_desc(webhook_delivery::Column::Id)
+            .one(&self.db)
+            .await?;
+        Ok(delivery.map(webhook_delivery_from_db))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_webhook_deliveries(
+        &self,
+        limit: u64,
+    ) -> anyhow::Result<Vec<WebhookDeliveryModel>> {
+        let deliveries = webhook_delivery::Entity::find()
+            .order_by_desc(webhook_delivery::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+        Ok(deliveries
+            .into_iter()
+            .map(webhook_delivery_from_db)
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn delete_webhook_deliveries_before(&self, time: DateTime<Utc>) -> anyhow::Result<u64> {
+        let result = webhook_delivery::Entity::delete_many()
+            .filter(webhook_delivery::Column::ReceivedAt.lt(time.naive_utc()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_workflows_for_repository(
+        &self,
+        repo: &GithubRepoName,
+        since: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Vec<WorkflowModel>> {
+        let mut query = workflow::Entity::find()
+            .find_also_related(build::Entity)
+            .filter(build::Column::Repository.eq(full_repo_name(repo)));
+        if let Some(since) = since {
+            query = query.filter(workflow::Column::CreatedAt.gte(since.naive_utc()));
+        }
+        let workflows = query.all(&self.db).await?;
+        Ok(workflows
+            .into_iter()
+            .map(|(workflow, build)| workflow_from_db(workflow, build))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_workflows_for_build(
         &self,
         build: &BuildModel,
@@ -238,6 +1163,7 @@ fn workflow_type_to_db(workflow_type: WorkflowType) -> &'static str {
     match workflow_type {
         WorkflowType::Github => "github",
         WorkflowType::External => "external",
+        WorkflowType::Reported => "reported",
     }
 }
 
@@ -245,6 +1171,7 @@ fn workflow_type_from_db(workflow_type: String) -> WorkflowType {
     match workflow_type.as_str() {
         "github" => WorkflowType::Github,
         "external" => WorkflowType::External,
+        "reported" => WorkflowType::Reported,
         _ => panic!("Encountered unknown workflow type in DB: {workflow_type}"),
     }
 }
@@ -261,6 +1188,11 @@ fn workflow_from_db(workflow: workflow::Model, build: Option<build::Model>) -> W
         workflow_type: workflow_type_from_db(workflow.r#type),
         status: workflow_status_from_db(workflow.status),
         created_at: datetime_from_db(workflow.created_at),
+        finished_at: workflow.finished_at.map(datetime_from_db),
+        artifacts: workflow
+            .artifacts
+            .map(|artifacts| artifacts.lines().map(str::to_string).collect())
+            .unwrap_or_default(),
     }
 }
 
@@ -293,6 +1225,14 @@ fn build_from_db(model: build::Model) -> BuildModel {
         commit_sha: model.commit_sha,
         status: build_status_from_db(model.status),
         created_at: datetime_from_db(model.created_at),
+        attempt: model.attempt as u32,
+        retry_at: model.retry_at.map(datetime_from_db),
+        args: TryBuildArgs {
+            pr_head_sha: model.pr_head_sha.map(CommitSha),
+            parent: model.parent_sha.map(CommitSha),
+            commit: model.pr_commit_sha.map(CommitSha),
+            jobs: model.jobs,
+        },
     }
 }
 
@@ -303,6 +1243,133 @@ fn pr_from_db(pr: pull_request::Model, build: Option<build::Model>) -> PullReque
         number: PullRequestNumber(pr.number as u64),
         try_build: build.map(build_from_db),
         created_at: datetime_from_db(pr.created_at),
+        status_comment: pr.status_comment.map(CommentId),
+        approved_by: pr.approved_by,
+        summary_comment: pr.summary_comment.map(CommentId),
+        approved_at: pr.approved_at.map(datetime_from_db),
+        priority: pr.priority.max(0) as u32,
+        rollup: pr.rollup.and_then(rollup_mode_from_db),
+        delegated_to: pr.delegated_to,
+        updated_at: pr.updated_at.map(datetime_from_db),
+        approved_sha: pr.approved_sha.map(CommitSha),
+    }
+}
+
+/// Approved PRs of the given repository, in the order of the merge queue.
+fn merge_queue(repo: &GithubRepoName) -> SelectTwo<pull_request::Entity, build::Entity> {
+    pull_request::Entity::find()
+        .filter(
+            pull_request::Column::Repository
+                .eq(full_repo_name(repo))
+                .and(pull_request::Column::ApprovedBy.is_not_null()),
+        )
+        .order_by_desc(pull_request::Column::Priority)
+        .order_by_asc(pull_request::Column::ApprovedAt)
+        .order_by_asc(pull_request::Column::Id)
+        .find_also_related(build::Entity)
+}
+
+/// Inserts the row of a repository, or updates the columns set in `model` if the row exists.
+async fn upsert_repository<C: ConnectionTrait>(
+    db: &C,
+    model: repository::ActiveModel,
+) -> anyhow::Result<()> {
+    let columns: Vec<repository::Column> = [
+        repository::Column::TreeClosedPriority,
+        repository::Column::TreeClosedBy,
+        repository::Column::TreeClosedReason,
+        repository::Column::TreeClosedAt,
+        repository::Column::PausedBy,
+        repository::Column::PauseReason,
+        repository::Column::PausedAt,
+        repository::Column::LeaseHolder,
+        repository::Column::LeaseExpiresAt,
+    ]
+    .into_iter()
+    .filter(|column| model.get(*column).is_set())
+    .collect();
+    repository::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(repository::Column::Name)
+                .update_columns(columns)
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(())
+}
+
+async fn insert_event<C: ConnectionTrait>(
+    db: &C,
+    repository: String,
+    pr: Option<PullRequestNumber>,
+    event: &LoggedEvent,
+) -> anyhow::Result<()> {
+    let model = event_log::ActiveModel {
+        repository: Set(repository),
+        pr_number: Set(pr.map(|pr| pr.0 as i32)),
+        data: Set(serde_json::to_string(event)?),
+        kind: Set(Some(event.kind().to_string())),
+        ..Default::default()
+    };
+    model.insert(db).await?;
+    Ok(())
+}
+
+fn event_from_db(event: event_log::Model) -> anyhow::Result<EventModel> {
+    Ok(EventModel {
+        id: event.id,
+        repository: event.repository,
+        pr: event.pr_number.map(|pr| PullRequestNumber(pr as u64)),
+        event: serde_json::from_str(&event.data)
+            .map_err(|error| anyhow!("Cannot parse logged event {}: {error:?}", event.data))?,
+        created_at: datetime_from_db(event.created_at),
+    })
+}
+
+fn rollup_mode_from_db(rollup: String) -> Option<RollupMode> {
+    let mode = RollupMode::from_name(&rollup);
+    if mode.is_none() {
+        tracing::warn!("Encountered unknown rollup mode in DB: {rollup}");
+    }
+    mode
+}
+
+fn api_token_from_db(model: api_token::Model) -> anyhow::Result<ApiTokenModel> {
+    let scopes = model
+        .scopes
+        .split(',')
+        .filter(|scope| !scope.is_empty())
+        .map(|scope| {
+            TokenScope::parse(scope).ok_or_else(|| anyhow!("Unknown token scope `{scope}`"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(ApiTokenModel {
+        id: model.id,
+        name: model.name,
+        scopes,
+        repositories: model
+            .repositories
+            .unwrap_or_default()
+            .split(',')
+            .filter(|repo| !repo.is_empty())
+            .map(|repo| repo.to_string())
+            .collect(),
+        created_at: datetime_from_db(model.created_at),
+        expires_at: model.expires_at.map(datetime_from_db),
+        revoked_at: model.revoked_at.map(datetime_from_db),
+    })
+}
+
+fn webhook_delivery_from_db(model: webhook_delivery::Model) -> WebhookDeliveryModel {
+    WebhookDeliveryModel {
+        id: model.id,
+        delivery_id: model.delivery_id,
+        event_type: model.event_type,
+        payload_hash: model.payload_hash,
+        payload: model.payload,
+        result: model.result,
+        received_at: datetime_from_db(model.received_at),
     }
 }
 
@@ -310,6 +1377,211 @@ fn datetime_from_db(datetime: NaiveDateTime) -> DateTime<Utc> {
     DateTime::from_utc(datetime, Utc)
 }
 
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
 fn full_repo_name(repo: &GithubRepoName) -> String {
-    format!("{}/{}", repo.owner(), repo.name())
+    repo.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{DbClient, LoggedEvent, RollupMode};
+    use crate::github::PullRequestNumber;
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn merge_queue_order() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr1 = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        let pr2 = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap();
+        db.approve(&pr1, "foo", None).await.unwrap();
+        db.approve(&pr2, "foo", None).await.unwrap();
+        db.set_priority(&pr2, 1).await.unwrap();
+
+        let queue = db.get_approved_pull_requests(&repo).await.unwrap();
+        let order: Vec<u64> = queue.iter().map(|pr| pr.number.0).collect();
+        assert_eq!(order, vec![2, 1]);
+
+        db.unapprove(&queue[0]).await.unwrap();
+        let queue = db.get_approved_pull_requests(&repo).await.unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].number.0, 1);
+    }
+
+    #[tokio::test]
+    async fn record_pr_state_transitions() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.set_priority(&pr, 3).await.unwrap();
+        db.set_rollup(&pr, Some(RollupMode::Never)).await.unwrap();
+        db.set_delegate(&pr, Some("foo")).await.unwrap();
+        db.set_delegate(&pr, None).await.unwrap();
+
+        let events: Vec<LoggedEvent> = db
+            .get_events(&repo, Some(pr.number))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                LoggedEvent::PriorityChanged { priority: 3 },
+                LoggedEvent::RollupChanged {
+                    rollup: Some("never".to_string())
+                },
+                LoggedEvent::DelegationChanged {
+                    delegate: Some("foo".to_string())
+                },
+                LoggedEvent::DelegationChanged { delegate: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn event_kind_matches_serialized_type() {
+        let events = [
+            LoggedEvent::Unapproved,
+            LoggedEvent::BuildTimedOut {
+                commit_sha: "sha".to_string(),
+            },
+            LoggedEvent::DurationRegressionReported {
+                workflow: "CI".to_string(),
+            },
+        ];
+        for event in events {
+            let value = serde_json::to_value(&event).unwrap();
+            assert_eq!(value["type"], event.kind());
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_events_by_kind() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.approve(&pr, "foo", None).await.unwrap();
+        db.set_priority(&pr, 3).await.unwrap();
+        db.unapprove(&pr).await.unwrap();
+
+        let events: Vec<LoggedEvent> = db
+            .get_events_of_kinds(&repo, None, &["approved", "unapproved"])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                LoggedEvent::Approved {
+                    approver: "foo".to_string()
+                },
+                LoggedEvent::Unapproved
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn close_and_open_tree() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        assert!(db.get_tree_closure(&repo).await.unwrap().is_none());
+
+        db.close_tree(&repo, 5, Some("foo"), "Broken CI")
+            .await
+            .unwrap();
+        db.close_tree(&repo, 10, None, "Too many failures")
+            .await
+            .unwrap();
+        let closure = db.get_tree_closure(&repo).await.unwrap().unwrap();
+        assert_eq!(closure.priority, 10);
+        assert_eq!(closure.closed_by, None);
+        assert_eq!(closure.reason, "Too many failures");
+
+        db.open_tree(&repo, Some("foo")).await.unwrap();
+        assert!(db.get_tree_closure(&repo).await.unwrap().is_none());
+        assert_eq!(db.get_events(&repo, None).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume_repository() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        db.close_tree(&repo, 5, Some("foo"), "Broken CI")
+            .await
+            .unwrap();
+        db.pause_repository(&repo, Some("bar"), "Release freeze")
+            .await
+            .unwrap();
+        let pause = db.get_pause(&repo).await.unwrap().unwrap();
+        assert_eq!(pause.paused_by.as_deref(), Some("bar"));
+        assert_eq!(pause.reason, "Release freeze");
+        // Pausing does not change the closure of the tree
+        assert!(db.get_tree_closure(&repo).await.unwrap().is_some());
+
+        db.resume_repository(&repo, Some("bar")).await.unwrap();
+        assert!(db.get_pause(&repo).await.unwrap().is_none());
+        assert!(db.get_tree_closure(&repo).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn repository_lease() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let duration = chrono::Duration::minutes(5);
+        assert!(db.get_repository_lease(&repo).await.unwrap().is_none());
+
+        assert!(db
+            .acquire_repository_lease(&repo, "a", duration)
+            .await
+            .unwrap());
+        // Another replica cannot acquire the lease, but its holder can renew it
+        assert!(!db
+            .acquire_repository_lease(&repo, "b", duration)
+            .await
+            .unwrap());
+        assert!(db
+            .acquire_repository_lease(&repo, "a", duration)
+            .await
+            .unwrap());
+        let lease = db.get_repository_lease(&repo).await.unwrap().unwrap();
+        assert_eq!(lease.holder, "a");
+
+        // Only the holder can release the lease
+        db.release_repository_lease(&repo, "b").await.unwrap();
+        assert!(db.get_repository_lease(&repo).await.unwrap().is_some());
+        db.release_repository_lease(&repo, "a").await.unwrap();
+        assert!(db.get_repository_lease(&repo).await.unwrap().is_none());
+
+        // An expired lease can be taken over by another replica
+        assert!(db
+            .acquire_repository_lease(&repo, "a", chrono::Duration::seconds(-1))
+            .await
+            .unwrap());
+        assert!(db
+            .acquire_repository_lease(&repo, "b", duration)
+            .await
+            .unwrap());
+        let lease = db.get_repository_lease(&repo).await.unwrap().unwrap();
+        assert_eq!(lease.holder, "b");
+    }
 }
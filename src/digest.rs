@@ -0,0 +1,268 @@
+//! Periodic digest of the activity of a repository (landed PRs, build failures and retries and
+//! flaky checks), computed from the builds and the event log stored in the database.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::{BuildStatus, DbClient, LoggedEvent, WorkflowStatus};
+use crate::github::GithubRepoName;
+use crate::stats::format_duration;
+
+/// How many flaky checks are listed in a digest.
+const FLAKY_CHECKS_LIMIT: usize = 3;
+
+pub struct Digest {
+    pub repository: GithubRepoName,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Number of PRs merged in the period.
+    pub landed_prs: usize,
+    /// How long did the landed PRs wait between their approval and their merge, on average.
+    pub average_queue_time: Option<Duration>,
+    /// Number of builds started in the period that have finished.
+    pub finished_builds: usize,
+    pub failed_builds: usize,
+    /// Builds that were automatic retries of a failed build.
+    pub retried_builds: usize,
+    /// Checks with the most flaky failures, i.e. failures of a commit on which the same check has
+    /// also succeeded. Sorted by the number of flaky failures, the most flaky first.
+    pub flaky_checks: Vec<(String, usize)>,
+}
+
+impl Digest {
+    pub fn failure_rate(&self) -> f64 {
+        rate(self.failed_builds, self.finished_builds)
+    }
+
+    pub fn retry_rate(&self) -> f64 {
+        rate(self.retried_builds, self.finished_builds)
+    }
+}
+
+fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// Computes the digest of the repository for the period between `since` and `until`.
+pub async fn compute_digest(
+    db: &dyn DbClient,
+    repo: &GithubRepoName,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<Digest> {
+    let in_period = |time: DateTime<Utc>| time >= since && time < until;
+
+    let mut approvals: HashMap<u64, DateTime<Utc>> = HashMap::new();
+    let mut queue_times = vec![];
+    let mut landed_prs = 0;
+    for event in db
+        .get_events_of_kinds(repo, None, &["approved", "merged"])
+        .await?
+    {
+        let Some(pr) = event.pr else {
+            continue;
+        };
+        match event.event {
+            LoggedEvent::Approved { .. } => {
+                approvals.insert(pr.0, event.created_at);
+            }
+            LoggedEvent::Merged if in_period(event.created_at) => {
+                landed_prs += 1;
+                if let Some(approved_at) = approvals.get(&pr.0) {
+                    queue_times.push(event.created_at - *approved_at);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let workflows: Vec<_> = db
+        .get_workflows_for_repository(repo, None)
+        .await?
+        .into_iter()
+        .filter(|workflow| in_period(workflow.build.created_at))
+        .collect();
+
+    let mut builds = HashMap::new();
+    for workflow in &workflows {
+        builds.insert(workflow.build.id, &workflow.build);
+    }
+    let finished: Vec<_> = builds
+        .values()
+        .filter(|build| {
+            matches!(
+                build.status,
+                BuildStatus::Success | BuildStatus::Failure | BuildStatus::Cancelled
+            )
+        })
+        .collect();
+
+    // Checks that have succeeded on each commit
+    let succeeded: HashSet<(&str, &str)> = workflows
+        .iter()
+        .filter(|workflow| workflow.status == WorkflowStatus::Success)
+        .map(|workflow| (workflow.name.as_str(), workflow.build.commit_sha.as_str()))
+        .collect();
+    let mut flaky: BTreeMap<&str, usize> = BTreeMap::new();
+    for workflow in &workflows {
+        if workflow.status == WorkflowStatus::Failure
+            && succeeded.contains(&(workflow.name.as_str(), workflow.build.commit_sha.as_str()))
+        {
+            *flaky.entry(workflow.name.as_str()).or_default() += 1;
+        }
+    }
+    let mut flaky_checks: Vec<(String, usize)> = flaky
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    // The sort is stable, so checks with the same count stay sorted by their name
+    flaky_checks.sort_by(|a, b| b.1.cmp(&a.1));
+    flaky_checks.truncate(FLAKY_CHECKS_LIMIT);
+
+    Ok(Digest {
+        repository: repo.clone(),
+        since,
+        until,
+        landed_prs,
+        average_queue_time: average(&queue_times),
+        finished_builds: finished.len(),
+        failed_builds: finished
+            .iter()
+            .filter(|build| build.status == BuildStatus::Failure)
+            .count(),
+        retried_builds: finished.iter().filter(|build| build.attempt > 0).count(),
+        flaky_checks,
+    })
+}
+
+fn average(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    let total = durations
+        .iter()
+        .fold(Duration::zero(), |total, duration| total + *duration);
+    Some(total / durations.len() as i32)
+}
+
+/// Formats the digest as Markdown.
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "**Digest of {} from {} to {}**",
+            self.repository,
+            self.since.format("%Y-%m-%d"),
+            self.until.format("%Y-%m-%d")
+        )?;
+        write!(f, "- Landed PRs: {}", self.landed_prs)?;
+        if let Some(queue_time) = self.average_queue_time {
+            write!(f, " (average queue time: {})", format_duration(queue_time))?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "- Builds: {}, {:.1}% failed, {:.1}% were retries",
+            self.finished_builds,
+            self.failure_rate() * 100.0,
+            self.retry_rate() * 100.0
+        )?;
+        if self.flaky_checks.is_empty() {
+            writeln!(f, "- No flaky checks")?;
+        } else {
+            writeln!(f, "- Most flaky checks:")?;
+            for (name, failures) in &self.flaky_checks {
+                writeln!(f, "  - {name}: {failures} flaky failure(s)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use octocrab::models::RunId;
+
+    use crate::database::{DbClient, WorkflowStatus, WorkflowType};
+    use crate::digest::compute_digest;
+    use crate::github::CommitSha;
+    use crate::tests::event::{default_pr_number, suite_failure};
+    use crate::tests::state::{default_merge_sha, default_repo_name, ClientBuilder};
+
+    #[tokio::test]
+    async fn digest_of_builds_and_merges() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_failure()]);
+        state.comment("@bors try").await;
+        let branch = "automation/bors/try";
+        state
+            .perform_workflow_events(1, branch, &default_merge_sha(), WorkflowStatus::Failure)
+            .await;
+        // The failed workflow has been re-run manually and it has succeeded
+        let build = state
+            .db
+            .find_build(
+                &default_repo_name(),
+                branch.to_string(),
+                CommitSha(default_merge_sha()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        state
+            .db
+            .create_workflow(
+                &build,
+                "workflow-1".to_string(),
+                "https://workflow-1.com".to_string(),
+                RunId(2),
+                WorkflowType::Github,
+                WorkflowStatus::Success,
+            )
+            .await
+            .unwrap();
+        state.comment("@bors r+").await;
+        state.pull_request_merged(default_pr_number(), "main").await;
+
+        let now = Utc::now();
+        let digest = compute_digest(
+            &state.db,
+            &default_repo_name(),
+            now - Duration::days(7),
+            now + Duration::minutes(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(digest.landed_prs, 1);
+        assert!(digest.average_queue_time.is_some());
+        assert_eq!(digest.finished_builds, 1);
+        assert_eq!(digest.failed_builds, 1);
+        assert_eq!(digest.retried_builds, 0);
+        assert_eq!(digest.flaky_checks, vec![("workflow-1".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn digest_of_empty_period() {
+        let state = ClientBuilder::default().create_state().await;
+        let now = Utc::now();
+        let digest = compute_digest(
+            &state.db,
+            &default_repo_name(),
+            now - Duration::days(7),
+            now,
+        )
+        .await
+        .unwrap();
+        assert_eq!(digest.landed_prs, 0);
+        assert_eq!(digest.failure_rate(), 0.0);
+        assert!(digest.to_string().contains("- No flaky checks"));
+    }
+}
@@ -0,0 +1,49 @@
+//! Reporting of errors and panics to Sentry (or a compatible service, e.g. GlitchTip), so that
+//! operators of the bot learn about failures before its users do.
+//!
+//! Reporting is a no-op until [`init`] is called.
+use anyhow::Context;
+use sentry::ClientInitGuard;
+
+use crate::github::{GithubRepoName, PullRequestNumber};
+use crate::utils::logging::CorrelationId;
+
+/// Starts reporting panics and errors passed to [`report_error`] to the Sentry project with the
+/// given DSN. Reports are sent until the returned guard is dropped.
+pub fn init(dsn: &str) -> anyhow::Result<ClientInitGuard> {
+    let dsn: sentry::types::Dsn = dsn.parse().context("Invalid Sentry DSN")?;
+    Ok(sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: sentry::release_name!(),
+        ..Default::default()
+    }))
+}
+
+/// Where has an error happened.
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorContext<'a> {
+    /// Name of the span of the event during which the error has happened.
+    pub event: &'a str,
+    pub repository: Option<&'a GithubRepoName>,
+    pub pr: Option<PullRequestNumber>,
+}
+
+/// Reports the error to Sentry, tagged with its context and with the correlation ID of the event
+/// that is being handled.
+pub fn report_error(error: &anyhow::Error, context: ErrorContext) {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("event", context.event);
+            if let Some(repository) = context.repository {
+                scope.set_tag("repository", repository);
+            }
+            if let Some(pr) = context.pr {
+                scope.set_tag("pr", pr);
+            }
+            if let Some(id) = CorrelationId::current() {
+                scope.set_tag("correlation_id", id);
+            }
+        },
+        || sentry::integrations::anyhow::capture_anyhow(error),
+    );
+}
@@ -0,0 +1,201 @@
+//! Injection of failures (errors of the GitHub API, lost webhooks, an unavailable database and a
+//! skewed clock), so that tests can exercise the retry, reconciliation and recovery paths of the
+//! bot deterministically.
+//!
+//! Faults can only be injected in unit tests and with the `fault-injection` feature. Otherwise
+//! nothing is ever injected and the bot behaves normally. GitHub errors and lost webhooks are
+//! simulated by the mock in [`crate::github::mock`], timeouts of the database by the check of its
+//! availability before events are handled, and the clock skew by the periodic refresh.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use reqwest::StatusCode;
+
+/// Failures injected into the bot, shared by its components.
+#[derive(Default)]
+pub struct Faults {
+    /// Statuses of the responses of the next failing GitHub API requests.
+    github_errors: Mutex<VecDeque<StatusCode>>,
+    /// Number of the next webhooks that are lost.
+    dropped_webhooks: AtomicUsize,
+    /// Number of the next checks of the database that time out.
+    db_timeouts: AtomicUsize,
+    /// Shift of the clock of the bot, in seconds.
+    clock_skew: AtomicI64,
+}
+
+impl Faults {
+    /// Makes the next `count` requests of the GitHub API fail with the given status.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn fail_github_requests(&self, count: usize, status: StatusCode) {
+        let mut errors = self.github_errors.lock().unwrap();
+        errors.extend(std::iter::repeat(status).take(count));
+    }
+
+    /// Makes the next `count` webhooks get lost before they are handled.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn drop_webhooks(&self, count: usize) {
+        self.dropped_webhooks.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Makes the next `count` checks of the availability of the database time out.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn time_out_database(&self, count: usize) {
+        self.db_timeouts.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Shifts the clock of the bot by the given duration (negative values move it to the past).
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn skew_clock(&self, skew: chrono::Duration) {
+        self.clock_skew.store(skew.num_seconds(), Ordering::SeqCst);
+    }
+
+    /// Status of the response of a GitHub API request that should fail.
+    pub fn take_github_error(&self) -> Option<StatusCode> {
+        self.github_errors.lock().unwrap().pop_front()
+    }
+
+    /// Should the webhook that has just been received be lost?
+    pub fn take_dropped_webhook(&self) -> bool {
+        take(&self.dropped_webhooks)
+    }
+
+    /// Should the check of the availability of the database time out?
+    pub fn take_db_timeout(&self) -> bool {
+        take(&self.db_timeouts)
+    }
+
+    pub fn clock_skew(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.clock_skew.load(Ordering::SeqCst))
+    }
+}
+
+/// Decrements the counter of a fault, returns `false` if the fault is not injected.
+fn take(counter: &AtomicUsize) -> bool {
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+            count.checked_sub(1)
+        })
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use crate::bors::event::BorsEvent;
+    use crate::config::CONFIG_FILE_PATH;
+    use crate::github::api::client::fetch_file_content;
+    use crate::github::mock::{MockGithub, MockPullRequest, MOCK_DEFAULT_BRANCH};
+    use crate::github::GithubRepoName;
+    use crate::tests::database::create_test_db;
+
+    async fn create_mock(config: &str) -> (MockGithub, GithubRepoName) {
+        let repo = GithubRepoName::new("owner", "name");
+        let mock = MockGithub::start().await.unwrap();
+        mock.add_repository(&repo, config);
+        mock.add_pull_request(&repo, MockPullRequest::new(1, "pr-sha"));
+        (mock, repo)
+    }
+
+    #[tokio::test]
+    async fn github_error() {
+        let (mock, repo) = create_mock("").await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        mock.faults()
+            .fail_github_requests(1, StatusCode::INTERNAL_SERVER_ERROR);
+        let webhook = mock.comment_webhook(&repo, 1, "reviewer", "@bors ping");
+        state.webhook("issue_comment", &webhook).await.unwrap();
+        state.webhook("issue_comment", &webhook).await.unwrap();
+        assert_eq!(
+            mock.comments(&repo, 1),
+            vec![
+                ":x: Encountered an error while executing command",
+                "Pong 🏓!"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn github_error_loading_file() {
+        let (mock, repo) = create_mock("").await;
+        let client = mock.repository_client(&repo).unwrap();
+        mock.faults()
+            .fail_github_requests(1, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(fetch_file_content(
+            client.client(),
+            &repo,
+            CONFIG_FILE_PATH,
+            MOCK_DEFAULT_BRANCH
+        )
+        .await
+        .is_err());
+        assert!(fetch_file_content(
+            client.client(),
+            &repo,
+            CONFIG_FILE_PATH,
+            MOCK_DEFAULT_BRANCH
+        )
+        .await
+        .unwrap()
+        .is_some());
+    }
+
+    #[tokio::test]
+    async fn dropped_webhook() {
+        let (mock, repo) = create_mock("").await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        mock.faults().drop_webhooks(1);
+        let webhook = mock.comment_webhook(&repo, 1, "reviewer", "@bors ping");
+        state.webhook("issue_comment", &webhook).await.unwrap();
+        assert!(mock.comments(&repo, 1).is_empty());
+        state.webhook("issue_comment", &webhook).await.unwrap();
+        assert_eq!(mock.comments(&repo, 1), vec!["Pong 🏓!"]);
+    }
+
+    #[tokio::test]
+    async fn database_timeout() {
+        let (mock, repo) = create_mock("").await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        mock.faults().time_out_database(1);
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors ping"),
+            )
+            .await
+            .unwrap();
+        // The postponed command is executed once the database is available again
+        state.event(BorsEvent::Refresh).await.unwrap();
+        assert_eq!(
+            mock.comments(&repo, 1),
+            vec![
+                ":warning: The database of the bot is currently unavailable. The command will be executed once it becomes available again.",
+                "Pong 🏓!"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn clock_skew() {
+        let (mock, repo) = create_mock("build_timeout = 3600").await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors try"),
+            )
+            .await
+            .unwrap();
+        state.event(BorsEvent::Refresh).await.unwrap();
+        assert_eq!(mock.comments(&repo, 1).len(), 1);
+
+        mock.faults().skew_clock(chrono::Duration::hours(2));
+        state.event(BorsEvent::Refresh).await.unwrap();
+        assert_eq!(
+            mock.comments(&repo, 1).last().unwrap(),
+            ":boom: Test timed out"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Recording of received webhooks as fixtures, which can be replayed in tests.
+//!
+//! When recording is enabled, every authenticated webhook is stored as a JSON file in the
+//! recording directory, after personal data (e.g. emails) has been removed from its payload. The
+//! files are numbered in the order in which the webhooks have been received, so that a sequence of
+//! webhooks that has triggered a bug in production can be copied to `tests/data/fixtures` and
+//! replayed against the test forge as a deterministic regression test.
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::bors::event::BorsEvent;
+use crate::ci::buildkite::BuildkiteForge;
+use crate::ci::circleci::CircleCiForge;
+use crate::ci::jenkins::JenkinsForge;
+use crate::forge::Forge;
+use crate::gitea::GiteaForge;
+use crate::github::GithubForge;
+use crate::gitlab::GitlabForge;
+
+/// Keys of payload fields that contain personal data or credentials. Their values are replaced
+/// by [`REDACTED`].
+const SENSITIVE_KEYS: &[&str] = &["email", "token", "secret", "password"];
+
+/// Keys of payload fields that are removed, because they identify the installation of the bot.
+const REMOVED_KEYS: &[&str] = &["installation"];
+
+const REDACTED: &str = "<redacted>";
+
+/// A webhook received from a forge or a CI service.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookFixture {
+    /// Name of the forge that has sent the webhook, see [`Forge::name`].
+    pub forge: String,
+    /// Event type of the webhook, as accepted by [`Forge::parse_webhook`].
+    pub event_type: String,
+    pub payload: Value,
+}
+
+impl WebhookFixture {
+    /// Parses the webhook into the event that the bot has handled when it was received.
+    /// Returns `None` if the webhook is not interesting for the bot.
+    pub fn parse(&self) -> anyhow::Result<Option<BorsEvent>> {
+        let forge = forge_by_name(&self.forge)
+            .ok_or_else(|| anyhow::anyhow!("Unknown forge `{}`", self.forge))?;
+        let body = serde_json::to_vec(&self.payload)?;
+        forge.parse_webhook(&self.event_type, &body)
+    }
+}
+
+/// Stores received webhooks in a directory.
+pub struct FixtureRecorder {
+    directory: PathBuf,
+    /// Number of the next recorded webhook.
+    next: AtomicUsize,
+}
+
+impl FixtureRecorder {
+    /// Records webhooks into the given directory, which is created if it does not exist.
+    /// Webhooks recorded before (e.g. before a restart) are kept.
+    pub fn new(directory: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Cannot create directory {}", directory.display()))?;
+        let recorded = fixture_files(&directory)?.len();
+        Ok(Self {
+            directory,
+            next: AtomicUsize::new(recorded + 1),
+        })
+    }
+
+    /// Stores the sanitized payload of a webhook. Failures are only logged, so that they do not
+    /// prevent the webhook from being processed.
+    pub fn record(&self, forge: &dyn Forge, event_type: &str, body: &[u8]) {
+        if let Err(error) = self.try_record(forge, event_type, body) {
+            tracing::error!(
+                "Could not record {} webhook fixture: {error:?}",
+                forge.name()
+            );
+        }
+    }
+
+    fn try_record(&self, forge: &dyn Forge, event_type: &str, body: &[u8]) -> anyhow::Result<()> {
+        let mut payload: Value = serde_json::from_slice(body)?;
+        sanitize_payload(&mut payload);
+        let fixture = WebhookFixture {
+            forge: forge.name().to_string(),
+            event_type: event_type.to_string(),
+            payload,
+        };
+
+        let number = self.next.fetch_add(1, Ordering::SeqCst);
+        let event_name: String = event_type
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let path = self
+            .directory
+            .join(format!("{number:05}-{}-{event_name}.json", forge.name()));
+        std::fs::write(&path, serde_json::to_string_pretty(&fixture)?)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        tracing::debug!("Recorded webhook fixture {}", path.display());
+        Ok(())
+    }
+}
+
+/// Loads the webhooks recorded in the given directory, in the order in which they were received.
+pub fn load_fixtures(directory: &Path) -> anyhow::Result<Vec<WebhookFixture>> {
+    fixture_files(directory)?
+        .into_iter()
+        .map(|path| {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Cannot read {}", path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Cannot parse fixture {}", path.display()))
+        })
+        .collect()
+}
+
+/// Replaces personal data and credentials in a webhook payload, while keeping its structure, so
+/// that it can still be parsed.
+pub fn sanitize_payload(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, _| !REMOVED_KEYS.contains(&key.as_str()));
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) && value.is_string() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    sanitize_payload(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(sanitize_payload),
+        _ => {}
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS
+        .iter()
+        .any(|sensitive| key.contains(sensitive))
+}
+
+fn forge_by_name(name: &str) -> Option<&'static dyn Forge> {
+    let forges: [&'static dyn Forge; 6] = [
+        &GithubForge,
+        &GitlabForge,
+        &GiteaForge,
+        &BuildkiteForge,
+        &JenkinsForge,
+        &CircleCiForge,
+    ];
+    forges.into_iter().find(|forge| forge.name() == name)
+}
+
+/// Paths of the recorded fixtures in the directory, sorted by their number.
+fn fixture_files(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("Cannot read directory {}", directory.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) == Some("json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::bors::event::BorsEvent;
+    use crate::fixtures::{load_fixtures, sanitize_payload, FixtureRecorder, WebhookFixture};
+    use crate::github::GithubForge;
+    use crate::tests::event::default_pr_number;
+    use crate::tests::fixtures::replay_scenario;
+    use crate::tests::io::load_test_file;
+    use crate::tests::state::{default_merge_sha, ClientBuilder};
+
+    #[test]
+    fn sanitize() {
+        let mut payload = json!({
+            "installation": {"id": 1},
+            "sender": {"login": "alice", "email": "alice@example.com"},
+            "commits": [{"author": {"email": "bob@example.com", "name": "Bob"}}],
+            "webhook_token": "secret",
+        });
+        sanitize_payload(&mut payload);
+        assert_eq!(
+            payload,
+            json!({
+                "sender": {"login": "alice", "email": "<redacted>"},
+                "commits": [{"author": {"email": "<redacted>", "name": "Bob"}}],
+                "webhook_token": "<redacted>",
+            })
+        );
+    }
+
+    #[test]
+    fn sanitized_payload_can_be_parsed() {
+        let mut payload =
+            serde_json::from_str(&load_test_file("webhook/issue-comment.json")).unwrap();
+        sanitize_payload(&mut payload);
+        let fixture = WebhookFixture {
+            forge: "github".to_string(),
+            event_type: "issue_comment".to_string(),
+            payload,
+        };
+        assert!(matches!(
+            fixture.parse().unwrap(),
+            Some(BorsEvent::Comment(_))
+        ));
+    }
+
+    #[test]
+    fn record_and_load() {
+        let directory = std::env::temp_dir().join(format!("bors-fixtures-{}", std::process::id()));
+        let recorder = FixtureRecorder::new(directory.clone()).unwrap();
+        recorder.record(&GithubForge, "issue_comment", br#"{"action": "created"}"#);
+        recorder.record(&GithubForge, "push", br#"{"ref": "refs/heads/main"}"#);
+        // Recording continues after the fixtures that already exist
+        let recorder = FixtureRecorder::new(directory.clone()).unwrap();
+        recorder.record(&GithubForge, "check_suite", br#"{"action": "completed"}"#);
+
+        let fixtures = load_fixtures(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let event_types: Vec<_> = fixtures
+            .iter()
+            .map(|fixture| fixture.event_type.as_str())
+            .collect();
+        assert_eq!(event_types, vec!["issue_comment", "push", "check_suite"]);
+    }
+
+    #[tokio::test]
+    async fn replay_approve_and_try() {
+        let mut state = ClientBuilder::default().create_state().await;
+        replay_scenario(&mut state, "approve-and-try").await;
+        state.client().check_comments(
+            default_pr_number(),
+            &[
+                ":pushpin: Commit pr-sha has been approved by `reviewer`",
+                ":hourglass: Trying commit pr-sha with merge sha-merged…",
+            ],
+        );
+        state
+            .client()
+            .check_branch_history("automation/bors/try", &[&default_merge_sha()]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Abstraction of the code hosting service ("forge") that hosts the repositories managed by the bot.
+//!
+//! The command handlers and the merge queue work only with [`BorsState`](crate::bors::BorsState),
+//! [`RepositoryClient`](crate::bors::RepositoryClient) (comments, branches, merges, checks and
+//! labels) and [`PermissionResolver`](crate::permissions::PermissionResolver). To plug in another
+//! forge, these traits have to be implemented for it, together with [`Forge`], which turns the
+//! webhooks of the forge into [`BorsEvent`]s. Repositories are identified by their `owner/name`,
+//! prefixed by the name of the forge for other forges than GitHub (e.g. `gitlab:group/project`,
+//! see [`GithubRepoName`](crate::github::GithubRepoName)).
+use std::fmt::Debug;
+
+use axum::body::HttpBody;
+use axum::http::{HeaderMap, Request, StatusCode};
+use hyper::body::Buf;
+
+use crate::bors::event::BorsEvent;
+use crate::github::server::ServerStateRef;
+use crate::github::WebhookSecret;
+
+/// Receives webhooks of a forge.
+pub trait Forge: Send + Sync {
+    /// Name of the forge, e.g. `github`.
+    fn name(&self) -> &'static str;
+
+    /// Checks that the webhook has been sent by the forge, using the shared webhook secret.
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool;
+
+    /// Returns the type of the webhook event, which is passed to [`Forge::parse_webhook`].
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str>;
+
+    /// Returns the unique ID of the webhook delivery, if the forge sends one.
+    fn delivery_id<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str>;
+
+    /// Event type under which the webhook is stored in the delivery log. It has to be accepted by
+    /// [`Forge::parse_webhook`] when the delivery is replayed.
+    fn recorded_event_type(&self, event_type: &str) -> String {
+        event_type.to_string()
+    }
+
+    /// Parses the payload of a webhook with the given event type.
+    /// Returns `None` if the webhook is not interesting for the bot.
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>>;
+}
+
+/// Maximum size of a webhook payload used when no limit is configured. GitHub does not send
+/// larger payloads.
+pub const DEFAULT_MAX_WEBHOOK_SIZE: usize = 25 * 1024 * 1024;
+
+/// Authenticates and parses a webhook of the forge and records it in the delivery log.
+///
+/// Returns [`StatusCode::OK`] as the error if the webhook is valid, but is ignored by the bot.
+/// Webhooks larger than the configured limit are rejected with
+/// [`StatusCode::PAYLOAD_TOO_LARGE`].
+pub async fn extract_webhook<B>(
+    forge: &dyn Forge,
+    request: Request<B>,
+    state: &ServerStateRef,
+) -> Result<BorsEvent, StatusCode>
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    if state.is_shutting_down() {
+        tracing::warn!(
+            "Rejecting {} webhook, because the bot is shutting down",
+            forge.name()
+        );
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let (parts, body) = request.into_parts();
+    let body = read_body(body, &parts.headers, state.max_webhook_size()).await?;
+
+    // Verify that the request is valid
+    let secrets = state.get_webhook_secrets_for(forge);
+    if !secrets
+        .iter()
+        .any(|secret| forge.verify_webhook(&parts.headers, &body, secret))
+    {
+        tracing::error!(
+            "Webhook request failed, could not authenticate {} webhook",
+            forge.name()
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(event_type) = forge.event_type(&parts.headers) else {
+        tracing::error!(
+            "Webhook request failed, {} webhook has no event type",
+            forge.name()
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // Parse webhook content
+    let event = forge.parse_webhook(event_type, &body);
+    let delivery_id = forge.delivery_id(&parts.headers).unwrap_or_default();
+    state
+        .record_webhook_delivery(
+            delivery_id,
+            &forge.recorded_event_type(event_type),
+            &body,
+            &delivery_result(&event),
+        )
+        .await;
+    if event.is_ok() {
+        state.record_fixture(forge, &forge.recorded_event_type(event_type), &body);
+    }
+    match event {
+        Ok(Some(event)) => {
+            tracing::trace!("Received webhook event {event:?}");
+            Ok(event)
+        }
+        Ok(None) => Err(StatusCode::OK),
+        Err(error) => {
+            tracing::error!("Cannot parse webhook event: {error:?}");
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Reads the body of a webhook chunk by chunk into a buffer allocated once using its
+/// `Content-Length`. The request is rejected as soon as it is known to exceed `limit`, before the
+/// rest of it is received.
+///
+/// The body is not deserialized while it is streamed: the signature of the webhook covers the whole
+/// body and has to be verified before anything is parsed, and the body is stored in the delivery
+/// log.
+async fn read_body<B>(body: B, headers: &HeaderMap, limit: usize) -> Result<Vec<u8>, StatusCode>
+where
+    B: HttpBody,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let length = header(headers, "content-length").and_then(|length| length.parse().ok());
+    if let Some(length) = length.filter(|length: &usize| *length > limit) {
+        tracing::warn!("Rejecting webhook of {length} bytes, the limit is {limit} bytes");
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let mut buffer = Vec::with_capacity(length.unwrap_or_default());
+    let mut body = Box::pin(body);
+    while let Some(chunk) = body.data().await {
+        let mut chunk = chunk.map_err(|error| {
+            tracing::error!("Reading webhook body failed: {error:?}");
+            StatusCode::BAD_REQUEST
+        })?;
+        if buffer.len() + chunk.remaining() > limit {
+            tracing::warn!("Rejecting webhook larger than {limit} bytes");
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        while chunk.has_remaining() {
+            let read = chunk.chunk().len();
+            buffer.extend_from_slice(chunk.chunk());
+            chunk.advance(read);
+        }
+    }
+    Ok(buffer)
+}
+
+/// Result of a delivery that contained an event handled by the bot.
+pub const ACCEPTED_DELIVERY: &str = "accepted";
+
+/// Describes what has been done with a webhook, to be stored in its delivery log.
+pub fn delivery_result(event: &anyhow::Result<Option<BorsEvent>>) -> String {
+    match event {
+        Ok(Some(_)) => ACCEPTED_DELIVERY.to_string(),
+        Ok(None) => "ignored".to_string(),
+        Err(error) => format!("invalid: {error}"),
+    }
+}
+
+/// Compares a secret token received in a webhook in constant time.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Returns the value of a header, if it is valid UTF-8.
+pub fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
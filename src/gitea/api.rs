@@ -0,0 +1,176 @@
+//! A minimal client of the Gitea (and Forgejo) REST API (v1), authenticated with an access token of
+//! the bot user.
+use anyhow::Context;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde::de::DeserializeOwned;
+
+use crate::github::GithubRepoName;
+use crate::http::SendLimited;
+
+/// Number of items requested on a single page of a list endpoint.
+pub const PAGE_SIZE: usize = 50;
+
+/// A request to the Gitea API has failed with an unexpected status.
+#[derive(Debug, thiserror::Error)]
+#[error("Gitea API request failed ({status}): {text}")]
+pub struct GiteaApiError {
+    pub status: StatusCode,
+    pub text: String,
+}
+
+pub struct GiteaApi {
+    client: reqwest::Client,
+    /// URL of the Gitea instance, e.g. `https://codeberg.org`.
+    url: String,
+    token: SecretString,
+}
+
+impl GiteaApi {
+    pub fn new(url: &str, token: SecretString) -> Self {
+        Self {
+            client: crate::http::client(),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    /// Sends a request to the given path (relative to `/api/v1`) and returns the status and the
+    /// body of the response, regardless of the status.
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}/api/v1{path}", self.url))
+            .header(
+                AUTHORIZATION,
+                format!("token {}", self.token.expose_secret()),
+            );
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        let response = request
+            .send_limited()
+            .await
+            .with_context(|| format!("Cannot send Gitea API request {method} {path}"))?;
+        let status = response.status();
+        let text = response.text().await?;
+        Ok((status, text))
+    }
+
+    /// Sends a request and parses its successful response.
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<T> {
+        let (status, text) = self.send(method, path, body).await?;
+        if !status.is_success() {
+            return Err(GiteaApiError { status, text }.into());
+        }
+        serde_json::from_str(&text)
+            .with_context(|| format!("Cannot parse Gitea API response of {path}"))
+    }
+
+    /// Sends a request whose response has no interesting content (or no content at all).
+    pub async fn execute(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        let (status, text) = self.send(method, path, body).await?;
+        if !status.is_success() {
+            return Err(GiteaApiError { status, text }.into());
+        }
+        Ok(())
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        self.call(Method::GET, path, None).await
+    }
+
+    /// Loads a resource, or returns `None` if it does not exist.
+    pub async fn get_optional<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Option<T>> {
+        match self.get(path).await {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => match error.downcast_ref::<GiteaApiError>() {
+                Some(GiteaApiError {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                }) => Ok(None),
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Loads all items of a list endpoint. `path` must already contain a query string.
+    pub async fn get_all<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Vec<T>> {
+        let mut items = vec![];
+        for page in 1.. {
+            let batch: Vec<T> = self
+                .get(&format!("{path}&limit={PAGE_SIZE}&page={page}"))
+                .await?;
+            let last_page = batch.len() < PAGE_SIZE;
+            items.extend(batch);
+            if last_page {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// Path of the repository in API URLs, e.g. `/repos/owner/name`.
+pub fn repo_path(repo: &GithubRepoName) -> String {
+    format!("/repos/{}/{}", encode(repo.owner()), encode(repo.name()))
+}
+
+/// Encodes a path (e.g. a branch name or a file path) used in an API URL. Slashes are kept,
+/// because Gitea matches the rest of the URL as the path.
+pub fn encode_path(value: &str) -> String {
+    value.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+/// Encodes a single segment or a query value of an API URL.
+pub fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
+
+/// Parses the full name of a repository (`owner/name`).
+pub fn parse_repo_name(full_name: &str) -> anyhow::Result<GithubRepoName> {
+    match full_name.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(GithubRepoName::on_forge("gitea", owner, name))
+        }
+        _ => Err(anyhow::anyhow!("Invalid Gitea repository `{full_name}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gitea::api::{encode_path, parse_repo_name, repo_path};
+
+    #[test]
+    fn repository_paths() {
+        let repo = parse_repo_name("Owner/Repo").unwrap();
+        assert_eq!(repo_path(&repo), "/repos/owner/repo");
+        assert_eq!(repo.to_string(), "gitea:owner/repo");
+        assert_eq!(
+            encode_path("automation/bors/try merge"),
+            "automation/bors/try%20merge"
+        );
+        assert!(parse_repo_name("repo").is_err());
+        assert!(parse_repo_name("group/sub/repo").is_err());
+    }
+}
@@ -0,0 +1,744 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use octocrab::models::RunId;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use crate::bors::{
+    BranchProtection, CheckSuite, CheckSuiteStatus, DispatchedWorkflow, JobLog, RepositoryClient,
+    WorkflowRunDetails,
+};
+use crate::gitea::api::{encode, encode_path, repo_path, GiteaApi, GiteaApiError};
+use crate::github::{
+    Branch, BranchUpdateError, CheckAnnotation, CommentId, Commit, CommitSha, GithubRepoName,
+    MergeError, Milestone, PullRequest, PullRequestNumber,
+};
+
+/// Provides access to a single Gitea (or Forgejo) repository. Builds are reported by commit
+/// statuses, so all workflows are external and cannot be inspected, cancelled or dispatched.
+pub struct GiteaRepositoryClient {
+    pub api: Arc<GiteaApi>,
+    pub repo_name: GithubRepoName,
+    /// Login of the bot user, which is allowed to push to protected branches.
+    pub bot_username: String,
+}
+
+impl GiteaRepositoryClient {
+    fn repo(&self) -> String {
+        repo_path(&self.repo_name)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UserPayload {
+    login: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct LabelPayload {
+    id: u64,
+    name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BranchRefPayload {
+    #[serde(rename = "ref")]
+    name: String,
+    sha: String,
+    repo_id: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PullRequestPayload {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    user: UserPayload,
+    #[serde(default)]
+    draft: bool,
+    state: String,
+    #[serde(default)]
+    merged: bool,
+    #[serde(default)]
+    labels: Vec<LabelPayload>,
+    head: BranchRefPayload,
+    base: BranchRefPayload,
+    /// Common ancestor of the head and of the current commit of the base branch.
+    merge_base: Option<String>,
+    #[serde(default)]
+    additions: u64,
+    #[serde(default)]
+    deletions: u64,
+    #[serde(default)]
+    changed_files: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CommentPayload {
+    id: u64,
+}
+
+// Each API call has its own span, nested in the span of the event (and build) that has made it.
+#[async_trait]
+impl RepositoryClient for GiteaRepositoryClient {
+    fn repository(&self) -> &GithubRepoName {
+        &self.repo_name
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request(&mut self, pr: PullRequestNumber) -> anyhow::Result<PullRequest> {
+        let pull: PullRequestPayload =
+            self.api
+                .get(&format!("{}/pulls/{pr}", self.repo()))
+                .await
+                .with_context(|| format!("Could not get PR {}#{pr}", self.repo_name))?;
+        Ok(gitea_pr_to_pr(&self.repo_name, pull))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_files(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct FilePayload {
+            filename: String,
+        }
+
+        let files: Vec<FilePayload> = self
+            .api
+            .get_all(&format!("{}/pulls/{pr}/files?", self.repo()))
+            .await
+            .with_context(|| format!("Cannot load files of PR {pr}"))?;
+        Ok(files.into_iter().map(|file| file.filename).collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_commits(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<Commit>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct AuthorPayload {
+            name: String,
+            email: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct VerificationPayload {
+            verified: bool,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitDetailsPayload {
+            message: String,
+            author: AuthorPayload,
+            verification: Option<VerificationPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            sha: String,
+            commit: CommitDetailsPayload,
+        }
+
+        let commits: Vec<CommitPayload> = self
+            .api
+            .get_all(&format!(
+                "{}/pulls/{pr}/commits?verification=true&files=false",
+                self.repo()
+            ))
+            .await
+            .with_context(|| format!("Cannot load commits of PR {pr}"))?;
+        Ok(commits
+            .into_iter()
+            .map(|commit| Commit {
+                sha: CommitSha(commit.sha),
+                message: commit.commit.message,
+                author_name: commit.commit.author.name,
+                author_email: commit.commit.author.email,
+                verified: commit
+                    .commit
+                    .verification
+                    .map_or(false, |verification| verification.verified),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn is_commit_verified(&mut self, sha: &CommitSha) -> anyhow::Result<bool> {
+        #[derive(serde::Deserialize, Debug)]
+        struct VerificationPayload {
+            verified: bool,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitDetailsPayload {
+            verification: Option<VerificationPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            commit: CommitDetailsPayload,
+        }
+
+        let commit: CommitPayload = self
+            .api
+            .call(
+                Method::GET,
+                &format!("{}/git/commits/{sha}", self.repo()),
+                None,
+            )
+            .await
+            .with_context(|| format!("Cannot load commit {sha}"))?;
+        Ok(commit
+            .commit
+            .verification
+            .map_or(false, |verification| verification.verified))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn post_comment(
+        &mut self,
+        pr: PullRequestNumber,
+        text: &str,
+    ) -> anyhow::Result<CommentId> {
+        let comment: CommentPayload = self
+            .api
+            .call(
+                Method::POST,
+                &format!("{}/issues/{pr}/comments", self.repo()),
+                Some(json!({ "body": text })),
+            )
+            .await
+            .with_context(|| format!("Cannot post comment to PR {}#{pr}", self.repo_name))?;
+        Ok(CommentId(comment.id.to_string()))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn edit_comment(&mut self, comment: &CommentId, text: &str) -> anyhow::Result<()> {
+        self.api
+            .execute(
+                Method::PATCH,
+                &format!("{}/issues/comments/{}", self.repo(), encode(&comment.0)),
+                Some(json!({ "body": text })),
+            )
+            .await
+            .with_context(|| format!("Cannot edit comment {comment}"))
+    }
+
+    async fn minimize_comment(&mut self, comment: &CommentId) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Gitea cannot hide comment {comment}, use `outdated_comments = \"edit\"` instead"
+        ))
+    }
+
+    async fn post_commit_comment(&mut self, sha: &CommitSha, _text: &str) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Gitea does not support comments on commit {sha}"
+        ))
+    }
+
+    /// Gitea has no check runs, the result is reported as a commit status. Annotations are
+    /// appended to the description.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn create_check_run(
+        &mut self,
+        sha: &CommitSha,
+        name: &str,
+        success: bool,
+        summary: &str,
+        annotations: &[CheckAnnotation],
+    ) -> anyhow::Result<()> {
+        let mut description = summary.to_string();
+        for annotation in annotations {
+            description.push_str(&format!(
+                "\n{}:{}: {}",
+                annotation.path, annotation.line, annotation.message
+            ));
+        }
+        self.api
+            .execute(
+                Method::POST,
+                &format!("{}/statuses/{sha}", self.repo()),
+                Some(json!({
+                    "state": if success { "success" } else { "failure" },
+                    "context": name,
+                    "description": description,
+                })),
+            )
+            .await
+            .with_context(|| format!("Cannot set status {name} of commit {sha}"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_file_content(
+        &mut self,
+        sha: &CommitSha,
+        path: &str,
+    ) -> anyhow::Result<Option<String>> {
+        fetch_file_content(&self.api, &self.repo_name, path, &sha.0).await
+    }
+
+    /// Gitea cannot move a branch to another commit, so the branch is deleted and created again.
+    /// This is fine for the branches used by bors, which are never updated by anyone else.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_branch_to_sha(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError> {
+        self.delete_branch(branch)
+            .await
+            .map_err(|error| BranchUpdateError::Custom(format!("{error:?}")))?;
+        let result = self
+            .api
+            .execute(
+                Method::POST,
+                &format!("{}/branches", self.repo()),
+                Some(json!({ "new_branch_name": branch, "old_ref_name": sha.0 })),
+            )
+            .await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => match error.downcast_ref::<GiteaApiError>() {
+                Some(GiteaApiError {
+                    status: StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED,
+                    ..
+                }) => Err(BranchUpdateError::PermissionDenied(branch.to_string())),
+                _ => Err(BranchUpdateError::Custom(format!("{error:?}"))),
+            },
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn delete_branch(&mut self, branch: &str) -> anyhow::Result<()> {
+        let (status, text) = self
+            .api
+            .send(
+                Method::DELETE,
+                &format!("{}/branches/{}", self.repo(), encode_path(branch)),
+                None,
+            )
+            .await?;
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            Ok(())
+        } else {
+            Err(GiteaApiError { status, text })
+                .with_context(|| format!("Cannot delete branch {branch}"))
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_branch_sha(&mut self, branch: &str) -> anyhow::Result<Option<CommitSha>> {
+        fetch_branch_sha(&self.api, &self.repo_name, branch).await
+    }
+
+    /// Gitea cannot merge arbitrary commits, so only PRs that are based on the current commit of
+    /// their base branch (which is what `base` is set to) can be tested. Their "merge" is a
+    /// fast-forward to the head of the PR, so the commit message is not used. Other PRs are
+    /// reported as conflicting, and have to be rebased.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn merge_branches(
+        &mut self,
+        base: &str,
+        head: &CommitSha,
+        _commit_message: &str,
+    ) -> Result<CommitSha, MergeError> {
+        let base_sha = fetch_branch_sha(&self.api, &self.repo_name, base)
+            .await
+            .map_err(merge_error)?
+            .ok_or(MergeError::NotFound)?;
+        let pulls: Vec<PullRequestPayload> = self
+            .api
+            .get_all(&format!("{}/pulls?state=open", self.repo()))
+            .await
+            .map_err(merge_error)?;
+        let Some(pull) = pulls.into_iter().find(|pull| pull.head.sha == head.0) else {
+            tracing::warn!("No open PR has head {head}, cannot merge it into {base}");
+            return Err(MergeError::NotFound);
+        };
+        if pull.merge_base.as_deref() == Some(base_sha.0.as_str()) {
+            Ok(head.clone())
+        } else {
+            tracing::info!(
+                "PR {} is not based on {base_sha}, it cannot be fast-forwarded",
+                pull.number
+            );
+            Err(MergeError::Conflict)
+        }
+    }
+
+    /// Each commit status (the latest one of each context) is a check suite.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_check_suites_for_commit(
+        &mut self,
+        _branch: &str,
+        sha: &CommitSha,
+    ) -> anyhow::Result<Vec<CheckSuite>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct StatusPayload {
+            status: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CombinedStatusPayload {
+            #[serde(default)]
+            statuses: Vec<StatusPayload>,
+        }
+
+        let combined: CombinedStatusPayload = self
+            .api
+            .get(&format!("{}/commits/{sha}/status", self.repo()))
+            .await
+            .with_context(|| format!("Cannot load statuses of commit {sha}"))?;
+        Ok(combined
+            .statuses
+            .into_iter()
+            .map(|status| CheckSuite {
+                status: commit_status(&status.status),
+            })
+            .collect())
+    }
+
+    async fn get_workflow_run_details(
+        &mut self,
+        _run_id: RunId,
+    ) -> anyhow::Result<WorkflowRunDetails> {
+        Ok(WorkflowRunDetails::default())
+    }
+
+    async fn get_failed_job_logs(
+        &mut self,
+        _run_id: RunId,
+        _max_size: usize,
+    ) -> anyhow::Result<Vec<JobLog>> {
+        Ok(vec![])
+    }
+
+    async fn cancel_workflows(&mut self, run_ids: Vec<RunId>) -> anyhow::Result<()> {
+        if run_ids.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Workflows of Gitea repositories cannot be cancelled"
+            ))
+        }
+    }
+
+    async fn dispatch_workflow(
+        &mut self,
+        workflow: &str,
+        _git_ref: &str,
+        _inputs: &HashMap<String, String>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        Err(anyhow::anyhow!(
+            "Workflow {workflow} cannot be dispatched in a Gitea repository"
+        ))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn add_labels(&mut self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()> {
+        if labels.is_empty() {
+            return Ok(());
+        }
+        let ids = self.label_ids(labels).await?;
+        self.api
+            .execute(
+                Method::POST,
+                &format!("{}/issues/{pr}/labels", self.repo()),
+                Some(json!({ "labels": ids })),
+            )
+            .await
+            .context("Cannot add label(s) to PR")
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn remove_labels(
+        &mut self,
+        pr: PullRequestNumber,
+        labels: &[String],
+    ) -> anyhow::Result<()> {
+        if labels.is_empty() {
+            return Ok(());
+        }
+        for id in self.label_ids(labels).await? {
+            let (status, text) = self
+                .api
+                .send(
+                    Method::DELETE,
+                    &format!("{}/issues/{pr}/labels/{id}", self.repo()),
+                    None,
+                )
+                .await?;
+            if !status.is_success() && status != StatusCode::NOT_FOUND {
+                return Err(GiteaApiError { status, text })
+                    .context("Cannot remove label(s) from PR");
+            }
+        }
+        Ok(())
+    }
+
+    async fn add_to_merge_queue(&mut self, _pr: PullRequestNumber) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "The GitHub merge queue cannot be used with Gitea"
+        ))
+    }
+
+    async fn remove_from_merge_queue(&mut self, _pr: PullRequestNumber) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "The GitHub merge queue cannot be used with Gitea"
+        ))
+    }
+
+    /// Only the bot can push to the protected branch, after the required statuses have passed.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn protect_branch(
+        &mut self,
+        branch: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()> {
+        // Existing protection is replaced as a whole
+        let (status, text) = self
+            .api
+            .send(
+                Method::DELETE,
+                &format!("{}/branch_protections/{}", self.repo(), encode_path(branch)),
+                None,
+            )
+            .await?;
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            return Err(GiteaApiError { status, text })
+                .with_context(|| format!("Cannot unprotect branch {branch}"));
+        }
+        self.api
+            .execute(
+                Method::POST,
+                &format!("{}/branch_protections", self.repo()),
+                Some(json!({
+                    "rule_name": branch,
+                    "enable_push": true,
+                    "enable_push_whitelist": true,
+                    "push_whitelist_usernames": [self.bot_username],
+                    "enable_force_push": protection.allow_force_pushes,
+                    "enable_force_push_allowlist": true,
+                    "force_push_allowlist_usernames": [self.bot_username],
+                    "enable_status_check": !protection.required_checks.is_empty(),
+                    "status_check_contexts": protection.required_checks,
+                })),
+            )
+            .await
+            .with_context(|| format!("Cannot protect branch {branch}"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_open_milestones(&mut self) -> anyhow::Result<Vec<Milestone>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct MilestonePayload {
+            id: u64,
+            title: String,
+            due_on: Option<DateTime<Utc>>,
+        }
+
+        let milestones: Vec<MilestonePayload> = self
+            .api
+            .get_all(&format!("{}/milestones?state=open", self.repo()))
+            .await
+            .context("Cannot load milestones")?;
+        Ok(milestones
+            .into_iter()
+            .map(|milestone| Milestone {
+                number: milestone.id,
+                title: milestone.title,
+                due_on: milestone.due_on,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_milestone(&mut self, pr: PullRequestNumber, milestone: u64) -> anyhow::Result<()> {
+        self.api
+            .execute(
+                Method::PATCH,
+                &format!("{}/issues/{pr}", self.repo()),
+                Some(json!({ "milestone": milestone })),
+            )
+            .await
+            .with_context(|| format!("Cannot set milestone of PR {pr}"))
+    }
+}
+
+impl GiteaRepositoryClient {
+    /// Labels are added and removed using their IDs. Labels that do not exist in the repository
+    /// are skipped.
+    async fn label_ids(&self, labels: &[String]) -> anyhow::Result<Vec<u64>> {
+        let existing: Vec<LabelPayload> = self
+            .api
+            .get_all(&format!("{}/labels?", self.repo()))
+            .await
+            .context("Cannot load labels")?;
+        Ok(labels
+            .iter()
+            .filter_map(|name| {
+                let label = existing.iter().find(|label| &label.name == name);
+                if label.is_none() {
+                    tracing::warn!("Label {name} does not exist in {}", self.repo_name);
+                }
+                label.map(|label| label.id)
+            })
+            .collect())
+    }
+}
+
+/// Loads the content of the file at `path` in the given branch or commit, or `None` if the file
+/// does not exist.
+pub async fn fetch_file_content(
+    api: &GiteaApi,
+    repo: &GithubRepoName,
+    path: &str,
+    git_ref: &str,
+) -> anyhow::Result<Option<String>> {
+    let (status, text) = api
+        .send(
+            Method::GET,
+            &format!(
+                "{}/raw/{}?ref={}",
+                repo_path(repo),
+                encode_path(path),
+                encode(git_ref)
+            ),
+            None,
+        )
+        .await?;
+    match status {
+        _ if status.is_success() => Ok(Some(text)),
+        StatusCode::NOT_FOUND => Ok(None),
+        _ => Err(GiteaApiError { status, text })
+            .with_context(|| format!("Cannot load file {path} of {repo}")),
+    }
+}
+
+async fn fetch_branch_sha(
+    api: &GiteaApi,
+    repo: &GithubRepoName,
+    branch: &str,
+) -> anyhow::Result<Option<CommitSha>> {
+    #[derive(serde::Deserialize, Debug)]
+    struct CommitPayload {
+        id: String,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct BranchPayload {
+        commit: CommitPayload,
+    }
+
+    let branch: Option<BranchPayload> = api
+        .get_optional(&format!(
+            "{}/branches/{}",
+            repo_path(repo),
+            encode_path(branch)
+        ))
+        .await
+        .with_context(|| format!("Cannot load branch {branch}"))?;
+    Ok(branch.map(|branch| CommitSha(branch.commit.id)))
+}
+
+/// Maps the state of a Gitea commit status to the status of a check suite.
+pub fn commit_status(state: &str) -> CheckSuiteStatus {
+    match state {
+        "success" | "warning" => CheckSuiteStatus::Success,
+        "failure" | "error" => CheckSuiteStatus::Failure,
+        _ => CheckSuiteStatus::Pending,
+    }
+}
+
+fn merge_error(error: anyhow::Error) -> MergeError {
+    match error.downcast::<GiteaApiError>() {
+        Ok(GiteaApiError { status, text }) => MergeError::Unknown { status, text },
+        Err(error) => MergeError::Unknown {
+            status: StatusCode::BAD_GATEWAY,
+            text: format!("{error:?}"),
+        },
+    }
+}
+
+fn gitea_pr_to_pr(repo: &GithubRepoName, pull: PullRequestPayload) -> PullRequest {
+    // The owner of the head is only used to find out whether the PR comes from a fork
+    let head_owner = if pull.head.repo_id == pull.base.repo_id {
+        repo.owner().to_string()
+    } else {
+        pull.user.login.clone()
+    };
+    PullRequest {
+        number: pull.number.into(),
+        head_label: format!("{head_owner}:{}", pull.head.name),
+        head: Branch {
+            name: pull.head.name,
+            sha: pull.head.sha.into(),
+        },
+        base: Branch {
+            name: pull.base.name,
+            sha: pull.base.sha.into(),
+        },
+        title: pull.title,
+        message: pull.body.unwrap_or_default(),
+        author: pull.user.login,
+        draft: pull.draft,
+        open: pull.state == "open",
+        merged: pull.merged,
+        labels: pull.labels.into_iter().map(|label| label.name).collect(),
+        changed_lines: pull.additions + pull.deletions,
+        changed_files: pull.changed_files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bors::CheckSuiteStatus;
+    use crate::gitea::client::{commit_status, gitea_pr_to_pr, PullRequestPayload};
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn pull_request_from_fork() {
+        let pull: PullRequestPayload = serde_json::from_value(serde_json::json!({
+            "number": 5,
+            "title": "Fix typo",
+            "body": null,
+            "user": { "login": "contributor" },
+            "state": "open",
+            "labels": [{ "id": 1, "name": "docs" }],
+            "head": { "ref": "typo", "sha": "abc", "repo_id": 2 },
+            "base": { "ref": "main", "sha": "def", "repo_id": 1 },
+            "merge_base": "def",
+            "additions": 3,
+            "deletions": 1,
+            "changed_files": 1,
+        }))
+        .unwrap();
+        let repo = default_repo_name();
+        let pr = gitea_pr_to_pr(&repo, pull);
+        assert!(pr.is_from_fork(&repo));
+        assert_eq!(pr.labels, vec!["docs".to_string()]);
+        assert_eq!(pr.changed_lines, 4);
+        assert!(pr.open);
+        assert!(!pr.draft);
+    }
+
+    #[test]
+    fn statuses() {
+        assert!(matches!(
+            commit_status("warning"),
+            CheckSuiteStatus::Success
+        ));
+        assert!(matches!(commit_status("error"), CheckSuiteStatus::Failure));
+        assert!(matches!(
+            commit_status("pending"),
+            CheckSuiteStatus::Pending
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! Gitea (and Forgejo) backend of the bot. Pull requests of the configured repositories are handled
+//! in the same way as pull requests of GitHub repositories: commands are read from comments, bors
+//! branches are pushed using the API and builds are reported by the commit statuses of external CI
+//! services (e.g. Woodpecker or Gitea Actions).
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Context;
+use secrecy::SecretString;
+
+use crate::bors::event::PullRequestComment;
+use crate::bors::shard::{RepoShard, ShardedState};
+use crate::bors::{BorsState, RepositoryState};
+use crate::config::{RepositoryConfig, CONFIG_FILE_PATH};
+use crate::database::{DbClient, SeaORMClient};
+use crate::gitea::api::{parse_repo_name, repo_path, GiteaApi};
+use crate::gitea::client::{fetch_file_content, GiteaRepositoryClient};
+use crate::gitea::permissions::GiteaPermissionResolver;
+use crate::github::GithubRepoName;
+use crate::notifications::{create_notification_routes, NotificationCredentials};
+
+pub mod api;
+pub mod client;
+mod permissions;
+mod webhook;
+
+pub use webhook::{GiteaForge, GiteaWebhook, EVENT_TYPE_PREFIX};
+
+type GiteaRepositoryState = RepositoryState<GiteaRepositoryClient>;
+
+/// Provides access to the configured Gitea repositories.
+pub struct GiteaState {
+    api: Arc<GiteaApi>,
+    /// Login of the bot user that owns the access token.
+    username: String,
+    /// Full names of the managed repositories (`owner/name`).
+    repository_names: Vec<String>,
+    repositories: HashMap<GithubRepoName, GiteaRepositoryState>,
+    db: SeaORMClient,
+    notification_credentials: NotificationCredentials,
+}
+
+impl GiteaState {
+    /// Loads the given repositories from the Gitea instance at `url`, using the access token of
+    /// the bot user.
+    pub async fn load(
+        url: &str,
+        token: SecretString,
+        repository_names: Vec<String>,
+        db: SeaORMClient,
+        notification_credentials: NotificationCredentials,
+    ) -> anyhow::Result<Self> {
+        #[derive(serde::Deserialize, Debug)]
+        struct UserPayload {
+            login: String,
+        }
+
+        let api = Arc::new(GiteaApi::new(url, token));
+        let user: UserPayload = api
+            .get("/user")
+            .await
+            .context("Could not load the Gitea bot user")?;
+        let repositories = load_repositories(
+            &api,
+            &user.login,
+            &repository_names,
+            &notification_credentials,
+        )
+        .await?;
+        Ok(Self {
+            api,
+            username: user.login,
+            repository_names,
+            repositories,
+            db,
+            notification_credentials,
+        })
+    }
+}
+
+async fn load_repositories(
+    api: &Arc<GiteaApi>,
+    bot_username: &str,
+    repository_names: &[String],
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<HashMap<GithubRepoName, GiteaRepositoryState>> {
+    let mut repositories = HashMap::new();
+    for full_name in repository_names {
+        let repo_state = create_repo_state(api, bot_username, full_name, notification_credentials)
+            .await
+            .with_context(|| format!("Cannot load Gitea repository {full_name}"))?;
+        if let Some(repo_state) = repo_state {
+            tracing::info!("Loaded Gitea repository {}", repo_state.repository);
+            repositories.insert(repo_state.repository.clone(), repo_state);
+        }
+    }
+    Ok(repositories)
+}
+
+async fn create_repo_state(
+    api: &Arc<GiteaApi>,
+    bot_username: &str,
+    full_name: &str,
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<Option<GiteaRepositoryState>> {
+    #[derive(serde::Deserialize, Debug)]
+    struct RepositoryPayload {
+        default_branch: Option<String>,
+    }
+
+    let name = parse_repo_name(full_name)?;
+    let repository: RepositoryPayload = api.get(&repo_path(&name)).await?;
+    let default_branch = repository.default_branch.as_deref().unwrap_or("main");
+
+    let Some(content) = fetch_file_content(api, &name, CONFIG_FILE_PATH, default_branch).await?
+    else {
+        tracing::warn!(
+            "Repository {name} does not contain {CONFIG_FILE_PATH} in its default branch, ignoring it"
+        );
+        return Ok(None);
+    };
+    let config: RepositoryConfig = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(error) => {
+            tracing::warn!("Repository {name} has an invalid configuration, ignoring it: {error}");
+            return Ok(None);
+        }
+    };
+
+    let notifications =
+        create_notification_routes(&name, &config.notifications, notification_credentials);
+    Ok(Some(RepositoryState {
+        repository: name.clone(),
+        client: GiteaRepositoryClient {
+            api: api.clone(),
+            repo_name: name.clone(),
+            bot_username: bot_username.to_string(),
+        },
+        permissions_resolver: Box::new(GiteaPermissionResolver::new(api.clone(), name)),
+        // Contributor agreements are checked using GitHub usernames
+        agreement_checker: None,
+        notifications,
+        config,
+        deferred_updates: Default::default(),
+    }))
+}
+
+impl BorsState<GiteaRepositoryClient> for GiteaState {
+    fn is_comment_internal(&self, comment: &PullRequestComment) -> bool {
+        comment.author.username == self.username
+    }
+
+    fn get_repo_state_mut(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<(&mut GiteaRepositoryState, &mut dyn DbClient)> {
+        self.repositories
+            .get_mut(repo)
+            .map(|repo| (repo, (&mut self.db) as &mut dyn DbClient))
+    }
+
+    fn get_all_repos_mut(&mut self) -> (Vec<&mut GiteaRepositoryState>, &mut dyn DbClient) {
+        (
+            self.repositories.values_mut().collect(),
+            (&mut self.db) as &mut dyn DbClient,
+        )
+    }
+
+    /// Reloads the configuration of the repositories.
+    fn reload_repositories(&mut self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
+        Box::pin(async move {
+            self.repositories = load_repositories(
+                &self.api,
+                &self.username,
+                &self.repository_names,
+                &self.notification_credentials,
+            )
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+impl ShardedState<GiteaRepositoryClient> for GiteaState {
+    fn repository_names(&self) -> Vec<GithubRepoName> {
+        self.repositories.keys().cloned().collect()
+    }
+
+    fn take_repository(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<RepoShard<GiteaRepositoryClient>> {
+        self.repositories
+            .remove(repo)
+            .map(|repo| RepoShard::new(repo, Box::new(self.db.clone())))
+    }
+
+    fn return_repository(&mut self, shard: RepoShard<GiteaRepositoryClient>) {
+        self.repositories
+            .insert(shard.repository().clone(), shard.into_repository_state());
+    }
+}
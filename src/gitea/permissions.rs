@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::async_trait;
+use tokio::sync::Mutex;
+
+use crate::gitea::api::{encode, repo_path, GiteaApi};
+use crate::github::GithubRepoName;
+use crate::permissions::{PermissionResolver, PermissionType, CACHE_DURATION};
+use crate::utils::lru::LruCache;
+
+/// Maximum number of users whose permission is cached in a single repository.
+const MAX_CACHED_USERS: usize = 1000;
+
+/// Resolves permissions from the permission of a user in a Gitea repository. Users with write
+/// access can start try builds and admins (and owners) can approve.
+pub struct GiteaPermissionResolver {
+    api: Arc<GiteaApi>,
+    repo: GithubRepoName,
+    /// Permissions by username, and when they were loaded.
+    permissions: Mutex<LruCache<String, (String, SystemTime)>>,
+}
+
+impl GiteaPermissionResolver {
+    pub fn new(api: Arc<GiteaApi>, repo: GithubRepoName) -> Self {
+        Self {
+            api,
+            repo,
+            permissions: Mutex::new(LruCache::new("gitea_permissions", MAX_CACHED_USERS)),
+        }
+    }
+
+    async fn load_permission(&self, username: &str) -> anyhow::Result<String> {
+        #[derive(serde::Deserialize, Debug)]
+        struct PermissionPayload {
+            permission: String,
+        }
+
+        tracing::info!("Reloading permission of {username} in {}", self.repo);
+        // Users that are not collaborators are reported as missing
+        let permission: Option<PermissionPayload> = self
+            .api
+            .get_optional(&format!(
+                "{}/collaborators/{}/permission",
+                repo_path(&self.repo),
+                encode(username)
+            ))
+            .await?;
+        Ok(permission.map_or_else(|| "none".to_string(), |payload| payload.permission))
+    }
+}
+
+#[async_trait]
+impl PermissionResolver for GiteaPermissionResolver {
+    async fn has_permission(&self, username: &str, permission: PermissionType) -> bool {
+        let username = username.to_lowercase();
+        let mut permissions = self.permissions.lock().await;
+        let cached = permissions.get(&username).and_then(|(value, loaded_at)| {
+            let fresh = loaded_at
+                .elapsed()
+                .map_or(false, |elapsed| elapsed <= CACHE_DURATION);
+            fresh.then(|| value.clone())
+        });
+        let value = match cached {
+            Some(value) => value,
+            None => match self.load_permission(&username).await {
+                Ok(value) => {
+                    permissions.insert(username, (value.clone(), SystemTime::now()));
+                    value
+                }
+                Err(error) => {
+                    tracing::error!("Cannot load permission of {username}: {error:?}");
+                    return false;
+                }
+            },
+        };
+        grants(&value, permission)
+    }
+}
+
+/// Does the permission of a Gitea user (`none`, `read`, `write`, `admin` or `owner`) grant the
+/// given bors permission?
+fn grants(value: &str, permission: PermissionType) -> bool {
+    match permission {
+        PermissionType::Review => matches!(value, "admin" | "owner"),
+        PermissionType::Try => matches!(value, "write" | "admin" | "owner"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gitea::permissions::grants;
+    use crate::permissions::PermissionType;
+
+    #[test]
+    fn permission_levels() {
+        assert!(grants("write", PermissionType::Try));
+        assert!(!grants("write", PermissionType::Review));
+        assert!(grants("owner", PermissionType::Review));
+        assert!(!grants("read", PermissionType::Try));
+    }
+}
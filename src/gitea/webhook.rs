@@ -0,0 +1,414 @@
+//! Parsing of Gitea (and Forgejo) webhooks (comment, pull request, review, commit status and push
+//! events) into [`BorsEvent`]s.
+use std::fmt::Debug;
+
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::{HeaderMap, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use octocrab::models::RunId;
+use sha2::Sha256;
+use url::Url;
+
+use crate::bors::event::{
+    BorsEvent, BranchRewrite, BranchRewritten, CheckSuiteCompleted, ConfigChanged,
+    PullRequestClosed, PullRequestComment, PullRequestPushed, WorkflowStarted,
+};
+use crate::config::CONFIG_FILE_PATH;
+use crate::database::WorkflowType;
+use crate::forge::{extract_webhook, header, Forge};
+use crate::gitea::api::parse_repo_name;
+use crate::github::server::ServerStateRef;
+use crate::github::{CommitSha, GithubUser, PullRequestNumber, WebhookSecret};
+
+/// SHA used by Gitea for the missing side of a created or deleted branch.
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// Prefix of the event types of Gitea deliveries stored in the delivery log, because Gitea uses
+/// the same event types as GitHub.
+pub const EVENT_TYPE_PREFIX: &str = "gitea:";
+
+/// axum extractor for Gitea webhook events.
+#[derive(Debug)]
+pub struct GiteaWebhook(pub BorsEvent);
+
+#[async_trait]
+impl<B> FromRequest<ServerStateRef, B> for GiteaWebhook
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: Request<B>,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        extract_webhook(&GiteaForge, request, state)
+            .await
+            .map(GiteaWebhook)
+    }
+}
+
+/// Webhooks of Gitea repositories, signed with HMAC-SHA256 in the `X-Gitea-Signature` header.
+/// Forgejo sends the same headers for compatibility.
+pub struct GiteaForge;
+
+impl Forge for GiteaForge {
+    fn name(&self) -> &'static str {
+        "gitea"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool {
+        let Some(signature) =
+            header(headers, "x-gitea-signature").and_then(|value| hex::decode(value).ok())
+        else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+            .expect("Cannot create HMAC key");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-gitea-event")
+    }
+
+    fn delivery_id<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-gitea-delivery")
+    }
+
+    fn recorded_event_type(&self, event_type: &str) -> String {
+        format!("{EVENT_TYPE_PREFIX}{event_type}")
+    }
+
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        let event_type = event_type
+            .strip_prefix(EVENT_TYPE_PREFIX)
+            .unwrap_or(event_type);
+        parse_gitea_event(event_type, body)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct RepositoryPayload {
+    full_name: String,
+    html_url: String,
+    default_branch: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UserPayload {
+    login: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct IssuePayload {
+    number: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CommentPayload {
+    body: String,
+    user: UserPayload,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct IssueCommentEvent {
+    action: String,
+    issue: IssuePayload,
+    comment: CommentPayload,
+    repository: RepositoryPayload,
+    #[serde(default)]
+    is_pull: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct HeadPayload {
+    sha: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BasePayload {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PullRequestPayload {
+    #[serde(default)]
+    merged: bool,
+    head: HeadPayload,
+    base: BasePayload,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ReviewPayload {
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PullRequestEvent {
+    action: String,
+    number: u64,
+    pull_request: PullRequestPayload,
+    repository: RepositoryPayload,
+    sender: UserPayload,
+    review: Option<ReviewPayload>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct StatusBranch {
+    name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct StatusEvent {
+    id: u64,
+    sha: String,
+    state: String,
+    context: String,
+    target_url: Option<String>,
+    /// Branches that contain the commit.
+    #[serde(default)]
+    branches: Vec<StatusBranch>,
+    repository: RepositoryPayload,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PushCommit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    before: String,
+    after: String,
+    repository: RepositoryPayload,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+}
+
+/// Parses the payload of a webhook with the given type (the `X-Gitea-Event` header).
+/// Returns `None` if the webhook is not interesting for the bot.
+pub fn parse_gitea_event(event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+    match event_type {
+        "issue_comment" => {
+            let payload: IssueCommentEvent = serde_json::from_slice(body)?;
+            if payload.action != "created" || !payload.is_pull {
+                return Ok(None);
+            }
+            Ok(Some(BorsEvent::Comment(PullRequestComment {
+                repository: parse_repo_name(&payload.repository.full_name)?,
+                author: parse_user(&payload.repository, payload.comment.user)?,
+                pr_number: PullRequestNumber(payload.issue.number),
+                text: payload.comment.body,
+            })))
+        }
+        "pull_request" => {
+            let payload: PullRequestEvent = serde_json::from_slice(body)?;
+            let repository = parse_repo_name(&payload.repository.full_name)?;
+            let pr_number = PullRequestNumber(payload.number);
+            let result = match payload.action.as_str() {
+                "opened" | "reopened" | "synchronized" => {
+                    Some(BorsEvent::PullRequestPushed(PullRequestPushed {
+                        repository,
+                        pr_number,
+                        head_sha: CommitSha(payload.pull_request.head.sha),
+                    }))
+                }
+                "closed" => Some(BorsEvent::PullRequestClosed(PullRequestClosed {
+                    repository,
+                    pr_number,
+                    merged: payload.pull_request.merged,
+                    base_branch: payload.pull_request.base.name,
+                })),
+                _ => None,
+            };
+            Ok(result)
+        }
+        "pull_request_approved" | "pull_request_review_approved" => {
+            let payload: PullRequestEvent = serde_json::from_slice(body)?;
+            Ok(Some(BorsEvent::ReviewApproved(PullRequestComment {
+                repository: parse_repo_name(&payload.repository.full_name)?,
+                author: parse_user(&payload.repository, payload.sender)?,
+                pr_number: PullRequestNumber(payload.number),
+                text: payload
+                    .review
+                    .map(|review| review.content)
+                    .unwrap_or_default(),
+            })))
+        }
+        "status" => {
+            let payload: StatusEvent = serde_json::from_slice(body)?;
+            let repository = parse_repo_name(&payload.repository.full_name)?;
+            // Builds run on bors branches, which contain only the tested commit
+            let Some(branch) = payload.branches.into_iter().next() else {
+                tracing::debug!("Ignoring status of commit {} without branches", payload.sha);
+                return Ok(None);
+            };
+            let result = match payload.state.as_str() {
+                "pending" => Some(BorsEvent::WorkflowStarted(WorkflowStarted {
+                    repository,
+                    name: payload.context,
+                    branch: branch.name,
+                    commit_sha: CommitSha(payload.sha),
+                    run_id: RunId(payload.id),
+                    workflow_type: WorkflowType::External,
+                    url: payload.target_url.unwrap_or_default(),
+                })),
+                // The result of the build is decided from all statuses of the commit
+                _ => Some(BorsEvent::CheckSuiteCompleted(CheckSuiteCompleted {
+                    repository,
+                    branch: branch.name,
+                    commit_sha: CommitSha(payload.sha),
+                })),
+            };
+            Ok(result)
+        }
+        "push" => {
+            let payload: PushEvent = serde_json::from_slice(body)?;
+            let Some(branch) = payload.git_ref.strip_prefix("refs/heads/") else {
+                return Ok(None);
+            };
+            let repository = parse_repo_name(&payload.repository.full_name)?;
+            let default_branch = payload.repository.default_branch.as_deref() == Some(branch);
+            // Gitea does not tell whether a push was forced, so only deletions are reported
+            if payload.after == NULL_SHA {
+                return Ok(Some(BorsEvent::BranchRewritten(BranchRewritten {
+                    repository,
+                    branch: branch.to_string(),
+                    default_branch,
+                    rewrite: BranchRewrite::Deleted {
+                        before: CommitSha(payload.before),
+                    },
+                })));
+            }
+            let config_changed = payload.commits.iter().any(|commit| {
+                commit
+                    .added
+                    .iter()
+                    .chain(&commit.modified)
+                    .chain(&commit.removed)
+                    .any(|path| path == CONFIG_FILE_PATH)
+            });
+            if default_branch && config_changed {
+                return Ok(Some(BorsEvent::ConfigChanged(ConfigChanged {
+                    repository,
+                    commit_sha: CommitSha(payload.after),
+                })));
+            }
+            Ok(None)
+        }
+        _ => {
+            tracing::debug!("Ignoring unknown event type {event_type:?}");
+            Ok(None)
+        }
+    }
+}
+
+/// The profile URL of users is derived from the URL of the repository, because older versions of
+/// Gitea do not send it.
+fn parse_user(repository: &RepositoryPayload, user: UserPayload) -> anyhow::Result<GithubUser> {
+    let mut html_url = Url::parse(&repository.html_url)?;
+    html_url.set_path(&user.login);
+    Ok(GithubUser {
+        username: user.login,
+        html_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use crate::bors::event::BorsEvent;
+    use crate::database::WorkflowType;
+    use crate::forge::Forge;
+    use crate::gitea::webhook::{parse_gitea_event, GiteaForge};
+    use crate::github::WebhookSecret;
+    use crate::tests::io::load_test_file;
+
+    fn parse(file: &str, event: &str) -> Option<BorsEvent> {
+        parse_gitea_event(event, load_test_file(file).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn verify_signature() {
+        let secret = WebhookSecret::new("ABCDEF".to_string());
+        let body = br#"{"action": "created"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"ABCDEF").unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        assert!(!GiteaForge.verify_webhook(&headers, body, &secret));
+        headers.insert("x-gitea-signature", signature.parse().unwrap());
+        assert!(GiteaForge.verify_webhook(&headers, body, &secret));
+        assert!(!GiteaForge.verify_webhook(&headers, b"{}", &secret));
+    }
+
+    #[test]
+    fn recorded_event_types() {
+        let event_type = GiteaForge.recorded_event_type("issue_comment");
+        assert_eq!(event_type, "gitea:issue_comment");
+        let body = load_test_file("gitea/issue-comment.json");
+        assert!(matches!(
+            GiteaForge.parse_webhook(&event_type, body.as_bytes()),
+            Ok(Some(BorsEvent::Comment(_)))
+        ));
+    }
+
+    #[test]
+    fn pull_request_comment() {
+        let Some(BorsEvent::Comment(comment)) = parse("gitea/issue-comment.json", "issue_comment")
+        else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.repository.path(), "owner/repo");
+        assert_eq!(comment.author.username, "reviewer");
+        assert_eq!(
+            comment.author.html_url.as_str(),
+            "https://gitea.example.com/reviewer"
+        );
+        assert_eq!(comment.pr_number.0, 3);
+        assert_eq!(comment.text, "@bors try");
+    }
+
+    #[test]
+    fn review_approved() {
+        let Some(BorsEvent::ReviewApproved(review)) =
+            parse("gitea/pull-request-approved.json", "pull_request_approved")
+        else {
+            panic!("Expected an approval");
+        };
+        assert_eq!(review.author.username, "reviewer");
+        assert_eq!(review.pr_number.0, 3);
+        assert_eq!(review.text, "Looks good");
+    }
+
+    #[test]
+    fn commit_statuses() {
+        let Some(BorsEvent::WorkflowStarted(workflow)) =
+            parse("gitea/status-pending.json", "status")
+        else {
+            panic!("Expected a started workflow");
+        };
+        assert_eq!(workflow.branch, "automation/bors/try");
+        assert_eq!(workflow.name, "ci/woodpecker/push/test");
+        assert_eq!(workflow.workflow_type, WorkflowType::External);
+    }
+}
@@ -0,0 +1,123 @@
+//! Conditional requests for frequently polled GitHub resources.
+//!
+//! Responses with `304 Not Modified` do not count against the rate limit of an installation.
+//! The [`ResponseCache`] remembers the `ETag` and the body of successful responses, sends the
+//! `ETag` in the `If-None-Match` header of the next request of the same URL and reuses the cached
+//! body when GitHub replies that the resource has not changed. The least recently used responses
+//! (e.g. of PRs that have been merged long ago) are evicted once the cache is full.
+use std::sync::Mutex;
+
+use anyhow::Context;
+use octocrab::Octocrab;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Method, StatusCode, Url};
+
+use crate::http;
+use crate::utils::lru::LruCache;
+
+/// Maximum number of cached responses of a single installation.
+const MAX_ENTRIES: usize = 1000;
+
+struct CachedResponse {
+    etag: String,
+    body: String,
+}
+
+/// Caches responses of GET requests of a single app installation (PRs, check suites, ...) by their
+/// URL.
+pub struct ResponseCache {
+    responses: Mutex<LruCache<String, CachedResponse>>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new(MAX_ENTRIES)
+    }
+}
+
+impl ResponseCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            responses: Mutex::new(LruCache::new("github_responses", max_entries)),
+        }
+    }
+
+    /// Sends a GET request to the given URL, conditional on the `ETag` of the cached response.
+    /// Returns the status and the body of the response, which is the cached body if the resource
+    /// has not been modified.
+    pub async fn get(&self, client: &Octocrab, url: Url) -> anyhow::Result<(StatusCode, String)> {
+        let etag = self.etag(url.as_str());
+        let mut request = client.request_builder(url.clone(), Method::GET);
+        if let Some(etag) = &etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let _permit = http::acquire_host(&url).await;
+        let mut response = client
+            .execute(request)
+            .await
+            .with_context(|| format!("Cannot send request to {url}"))?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(body) = self.body(url.as_str()) {
+                tracing::debug!("Reusing cached response of {url}");
+                return Ok((StatusCode::OK, body));
+            }
+            // The response has been evicted in the meantime
+            response = client
+                .execute(client.request_builder(url.clone(), Method::GET))
+                .await
+                .with_context(|| format!("Cannot send request to {url}"))?;
+        }
+
+        let status = response.status();
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string());
+        let body = response.text().await?;
+        if let (StatusCode::OK, Some(etag)) = (status, etag) {
+            self.store(url.as_str(), etag, body.clone());
+        }
+        Ok((status, body))
+    }
+
+    fn etag(&self, url: &str) -> Option<String> {
+        let mut responses = self.responses.lock().unwrap();
+        responses.get(url).map(|response| response.etag.clone())
+    }
+
+    fn body(&self, url: &str) -> Option<String> {
+        let mut responses = self.responses.lock().unwrap();
+        responses.get(url).map(|response| response.body.clone())
+    }
+
+    fn store(&self, url: &str, etag: String, body: String) {
+        let mut responses = self.responses.lock().unwrap();
+        responses.insert(url.to_string(), CachedResponse { etag, body });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::api::cache::ResponseCache;
+
+    #[test]
+    fn store_responses() {
+        let cache = ResponseCache::new(2);
+        cache.store("/a", "\"1\"".to_string(), "a".to_string());
+        cache.store("/b", "\"2\"".to_string(), "b".to_string());
+        assert_eq!(cache.etag("/a").as_deref(), Some("\"1\""));
+        assert_eq!(cache.body("/b").as_deref(), Some("b"));
+
+        // Replacing a response does not evict anything
+        cache.store("/a", "\"3\"".to_string(), "a2".to_string());
+        assert_eq!(cache.etag("/a").as_deref(), Some("\"3\""));
+        assert_eq!(cache.body("/b").as_deref(), Some("b"));
+
+        // The least recently used response is evicted
+        cache.store("/c", "\"4\"".to_string(), "c".to_string());
+        assert_eq!(cache.etag("/b"), None);
+        assert_eq!(cache.body("/a").as_deref(), Some("a2"));
+        assert_eq!(cache.body("/c").as_deref(), Some("c"));
+    }
+}
@@ -1,12 +1,35 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Context;
 use axum::async_trait;
+use base64::Engine;
+use chrono::{DateTime, Utc};
 use octocrab::models::{Repository, RunId};
 use octocrab::{Error, Octocrab};
 use tracing::log;
 
-use crate::bors::{CheckSuite, CheckSuiteStatus, RepositoryClient};
-use crate::github::api::operations::{merge_branches, set_branch_to_commit, MergeError};
-use crate::github::{Branch, CommitSha, GithubRepoName, PullRequest, PullRequestNumber};
+use crate::bors::{
+    BranchProtection, CheckSuite, CheckSuiteStatus, DispatchedWorkflow, JobLog, RepositoryClient,
+    WorkflowLink, WorkflowRunDetails,
+};
+use crate::github::api::cache::ResponseCache;
+use crate::github::api::operations::{
+    delete_branch, dequeue_pull_request, dispatch_workflow, edit_comment, enqueue_pull_request,
+    find_branch_protection_rule, merge_branches, minimize_comment, protect_branch_pattern,
+    set_branch_to_commit, BranchUpdateError, MergeError,
+};
+use crate::github::api::rate_limit::MutationPacer;
+use crate::github::{
+    Branch, CheckAnnotation, CommentId, Commit, CommitSha, GithubRepoName, Milestone, PullRequest,
+    PullRequestNumber,
+};
+use crate::health::{heartbeat, Heartbeat};
+use crate::log_excerpt::{log_tail, step_log};
+
+/// Logs of failed jobs are only downloaded up to this size, to bound the memory used for them.
+const MAX_JOB_LOG_SIZE: usize = 16 * 1024 * 1024;
 
 /// Provides access to a single app installation (repository) using the GitHub API.
 pub struct GithubRepositoryClient {
@@ -17,9 +40,33 @@ pub struct GithubRepositoryClient {
     // we must always have some owner of the repo.
     pub repo_name: GithubRepoName,
     pub repository: Repository,
+    /// Paces mutating calls, it is shared by all repositories of the same installation.
+    pub mutation_pacer: Arc<MutationPacer>,
+    /// Caches frequently polled resources, it is shared by all repositories of the same
+    /// installation.
+    pub response_cache: Arc<ResponseCache>,
+    /// Slug of the GitHub App of the bot.
+    pub app_slug: Option<String>,
 }
 
 impl GithubRepositoryClient {
+    /// Creates a client of a single repository that sends mutating calls without any delay, which
+    /// is only useful against a mock of the GitHub API.
+    pub fn without_pacing(
+        client: Octocrab,
+        repo_name: GithubRepoName,
+        repository: Repository,
+    ) -> Self {
+        Self {
+            client,
+            repo_name,
+            repository,
+            mutation_pacer: Arc::new(MutationPacer::new(Duration::ZERO, Duration::ZERO)),
+            response_cache: Arc::new(ResponseCache::default()),
+            app_slug: None,
+        }
+    }
+
     pub fn client(&self) -> &Octocrab {
         &self.client
     }
@@ -33,64 +80,402 @@ impl GithubRepositoryClient {
     }
 }
 
+// Each API call has its own span, nested in the span of the event (and build) that has made it.
 #[async_trait]
 impl RepositoryClient for GithubRepositoryClient {
     fn repository(&self) -> &GithubRepoName {
         self.name()
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_pull_request(&mut self, pr: PullRequestNumber) -> anyhow::Result<PullRequest> {
-        let pr = self
-            .client
-            .pulls(self.repository().owner(), self.repository().name())
-            .get(pr.0)
-            .await
-            .map_err(|error| {
-                anyhow::anyhow!("Could not get PR {}/{}: {error:?}", self.repository(), pr.0)
+        heartbeat(Heartbeat::GithubApiCall);
+        let (status, body) = self
+            .response_cache
+            .get(
+                &self.client,
+                self.client.base_url.join(&format!(
+                    "/repos/{}/{}/pulls/{}",
+                    self.repo_name.owner(),
+                    self.repo_name.name(),
+                    pr.0
+                ))?,
+            )
+            .await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "Could not get PR {}/{} ({status}): {body}",
+                self.repository(),
+                pr.0
+            ));
+        }
+        let pr: octocrab::models::pulls::PullRequest =
+            serde_json::from_str(&body).map_err(|error| {
+                anyhow::anyhow!("Cannot parse PR {}/{}: {error:?}", self.repository(), pr.0)
             })?;
         Ok(github_pr_to_pr(pr))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_files(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<String>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct FilePayload {
+            filename: String,
+        }
+
+        // GitHub returns at most 3000 files of a PR
+        let mut files = vec![];
+        for page in 1..=30 {
+            let payload: Vec<FilePayload> = self
+                .client
+                .get(
+                    format!(
+                        "/repos/{}/{}/pulls/{pr}/files?per_page=100&page={page}",
+                        self.repo_name.owner(),
+                        self.repo_name.name()
+                    ),
+                    None::<&()>,
+                )
+                .await
+                .with_context(|| format!("Cannot load files of PR {pr}"))?;
+            let last_page = payload.len() < 100;
+            files.extend(payload.into_iter().map(|file| file.filename));
+            if last_page {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_commits(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<Commit>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct AuthorPayload {
+            name: String,
+            email: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct VerificationPayload {
+            verified: bool,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitDetailsPayload {
+            message: String,
+            author: AuthorPayload,
+            verification: Option<VerificationPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            sha: String,
+            commit: CommitDetailsPayload,
+        }
+
+        // GitHub returns at most 250 commits of a PR
+        let mut commits = vec![];
+        for page in 1..=3 {
+            let payload: Vec<CommitPayload> = self
+                .client
+                .get(
+                    format!(
+                        "/repos/{}/{}/pulls/{pr}/commits?per_page=100&page={page}",
+                        self.repo_name.owner(),
+                        self.repo_name.name()
+                    ),
+                    None::<&()>,
+                )
+                .await
+                .with_context(|| format!("Cannot load commits of PR {pr}"))?;
+            let last_page = payload.len() < 100;
+            commits.extend(payload.into_iter().map(|commit| {
+                Commit {
+                    sha: commit.sha.into(),
+                    message: commit.commit.message,
+                    author_name: commit.commit.author.name,
+                    author_email: commit.commit.author.email,
+                    verified: commit
+                        .commit
+                        .verification
+                        .map(|verification| verification.verified)
+                        .unwrap_or(false),
+                }
+            }));
+            if last_page {
+                break;
+            }
+        }
+        Ok(commits)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn is_commit_verified(&mut self, sha: &CommitSha) -> anyhow::Result<bool> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct VerificationPayload {
+            verified: bool,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitDetailsPayload {
+            verification: Option<VerificationPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            commit: CommitDetailsPayload,
+        }
+
+        let payload: CommitPayload = self
+            .client
+            .get(
+                format!(
+                    "/repos/{}/{}/commits/{sha}",
+                    self.repo_name.owner(),
+                    self.repo_name.name()
+                ),
+                None::<&()>,
+            )
+            .await
+            .with_context(|| format!("Cannot load commit {sha}"))?;
+        Ok(payload
+            .commit
+            .verification
+            .map_or(false, |verification| verification.verified))
+    }
+
     /// The comment will be posted as the Github App user of the bot.
-    async fn post_comment(&mut self, pr: PullRequestNumber, text: &str) -> anyhow::Result<()> {
-        self.client
-            .issues(&self.name().owner, &self.name().name)
-            .create_comment(pr.0, text)
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn post_comment(
+        &mut self,
+        pr: PullRequestNumber,
+        text: &str,
+    ) -> anyhow::Result<CommentId> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        let comment = self
+            .mutation_pacer
+            .run(move || async move {
+                client
+                    .issues(repo.owner(), repo.name())
+                    .create_comment(pr.0, text)
+                    .await
+            })
             .await
             .with_context(|| format!("Cannot post comment to {}", self.format_pr(pr)))?;
+        Ok(CommentId(comment.node_id))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn post_commit_comment(&mut self, sha: &CommitSha, text: &str) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        self.mutation_pacer
+            .run(move || async move {
+                client
+                    .post(
+                        format!(
+                            "/repos/{}/{}/commits/{sha}/comments",
+                            repo.owner(),
+                            repo.name()
+                        ),
+                        Some(&serde_json::json!({ "body": text })),
+                    )
+                    .await
+                    .map(|_: serde_json::Value| ())
+            })
+            .await
+            .with_context(|| format!("Cannot post comment to commit {sha}"))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn create_check_run(
+        &mut self,
+        sha: &CommitSha,
+        name: &str,
+        success: bool,
+        summary: &str,
+        annotations: &[CheckAnnotation],
+    ) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        let annotations: Vec<serde_json::Value> = annotations
+            .iter()
+            .map(|annotation| {
+                serde_json::json!({
+                    "path": annotation.path,
+                    "start_line": annotation.line,
+                    "end_line": annotation.line,
+                    "annotation_level": "warning",
+                    "message": annotation.message,
+                })
+            })
+            .collect();
+        let annotations = &annotations;
+        self.mutation_pacer
+            .run(move || async move {
+                client
+                    .post(
+                        format!("/repos/{}/{}/check-runs", repo.owner(), repo.name()),
+                        Some(&serde_json::json!({
+                            "name": name,
+                            "head_sha": sha.0,
+                            "status": "completed",
+                            "conclusion": if success { "success" } else { "failure" },
+                            "output": {
+                                "title": name,
+                                "summary": summary,
+                                "annotations": annotations,
+                            }
+                        })),
+                    )
+                    .await
+                    .map(|_: serde_json::Value| ())
+            })
+            .await
+            .with_context(|| format!("Cannot create check run {name} for commit {sha}"))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_file_content(
+        &mut self,
+        sha: &CommitSha,
+        path: &str,
+    ) -> anyhow::Result<Option<String>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        fetch_file_content(&self.client, &self.repo_name, path, &sha.0).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn edit_comment(&mut self, comment: &CommentId, text: &str) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || edit_comment(repo, comment, text))
+            .await
+            .with_context(|| format!("Cannot edit comment {comment}"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn minimize_comment(&mut self, comment: &CommentId) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || minimize_comment(repo, comment))
+            .await
+            .with_context(|| format!("Cannot minimize comment {comment}"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_branch_to_sha(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || set_branch_to_commit(repo, branch.to_string(), sha))
+            .await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn delete_branch(&mut self, branch: &str) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || delete_branch(repo, branch))
+            .await
+            .with_context(|| format!("Cannot delete branch {branch}"))?;
         Ok(())
     }
 
-    async fn set_branch_to_sha(&mut self, branch: &str, sha: &CommitSha) -> anyhow::Result<()> {
-        Ok(set_branch_to_commit(self, branch.to_string(), sha).await?)
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_branch_sha(&mut self, branch: &str) -> anyhow::Result<Option<CommitSha>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize)]
+        struct RefObject {
+            sha: String,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct RefPayload {
+            object: RefObject,
+        }
+
+        let response = self
+            .client
+            ._get(
+                self.client.base_url.join(&format!(
+                    "/repos/{}/{}/git/ref/heads/{branch}",
+                    self.repo_name.owner(),
+                    self.repo_name.name(),
+                ))?,
+                None::<&()>,
+            )
+            .await
+            .with_context(|| format!("Cannot load branch {branch}"))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let text = response.text().await?;
+        let payload: RefPayload = serde_json::from_str(&text)
+            .map_err(|error| anyhow::anyhow!("Cannot parse branch {branch}: {error:?}"))?;
+        Ok(Some(CommitSha(payload.object.sha)))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn merge_branches(
         &mut self,
         base: &str,
         head: &CommitSha,
         commit_message: &str,
     ) -> Result<CommitSha, MergeError> {
-        Ok(merge_branches(self, base, head, commit_message).await?)
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || merge_branches(repo, base, head, commit_message))
+            .await
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_check_suites_for_commit(
         &mut self,
         branch: &str,
         sha: &CommitSha,
     ) -> anyhow::Result<Vec<CheckSuite>> {
-        let response = self
-            .client
-            ._get(
+        heartbeat(Heartbeat::GithubApiCall);
+        let (status, text) = self
+            .response_cache
+            .get(
+                &self.client,
                 self.client.base_url.join(&format!(
                     "/repos/{}/{}/commits/{}/check-suites",
                     self.repo_name.owner(),
                     self.repo_name.name(),
                     sha.0
                 ))?,
-                None::<&()>,
             )
             .await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "Cannot load check suites of {sha} ({status}): {text}"
+            ));
+        }
 
         #[derive(serde::Deserialize, Debug)]
         struct CheckSuitePayload<'a> {
@@ -104,8 +489,6 @@ impl RepositoryClient for GithubRepositoryClient {
             check_suites: Vec<CheckSuitePayload<'a>>,
         }
 
-        // `response.json()` is not used because of the 'a lifetime
-        let text = response.text().await?;
         let response: CheckSuiteResponse = serde_json::from_str(&text)?;
         let suites = response
             .check_suites
@@ -135,25 +518,248 @@ impl RepositoryClient for GithubRepositoryClient {
         Ok(suites)
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_workflow_run_details(
+        &mut self,
+        run_id: RunId,
+    ) -> anyhow::Result<WorkflowRunDetails> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct JobPayload {
+            name: String,
+            html_url: String,
+            conclusion: Option<String>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct JobsResponse {
+            jobs: Vec<JobPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct ArtifactPayload {
+            id: u64,
+            name: String,
+            expired: bool,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct ArtifactsResponse {
+            artifacts: Vec<ArtifactPayload>,
+        }
+
+        let run_url = format!(
+            "/repos/{}/{}/actions/runs/{run_id}",
+            self.repo_name.owner(),
+            self.repo_name.name()
+        );
+        let jobs: JobsResponse = self
+            .client
+            .get(format!("{run_url}/jobs?per_page=100"), None::<&()>)
+            .await
+            .context("Cannot load workflow jobs")?;
+        let artifacts: ArtifactsResponse = self
+            .client
+            .get(format!("{run_url}/artifacts?per_page=100"), None::<&()>)
+            .await
+            .context("Cannot load workflow artifacts")?;
+
+        // Artifacts do not have a web URL in the API response, so it has to be constructed
+        let html_url = self
+            .repository
+            .html_url
+            .as_ref()
+            .map(|url| url.to_string())
+            .unwrap_or_else(|| format!("https://github.com/{}", self.repo_name));
+        let html_url = html_url.trim_end_matches('/');
+
+        Ok(WorkflowRunDetails {
+            failed_jobs: jobs
+                .jobs
+                .into_iter()
+                .filter(|job| matches!(job.conclusion.as_deref(), Some("failure" | "timed_out")))
+                .map(|job| WorkflowLink {
+                    name: job.name,
+                    url: job.html_url,
+                })
+                .collect(),
+            artifacts: artifacts
+                .artifacts
+                .into_iter()
+                .filter(|artifact| !artifact.expired)
+                .map(|artifact| WorkflowLink {
+                    name: artifact.name,
+                    url: format!("{html_url}/actions/runs/{run_id}/artifacts/{}", artifact.id),
+                })
+                .collect(),
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_failed_job_logs(
+        &mut self,
+        run_id: RunId,
+        max_size: usize,
+    ) -> anyhow::Result<Vec<JobLog>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct StepPayload {
+            name: String,
+            conclusion: Option<String>,
+            started_at: Option<String>,
+            completed_at: Option<String>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct JobPayload {
+            id: u64,
+            name: String,
+            conclusion: Option<String>,
+            #[serde(default)]
+            steps: Vec<StepPayload>,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct JobsResponse {
+            jobs: Vec<JobPayload>,
+        }
+
+        let jobs: JobsResponse = self
+            .client
+            .get(
+                format!(
+                    "/repos/{}/{}/actions/runs/{run_id}/jobs?per_page=100",
+                    self.repo_name.owner(),
+                    self.repo_name.name()
+                ),
+                None::<&()>,
+            )
+            .await
+            .context("Cannot load workflow jobs")?;
+
+        let mut logs = vec![];
+        for job in jobs
+            .jobs
+            .into_iter()
+            .filter(|job| matches!(job.conclusion.as_deref(), Some("failure" | "timed_out")))
+        {
+            // GitHub responds with a redirect to the plain text log
+            let mut response = self
+                .client
+                ._get(
+                    self.client.base_url.join(&format!(
+                        "/repos/{}/{}/actions/jobs/{}/logs",
+                        self.repo_name.owner(),
+                        self.repo_name.name(),
+                        job.id
+                    ))?,
+                    None::<&()>,
+                )
+                .await
+                .with_context(|| format!("Cannot load log of job {}", job.name))?;
+            if !response.status().is_success() {
+                tracing::warn!("Cannot load log of job {}: {}", job.name, response.status());
+                continue;
+            }
+            let mut log = vec![];
+            while let Some(chunk) = response.chunk().await? {
+                if log.len() + chunk.len() > MAX_JOB_LOG_SIZE {
+                    tracing::warn!(
+                        "Log of job {} is larger than {MAX_JOB_LOG_SIZE} bytes, it is truncated",
+                        job.name
+                    );
+                    break;
+                }
+                log.extend_from_slice(&chunk);
+            }
+            let log = String::from_utf8_lossy(&log);
+
+            // Each failed step gets its own excerpt. If the failed steps cannot be found in the
+            // log (e.g. the job has timed out), the end of the log of the whole job is used.
+            let step_logs = job
+                .steps
+                .iter()
+                .filter(|step| step.conclusion.as_deref() == Some("failure"))
+                .filter_map(|step| {
+                    let time = |time: &Option<String>| {
+                        DateTime::parse_from_rfc3339(time.as_deref()?)
+                            .ok()
+                            .map(|time| time.with_timezone(&Utc))
+                    };
+                    let step_log =
+                        step_log(&log, time(&step.started_at)?, time(&step.completed_at)?);
+                    (!step_log.is_empty()).then(|| JobLog {
+                        job_name: format!("{} / {}", job.name, step.name),
+                        excerpt: log_tail(&step_log, max_size).to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            if step_logs.is_empty() {
+                logs.push(JobLog {
+                    job_name: job.name,
+                    excerpt: log_tail(&log, max_size).to_string(),
+                });
+            } else {
+                logs.extend(step_logs);
+            }
+        }
+        Ok(logs)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn cancel_workflows(&mut self, run_ids: Vec<RunId>) -> anyhow::Result<()> {
-        let actions = self.client.actions();
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        let run_ids = &run_ids;
 
-        // Cancel all workflows in parallel
-        futures::future::join_all(run_ids.into_iter().map(|run_id| {
-            actions.cancel_workflow_run(self.repo_name.owner(), self.repo_name.name(), run_id)
-        }))
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()?;
+        self.mutation_pacer
+            .run(move || async move {
+                let actions = client.actions();
+
+                // Cancel all workflows in parallel
+                futures::future::join_all(
+                    run_ids.iter().map(|run_id| {
+                        actions.cancel_workflow_run(repo.owner(), repo.name(), *run_id)
+                    }),
+                )
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+            })
+            .await?;
 
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn dispatch_workflow(
+        &mut self,
+        workflow: &str,
+        git_ref: &str,
+        inputs: &HashMap<String, String>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || dispatch_workflow(repo, workflow, git_ref, inputs))
+            .await
+            .with_context(|| format!("Cannot dispatch workflow {workflow}"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn add_labels(&mut self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()> {
-        let client = self.client.issues(self.name().owner(), self.name().name());
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
         if !labels.is_empty() {
-            client
-                .add_labels(pr.0, labels)
+            self.mutation_pacer
+                .run(move || async move {
+                    client
+                        .issues(repo.owner(), repo.name())
+                        .add_labels(pr.0, labels)
+                        .await
+                })
                 .await
                 .context("Cannot add label(s) to PR")?;
         }
@@ -161,39 +767,310 @@ impl RepositoryClient for GithubRepositoryClient {
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn remove_labels(
         &mut self,
         pr: PullRequestNumber,
         labels: &[String],
     ) -> anyhow::Result<()> {
-        let client = self.client.issues(self.name().owner(), self.name().name());
-        // The GitHub API only allows removing labels one by one, so we remove all of them in
-        // parallel to speed it up a little.
-        let labels_to_remove_futures = labels.iter().map(|label| client.remove_label(pr.0, label));
-        futures::future::join_all(labels_to_remove_futures)
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        self.mutation_pacer
+            .run(move || async move {
+                let client = client.issues(repo.owner(), repo.name());
+                // The GitHub API only allows removing labels one by one, so we remove all of them
+                // in parallel to speed it up a little.
+                let labels_to_remove_futures =
+                    labels.iter().map(|label| client.remove_label(pr.0, label));
+                futures::future::join_all(labels_to_remove_futures)
+                    .await
+                    .into_iter()
+                    .filter(|result| match result {
+                        Ok(_) => false,
+                        Err(error) => match error {
+                            // This error is returned if we try to remove a label that does not
+                            // exist on the issue. This should be a no-op, rather than an error,
+                            // therefore we swallow this error.
+                            Error::GitHub { source, .. }
+                                if source.message.contains("Label does not exist") =>
+                            {
+                                log::trace!(
+                                    "Trying to remove label which does not exist on PR {pr}"
+                                );
+                                false
+                            }
+                            _ => true,
+                        },
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
             .await
+            .context("Cannot remove label(s) from PR")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_open_milestones(&mut self) -> anyhow::Result<Vec<Milestone>> {
+        heartbeat(Heartbeat::GithubApiCall);
+        #[derive(serde::Deserialize, Debug)]
+        struct MilestonePayload {
+            number: u64,
+            title: String,
+            due_on: Option<DateTime<Utc>>,
+        }
+
+        let milestones: Vec<MilestonePayload> = self
+            .client
+            .get(
+                format!(
+                    "/repos/{}/{}/milestones?state=open&per_page=100",
+                    self.repo_name.owner(),
+                    self.repo_name.name()
+                ),
+                None::<&()>,
+            )
+            .await
+            .context("Cannot load milestones")?;
+        Ok(milestones
             .into_iter()
-            .filter(|result| match result {
-                Ok(_) => false,
-                Err(error) => match error {
-                    // This error is returned if we try to remove a label that does not exist on the issue.
-                    // This should be a no-op, rather than an error, therefore we swallow this error.
-                    Error::GitHub { source, .. }
-                        if source.message.contains("Label does not exist") =>
-                    {
-                        log::trace!("Trying to remove label which does not exist on PR {pr}");
-                        false
-                    }
-                    _ => true,
-                },
+            .map(|milestone| Milestone {
+                number: milestone.number,
+                title: milestone.title,
+                due_on: milestone.due_on,
             })
-            .collect::<Result<Vec<_>, _>>()
-            .context("Cannot remove label(s) from PR")?;
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn add_to_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let node_id = &self.get_pull_request_node_id(pr).await?;
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || enqueue_pull_request(repo, node_id))
+            .await
+            .with_context(|| format!("Cannot add PR {pr} to the merge queue"))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn remove_from_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let node_id = &self.get_pull_request_node_id(pr).await?;
+        let repo = &*self;
+        repo.mutation_pacer
+            .run(move || dequeue_pull_request(repo, node_id))
+            .await
+            .with_context(|| format!("Cannot remove PR {pr} from the merge queue"))
+    }
+
+    /// Pushes to the branch are restricted to the GitHub App of the bot. Patterns are protected
+    /// using a branch protection rule of the GraphQL API, because the REST API can only protect
+    /// existing branches.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn protect_branch(
+        &mut self,
+        branch: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        if branch.contains('*') {
+            return self.protect_branch_pattern(branch, protection).await;
+        }
+        let required_status_checks = if protection.required_checks.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::json!({ "strict": false, "contexts": protection.required_checks })
+        };
+        let restrictions = match self.app_slug {
+            Some(ref slug) => serde_json::json!({ "users": [], "teams": [], "apps": [slug] }),
+            None => {
+                tracing::warn!("Slug of the bot is unknown, pushes to {branch} are not restricted");
+                serde_json::Value::Null
+            }
+        };
+        let body = serde_json::json!({
+            "required_status_checks": required_status_checks,
+            "enforce_admins": false,
+            "required_pull_request_reviews": null,
+            "restrictions": restrictions,
+            "allow_force_pushes": protection.allow_force_pushes,
+            "allow_deletions": protection.allow_deletions,
+        });
 
+        let client = &self.client;
+        let repo = &self.repo_name;
+        let body = &body;
+        self.mutation_pacer
+            .run(move || async move {
+                client
+                    .put::<serde_json::Value, _, _>(
+                        format!(
+                            "/repos/{}/{}/branches/{branch}/protection",
+                            repo.owner(),
+                            repo.name()
+                        ),
+                        Some(body),
+                    )
+                    .await
+            })
+            .await
+            .with_context(|| format!("Cannot protect branch {branch}"))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_milestone(&mut self, pr: PullRequestNumber, milestone: u64) -> anyhow::Result<()> {
+        heartbeat(Heartbeat::GithubApiCall);
+        let client = &self.client;
+        let repo = &self.repo_name;
+        self.mutation_pacer
+            .run(move || async move {
+                client
+                    .patch::<serde_json::Value, _, _>(
+                        format!("/repos/{}/{}/issues/{pr}", repo.owner(), repo.name()),
+                        Some(&serde_json::json!({ "milestone": milestone })),
+                    )
+                    .await
+            })
+            .await
+            .with_context(|| format!("Cannot set milestone of PR {pr}"))?;
         Ok(())
     }
 }
 
+/// Loads the content of the file at `path` in the given git ref (a branch or a commit SHA).
+/// Returns `None` if the file does not exist.
+pub(crate) async fn fetch_file_content(
+    client: &Octocrab,
+    repo: &GithubRepoName,
+    path: &str,
+    git_ref: &str,
+) -> anyhow::Result<Option<String>> {
+    #[derive(serde::Deserialize)]
+    struct ContentPayload {
+        content: Option<String>,
+    }
+
+    let response = client
+        ._get(
+            client.base_url.join(&format!(
+                "/repos/{}/{}/contents/{path}?ref={git_ref}",
+                repo.owner(),
+                repo.name()
+            ))?,
+            None::<&()>,
+        )
+        .await
+        .with_context(|| format!("Cannot fetch {path} from {repo}"))?;
+    // Only a missing file means that there is no content, other errors (e.g. rate limits or
+    // outages) must not be mistaken for it
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!(
+            "Cannot fetch {path} from {repo}: {status}: {text}"
+        ));
+    }
+    let payload: ContentPayload = serde_json::from_str(&text)
+        .map_err(|error| anyhow::anyhow!("Cannot parse {path} of {repo}: {error:?}"))?;
+    let Some(content) = payload.content else {
+        return Ok(None);
+    };
+
+    // The content is split into multiple lines
+    let content: String = content.split_whitespace().collect();
+    let content = base64::engine::general_purpose::STANDARD
+        .decode(content)
+        .with_context(|| format!("Cannot decode {path}"))?;
+    Ok(Some(String::from_utf8(content)?))
+}
+
+impl GithubRepositoryClient {
+    /// Creates or updates the branch protection rule of the given pattern.
+    async fn protect_branch_pattern(
+        &self,
+        pattern: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()> {
+        let Some(ref repository_id) = self.repository.node_id else {
+            return Err(anyhow::anyhow!(
+                "Node ID of repository {} is unknown",
+                self.repo_name
+            ));
+        };
+        let push_actor = match self.app_slug {
+            Some(ref slug) => Some(self.get_app_node_id(slug).await?),
+            None => {
+                tracing::warn!(
+                    "Slug of the bot is unknown, pushes to {pattern} are not restricted"
+                );
+                None
+            }
+        };
+        let rule_id = find_branch_protection_rule(self, pattern)
+            .await
+            .with_context(|| format!("Cannot load protection rules of {}", self.repo_name))?;
+
+        let repo = self;
+        let (rule_id, push_actor) = (rule_id.as_deref(), push_actor.as_deref());
+        self.mutation_pacer
+            .run(move || {
+                protect_branch_pattern(
+                    repo,
+                    repository_id,
+                    rule_id,
+                    pattern,
+                    protection,
+                    push_actor,
+                )
+            })
+            .await
+            .with_context(|| format!("Cannot protect branches {pattern}"))
+    }
+
+    /// Finds the GraphQL node ID of the GitHub App with the given slug.
+    async fn get_app_node_id(&self, slug: &str) -> anyhow::Result<String> {
+        #[derive(serde::Deserialize, Debug)]
+        struct AppPayload {
+            node_id: String,
+        }
+
+        let payload: AppPayload = self
+            .client
+            .get(format!("/apps/{slug}"), None::<&()>)
+            .await
+            .with_context(|| format!("Cannot load node ID of app {slug}"))?;
+        Ok(payload.node_id)
+    }
+
+    /// Finds the GraphQL node ID of the given PR.
+    async fn get_pull_request_node_id(&self, pr: PullRequestNumber) -> anyhow::Result<String> {
+        #[derive(serde::Deserialize, Debug)]
+        struct PullRequestPayload {
+            node_id: String,
+        }
+
+        let payload: PullRequestPayload = self
+            .client
+            .get(
+                format!(
+                    "/repos/{}/{}/pulls/{pr}",
+                    self.repo_name.owner(),
+                    self.repo_name.name()
+                ),
+                None::<&()>,
+            )
+            .await
+            .with_context(|| format!("Cannot load node ID of PR {pr}"))?;
+        Ok(payload.node_id)
+    }
+}
+
 fn github_pr_to_pr(pr: octocrab::models::pulls::PullRequest) -> PullRequest {
     PullRequest {
         number: pr.number.into(),
@@ -208,5 +1085,20 @@ fn github_pr_to_pr(pr: octocrab::models::pulls::PullRequest) -> PullRequest {
         },
         title: pr.title.unwrap_or_default(),
         message: pr.body.unwrap_or_default(),
+        author: pr
+            .user
+            .map(|user| user.login)
+            .unwrap_or_else(|| "<unknown>".to_string()),
+        draft: pr.draft.unwrap_or(false),
+        open: matches!(pr.state, Some(octocrab::models::IssueState::Open)),
+        merged: pr.merged_at.is_some(),
+        labels: pr
+            .labels
+            .unwrap_or_default()
+            .into_iter()
+            .map(|label| label.name)
+            .collect(),
+        changed_lines: pr.additions.unwrap_or_default() + pr.deletions.unwrap_or_default(),
+        changed_files: pr.changed_files.unwrap_or_default(),
     }
 }
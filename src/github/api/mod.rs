@@ -1,24 +1,33 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 
 use anyhow::Context;
-use base64::Engine;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use octocrab::models::{App, AppId, InstallationRepositories, Repository};
 use octocrab::Octocrab;
 use secrecy::{ExposeSecret, SecretVec};
 
-use client::GithubRepositoryClient;
+use client::{fetch_file_content, GithubRepositoryClient};
 
+use crate::agreement::{AgreementChecker, ServiceAgreementChecker};
 use crate::bors::event::PullRequestComment;
+use crate::bors::shard::{RepoShard, ShardedState};
 use crate::bors::{BorsState, RepositoryState};
+use crate::bors_ng::{parse_bors_ng_config, BORS_NG_CONFIG_FILE_PATH};
 use crate::config::{RepositoryConfig, CONFIG_FILE_PATH};
 use crate::database::{DbClient, SeaORMClient};
+use crate::github::api::cache::ResponseCache;
+use crate::github::api::rate_limit::MutationPacer;
 use crate::github::GithubRepoName;
+use crate::notifications::{create_notification_routes, NotificationCredentials};
 use crate::permissions::TeamApiPermissionResolver;
 
+mod cache;
 pub mod client;
 pub(crate) mod operations;
+mod rate_limit;
 
 type GHRepositoryState = RepositoryState<GithubRepositoryClient>;
 
@@ -30,6 +39,7 @@ pub struct GithubAppState {
     client: Octocrab,
     repositories: RepositoryMap,
     db: SeaORMClient,
+    notification_credentials: NotificationCredentials,
 }
 
 impl GithubAppState {
@@ -38,6 +48,7 @@ impl GithubAppState {
         app_id: AppId,
         private_key: SecretVec<u8>,
         db: SeaORMClient,
+        notification_credentials: NotificationCredentials,
     ) -> anyhow::Result<GithubAppState> {
         let key = jsonwebtoken::EncodingKey::from_rsa_pem(private_key.expose_secret().as_ref())
             .context("Could not encode private key")?;
@@ -53,18 +64,29 @@ impl GithubAppState {
             .await
             .context("Could not load Github App")?;
 
-        let repositories = load_repositories(&client).await?;
+        let repositories = load_repositories(&client, &app, &notification_credentials).await?;
         Ok(GithubAppState {
             app,
             client,
             repositories,
             db,
+            notification_credentials,
         })
     }
 }
 
+/// How many installations or repositories are loaded concurrently.
+const LOAD_PARALLELISM: usize = 16;
+
 /// Loads repositories that are connected to the given GitHub App client.
-pub async fn load_repositories(client: &Octocrab) -> anyhow::Result<RepositoryMap> {
+///
+/// Installations and their repositories are loaded concurrently (at most [`LOAD_PARALLELISM`] at
+/// a time), so that the bot starts quickly even if it manages many repositories.
+pub async fn load_repositories(
+    client: &Octocrab,
+    app: &App,
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<RepositoryMap> {
     let installations = client
         .apps()
         .installations()
@@ -72,45 +94,64 @@ pub async fn load_repositories(client: &Octocrab) -> anyhow::Result<RepositoryMa
         .await
         .context("Could not load app installations")?;
 
-    let mut repositories = HashMap::default();
-    for installation in installations {
-        if let Some(ref repositories_url) = installation.repositories_url {
+    let installations: Vec<_> = stream::iter(installations)
+        .map(|installation| async move {
+            let repositories_url = installation.repositories_url?;
             let installation_client = client.installation(installation.id);
-
             match installation_client
-                .get::<InstallationRepositories, _, ()>(repositories_url, None)
+                .get::<InstallationRepositories, _, ()>(&repositories_url, None)
                 .await
             {
-                Ok(repos) => {
-                    for repo in repos.repositories {
-                        let repo_state =
-                            create_repo_state(installation_client.clone(), repo.clone())
-                                .await
-                                .map_err(|error| {
-                                    anyhow::anyhow!(
-                                        "Cannot load repository {:?}: {error:?}",
-                                        repo.full_name
-                                    )
-                                })?;
-                        tracing::info!("Loaded repository {}", repo_state.repository);
-
-                        if let Some(existing) =
-                            repositories.insert(repo_state.repository.clone(), repo_state)
-                        {
-                            return Err(anyhow::anyhow!(
-                                "Repository {} found in multiple installations!",
-                                existing.repository
-                            ));
-                        }
-                    }
-                }
+                Ok(repos) => Some((installation_client, repos.repositories)),
                 Err(error) => {
                     tracing::error!(
                         "Could not load repositories of installation {}: {error}",
                         installation.id
                     );
+                    None
                 }
-            };
+            }
+        })
+        .buffer_unordered(LOAD_PARALLELISM)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut loading = vec![];
+    for (installation_client, repos) in installations.into_iter().flatten() {
+        let mutation_pacer = Arc::new(MutationPacer::default());
+        let response_cache = Arc::new(ResponseCache::default());
+        for repo in repos {
+            let installation_client = installation_client.clone();
+            let mutation_pacer = mutation_pacer.clone();
+            let response_cache = response_cache.clone();
+            loading.push(async move {
+                let full_name = repo.full_name.clone();
+                create_repo_state(
+                    installation_client,
+                    repo,
+                    mutation_pacer,
+                    response_cache,
+                    app.slug.clone(),
+                    notification_credentials,
+                )
+                .await
+                .map_err(|error| anyhow::anyhow!("Cannot load repository {full_name:?}: {error:?}"))
+            });
+        }
+    }
+    let loaded: Vec<Option<GHRepositoryState>> = stream::iter(loading)
+        .buffer_unordered(LOAD_PARALLELISM)
+        .try_collect()
+        .await?;
+
+    let mut repositories = HashMap::default();
+    for repo_state in loaded.into_iter().flatten() {
+        tracing::info!("Loaded repository {}", repo_state.repository);
+        if let Some(existing) = repositories.insert(repo_state.repository.clone(), repo_state) {
+            return Err(anyhow::anyhow!(
+                "Repository {} found in multiple installations!",
+                existing.repository
+            ));
         }
     }
     Ok(repositories)
@@ -119,7 +160,11 @@ pub async fn load_repositories(client: &Octocrab) -> anyhow::Result<RepositoryMa
 async fn create_repo_state(
     repo_client: Octocrab,
     repo: Repository,
-) -> anyhow::Result<GHRepositoryState> {
+    mutation_pacer: Arc<MutationPacer>,
+    response_cache: Arc<ResponseCache>,
+    app_slug: Option<String>,
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<Option<GHRepositoryState>> {
     let Some(owner) = repo.owner.clone() else {
         return Err(anyhow::anyhow!("Repository {} has no owner", repo.name));
     };
@@ -127,12 +172,26 @@ async fn create_repo_state(
     let name = GithubRepoName::new(&owner.login, &repo.name);
     tracing::info!("Found repository {name}");
 
-    let config = match load_repository_config(&repo_client, &name).await {
+    let default_branch = repo.default_branch.as_deref().unwrap_or("master");
+    let config = match load_repository_config(&repo_client, &name, default_branch).await {
         Ok(config) => {
             tracing::info!("Loaded repository config for {name}: {config:#?}");
             config
         }
-        Err(error) => {
+        Err(RepositoryConfigError::Missing) => {
+            tracing::warn!(
+                "Repository {name} does not contain {CONFIG_FILE_PATH} (or {BORS_NG_CONFIG_FILE_PATH}) in its default branch, ignoring it"
+            );
+            return Ok(None);
+        }
+        Err(RepositoryConfigError::Invalid(error)) => {
+            tracing::warn!("Repository {name} has an invalid configuration, ignoring it: {error}");
+            if let Err(error) = report_config_error(&repo_client, &name, &error).await {
+                tracing::error!("Could not report configuration error of {name}: {error:?}");
+            }
+            return Ok(None);
+        }
+        Err(RepositoryConfigError::Other(error)) => {
             return Err(anyhow::anyhow!(
                 "Could not load repository config for {name}: {error:?}"
             ));
@@ -143,51 +202,109 @@ async fn create_repo_state(
         .await
         .map_err(|error| anyhow::anyhow!("Could not load permissions for {name}: {error:?}"))?;
 
+    let agreement_checker = config.agreement.as_ref().map(|agreement| {
+        Box::new(ServiceAgreementChecker::new(agreement)) as Box<dyn AgreementChecker>
+    });
+
+    let notifications =
+        create_notification_routes(&name, &config.notifications, notification_credentials);
+
     let client = GithubRepositoryClient {
         client: repo_client,
         repo_name: name.clone(),
         repository: repo,
+        mutation_pacer,
+        response_cache,
+        app_slug,
     };
 
-    Ok(RepositoryState {
+    Ok(Some(RepositoryState {
         repository: name,
         client,
         config,
         permissions_resolver: Box::new(permissions_resolver),
-    })
+        agreement_checker,
+        notifications,
+        deferred_updates: Default::default(),
+    }))
+}
+
+/// Title of the issue that reports an invalid repository configuration.
+const CONFIG_ERROR_ISSUE_TITLE: &str = "Invalid bors configuration";
+
+#[derive(Debug, thiserror::Error)]
+enum RepositoryConfigError {
+    #[error("{CONFIG_FILE_PATH} was not found")]
+    Missing,
+    #[error("{CONFIG_FILE_PATH} is invalid: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
 }
 
-/// Loads repository configuration from a file located at `[CONFIG_FILE_PATH]` in the main
-/// branch.
+/// Loads repository configuration from a file located at `[CONFIG_FILE_PATH]` in the default
+/// branch of the repository. Repositories without it can still use the `bors.toml` file of
+/// bors-ng.
 async fn load_repository_config(
     gh_client: &Octocrab,
     repo: &GithubRepoName,
-) -> anyhow::Result<RepositoryConfig> {
-    let mut response = gh_client
-        .repos(&repo.owner, &repo.name)
-        .get_content()
-        .path(CONFIG_FILE_PATH)
+    default_branch: &str,
+) -> Result<RepositoryConfig, RepositoryConfigError> {
+    if let Some(content) =
+        fetch_file_content(gh_client, repo, CONFIG_FILE_PATH, default_branch).await?
+    {
+        return parse_repository_config(&content);
+    }
+    let content = fetch_file_content(gh_client, repo, BORS_NG_CONFIG_FILE_PATH, default_branch)
+        .await?
+        .ok_or(RepositoryConfigError::Missing)?;
+    let translated = parse_bors_ng_config(&content).map_err(|error| {
+        RepositoryConfigError::Invalid(format!("{BORS_NG_CONFIG_FILE_PATH}: {error:#}"))
+    })?;
+    for warning in translated.warnings {
+        tracing::warn!("{BORS_NG_CONFIG_FILE_PATH} of {repo}: {warning}");
+    }
+    Ok(translated.config)
+}
+
+fn parse_repository_config(content: &str) -> Result<RepositoryConfig, RepositoryConfigError> {
+    toml::from_str(content).map_err(|error| RepositoryConfigError::Invalid(error.to_string()))
+}
+
+/// Opens an issue that describes why the configuration of the repository could not be loaded,
+/// unless such an issue is already open.
+async fn report_config_error(
+    gh_client: &Octocrab,
+    repo: &GithubRepoName,
+    error: &str,
+) -> anyhow::Result<()> {
+    let issues = gh_client.issues(&repo.owner, &repo.name);
+    let open_issues = issues
+        .list()
+        .state(octocrab::params::State::Open)
+        .per_page(100)
         .send()
-        .await
-        .map_err(|error| {
-            anyhow::anyhow!("Could not fetch {CONFIG_FILE_PATH} from {repo}: {error:?}")
-        })?;
-
-    let engine = base64::engine::general_purpose::STANDARD;
-    response
-        .take_items()
-        .into_iter()
-        .next()
-        .and_then(|content| content.content)
-        .ok_or_else(|| anyhow::anyhow!("Configuration file not found"))
-        .and_then(|content| Ok(engine.decode(content.trim())?))
-        .and_then(|content| Ok(String::from_utf8(content)?))
-        .and_then(|content| {
-            let config: RepositoryConfig = toml::from_str(&content).map_err(|error| {
-                anyhow::anyhow!("Could not deserialize repository config: {error:?}")
-            })?;
-            Ok(config)
-        })
+        .await?;
+    if open_issues
+        .items
+        .iter()
+        .any(|issue| issue.title == CONFIG_ERROR_ISSUE_TITLE)
+    {
+        return Ok(());
+    }
+
+    issues
+        .create(CONFIG_ERROR_ISSUE_TITLE)
+        .body(format!(
+            r#":x: Bors cannot operate on this repository, because its `{CONFIG_FILE_PATH}` file could not be parsed:
+```
+{error}
+```
+Fix the file in the default branch and reinstall the app (or wait until it is reloaded)."#
+        ))
+        .send()
+        .await?;
+    Ok(())
 }
 
 impl BorsState<GithubRepositoryClient> for GithubAppState {
@@ -222,8 +339,40 @@ impl BorsState<GithubRepositoryClient> for GithubAppState {
     /// Re-download information about repositories connected to this GitHub app.
     fn reload_repositories(&mut self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
         Box::pin(async move {
-            self.repositories = load_repositories(&self.client).await?;
+            self.repositories =
+                load_repositories(&self.client, &self.app, &self.notification_credentials).await?;
             Ok(())
         })
     }
 }
+
+impl ShardedState<GithubRepositoryClient> for GithubAppState {
+    fn repository_names(&self) -> Vec<GithubRepoName> {
+        self.repositories.keys().cloned().collect()
+    }
+
+    fn take_repository(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<RepoShard<GithubRepositoryClient>> {
+        self.repositories
+            .remove(repo)
+            .map(|repo| RepoShard::new(repo, Box::new(self.db.clone())))
+    }
+
+    fn return_repository(&mut self, shard: RepoShard<GithubRepositoryClient>) {
+        self.repositories
+            .insert(shard.repository().clone(), shard.into_repository_state());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::api::{parse_repository_config, RepositoryConfigError};
+
+    #[test]
+    fn parse_invalid_repository_config() {
+        let result = parse_repository_config("timeout = \"foo\"");
+        assert!(matches!(result, Err(RepositoryConfigError::Invalid(_))));
+    }
+}
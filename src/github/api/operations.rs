@@ -1,9 +1,15 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use octocrab::models::RunId;
 use octocrab::params::repos::Reference;
 use reqwest::StatusCode;
 use thiserror::Error;
 
+use crate::bors::{BranchProtection, DispatchedWorkflow};
 use crate::github::api::client::GithubRepositoryClient;
-use crate::github::CommitSha;
+use crate::github::api::rate_limit::{response_secondary_rate_limit, SecondaryRateLimit};
+use crate::github::{CommentId, CommitSha};
 
 #[derive(Error, Debug)]
 pub enum MergeError {
@@ -15,6 +21,8 @@ pub enum MergeError {
     AlreadyMerged,
     #[error("Unknown error ({status}): {text}")]
     Unknown { status: StatusCode, text: String },
+    #[error("Secondary rate limit exceeded")]
+    SecondaryRateLimit { retry_after: Option<Duration> },
     #[error("Network error: {0}")]
     NetworkError(#[from] octocrab::Error),
 }
@@ -65,6 +73,7 @@ pub async fn merge_branches(
     match response {
         Ok(response) => {
             let status = response.status();
+            let rate_limit = response_secondary_rate_limit(&response);
             let text = response.text().await.unwrap_or_default();
 
             tracing::trace!(
@@ -72,6 +81,10 @@ pub async fn merge_branches(
                 repo.name(),
             );
 
+            if let Some(retry_after) = rate_limit {
+                return Err(MergeError::SecondaryRateLimit { retry_after });
+            }
+
             match status {
                 StatusCode::CREATED => {
                     let response: MergeResponse =
@@ -112,7 +125,15 @@ pub async fn set_branch_to_commit(
             // Branch does not exist yet, try to create it
             match create_branch(repo, branch_name.clone(), sha).await {
                 Ok(_) => Ok(()),
-                Err(error) => Err(BranchUpdateError::Custom(error)),
+                Err(error) => match error.secondary_rate_limit() {
+                    Some(retry_after) => Err(BranchUpdateError::SecondaryRateLimit { retry_after }),
+                    None if is_permission_error(&error) => {
+                        Err(BranchUpdateError::PermissionDenied(branch_name))
+                    }
+                    None => Err(BranchUpdateError::Custom(format!(
+                        "Cannot create branch: {error}"
+                    ))),
+                },
             }
         }
         Err(error) => Err(error),
@@ -123,12 +144,11 @@ async fn create_branch(
     repo: &GithubRepositoryClient,
     name: String,
     sha: &CommitSha,
-) -> Result<(), String> {
+) -> Result<(), octocrab::Error> {
     repo.client
         .repos(repo.repo_name.owner(), repo.repo_name.name())
         .create_ref(&Reference::Branch(name), sha.as_ref())
-        .await
-        .map_err(|error| format!("Cannot create branch: {error}"))?;
+        .await?;
     Ok(())
 }
 
@@ -138,6 +158,10 @@ pub enum BranchUpdateError {
     BranchNotFound(String),
     #[error("IO error")]
     IOError(#[from] octocrab::Error),
+    #[error("Secondary rate limit exceeded")]
+    SecondaryRateLimit { retry_after: Option<Duration> },
+    #[error("Permission to update branch {0} was denied")]
+    PermissionDenied(String),
     #[error("Unknown error: {0}")]
     Custom(String),
 }
@@ -170,14 +194,341 @@ async fn update_branch(
         .await?;
 
     let status = res.status();
+    let rate_limit = response_secondary_rate_limit(&res);
+    let text = res.text().await.unwrap_or_default();
     tracing::trace!(
         "Updating branch response: status={}, text={:?}",
         status,
-        res.text().await
+        text
     );
 
+    if let Some(retry_after) = rate_limit {
+        return Err(BranchUpdateError::SecondaryRateLimit { retry_after });
+    }
+
     match status {
         StatusCode::OK => Ok(()),
+        StatusCode::FORBIDDEN => Err(BranchUpdateError::PermissionDenied(branch_name)),
+        StatusCode::UNPROCESSABLE_ENTITY if is_protected_branch_message(&text) => {
+            Err(BranchUpdateError::PermissionDenied(branch_name))
+        }
         _ => Err(BranchUpdateError::BranchNotFound(branch_name)),
     }
 }
+
+/// Deletes the branch with the given `branch_name`.
+/// If the branch does not exist, nothing happens.
+pub async fn delete_branch(
+    repo: &GithubRepositoryClient,
+    branch_name: &str,
+) -> Result<(), BranchUpdateError> {
+    let url = repo
+        .client()
+        .base_url
+        .join(&format!(
+            "repos/{}/{}/git/refs/{}",
+            repo.name().owner(),
+            repo.name().name(),
+            Reference::Branch(branch_name.to_string()).ref_url()
+        ))
+        .unwrap();
+    let res = repo.client._delete(url, None::<&()>).await?;
+
+    let status = res.status();
+    let rate_limit = response_secondary_rate_limit(&res);
+    let text = res.text().await.unwrap_or_default();
+    tracing::trace!("Deleting branch response: status={status}, text={text:?}");
+
+    if let Some(retry_after) = rate_limit {
+        return Err(BranchUpdateError::SecondaryRateLimit { retry_after });
+    }
+
+    match status {
+        StatusCode::NO_CONTENT | StatusCode::OK => Ok(()),
+        // The branch does not exist (anymore)
+        StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => Ok(()),
+        StatusCode::FORBIDDEN => Err(BranchUpdateError::PermissionDenied(branch_name.to_string())),
+        _ => Err(BranchUpdateError::Custom(format!(
+            "Cannot delete branch ({status}): {text}"
+        ))),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WorkflowDispatchError {
+    #[error("Workflow not found")]
+    NotFound,
+    #[error("GitHub did not return the ID of the dispatched workflow run")]
+    MissingRunDetails,
+    #[error("Unknown error ({status}): {text}")]
+    Unknown { status: StatusCode, text: String },
+    #[error("Secondary rate limit exceeded")]
+    SecondaryRateLimit { retry_after: Option<Duration> },
+    #[error("Network error: {0}")]
+    NetworkError(#[from] octocrab::Error),
+}
+
+#[derive(serde::Serialize)]
+struct WorkflowDispatchRequest<'a, 'b> {
+    #[serde(rename = "ref")]
+    git_ref: &'a str,
+    inputs: &'b HashMap<String, String>,
+    return_run_details: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct WorkflowDispatchResponse {
+    workflow_run_id: u64,
+    html_url: String,
+}
+
+/// Starts a run of the given `workflow` (its file name or ID) using a `workflow_dispatch` event.
+/// Returns the ID of the started run, so that it can be tracked.
+///
+/// Documentation: https://docs.github.com/en/rest/actions/workflows?apiVersion=2022-11-28#create-a-workflow-dispatch-event
+pub async fn dispatch_workflow(
+    repo: &GithubRepositoryClient,
+    workflow: &str,
+    git_ref: &str,
+    inputs: &HashMap<String, String>,
+) -> Result<DispatchedWorkflow, WorkflowDispatchError> {
+    let url = repo
+        .client()
+        .base_url
+        .join(&format!(
+            "repos/{}/{}/actions/workflows/{workflow}/dispatches",
+            repo.name().owner(),
+            repo.name().name(),
+        ))
+        .unwrap();
+    let request = WorkflowDispatchRequest {
+        git_ref,
+        inputs,
+        return_run_details: true,
+    };
+    let response = repo.client()._post(url, Some(&request)).await?;
+
+    let status = response.status();
+    let rate_limit = response_secondary_rate_limit(&response);
+    let text = response.text().await.unwrap_or_default();
+    tracing::trace!("Workflow dispatch response: status={status}, text={text:?}");
+
+    if let Some(retry_after) = rate_limit {
+        return Err(WorkflowDispatchError::SecondaryRateLimit { retry_after });
+    }
+
+    match status {
+        StatusCode::OK => {
+            let response: WorkflowDispatchResponse =
+                serde_json::from_str(&text).map_err(|error| WorkflowDispatchError::Unknown {
+                    status,
+                    text: format!("{error:?}"),
+                })?;
+            Ok(DispatchedWorkflow {
+                run_id: RunId(response.workflow_run_id),
+                url: response.html_url,
+            })
+        }
+        // The run was started, but we have no way to find out which one it is
+        StatusCode::NO_CONTENT => Err(WorkflowDispatchError::MissingRunDetails),
+        StatusCode::NOT_FOUND => Err(WorkflowDispatchError::NotFound),
+        _ => Err(WorkflowDispatchError::Unknown { status, text }),
+    }
+}
+
+fn is_permission_error(error: &octocrab::Error) -> bool {
+    match error {
+        octocrab::Error::GitHub { source, .. } => {
+            source.message.contains("not accessible by integration")
+                || is_protected_branch_message(&source.message)
+        }
+        _ => false,
+    }
+}
+
+fn is_protected_branch_message(message: &str) -> bool {
+    message.to_lowercase().contains("protected branch")
+}
+
+/// Replaces the text of the given comment.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/mutations#updateissuecomment
+pub async fn edit_comment(
+    repo: &GithubRepositoryClient,
+    comment: &CommentId,
+    text: &str,
+) -> anyhow::Result<()> {
+    const QUERY: &str = r#"mutation($id: ID!, $body: String!) {
+  updateIssueComment(input: {id: $id, body: $body}) { clientMutationId }
+}"#;
+    run_graphql(
+        repo,
+        QUERY,
+        serde_json::json!({ "id": comment.0, "body": text }),
+    )
+    .await
+}
+
+/// Hides the given comment and marks it as outdated.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/mutations#minimizecomment
+pub async fn minimize_comment(
+    repo: &GithubRepositoryClient,
+    comment: &CommentId,
+) -> anyhow::Result<()> {
+    const QUERY: &str = r#"mutation($id: ID!) {
+  minimizeComment(input: {subjectId: $id, classifier: OUTDATED}) { clientMutationId }
+}"#;
+    run_graphql(repo, QUERY, serde_json::json!({ "id": comment.0 })).await
+}
+
+/// Adds the PR with the given node ID to the native merge queue of GitHub.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/mutations#enqueuepullrequest
+pub async fn enqueue_pull_request(
+    repo: &GithubRepositoryClient,
+    pr_node_id: &str,
+) -> anyhow::Result<()> {
+    const QUERY: &str = r#"mutation($id: ID!) {
+  enqueuePullRequest(input: {pullRequestId: $id}) { clientMutationId }
+}"#;
+    run_graphql(repo, QUERY, serde_json::json!({ "id": pr_node_id })).await
+}
+
+/// Removes the PR with the given node ID from the native merge queue of GitHub.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/mutations#dequeuepullrequest
+pub async fn dequeue_pull_request(
+    repo: &GithubRepositoryClient,
+    pr_node_id: &str,
+) -> anyhow::Result<()> {
+    const QUERY: &str = r#"mutation($id: ID!) {
+  dequeuePullRequest(input: {id: $id}) { clientMutationId }
+}"#;
+    run_graphql(repo, QUERY, serde_json::json!({ "id": pr_node_id })).await
+}
+
+/// Finds the ID of the branch protection rule of the repository with the given pattern.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/objects#branchprotectionrule
+pub async fn find_branch_protection_rule(
+    repo: &GithubRepositoryClient,
+    pattern: &str,
+) -> anyhow::Result<Option<String>> {
+    const QUERY: &str = r#"query($owner: String!, $name: String!) {
+  repository(owner: $owner, name: $name) {
+    branchProtectionRules(first: 100) { nodes { id pattern } }
+  }
+}"#;
+
+    #[derive(serde::Deserialize)]
+    struct Response {
+        repository: RepositoryRules,
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RepositoryRules {
+        branch_protection_rules: RuleNodes,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct RuleNodes {
+        nodes: Vec<Rule>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Rule {
+        id: String,
+        pattern: String,
+    }
+
+    let response: Response = query_graphql(
+        repo,
+        QUERY,
+        serde_json::json!({ "owner": repo.name().owner(), "name": repo.name().name() }),
+    )
+    .await?;
+    Ok(response
+        .repository
+        .branch_protection_rules
+        .nodes
+        .into_iter()
+        .find(|rule| rule.pattern == pattern)
+        .map(|rule| rule.id))
+}
+
+/// Protects the branches matching the pattern, including branches that do not exist yet. The
+/// existing rule `rule_id` is updated, otherwise a new rule is created. Pushes are restricted to
+/// `push_actor`, if it is known.
+///
+/// Documentation: https://docs.github.com/en/graphql/reference/mutations#createbranchprotectionrule
+pub async fn protect_branch_pattern(
+    repo: &GithubRepositoryClient,
+    repository_id: &str,
+    rule_id: Option<&str>,
+    pattern: &str,
+    protection: &BranchProtection,
+    push_actor: Option<&str>,
+) -> anyhow::Result<()> {
+    const CREATE_QUERY: &str = r#"mutation($input: CreateBranchProtectionRuleInput!) {
+  createBranchProtectionRule(input: $input) { clientMutationId }
+}"#;
+    const UPDATE_QUERY: &str = r#"mutation($input: UpdateBranchProtectionRuleInput!) {
+  updateBranchProtectionRule(input: $input) { clientMutationId }
+}"#;
+
+    let mut input = serde_json::json!({
+        "pattern": pattern,
+        "requiresStatusChecks": !protection.required_checks.is_empty(),
+        "requiredStatusCheckContexts": protection.required_checks,
+        "restrictsPushes": push_actor.is_some(),
+        "pushActorIds": push_actor.into_iter().collect::<Vec<_>>(),
+        "allowsForcePushes": protection.allow_force_pushes,
+        "allowsDeletions": protection.allow_deletions,
+    });
+    let query = match rule_id {
+        Some(rule_id) => {
+            input["branchProtectionRuleId"] = rule_id.into();
+            UPDATE_QUERY
+        }
+        None => {
+            input["repositoryId"] = repository_id.into();
+            CREATE_QUERY
+        }
+    };
+    run_graphql(repo, query, serde_json::json!({ "input": input })).await
+}
+
+async fn run_graphql(
+    repo: &GithubRepositoryClient,
+    query: &str,
+    variables: serde_json::Value,
+) -> anyhow::Result<()> {
+    query_graphql::<serde_json::Value>(repo, query, variables).await?;
+    Ok(())
+}
+
+/// Sends a GraphQL request and deserializes the `data` of its response.
+async fn query_graphql<T: serde::de::DeserializeOwned>(
+    repo: &GithubRepositoryClient,
+    query: &str,
+    variables: serde_json::Value,
+) -> anyhow::Result<T> {
+    let mut response: serde_json::Value = repo
+        .client()
+        .post(
+            "graphql",
+            Some(&serde_json::json!({
+                "query": query,
+                "variables": variables,
+            })),
+        )
+        .await?;
+    match response.get("errors") {
+        Some(errors) if !errors.is_null() => {
+            Err(anyhow::anyhow!("GraphQL request failed: {errors}"))
+        }
+        _ => Ok(serde_json::from_value(response["data"].take())?),
+    }
+}
@@ -0,0 +1,275 @@
+//! Protection against GitHub secondary rate limits.
+//!
+//! GitHub punishes bursts of mutating requests (posting comments, creating branches, merging)
+//! with `403` responses that ask the client to back off ("secondary rate limit" or, in older
+//! responses, "abuse detection"). To avoid being temporarily banned, all mutating calls of a
+//! single app installation go through a shared [`MutationPacer`], which serializes them, keeps
+//! a minimal delay between them and retries calls that were rejected by the secondary rate limit.
+use std::future::Future;
+use std::time::Duration;
+
+use octocrab::Error;
+use reqwest::{Response, StatusCode};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::github::api::operations::{BranchUpdateError, MergeError, WorkflowDispatchError};
+
+/// Minimal delay between two mutating API calls of a single installation.
+const MUTATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait when GitHub does not tell us (using `Retry-After`) how long we should wait.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How many times should a call rejected because of a secondary rate limit be retried.
+const MAX_RETRIES: u32 = 3;
+
+/// Serializes and paces mutating API calls of a single GitHub app installation.
+pub struct MutationPacer {
+    interval: Duration,
+    default_retry_after: Duration,
+    last_mutation: Mutex<Option<Instant>>,
+}
+
+impl Default for MutationPacer {
+    fn default() -> Self {
+        Self::new(MUTATION_INTERVAL, DEFAULT_RETRY_AFTER)
+    }
+}
+
+impl MutationPacer {
+    pub fn new(interval: Duration, default_retry_after: Duration) -> Self {
+        Self {
+            interval,
+            default_retry_after,
+            last_mutation: Mutex::new(None),
+        }
+    }
+
+    /// Performs the mutating call created by `func`.
+    ///
+    /// Only a single call is performed at any given time. If the call fails because of a
+    /// secondary rate limit, the pacer waits for the requested time and then tries again.
+    pub async fn run<T, E, F, Fut>(&self, mut func: F) -> Result<T, E>
+    where
+        E: SecondaryRateLimit,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut last_mutation = self.last_mutation.lock().await;
+        let mut attempt = 0;
+        loop {
+            if let Some(last) = *last_mutation {
+                tokio::time::sleep_until(last + self.interval).await;
+            }
+            let result = func().await;
+            *last_mutation = Some(Instant::now());
+
+            match result {
+                Err(error) if attempt < MAX_RETRIES => match error.secondary_rate_limit() {
+                    Some(retry_after) => {
+                        let wait = retry_after.unwrap_or(self.default_retry_after);
+                        attempt += 1;
+                        tracing::warn!(
+                            "Hit GitHub secondary rate limit, retrying in {}s (attempt {attempt}/{MAX_RETRIES})",
+                            wait.as_secs()
+                        );
+                        tokio::time::sleep(wait).await;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Errors that can tell if they were caused by a GitHub secondary rate limit.
+pub trait SecondaryRateLimit {
+    /// Returns `Some` if the error was caused by a secondary rate limit.
+    /// The inner value contains the time that should be waited before the next attempt, if
+    /// GitHub has provided it.
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>>;
+}
+
+impl SecondaryRateLimit for Error {
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>> {
+        match self {
+            Error::GitHub { source, .. } if is_secondary_rate_limit_message(&source.message) => {
+                Some(None)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl SecondaryRateLimit for MergeError {
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>> {
+        match self {
+            MergeError::SecondaryRateLimit { retry_after } => Some(*retry_after),
+            MergeError::NetworkError(error) => error.secondary_rate_limit(),
+            _ => None,
+        }
+    }
+}
+
+impl SecondaryRateLimit for BranchUpdateError {
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>> {
+        match self {
+            BranchUpdateError::SecondaryRateLimit { retry_after } => Some(*retry_after),
+            BranchUpdateError::IOError(error) => error.secondary_rate_limit(),
+            _ => None,
+        }
+    }
+}
+
+impl SecondaryRateLimit for WorkflowDispatchError {
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>> {
+        match self {
+            WorkflowDispatchError::SecondaryRateLimit { retry_after } => Some(*retry_after),
+            WorkflowDispatchError::NetworkError(error) => error.secondary_rate_limit(),
+            _ => None,
+        }
+    }
+}
+
+impl SecondaryRateLimit for anyhow::Error {
+    fn secondary_rate_limit(&self) -> Option<Option<Duration>> {
+        if let Some(error) = self.downcast_ref::<Error>() {
+            error.secondary_rate_limit()
+        } else if let Some(error) = self.downcast_ref::<MergeError>() {
+            error.secondary_rate_limit()
+        } else if let Some(error) = self.downcast_ref::<BranchUpdateError>() {
+            error.secondary_rate_limit()
+        } else if let Some(error) = self.downcast_ref::<WorkflowDispatchError>() {
+            error.secondary_rate_limit()
+        } else {
+            None
+        }
+    }
+}
+
+fn is_secondary_rate_limit_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("secondary rate limit") || message.contains("abuse detection")
+}
+
+/// Checks if a raw HTTP response from GitHub signals a secondary rate limit.
+///
+/// Returns the same value as [`SecondaryRateLimit::secondary_rate_limit`].
+pub fn response_secondary_rate_limit(response: &Response) -> Option<Option<Duration>> {
+    let headers = response.headers();
+    parse_secondary_rate_limit(
+        response.status(),
+        headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()),
+        headers
+            .get("x-ratelimit-remaining")
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+fn parse_secondary_rate_limit(
+    status: StatusCode,
+    retry_after: Option<&str>,
+    remaining: Option<&str>,
+) -> Option<Option<Duration>> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    // An exhausted primary rate limit cannot be solved by a short wait.
+    if remaining == Some("0") {
+        return None;
+    }
+    match retry_after {
+        Some(value) => Some(value.trim().parse::<u64>().ok().map(Duration::from_secs)),
+        None if status == StatusCode::TOO_MANY_REQUESTS => Some(None),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use crate::github::api::operations::MergeError;
+    use crate::github::api::rate_limit::{
+        is_secondary_rate_limit_message, parse_secondary_rate_limit, MutationPacer,
+    };
+
+    #[test]
+    fn detect_secondary_rate_limit_message() {
+        assert!(is_secondary_rate_limit_message(
+            "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."
+        ));
+        assert!(is_secondary_rate_limit_message(
+            "You have triggered an abuse detection mechanism."
+        ));
+        assert!(!is_secondary_rate_limit_message(
+            "Resource not accessible by integration"
+        ));
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        assert_eq!(
+            parse_secondary_rate_limit(StatusCode::FORBIDDEN, Some("30"), None),
+            Some(Some(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            parse_secondary_rate_limit(StatusCode::TOO_MANY_REQUESTS, None, None),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn ignore_other_forbidden_responses() {
+        assert_eq!(
+            parse_secondary_rate_limit(StatusCode::FORBIDDEN, None, None),
+            None
+        );
+        assert_eq!(
+            parse_secondary_rate_limit(StatusCode::FORBIDDEN, Some("30"), Some("0")),
+            None
+        );
+        assert_eq!(
+            parse_secondary_rate_limit(StatusCode::CONFLICT, Some("30"), None),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_after_secondary_rate_limit() {
+        let pacer = MutationPacer::new(Duration::ZERO, Duration::ZERO);
+        let attempts = &AtomicU32::new(0);
+        let result = pacer
+            .run(move || async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(MergeError::SecondaryRateLimit { retry_after: None })
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn do_not_retry_other_errors() {
+        let pacer = MutationPacer::new(Duration::ZERO, Duration::ZERO);
+        let attempts = &AtomicU32::new(0);
+        let result: Result<(), _> = pacer
+            .run(move || async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(MergeError::Conflict)
+            })
+            .await;
+        assert!(matches!(result, Err(MergeError::Conflict)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,963 @@
+//! In-process mock of the GitHub API, which lets tests drive the whole bot without network access:
+//! webhooks built by the mock are parsed and handled by the bot, which talks to the mock using the
+//! real GitHub client.
+//!
+//! The mock models repositories with their files and branches, pull requests, comments, labels
+//! and check suites. Requests to endpoints that are not modelled are answered with `404 Not Found`
+//! and can be inspected using [`MockGithub::unhandled_requests`]. Failing requests and lost
+//! webhooks can be injected using [`MockGithub::faults`].
+//!
+//! It is available to other crates with the `mock-github` feature.
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{Method, Request, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, patch, post};
+use axum::{Json, Router};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use octocrab::models::Repository;
+use octocrab::Octocrab;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use url::Url;
+
+use crate::bors::degraded::EventProcessor;
+use crate::bors::event::{BorsEvent, PullRequestComment};
+use crate::bors::lease::LeaseConfig;
+use crate::bors::{BorsContext, BorsState, CommandParser, RepositoryState};
+use crate::config::{RepositoryConfig, CONFIG_FILE_PATH};
+use crate::database::{DbClient, SeaORMClient};
+use crate::faults::Faults;
+use crate::forge::Forge;
+use crate::github::api::client::{fetch_file_content, GithubRepositoryClient};
+use crate::github::webhook::payload_hash;
+use crate::github::{GithubForge, GithubRepoName};
+use crate::http::SendLimited;
+use crate::permissions::{PermissionResolver, PermissionType};
+
+/// Login of the user that posts the comments of the bot.
+pub const MOCK_BOT_LOGIN: &str = "bors[bot]";
+
+/// Branch that is used as the default branch of mocked repositories.
+pub const MOCK_DEFAULT_BRANCH: &str = "main";
+
+const ISSUE_COMMENT_TEMPLATE: &str = include_str!("../../tests/data/webhook/issue-comment.json");
+const PULL_REQUEST_TEMPLATE: &str =
+    include_str!("../../tests/data/webhook/pull-request-synchronize.json");
+
+/// A pull request of a mocked repository.
+#[derive(Clone, Debug)]
+pub struct MockPullRequest {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    pub author: String,
+    pub head_branch: String,
+    pub head_sha: String,
+    pub base_branch: String,
+    pub draft: bool,
+    pub open: bool,
+}
+
+impl MockPullRequest {
+    /// An open PR from the branch `pr-<number>` of the same repository into the default branch.
+    pub fn new(number: u64, head_sha: &str) -> Self {
+        Self {
+            number,
+            title: "PR title".to_string(),
+            body: "PR message".to_string(),
+            author: "author".to_string(),
+            head_branch: format!("pr-{number}"),
+            head_sha: head_sha.to_string(),
+            base_branch: MOCK_DEFAULT_BRANCH.to_string(),
+            draft: false,
+            open: true,
+        }
+    }
+
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = author.to_string();
+        self
+    }
+
+    pub fn with_base_branch(mut self, branch: &str) -> Self {
+        self.base_branch = branch.to_string();
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockRepository {
+    /// Content of files by their path. Files are the same in all branches.
+    files: HashMap<String, String>,
+    /// SHA of the commit of each branch.
+    branches: HashMap<String, String>,
+    pull_requests: HashMap<u64, MockPullRequest>,
+    comments: HashMap<u64, Vec<String>>,
+    commit_comments: HashMap<String, Vec<String>>,
+    labels: HashMap<u64, Vec<String>>,
+    /// Check suites of each commit, as `(branch, conclusion)`.
+    check_suites: HashMap<String, Vec<(String, Option<String>)>>,
+    /// Commits that cannot be merged into any branch.
+    conflicts: HashSet<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    repositories: HashMap<GithubRepoName, MockRepository>,
+    unhandled: Vec<String>,
+    next_id: u64,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Mock of the GitHub API served on a random local port.
+#[derive(Clone)]
+pub struct MockGithub {
+    state: Arc<Mutex<MockState>>,
+    url: Url,
+    /// Failures injected into the mock and into the state of the bot created by it.
+    faults: Arc<Faults>,
+}
+
+impl MockGithub {
+    /// Starts the server of the mock in the background.
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .context("Cannot bind the mock GitHub server")?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let mock = Self {
+            state: Arc::default(),
+            url: format!("http://{address}/").parse()?,
+            faults: Arc::default(),
+        };
+
+        let server = axum::Server::from_tcp(listener)?.serve(mock.router().into_make_service());
+        tokio::spawn(async move {
+            if let Err(error) = server.await {
+                tracing::error!("Mock GitHub server has failed: {error:?}");
+            }
+        });
+        Ok(mock)
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Base URL of the mocked API.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Adds a repository whose default branch points to `main-sha` and contains a `rust-bors.toml`
+    /// file with the given content.
+    pub fn add_repository(&self, repo: &GithubRepoName, config: &str) {
+        let mut repository = MockRepository::default();
+        repository
+            .files
+            .insert(CONFIG_FILE_PATH.to_string(), config.to_string());
+        repository
+            .branches
+            .insert(MOCK_DEFAULT_BRANCH.to_string(), "main-sha".to_string());
+        self.lock().repositories.insert(repo.clone(), repository);
+    }
+
+    pub fn add_pull_request(&self, repo: &GithubRepoName, pr: MockPullRequest) {
+        self.with_repo(repo, |repository| {
+            repository
+                .branches
+                .insert(pr.head_branch.clone(), pr.head_sha.clone());
+            repository.pull_requests.insert(pr.number, pr);
+        });
+    }
+
+    /// Adds a check suite of the given commit that has run on `branch`. A missing conclusion
+    /// means that the suite is still running.
+    pub fn add_check_suite(
+        &self,
+        repo: &GithubRepoName,
+        sha: &str,
+        branch: &str,
+        conclusion: Option<&str>,
+    ) {
+        self.with_repo(repo, |repository| {
+            repository
+                .check_suites
+                .entry(sha.to_string())
+                .or_default()
+                .push((branch.to_string(), conclusion.map(|c| c.to_string())));
+        });
+    }
+
+    /// Makes merges of the given commit fail with a conflict.
+    pub fn add_merge_conflict(&self, repo: &GithubRepoName, sha: &str) {
+        self.with_repo(repo, |repository| {
+            repository.conflicts.insert(sha.to_string());
+        });
+    }
+
+    pub fn set_branch(&self, repo: &GithubRepoName, branch: &str, sha: &str) {
+        self.with_repo(repo, |repository| {
+            repository
+                .branches
+                .insert(branch.to_string(), sha.to_string());
+        });
+    }
+
+    /// SHA of the commit of the branch, if it exists.
+    pub fn branch(&self, repo: &GithubRepoName, branch: &str) -> Option<String> {
+        self.with_repo(repo, |repository| repository.branches.get(branch).cloned())
+    }
+
+    /// Comments posted to the PR, in the order in which they were posted.
+    pub fn comments(&self, repo: &GithubRepoName, pr: u64) -> Vec<String> {
+        self.with_repo(repo, |repository| {
+            repository.comments.get(&pr).cloned().unwrap_or_default()
+        })
+    }
+
+    pub fn commit_comments(&self, repo: &GithubRepoName, sha: &str) -> Vec<String> {
+        self.with_repo(repo, |repository| {
+            repository
+                .commit_comments
+                .get(sha)
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn labels(&self, repo: &GithubRepoName, pr: u64) -> Vec<String> {
+        self.with_repo(repo, |repository| {
+            repository.labels.get(&pr).cloned().unwrap_or_default()
+        })
+    }
+
+    /// Requests of endpoints that are not modelled by the mock (e.g. `GET /repos/a/b/milestones`).
+    pub fn unhandled_requests(&self) -> Vec<String> {
+        self.lock().unhandled.clone()
+    }
+
+    /// Creates a client of the repository that uses the mocked API.
+    pub fn repository_client(
+        &self,
+        repo: &GithubRepoName,
+    ) -> anyhow::Result<GithubRepositoryClient> {
+        let client = Octocrab::builder()
+            .base_url(self.url.clone())?
+            .personal_token("mock-token".to_string())
+            .build()
+            .context("Cannot create the client of the mock")?;
+        let repository: Repository = serde_json::from_value(self.repository_payload(repo))
+            .context("Cannot create the repository of the mock")?;
+        Ok(GithubRepositoryClient::without_pacing(
+            client,
+            repo.clone(),
+            repository,
+        ))
+    }
+
+    /// Creates the state of the bot for all mocked repositories. Their configuration is loaded
+    /// from the mock, and all users have all permissions.
+    pub async fn create_state(&self, db: SeaORMClient) -> anyhow::Result<MockBorsState> {
+        let names: Vec<GithubRepoName> = self.lock().repositories.keys().cloned().collect();
+        let mut repos = HashMap::new();
+        for name in names {
+            let client = self.repository_client(&name)?;
+            let content = fetch_file_content(
+                client.client(),
+                &name,
+                CONFIG_FILE_PATH,
+                MOCK_DEFAULT_BRANCH,
+            )
+            .await?
+            .unwrap_or_default();
+            let config: RepositoryConfig = toml::from_str(&content)
+                .with_context(|| format!("Invalid configuration of {name}"))?;
+            repos.insert(
+                name.clone(),
+                RepositoryState {
+                    repository: name,
+                    client,
+                    permissions_resolver: Box::new(MockPermissions),
+                    agreement_checker: None,
+                    notifications: vec![],
+                    config,
+                    deferred_updates: Default::default(),
+                },
+            );
+        }
+        Ok(MockBorsState {
+            repos,
+            db,
+            ctx: Arc::new(
+                BorsContext::new(CommandParser::new("@bors".to_string()))
+                    .with_faults(self.faults.clone()),
+            ),
+            processor: EventProcessor::default(),
+        })
+    }
+
+    /// Payload of an `issue_comment` webhook about a new comment of the PR.
+    pub fn comment_webhook(
+        &self,
+        repo: &GithubRepoName,
+        pr: u64,
+        author: &str,
+        text: &str,
+    ) -> Vec<u8> {
+        let mut payload: Value = serde_json::from_str(ISSUE_COMMENT_TEMPLATE).unwrap();
+        payload["action"] = json!("created");
+        payload["repository"] = self.repository_payload(repo);
+        payload["issue"]["number"] = json!(pr);
+        payload["comment"]["id"] = json!(self.lock().next_id());
+        payload["comment"]["body"] = json!(text);
+        payload["comment"]["user"]["login"] = json!(author);
+        payload["sender"]["login"] = json!(author);
+        serde_json::to_vec(&payload).unwrap()
+    }
+
+    /// Payload of a `pull_request` webhook with the given action (e.g. `opened` or `synchronize`)
+    /// about the current state of the PR.
+    pub fn pull_request_webhook(&self, repo: &GithubRepoName, pr: u64, action: &str) -> Vec<u8> {
+        let mut payload: Value = serde_json::from_str(PULL_REQUEST_TEMPLATE).unwrap();
+        payload["action"] = json!(action);
+        payload["repository"] = self.repository_payload(repo);
+        payload["pull_request"] = self
+            .pull_request_payload(repo, pr)
+            .unwrap_or_else(|| panic!("PR {repo}#{pr} is not mocked"));
+        serde_json::to_vec(&payload).unwrap()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    fn with_repo<T>(&self, repo: &GithubRepoName, f: impl FnOnce(&mut MockRepository) -> T) -> T {
+        let mut state = self.lock();
+        let repository = state
+            .repositories
+            .get_mut(repo)
+            .unwrap_or_else(|| panic!("Repository {repo} is not mocked"));
+        f(repository)
+    }
+
+    fn repository_payload(&self, repo: &GithubRepoName) -> Value {
+        let template: Value = serde_json::from_str(ISSUE_COMMENT_TEMPLATE).unwrap();
+        let mut payload = template["repository"].clone();
+        let api_url = format!("{}repos/{repo}", self.url);
+        payload["name"] = json!(repo.name());
+        payload["full_name"] = json!(repo.to_string());
+        payload["owner"]["login"] = json!(repo.owner());
+        payload["url"] = json!(api_url);
+        payload["merges_url"] = json!(format!("{api_url}/merges"));
+        payload["default_branch"] = json!(MOCK_DEFAULT_BRANCH);
+        payload
+    }
+
+    fn pull_request_payload(&self, repo: &GithubRepoName, number: u64) -> Option<Value> {
+        let (pr, base_sha, labels) = self.with_repo(repo, |repository| {
+            let pr = repository.pull_requests.get(&number)?.clone();
+            let base_sha = repository
+                .branches
+                .get(&pr.base_branch)
+                .cloned()
+                .unwrap_or_default();
+            let labels = repository.labels.get(&number).cloned().unwrap_or_default();
+            Some((pr, base_sha, labels))
+        })?;
+
+        let template: Value = serde_json::from_str(PULL_REQUEST_TEMPLATE).unwrap();
+        let mut payload = template["pull_request"].clone();
+        payload["number"] = json!(pr.number);
+        payload["node_id"] = json!(format!("mock-pr-{}", pr.number));
+        payload["title"] = json!(pr.title);
+        payload["body"] = json!(pr.body);
+        payload["state"] = json!(if pr.open { "open" } else { "closed" });
+        payload["draft"] = json!(pr.draft);
+        payload["user"]["login"] = json!(pr.author);
+        payload["labels"] = labels_payload(&labels);
+        payload["head"]["label"] = json!(format!("{}:{}", repo.owner(), pr.head_branch));
+        payload["head"]["ref"] = json!(pr.head_branch);
+        payload["head"]["sha"] = json!(pr.head_sha);
+        payload["base"]["label"] = json!(format!("{}:{}", repo.owner(), pr.base_branch));
+        payload["base"]["ref"] = json!(pr.base_branch);
+        payload["base"]["sha"] = json!(base_sha);
+        Some(payload)
+    }
+
+    fn comment_payload(&self, id: u64, text: &str) -> Value {
+        let template: Value = serde_json::from_str(ISSUE_COMMENT_TEMPLATE).unwrap();
+        let mut payload = template["comment"].clone();
+        payload["id"] = json!(id);
+        payload["node_id"] = json!(format!("mock-comment-{id}"));
+        payload["body"] = json!(text);
+        payload["user"]["login"] = json!(MOCK_BOT_LOGIN);
+        payload
+    }
+
+    fn router(&self) -> Router {
+        Router::new()
+            .route("/repos/:owner/:name/pulls/:number", get(get_pull_request))
+            .route("/repos/:owner/:name/pulls/:number/files", get(empty_list))
+            .route("/repos/:owner/:name/pulls/:number/commits", get(empty_list))
+            .route(
+                "/repos/:owner/:name/issues/:number/comments",
+                post(post_comment),
+            )
+            .route(
+                "/repos/:owner/:name/issues/:number/labels",
+                post(add_labels),
+            )
+            .route(
+                "/repos/:owner/:name/issues/:number/labels/:label",
+                delete(remove_label),
+            )
+            .route(
+                "/repos/:owner/:name/commits/:sha/comments",
+                post(post_commit_comment),
+            )
+            .route(
+                "/repos/:owner/:name/commits/:sha/check-suites",
+                get(get_check_suites),
+            )
+            .route("/repos/:owner/:name/contents/*path", get(get_content))
+            .route("/repos/:owner/:name/git/ref/heads/*branch", get(get_branch))
+            .route("/repos/:owner/:name/git/refs", post(create_branch))
+            .route(
+                "/repos/:owner/:name/git/refs/heads/*branch",
+                patch(update_branch).delete(delete_branch),
+            )
+            .route("/repos/:owner/:name/merges", post(merge))
+            .fallback(unhandled_request)
+            .route_layer(axum::middleware::from_fn_with_state(
+                self.clone(),
+                inject_error,
+            ))
+            .with_state(self.clone())
+    }
+
+    /// Looks up the repository of a request, or responds with `404 Not Found`.
+    fn handle<T: IntoResponse>(
+        &self,
+        owner: &str,
+        name: &str,
+        f: impl FnOnce(&mut MockRepository) -> T,
+    ) -> Response {
+        let mut state = self.lock();
+        match state
+            .repositories
+            .get_mut(&GithubRepoName::new(owner, name))
+        {
+            Some(repository) => f(repository).into_response(),
+            None => not_found(),
+        }
+    }
+}
+
+/// State of the bot whose repositories use the mocked API.
+pub struct MockBorsState {
+    repos: HashMap<GithubRepoName, RepositoryState<GithubRepositoryClient>>,
+    pub db: SeaORMClient,
+    ctx: Arc<BorsContext>,
+    /// Keeps events while the database is unavailable, like the event loop of the bot.
+    processor: EventProcessor,
+}
+
+impl MockBorsState {
+    /// Parses the payload of a GitHub webhook and handles the event, like the bot does when it
+    /// receives the webhook.
+    pub async fn webhook(&mut self, event_type: &str, body: &[u8]) -> anyhow::Result<()> {
+        if self.ctx.faults.take_dropped_webhook() {
+            tracing::info!("Dropping {event_type} webhook");
+            return Ok(());
+        }
+        let Some(event) = GithubForge.parse_webhook(event_type, body)? else {
+            return Ok(());
+        };
+        self.event(event).await
+    }
+
+    /// Makes the bot behave like the replica with the given lease configuration.
+    pub fn set_lease(&mut self, lease: LeaseConfig) {
+        Arc::get_mut(&mut self.ctx)
+            .expect("The context is not shared outside of event handling")
+            .lease = Some(lease);
+    }
+
+    /// Handles an event, e.g. [`BorsEvent::Refresh`].
+    pub async fn event(&mut self, event: BorsEvent) -> anyhow::Result<()> {
+        let ctx = self.ctx.clone();
+        let mut processor = std::mem::take(&mut self.processor);
+        let result = processor.process(event, self, &ctx).await;
+        self.processor = processor;
+        result
+    }
+}
+
+impl BorsState<GithubRepositoryClient> for MockBorsState {
+    fn is_comment_internal(&self, comment: &PullRequestComment) -> bool {
+        comment.author.username == MOCK_BOT_LOGIN
+    }
+
+    fn get_repo_state_mut(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<(
+        &mut RepositoryState<GithubRepositoryClient>,
+        &mut dyn DbClient,
+    )> {
+        self.repos
+            .get_mut(repo)
+            .map(|repo| (repo, (&mut self.db) as &mut dyn DbClient))
+    }
+
+    fn get_all_repos_mut(
+        &mut self,
+    ) -> (
+        Vec<&mut RepositoryState<GithubRepositoryClient>>,
+        &mut dyn DbClient,
+    ) {
+        (
+            self.repos.values_mut().collect(),
+            (&mut self.db) as &mut dyn DbClient,
+        )
+    }
+
+    fn reload_repositories(&mut self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+/// Grants all permissions to everyone.
+struct MockPermissions;
+
+#[async_trait]
+impl PermissionResolver for MockPermissions {
+    async fn has_permission(&self, _username: &str, _permission: PermissionType) -> bool {
+        true
+    }
+}
+
+/// Value of the `X-Hub-Signature-256` header of a webhook signed with the given secret.
+pub fn sign_webhook(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Cannot create HMAC key");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Sends a signed GitHub webhook to the `/github` endpoint of a running bot. The delivery ID is
+/// derived from the payload, so sending the same payload twice is treated as a redelivery.
+pub async fn send_webhook(
+    bot_url: &str,
+    secret: &str,
+    event_type: &str,
+    body: Vec<u8>,
+) -> anyhow::Result<StatusCode> {
+    let url = format!("{}/github", bot_url.trim_end_matches('/'));
+    let response = crate::http::client()
+        .post(&url)
+        .header("content-type", "application/json")
+        .header("x-github-event", event_type)
+        .header("x-github-delivery", payload_hash(&body))
+        .header("x-hub-signature-256", sign_webhook(secret, &body))
+        .body(body)
+        .send_limited()
+        .await
+        .with_context(|| format!("Cannot send webhook to {url}"))?;
+    Ok(response.status())
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "message": "Not Found" })),
+    )
+        .into_response()
+}
+
+fn labels_payload(labels: &[String]) -> Value {
+    labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| {
+            json!({
+                "id": index + 1,
+                "node_id": format!("mock-label-{}", index + 1),
+                "url": format!("https://api.github.com/labels/{}", index + 1),
+                "name": label,
+                "color": "ffffff",
+                "default": false,
+                "description": null,
+            })
+        })
+        .collect()
+}
+
+fn ref_payload(branch: &str, sha: &str) -> Value {
+    json!({
+        "ref": format!("refs/heads/{branch}"),
+        "node_id": format!("mock-ref-{branch}"),
+        "url": format!("https://api.github.com/refs/heads/{branch}"),
+        "object": {
+            "type": "commit",
+            "sha": sha,
+            "url": format!("https://api.github.com/commits/{sha}"),
+        }
+    })
+}
+
+type RepoPath = Path<(String, String)>;
+type RepoItemPath = Path<(String, String, String)>;
+
+async fn get_pull_request(
+    State(mock): State<MockGithub>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+) -> Response {
+    match mock.pull_request_payload(&GithubRepoName::new(&owner, &name), number) {
+        Some(payload) => Json(payload).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn empty_list() -> Json<Value> {
+    Json(json!([]))
+}
+
+async fn post_comment(
+    State(mock): State<MockGithub>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    body: Bytes,
+) -> Response {
+    let Some(text) = request_field(&body, "body") else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    let id = mock.lock().next_id();
+    let payload = mock.comment_payload(id, &text);
+    mock.handle(&owner, &name, |repository| {
+        repository.comments.entry(number).or_default().push(text);
+        (StatusCode::CREATED, Json(payload))
+    })
+}
+
+async fn post_commit_comment(
+    State(mock): State<MockGithub>,
+    Path((owner, name, sha)): RepoItemPath,
+    body: Bytes,
+) -> Response {
+    let Some(text) = request_field(&body, "body") else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    mock.handle(&owner, &name, |repository| {
+        repository
+            .commit_comments
+            .entry(sha)
+            .or_default()
+            .push(text);
+        (StatusCode::CREATED, Json(json!({})))
+    })
+}
+
+async fn add_labels(
+    State(mock): State<MockGithub>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    body: Bytes,
+) -> Response {
+    let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+    // Labels are sent either as an array or as an object with a `labels` array
+    let added: Vec<String> = request
+        .get("labels")
+        .unwrap_or(&request)
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|label| label.as_str().map(|label| label.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    mock.handle(&owner, &name, |repository| {
+        let labels = repository.labels.entry(number).or_default();
+        for label in added {
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        Json(labels_payload(labels))
+    })
+}
+
+async fn remove_label(
+    State(mock): State<MockGithub>,
+    Path((owner, name, number, label)): Path<(String, String, u64, String)>,
+) -> Response {
+    mock.handle(&owner, &name, |repository| {
+        let labels = repository.labels.entry(number).or_default();
+        labels.retain(|existing| existing != &label);
+        Json(labels_payload(labels))
+    })
+}
+
+async fn get_check_suites(
+    State(mock): State<MockGithub>,
+    Path((owner, name, sha)): RepoItemPath,
+) -> Response {
+    mock.handle(&owner, &name, |repository| {
+        let suites: Vec<Value> = repository
+            .check_suites
+            .get(&sha)
+            .map(|suites| {
+                suites
+                    .iter()
+                    .map(|(branch, conclusion)| {
+                        json!({ "head_branch": branch, "conclusion": conclusion })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Json(json!({ "total_count": suites.len(), "check_suites": suites }))
+    })
+}
+
+async fn get_content(
+    State(mock): State<MockGithub>,
+    Path((owner, name, path)): RepoItemPath,
+) -> Response {
+    mock.handle(&owner, &name, |repository| {
+        match repository.files.get(path.trim_start_matches('/')) {
+            Some(content) => Json(json!({
+                "type": "file",
+                "encoding": "base64",
+                "content": base64::engine::general_purpose::STANDARD.encode(content),
+            }))
+            .into_response(),
+            None => not_found(),
+        }
+    })
+}
+
+async fn get_branch(
+    State(mock): State<MockGithub>,
+    Path((owner, name, branch)): RepoItemPath,
+) -> Response {
+    let branch = branch.trim_start_matches('/');
+    mock.handle(&owner, &name, |repository| {
+        match repository.branches.get(branch) {
+            Some(sha) => Json(ref_payload(branch, sha)).into_response(),
+            None => not_found(),
+        }
+    })
+}
+
+async fn create_branch(
+    State(mock): State<MockGithub>,
+    Path((owner, name)): RepoPath,
+    body: Bytes,
+) -> Response {
+    let (Some(reference), Some(sha)) = (request_field(&body, "ref"), request_field(&body, "sha"))
+    else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    let Some(branch) = reference.strip_prefix("refs/heads/").map(|b| b.to_string()) else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    mock.handle(&owner, &name, |repository| {
+        if repository.branches.contains_key(&branch) {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "message": "Reference already exists" })),
+            )
+                .into_response();
+        }
+        repository.branches.insert(branch.clone(), sha.clone());
+        (StatusCode::CREATED, Json(ref_payload(&branch, &sha))).into_response()
+    })
+}
+
+async fn update_branch(
+    State(mock): State<MockGithub>,
+    Path((owner, name, branch)): RepoItemPath,
+    body: Bytes,
+) -> Response {
+    let branch = branch.trim_start_matches('/').to_string();
+    let Some(sha) = request_field(&body, "sha") else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    mock.handle(&owner, &name, |repository| {
+        match repository.branches.get_mut(&branch) {
+            Some(current) => {
+                *current = sha.clone();
+                Json(ref_payload(&branch, &sha)).into_response()
+            }
+            None => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "message": "Reference does not exist" })),
+            )
+                .into_response(),
+        }
+    })
+}
+
+async fn delete_branch(
+    State(mock): State<MockGithub>,
+    Path((owner, name, branch)): RepoItemPath,
+) -> Response {
+    let branch = branch.trim_start_matches('/').to_string();
+    mock.handle(&owner, &name, |repository| {
+        match repository.branches.remove(&branch) {
+            Some(_) => StatusCode::NO_CONTENT.into_response(),
+            None => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "message": "Reference does not exist" })),
+            )
+                .into_response(),
+        }
+    })
+}
+
+async fn merge(
+    State(mock): State<MockGithub>,
+    Path((owner, name)): RepoPath,
+    body: Bytes,
+) -> Response {
+    let (Some(base), Some(head)) = (request_field(&body, "base"), request_field(&body, "head"))
+    else {
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    };
+    let id = mock.lock().next_id();
+    mock.handle(&owner, &name, |repository| {
+        if repository.conflicts.contains(&head) {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "message": "Merge conflict" })),
+            )
+                .into_response();
+        }
+        let Some(current) = repository.branches.get_mut(&base) else {
+            return not_found();
+        };
+        let sha = format!("merge-{id}");
+        *current = sha.clone();
+        (StatusCode::CREATED, Json(json!({ "sha": sha }))).into_response()
+    })
+}
+
+/// Responds with an injected error instead of handling the request.
+async fn inject_error<B>(
+    State(mock): State<MockGithub>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match mock.faults.take_github_error() {
+        Some(status) => (status, Json(json!({ "message": "Injected failure" }))).into_response(),
+        None => next.run(request).await,
+    }
+}
+
+async fn unhandled_request(State(mock): State<MockGithub>, method: Method, uri: Uri) -> Response {
+    tracing::warn!("Mock GitHub server received an unhandled request: {method} {uri}");
+    mock.lock()
+        .unhandled
+        .push(format!("{method} {}", uri.path()));
+    not_found()
+}
+
+/// Reads a string field of a JSON request body.
+fn request_field(body: &[u8], field: &str) -> Option<String> {
+    let request: Value = serde_json::from_slice(body).ok()?;
+    request.get(field)?.as_str().map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::mock::{sign_webhook, MockGithub, MockPullRequest};
+    use crate::github::GithubRepoName;
+    use crate::tests::database::create_test_db;
+
+    async fn create_mock() -> (MockGithub, GithubRepoName) {
+        let repo = GithubRepoName::new("owner", "name");
+        let mock = MockGithub::start().await.unwrap();
+        mock.add_repository(&repo, "");
+        mock.add_pull_request(&repo, MockPullRequest::new(1, "pr-sha"));
+        (mock, repo)
+    }
+
+    #[tokio::test]
+    async fn ping_through_webhook() {
+        let (mock, repo) = create_mock().await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors ping"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(mock.comments(&repo, 1), vec!["Pong 🏓!"]);
+        assert!(mock.unhandled_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn try_build_through_webhook() {
+        let (mock, repo) = create_mock().await;
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors try"),
+            )
+            .await
+            .unwrap();
+
+        let merge_sha = mock.branch(&repo, "automation/bors/try").unwrap();
+        assert_eq!(
+            mock.comments(&repo, 1),
+            vec![format!(
+                ":hourglass: Trying commit pr-sha with merge {merge_sha}…"
+            )]
+        );
+        // The temporary merge branch has been deleted
+        assert_eq!(mock.branch(&repo, "automation/bors/try-merge-1"), None);
+        assert_eq!(mock.branch(&repo, "main").as_deref(), Some("main-sha"));
+    }
+
+    #[tokio::test]
+    async fn merge_conflict() {
+        let (mock, repo) = create_mock().await;
+        mock.add_merge_conflict(&repo, "pr-sha");
+        let mut state = mock.create_state(create_test_db().await).await.unwrap();
+        state
+            .webhook(
+                "issue_comment",
+                &mock.comment_webhook(&repo, 1, "reviewer", "@bors try"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(mock.branch(&repo, "automation/bors/try"), None);
+        assert!(mock.comments(&repo, 1)[0].starts_with(":lock: Merge conflict"));
+    }
+
+    #[test]
+    fn signature() {
+        assert_eq!(
+            sign_webhook("secret", b"{}"),
+            "sha256=77325902caca812dc259733aacd046b73817372c777b8d95b402647474516e13"
+        );
+    }
+}
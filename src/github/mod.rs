@@ -2,33 +2,78 @@
 //! for working with (GitHub) repositories.
 use std::fmt::{Debug, Display, Formatter};
 
+use chrono::{DateTime, Utc};
 use url::Url;
 
 pub mod api;
 mod labels;
+#[cfg(any(test, feature = "mock-github"))]
+pub mod mock;
+pub mod oauth;
 pub mod server;
 mod webhook;
 
-pub use api::operations::MergeError;
+pub use api::operations::{BranchUpdateError, MergeError};
 pub use api::GithubAppState;
 pub use labels::{LabelModification, LabelTrigger};
-pub use webhook::WebhookSecret;
+pub use webhook::{GithubForge, WebhookSecret};
 
-/// Unique identifier of a GitHub repository
+/// Unique identifier of a repository.
+///
+/// Repositories of other forges than GitHub are prefixed by the name of their forge (e.g.
+/// `gitlab:group/project`), so that they do not share their state with a GitHub repository with
+/// the same path. The prefixed name is also used as the key of the repository in the database.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct GithubRepoName {
+    forge: Option<String>,
     owner: String,
     name: String,
 }
 
 impl GithubRepoName {
+    /// Name of a GitHub repository.
     pub fn new(owner: &str, name: &str) -> Self {
         Self {
+            forge: None,
             owner: owner.to_lowercase(),
             name: name.to_lowercase(),
         }
     }
 
+    /// Name of a repository hosted by the given forge (e.g. `gitlab`).
+    pub fn on_forge(forge: &str, owner: &str, name: &str) -> Self {
+        Self {
+            forge: Some(forge.to_lowercase()),
+            ..Self::new(owner, name)
+        }
+    }
+
+    /// Name of a repository given by the owner and name segments of an URL path. The owner can be
+    /// prefixed by the forge of the repository (`gitlab:group`).
+    pub fn from_path(owner: &str, name: &str) -> Self {
+        match owner.split_once(':') {
+            Some((forge, owner)) => Self::on_forge(forge, owner, name),
+            None => Self::new(owner, name),
+        }
+    }
+
+    /// Parses the full name of a repository (`[forge:]owner/name`), as it is displayed.
+    pub fn parse(full_name: &str) -> anyhow::Result<Self> {
+        match full_name.rsplit_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() => {
+                Ok(Self::from_path(owner, name))
+            }
+            _ => Err(anyhow::anyhow!(
+                "Repository must be in the format <owner>/<name>, found `{full_name}`"
+            )),
+        }
+    }
+
+    /// Forge that hosts the repository, `None` for GitHub.
+    pub fn forge(&self) -> Option<&str> {
+        self.forge.as_deref()
+    }
+
     pub fn owner(&self) -> &str {
         &self.owner
     }
@@ -36,15 +81,23 @@ impl GithubRepoName {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Path of the repository in its forge (`owner/name`), without the forge.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
 }
 
 impl Display for GithubRepoName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref forge) = self.forge {
+            f.write_fmt(format_args!("{forge}:"))?;
+        }
         f.write_fmt(format_args!("{}/{}", self.owner, self.name))
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GithubUser {
     pub username: String,
     pub html_url: Url,
@@ -69,12 +122,34 @@ impl Display for CommitSha {
     }
 }
 
+/// Identifier of a comment.
+/// It contains the GraphQL node ID of the comment, which is needed to modify it later.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CommentId(pub String);
+
+impl Display for CommentId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Branch {
     pub name: String,
     pub sha: CommitSha,
 }
 
+/// A commit that is a part of a pull request.
+#[derive(Clone, Debug)]
+pub struct Commit {
+    pub sha: CommitSha,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Does the commit have a GPG/SSH signature that was verified by GitHub?
+    pub verified: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct PullRequest {
     pub number: PullRequestNumber,
@@ -84,6 +159,43 @@ pub struct PullRequest {
     pub base: Branch,
     pub title: String,
     pub message: String,
+    /// Username of the author of the PR.
+    pub author: String,
+    pub draft: bool,
+    /// Is the PR open (neither closed nor merged)?
+    pub open: bool,
+    /// Has the PR been merged?
+    pub merged: bool,
+    pub labels: Vec<String>,
+    /// Number of added and removed lines.
+    pub changed_lines: u64,
+    pub changed_files: u64,
+}
+
+impl PullRequest {
+    /// Was the PR opened from a branch of a different repository than `repo`?
+    pub fn is_from_fork(&self, repo: &GithubRepoName) -> bool {
+        self.head_label
+            .split_once(':')
+            .map(|(owner, _)| !owner.eq_ignore_ascii_case(repo.owner()))
+            .unwrap_or(false)
+    }
+}
+
+/// A warning attached to a line of a file in the output of a check run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckAnnotation {
+    pub path: String,
+    pub line: usize,
+    pub message: String,
+}
+
+/// A milestone of a repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Milestone {
+    pub number: u64,
+    pub title: String,
+    pub due_on: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -100,3 +212,23 @@ impl Display for PullRequestNumber {
         <u64 as Display>::fmt(&self.0, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::github::GithubRepoName;
+
+    #[test]
+    fn repository_names_of_forges() {
+        let github = GithubRepoName::parse("Owner/Name").unwrap();
+        assert_eq!(github, GithubRepoName::new("owner", "name"));
+        assert_eq!(github.to_string(), "owner/name");
+
+        let gitlab = GithubRepoName::parse("gitlab:group/subgroup/project").unwrap();
+        assert_eq!(gitlab.forge(), Some("gitlab"));
+        assert_eq!(gitlab.path(), "group/subgroup/project");
+        assert_eq!(gitlab.to_string(), "gitlab:group/subgroup/project");
+        assert_ne!(gitlab, GithubRepoName::new("group/subgroup", "project"));
+
+        assert!(GithubRepoName::parse("name").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Login to the dashboard using a GitHub OAuth App.
+//!
+//! After a user authorizes the OAuth App, their GitHub username is stored in a session cookie
+//! signed by the client secret of the app, so the bot does not have to store any sessions.
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::{ACCEPT, AUTHORIZATION, USER_AGENT};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+
+use crate::http::SendLimited;
+
+/// Name of the cookie that stores the session of a logged in user.
+pub const SESSION_COOKIE: &str = "bors_session";
+
+const OAUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Credentials of the GitHub OAuth App used to log in to the dashboard.
+#[derive(Debug)]
+pub struct OAuthConfig {
+    client_id: String,
+    client_secret: SecretString,
+}
+
+impl OAuthConfig {
+    pub fn new(client_id: String, client_secret: SecretString) -> Self {
+        Self {
+            client_id,
+            client_secret,
+        }
+    }
+
+    /// URL of the GitHub page where the user authorizes the app. After the authorization, the
+    /// user is redirected back to `return_to` (a path of the dashboard).
+    pub fn authorize_url(&self, return_to: &str, now: DateTime<Utc>) -> String {
+        let state = self.sign("state", return_to, now + chrono::Duration::minutes(10));
+        format!(
+            "https://github.com/login/oauth/authorize?client_id={}&state={state}&allow_signup=false",
+            self.client_id
+        )
+    }
+
+    /// Returns the path where the user should be redirected after logging in, if the OAuth
+    /// `state` was created by [`OAuthConfig::authorize_url`] and it has not expired.
+    pub fn verify_state(&self, state: &str, now: DateTime<Utc>) -> Option<String> {
+        self.verify("state", state, now)
+    }
+
+    /// Exchanges the code received from GitHub for an access token and returns the username of
+    /// the user that has authorized the app.
+    pub async fn login(&self, code: &str) -> anyhow::Result<String> {
+        let client = crate::http::client();
+        let response = client
+            .post("https://github.com/login/oauth/access_token")
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.expose_secret().as_str()),
+                ("code", code),
+            ])
+            .timeout(OAUTH_TIMEOUT)
+            .send_limited()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let response: serde_json::Value = serde_json::from_str(&response)
+            .map_err(|error| anyhow::anyhow!("Cannot parse access token response: {error:?}"))?;
+        let token = response["access_token"].as_str().ok_or_else(|| {
+            anyhow::anyhow!(
+                "GitHub did not return an access token: {}",
+                response["error_description"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+            )
+        })?;
+
+        let user = client
+            .get("https://api.github.com/user")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header(USER_AGENT, "bors")
+            .timeout(OAUTH_TIMEOUT)
+            .send_limited()
+            .await?
+            .error_for_status()?
+            .text()
+            .await
+            .context("Cannot load the logged in user")?;
+        let user: serde_json::Value = serde_json::from_str(&user)
+            .map_err(|error| anyhow::anyhow!("Cannot parse user response: {error:?}"))?;
+        user["login"]
+            .as_str()
+            .map(|login| login.to_string())
+            .ok_or_else(|| anyhow::anyhow!("User response does not contain a login"))
+    }
+
+    /// Creates the value of the session cookie of the given user.
+    pub fn create_session(&self, username: &str, now: DateTime<Utc>) -> String {
+        self.sign("session", username, now + chrono::Duration::days(7))
+    }
+
+    /// Returns the username stored in the session cookie, if the cookie is valid.
+    pub fn verify_session(&self, session: &str, now: DateTime<Utc>) -> Option<String> {
+        self.verify("session", session, now)
+    }
+
+    /// Creates a token `<payload>.<signature>` that contains the given value and expires at
+    /// `expires`. The `kind` of the token prevents using e.g. an OAuth state as a session.
+    fn sign(&self, kind: &str, value: &str, expires: DateTime<Utc>) -> String {
+        let payload = format!("{kind}|{}|{value}", expires.timestamp());
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!(
+            "{}.{}",
+            hex::encode(&payload),
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+
+    fn verify(&self, kind: &str, token: &str, now: DateTime<Utc>) -> Option<String> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = String::from_utf8(hex::decode(payload).ok()?).ok()?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&hex::decode(signature).ok()?).ok()?;
+
+        let mut parts = payload.splitn(3, '|');
+        if parts.next()? != kind {
+            return None;
+        }
+        let expires: i64 = parts.next()?.parse().ok()?;
+        if now.timestamp() > expires {
+            return None;
+        }
+        parts.next().map(|value| value.to_string())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(self.client_secret.expose_secret().as_bytes())
+            .expect("Cannot create HMAC key")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use secrecy::SecretString;
+
+    use crate::github::oauth::OAuthConfig;
+
+    fn config(secret: &str) -> OAuthConfig {
+        OAuthConfig::new("client".to_string(), SecretString::new(secret.to_string()))
+    }
+
+    #[test]
+    fn session_roundtrip() {
+        let config = config("secret");
+        let now = Utc::now();
+        let session = config.create_session("foo", now);
+        assert_eq!(config.verify_session(&session, now).as_deref(), Some("foo"));
+        assert_eq!(
+            config.verify_session(&session, now + Duration::days(8)),
+            None
+        );
+    }
+
+    #[test]
+    fn reject_forged_session() {
+        let now = Utc::now();
+        let session = config("other").create_session("foo", now);
+        assert_eq!(config("secret").verify_session(&session, now), None);
+        assert_eq!(config("secret").verify_session("garbage", now), None);
+    }
+
+    #[test]
+    fn state_is_not_a_session() {
+        let config = config("secret");
+        let now = Utc::now();
+        let url = config.authorize_url("/queue/owner/name", now);
+        let state = url
+            .split("state=")
+            .nth(1)
+            .and_then(|rest| rest.split('&').next())
+            .unwrap();
+        assert_eq!(
+            config.verify_state(state, now).as_deref(),
+            Some("/queue/owner/name")
+        );
+        assert_eq!(config.verify_session(state, now), None);
+    }
+}
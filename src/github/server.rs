@@ -1,33 +1,424 @@
-use crate::bors::event::BorsEvent;
-use crate::bors::{handle_bors_event, BorsContext};
-use crate::github::api::GithubAppState;
-use crate::github::webhook::GitHubWebhook;
+use crate::api::{get_build, get_pull_request, get_queue, get_repositories};
+use crate::audit::{load_audit_trail, parse_period, render_audit_trail, AuditFormat};
+use crate::badge::{pull_request_badge, repository_badge, Badge};
+use crate::bors::command::BorsCommand;
+use crate::bors::degraded::EventProcessor;
+use crate::bors::event::{
+    BorsEvent, BuildResultReported, DashboardCommand, PauseRepository, PullRequestChanged,
+    WorkflowCompleted,
+};
+use crate::bors::shard::{RepoShard, ShardedState};
+use crate::bors::{handle_shutdown, BorsContext, BorsState, RepositoryClient};
+use crate::ci::buildkite::{self, BuildkiteForge, BuildkiteWebhook};
+use crate::ci::circleci::{self, CircleCiForge, CircleCiWebhook};
+use crate::ci::jenkins::{self, JenkinsForge, JenkinsWebhook};
+use crate::config::{config_warnings, validate_config, ConfigWarning};
+use crate::consistency::ConsistencyReports;
+use crate::dashboard::{
+    load_queue_and_tree, render_queue, render_queue_page, DashboardUpdate, DashboardUpdates, Viewer,
+};
+use crate::database::{BuildStatus, DbClient, PrimaryKey, TokenScope, WorkflowStatus};
+use crate::error_reporting::{self, ErrorContext};
+use crate::fixtures::FixtureRecorder;
+use crate::forge::{constant_time_eq, Forge, ACCEPTED_DELIVERY, DEFAULT_MAX_WEBHOOK_SIZE};
+use crate::gitea::{self, GiteaForge, GiteaWebhook};
+use crate::github::oauth::{OAuthConfig, SESSION_COOKIE};
 use crate::github::webhook::WebhookSecret;
-use crate::utils::logging::LogError;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use crate::github::webhook::{payload_hash, GitHubWebhook, GithubForge};
+use crate::github::{GithubRepoName, GithubUser, PullRequestNumber};
+use crate::gitlab::{GitLabWebhook, GitlabForge};
+use crate::health::{heartbeat, Heartbeat, HEARTBEATS};
+use crate::history::{load_history, render_history_page};
+use crate::metrics::{CommandMetrics, CACHES, EVENT_QUEUES};
+use crate::openapi::openapi_document;
+use crate::stats::merge_statistics;
+use crate::tokens::{check_token, rotate_token, TokenCheck};
+use crate::utils::logging::{CorrelationId, LogError, LogFilter};
+use axum::extract::{Path, Query, State};
+use axum::http::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Form, Json};
+use chrono::Utc;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::Semaphore;
 use tracing::Instrument;
 
 /// Shared server state for all axum handlers.
 pub struct ServerState {
     webhook_sender: WebhookSender,
-    webhook_secret: WebhookSecret,
+    /// The current webhook secret and the secret that was used before it was rotated.
+    webhook_secrets: RwLock<(WebhookSecret, Option<WebhookSecret>)>,
+    /// Token that authenticates requests to admin endpoints, in addition to API tokens with the
+    /// `admin` scope.
+    admin_token: Option<String>,
+    /// Database used to show the state of the bot in the dashboard.
+    /// If it is not set, the dashboard is disabled.
+    db: Option<Arc<dyn DbClient + Send + Sync>>,
+    /// Notifications about changes of the state shown in the dashboard.
+    updates: Option<DashboardUpdates>,
+    /// Token that authenticates requests to the JSON API, in addition to API tokens with the
+    /// `read` scope.
+    api_token: Option<String>,
+    /// OAuth App used to log in to the dashboard.
+    /// If it is not set, the admin actions of the dashboard are disabled.
+    oauth: Option<OAuthConfig>,
+    /// Usage metrics of the bot. If they are not set, the metrics endpoint is disabled.
+    metrics: Option<Arc<CommandMetrics>>,
+    /// Results of consistency sweeps. If they are not set, the consistency endpoint is disabled.
+    consistency: Option<Arc<ConsistencyReports>>,
+    /// Filter of the log output, changed using an admin endpoint.
+    log_filter: Option<Arc<LogFilter>>,
+    /// Event loops of forges other than GitHub (e.g. GitLab), by the name of the forge.
+    forge_senders: HashMap<&'static str, WebhookSender>,
+    /// Secrets of webhooks of CI services, which are generated by the service instead of using
+    /// the webhook secret of the bot, by the name of the service.
+    ci_webhook_secrets: HashMap<&'static str, WebhookSecret>,
+    /// Maximum size of a webhook payload in bytes.
+    max_webhook_size: usize,
+    /// Set once the bot has started to shut down, after which webhooks are rejected.
+    shutting_down: AtomicBool,
+    /// Stores received webhooks as test fixtures. If it is not set, webhooks are not recorded.
+    fixture_recorder: Option<FixtureRecorder>,
 }
 
 impl ServerState {
-    pub fn new(webhook_sender: WebhookSender, webhook_secret: WebhookSecret) -> Self {
+    pub fn new(
+        webhook_sender: WebhookSender,
+        webhook_secret: WebhookSecret,
+        admin_token: Option<String>,
+    ) -> Self {
         Self {
             webhook_sender,
-            webhook_secret,
+            webhook_secrets: RwLock::new((webhook_secret, None)),
+            admin_token,
+            db: None,
+            updates: None,
+            api_token: None,
+            oauth: None,
+            metrics: None,
+            consistency: None,
+            log_filter: None,
+            forge_senders: HashMap::new(),
+            ci_webhook_secrets: HashMap::new(),
+            max_webhook_size: DEFAULT_MAX_WEBHOOK_SIZE,
+            shutting_down: AtomicBool::new(false),
+            fixture_recorder: None,
         }
     }
 
-    pub fn get_webhook_secret(&self) -> &WebhookSecret {
-        &self.webhook_secret
+    /// Enables the dashboard, which reads the state of the bot from the given database.
+    /// Open dashboard pages are refreshed when a notification is sent through `updates`.
+    pub fn with_dashboard(
+        mut self,
+        db: Arc<dyn DbClient + Send + Sync>,
+        updates: DashboardUpdates,
+    ) -> Self {
+        self.db = Some(db);
+        self.updates = Some(updates);
+        self
+    }
+
+    /// Enables the JSON API, authenticated with the given token.
+    pub fn with_api_token(mut self, token: Option<String>) -> Self {
+        self.api_token = token;
+        self
+    }
+
+    /// Enables logging in to the dashboard using the given GitHub OAuth App.
+    pub fn with_oauth(mut self, oauth: Option<OAuthConfig>) -> Self {
+        self.oauth = oauth;
+        self
+    }
+
+    /// Enables the metrics endpoint, which exposes the given metrics. It is authenticated like the
+    /// JSON API.
+    pub fn with_metrics(mut self, metrics: Arc<CommandMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Enables the admin endpoint that returns the results of consistency sweeps, which are also
+    /// exposed by the metrics endpoint.
+    pub fn with_consistency(mut self, consistency: Arc<ConsistencyReports>) -> Self {
+        self.consistency = Some(consistency);
+        self
+    }
+
+    /// Enables the admin endpoint that changes the filter of the log output.
+    pub fn with_log_filter(mut self, filter: Arc<LogFilter>) -> Self {
+        self.log_filter = Some(filter);
+        self
+    }
+
+    /// Enables receiving webhooks of the given forge, whose events are sent to `sender`.
+    pub fn with_forge(mut self, forge: &dyn Forge, sender: WebhookSender) -> Self {
+        self.forge_senders.insert(forge.name(), sender);
+        self
+    }
+
+    /// Authenticates webhooks of the given CI service using its own secret.
+    pub fn with_ci_webhook_secret(mut self, service: &dyn Forge, secret: WebhookSecret) -> Self {
+        self.ci_webhook_secrets.insert(service.name(), secret);
+        self
+    }
+
+    /// Rejects webhooks whose payload is larger than `size` bytes.
+    pub fn with_max_webhook_size(mut self, size: usize) -> Self {
+        self.max_webhook_size = size;
+        self
+    }
+
+    pub fn max_webhook_size(&self) -> usize {
+        self.max_webhook_size
+    }
+
+    /// Records sanitized payloads of received webhooks, see [`crate::fixtures`].
+    pub fn with_fixture_recorder(mut self, recorder: Option<FixtureRecorder>) -> Self {
+        self.fixture_recorder = recorder;
+        self
+    }
+
+    /// Stores a received webhook as a test fixture, if recording is enabled.
+    pub fn record_fixture(&self, forge: &dyn Forge, event_type: &str, body: &[u8]) {
+        if let Some(recorder) = &self.fixture_recorder {
+            recorder.record(forge, event_type, body);
+        }
+    }
+
+    /// Starts rejecting webhooks with `503`, so that they are redelivered (e.g. by a load balancer
+    /// to another instance, or by the forge later) instead of being lost when the bot stops.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Finds out who has sent a request to the dashboard, using the session cookie.
+    fn viewer(&self, headers: &HeaderMap) -> Viewer {
+        let Some(ref oauth) = self.oauth else {
+            return Viewer::Anonymous;
+        };
+        match session_cookie(headers).and_then(|session| oauth.verify_session(session, Utc::now()))
+        {
+            Some(user) => Viewer::LoggedIn(user),
+            None => Viewer::LoggedOut,
+        }
+    }
+
+    /// Checks that the request is authenticated using a `Bearer` token that has the given scope and
+    /// that can be used in the repository of the request (`None` if the request does not belong
+    /// to a repository).
+    /// The token is either the token configured for the scope (`api_token` or `admin_token`), or
+    /// an API token stored in the database (see [`crate::tokens`]). There is no configured token
+    /// for reporting build results.
+    async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        scope: TokenScope,
+        repo: Option<&GithubRepoName>,
+    ) -> Result<(), StatusCode> {
+        let configured = match scope {
+            TokenScope::Read => self.api_token.as_deref(),
+            TokenScope::Admin => self.admin_token.as_deref(),
+            TokenScope::Report => None,
+        };
+        if configured.is_none() && self.db.is_none() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let Some(token) = bearer_token(headers) else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        if configured.map_or(false, |configured| constant_time_eq(configured, token)) {
+            return Ok(());
+        }
+        let Some(ref db) = self.db else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        match check_token(db.as_ref(), token, scope, repo, Utc::now()).await {
+            Ok(TokenCheck::Allowed) => Ok(()),
+            Ok(TokenCheck::MissingScope | TokenCheck::ForbiddenRepository) => {
+                Err(StatusCode::FORBIDDEN)
+            }
+            Ok(TokenCheck::Invalid) => Err(StatusCode::UNAUTHORIZED),
+            Err(error) => {
+                tracing::error!("Could not check API token: {error:?}");
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+
+    /// Returns the database if the request to the JSON API of the given repository is
+    /// authenticated.
+    async fn api_db(
+        &self,
+        headers: &HeaderMap,
+        repo: &GithubRepoName,
+    ) -> Result<&(dyn DbClient + Send + Sync), StatusCode> {
+        let Some(ref db) = self.db else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        self.authorize(headers, TokenScope::Read, Some(repo))
+            .await?;
+        Ok(db.as_ref())
+    }
+
+    /// Returns the database if the request to an admin endpoint is authenticated.
+    async fn admin_db(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<&(dyn DbClient + Send + Sync), StatusCode> {
+        let Some(ref db) = self.db else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        self.authorize(headers, TokenScope::Admin, None).await?;
+        Ok(db.as_ref())
+    }
+
+    /// Sends an event requested by an administrator to the event loops of all forges, because the
+    /// request does not say which of them manages the repository. The other loops ignore the
+    /// event.
+    async fn send_admin_event(
+        &self,
+        create_event: impl Fn() -> BorsEvent,
+    ) -> Result<(), EnqueueError> {
+        for sender in std::iter::once(&self.webhook_sender).chain(self.forge_senders.values()) {
+            sender.send(create_event()).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the secrets that are accepted for webhooks. The previous secret is still accepted
+    /// after a rotation, because webhooks might have been sent before GitHub started to use the
+    /// new secret.
+    pub fn get_webhook_secrets(&self) -> Vec<WebhookSecret> {
+        let secrets = self.webhook_secrets.read().unwrap();
+        std::iter::once(secrets.0.clone())
+            .chain(secrets.1.clone())
+            .collect()
+    }
+
+    /// Returns the secrets that are accepted for webhooks of the given forge or CI service.
+    pub fn get_webhook_secrets_for(&self, forge: &dyn Forge) -> Vec<WebhookSecret> {
+        match self.ci_webhook_secrets.get(forge.name()) {
+            Some(secret) => vec![secret.clone()],
+            None => self.get_webhook_secrets(),
+        }
+    }
+
+    /// Sends an event reported by a CI service. The webhook does not say which forge manages the
+    /// repository, so results of builds are sent to the event loop of the repository of the build
+    /// that they belong to. If the build cannot be found, they are sent to the event loops of all
+    /// forges, and the other loops ignore the event.
+    pub async fn send_ci_event(&self, event: BorsEvent) -> Result<(), EnqueueError> {
+        let build = match (&event, &self.db) {
+            (BorsEvent::WorkflowCompleted(payload), Some(db)) => {
+                db.find_build_by_workflow_run(payload.run_id).await
+            }
+            (BorsEvent::BuildResultReported(payload), Some(db)) => {
+                db.find_build_by_key(payload.build_id).await
+            }
+            (BorsEvent::WorkflowCompleted(_) | BorsEvent::BuildResultReported(_), None) => Ok(None),
+            _ => return self.webhook_sender.enqueue(event).await,
+        };
+        let repository = match build {
+            Ok(Some(build)) => match GithubRepoName::parse(&build.repository) {
+                Ok(repository) => Some(repository),
+                Err(error) => {
+                    tracing::warn!("Invalid repository of build {}: {error:?}", build.id);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(error) => {
+                tracing::warn!("Cannot find the build of a CI result: {error:?}");
+                None
+            }
+        };
+        let create_event: Box<dyn Fn(Option<GithubRepoName>) -> BorsEvent + Send + Sync> =
+            match event {
+                BorsEvent::WorkflowCompleted(payload) => Box::new(move |repository| {
+                    BorsEvent::WorkflowCompleted(WorkflowCompleted {
+                        repository: repository.unwrap_or_else(|| payload.repository.clone()),
+                        ..payload.clone()
+                    })
+                }),
+                BorsEvent::BuildResultReported(payload) => Box::new(move |repository| {
+                    BorsEvent::BuildResultReported(BuildResultReported {
+                        repository: repository.unwrap_or_else(|| payload.repository.clone()),
+                        ..payload.clone()
+                    })
+                }),
+                event => return self.webhook_sender.enqueue(event).await,
+            };
+        let Some(repository) = repository else {
+            for sender in std::iter::once(&self.webhook_sender).chain(self.forge_senders.values()) {
+                sender.enqueue(create_event(None)).await?;
+            }
+            return Ok(());
+        };
+        let sender = match repository.forge() {
+            None => Some(&self.webhook_sender),
+            Some(forge) => self.forge_senders.get(forge),
+        };
+        match sender {
+            Some(sender) => sender.enqueue(create_event(Some(repository))).await,
+            None => {
+                tracing::warn!("No event loop handles the repository {repository}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Stores a received webhook in the delivery log, if the database is available.
+    /// Only the payloads of accepted webhooks are stored, the other ones cannot be replayed.
+    /// Failures are only logged, so that they do not prevent the webhook from being processed.
+    pub async fn record_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        event_type: &str,
+        body: &[u8],
+        result: &str,
+    ) {
+        let Some(ref db) = self.db else {
+            return;
+        };
+        let payload = if result == ACCEPTED_DELIVERY {
+            String::from_utf8_lossy(body)
+        } else {
+            Default::default()
+        };
+        if let Err(error) = db
+            .record_webhook_delivery(
+                delivery_id,
+                event_type,
+                &payload_hash(body),
+                &payload,
+                result,
+            )
+            .await
+        {
+            tracing::error!("Could not record webhook delivery {delivery_id}: {error:?}");
+        }
+    }
+
+    /// Replaces the current webhook secret.
+    pub fn rotate_webhook_secret(&self, secret: WebhookSecret) {
+        let mut secrets = self.webhook_secrets.write().unwrap();
+        let previous = std::mem::replace(&mut secrets.0, secret);
+        secrets.1 = Some(previous);
     }
 }
 
@@ -38,37 +429,1378 @@ pub async fn github_webhook_handler(
     State(state): State<ServerStateRef>,
     GitHubWebhook(event): GitHubWebhook,
 ) -> impl IntoResponse {
+    webhook_response(state.webhook_sender.enqueue(event).await)
+}
+
+/// Axum handler that receives a webhook of a GitLab project and sends it to the event loop of
+/// GitLab projects.
+pub async fn gitlab_webhook_handler(
+    State(state): State<ServerStateRef>,
+    GitLabWebhook(event): GitLabWebhook,
+) -> impl IntoResponse {
+    let Some(sender) = state.forge_senders.get(GitlabForge.name()) else {
+        return (StatusCode::NOT_FOUND, "");
+    };
+    webhook_response(sender.enqueue(event).await)
+}
+
+/// Axum handler that receives a webhook of a Gitea repository and sends it to the event loop of
+/// Gitea repositories.
+pub async fn gitea_webhook_handler(
+    State(state): State<ServerStateRef>,
+    GiteaWebhook(event): GiteaWebhook,
+) -> impl IntoResponse {
+    let Some(sender) = state.forge_senders.get(GiteaForge.name()) else {
+        return (StatusCode::NOT_FOUND, "");
+    };
+    webhook_response(sender.enqueue(event).await)
+}
+
+/// Responds to a webhook according to the result of sending its event to an event loop. Webhooks
+/// that do not fit into a full queue are rejected with `503`, so that the forge reports them as
+/// failed deliveries, which can be redelivered once the bot has caught up.
+fn webhook_response(result: Result<(), EnqueueError>) -> (StatusCode, &'static str) {
+    match result {
+        Ok(()) => (StatusCode::OK, ""),
+        Err(EnqueueError::Full) => {
+            tracing::warn!("Rejecting webhook event, because the event queue is full");
+            (StatusCode::SERVICE_UNAVAILABLE, "")
+        }
+        Err(err @ EnqueueError::Closed) => {
+            tracing::error!("Could not send webhook event: {err:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "")
+        }
+    }
+}
+
+/// Axum handler that receives a webhook of a Buildkite notification service and sends the result
+/// of a build started by bors to the event loops.
+pub async fn buildkite_webhook_handler(
+    State(state): State<ServerStateRef>,
+    BuildkiteWebhook(event): BuildkiteWebhook,
+) -> impl IntoResponse {
+    webhook_response(state.send_ci_event(event).await)
+}
+
+/// Axum handler that receives a webhook of a CircleCI project and sends the result of a workflow
+/// to the event loops.
+pub async fn circleci_webhook_handler(
+    State(state): State<ServerStateRef>,
+    CircleCiWebhook(event): CircleCiWebhook,
+) -> impl IntoResponse {
+    webhook_response(state.send_ci_event(event).await)
+}
+
+/// Axum handler that receives the result callback of a Jenkins job and sends it to the event
+/// loops.
+pub async fn jenkins_webhook_handler(
+    State(state): State<ServerStateRef>,
+    JenkinsWebhook(event): JenkinsWebhook,
+) -> impl IntoResponse {
+    webhook_response(state.send_ci_event(event).await)
+}
+
+/// Axum handler that reports whether the loops of the bot are making progress, for liveness
+/// probes. It is not authenticated and responds with `503` if some loop is stalled.
+pub async fn health_handler(State(state): State<ServerStateRef>) -> Response {
+    let report = HEARTBEATS.check(Utc::now());
+    // The load balancer should stop sending requests to a bot that is shutting down
+    let status = if report.healthy && !state.is_shutting_down() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
+
+/// Axum handler that returns the usage metrics of the bot in the Prometheus text format.
+pub async fn metrics_handler(State(state): State<ServerStateRef>, headers: HeaderMap) -> Response {
+    let Some(ref metrics) = state.metrics else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = state.authorize(&headers, TokenScope::Read, None).await {
+        return status.into_response();
+    }
+    let mut text = metrics.render() + &EVENT_QUEUES.render() + &CACHES.render();
+    if let Some(ref consistency) = state.consistency {
+        text.push_str(&consistency.render());
+    }
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response()
+}
+
+/// Axum handler that configures branch protection of the given repository.
+/// It has to be authenticated with a token with the `admin` scope using a `Bearer` authorization
+/// header.
+pub async fn protect_branches_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return (status, "");
+    }
+
+    let event = BorsEvent::ProtectBranches(GithubRepoName::from_path(&owner, &name));
     match state.webhook_sender.send(event).await {
-        Ok(_) => (StatusCode::OK, ""),
+        Ok(_) => (StatusCode::ACCEPTED, ""),
         Err(err) => {
-            tracing::error!("Could not send webhook event: {err:?}");
+            tracing::error!("Could not send protect branches event: {err:?}");
             (StatusCode::INTERNAL_SERVER_ERROR, "")
         }
     }
 }
 
-type WebhookSender = mpsc::Sender<BorsEvent>;
+/// Axum handler that processes a stored webhook delivery again, as if it has just been received
+/// from GitHub (or another forge). It has to be authenticated with a token with the `admin` scope
+/// using a `Bearer` authorization header.
+pub async fn replay_delivery_handler(
+    State(state): State<ServerStateRef>,
+    Path(delivery_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(ref db) = state.db else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+
+    let delivery = match db.find_webhook_delivery(&delivery_id).await {
+        Ok(Some(delivery)) => delivery,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Could not load webhook delivery {delivery_id}: {error:?}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    // Only GitLab event types end with `Hook`, e.g. `Note Hook`
+    let (forge, sender): (&dyn Forge, _) = if delivery.event_type.ends_with(" Hook") {
+        (&GitlabForge, state.forge_senders.get(GitlabForge.name()))
+    } else if delivery.event_type.starts_with(gitea::EVENT_TYPE_PREFIX) {
+        (&GiteaForge, state.forge_senders.get(GiteaForge.name()))
+    } else if delivery
+        .event_type
+        .starts_with(buildkite::EVENT_TYPE_PREFIX)
+    {
+        (&BuildkiteForge, Some(&state.webhook_sender))
+    } else if delivery.event_type.starts_with(jenkins::EVENT_TYPE_PREFIX) {
+        (&JenkinsForge, Some(&state.webhook_sender))
+    } else if delivery.event_type.starts_with(circleci::EVENT_TYPE_PREFIX) {
+        (&CircleCiForge, Some(&state.webhook_sender))
+    } else {
+        (&GithubForge, Some(&state.webhook_sender))
+    };
+    let Some(sender) = sender else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if delivery.payload.is_empty() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "The payload of the delivery was not stored ({})",
+                delivery.result
+            ),
+        )
+            .into_response();
+    }
+    let event = match forge.parse_webhook(&delivery.event_type, delivery.payload.as_bytes()) {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            return (
+                StatusCode::OK,
+                "The delivery does not contain an event handled by bors",
+            )
+                .into_response()
+        }
+        Err(error) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Cannot parse the delivery: {error}"),
+            )
+                .into_response()
+        }
+    };
+    tracing::info!(
+        "Replaying webhook delivery {delivery_id} ({})",
+        delivery.event_type
+    );
+    let result = if state.ci_webhook_secrets.contains_key(forge.name()) {
+        state.send_ci_event(event).await
+    } else {
+        sender.enqueue(event).await
+    };
+    match result {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => {
+            tracing::error!("Could not send replayed webhook event: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that returns the current directives of the log filter.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn log_filter_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(ref filter) = state.log_filter else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    filter.directives().into_response()
+}
+
+/// Axum handler that replaces the directives of the log filter (in the `RUST_LOG` syntax, e.g.
+/// `info,bors::github=debug`) with the body of the request.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn set_log_filter_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+    directives: String,
+) -> Response {
+    let Some(ref filter) = state.log_filter else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    let directives = directives.trim();
+    match filter.set_directives(directives) {
+        Ok(()) => directives.to_string().into_response(),
+        Err(error) => (StatusCode::BAD_REQUEST, format!("{error:#}")).into_response(),
+    }
+}
+
+/// Axum handler that returns all repositories known to the bot with their merge queues as JSON.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_repositories_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+) -> Response {
+    let db = match state.admin_db(&headers).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    api_response(get_repositories(db).await.map(Some))
+}
+
+/// Axum handler that returns the report of the last consistency sweep of each repository as JSON.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_consistency_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(ref consistency) = state.consistency else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    Json(consistency.reports()).into_response()
+}
+
+/// Axum handler that removes a PR from the merge queue, regardless of who has approved it.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_dequeue_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    let repository = GithubRepoName::from_path(&owner, &name);
+    let result = state
+        .send_admin_event(|| {
+            BorsEvent::ForceDequeue(PullRequestChanged {
+                repository: repository.clone(),
+                pr_number: PullRequestNumber(number),
+            })
+        })
+        .await;
+    admin_event_response(result)
+}
+
+/// Axum handler that reconciles the persisted state of the given repository with its forge
+/// (running builds, the GitHub merge queue), like when the bot starts.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_resync_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    let repository = GithubRepoName::from_path(&owner, &name);
+    let result = state
+        .send_admin_event(|| BorsEvent::Resync(repository.clone()))
+        .await;
+    admin_event_response(result)
+}
+
+/// Axum handler that pauses the given repository, see [`BorsEvent::Pause`]. The body of the
+/// request is the reason of the pause.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_pause_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+    reason: String,
+) -> Response {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    let repository = GithubRepoName::from_path(&owner, &name);
+    let reason = reason.trim().to_string();
+    let result = state
+        .send_admin_event(|| {
+            BorsEvent::Pause(PauseRepository {
+                repository: repository.clone(),
+                reason: reason.clone(),
+            })
+        })
+        .await;
+    admin_event_response(result)
+}
+
+/// Axum handler that resumes the given paused repository.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_resume_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    let repository = GithubRepoName::from_path(&owner, &name);
+    let result = state
+        .send_admin_event(|| BorsEvent::Resume(repository.clone()))
+        .await;
+    admin_event_response(result)
+}
+
+#[derive(serde::Serialize)]
+struct RotatedToken {
+    id: PrimaryKey,
+    token: String,
+}
+
+/// Axum handler that replaces an API token with a new token, see [`rotate_token`]. The value of
+/// the new token is returned as JSON.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_rotate_token_handler(
+    State(state): State<ServerStateRef>,
+    Path(id): Path<PrimaryKey>,
+    headers: HeaderMap,
+) -> Response {
+    let db = match state.admin_db(&headers).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    match rotate_token(db, id, Utc::now()).await {
+        Ok((id, token)) => {
+            tracing::info!("API token {id} has been rotated");
+            Json(RotatedToken { id, token }).into_response()
+        }
+        Err(error) => (StatusCode::BAD_REQUEST, format!("{error:#}")).into_response(),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ConfigValidation {
+    problems: Vec<String>,
+    warnings: Vec<ConfigWarning>,
+}
+
+/// Axum handler that checks the repository configuration file in the body of the request using
+/// the version of the running bot, and returns the problems and warnings as JSON.
+/// It has to be authenticated with a token with the `admin` scope.
+pub async fn admin_validate_config_handler(
+    State(state): State<ServerStateRef>,
+    headers: HeaderMap,
+    content: String,
+) -> Response {
+    if let Err(status) = state.authorize(&headers, TokenScope::Admin, None).await {
+        return status.into_response();
+    }
+    Json(ConfigValidation {
+        problems: validate_config(&content),
+        warnings: config_warnings(&content),
+    })
+    .into_response()
+}
+
+fn admin_event_response(result: Result<(), EnqueueError>) -> Response {
+    match result {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => {
+            tracing::error!("Could not send admin event: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that shows the merge queue of the given repository as an HTML page.
+pub async fn queue_page_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(ref db) = state.db else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let viewer = state.viewer(&headers);
+    match load_queue_and_tree(db.as_ref(), &repo).await {
+        Ok((entries, tree)) => Ok(Html(render_queue_page(
+            &repo,
+            &entries,
+            tree.as_ref(),
+            &viewer,
+        ))),
+        Err(error) => {
+            tracing::error!("Could not load queue of {repo}: {error:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Axum handler that streams new versions of the queue of the given repository (rendered as HTML)
+/// using server-sent events, whenever the state of the repository might have changed.
+pub async fn queue_events_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(ref updates) = state.updates else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let receiver = updates.subscribe();
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let viewer = state.viewer(&headers);
+    let stream = futures::stream::unfold(
+        (receiver, state.clone(), repo, viewer),
+        |(mut receiver, state, repo, viewer)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(update) if !update.affects(&repo) => continue,
+                    // Some updates were missed, so the state has to be sent anyway
+                    Ok(_) | Err(RecvError::Lagged(_)) => break,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+            let db = state.db.as_ref()?;
+            let queue = match load_queue_and_tree(db.as_ref(), &repo).await {
+                Ok((entries, tree)) => render_queue(&repo, &entries, tree.as_ref(), &viewer),
+                Err(error) => {
+                    tracing::error!("Could not load queue of {repo}: {error:?}");
+                    return None;
+                }
+            };
+            let event = Event::default().data(queue);
+            Some((Ok::<_, Infallible>(event), (receiver, state, repo, viewer)))
+        },
+    );
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct HistoryParams {
+    page: Option<u64>,
+}
+
+/// Axum handler that shows a page of the build history of the given repository as an HTML page.
+pub async fn history_page_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    Query(params): Query<HistoryParams>,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let path = format!("/history/{repo}");
+    history_page(&state, repo, None, params.page, &path).await
+}
+
+/// Axum handler that shows a page of the build history of a PR of the given repository as an
+/// HTML page.
+pub async fn pull_request_history_page_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    Query(params): Query<HistoryParams>,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let path = format!("/history/{repo}/pr/{number}");
+    history_page(
+        &state,
+        repo,
+        Some(PullRequestNumber(number)),
+        params.page,
+        &path,
+    )
+    .await
+}
+
+async fn history_page(
+    state: &ServerState,
+    repo: GithubRepoName,
+    pr: Option<PullRequestNumber>,
+    page: Option<u64>,
+    path: &str,
+) -> Response {
+    let Some(ref db) = state.db else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match load_history(db.as_ref(), &repo, pr, page.unwrap_or(1)).await {
+        Ok(history) => Html(render_history_page(&repo, pr, &history, path)).into_response(),
+        Err(error) => {
+            tracing::error!("Could not load build history of {repo}: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that returns an SVG badge with the state of the merge queue of the given
+/// repository. The file name is `<name>.svg`.
+pub async fn repository_badge_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, file)): Path<(String, String)>,
+) -> Response {
+    let (Some(db), Some(name)) = (&state.db, file.strip_suffix(".svg")) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let repo = GithubRepoName::from_path(&owner, name);
+    badge_response(repository_badge(db.as_ref(), &repo).await.map(Some))
+}
+
+/// Axum handler that returns an SVG badge with the state of a PR of the given repository.
+/// The file name is `<number>.svg`.
+pub async fn pull_request_badge_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, file)): Path<(String, String, String)>,
+) -> Response {
+    let (Some(db), Some(number)) = (
+        &state.db,
+        file.strip_suffix(".svg")
+            .and_then(|number| number.parse::<u64>().ok()),
+    ) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let repo = GithubRepoName::from_path(&owner, &name);
+    badge_response(pull_request_badge(db.as_ref(), &repo, PullRequestNumber(number)).await)
+}
+
+/// Badges must not be cached, otherwise e.g. READMEs on GitHub would show an outdated state.
+fn badge_response(result: anyhow::Result<Option<Badge>>) -> Response {
+    match result {
+        Ok(Some(badge)) => (
+            [
+                (CONTENT_TYPE, "image/svg+xml"),
+                (CACHE_CONTROL, "no-cache, max-age=0"),
+            ],
+            badge.render(),
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Could not create badge: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginParams {
+    return_to: Option<String>,
+}
+
+/// Axum handler that redirects the user to GitHub, where they authorize the OAuth App of the
+/// dashboard.
+pub async fn login_handler(
+    State(state): State<ServerStateRef>,
+    Query(params): Query<LoginParams>,
+) -> Response {
+    let Some(ref oauth) = state.oauth else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let return_to = params
+        .return_to
+        .filter(|path| is_local_path(path))
+        .unwrap_or_else(|| "/".to_string());
+    Redirect::to(&oauth.authorize_url(&return_to, Utc::now())).into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct OAuthCallbackParams {
+    code: String,
+    state: String,
+}
+
+/// Axum handler that receives the user from GitHub after they have authorized the OAuth App,
+/// stores their username in a session cookie and redirects them back to the dashboard.
+pub async fn oauth_callback_handler(
+    State(state): State<ServerStateRef>,
+    Query(params): Query<OAuthCallbackParams>,
+) -> Response {
+    let Some(ref oauth) = state.oauth else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let now = Utc::now();
+    let Some(return_to) = oauth.verify_state(&params.state, now) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    match oauth.login(&params.code).await {
+        Ok(user) => {
+            tracing::info!("User {user} logged in to the dashboard");
+            let cookie = format!(
+                "{SESSION_COOKIE}={}; Path=/; HttpOnly; Secure; SameSite=Lax; Max-Age=604800",
+                oauth.create_session(&user, now)
+            );
+            ([(SET_COOKIE, cookie)], Redirect::to(&return_to)).into_response()
+        }
+        Err(error) => {
+            tracing::error!("Could not log in to the dashboard: {error:?}");
+            StatusCode::UNAUTHORIZED.into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ActionForm {
+    priority: Option<u32>,
+}
+
+/// Axum handler for the buttons of the dashboard. The action is executed as a command of the
+/// logged in user, so it has the same permission checks and it is recorded in the same event log
+/// as a command posted in a comment:
+/// - `retry` starts a new try build (`try`)
+/// - `priority` changes the priority of the PR (`p=<priority>`)
+/// - `dequeue` removes the PR from the queue (`r-`)
+///
+/// The session cookie is not sent with cross-site form submissions (`SameSite=Lax`), which
+/// protects the actions against cross-site request forgery.
+pub async fn dashboard_action_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, number, action)): Path<(String, String, u64, String)>,
+    headers: HeaderMap,
+    Form(form): Form<ActionForm>,
+) -> Response {
+    let Viewer::LoggedIn(username) = state.viewer(&headers) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let command = match (action.as_str(), form.priority) {
+        ("retry", _) => BorsCommand::Try {
+            parent: None,
+            commit: None,
+            jobs: None,
+        },
+        ("priority", Some(priority)) => BorsCommand::SetPriority { priority },
+        ("priority", None) => return StatusCode::BAD_REQUEST.into_response(),
+        ("dequeue", _) => BorsCommand::Unapprove,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let Ok(html_url) = format!("https://github.com/{username}").parse() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let repository = GithubRepoName::from_path(&owner, &name);
+    let event = BorsEvent::DashboardCommand(DashboardCommand {
+        repository: repository.clone(),
+        author: GithubUser { username, html_url },
+        pr_number: PullRequestNumber(number),
+        command,
+    });
+    match state.webhook_sender.send(event).await {
+        Ok(_) => Redirect::to(&format!("/queue/{repository}")).into_response(),
+        Err(err) => {
+            tracing::error!("Could not send dashboard command: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Axum handler that returns the OpenAPI document of the JSON API.
+/// It does not require authentication, so that client generators can fetch it.
+pub async fn openapi_handler() -> Json<serde_json::Value> {
+    Json(openapi_document())
+}
+
+/// Axum handler that returns the merge queue of the given repository as JSON.
+pub async fn api_queue_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    api_response(get_queue(db, &repo).await.map(Some))
+}
+
+/// Axum handler that returns a build of the given repository with its workflows as JSON.
+pub async fn api_build_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, id)): Path<(String, String, PrimaryKey)>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    api_response(get_build(db, &repo, id).await)
+}
+
+/// Axum handler that returns a page of the build history of the given repository as JSON.
+pub async fn api_builds_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    Query(params): Query<HistoryParams>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    api_response(
+        load_history(db, &repo, None, params.page.unwrap_or(1))
+            .await
+            .map(Some),
+    )
+}
+
+/// Axum handler that returns a page of the build history of a PR of the given repository as JSON.
+pub async fn api_pull_request_builds_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    Query(params): Query<HistoryParams>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    let pr = Some(PullRequestNumber(number));
+    api_response(
+        load_history(db, &repo, pr, params.page.unwrap_or(1))
+            .await
+            .map(Some),
+    )
+}
+
+/// Axum handler that returns the state of a PR of the given repository as JSON.
+pub async fn api_pull_request_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, number)): Path<(String, String, u64)>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    api_response(get_pull_request(db, &repo, PullRequestNumber(number)).await)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportedStatus {
+    Pending,
+    Success,
+    Failure,
+}
+
+/// Result of a build reported by an external system.
+#[derive(serde::Deserialize)]
+pub struct ReportedResult {
+    /// Identifies the system, it is shown as the name of its workflow.
+    name: String,
+    status: ReportedStatus,
+    /// Link to the build in the system.
+    url: String,
+    #[serde(default)]
+    artifacts: Vec<String>,
+}
+
+/// Axum handler where external systems report the result of their build of a candidate commit
+/// created by bors in the given repository. The result is stored as a workflow of the build, so a
+/// failure fails the build, and the build waits for a pending result. It has to be authenticated
+/// with a token with the `report` scope that can be used in the repository.
+pub async fn api_build_result_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name, id)): Path<(String, String, PrimaryKey)>,
+    headers: HeaderMap,
+    Json(result): Json<ReportedResult>,
+) -> Response {
+    let Some(ref db) = state.db else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let repo = GithubRepoName::from_path(&owner, &name);
+    if let Err(status) = state
+        .authorize(&headers, TokenScope::Report, Some(&repo))
+        .await
+    {
+        return status.into_response();
+    }
+    if result.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "The name must not be empty").into_response();
+    }
+
+    let build = match db.find_build_by_key(id).await {
+        // Builds of other repositories are not visible to the token
+        Ok(Some(build)) if build.repository.eq_ignore_ascii_case(&repo.to_string()) => build,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Could not load build {id}: {error:?}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if build.status != BuildStatus::Pending {
+        return (StatusCode::CONFLICT, "The build has already finished").into_response();
+    }
+
+    let event = BorsEvent::BuildResultReported(BuildResultReported {
+        repository: repo,
+        build_id: build.id,
+        name: result.name,
+        url: result.url,
+        status: match result.status {
+            ReportedStatus::Pending => WorkflowStatus::Pending,
+            ReportedStatus::Success => WorkflowStatus::Success,
+            ReportedStatus::Failure => WorkflowStatus::Failure,
+        },
+        artifacts: result.artifacts,
+    });
+    match state.send_ci_event(event).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => {
+            tracing::error!("Could not send reported build result: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Longest period for which the statistics can be computed, so that the start of the period is
+/// always a valid date.
+const MAX_STATISTICS_DAYS: u32 = 3650;
+
+#[derive(serde::Deserialize)]
+pub struct StatisticsParams {
+    days: Option<u32>,
+}
+
+/// Axum handler that returns the merge statistics of the given repository as JSON, for the last
+/// `days` days (30 by default).
+pub async fn api_statistics_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    Query(params): Query<StatisticsParams>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    let until = Utc::now();
+    let days = params.days.unwrap_or(30).min(MAX_STATISTICS_DAYS);
+    let since = until - chrono::Duration::days(days.into());
+    api_response(merge_statistics(db, &repo, since, until).await.map(Some))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AuditParams {
+    since: Option<String>,
+    until: Option<String>,
+    format: Option<String>,
+}
+
+/// Axum handler that exports the audit trail of the given repository (commands, approvals and
+/// merges) between the `since` and `until` dates, as CSV or JSON lines.
+pub async fn api_audit_handler(
+    State(state): State<ServerStateRef>,
+    Path((owner, name)): Path<(String, String)>,
+    Query(params): Query<AuditParams>,
+    headers: HeaderMap,
+) -> Response {
+    let repo = GithubRepoName::from_path(&owner, &name);
+    let db = match state.api_db(&headers, &repo).await {
+        Ok(db) => db,
+        Err(status) => return status.into_response(),
+    };
+    let format = params.format.as_deref().unwrap_or("csv");
+    let Some(format) = AuditFormat::parse(format) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown format `{format}`, expected `csv` or `jsonl`"),
+        )
+            .into_response();
+    };
+    let (since, until) =
+        match parse_period(params.since.as_deref(), params.until.as_deref(), Utc::now()) {
+            Ok(period) => period,
+            Err(error) => return (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+        };
+    match load_audit_trail(db, &repo, since, until).await {
+        Ok(records) => (
+            [(CONTENT_TYPE, format.content_type())],
+            render_audit_trail(&records, format),
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::error!("Could not export audit trail: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn api_response<T: serde::Serialize>(result: anyhow::Result<Option<T>>) -> Response {
+    match result {
+        Ok(Some(value)) => Json(value).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Could not answer API request: {error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Returns the token from the `Bearer` authorization header of the request.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Returns the value of the session cookie of the dashboard.
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(SESSION_COOKIE)?
+                .strip_prefix('=')
+        })
+}
+
+/// Does the path point to this server? Used to avoid redirecting users to other websites.
+/// Browsers treat a backslash like a slash and they drop tabs and line breaks, so e.g. `/\evil.com`
+/// would be a protocol-relative URL.
+fn is_local_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(|c| c.is_control())
+}
+
+/// Maximum number of events waiting in the queue of an event loop.
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// How long can a webhook wait for a place in a full event queue. GitHub gives up on a delivery
+/// that is not answered within 10 seconds.
+const ENQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long can a shard handle a single event before it is abandoned. The periodic reconciliation
+/// of a large repository can take minutes, but it should finish well before the event loop is
+/// considered to be stalled.
+const SHARD_EVENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Maximum number of repositories that are reconciled with GitHub (or swept) at the same time, so
+/// that the reconciliation after a start does not exceed the secondary rate limits of the API.
+const RECONCILE_PARALLELISM: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnqueueError {
+    #[error("The event queue is full")]
+    Full,
+    #[error("The event loop has ended")]
+    Closed,
+}
+
+/// Sends events to the bounded queue of an event loop created by [`create_bors_process`].
+#[derive(Clone)]
+pub struct WebhookSender {
+    /// Name of the queue in the metrics, e.g. `github`.
+    queue: &'static str,
+    sender: mpsc::Sender<BorsEvent>,
+}
+
+impl WebhookSender {
+    /// Creates the queue of an event loop, named `queue` in the metrics.
+    pub fn channel(queue: &'static str) -> (Self, mpsc::Receiver<BorsEvent>) {
+        let (sender, receiver) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+        (Self { queue, sender }, receiver)
+    }
+
+    /// Sends an event created by the bot itself (e.g. the periodic refresh), waiting until there
+    /// is a place for it in the queue.
+    pub async fn send(&self, event: BorsEvent) -> Result<(), EnqueueError> {
+        self.sender
+            .send(event)
+            .await
+            .map_err(|_| EnqueueError::Closed)?;
+        EVENT_QUEUES.record_enqueued(self.queue);
+        Ok(())
+    }
+
+    /// Sends a received event. If the queue is full, the event waits for at most
+    /// [`ENQUEUE_TIMEOUT`], so that the request that has delivered it can be answered in time.
+    pub async fn enqueue(&self, event: BorsEvent) -> Result<(), EnqueueError> {
+        if self.sender.capacity() < EVENT_QUEUE_CAPACITY / 4 {
+            tracing::warn!(
+                "The {} event loop is lagging behind, {} events are waiting",
+                self.queue,
+                EVENT_QUEUE_CAPACITY - self.sender.capacity()
+            );
+        }
+        match self.sender.send_timeout(event, ENQUEUE_TIMEOUT).await {
+            Ok(()) => {
+                EVENT_QUEUES.record_enqueued(self.queue);
+                Ok(())
+            }
+            Err(SendTimeoutError::Timeout(_)) => {
+                EVENT_QUEUES.record_rejected(self.queue);
+                Err(EnqueueError::Full)
+            }
+            Err(SendTimeoutError::Closed(_)) => Err(EnqueueError::Closed),
+        }
+    }
+}
 
 /// Creates a future with a Bors process that continuously receives webhook events and reacts to
 /// them.
-pub fn create_bors_process(
-    mut state: GithubAppState,
+///
+/// Events of each repository are handled by its own shard (see [`crate::bors::shard`]), so that
+/// repositories do not wait for each other. The periodic refresh and the reconciliation are
+/// handled by each shard separately, at most [`RECONCILE_PARALLELISM`] repositories are
+/// reconciled at the same time. Reloading of the repositories waits until all shards have
+/// finished, while the events received in the meantime are kept until it is done.
+///
+/// After each event, a notification is sent through `updates`, so that open dashboard pages of the
+/// affected repository are refreshed.
+///
+/// Once `stop` completes (or all senders of the events have been dropped), the process stops
+/// receiving events, handles the events it has already received, writes out the state kept in
+/// memory (see [`handle_shutdown`]) and ends.
+///
+/// The state can belong to any forge, e.g. [`GithubAppState`](crate::github::GithubAppState) for repositories of a GitHub app.
+/// `queue` names the queue of the events in the metrics.
+pub fn create_bors_process<Client, State>(
+    queue: &'static str,
+    mut state: State,
     ctx: BorsContext,
-) -> (WebhookSender, impl Future<Output = ()>) {
-    let (tx, mut rx) = mpsc::channel::<BorsEvent>(1024);
+    updates: DashboardUpdates,
+    stop: impl Future<Output = ()> + 'static,
+) -> (WebhookSender, impl Future<Output = ()>)
+where
+    Client: RepositoryClient + 'static,
+    State: ShardedState<Client> + 'static,
+{
+    let (tx, mut rx) = WebhookSender::channel(queue);
 
     let service = async move {
-        while let Some(event) = rx.recv().await {
-            tracing::trace!("Received event: {event:#?}");
-
-            let span = tracing::info_span!("Event");
-            if let Err(error) = handle_bors_event(event, &mut state, &ctx)
-                .instrument(span.clone())
-                .await
-            {
-                span.log_error(error);
+        tokio::pin!(stop);
+        let mut stopping = false;
+        // Postponed events of each repository, kept while the database is unavailable
+        let mut processors: HashMap<GithubRepoName, EventProcessor> = HashMap::new();
+        let mut global_processor = EventProcessor::default();
+        // Events waiting for the running shard of their repository
+        let mut pending: HashMap<GithubRepoName, VecDeque<BorsEvent>> = HashMap::new();
+        let reconciliations = Semaphore::new(RECONCILE_PARALLELISM);
+        let mut shards = FuturesUnordered::new();
+        // Repositories whose postponed updates of summary comments will be posted after a delay
+        let mut flushes = FuturesUnordered::new();
+        // Events that affect all repositories, which wait until all shards have finished
+        let mut global_events: VecDeque<BorsEvent> = VecDeque::new();
+        // Events of repositories received after a global event, which are handled after it.
+        // Events are still received while the global events wait, so that the queue does not fill
+        // up while a shard is slow.
+        let mut waiting: Vec<(GithubRepoName, BorsEvent)> = vec![];
+
+        loop {
+            let mut events = vec![];
+            if shards.is_empty() && !global_events.is_empty() {
+                for event in global_events.drain(..) {
+                    process_event(&mut global_processor, event, &mut state, &ctx, &updates).await;
+                }
+                events = std::mem::take(&mut waiting);
+            } else {
+                let event = tokio::select! {
+                    () = &mut stop, if !stopping => {
+                        // The events that are already in the queue are still received
+                        tracing::info!("Stopping the {queue} event loop");
+                        stopping = true;
+                        rx.close();
+                        continue;
+                    }
+                    event = rx.recv() => {
+                        let Some(event) = event else {
+                            break;
+                        };
+                        EVENT_QUEUES.record_processed(queue);
+                        event
+                    }
+                    Some(repo) = flushes.next(), if !flushes.is_empty() => {
+                        BorsEvent::FlushUpdates(repo)
+                    }
+                    Some((mut shard, processor)) = shards.next(), if !shards.is_empty() => {
+                        let repo = shard.repository().clone();
+                        if let Some(delay) = shard.schedule_deferred_updates() {
+                            let repo = repo.clone();
+                            flushes.push(async move {
+                                tokio::time::sleep(delay).await;
+                                repo
+                            });
+                        }
+                        let events = pending.get_mut(&repo).map(std::mem::take).unwrap_or_default();
+                        if events.is_empty() {
+                            pending.remove(&repo);
+                            processors.insert(repo, processor);
+                            state.return_repository(shard);
+                        } else {
+                            shards.push(run_shard(
+                                shard,
+                                processor,
+                                events,
+                                &ctx,
+                                &updates,
+                                &reconciliations,
+                            ));
+                        }
+                        continue;
+                    }
+                };
+                tracing::trace!("Received event: {event:#?}");
+
+                match event {
+                    BorsEvent::Refresh | BorsEvent::Reconcile | BorsEvent::Sweep => {
+                        let repos: Vec<GithubRepoName> = state
+                            .repository_names()
+                            .into_iter()
+                            .chain(pending.keys().cloned())
+                            .collect();
+                        if repos.is_empty() && matches!(event, BorsEvent::Refresh) {
+                            heartbeat(Heartbeat::RefreshProcessed);
+                        }
+                        for repo in repos {
+                            if matches!(event, BorsEvent::Refresh) {
+                                HEARTBEATS.record_refresh_sent(&repo.to_string(), Utc::now());
+                            }
+                            let event = match event {
+                                BorsEvent::Refresh => BorsEvent::Refresh,
+                                BorsEvent::Sweep => BorsEvent::Sweep,
+                                _ => BorsEvent::Reconcile,
+                            };
+                            events.push((repo, event));
+                        }
+                    }
+                    BorsEvent::Comment(ref comment) | BorsEvent::ReviewApproved(ref comment)
+                        if state.is_comment_internal(comment) =>
+                    {
+                        tracing::trace!("Ignoring {comment:?} because it was authored by this bot");
+                    }
+                    event => match event.repository().cloned() {
+                        Some(repo) => events.push((repo, event)),
+                        None => global_events.push_back(event),
+                    },
+                }
+            }
+
+            for (repo, event) in events {
+                if !global_events.is_empty() {
+                    waiting.push((repo, event));
+                } else if let Some(waiting) = pending.get_mut(&repo) {
+                    // A single refresh is enough for a shard that is lagging behind
+                    let refreshing = waiting
+                        .iter()
+                        .any(|event| matches!(event, BorsEvent::Refresh));
+                    if !(refreshing && matches!(event, BorsEvent::Refresh)) {
+                        waiting.push_back(event);
+                    }
+                } else if let Some(shard) = state.take_repository(&repo) {
+                    let processor = processors.remove(&repo).unwrap_or_default();
+                    pending.insert(repo, VecDeque::new());
+                    let events = VecDeque::from([event]);
+                    shards.push(run_shard(
+                        shard,
+                        processor,
+                        events,
+                        &ctx,
+                        &updates,
+                        &reconciliations,
+                    ));
+                } else if event.may_change_config() {
+                    // The repository may have been skipped because of its configuration, so the
+                    // repositories are reloaded once all shards have finished
+                    global_events.push_back(event);
+                } else {
+                    // The repository is unknown, which is reported by the handler
+                    if matches!(event, BorsEvent::Refresh) {
+                        HEARTBEATS.record_refresh_done(&repo.to_string());
+                    }
+                    process_event(&mut global_processor, event, &mut state, &ctx, &updates).await;
+                }
             }
         }
+        // Finish the events that have already been received
+        while let Some((shard, processor)) = shards.next().await {
+            let repo = shard.repository().clone();
+            let events = pending.remove(&repo).unwrap_or_default();
+            if events.is_empty() {
+                processors.insert(repo, processor);
+                state.return_repository(shard);
+            } else {
+                pending.insert(repo, VecDeque::new());
+                shards.push(run_shard(
+                    shard,
+                    processor,
+                    events,
+                    &ctx,
+                    &updates,
+                    &reconciliations,
+                ));
+            }
+        }
+        for event in global_events {
+            process_event(&mut global_processor, event, &mut state, &ctx, &updates).await;
+        }
+        for (repo, event) in waiting {
+            let processor = processors.entry(repo).or_default();
+            process_event(processor, event, &mut state, &ctx, &updates).await;
+        }
+        let postponed: usize = processors
+            .values()
+            .chain([&global_processor])
+            .map(EventProcessor::postponed_events)
+            .sum();
+        if postponed > 0 {
+            tracing::warn!(
+                "Dropping {postponed} event(s) postponed while the database was unavailable"
+            );
+        }
+        handle_shutdown(&mut state, &ctx).await;
+        tracing::info!("The {queue} event loop has stopped");
     };
     (tx, service)
 }
+
+/// Handles the events of a single repository in order, and then gives its state back.
+///
+/// An event that takes longer than [`SHARD_EVENT_TIMEOUT`] (e.g. because of a stuck API call) is
+/// abandoned, so that the shard cannot block the events of its repository and the global events
+/// forever. The state that it has left behind is repaired by the next refresh and reconciliation.
+async fn run_shard<Client: RepositoryClient>(
+    mut shard: RepoShard<Client>,
+    mut processor: EventProcessor,
+    events: VecDeque<BorsEvent>,
+    ctx: &BorsContext,
+    updates: &DashboardUpdates,
+    reconciliations: &Semaphore,
+) -> (RepoShard<Client>, EventProcessor) {
+    for event in events {
+        // The semaphore is never closed
+        let _permit = match event {
+            BorsEvent::Reconcile | BorsEvent::Sweep => reconciliations.acquire().await.ok(),
+            _ => None,
+        };
+        let refresh = matches!(event, BorsEvent::Refresh);
+        let processed = tokio::time::timeout(
+            SHARD_EVENT_TIMEOUT,
+            process_event(&mut processor, event, &mut shard, ctx, updates),
+        )
+        .await;
+        match processed {
+            Ok(()) if refresh => HEARTBEATS.record_refresh_done(&shard.repository().to_string()),
+            Ok(()) => {}
+            Err(_) => tracing::error!(
+                "An event of {} did not finish in {SHARD_EVENT_TIMEOUT:?} and was abandoned",
+                shard.repository()
+            ),
+        }
+    }
+    (shard, processor)
+}
+
+/// Handles a single event. Errors are reported, they do not stop the event loop.
+async fn process_event<Client: RepositoryClient>(
+    processor: &mut EventProcessor,
+    event: BorsEvent,
+    state: &mut dyn BorsState<Client>,
+    ctx: &BorsContext,
+    updates: &DashboardUpdates,
+) {
+    let repository = event.repository().cloned();
+    let processed = match event {
+        BorsEvent::Refresh => Heartbeat::RefreshProcessed,
+        _ => Heartbeat::EventProcessed,
+    };
+
+    let correlation_id = CorrelationId::generate();
+    let span = tracing::info_span!("Event", correlation_id = %correlation_id);
+    let result = correlation_id
+        .scope(
+            processor
+                .process(event, state, ctx)
+                .instrument(span.clone()),
+        )
+        .await;
+    // The refresh is a sign of life of the event loop even if it has failed
+    if result.is_ok() || processed == Heartbeat::RefreshProcessed {
+        heartbeat(processed);
+    }
+    if let Err(error) = result {
+        error_reporting::report_error(
+            &error,
+            ErrorContext {
+                event: "Event",
+                repository: repository.as_ref(),
+                pr: None,
+            },
+        );
+        span.log_error(error);
+    }
+    // Fails only if no dashboard page is open
+    let _ = updates.send(DashboardUpdate { repository });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::COOKIE;
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use crate::github::server::{is_local_path, session_cookie};
+
+    #[test]
+    fn find_session_cookie() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            COOKIE,
+            HeaderValue::from_static("theme=dark; bors_session=abc.def"),
+        );
+        assert_eq!(session_cookie(&headers), Some("abc.def"));
+        assert_eq!(session_cookie(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn local_paths() {
+        assert!(is_local_path("/queue/owner/name"));
+        assert!(!is_local_path("//evil.com"));
+        assert!(!is_local_path("https://evil.com"));
+        assert!(!is_local_path("/\\evil.com"));
+        assert!(!is_local_path("/\t/evil.com"));
+    }
+}
@@ -1,30 +1,35 @@
 //! This module handles parsing webhooks and generating [`BorsEvent`]s from them.
 use std::fmt::Debug;
 
-use axum::body::{Bytes, HttpBody};
+use axum::async_trait;
+use axum::body::HttpBody;
 use axum::extract::FromRequest;
-use axum::http::request::Parts;
 use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
-use axum::{async_trait, RequestExt};
 use hmac::{Hmac, Mac};
 use octocrab::models::events::payload::{
     IssueCommentEventAction, IssueCommentEventPayload, PullRequestReviewCommentEventAction,
     PullRequestReviewCommentEventPayload,
 };
-use octocrab::models::pulls::{PullRequest, Review};
+use octocrab::models::pulls::{PullRequest, Review, ReviewState};
 use octocrab::models::{workflows, App, CheckRun, Repository, RunId, User};
 use secrecy::{ExposeSecret, SecretString};
 use sha2::Sha256;
 
 use crate::bors::event::{
-    BorsEvent, CheckSuiteCompleted, PullRequestComment, WorkflowCompleted, WorkflowStarted,
+    BorsEvent, BranchRewrite, BranchRewritten, CheckSuiteCompleted, ConfigChanged,
+    PullRequestChanged, PullRequestClosed, PullRequestComment, PullRequestLabelChanged,
+    PullRequestPushed, ReviewDismissed, WorkflowCompleted, WorkflowStarted,
 };
+use crate::bors_ng::BORS_NG_CONFIG_FILE_PATH;
+use crate::config::CONFIG_FILE_PATH;
 use crate::database::{WorkflowStatus, WorkflowType};
+use crate::forge::{extract_webhook, header, Forge};
 use crate::github::server::ServerStateRef;
 use crate::github::{CommitSha, GithubRepoName, GithubUser, PullRequestNumber};
 
 /// Wrapper for a secret which is zeroed on drop and can be exposed only through the
 /// [`WebhookSecret::expose`] method.
+#[derive(Clone)]
 pub struct WebhookSecret(SecretString);
 
 impl WebhookSecret {
@@ -89,6 +94,67 @@ pub struct WebhookPullRequestReviewEvent<'a> {
     sender: User,
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct WebhookPullRequestEvent<'a> {
+    action: &'a str,
+    pull_request: PullRequest,
+    repository: Repository,
+    /// Present for the `labeled` and `unlabeled` actions.
+    label: Option<WebhookLabel>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WebhookLabel {
+    name: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WebhookPushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    before: String,
+    after: String,
+    deleted: bool,
+    forced: bool,
+    repository: PushRepository,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PushCommit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+}
+
+impl PushCommit {
+    fn touches(&self, path: &str) -> bool {
+        self.added
+            .iter()
+            .chain(&self.modified)
+            .chain(&self.removed)
+            .any(|p| p == path)
+    }
+}
+
+/// The repository in push events uses numeric timestamps, so it cannot be parsed as octocrab's
+/// `Repository`.
+#[derive(Debug, serde::Deserialize)]
+pub struct PushRepository {
+    name: String,
+    owner: PushRepositoryOwner,
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PushRepositoryOwner {
+    login: String,
+}
+
 /// axum extractor for GitHub webhook events.
 #[derive(Debug)]
 pub struct GitHubWebhook(pub BorsEvent);
@@ -107,46 +173,47 @@ where
         request: Request<B>,
         state: &ServerStateRef,
     ) -> Result<Self, Self::Rejection> {
-        let (parts, body) = request
-            .with_limited_body()
-            .expect("There should be a body size limit")
-            .into_parts();
-
-        // Eagerly load body
-        let body: Bytes = hyper::body::to_bytes(body).await.map_err(|error| {
-            tracing::error!("Parsing webhook body failed: {error:?}");
-            StatusCode::BAD_REQUEST
-        })?;
-
-        // Verify that the request is valid
-        if !verify_gh_signature(&parts.headers, &body, state.get_webhook_secret()) {
-            tracing::error!("Webhook request failed, could not authenticate webhook");
-            return Err(StatusCode::BAD_REQUEST);
-        }
+        extract_webhook(&GithubForge, request, state)
+            .await
+            .map(GitHubWebhook)
+    }
+}
 
-        // Parse webhook content
-        match parse_webhook_event(parts, &body) {
-            Ok(Some(event)) => {
-                tracing::trace!("Received webhook event {event:?}");
-                Ok(GitHubWebhook(event))
-            }
-            Ok(None) => Err(StatusCode::OK),
-            Err(error) => {
-                tracing::error!("Cannot parse webhook event: {error:?}");
-                Err(StatusCode::BAD_REQUEST)
-            }
-        }
+/// Webhooks of a GitHub app.
+pub struct GithubForge;
+
+impl Forge for GithubForge {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8], secret: &WebhookSecret) -> bool {
+        verify_gh_signature(headers, body, secret)
+    }
+
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-github-event")
+    }
+
+    fn delivery_id<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-github-delivery")
+    }
+
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        parse_webhook_event(event_type, body)
     }
 }
 
-fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
-    let Some(event_type) = request.headers.get("x-github-event") else {
-         return Err(anyhow::anyhow!("x-github-event header not found"));
-    };
+/// Hex-encoded SHA-256 hash of a webhook payload.
+pub fn payload_hash(body: &[u8]) -> String {
+    hex::encode(<Sha256 as sha2::Digest>::digest(body))
+}
 
+/// Parses the payload of a webhook with the given type (the `X-GitHub-Event` header).
+/// Returns `None` if the webhook is not interesting for the bot.
+pub fn parse_webhook_event(event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
     tracing::trace!(
-        "Webhook: event_type `{}`, payload\n{}",
-        event_type.to_str().unwrap_or_default(),
+        "Webhook: event_type `{event_type}`, payload\n{}",
         std::str::from_utf8(body).unwrap_or_default()
     );
 
@@ -165,11 +232,22 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
         }
         b"pull_request_review" => {
             let payload: WebhookPullRequestReviewEvent = serde_json::from_slice(body)?;
-            if payload.action == "submitted" {
-                let comment = parse_comment_from_pr_review(payload)?;
-                Ok(Some(BorsEvent::Comment(comment)))
-            } else {
-                Ok(None)
+            match payload.action {
+                "submitted" => {
+                    let approved = matches!(payload.review.state, Some(ReviewState::Approved));
+                    let comment = parse_comment_from_pr_review(payload)?;
+                    if approved {
+                        Ok(Some(BorsEvent::ReviewApproved(comment)))
+                    } else {
+                        Ok(Some(BorsEvent::Comment(comment)))
+                    }
+                }
+                "dismissed" => Ok(Some(BorsEvent::ReviewDismissed(ReviewDismissed {
+                    repository: parse_repository_name(&payload.repository)?,
+                    pr_number: PullRequestNumber(payload.pull_request.number),
+                    reviewer: parse_user(payload.review.user),
+                }))),
+                _ => Ok(None),
             }
         }
         b"pull_request_review_comment" => {
@@ -184,6 +262,52 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
                 Ok(None)
             }
         }
+        b"pull_request" => {
+            let payload: WebhookPullRequestEvent = serde_json::from_slice(body)?;
+            let repository = parse_repository_name(&payload.repository)?;
+            let pr_number = PullRequestNumber(payload.pull_request.number);
+            let result = match payload.action {
+                "closed" => Some(BorsEvent::PullRequestClosed(PullRequestClosed {
+                    repository,
+                    pr_number,
+                    merged: payload.pull_request.merged_at.is_some(),
+                    base_branch: payload.pull_request.base.ref_field,
+                })),
+                "labeled" | "unlabeled" => {
+                    let Some(label) = payload.label else {
+                        return Err(anyhow::anyhow!(
+                            "Label is missing in {} event",
+                            payload.action
+                        ));
+                    };
+                    let event = PullRequestLabelChanged {
+                        repository,
+                        pr_number,
+                        label: label.name,
+                    };
+                    if payload.action == "labeled" {
+                        Some(BorsEvent::PullRequestLabeled(event))
+                    } else {
+                        Some(BorsEvent::PullRequestUnlabeled(event))
+                    }
+                }
+                "opened" | "reopened" | "synchronize" => {
+                    Some(BorsEvent::PullRequestPushed(PullRequestPushed {
+                        repository,
+                        pr_number,
+                        head_sha: CommitSha(payload.pull_request.head.sha),
+                    }))
+                }
+                "converted_to_draft" => {
+                    Some(BorsEvent::PullRequestConvertedToDraft(PullRequestChanged {
+                        repository,
+                        pr_number,
+                    }))
+                }
+                _ => None,
+            };
+            Ok(result)
+        }
         b"installation_repositories" | b"installation" => Ok(Some(BorsEvent::InstallationsChanged)),
         b"workflow_run" => {
             let payload: WebhookWorkflowRun = serde_json::from_slice(body)?;
@@ -213,7 +337,7 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
             Ok(result)
         }
         b"check_run" => {
-            let payload: WebhookCheckRun = serde_json::from_slice(body).unwrap();
+            let payload: WebhookCheckRun = serde_json::from_slice(body)?;
 
             // We are only interested in check runs from external CI services.
             // These basically correspond to workflow runs from GHA.
@@ -236,6 +360,44 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
                 Ok(None)
             }
         }
+        b"push" => {
+            let payload: WebhookPushEvent = serde_json::from_slice(body)?;
+            // Only rewrites of branches (not tags) are interesting
+            let Some(branch) = payload.git_ref.strip_prefix("refs/heads/") else {
+                return Ok(None);
+            };
+            let rewrite = if payload.deleted {
+                BranchRewrite::Deleted {
+                    before: CommitSha(payload.before),
+                }
+            } else if payload.forced {
+                BranchRewrite::ForcePushed {
+                    before: CommitSha(payload.before),
+                    after: CommitSha(payload.after),
+                }
+            } else {
+                let default_branch = payload.repository.default_branch.as_deref() == Some(branch);
+                if default_branch
+                    && payload.commits.iter().any(|commit| {
+                        commit.touches(CONFIG_FILE_PATH) || commit.touches(BORS_NG_CONFIG_FILE_PATH)
+                    })
+                {
+                    let repository = payload.repository;
+                    return Ok(Some(BorsEvent::ConfigChanged(ConfigChanged {
+                        repository: GithubRepoName::new(&repository.owner.login, &repository.name),
+                        commit_sha: CommitSha(payload.after),
+                    })));
+                }
+                return Ok(None);
+            };
+            let repository = payload.repository;
+            Ok(Some(BorsEvent::BranchRewritten(BranchRewritten {
+                repository: GithubRepoName::new(&repository.owner.login, &repository.name),
+                branch: branch.to_string(),
+                default_branch: repository.default_branch.as_deref() == Some(branch),
+                rewrite,
+            })))
+        }
         b"check_suite" => {
             let payload: WebhookCheckSuite = serde_json::from_slice(body)?;
             let repository_name = parse_repository_name(&payload.repository)?;
@@ -250,8 +412,7 @@ fn parse_webhook_event(request: Parts, body: &[u8]) -> anyhow::Result<Option<Bor
             }
         }
         _ => {
-            tracing::debug!("Ignoring unknown event type {:?}", event_type.to_str());
-            std::fs::write(format!("{}.json", event_type.to_str().unwrap()), body).unwrap();
+            tracing::debug!("Ignoring unknown event type {event_type:?}");
             Ok(None)
         }
     }
@@ -311,10 +472,7 @@ fn parse_pr_comment(
 
 fn parse_repository_name(repository: &Repository) -> anyhow::Result<GithubRepoName> {
     let repo_name = &repository.name;
-    let Some(repo_owner) = repository
-        .owner
-        .as_ref()
-        .map(|u| &u.login) else {
+    let Some(repo_owner) = repository.owner.as_ref().map(|u| &u.login) else {
         return Err(anyhow::anyhow!("Owner for repo {repo_name} is missing"));
     };
     Ok(GithubRepoName::new(repo_owner, repo_name))
@@ -331,7 +489,10 @@ fn verify_gh_signature(
     let Some(signature) = headers.get("x-hub-signature-256").map(|v| v.as_bytes()) else {
         return false;
     };
-    let Some(signature) = signature.get(b"sha256=".len()..).and_then(|v| hex::decode(v).ok()) else {
+    let Some(signature) = signature
+        .get(b"sha256=".len()..)
+        .and_then(|v| hex::decode(v).ok())
+    else {
         return false;
     };
 
@@ -343,16 +504,20 @@ fn verify_gh_signature(
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use axum::extract::FromRequest;
     use axum::http::{HeaderValue, Method};
     use hmac::Mac;
     use hyper::{Request, StatusCode};
-    use tokio::sync::mpsc;
+    use tokio::sync::broadcast;
 
     use crate::bors::event::BorsEvent;
-    use crate::github::server::{ServerState, ServerStateRef};
+    use crate::database::DbClient;
+    use crate::github::server::{ServerState, ServerStateRef, WebhookSender};
     use crate::github::webhook::WebhookSecret;
-    use crate::github::webhook::{GitHubWebhook, HmacSha256};
+    use crate::github::webhook::{parse_webhook_event, payload_hash, GitHubWebhook, HmacSha256};
+    use crate::tests::database::create_test_db;
     use crate::tests::io::load_test_file;
 
     #[tokio::test]
@@ -457,6 +622,32 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_pull_request_review_approved() {
+        assert!(matches!(
+            check_webhook(
+                "webhook/pull-request-review-approved.json",
+                "pull_request_review"
+            )
+            .await,
+            Ok(GitHubWebhook(BorsEvent::ReviewApproved(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_review_dismissed() {
+        let Ok(GitHubWebhook(BorsEvent::ReviewDismissed(review))) = check_webhook(
+            "webhook/pull-request-review-dismissed.json",
+            "pull_request_review",
+        )
+        .await
+        else {
+            panic!("Review dismissed event was not parsed");
+        };
+        assert_eq!(review.pr_number.0, 6);
+        assert_eq!(review.reviewer.username, "Kobzol");
+    }
+
     #[tokio::test]
     async fn test_pull_request_review_comment() {
         insta::assert_debug_snapshot!(
@@ -500,6 +691,108 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_pull_request_closed() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/pull-request-closed.json", "pull_request").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                PullRequestClosed(
+                    PullRequestClosed {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        pr_number: PullRequestNumber(
+                            6,
+                        ),
+                        merged: false,
+                        base_branch: "main",
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_synchronize() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/pull-request-synchronize.json", "pull_request").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                PullRequestPushed(
+                    PullRequestPushed {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        pr_number: PullRequestNumber(
+                            6,
+                        ),
+                        head_sha: CommitSha(
+                            "dd929458c62ac2a2958a5dadde1465732dff6f90",
+                        ),
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_unlabeled() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/pull-request-unlabeled.json", "pull_request").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                PullRequestUnlabeled(
+                    PullRequestLabelChanged {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        pr_number: PullRequestNumber(
+                            6,
+                        ),
+                        label: "do-not-merge",
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_converted_to_draft() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/pull-request-converted-to-draft.json", "pull_request").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                PullRequestConvertedToDraft(
+                    PullRequestChanged {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        pr_number: PullRequestNumber(
+                            6,
+                        ),
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
     #[tokio::test]
     async fn test_workflow_run_requested() {
         insta::assert_debug_snapshot!(
@@ -599,6 +892,61 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_push_forced() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/push-forced.json", "push").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                BranchRewritten(
+                    BranchRewritten {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        branch: "main",
+                        default_branch: true,
+                        rewrite: ForcePushed {
+                            before: CommitSha(
+                                "ea9c1b050cc8b420c2c211d2177811e564a4dc60",
+                            ),
+                            after: CommitSha(
+                                "bc7370e473896a94d40a7dff71f197a3ff0208f5",
+                            ),
+                        },
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_config() {
+        insta::assert_debug_snapshot!(
+            check_webhook("webhook/push-config.json", "push").await,
+            @r###"
+        Ok(
+            GitHubWebhook(
+                ConfigChanged(
+                    ConfigChanged {
+                        repository: GithubRepoName {
+                            owner: "kobzol",
+                            name: "bors-kindergarten",
+                        },
+                        commit_sha: CommitSha(
+                            "bc7370e473896a94d40a7dff71f197a3ff0208f5",
+                        ),
+                    },
+                ),
+            ),
+        )
+        "###
+        );
+    }
+
     #[tokio::test]
     async fn test_unknown_event() {
         assert_eq!(
@@ -609,11 +957,132 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_rotated_secret() {
+        let (tx, _) = WebhookSender::channel("github");
+        let state = ServerStateRef::new(ServerState::new(
+            tx,
+            WebhookSecret::new("ABCDEF".to_string()),
+            None,
+        ));
+        state.rotate_webhook_secret(WebhookSecret::new("GHIJKL".to_string()));
+        let request =
+            |secret| signed_request("webhook/installation-suspend.json", "installation", secret);
+
+        // The previous secret is accepted until the next rotation
+        assert!(GitHubWebhook::from_request(request("ABCDEF"), &state)
+            .await
+            .is_ok());
+        assert!(GitHubWebhook::from_request(request("GHIJKL"), &state)
+            .await
+            .is_ok());
+        state.rotate_webhook_secret(WebhookSecret::new("MNOPQR".to_string()));
+        assert!(matches!(
+            GitHubWebhook::from_request(request("ABCDEF"), &state).await,
+            Err(StatusCode::BAD_REQUEST)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_delivery() {
+        let db = Arc::new(create_test_db().await);
+        let (tx, _) = WebhookSender::channel("github");
+        let state = ServerStateRef::new(
+            ServerState::new(tx, WebhookSecret::new("ABCDEF".to_string()), None)
+                .with_dashboard(db.clone(), broadcast::channel(1).0),
+        );
+        let mut request = signed_request("webhook/issue-comment.json", "issue_comment", "ABCDEF");
+        request
+            .headers_mut()
+            .insert("x-github-delivery", HeaderValue::from_static("delivery-1"));
+        GitHubWebhook::from_request(request, &state).await.unwrap();
+
+        let delivery = db
+            .find_webhook_delivery("delivery-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivery.event_type, "issue_comment");
+        assert_eq!(delivery.result, "accepted");
+        assert_eq!(
+            delivery.payload_hash,
+            payload_hash(load_test_file("webhook/issue-comment.json").as_bytes())
+        );
+        assert!(matches!(
+            parse_webhook_event(&delivery.event_type, delivery.payload.as_bytes()),
+            Ok(Some(BorsEvent::Comment(_)))
+        ));
+
+        // Only the payloads of accepted deliveries are stored
+        let mut request =
+            signed_request("webhook/check-run-created-gha.json", "check_run", "ABCDEF");
+        request
+            .headers_mut()
+            .insert("x-github-delivery", HeaderValue::from_static("delivery-2"));
+        assert!(GitHubWebhook::from_request(request, &state).await.is_err());
+        let delivery = db
+            .find_webhook_delivery("delivery-2")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivery.result, "ignored");
+        assert!(delivery.payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_oversized_webhook() {
+        let (tx, _) = WebhookSender::channel("github");
+        let state = ServerStateRef::new(
+            ServerState::new(tx, WebhookSecret::new("ABCDEF".to_string()), None)
+                .with_max_webhook_size(100),
+        );
+        let request = signed_request("webhook/issue-comment.json", "issue_comment", "ABCDEF");
+        assert!(matches!(
+            GitHubWebhook::from_request(request, &state).await,
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        ));
+
+        // The size is also checked while reading bodies without a length
+        let mut request = signed_request("webhook/issue-comment.json", "issue_comment", "ABCDEF");
+        request.headers_mut().remove("content-length");
+        assert!(matches!(
+            GitHubWebhook::from_request(request, &state).await,
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reject_webhook_while_shutting_down() {
+        let (tx, _) = WebhookSender::channel("github");
+        let state = ServerStateRef::new(ServerState::new(
+            tx,
+            WebhookSecret::new("ABCDEF".to_string()),
+            None,
+        ));
+        state.begin_shutdown();
+        let request = signed_request("webhook/issue-comment.json", "issue_comment", "ABCDEF");
+        assert!(matches!(
+            GitHubWebhook::from_request(request, &state).await,
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        ));
+    }
+
     async fn check_webhook(file: &str, event: &str) -> Result<GitHubWebhook, StatusCode> {
+        let secret = "ABCDEF";
+        let request = signed_request(file, event, secret);
+        let (tx, _) = WebhookSender::channel("github");
+        let server_ref = ServerStateRef::new(ServerState::new(
+            tx,
+            WebhookSecret::new(secret.to_string()),
+            None,
+        ));
+        GitHubWebhook::from_request(request, &server_ref).await
+    }
+
+    fn signed_request(file: &str, event: &str, secret: &str) -> Request<String> {
         let body = load_test_file(file);
         let body_length = body.len();
 
-        let secret = "ABCDEF".to_string();
         let mut mac =
             HmacSha256::new_from_slice(secret.as_bytes()).expect("Cannot create HMAC key");
         mac.update(body.as_bytes());
@@ -634,9 +1103,6 @@ mod tests {
             "x-hub-signature-256",
             HeaderValue::from_str(&signature).unwrap(),
         );
-
-        let (tx, _) = mpsc::channel(1024);
-        let server_ref = ServerStateRef::new(ServerState::new(tx, WebhookSecret::new(secret)));
-        GitHubWebhook::from_request(request, &server_ref).await
+        request
     }
 }
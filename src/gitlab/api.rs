@@ -0,0 +1,155 @@
+//! A minimal client of the GitLab REST API (v4), authenticated with a personal, group or project
+//! access token of the bot user.
+use anyhow::Context;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde::de::DeserializeOwned;
+
+use crate::github::GithubRepoName;
+use crate::http::SendLimited;
+
+/// Number of items requested on a single page of a list endpoint.
+pub const PAGE_SIZE: usize = 100;
+
+/// A request to the GitLab API has failed with an unexpected status.
+#[derive(Debug, thiserror::Error)]
+#[error("GitLab API request failed ({status}): {text}")]
+pub struct GitlabApiError {
+    pub status: StatusCode,
+    pub text: String,
+}
+
+pub struct GitlabApi {
+    client: reqwest::Client,
+    /// URL of the GitLab instance, e.g. `https://gitlab.com`.
+    url: String,
+    token: SecretString,
+}
+
+impl GitlabApi {
+    pub fn new(url: &str, token: SecretString) -> Self {
+        Self {
+            client: crate::http::client(),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    /// URL of the GitLab instance.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Sends a request to the given path (relative to `/api/v4`) and returns the status and the
+    /// body of the response, regardless of the status.
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}/api/v4{path}", self.url))
+            .header("PRIVATE-TOKEN", self.token.expose_secret());
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        let response = request
+            .send_limited()
+            .await
+            .with_context(|| format!("Cannot send GitLab API request {method} {path}"))?;
+        let status = response.status();
+        let text = response.text().await?;
+        Ok((status, text))
+    }
+
+    /// Sends a request and parses its successful response.
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<T> {
+        let (status, text) = self.send(method, path, body).await?;
+        if !status.is_success() {
+            return Err(GitlabApiError { status, text }.into());
+        }
+        serde_json::from_str(&text)
+            .with_context(|| format!("Cannot parse GitLab API response of {path}"))
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        self.call(Method::GET, path, None).await
+    }
+
+    /// Loads a resource, or returns `None` if it does not exist.
+    pub async fn get_optional<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Option<T>> {
+        match self.get(path).await {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => match error.downcast_ref::<GitlabApiError>() {
+                Some(GitlabApiError {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                }) => Ok(None),
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Loads all items of a list endpoint. `path` must already contain a query string.
+    pub async fn get_all<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Vec<T>> {
+        let mut items = vec![];
+        for page in 1.. {
+            let batch: Vec<T> = self
+                .get(&format!("{path}&per_page={PAGE_SIZE}&page={page}"))
+                .await?;
+            let last_page = batch.len() < PAGE_SIZE;
+            items.extend(batch);
+            if last_page {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// Path of the project in API URLs (`/projects/<id>`), e.g. `group%2Fsubgroup%2Fproject`.
+pub fn project_id(repo: &GithubRepoName) -> String {
+    encode(&repo.path())
+}
+
+/// Encodes a value (e.g. a branch name or a file path) used as a segment of an API URL.
+pub fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
+
+/// Converts the path of a project (`group/subgroup/project`) to a repository name, whose owner is
+/// the (possibly nested) group.
+pub fn parse_project_path(path: &str) -> anyhow::Result<GithubRepoName> {
+    let (owner, name) = path
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow::anyhow!("Invalid GitLab project path `{path}`"))?;
+    Ok(GithubRepoName::on_forge("gitlab", owner, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gitlab::api::{encode, parse_project_path, project_id};
+
+    #[test]
+    fn project_paths() {
+        let repo = parse_project_path("Group/Subgroup/Project").unwrap();
+        assert_eq!(repo.owner(), "group/subgroup");
+        assert_eq!(repo.name(), "project");
+        assert_eq!(repo.to_string(), "gitlab:group/subgroup/project");
+        assert_eq!(project_id(&repo), "group%2Fsubgroup%2Fproject");
+        assert_eq!(encode("docs/a file.md"), "docs%2Fa%20file.md");
+        assert!(parse_project_path("project").is_err());
+    }
+}
@@ -0,0 +1,812 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use octocrab::models::RunId;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use crate::bors::{
+    BranchProtection, CheckSuite, CheckSuiteStatus, DispatchedWorkflow, JobLog, RepositoryClient,
+    WorkflowLink, WorkflowRunDetails,
+};
+use crate::github::{
+    Branch, BranchUpdateError, CheckAnnotation, CommentId, Commit, CommitSha, GithubRepoName,
+    MergeError, Milestone, PullRequest, PullRequestNumber,
+};
+use crate::gitlab::api::{encode, project_id, GitlabApi, GitlabApiError};
+use crate::log_excerpt::log_tail;
+
+/// GitLab access level of maintainers, which can push to protected branches.
+const MAINTAINER_ACCESS_LEVEL: u32 = 40;
+
+/// Provides access to a single GitLab project. Merge requests are identified by their IID, which
+/// is used as the PR number.
+pub struct GitlabRepositoryClient {
+    pub api: Arc<GitlabApi>,
+    pub repo_name: GithubRepoName,
+    /// URL of the project page, e.g. `https://gitlab.com/group/project`.
+    pub web_url: String,
+}
+
+impl GitlabRepositoryClient {
+    fn project(&self) -> String {
+        format!("/projects/{}", project_id(&self.repo_name))
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UserPayload {
+    username: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct DiffRefsPayload {
+    base_sha: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct MergeRequestPayload {
+    iid: u64,
+    title: String,
+    description: Option<String>,
+    author: UserPayload,
+    #[serde(default)]
+    draft: bool,
+    state: String,
+    #[serde(default)]
+    labels: Vec<String>,
+    source_branch: String,
+    target_branch: String,
+    source_project_id: u64,
+    target_project_id: u64,
+    sha: String,
+    diff_refs: Option<DiffRefsPayload>,
+    /// A number, or e.g. `1000+` for large merge requests.
+    changes_count: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NotePayload {
+    id: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PipelinePayload {
+    id: u64,
+    status: String,
+    web_url: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct JobPayload {
+    id: u64,
+    name: String,
+    status: String,
+    web_url: String,
+    artifacts_file: Option<serde_json::Value>,
+}
+
+// Each API call has its own span, nested in the span of the event (and build) that has made it.
+#[async_trait]
+impl RepositoryClient for GitlabRepositoryClient {
+    fn repository(&self) -> &GithubRepoName {
+        &self.repo_name
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request(&mut self, pr: PullRequestNumber) -> anyhow::Result<PullRequest> {
+        let mr: MergeRequestPayload = self
+            .api
+            .get(&format!("{}/merge_requests/{pr}", self.project()))
+            .await
+            .with_context(|| format!("Could not get MR {}!{pr}", self.repo_name))?;
+        Ok(gitlab_mr_to_pr(&self.repo_name, mr))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_files(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct DiffPayload {
+            new_path: String,
+        }
+
+        let diffs: Vec<DiffPayload> = self
+            .api
+            .get_all(&format!("{}/merge_requests/{pr}/diffs?", self.project()))
+            .await
+            .with_context(|| format!("Cannot load files of MR {pr}"))?;
+        Ok(diffs.into_iter().map(|diff| diff.new_path).collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_pull_request_commits(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<Commit>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            id: String,
+            message: String,
+            author_name: String,
+            author_email: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct SignaturePayload {
+            verification_status: String,
+        }
+
+        let commits: Vec<CommitPayload> = self
+            .api
+            .get_all(&format!("{}/merge_requests/{pr}/commits?", self.project()))
+            .await
+            .with_context(|| format!("Cannot load commits of MR {pr}"))?;
+
+        // GitLab does not return signatures in the list of commits
+        let mut result = vec![];
+        for commit in commits {
+            let signature: Option<SignaturePayload> = self
+                .api
+                .get_optional(&format!(
+                    "{}/repository/commits/{}/signature",
+                    self.project(),
+                    commit.id
+                ))
+                .await?;
+            result.push(Commit {
+                sha: CommitSha(commit.id),
+                message: commit.message,
+                author_name: commit.author_name,
+                author_email: commit.author_email,
+                verified: signature.map_or(false, |signature| {
+                    signature.verification_status == "verified"
+                }),
+            });
+        }
+        Ok(result)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn is_commit_verified(&mut self, sha: &CommitSha) -> anyhow::Result<bool> {
+        #[derive(serde::Deserialize, Debug)]
+        struct SignaturePayload {
+            verification_status: String,
+        }
+
+        let signature: Option<SignaturePayload> = self
+            .api
+            .get_optional(&format!(
+                "{}/repository/commits/{sha}/signature",
+                self.project()
+            ))
+            .await?;
+        Ok(signature.map_or(false, |signature| {
+            signature.verification_status == "verified"
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn post_comment(
+        &mut self,
+        pr: PullRequestNumber,
+        text: &str,
+    ) -> anyhow::Result<CommentId> {
+        let note: NotePayload = self
+            .api
+            .call(
+                Method::POST,
+                &format!("{}/merge_requests/{pr}/notes", self.project()),
+                Some(json!({ "body": text })),
+            )
+            .await
+            .with_context(|| format!("Cannot post comment to MR {}!{pr}", self.repo_name))?;
+        Ok(CommentId(format!("{pr}/{}", note.id)))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn edit_comment(&mut self, comment: &CommentId, text: &str) -> anyhow::Result<()> {
+        let (pr, note) = parse_comment_id(comment)?;
+        self.api
+            .call::<serde_json::Value>(
+                Method::PUT,
+                &format!("{}/merge_requests/{pr}/notes/{note}", self.project()),
+                Some(json!({ "body": text })),
+            )
+            .await
+            .with_context(|| format!("Cannot edit comment {comment}"))?;
+        Ok(())
+    }
+
+    async fn minimize_comment(&mut self, comment: &CommentId) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "GitLab cannot hide comment {comment}, use `outdated_comments = \"edit\"` instead"
+        ))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn post_commit_comment(&mut self, sha: &CommitSha, text: &str) -> anyhow::Result<()> {
+        self.api
+            .call::<serde_json::Value>(
+                Method::POST,
+                &format!("{}/repository/commits/{sha}/comments", self.project()),
+                Some(json!({ "note": text })),
+            )
+            .await
+            .with_context(|| format!("Cannot post comment to commit {sha}"))?;
+        Ok(())
+    }
+
+    /// GitLab has no check runs, the result is reported as a commit status. Annotations are
+    /// appended to the description.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn create_check_run(
+        &mut self,
+        sha: &CommitSha,
+        name: &str,
+        success: bool,
+        summary: &str,
+        annotations: &[CheckAnnotation],
+    ) -> anyhow::Result<()> {
+        let mut description = summary.to_string();
+        for annotation in annotations {
+            description.push_str(&format!(
+                "\n{}:{}: {}",
+                annotation.path, annotation.line, annotation.message
+            ));
+        }
+        self.api
+            .call::<serde_json::Value>(
+                Method::POST,
+                &format!("{}/statuses/{sha}", self.project()),
+                Some(json!({
+                    "state": if success { "success" } else { "failed" },
+                    "name": name,
+                    "description": description,
+                })),
+            )
+            .await
+            .with_context(|| format!("Cannot set status {name} of commit {sha}"))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_file_content(
+        &mut self,
+        sha: &CommitSha,
+        path: &str,
+    ) -> anyhow::Result<Option<String>> {
+        fetch_file_content(&self.api, &self.repo_name, path, &sha.0).await
+    }
+
+    /// GitLab cannot move a branch to another commit, so the branch is deleted and created again.
+    /// This is fine for the branches used by bors, which are never updated by anyone else.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_branch_to_sha(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError> {
+        self.delete_branch(branch)
+            .await
+            .map_err(|error| BranchUpdateError::Custom(format!("{error:?}")))?;
+        let result = self
+            .api
+            .call::<serde_json::Value>(
+                Method::POST,
+                &format!(
+                    "{}/repository/branches?branch={}&ref={sha}",
+                    self.project(),
+                    encode(branch)
+                ),
+                None,
+            )
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => match error.downcast_ref::<GitlabApiError>() {
+                Some(GitlabApiError {
+                    status: StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED,
+                    ..
+                }) => Err(BranchUpdateError::PermissionDenied(branch.to_string())),
+                _ => Err(BranchUpdateError::Custom(format!("{error:?}"))),
+            },
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn delete_branch(&mut self, branch: &str) -> anyhow::Result<()> {
+        let (status, text) = self
+            .api
+            .send(
+                Method::DELETE,
+                &format!("{}/repository/branches/{}", self.project(), encode(branch)),
+                None,
+            )
+            .await?;
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            Ok(())
+        } else {
+            Err(GitlabApiError { status, text })
+                .with_context(|| format!("Cannot delete branch {branch}"))
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_branch_sha(&mut self, branch: &str) -> anyhow::Result<Option<CommitSha>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct CommitPayload {
+            id: String,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        struct BranchPayload {
+            commit: CommitPayload,
+        }
+
+        let branch: Option<BranchPayload> = self
+            .api
+            .get_optional(&format!(
+                "{}/repository/branches/{}",
+                self.project(),
+                encode(branch)
+            ))
+            .await
+            .with_context(|| format!("Cannot load branch {branch}"))?;
+        Ok(branch.map(|branch| CommitSha(branch.commit.id)))
+    }
+
+    /// GitLab cannot merge arbitrary commits, so the merge is computed by GitLab as the merge ref
+    /// of the open merge request whose head is `head`. It merges the MR into the current commit of
+    /// its target branch (which is what `base` is set to) and uses the default message of GitLab.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn merge_branches(
+        &mut self,
+        base: &str,
+        head: &CommitSha,
+        _commit_message: &str,
+    ) -> Result<CommitSha, MergeError> {
+        #[derive(serde::Deserialize, Debug)]
+        struct MergeRefPayload {
+            commit_id: String,
+        }
+
+        let mrs: Vec<MergeRequestPayload> = self
+            .api
+            .get_all(&format!("{}/merge_requests?state=opened", self.project()))
+            .await
+            .map_err(merge_error)?;
+        let Some(mr) = mrs.into_iter().find(|mr| mr.sha == head.0) else {
+            tracing::warn!("No open MR has head {head}, cannot merge it into {base}");
+            return Err(MergeError::NotFound);
+        };
+
+        let (status, text) = self
+            .api
+            .send(
+                Method::GET,
+                &format!("{}/merge_requests/{}/merge_ref", self.project(), mr.iid),
+                None,
+            )
+            .await
+            .map_err(merge_error)?;
+        match status {
+            _ if status.is_success() => {
+                let merge_ref: MergeRefPayload =
+                    serde_json::from_str(&text).map_err(|error| MergeError::Unknown {
+                        status,
+                        text: format!("Cannot parse merge ref: {error}"),
+                    })?;
+                Ok(CommitSha(merge_ref.commit_id))
+            }
+            // The MR cannot be merged, e.g. because of a conflict
+            StatusCode::BAD_REQUEST => Err(MergeError::Conflict),
+            StatusCode::NOT_FOUND => Err(MergeError::NotFound),
+            _ => Err(MergeError::Unknown { status, text }),
+        }
+    }
+
+    /// Each GitLab CI pipeline of the commit is a check suite.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_check_suites_for_commit(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> anyhow::Result<Vec<CheckSuite>> {
+        let pipelines: Vec<PipelinePayload> = self
+            .api
+            .get_all(&format!(
+                "{}/pipelines?sha={sha}&ref={}",
+                self.project(),
+                encode(branch)
+            ))
+            .await
+            .with_context(|| format!("Cannot load pipelines of commit {sha}"))?;
+        Ok(pipelines
+            .into_iter()
+            .map(|pipeline| CheckSuite {
+                status: pipeline_status(&pipeline.status),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_workflow_run_details(
+        &mut self,
+        run_id: RunId,
+    ) -> anyhow::Result<WorkflowRunDetails> {
+        let jobs = self.get_pipeline_jobs(run_id).await?;
+        let mut details = WorkflowRunDetails::default();
+        for job in jobs {
+            if job.status == "failed" {
+                details.failed_jobs.push(WorkflowLink {
+                    name: job.name.clone(),
+                    url: job.web_url.clone(),
+                });
+            }
+            if job.artifacts_file.is_some() {
+                details.artifacts.push(WorkflowLink {
+                    name: job.name,
+                    url: format!("{}/artifacts/browse", job.web_url),
+                });
+            }
+        }
+        Ok(details)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_failed_job_logs(
+        &mut self,
+        run_id: RunId,
+        max_size: usize,
+    ) -> anyhow::Result<Vec<JobLog>> {
+        let jobs = self.get_pipeline_jobs(run_id).await?;
+        let mut logs = vec![];
+        for job in jobs.into_iter().filter(|job| job.status == "failed") {
+            let (status, text) = self
+                .api
+                .send(
+                    Method::GET,
+                    &format!("{}/jobs/{}/trace", self.project(), job.id),
+                    None,
+                )
+                .await?;
+            if !status.is_success() {
+                tracing::warn!("Cannot load log of job {}: {status}", job.name);
+                continue;
+            }
+            logs.push(JobLog {
+                job_name: job.name,
+                excerpt: log_tail(&text, max_size).to_string(),
+            });
+        }
+        Ok(logs)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn cancel_workflows(&mut self, run_ids: Vec<RunId>) -> anyhow::Result<()> {
+        for run_id in run_ids {
+            self.api
+                .call::<serde_json::Value>(
+                    Method::POST,
+                    &format!("{}/pipelines/{run_id}/cancel", self.project()),
+                    None,
+                )
+                .await
+                .with_context(|| format!("Cannot cancel pipeline {run_id}"))?;
+        }
+        Ok(())
+    }
+
+    /// Pipelines cannot be selected by name, so a pipeline of `git_ref` is created and the
+    /// name of the workflow is passed to it in the `BORS_WORKFLOW` variable, together with the
+    /// inputs.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn dispatch_workflow(
+        &mut self,
+        workflow: &str,
+        git_ref: &str,
+        inputs: &HashMap<String, String>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        let mut variables = vec![json!({ "key": "BORS_WORKFLOW", "value": workflow })];
+        variables.extend(
+            inputs
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value })),
+        );
+        let pipeline: PipelinePayload = self
+            .api
+            .call(
+                Method::POST,
+                &format!("{}/pipeline", self.project()),
+                Some(json!({ "ref": git_ref, "variables": variables })),
+            )
+            .await
+            .with_context(|| format!("Cannot create pipeline for workflow {workflow}"))?;
+        Ok(DispatchedWorkflow {
+            run_id: RunId(pipeline.id),
+            url: pipeline.web_url,
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn add_labels(&mut self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()> {
+        if !labels.is_empty() {
+            self.update_merge_request(pr, json!({ "add_labels": labels.join(",") }))
+                .await
+                .context("Cannot add label(s) to MR")?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn remove_labels(
+        &mut self,
+        pr: PullRequestNumber,
+        labels: &[String],
+    ) -> anyhow::Result<()> {
+        if !labels.is_empty() {
+            self.update_merge_request(pr, json!({ "remove_labels": labels.join(",") }))
+                .await
+                .context("Cannot remove label(s) from MR")?;
+        }
+        Ok(())
+    }
+
+    async fn add_to_merge_queue(&mut self, _pr: PullRequestNumber) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "The GitHub merge queue cannot be used with GitLab"
+        ))
+    }
+
+    async fn remove_from_merge_queue(&mut self, _pr: PullRequestNumber) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "The GitHub merge queue cannot be used with GitLab"
+        ))
+    }
+
+    /// Only maintainers (including the bot) can push to the protected branch, and nobody can merge
+    /// MRs into it. GitLab has no required checks of protected branches.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn protect_branch(
+        &mut self,
+        branch: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()> {
+        if !protection.required_checks.is_empty() {
+            tracing::warn!("GitLab does not support required checks of branch {branch}");
+        }
+        // Existing protection cannot be changed, only replaced
+        let (status, text) = self
+            .api
+            .send(
+                Method::DELETE,
+                &format!("{}/protected_branches/{}", self.project(), encode(branch)),
+                None,
+            )
+            .await?;
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            return Err(GitlabApiError { status, text })
+                .with_context(|| format!("Cannot unprotect branch {branch}"));
+        }
+        self.api
+            .call::<serde_json::Value>(
+                Method::POST,
+                &format!("{}/protected_branches", self.project()),
+                Some(json!({
+                    "name": branch,
+                    "push_access_level": MAINTAINER_ACCESS_LEVEL,
+                    "merge_access_level": 0,
+                    "allow_force_push": protection.allow_force_pushes,
+                })),
+            )
+            .await
+            .with_context(|| format!("Cannot protect branch {branch}"))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_open_milestones(&mut self) -> anyhow::Result<Vec<Milestone>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct MilestonePayload {
+            id: u64,
+            title: String,
+            due_date: Option<NaiveDate>,
+        }
+
+        let milestones: Vec<MilestonePayload> = self
+            .api
+            .get_all(&format!("{}/milestones?state=active", self.project()))
+            .await
+            .context("Cannot load milestones")?;
+        // The ID (not the IID) is used to assign MRs to the milestone
+        Ok(milestones
+            .into_iter()
+            .map(|milestone| Milestone {
+                number: milestone.id,
+                title: milestone.title,
+                due_on: milestone
+                    .due_date
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|date| DateTime::from_utc(date, Utc)),
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_milestone(&mut self, pr: PullRequestNumber, milestone: u64) -> anyhow::Result<()> {
+        self.update_merge_request(pr, json!({ "milestone_id": milestone }))
+            .await
+            .with_context(|| format!("Cannot set milestone of MR {pr}"))
+    }
+}
+
+impl GitlabRepositoryClient {
+    async fn update_merge_request(
+        &self,
+        pr: PullRequestNumber,
+        body: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.api
+            .call::<serde_json::Value>(
+                Method::PUT,
+                &format!("{}/merge_requests/{pr}", self.project()),
+                Some(body),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_pipeline_jobs(&self, run_id: RunId) -> anyhow::Result<Vec<JobPayload>> {
+        self.api
+            .get_all(&format!(
+                "{}/pipelines/{run_id}/jobs?include_retried=false",
+                self.project()
+            ))
+            .await
+            .with_context(|| format!("Cannot load jobs of pipeline {run_id}"))
+    }
+}
+
+/// Loads the content of the file at `path` in the given branch or commit, or `None` if the file
+/// does not exist.
+pub async fn fetch_file_content(
+    api: &GitlabApi,
+    repo: &GithubRepoName,
+    path: &str,
+    git_ref: &str,
+) -> anyhow::Result<Option<String>> {
+    let (status, text) = api
+        .send(
+            Method::GET,
+            &format!(
+                "/projects/{}/repository/files/{}/raw?ref={}",
+                project_id(repo),
+                encode(path),
+                encode(git_ref)
+            ),
+            None,
+        )
+        .await?;
+    match status {
+        _ if status.is_success() => Ok(Some(text)),
+        StatusCode::NOT_FOUND => Ok(None),
+        _ => Err(GitlabApiError { status, text })
+            .with_context(|| format!("Cannot load file {path} of {repo}")),
+    }
+}
+
+/// Maps the status of a GitLab CI pipeline to the status of a check suite.
+pub fn pipeline_status(status: &str) -> CheckSuiteStatus {
+    match status {
+        "success" => CheckSuiteStatus::Success,
+        "failed" | "canceled" | "skipped" => CheckSuiteStatus::Failure,
+        _ => CheckSuiteStatus::Pending,
+    }
+}
+
+fn merge_error(error: anyhow::Error) -> MergeError {
+    match error.downcast::<GitlabApiError>() {
+        Ok(GitlabApiError { status, text }) => MergeError::Unknown { status, text },
+        Err(error) => MergeError::Unknown {
+            status: StatusCode::BAD_GATEWAY,
+            text: format!("{error:?}"),
+        },
+    }
+}
+
+/// Comments are identified by the IID of their MR and the ID of the note, because notes can only
+/// be edited through their MR.
+fn parse_comment_id(comment: &CommentId) -> anyhow::Result<(u64, u64)> {
+    comment
+        .0
+        .split_once('/')
+        .and_then(|(pr, note)| Some((pr.parse().ok()?, note.parse().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid GitLab comment ID {comment}"))
+}
+
+fn gitlab_mr_to_pr(repo: &GithubRepoName, mr: MergeRequestPayload) -> PullRequest {
+    // The owner of the head is only used to find out whether the MR comes from a fork
+    let head_owner = if mr.source_project_id == mr.target_project_id {
+        repo.owner().to_string()
+    } else {
+        mr.author.username.clone()
+    };
+    PullRequest {
+        number: mr.iid.into(),
+        head_label: format!("{head_owner}:{}", mr.source_branch),
+        head: Branch {
+            name: mr.source_branch,
+            sha: mr.sha.into(),
+        },
+        base: Branch {
+            name: mr.target_branch,
+            sha: mr
+                .diff_refs
+                .and_then(|refs| refs.base_sha)
+                .unwrap_or_default()
+                .into(),
+        },
+        title: mr.title,
+        message: mr.description.unwrap_or_default(),
+        author: mr.author.username,
+        draft: mr.draft,
+        open: mr.state == "opened",
+        merged: mr.state == "merged",
+        labels: mr.labels,
+        // GitLab does not return the number of changed lines
+        changed_lines: 0,
+        changed_files: mr
+            .changes_count
+            .and_then(|count| count.trim_end_matches('+').parse().ok())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::CommentId;
+    use crate::gitlab::client::{gitlab_mr_to_pr, parse_comment_id, MergeRequestPayload};
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn comment_ids() {
+        assert_eq!(
+            parse_comment_id(&CommentId("12/345".to_string())).unwrap(),
+            (12, 345)
+        );
+        assert!(parse_comment_id(&CommentId("MDEyOklzc3VlQ29tbWVudDE=".to_string())).is_err());
+    }
+
+    #[test]
+    fn merge_request_from_fork() {
+        let mr: MergeRequestPayload = serde_json::from_value(serde_json::json!({
+            "iid": 5,
+            "title": "Fix typo",
+            "description": null,
+            "author": { "username": "contributor" },
+            "draft": false,
+            "state": "opened",
+            "labels": ["docs"],
+            "source_branch": "typo",
+            "target_branch": "main",
+            "source_project_id": 2,
+            "target_project_id": 1,
+            "sha": "abc",
+            "diff_refs": { "base_sha": "def" },
+            "changes_count": "1000+",
+        }))
+        .unwrap();
+        let repo = default_repo_name();
+        let pr = gitlab_mr_to_pr(&repo, mr);
+        assert!(pr.is_from_fork(&repo));
+        assert_eq!(pr.base.sha.0, "def");
+        assert_eq!(pr.changed_files, 1000);
+        assert!(pr.open);
+    }
+}
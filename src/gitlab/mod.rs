@@ -0,0 +1,196 @@
+//! GitLab backend of the bot. Merge requests of the configured GitLab projects are handled in the
+//! same way as pull requests of GitHub repositories: commands are read from MR notes, builds run
+//! as GitLab CI pipelines of the bors branches (which the bot pushes to using the API) and
+//! permissions are derived from the membership in the project and its groups.
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Context;
+use secrecy::SecretString;
+
+use crate::bors::event::PullRequestComment;
+use crate::bors::shard::{RepoShard, ShardedState};
+use crate::bors::{BorsState, RepositoryState};
+use crate::config::{RepositoryConfig, CONFIG_FILE_PATH};
+use crate::database::{DbClient, SeaORMClient};
+use crate::github::GithubRepoName;
+use crate::gitlab::api::{parse_project_path, project_id, GitlabApi};
+use crate::gitlab::client::{fetch_file_content, GitlabRepositoryClient};
+use crate::gitlab::permissions::GitlabPermissionResolver;
+use crate::notifications::{create_notification_routes, NotificationCredentials};
+
+pub mod api;
+pub mod client;
+mod permissions;
+mod webhook;
+
+pub use webhook::{GitLabWebhook, GitlabForge};
+
+type GitlabRepositoryState = RepositoryState<GitlabRepositoryClient>;
+
+/// Provides access to the configured GitLab projects.
+pub struct GitlabState {
+    api: Arc<GitlabApi>,
+    /// Username of the bot user that owns the access token.
+    username: String,
+    /// Paths of the managed projects (`group/project`).
+    project_paths: Vec<String>,
+    repositories: HashMap<GithubRepoName, GitlabRepositoryState>,
+    db: SeaORMClient,
+    notification_credentials: NotificationCredentials,
+}
+
+impl GitlabState {
+    /// Loads the given projects from the GitLab instance at `url`, using the access token of the
+    /// bot user.
+    pub async fn load(
+        url: &str,
+        token: SecretString,
+        project_paths: Vec<String>,
+        db: SeaORMClient,
+        notification_credentials: NotificationCredentials,
+    ) -> anyhow::Result<Self> {
+        #[derive(serde::Deserialize, Debug)]
+        struct UserPayload {
+            username: String,
+        }
+
+        let api = Arc::new(GitlabApi::new(url, token));
+        let user: UserPayload = api
+            .get("/user")
+            .await
+            .context("Could not load the GitLab bot user")?;
+        let repositories = load_projects(&api, &project_paths, &notification_credentials).await?;
+        Ok(Self {
+            api,
+            username: user.username,
+            project_paths,
+            repositories,
+            db,
+            notification_credentials,
+        })
+    }
+}
+
+async fn load_projects(
+    api: &Arc<GitlabApi>,
+    project_paths: &[String],
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<HashMap<GithubRepoName, GitlabRepositoryState>> {
+    let mut repositories = HashMap::new();
+    for path in project_paths {
+        let repo_state = create_repo_state(api, path, notification_credentials)
+            .await
+            .with_context(|| format!("Cannot load GitLab project {path}"))?;
+        if let Some(repo_state) = repo_state {
+            tracing::info!("Loaded GitLab project {}", repo_state.repository);
+            repositories.insert(repo_state.repository.clone(), repo_state);
+        }
+    }
+    Ok(repositories)
+}
+
+async fn create_repo_state(
+    api: &Arc<GitlabApi>,
+    path: &str,
+    notification_credentials: &NotificationCredentials,
+) -> anyhow::Result<Option<GitlabRepositoryState>> {
+    #[derive(serde::Deserialize, Debug)]
+    struct ProjectPayload {
+        web_url: String,
+        default_branch: Option<String>,
+    }
+
+    let name = parse_project_path(path)?;
+    let project: ProjectPayload = api.get(&format!("/projects/{}", project_id(&name))).await?;
+    let default_branch = project.default_branch.as_deref().unwrap_or("main");
+
+    let Some(content) = fetch_file_content(api, &name, CONFIG_FILE_PATH, default_branch).await?
+    else {
+        tracing::warn!(
+            "Project {name} does not contain {CONFIG_FILE_PATH} in its default branch, ignoring it"
+        );
+        return Ok(None);
+    };
+    let config: RepositoryConfig = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(error) => {
+            tracing::warn!("Project {name} has an invalid configuration, ignoring it: {error}");
+            return Ok(None);
+        }
+    };
+
+    let notifications =
+        create_notification_routes(&name, &config.notifications, notification_credentials);
+    Ok(Some(RepositoryState {
+        repository: name.clone(),
+        client: GitlabRepositoryClient {
+            api: api.clone(),
+            repo_name: name.clone(),
+            web_url: project.web_url,
+        },
+        permissions_resolver: Box::new(GitlabPermissionResolver::new(api.clone(), name)),
+        // Contributor agreements are checked using GitHub usernames
+        agreement_checker: None,
+        notifications,
+        config,
+        deferred_updates: Default::default(),
+    }))
+}
+
+impl BorsState<GitlabRepositoryClient> for GitlabState {
+    fn is_comment_internal(&self, comment: &PullRequestComment) -> bool {
+        comment.author.username == self.username
+    }
+
+    fn get_repo_state_mut(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<(&mut GitlabRepositoryState, &mut dyn DbClient)> {
+        self.repositories
+            .get_mut(repo)
+            .map(|repo| (repo, (&mut self.db) as &mut dyn DbClient))
+    }
+
+    fn get_all_repos_mut(&mut self) -> (Vec<&mut GitlabRepositoryState>, &mut dyn DbClient) {
+        (
+            self.repositories.values_mut().collect(),
+            (&mut self.db) as &mut dyn DbClient,
+        )
+    }
+
+    /// Reloads the configuration of the projects.
+    fn reload_repositories(&mut self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
+        Box::pin(async move {
+            self.repositories = load_projects(
+                &self.api,
+                &self.project_paths,
+                &self.notification_credentials,
+            )
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+impl ShardedState<GitlabRepositoryClient> for GitlabState {
+    fn repository_names(&self) -> Vec<GithubRepoName> {
+        self.repositories.keys().cloned().collect()
+    }
+
+    fn take_repository(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<RepoShard<GitlabRepositoryClient>> {
+        self.repositories
+            .remove(repo)
+            .map(|repo| RepoShard::new(repo, Box::new(self.db.clone())))
+    }
+
+    fn return_repository(&mut self, shard: RepoShard<GitlabRepositoryClient>) {
+        self.repositories
+            .insert(shard.repository().clone(), shard.into_repository_state());
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::async_trait;
+use tokio::sync::Mutex;
+
+use crate::github::GithubRepoName;
+use crate::gitlab::api::{project_id, GitlabApi};
+use crate::permissions::{
+    diff_users, PermissionChange, PermissionResolver, PermissionType, CACHE_DURATION,
+};
+
+/// Minimal access level (Developer) required for starting try builds.
+const TRY_ACCESS_LEVEL: u32 = 30;
+/// Minimal access level (Maintainer) required for approving MRs.
+const REVIEW_ACCESS_LEVEL: u32 = 40;
+
+/// Resolves permissions from the members of a GitLab project, including the members of the groups
+/// that contain the project or that it is shared with. Developers can start try builds and
+/// maintainers can approve.
+pub struct GitlabPermissionResolver {
+    api: Arc<GitlabApi>,
+    repo: GithubRepoName,
+    /// Access levels by username, and when they were loaded.
+    members: Mutex<Option<(HashMap<String, u32>, SystemTime)>>,
+    /// Changes of permissions found by reloads of the members.
+    changes: std::sync::Mutex<Vec<PermissionChange>>,
+}
+
+impl GitlabPermissionResolver {
+    pub fn new(api: Arc<GitlabApi>, repo: GithubRepoName) -> Self {
+        Self {
+            api,
+            repo,
+            members: Mutex::new(None),
+            changes: Default::default(),
+        }
+    }
+
+    async fn load_members(&self) -> anyhow::Result<HashMap<String, u32>> {
+        #[derive(serde::Deserialize, Debug)]
+        struct MemberPayload {
+            username: String,
+            access_level: u32,
+        }
+
+        tracing::info!("Reloading members of project {}", self.repo);
+        let members: Vec<MemberPayload> = self
+            .api
+            .get_all(&format!(
+                "/projects/{}/members/all?state=active",
+                project_id(&self.repo)
+            ))
+            .await?;
+        Ok(members
+            .into_iter()
+            .map(|member| (member.username.to_lowercase(), member.access_level))
+            .collect())
+    }
+}
+
+/// Usernames of the members with at least the access level required for the permission.
+fn users_with(members: &HashMap<String, u32>, permission: PermissionType) -> HashSet<String> {
+    let required = required_level(permission);
+    members
+        .iter()
+        .filter(|(_, level)| **level >= required)
+        .map(|(username, _)| username.clone())
+        .collect()
+}
+
+fn required_level(permission: PermissionType) -> u32 {
+    match permission {
+        PermissionType::Review => REVIEW_ACCESS_LEVEL,
+        PermissionType::Try => TRY_ACCESS_LEVEL,
+    }
+}
+
+#[async_trait]
+impl PermissionResolver for GitlabPermissionResolver {
+    async fn has_permission(&self, username: &str, permission: PermissionType) -> bool {
+        let mut members = self.members.lock().await;
+        let stale = members.as_ref().map_or(true, |(_, loaded_at)| {
+            loaded_at
+                .elapsed()
+                .map_or(true, |elapsed| elapsed > CACHE_DURATION)
+        });
+        if stale {
+            match self.load_members().await {
+                Ok(loaded) => {
+                    // The first load is not a change
+                    if let Some((previous, _)) = members.as_ref() {
+                        let changes = [PermissionType::Review, PermissionType::Try]
+                            .into_iter()
+                            .filter_map(|permission| {
+                                diff_users(
+                                    permission,
+                                    &users_with(previous, permission),
+                                    &users_with(&loaded, permission),
+                                )
+                            });
+                        self.changes.lock().unwrap().extend(changes);
+                    }
+                    *members = Some((loaded, SystemTime::now()));
+                }
+                Err(error) => {
+                    tracing::error!("Cannot reload members of {}: {error:?}", self.repo);
+                }
+            }
+        }
+
+        let Some((members, _)) = members.as_ref() else {
+            return false;
+        };
+        members
+            .get(&username.to_lowercase())
+            .map_or(false, |level| *level >= required_level(permission))
+    }
+
+    fn take_changes(&self) -> Vec<PermissionChange> {
+        std::mem::take(&mut *self.changes.lock().unwrap())
+    }
+}
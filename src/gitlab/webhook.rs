@@ -0,0 +1,451 @@
+//! Parsing of GitLab webhooks (note, merge request, pipeline and push events) into
+//! [`BorsEvent`]s.
+use std::fmt::Debug;
+
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::{HeaderMap, Request, StatusCode};
+use octocrab::models::RunId;
+use url::Url;
+
+use crate::bors::event::{
+    BorsEvent, BranchRewrite, BranchRewritten, ConfigChanged, PullRequestChanged,
+    PullRequestClosed, PullRequestComment, PullRequestLabelChanged, PullRequestPushed,
+    ReviewDismissed, WorkflowCompleted, WorkflowStarted,
+};
+use crate::config::CONFIG_FILE_PATH;
+use crate::database::{WorkflowStatus, WorkflowType};
+use crate::forge::{constant_time_eq, extract_webhook, header, Forge};
+use crate::github::server::ServerStateRef;
+use crate::github::{CommitSha, GithubUser, PullRequestNumber, WebhookSecret};
+use crate::gitlab::api::parse_project_path;
+
+/// SHA used by GitLab for the missing side of a created or deleted branch.
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// axum extractor for GitLab webhook events.
+#[derive(Debug)]
+pub struct GitLabWebhook(pub BorsEvent);
+
+#[async_trait]
+impl<B> FromRequest<ServerStateRef, B> for GitLabWebhook
+where
+    B: HttpBody + Send + Debug + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(
+        request: Request<B>,
+        state: &ServerStateRef,
+    ) -> Result<Self, Self::Rejection> {
+        extract_webhook(&GitlabForge, request, state)
+            .await
+            .map(GitLabWebhook)
+    }
+}
+
+/// Webhooks of GitLab projects. GitLab does not sign webhooks, it sends the secret token
+/// configured in the webhook in the `X-Gitlab-Token` header.
+pub struct GitlabForge;
+
+impl Forge for GitlabForge {
+    fn name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, _body: &[u8], secret: &WebhookSecret) -> bool {
+        header(headers, "x-gitlab-token")
+            .map_or(false, |token| constant_time_eq(token, secret.expose()))
+    }
+
+    fn event_type<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-gitlab-event")
+    }
+
+    fn delivery_id<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        header(headers, "x-gitlab-event-uuid")
+    }
+
+    fn parse_webhook(&self, event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+        parse_gitlab_event(event_type, body)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ProjectPayload {
+    path_with_namespace: String,
+    web_url: String,
+    default_branch: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UserPayload {
+    username: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NoteAttributes {
+    note: String,
+    noteable_type: String,
+    #[serde(default)]
+    system: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NoteMergeRequest {
+    iid: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NoteEvent {
+    object_attributes: NoteAttributes,
+    user: UserPayload,
+    project: ProjectPayload,
+    merge_request: Option<NoteMergeRequest>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct LastCommit {
+    id: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct MergeRequestAttributes {
+    iid: u64,
+    action: Option<String>,
+    target_branch: String,
+    last_commit: LastCommit,
+    /// Set if new commits have been pushed by the update.
+    oldrev: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct LabelPayload {
+    title: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Change<T> {
+    previous: T,
+    current: T,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct MergeRequestChanges {
+    labels: Option<Change<Vec<LabelPayload>>>,
+    draft: Option<Change<bool>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct MergeRequestEvent {
+    object_attributes: MergeRequestAttributes,
+    user: UserPayload,
+    project: ProjectPayload,
+    #[serde(default)]
+    changes: MergeRequestChanges,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PipelineAttributes {
+    id: u64,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    sha: String,
+    status: String,
+    /// Is the pipeline running for a tag (instead of a branch)?
+    #[serde(default)]
+    tag: bool,
+    name: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PipelineEvent {
+    object_attributes: PipelineAttributes,
+    project: ProjectPayload,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PushCommit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    before: String,
+    after: String,
+    project: ProjectPayload,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+}
+
+/// Parses the payload of a webhook with the given type (the `X-Gitlab-Event` header).
+/// Returns `None` if the webhook is not interesting for the bot.
+pub fn parse_gitlab_event(event_type: &str, body: &[u8]) -> anyhow::Result<Option<BorsEvent>> {
+    match event_type {
+        "Note Hook" => {
+            let payload: NoteEvent = serde_json::from_slice(body)?;
+            if payload.object_attributes.noteable_type != "MergeRequest"
+                || payload.object_attributes.system
+            {
+                return Ok(None);
+            }
+            let Some(mr) = payload.merge_request else {
+                return Err(anyhow::anyhow!("Merge request is missing in note event"));
+            };
+            Ok(Some(BorsEvent::Comment(PullRequestComment {
+                repository: parse_project_path(&payload.project.path_with_namespace)?,
+                author: parse_user(&payload.project, payload.user)?,
+                pr_number: PullRequestNumber(mr.iid),
+                text: payload.object_attributes.note,
+            })))
+        }
+        "Merge Request Hook" => {
+            let payload: MergeRequestEvent = serde_json::from_slice(body)?;
+            parse_merge_request_event(payload)
+        }
+        "Pipeline Hook" => {
+            let payload: PipelineEvent = serde_json::from_slice(body)?;
+            let pipeline = payload.object_attributes;
+            if pipeline.tag {
+                return Ok(None);
+            }
+            let repository = parse_project_path(&payload.project.path_with_namespace)?;
+            let result = match pipeline.status.as_str() {
+                "pending" => Some(BorsEvent::WorkflowStarted(WorkflowStarted {
+                    repository,
+                    name: pipeline.name.unwrap_or_else(|| "pipeline".to_string()),
+                    branch: pipeline.git_ref,
+                    commit_sha: CommitSha(pipeline.sha),
+                    run_id: RunId(pipeline.id),
+                    workflow_type: WorkflowType::Github,
+                    url: format!("{}/-/pipelines/{}", payload.project.web_url, pipeline.id),
+                })),
+                "success" | "failed" | "canceled" | "skipped" => {
+                    Some(BorsEvent::WorkflowCompleted(WorkflowCompleted {
+                        repository,
+                        branch: pipeline.git_ref,
+                        commit_sha: CommitSha(pipeline.sha),
+                        run_id: RunId(pipeline.id),
+                        status: match pipeline.status.as_str() {
+                            "success" => WorkflowStatus::Success,
+                            _ => WorkflowStatus::Failure,
+                        },
+                    }))
+                }
+                _ => None,
+            };
+            Ok(result)
+        }
+        "Push Hook" => {
+            let payload: PushEvent = serde_json::from_slice(body)?;
+            let Some(branch) = payload.git_ref.strip_prefix("refs/heads/") else {
+                return Ok(None);
+            };
+            let repository = parse_project_path(&payload.project.path_with_namespace)?;
+            let default_branch = payload.project.default_branch.as_deref() == Some(branch);
+            // GitLab does not tell whether a push was forced, so only deletions are reported
+            if payload.after == NULL_SHA {
+                return Ok(Some(BorsEvent::BranchRewritten(BranchRewritten {
+                    repository,
+                    branch: branch.to_string(),
+                    default_branch,
+                    rewrite: BranchRewrite::Deleted {
+                        before: CommitSha(payload.before),
+                    },
+                })));
+            }
+            let config_changed = payload.commits.iter().any(|commit| {
+                commit
+                    .added
+                    .iter()
+                    .chain(&commit.modified)
+                    .chain(&commit.removed)
+                    .any(|path| path == CONFIG_FILE_PATH)
+            });
+            if default_branch && config_changed {
+                return Ok(Some(BorsEvent::ConfigChanged(ConfigChanged {
+                    repository,
+                    commit_sha: CommitSha(payload.after),
+                })));
+            }
+            Ok(None)
+        }
+        _ => {
+            tracing::debug!("Ignoring unknown event type {event_type:?}");
+            Ok(None)
+        }
+    }
+}
+
+/// Only one event is created from an update of a MR. If several labels have changed at once, only
+/// the first added (or removed) label is reported.
+fn parse_merge_request_event(payload: MergeRequestEvent) -> anyhow::Result<Option<BorsEvent>> {
+    let repository = parse_project_path(&payload.project.path_with_namespace)?;
+    let mr = payload.object_attributes;
+    let pr_number = PullRequestNumber(mr.iid);
+    let pushed = || {
+        BorsEvent::PullRequestPushed(PullRequestPushed {
+            repository: repository.clone(),
+            pr_number,
+            head_sha: CommitSha(mr.last_commit.id.clone()),
+        })
+    };
+    let result = match mr.action.as_deref() {
+        Some("open" | "reopen") => Some(pushed()),
+        Some("close" | "merge") => Some(BorsEvent::PullRequestClosed(PullRequestClosed {
+            repository: repository.clone(),
+            pr_number,
+            merged: mr.action.as_deref() == Some("merge"),
+            base_branch: mr.target_branch.clone(),
+        })),
+        Some("approved") => Some(BorsEvent::ReviewApproved(PullRequestComment {
+            repository: repository.clone(),
+            author: parse_user(&payload.project, payload.user)?,
+            pr_number,
+            text: String::new(),
+        })),
+        Some("unapproved") => Some(BorsEvent::ReviewDismissed(ReviewDismissed {
+            repository: repository.clone(),
+            pr_number,
+            reviewer: parse_user(&payload.project, payload.user)?,
+        })),
+        Some("update") if mr.oldrev.is_some() => Some(pushed()),
+        Some("update") => {
+            let changes = payload.changes;
+            if let Some(Change {
+                previous: false,
+                current: true,
+            }) = changes.draft
+            {
+                Some(BorsEvent::PullRequestConvertedToDraft(PullRequestChanged {
+                    repository: repository.clone(),
+                    pr_number,
+                }))
+            } else if let Some(labels) = changes.labels {
+                let has = |labels: &[LabelPayload], title: &str| {
+                    labels.iter().any(|label| label.title == title)
+                };
+                let added = labels
+                    .current
+                    .iter()
+                    .find(|label| !has(&labels.previous, &label.title));
+                let removed = labels
+                    .previous
+                    .iter()
+                    .find(|label| !has(&labels.current, &label.title));
+                let event = |label: &LabelPayload| PullRequestLabelChanged {
+                    repository: repository.clone(),
+                    pr_number,
+                    label: label.title.clone(),
+                };
+                match (added, removed) {
+                    (Some(label), _) => Some(BorsEvent::PullRequestLabeled(event(label))),
+                    (None, Some(label)) => Some(BorsEvent::PullRequestUnlabeled(event(label))),
+                    (None, None) => None,
+                }
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    Ok(result)
+}
+
+/// GitLab does not send the profile URL of users, it is derived from the URL of the project.
+fn parse_user(project: &ProjectPayload, user: UserPayload) -> anyhow::Result<GithubUser> {
+    let mut html_url = Url::parse(&project.web_url)?;
+    html_url.set_path(&user.username);
+    Ok(GithubUser {
+        username: user.username,
+        html_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+
+    use crate::bors::event::{BorsEvent, BranchRewrite};
+    use crate::database::WorkflowStatus;
+    use crate::forge::Forge;
+    use crate::github::WebhookSecret;
+    use crate::gitlab::webhook::{parse_gitlab_event, GitlabForge};
+    use crate::tests::io::load_test_file;
+
+    fn parse(file: &str, event: &str) -> Option<BorsEvent> {
+        parse_gitlab_event(event, load_test_file(file).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn verify_token() {
+        let secret = WebhookSecret::new("ABCDEF".to_string());
+        let mut headers = HeaderMap::new();
+        assert!(!GitlabForge.verify_webhook(&headers, b"", &secret));
+        headers.insert("x-gitlab-token", "ABCDEG".parse().unwrap());
+        assert!(!GitlabForge.verify_webhook(&headers, b"", &secret));
+        headers.insert("x-gitlab-token", "ABCDEF".parse().unwrap());
+        assert!(GitlabForge.verify_webhook(&headers, b"", &secret));
+    }
+
+    #[test]
+    fn merge_request_note() {
+        let Some(BorsEvent::Comment(comment)) =
+            parse("gitlab/note-merge-request.json", "Note Hook")
+        else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.repository.path(), "group/subgroup/project");
+        assert_eq!(comment.author.username, "reviewer");
+        assert_eq!(
+            comment.author.html_url.as_str(),
+            "https://gitlab.example.com/reviewer"
+        );
+        assert_eq!(comment.pr_number.0, 7);
+        assert_eq!(comment.text, "@bors r+");
+    }
+
+    #[test]
+    fn merge_request_labeled() {
+        let Some(BorsEvent::PullRequestLabeled(event)) =
+            parse("gitlab/merge-request-labeled.json", "Merge Request Hook")
+        else {
+            panic!("Expected a label event");
+        };
+        assert_eq!(event.label, "S-waiting-on-review");
+        assert_eq!(event.pr_number.0, 7);
+    }
+
+    #[test]
+    fn pipeline_events() {
+        let Some(BorsEvent::WorkflowCompleted(workflow)) =
+            parse("gitlab/pipeline-failed.json", "Pipeline Hook")
+        else {
+            panic!("Expected a completed workflow");
+        };
+        assert_eq!(workflow.branch, "automation/bors/try");
+        assert_eq!(workflow.run_id.0, 1234);
+        assert_eq!(workflow.status, WorkflowStatus::Failure);
+    }
+
+    #[test]
+    fn branch_deleted() {
+        let Some(BorsEvent::BranchRewritten(event)) =
+            parse("gitlab/push-deleted.json", "Push Hook")
+        else {
+            panic!("Expected a branch rewrite");
+        };
+        assert_eq!(event.branch, "automation/bors/try");
+        assert!(matches!(event.rewrite, BranchRewrite::Deleted { .. }));
+    }
+}
@@ -0,0 +1,223 @@
+//! Liveness of the bot. The bot records when it has last processed an event, called the GitHub
+//! API, and scheduled and processed the periodic refresh, so that a silent deadlock of one of its
+//! async loops can be detected (and reported by a watchdog and at `/health`) instead of the bot
+//! just quietly not doing anything.
+//!
+//! Repositories are handled by separate shards of the event loop, so the refresh is also tracked
+//! for each repository: a single stuck shard is detected even if the other ones keep working.
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+/// How long can a loop go without progress before it is considered to be stalled.
+/// The periodic refresh is scheduled every two minutes, so this allows several refreshes to be
+/// slow before an alert is raised.
+pub const STALL_TIMEOUT_SECS: i64 = 600;
+
+/// Heartbeats of the bot process.
+pub static HEARTBEATS: Heartbeats = Heartbeats::new();
+
+/// Records the heartbeat in [`HEARTBEATS`].
+pub fn heartbeat(kind: Heartbeat) {
+    HEARTBEATS.record(kind, Utc::now());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heartbeat {
+    /// The bot has started.
+    Started,
+    /// A webhook (or another event, e.g. a dashboard command) has been processed successfully.
+    EventProcessed,
+    /// A GitHub API call has been performed.
+    GithubApiCall,
+    /// The periodic refresh has been sent to the event loop.
+    RefreshScheduled,
+    /// The periodic refresh has been processed by the event loop.
+    RefreshProcessed,
+}
+
+/// Times of the last heartbeats, as Unix timestamps (`0` if there was no heartbeat yet).
+pub struct Heartbeats {
+    started: AtomicI64,
+    event_processed: AtomicI64,
+    github_api_call: AtomicI64,
+    refresh_scheduled: AtomicI64,
+    refresh_processed: AtomicI64,
+    /// Repositories that have not processed a refresh yet, with the time when the oldest of their
+    /// refreshes has been sent to their shard.
+    pending_refreshes: Mutex<BTreeMap<String, DateTime<Utc>>>,
+}
+
+impl Default for Heartbeats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heartbeats {
+    pub const fn new() -> Self {
+        Self {
+            started: AtomicI64::new(0),
+            event_processed: AtomicI64::new(0),
+            github_api_call: AtomicI64::new(0),
+            refresh_scheduled: AtomicI64::new(0),
+            refresh_processed: AtomicI64::new(0),
+            pending_refreshes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, kind: Heartbeat, time: DateTime<Utc>) {
+        self.slot(kind).store(time.timestamp(), Ordering::Relaxed);
+    }
+
+    /// Records that a refresh has been sent to the shard of the repository.
+    pub fn record_refresh_sent(&self, repository: &str, time: DateTime<Utc>) {
+        self.pending_refreshes
+            .lock()
+            .unwrap()
+            .entry(repository.to_string())
+            .or_insert(time);
+    }
+
+    /// Records that the shard of the repository has processed its refreshes, or that the
+    /// repository is no longer managed by the bot.
+    pub fn record_refresh_done(&self, repository: &str) {
+        self.pending_refreshes.lock().unwrap().remove(repository);
+    }
+
+    pub fn last(&self, kind: Heartbeat) -> Option<DateTime<Utc>> {
+        match self.slot(kind).load(Ordering::Relaxed) {
+            0 => None,
+            timestamp => NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                .map(|time| DateTime::from_utc(time, Utc)),
+        }
+    }
+
+    /// Checks whether the loops of the bot are making progress.
+    ///
+    /// The refresh scheduler is stalled if it has not scheduled a refresh for too long, and the
+    /// event loop is stalled if it has not processed a refresh for too long after one has been
+    /// scheduled. The shard of a repository is stalled if it has not processed a refresh for too
+    /// long after it has been sent to it.
+    pub fn check(&self, now: DateTime<Utc>) -> HealthReport {
+        let timeout = Duration::seconds(STALL_TIMEOUT_SECS);
+        let started = self.last(Heartbeat::Started).unwrap_or(now);
+        let scheduled = self.last(Heartbeat::RefreshScheduled).unwrap_or(started);
+        let processed = self.last(Heartbeat::RefreshProcessed).unwrap_or(started);
+
+        let mut stalled = vec![];
+        if now - scheduled > timeout {
+            stalled.push("scheduler");
+        }
+        if scheduled - processed > timeout {
+            stalled.push("event_loop");
+        }
+        let stalled_repositories: Vec<String> = self
+            .pending_refreshes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, sent)| now - **sent > timeout)
+            .map(|(repository, _)| repository.clone())
+            .collect();
+        if !stalled_repositories.is_empty() {
+            stalled.push("shards");
+        }
+        HealthReport {
+            healthy: stalled.is_empty(),
+            stalled,
+            stalled_repositories,
+            started_at: self.last(Heartbeat::Started),
+            last_event_processed_at: self.last(Heartbeat::EventProcessed),
+            last_github_api_call_at: self.last(Heartbeat::GithubApiCall),
+            last_refresh_scheduled_at: self.last(Heartbeat::RefreshScheduled),
+            last_refresh_processed_at: self.last(Heartbeat::RefreshProcessed),
+        }
+    }
+
+    fn slot(&self, kind: Heartbeat) -> &AtomicI64 {
+        match kind {
+            Heartbeat::Started => &self.started,
+            Heartbeat::EventProcessed => &self.event_processed,
+            Heartbeat::GithubApiCall => &self.github_api_call,
+            Heartbeat::RefreshScheduled => &self.refresh_scheduled,
+            Heartbeat::RefreshProcessed => &self.refresh_processed,
+        }
+    }
+}
+
+/// Liveness of the bot, returned by `/health`.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct HealthReport {
+    pub healthy: bool,
+    /// Loops that have stopped making progress (`scheduler`, `event_loop` or `shards`).
+    pub stalled: Vec<&'static str>,
+    /// Repositories whose shard has stopped making progress.
+    pub stalled_repositories: Vec<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub last_event_processed_at: Option<DateTime<Utc>>,
+    pub last_github_api_call_at: Option<DateTime<Utc>>,
+    pub last_refresh_scheduled_at: Option<DateTime<Utc>>,
+    pub last_refresh_processed_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::health::{Heartbeat, Heartbeats};
+
+    #[test]
+    fn detect_stalled_loops() {
+        let heartbeats = Heartbeats::new();
+        let start = Utc::now();
+        heartbeats.record(Heartbeat::Started, start);
+        assert!(heartbeats.check(start).healthy);
+
+        // Refreshes are scheduled and processed
+        for minutes in [2, 4, 6] {
+            let time = start + Duration::minutes(minutes);
+            heartbeats.record(Heartbeat::RefreshScheduled, time);
+            heartbeats.record(Heartbeat::RefreshProcessed, time);
+        }
+        assert!(heartbeats.check(start + Duration::minutes(7)).healthy);
+
+        // Refreshes are scheduled, but the event loop does not process them
+        heartbeats.record(Heartbeat::RefreshScheduled, start + Duration::minutes(20));
+        let report = heartbeats.check(start + Duration::minutes(20));
+        assert_eq!(report.stalled, ["event_loop"]);
+
+        // Nothing is scheduled anymore
+        let report = heartbeats.check(start + Duration::minutes(40));
+        assert_eq!(report.stalled, ["scheduler", "event_loop"]);
+        assert!(!report.healthy);
+    }
+
+    #[test]
+    fn detect_stalled_shards() {
+        let heartbeats = Heartbeats::new();
+        let start = Utc::now();
+        heartbeats.record(Heartbeat::Started, start);
+        heartbeats.record(Heartbeat::RefreshScheduled, start);
+        heartbeats.record(Heartbeat::RefreshProcessed, start);
+        heartbeats.record_refresh_sent("owner/fast", start);
+        heartbeats.record_refresh_sent("owner/stuck", start);
+        heartbeats.record_refresh_done("owner/fast");
+
+        // Other shards keep processing refreshes
+        let time = start + Duration::minutes(20);
+        heartbeats.record(Heartbeat::RefreshScheduled, time);
+        heartbeats.record(Heartbeat::RefreshProcessed, time);
+        heartbeats.record_refresh_sent("owner/fast", time);
+        heartbeats.record_refresh_sent("owner/stuck", time);
+        heartbeats.record_refresh_done("owner/fast");
+        let report = heartbeats.check(time);
+        assert_eq!(report.stalled, ["shards"]);
+        assert_eq!(report.stalled_repositories, ["owner/stuck"]);
+
+        heartbeats.record_refresh_done("owner/stuck");
+        assert!(heartbeats.check(time).healthy);
+    }
+}
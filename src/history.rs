@@ -0,0 +1,324 @@
+//! History of past builds of a repository or of a single PR, used e.g. for post-mortems of failed
+//! builds and for finding flaky checks.
+//!
+//! Builds are not linked to PRs after a new try build replaces them, so the PR and the approver of
+//! each build are reconstructed from the event log.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+
+use crate::dashboard::{build_status_name, escape_html};
+use crate::database::{BuildStatus, DbClient, LoggedEvent, PrimaryKey, WorkflowStatus};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+/// How many builds are shown on a single page of the history.
+pub const PAGE_SIZE: u64 = 50;
+
+/// A past (or running) build shown in the history.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub id: PrimaryKey,
+    pub pr: Option<u64>,
+    pub branch: String,
+    pub commit_sha: String,
+    pub status: &'static str,
+    /// Who has approved the PR when the build was started, if it was approved.
+    pub approved_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// How long did the build run, from its start to the end of its last workflow.
+    pub duration_secs: Option<i64>,
+    /// Names of workflows of the build that have failed.
+    pub failed_checks: Vec<String>,
+}
+
+/// A page of the build history, the newest builds first.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct HistoryPage {
+    pub page: u64,
+    pub has_next: bool,
+    pub builds: Vec<HistoryEntry>,
+}
+
+/// The PR of a build and the approval of the PR when the build was started.
+struct BuildOrigin {
+    pr: Option<PullRequestNumber>,
+    approved_by: Option<String>,
+}
+
+/// Loads the given page (starting at 1) of the build history of the repository, or only of the
+/// builds of the given PR.
+pub async fn load_history(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+    pr: Option<PullRequestNumber>,
+    page: u64,
+) -> anyhow::Result<HistoryPage> {
+    // Pages beyond the history are empty, the limit only keeps the offset from overflowing
+    let page = page.clamp(1, u32::MAX as u64);
+    let origins = build_origins(db, repo, pr).await?;
+    let commits: Option<Vec<String>> = pr.map(|_| origins.keys().cloned().collect());
+    // One more build is loaded to find out whether there is a next page
+    let mut builds = db
+        .get_builds(
+            repo,
+            commits.as_deref(),
+            (page - 1) * PAGE_SIZE,
+            PAGE_SIZE + 1,
+        )
+        .await?;
+    let has_next = builds.len() as u64 > PAGE_SIZE;
+    builds.truncate(PAGE_SIZE as usize);
+
+    let mut entries = Vec::with_capacity(builds.len());
+    for build in builds {
+        let workflows = db.get_workflows_for_build(&build).await?;
+        let duration_secs = if build.status == BuildStatus::Pending {
+            None
+        } else {
+            workflows
+                .iter()
+                .filter_map(|workflow| workflow.finished_at)
+                .max()
+                .map(|finished_at| (finished_at - build.created_at).num_seconds())
+        };
+        let failed_checks = workflows
+            .iter()
+            .filter(|workflow| workflow.status == WorkflowStatus::Failure)
+            .map(|workflow| workflow.name.clone())
+            .collect();
+        let origin = origins.get(&build.commit_sha);
+        entries.push(HistoryEntry {
+            id: build.id,
+            pr: origin.and_then(|origin| origin.pr).map(|pr| pr.0),
+            status: build_status_name(&build.status),
+            approved_by: origin.and_then(|origin| origin.approved_by.clone()),
+            branch: build.branch,
+            commit_sha: build.commit_sha,
+            created_at: build.created_at,
+            duration_secs,
+            failed_checks,
+        });
+    }
+    Ok(HistoryPage {
+        page,
+        has_next,
+        builds: entries,
+    })
+}
+
+/// Finds the PR and the approver of builds (identified by their commit) from the approvals and
+/// started builds in the event log.
+async fn build_origins(
+    db: &(dyn DbClient + Send + Sync),
+    repo: &GithubRepoName,
+    pr: Option<PullRequestNumber>,
+) -> anyhow::Result<HashMap<String, BuildOrigin>> {
+    let mut approvals: HashMap<Option<u64>, String> = HashMap::new();
+    let mut origins = HashMap::new();
+    let kinds = ["approved", "unapproved", "build_started"];
+    for event in db.get_events_of_kinds(repo, pr, &kinds).await? {
+        let number = event.pr.map(|pr| pr.0);
+        match event.event {
+            LoggedEvent::Approved { approver } => {
+                approvals.insert(number, approver);
+            }
+            LoggedEvent::Unapproved => {
+                approvals.remove(&number);
+            }
+            LoggedEvent::BuildStarted { commit_sha, .. } => {
+                origins.insert(
+                    commit_sha,
+                    BuildOrigin {
+                        pr: event.pr,
+                        approved_by: approvals.get(&number).cloned(),
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(origins)
+}
+
+/// Renders an HTML page with the build history. `path` is the path of the page, used for links
+/// to the other pages.
+pub fn render_history_page(
+    repo: &GithubRepoName,
+    pr: Option<PullRequestNumber>,
+    history: &HistoryPage,
+    path: &str,
+) -> String {
+    let repo_name = escape_html(&repo.to_string());
+    let path = escape_html(path);
+    let title = match pr {
+        Some(pr) => format!("Builds of {repo_name}#{pr}"),
+        None => format!("Builds of {repo_name}"),
+    };
+    let mut rows = String::new();
+    for build in &history.builds {
+        let pr = build
+            .pr
+            .map(|pr| format!(r#"<a href="https://github.com/{repo_name}/pull/{pr}">#{pr}</a>"#))
+            .unwrap_or_default();
+        let _ = writeln!(
+            rows,
+            r#"<tr class="{status}"><td>{id}</td><td>{pr}</td><td><code>{sha}</code></td><td>{status}</td><td>{approver}</td><td>{created_at}</td><td>{duration}</td><td>{failed}</td></tr>"#,
+            status = build.status,
+            id = build.id,
+            sha = escape_html(&build.commit_sha),
+            approver = escape_html(build.approved_by.as_deref().unwrap_or("")),
+            created_at = build.created_at.format("%Y-%m-%d %H:%M UTC"),
+            duration = build
+                .duration_secs
+                .map(|secs| format!("{}m {}s", secs / 60, secs % 60))
+                .unwrap_or_default(),
+            failed = escape_html(&build.failed_checks.join(", ")),
+        );
+    }
+
+    let mut navigation = String::new();
+    if history.page > 1 {
+        let _ = write!(
+            navigation,
+            r#"<a href="{path}?page={}">Newer</a> "#,
+            history.page - 1
+        );
+    }
+    if history.has_next {
+        let _ = write!(
+            navigation,
+            r#"<a href="{path}?page={}">Older</a>"#,
+            history.page + 1
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}
+tr.failure {{ background: #fde2e1; }}
+tr.pending {{ background: #fff3c4; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<table>
+<tr><th>Build</th><th>PR</th><th>Commit</th><th>Status</th><th>Approved by</th><th>Started at</th><th>Duration</th><th>Failed checks</th></tr>
+{rows}</table>
+<p>{navigation}</p>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use octocrab::models::RunId;
+
+    use crate::database::{BuildStatus, DbClient, TryBuildArgs, WorkflowStatus, WorkflowType};
+    use crate::github::{CommitSha, PullRequestNumber};
+    use crate::history::{load_history, render_history_page};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn history_of_pull_request() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        db.approve(&pr, "foo", None).await.unwrap();
+        for sha in ["sha1", "sha2"] {
+            let pr = db
+                .get_or_create_pull_request(&repo, PullRequestNumber(1))
+                .await
+                .unwrap();
+            db.attach_try_build(
+                pr,
+                "automation/bors/try".to_string(),
+                CommitSha(sha.to_string()),
+                0,
+                TryBuildArgs::default(),
+            )
+            .await
+            .unwrap();
+        }
+        let other = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap();
+        db.attach_try_build(
+            other,
+            "automation/bors/try".to_string(),
+            CommitSha("sha3".to_string()),
+            0,
+            TryBuildArgs::default(),
+        )
+        .await
+        .unwrap();
+
+        let build = db
+            .find_build(
+                &repo,
+                "automation/bors/try".to_string(),
+                CommitSha("sha1".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        db.create_workflow(
+            &build,
+            "CI".to_string(),
+            "https://ci".to_string(),
+            RunId(5),
+            WorkflowType::Github,
+            WorkflowStatus::Pending,
+        )
+        .await
+        .unwrap();
+        db.update_workflow_status(5, WorkflowStatus::Failure)
+            .await
+            .unwrap();
+        db.update_build_status(&build, BuildStatus::Failure)
+            .await
+            .unwrap();
+
+        let history = load_history(&db, &repo, Some(PullRequestNumber(1)), 1)
+            .await
+            .unwrap();
+        assert!(!history.has_next);
+        let builds: Vec<(&str, &str)> = history
+            .builds
+            .iter()
+            .map(|build| (build.commit_sha.as_str(), build.status))
+            .collect();
+        assert_eq!(builds, vec![("sha2", "pending"), ("sha1", "failure")]);
+        assert_eq!(history.builds[1].pr, Some(1));
+        assert_eq!(history.builds[1].approved_by.as_deref(), Some("foo"));
+        assert_eq!(history.builds[1].failed_checks, vec!["CI".to_string()]);
+        assert!(history.builds[1].duration_secs.is_some());
+
+        let history = load_history(&db, &repo, None, 1).await.unwrap();
+        assert_eq!(history.builds.len(), 3);
+        assert_eq!(history.builds[0].pr, Some(2));
+        assert_eq!(history.builds[0].approved_by, None);
+
+        let page = render_history_page(&repo, None, &history, "/history/owner/name");
+        assert!(page.contains("<title>Builds of owner/name</title>"));
+        assert!(!page.contains("Older"));
+
+        let history = load_history(&db, &repo, None, u64::MAX).await.unwrap();
+        assert!(history.builds.is_empty());
+        assert!(!history.has_next);
+    }
+}
@@ -0,0 +1,193 @@
+//! Import of the state of a homu instance (approvals, priorities, rollup modes and delegations of
+//! open PRs), so that a deployment can switch from homu to bors without losing its queue.
+//!
+//! The state is read from the SQLite database of homu, whose `pull` table contains a row for each
+//! open PR of the repositories managed by homu. Repositories are identified by their labels from
+//! the homu configuration, which have to be mapped to GitHub repositories.
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sea_orm::{ConnectionTrait, DatabaseConnection, Statement};
+
+use crate::database::{DbClient, RollupMode};
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+/// The state of an open PR stored by homu.
+#[derive(Debug, PartialEq)]
+pub struct HomuPull {
+    /// Label of the repository in the homu configuration.
+    pub repo: String,
+    pub number: u64,
+    pub approved_by: Option<String>,
+    pub priority: i64,
+    /// `1` for `rollup=always`, `0` for `rollup=maybe`, `-1` for `rollup=iffy` and `-2` for
+    /// `rollup=never`.
+    pub rollup: i64,
+    pub delegate: Option<String>,
+}
+
+/// What has been imported.
+#[derive(Debug, Default, PartialEq)]
+pub struct HomuImportSummary {
+    pub pull_requests: usize,
+    pub approvals: usize,
+    /// Labels of repositories that are not mapped to GitHub repositories, so their PRs were
+    /// skipped.
+    pub skipped_repos: Vec<String>,
+}
+
+/// Loads the open PRs from the database of homu.
+pub async fn load_homu_pulls(db: &DatabaseConnection) -> anyhow::Result<Vec<HomuPull>> {
+    let rows = db
+        .query_all(Statement::from_string(
+            db.get_database_backend(),
+            "SELECT repo, num, approved_by, priority, rollup, delegate FROM pull ORDER BY repo, num"
+                .to_string(),
+        ))
+        .await
+        .context("Cannot read the `pull` table of homu")?;
+    rows.into_iter()
+        .map(|row| {
+            let number: i64 = row.try_get("", "num")?;
+            Ok(HomuPull {
+                repo: row.try_get("", "repo")?,
+                number: number as u64,
+                approved_by: non_empty(row.try_get("", "approved_by")?),
+                priority: row.try_get::<Option<i64>>("", "priority")?.unwrap_or(0),
+                rollup: row.try_get::<Option<i64>>("", "rollup")?.unwrap_or(0),
+                delegate: non_empty(row.try_get("", "delegate")?),
+            })
+        })
+        .collect()
+}
+
+/// Parses mappings of homu repository labels to GitHub repositories (`<label>=<owner>/<name>`).
+pub fn parse_repo_mapping(mappings: &[String]) -> anyhow::Result<HashMap<String, GithubRepoName>> {
+    mappings
+        .iter()
+        .map(|mapping| {
+            let (label, repo) = mapping.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("Invalid mapping `{mapping}`, expected `<label>=<owner>/<name>`")
+            })?;
+            let (owner, name) = repo.split_once('/').ok_or_else(|| {
+                anyhow::anyhow!("Repository must be in the format <owner>/<name>, found `{repo}`")
+            })?;
+            Ok((label.to_string(), GithubRepoName::new(owner, name)))
+        })
+        .collect()
+}
+
+/// Stores the state of the given homu PRs in the database of bors. PRs that are already approved
+/// in bors keep their approval, so the import can be repeated.
+pub async fn import_homu_pulls(
+    db: &dyn DbClient,
+    pulls: &[HomuPull],
+    repos: &HashMap<String, GithubRepoName>,
+) -> anyhow::Result<HomuImportSummary> {
+    let mut summary = HomuImportSummary::default();
+    for pull in pulls {
+        let Some(repo) = repos.get(&pull.repo) else {
+            if !summary.skipped_repos.contains(&pull.repo) {
+                summary.skipped_repos.push(pull.repo.clone());
+            }
+            continue;
+        };
+        let pr = db
+            .get_or_create_pull_request(repo, PullRequestNumber(pull.number))
+            .await?;
+        if let Some(approver) = &pull.approved_by {
+            if !pr.is_approved() {
+                db.approve(&pr, approver, None).await?;
+                summary.approvals += 1;
+            }
+        }
+        if pull.priority > 0 {
+            db.set_priority(&pr, pull.priority.min(u32::MAX as i64) as u32)
+                .await?;
+        }
+        if let Some(rollup) = rollup_mode(pull.rollup) {
+            db.set_rollup(&pr, Some(rollup)).await?;
+        }
+        if let Some(delegate) = &pull.delegate {
+            db.set_delegate(&pr, Some(delegate)).await?;
+        }
+        summary.pull_requests += 1;
+    }
+    Ok(summary)
+}
+
+/// Maps the rollup value of homu to a rollup mode. `rollup=maybe` is the default of bors, so it
+/// is not stored.
+fn rollup_mode(rollup: i64) -> Option<RollupMode> {
+    match rollup {
+        1.. => Some(RollupMode::Always),
+        -1 => Some(RollupMode::Iffy),
+        ..=-2 => Some(RollupMode::Never),
+        _ => None,
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, Database, Statement};
+
+    use crate::database::{DbClient, RollupMode};
+    use crate::github::PullRequestNumber;
+    use crate::homu::{import_homu_pulls, load_homu_pulls, parse_repo_mapping};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn import_queue() {
+        let homu = Database::connect("sqlite::memory:").await.unwrap();
+        for sql in [
+            "CREATE TABLE pull (repo TEXT NOT NULL, num INTEGER NOT NULL, status TEXT NOT NULL, \
+             approved_by TEXT, priority INTEGER, rollup INTEGER, delegate TEXT)",
+            "INSERT INTO pull VALUES ('app', 1, 'approved', 'alice', 5, -2, '')",
+            "INSERT INTO pull VALUES ('app', 2, '', '', 0, 1, 'bob')",
+            "INSERT INTO pull VALUES ('other', 3, 'approved', 'alice', 0, 0, NULL)",
+        ] {
+            homu.execute(Statement::from_string(
+                homu.get_database_backend(),
+                sql.to_string(),
+            ))
+            .await
+            .unwrap();
+        }
+        let pulls = load_homu_pulls(&homu).await.unwrap();
+        assert_eq!(pulls.len(), 3);
+
+        let db = create_test_db().await;
+        let repos = parse_repo_mapping(&[format!("app={}", default_repo_name())]).unwrap();
+        let summary = import_homu_pulls(&db, &pulls, &repos).await.unwrap();
+        assert_eq!(summary.pull_requests, 2);
+        assert_eq!(summary.approvals, 1);
+        assert_eq!(summary.skipped_repos, vec!["other".to_string()]);
+
+        let repo = default_repo_name();
+        let approved = db
+            .find_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(approved.approved_by.as_deref(), Some("alice"));
+        assert_eq!(approved.priority, 5);
+        assert_eq!(approved.rollup, Some(RollupMode::Never));
+        let delegated = db
+            .find_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!delegated.is_approved());
+        assert_eq!(delegated.rollup, Some(RollupMode::Always));
+        assert_eq!(delegated.delegated_to.as_deref(), Some("bob"));
+
+        // Repeating the import does not approve the PR again
+        let summary = import_homu_pulls(&db, &pulls, &repos).await.unwrap();
+        assert_eq!(summary.approvals, 0);
+    }
+}
@@ -0,0 +1,128 @@
+//! The HTTP client used for requests of the bot to GitHub and other services (CI services,
+//! notifiers, Vault, ...).
+//!
+//! All requests share a single pool of connections, which are kept alive and reused (with HTTP/2
+//! if the server supports it), so that a burst of events does not open a new socket for each
+//! request. Every request has a timeout and the number of concurrent requests to a single host is
+//! limited by [`MAX_REQUESTS_PER_HOST`].
+//!
+//! The REST API of GitHub is called through `octocrab`, whose client is shared by all
+//! installations of the app. Its polled requests (see `ResponseCache`) are limited in the same
+//! way as the requests sent with [`SendLimited::send_limited`].
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::async_trait;
+use reqwest::{RequestBuilder, Response, Url};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Timeout of requests that do not set their own timeout.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an unused connection is kept open.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const MAX_IDLE_CONNECTIONS_PER_HOST: usize = 16;
+/// Maximum number of requests that are waiting for a response from a single host at the same
+/// time. Other requests wait until one of them has finished.
+pub const MAX_REQUESTS_PER_HOST: usize = 32;
+
+static CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+static HOST_LIMITS: HostLimits = HostLimits::new(MAX_REQUESTS_PER_HOST);
+
+/// Returns the shared HTTP client. Clones of the client share the same pool of connections.
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            reqwest::Client::builder()
+                .user_agent(concat!("bors/", env!("CARGO_PKG_VERSION")))
+                .timeout(REQUEST_TIMEOUT)
+                .connect_timeout(CONNECT_TIMEOUT)
+                .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+                .pool_max_idle_per_host(MAX_IDLE_CONNECTIONS_PER_HOST)
+                .tcp_keepalive(POOL_IDLE_TIMEOUT)
+                .http2_adaptive_window(true)
+                .build()
+                .expect("Cannot create the HTTP client")
+        })
+        .clone()
+}
+
+/// Waits until a request can be sent to the host of the given URL. The request should be sent
+/// while the returned permit is held.
+pub async fn acquire_host(url: &Url) -> OwnedSemaphorePermit {
+    HOST_LIMITS.acquire(url).await
+}
+
+/// Sending of requests limited by the number of concurrent requests to their host.
+#[async_trait]
+pub trait SendLimited {
+    async fn send_limited(self) -> reqwest::Result<Response>;
+}
+
+#[async_trait]
+impl SendLimited for RequestBuilder {
+    async fn send_limited(self) -> reqwest::Result<Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let _permit = acquire_host(request.url()).await;
+        client.execute(request).await
+    }
+}
+
+/// Semaphores limiting the number of concurrent requests, by host.
+struct HostLimits {
+    max_requests: usize,
+    hosts: Mutex<BTreeMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    const fn new(max_requests: usize) -> Self {
+        Self {
+            max_requests,
+            hosts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn semaphore(&self, url: &Url) -> Arc<Semaphore> {
+        let host = url.host_str().unwrap_or_default().to_string();
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_requests)))
+            .clone()
+    }
+
+    async fn acquire(&self, url: &Url) -> OwnedSemaphorePermit {
+        self.semaphore(url)
+            .acquire_owned()
+            .await
+            .expect("The semaphore of a host is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use crate::http::HostLimits;
+
+    #[tokio::test]
+    async fn limit_requests_per_host() {
+        let limits = HostLimits::new(2);
+        let github = Url::parse("https://api.github.com/repos/foo/bar").unwrap();
+        let other = Url::parse("https://api.github.com/repos/foo/baz").unwrap();
+        let buildkite = Url::parse("https://api.buildkite.com/v2/builds").unwrap();
+
+        let first = limits.acquire(&github).await;
+        let _second = limits.acquire(&other).await;
+        assert_eq!(limits.semaphore(&github).available_permits(), 0);
+        assert_eq!(limits.semaphore(&buildkite).available_permits(), 2);
+
+        drop(first);
+        assert_eq!(limits.semaphore(&github).available_permits(), 1);
+    }
+}
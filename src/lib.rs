@@ -1,9 +1,38 @@
 //! This is the library of the bors bot.
+pub mod agreement;
+pub mod api;
+pub mod audit;
+pub mod badge;
 pub mod bors;
+pub mod bors_ng;
+pub mod ci;
 pub mod config;
+pub mod consistency;
+pub mod dashboard;
 pub mod database;
+pub mod digest;
+pub mod error_reporting;
+pub mod faults;
+pub mod fixtures;
+pub mod forge;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
+pub mod global_config;
+pub mod health;
+pub mod history;
+pub mod homu;
+pub mod http;
+pub mod log_excerpt;
+pub mod messages;
+pub mod metrics;
+pub mod notifications;
+pub mod openapi;
 pub mod permissions;
+pub mod secrets;
+pub mod snapshot;
+pub mod stats;
+pub mod tokens;
 pub mod utils;
 
 #[cfg(test)]
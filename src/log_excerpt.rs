@@ -0,0 +1,208 @@
+//! Extraction of the relevant part of the log of a failed job, which is posted with the build
+//! result, so that contributors do not have to open the CI to find out why a build has failed.
+//!
+//! By default, the excerpt is the end of the log. Repositories can configure regular expressions
+//! (anchors) that match the beginning of an error (e.g. `^error(\[E\d+\])?:`), in which case the
+//! excerpt starts at the first error of the last errors of the log that fit into the excerpt.
+use chrono::{DateTime, Utc};
+use regex::Regex;
+
+/// Returns the last (at most) `max_size` bytes of the log, starting at the beginning of a line.
+pub fn log_tail(log: &str, max_size: usize) -> &str {
+    if log.len() <= max_size {
+        return log;
+    }
+    let mut start = log.len() - max_size;
+    while !log.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &log[start..];
+    match tail.find('\n') {
+        Some(index) if index + 1 < tail.len() => &tail[index + 1..],
+        _ => tail,
+    }
+}
+
+/// Compiles the anchors configured by a repository. Invalid anchors are reported when the
+/// configuration is validated, here they are skipped.
+pub fn compile_anchors(anchors: &[String]) -> Vec<Regex> {
+    anchors
+        .iter()
+        .filter_map(|anchor| match Regex::new(anchor) {
+            Ok(regex) => Some(regex),
+            Err(error) => {
+                tracing::warn!("Ignoring invalid log excerpt anchor `{anchor}`: {error}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Extracts an excerpt of at most `max_size` bytes from the log. The excerpt starts at the first
+/// line that matches one of the anchors from which the rest of the log fits into the excerpt, so
+/// that e.g. all errors of a failed compilation are included. If the rest of the log does not fit
+/// even from the last matching line, the excerpt starts at that line. If no line matches, the
+/// excerpt is the end of the log.
+pub fn extract_excerpt<'a>(log: &'a str, anchors: &[Regex], max_size: usize) -> &'a str {
+    let mut offset = 0;
+    let mut first_in_window = None;
+    let mut last = None;
+    for line in log.split_inclusive('\n') {
+        let text = strip_timestamp(line.trim_end());
+        if anchors.iter().any(|anchor| anchor.is_match(text)) {
+            if first_in_window.is_none() && log.len() - offset <= max_size {
+                first_in_window = Some(offset);
+            }
+            last = Some(offset);
+        }
+        offset += line.len();
+    }
+    match first_in_window.or(last) {
+        Some(start) => log_head(&log[start..], max_size),
+        None => log_tail(log, max_size),
+    }
+}
+
+/// Returns the first (at most) `max_size` bytes of the log, ending at the end of a line if
+/// possible.
+fn log_head(log: &str, max_size: usize) -> &str {
+    if log.len() <= max_size {
+        return log;
+    }
+    let mut end = max_size;
+    while !log.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = &log[..end];
+    match head.rfind('\n') {
+        Some(index) if index > 0 => &head[..index + 1],
+        _ => head,
+    }
+}
+
+/// GitHub Actions prefixes each line of a log with a timestamp (e.g.
+/// `2023-05-01T12:00:00.1234567Z `), which is removed so that anchors can match the beginning of
+/// the line.
+/// Returns the lines of the log of a job that were written while one of its steps was running,
+/// based on the timestamps added by GitHub Actions. The times of steps only have a precision of
+/// seconds, so lines written in the first and last second of the step are included. Lines without
+/// a timestamp belong to the preceding line.
+pub fn step_log(log: &str, started_at: DateTime<Utc>, completed_at: DateTime<Utc>) -> String {
+    let mut in_step = false;
+    let mut lines = String::new();
+    for line in log.split_inclusive('\n') {
+        if let Some(timestamp) = line_timestamp(line) {
+            in_step = (started_at.timestamp()..=completed_at.timestamp())
+                .contains(&timestamp.timestamp());
+        }
+        if in_step {
+            lines.push_str(line);
+        }
+    }
+    lines
+}
+
+/// Returns a Markdown code fence for the text, which is longer than any run of backticks in the
+/// text, so that the text cannot end the code block.
+pub fn code_fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or_default();
+    "`".repeat((longest + 1).max(3))
+}
+
+fn line_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    let (timestamp, _) = line.split_once(' ')?;
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+fn strip_timestamp(line: &str) -> &str {
+    match line.split_once(' ') {
+        Some((timestamp, rest))
+            if timestamp.ends_with('Z')
+                && timestamp.contains('T')
+                && timestamp.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            rest
+        }
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::log_excerpt::{code_fence, compile_anchors, extract_excerpt, log_tail, step_log};
+
+    const LOG: &str = "2023-05-01T12:00:00.0000000Z Compiling foo v0.1.0
+2023-05-01T12:00:01.0000000Z error[E0308]: mismatched types
+2023-05-01T12:00:01.0000000Z  --> src/lib.rs:1:1
+2023-05-01T12:00:02.0000000Z error: could not compile `foo`
+2023-05-01T12:00:02.0000000Z Process completed with exit code 101.
+";
+
+    #[test]
+    fn tail_without_anchors() {
+        assert_eq!(extract_excerpt(LOG, &[], 80), log_tail(LOG, 80));
+        assert_eq!(
+            log_tail(LOG, 80),
+            "2023-05-01T12:00:02.0000000Z Process completed with exit code 101.\n"
+        );
+    }
+
+    #[test]
+    fn start_at_first_anchor_in_window() {
+        let anchors = compile_anchors(&[r"^error(\[E\d+\])?:".to_string()]);
+        assert_eq!(
+            extract_excerpt(LOG, &anchors, 1000),
+            "2023-05-01T12:00:01.0000000Z error[E0308]: mismatched types
+2023-05-01T12:00:01.0000000Z  --> src/lib.rs:1:1
+2023-05-01T12:00:02.0000000Z error: could not compile `foo`
+2023-05-01T12:00:02.0000000Z Process completed with exit code 101.
+"
+        );
+        // Only the last error fits into the window
+        assert_eq!(
+            extract_excerpt(LOG, &anchors, 140),
+            "2023-05-01T12:00:02.0000000Z error: could not compile `foo`
+2023-05-01T12:00:02.0000000Z Process completed with exit code 101.
+"
+        );
+        // The rest of the log does not fit even from the last error, the excerpt is cut at the
+        // end of a line
+        assert_eq!(
+            extract_excerpt(LOG, &anchors, 80),
+            "2023-05-01T12:00:02.0000000Z error: could not compile `foo`\n"
+        );
+    }
+
+    #[test]
+    fn lines_of_step() {
+        let started_at = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 1).unwrap();
+        let completed_at = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 1).unwrap();
+        assert_eq!(
+            step_log(LOG, started_at, completed_at),
+            "2023-05-01T12:00:01.0000000Z error[E0308]: mismatched types
+2023-05-01T12:00:01.0000000Z  --> src/lib.rs:1:1
+"
+        );
+    }
+
+    #[test]
+    fn fence_longer_than_backticks() {
+        assert_eq!(code_fence("error: could not compile `foo`"), "```");
+        assert_eq!(code_fence("```rust\nfn main() {}\n```"), "````");
+    }
+
+    #[test]
+    fn skip_invalid_anchors() {
+        let anchors = compile_anchors(&["(".to_string(), "mismatched".to_string()]);
+        assert_eq!(anchors.len(), 1);
+        assert!(extract_excerpt(LOG, &anchors, 1000).contains("error[E0308]"));
+    }
+}
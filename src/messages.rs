@@ -0,0 +1,533 @@
+//! User-facing texts posted by the bot. Repositories can replace them with their own templates
+//! (e.g. in a different language) in the `[messages]` section of their configuration.
+use std::collections::HashMap;
+
+use crate::github::{GithubRepoName, PullRequestNumber};
+
+/// Values that can be used in the templates of all messages.
+const COMMON_PLACEHOLDERS: &[&str] = &["repo", "pr", "pr_url"];
+
+/// A message posted by the bot that can be customized.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// Reply to the `ping` command.
+    Pong,
+    /// A PR has been approved.
+    Approved,
+    /// A PR cannot be approved again by the same approver.
+    AlreadyApproved,
+    /// A try build has been started.
+    TryStarted,
+    /// A try build has succeeded.
+    TrySucceeded,
+    /// A try build has failed.
+    TryFailed,
+    /// A build of an approved PR has failed, so it cannot be merged until the failure is fixed.
+    ApprovedFailed,
+    /// A try build has been cancelled using a command.
+    TryCancelled,
+    /// A build has been cancelled, because it did not finish in time.
+    TimedOut,
+    /// The author of a command does not have the permission to perform it.
+    InsufficientPrivileges,
+    /// A comment contains a bors command that cannot be parsed.
+    ParseError,
+    /// The approval of a PR has been removed using a command.
+    Unapproved,
+    /// The approval of a PR has been removed, because the approving review was dismissed.
+    ReviewDismissed,
+    /// A draft PR cannot be approved.
+    ApproveDraft,
+    /// A draft PR cannot be tested.
+    TryDraft,
+    /// A PR with a blocking label cannot be approved.
+    ApproveBlocked,
+    /// A PR with a blocking label cannot be tested.
+    TryBlocked,
+    /// A try build cannot be started, because another one is running.
+    TryInProgress,
+    /// A try build cannot be cancelled, because none is running.
+    NoTryInProgress,
+    /// The author of a PR has to sign the contributor agreement before it can be approved.
+    AgreementMissing,
+    /// An approved PR has not been changed for a long time since its build has failed.
+    StaleApprovedFailing,
+    /// A PR has not been approved for a long time since review rights were delegated.
+    StaleDelegation,
+    /// A failed try build has not been retried for a long time.
+    StaleTryFailure,
+    /// A command cannot be used, because its feature is disabled in the repository.
+    FeatureDisabled,
+    /// The rollup mode of a PR has been set using a command.
+    RollupSet,
+    /// The priority of a PR has been set using a command.
+    PrioritySet,
+    /// The rollup mode of a PR has been removed using a command.
+    RollupCleared,
+    /// The rollup mode of a PR cannot be removed, because it has none.
+    NotMarkedForRollup,
+    /// The repository has been paused using a command.
+    Paused,
+    /// The repository cannot be resumed, because it is not paused.
+    NotPaused,
+    /// The repository has been resumed using a command.
+    Resumed,
+    /// A try build cannot be started, because the repository is paused.
+    TryPaused,
+    /// A try build cannot be started, because the PR comes from a fork.
+    TryFromFork,
+    /// A try build cannot be started with a job filter, because the repository does not start try
+    /// builds using a workflow dispatch.
+    JobFilterUnsupported,
+    /// A commit that is not a part of the PR cannot be tested.
+    CommitNotInPullRequest,
+    /// A commit that is not the head of the PR cannot be approved.
+    ApproveNotHead,
+    /// A PR cannot be approved, because it is missing some required labels.
+    MissingRequiredLabels,
+    /// The approval of a PR has been removed, because its head has changed from the approved
+    /// commit.
+    ApprovalOutdated,
+    /// The approval of a PR has been removed, because it was converted to a draft.
+    ConvertedToDraft,
+    /// An approved PR is held out of the queue, because it has a blocking label.
+    HeldByLabel,
+    /// An approved PR is back in the queue, because its blocking labels were removed.
+    BlockingLabelsRemoved,
+    /// A PR has been removed from the queue by an administrator.
+    ForceDequeued,
+    /// The tree has been closed using a command.
+    TreeClosed,
+    /// The tree cannot be opened, because it is not closed.
+    TreeNotClosed,
+    /// The tree has been reopened using a command.
+    TreeOpened,
+    /// A command is postponed, because the database of the bot is unavailable.
+    DatabaseUnavailable,
+    /// A branch used for testing cannot be updated, because the bot cannot push to it.
+    BranchPermissionDenied,
+    /// The merge commit created for testing does not have a verified signature.
+    MergeCommitUnverified,
+    /// A PR cannot be approved, because it exceeds the limits of the repository.
+    LimitsExceeded,
+    /// A limit violation listed in `LimitsExceeded`: the PR changes too many lines.
+    TooManyChangedLines,
+    /// A limit violation listed in `LimitsExceeded`: the PR changes too many files.
+    TooManyChangedFiles,
+    /// A limit violation listed in `LimitsExceeded`: the PR modifies forbidden paths.
+    ForbiddenFilesChanged,
+    /// A PR cannot be approved, because some of its commits are not valid.
+    InvalidCommits,
+    /// A problem listed in `InvalidCommits`: commits without a matching sign-off.
+    CommitsNotSignedOff,
+    /// A problem listed in `InvalidCommits`: commits without a verified signature.
+    CommitsUnverified,
+    /// A running build has been cancelled, because its branch changed while the bot was not
+    /// running.
+    BuildInterrupted,
+    /// A try build has been cancelled, because its base branch was force-pushed.
+    BaseBranchForcePushed,
+    /// A try build has been cancelled, because its base branch was deleted.
+    BaseBranchDeleted,
+    /// A running build has been cancelled, because its branch has been changed by someone else
+    /// than the bot.
+    BuildBranchChanged,
+    /// A try build would have been started on the try branch, if the bot was not in dry-run mode.
+    DryRunTry,
+    /// A try build would have been started using a workflow dispatch, if the bot was not in
+    /// dry-run mode.
+    DryRunTryDispatch,
+    /// A PR would have been added to the merge queue, if the bot was not in dry-run mode.
+    DryRunEnqueue,
+}
+
+impl MessageKind {
+    fn default_template(self) -> &'static str {
+        match self {
+            MessageKind::Pong => "Pong 🏓!",
+            MessageKind::Approved => ":pushpin: Commit {sha} has been approved by `{approver}`",
+            MessageKind::AlreadyApproved => {
+                ":information_source: This pull request is already approved by `{approver}`."
+            }
+            MessageKind::TryStarted => {
+                ":hourglass: Trying commit {head_sha} with merge {merge_sha}…"
+            }
+            MessageKind::TrySucceeded => {
+                ":sunny: Try build successful\n{workflows}\nBuild commit: {sha} (`{sha}`)"
+            }
+            MessageKind::TryFailed => ":broken_heart: Test failed\n{workflows}",
+            MessageKind::ApprovedFailed => {
+                ":bell: @{author} @{approver}, this pull request is approved, but its build has failed, so it will not be merged until the failure is fixed."
+            }
+            MessageKind::TryCancelled => "Try build cancelled.",
+            MessageKind::TimedOut => ":boom: Test timed out",
+            MessageKind::InsufficientPrivileges => {
+                "@{user}: :key: Insufficient privileges: not in {role} users"
+            }
+            MessageKind::ParseError => "{error}",
+            MessageKind::Unapproved => "Commit {sha} has been unapproved",
+            MessageKind::ReviewDismissed => {
+                "The review of `{reviewer}` was dismissed, so the approval of this pull request was removed."
+            }
+            MessageKind::ApproveDraft => {
+                ":construction: This pull request is a draft, so it cannot be approved. Mark it as ready for review first."
+            }
+            MessageKind::TryDraft => {
+                ":construction: This pull request is a draft, so it cannot be tested. Mark it as ready for review first."
+            }
+            MessageKind::ApproveBlocked => {
+                ":hourglass: This pull request cannot be approved until the following blocking label(s) are removed: {labels}"
+            }
+            MessageKind::TryBlocked => {
+                ":hourglass: This pull request cannot be tested until the following blocking label(s) are removed: {labels}"
+            }
+            MessageKind::TryInProgress => {
+                ":exclamation: A try build is currently in progress. You can cancel it using {prefix} try cancel."
+            }
+            MessageKind::NoTryInProgress => {
+                ":exclamation: There is currently no try build in progress."
+            }
+            MessageKind::AgreementMissing => {
+                ":pen: @{author}, you have not signed the contributor agreement of this repository yet, so this pull request cannot be approved. You can sign it [here]({sign_url})."
+            }
+            MessageKind::StaleApprovedFailing => {
+                ":hourglass: @{author} @{approver}, this pull request is approved, but its build has been failing for {days} day(s). Please fix the failure, or unapprove the pull request using {prefix} r-."
+            }
+            MessageKind::StaleDelegation => {
+                ":hourglass: @{delegate}, review rights for this pull request were delegated to you {days} day(s) ago. Please review it, or approve it using {prefix} r+."
+            }
+            MessageKind::StaleTryFailure => {
+                ":hourglass: @{author}, the try build of this pull request failed {days} day(s) ago and it has not been retried since."
+            }
+            MessageKind::FeatureDisabled => {
+                ":no_entry_sign: The `{feature}` feature is not enabled in this repository."
+            }
+            MessageKind::RollupSet => ":ok: The rollup mode of this pull request is now `{mode}`.",
+            MessageKind::PrioritySet => ":ok: The priority of this pull request is now {priority}.",
+            MessageKind::RollupCleared => {
+                ":ok: This pull request is no longer marked for rollup."
+            }
+            MessageKind::NotMarkedForRollup => {
+                ":exclamation: This pull request is not marked for rollup."
+            }
+            MessageKind::Paused => ":pause_button: {description}",
+            MessageKind::NotPaused => ":exclamation: The repository is not paused.",
+            MessageKind::Resumed => ":arrow_forward: The repository has been resumed by `{user}`.",
+            MessageKind::TryPaused => {
+                ":pause_button: Try builds are not started while the repository is paused. {description}"
+            }
+            MessageKind::TryFromFork => {
+                ":no_entry: Try builds of pull requests from forks are disabled in this repository."
+            }
+            MessageKind::JobFilterUnsupported => {
+                ":exclamation: A job filter can only be used if try builds are started using a workflow dispatch."
+            }
+            MessageKind::CommitNotInPullRequest => {
+                ":exclamation: Commit {sha} is not a part of this pull request, only its commits can be tested."
+            }
+            MessageKind::ApproveNotHead => {
+                ":no_entry: Commit `{sha}` is not the head of this pull request ({head_sha}), it cannot be approved."
+            }
+            MessageKind::MissingRequiredLabels => {
+                ":no_entry: This pull request cannot be approved, it is missing the following required label(s): {labels}"
+            }
+            MessageKind::ApprovalOutdated => {
+                ":warning: The head of this pull request has changed from the approved commit {approved_sha} to {head_sha}, so its approval was removed. Please review the new changes and approve it again."
+            }
+            MessageKind::ConvertedToDraft => {
+                ":construction: This pull request was converted to a draft, so its approval was removed and it was taken out of the queue."
+            }
+            MessageKind::HeldByLabel => {
+                ":hourglass: This pull request is held out of the queue until the `{label}` label is removed."
+            }
+            MessageKind::BlockingLabelsRemoved => {
+                ":arrow_forward: All blocking labels were removed, this pull request is back in the queue."
+            }
+            MessageKind::ForceDequeued => {
+                ":warning: This PR has been removed from the queue by an administrator."
+            }
+            MessageKind::TreeClosed => ":evergreen_tree: {description}",
+            MessageKind::TreeNotClosed => ":exclamation: The tree is not closed.",
+            MessageKind::TreeOpened => ":evergreen_tree: The tree has been reopened by `{user}`.",
+            MessageKind::DatabaseUnavailable => {
+                ":warning: The database of the bot is currently unavailable. The command will be executed once it becomes available again."
+            }
+            MessageKind::BranchPermissionDenied => {
+                ":no_entry: Cannot update branch `{branch}`, the bot does not have permission to push to it. Please check the branch protection settings of this repository."
+            }
+            MessageKind::MergeCommitUnverified => {
+                ":no_entry: The merge commit `{sha}` does not have a verified signature, so it cannot be tested."
+            }
+            MessageKind::LimitsExceeded => {
+                ":no_entry: This pull request cannot be approved:\n{violations}\n\nPlease consider splitting it into smaller pull requests. In an emergency, a reviewer can approve it anyway using `r+ ignore-limits`."
+            }
+            MessageKind::TooManyChangedLines => {
+                "- it changes {lines} lines, but at most {max} are allowed"
+            }
+            MessageKind::TooManyChangedFiles => {
+                "- it changes {files} files, but at most {max} are allowed"
+            }
+            MessageKind::ForbiddenFilesChanged => {
+                "- it modifies files that must not be changed: {files}"
+            }
+            MessageKind::InvalidCommits => {
+                ":no_entry: This pull request cannot be approved. {problems}"
+            }
+            MessageKind::CommitsNotSignedOff => {
+                "The following commit(s) do not contain a `Signed-off-by` trailer matching their author:\n{commits}"
+            }
+            MessageKind::CommitsUnverified => {
+                "The following commit(s) do not have a verified signature:\n{commits}"
+            }
+            MessageKind::BuildInterrupted => {
+                ":warning: The build of commit {sha} was interrupted while bors was not running. Please start it again."
+            }
+            MessageKind::BaseBranchForcePushed => {
+                ":warning: The base branch `{branch}` was force-pushed from `{before}` to `{after}`, so this try build was cancelled, because its merge commit is no longer based on the history of the branch. Please start it again."
+            }
+            MessageKind::BaseBranchDeleted => {
+                ":warning: The base branch `{branch}` was deleted (its last commit was `{before}`), so this try build was cancelled, because its merge commit is no longer based on the history of the branch. Please start it again."
+            }
+            MessageKind::BuildBranchChanged => {
+                ":warning: The build of commit {sha} was cancelled, because its branch `{branch}` has been changed. Please start it again."
+            }
+            MessageKind::DryRunTry => {
+                ":test_tube: **Dry run**: `{head_sha}` would now be merged into `{base_sha}` and tested on `{branch}`, but no branches are changed in dry-run mode."
+            }
+            MessageKind::DryRunTryDispatch => {
+                ":test_tube: **Dry run**: `{head_sha}` would now be merged into `{base_sha}` and tested by the `{workflow}` workflow, but no branches are changed and no workflows are started in dry-run mode."
+            }
+            MessageKind::DryRunEnqueue => {
+                ":test_tube: **Dry run**: this pull request would now be added to the merge queue."
+            }
+        }
+    }
+
+    /// Names of the values that can be used in the template of this message.
+    fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            MessageKind::Pong
+            | MessageKind::TryCancelled
+            | MessageKind::TimedOut
+            | MessageKind::ApproveDraft
+            | MessageKind::TryDraft
+            | MessageKind::NoTryInProgress
+            | MessageKind::RollupCleared
+            | MessageKind::NotMarkedForRollup
+            | MessageKind::NotPaused
+            | MessageKind::TryFromFork
+            | MessageKind::JobFilterUnsupported
+            | MessageKind::ConvertedToDraft
+            | MessageKind::BlockingLabelsRemoved
+            | MessageKind::ForceDequeued
+            | MessageKind::TreeNotClosed
+            | MessageKind::DatabaseUnavailable
+            | MessageKind::DryRunEnqueue => &[],
+            MessageKind::TryInProgress => &["prefix"],
+            MessageKind::Approved => &["sha", "approver"],
+            MessageKind::AlreadyApproved => &["approver"],
+            MessageKind::TryStarted => &["head_sha", "merge_sha"],
+            MessageKind::TrySucceeded => &["workflows", "sha"],
+            MessageKind::TryFailed => &["workflows"],
+            MessageKind::ApprovedFailed => &["author", "approver"],
+            MessageKind::InsufficientPrivileges => &["user", "role"],
+            MessageKind::ParseError => &["error"],
+            MessageKind::Unapproved => &["sha"],
+            MessageKind::ReviewDismissed => &["reviewer"],
+            MessageKind::AgreementMissing => &["author", "sign_url"],
+            MessageKind::StaleApprovedFailing => &["author", "approver", "days", "prefix"],
+            MessageKind::StaleDelegation => &["delegate", "days", "prefix"],
+            MessageKind::StaleTryFailure => &["author", "days"],
+            MessageKind::FeatureDisabled => &["feature"],
+            MessageKind::RollupSet => &["mode"],
+            MessageKind::PrioritySet => &["priority"],
+            MessageKind::Paused => &["user", "reason", "description"],
+            MessageKind::Resumed | MessageKind::TreeOpened => &["user"],
+            MessageKind::TryPaused => &["description"],
+            MessageKind::CommitNotInPullRequest => &["sha"],
+            MessageKind::ApproveNotHead => &["sha", "head_sha"],
+            MessageKind::MissingRequiredLabels
+            | MessageKind::ApproveBlocked
+            | MessageKind::TryBlocked => &["labels"],
+            MessageKind::ApprovalOutdated => &["approved_sha", "head_sha"],
+            MessageKind::HeldByLabel => &["label"],
+            MessageKind::TreeClosed => &["user", "priority", "reason", "description"],
+            MessageKind::BranchPermissionDenied => &["branch"],
+            MessageKind::MergeCommitUnverified | MessageKind::BuildInterrupted => &["sha"],
+            MessageKind::LimitsExceeded => &["violations"],
+            MessageKind::TooManyChangedLines => &["lines", "max"],
+            MessageKind::TooManyChangedFiles => &["files", "max"],
+            MessageKind::ForbiddenFilesChanged => &["files"],
+            MessageKind::InvalidCommits => &["problems"],
+            MessageKind::CommitsNotSignedOff | MessageKind::CommitsUnverified => &["commits"],
+            MessageKind::BaseBranchForcePushed => &["branch", "before", "after"],
+            MessageKind::BaseBranchDeleted => &["branch", "before"],
+            MessageKind::BuildBranchChanged => &["sha", "branch"],
+            MessageKind::DryRunTry => &["head_sha", "base_sha", "branch"],
+            MessageKind::DryRunTryDispatch => &["head_sha", "base_sha", "workflow"],
+        }
+    }
+}
+
+/// Templates of messages that replace the default texts of the bot.
+/// A template can refer to the values of the message using `{name}`. Besides the values of each
+/// message, all templates can use `{repo}` (`owner/name`), `{pr}` (the PR number) and `{pr_url}`.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct MessageTemplates(HashMap<MessageKind, String>);
+
+impl MessageTemplates {
+    /// Creates the text of a message posted to a PR by filling the given values, and the values
+    /// common to all messages, into its template.
+    pub fn render(
+        &self,
+        kind: MessageKind,
+        repo: &GithubRepoName,
+        pr: PullRequestNumber,
+        values: &[(&str, &str)],
+    ) -> String {
+        let template = self
+            .0
+            .get(&kind)
+            .map(|template| template.as_str())
+            .unwrap_or_else(|| kind.default_template());
+        let repo_name = repo.path();
+        let pr_number = pr.to_string();
+        let pr_url = format!("https://github.com/{repo_name}/pull/{pr}");
+        let common = [
+            ("repo", repo_name.as_str()),
+            ("pr", pr_number.as_str()),
+            ("pr_url", pr_url.as_str()),
+        ];
+        // The template is filled in a single pass, so that placeholders contained in the values
+        // (e.g. in the text of a user comment) are never replaced
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| {
+                let name = &rest[1..end];
+                values
+                    .iter()
+                    .chain(common.iter())
+                    .find(|(value_name, _)| *value_name == name)
+                    .map(|(_, value)| (*value, end))
+            });
+            match value {
+                Some((value, end)) => {
+                    text.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+
+    /// Finds templates that refer to values that their message does not have.
+    pub fn unknown_placeholders(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (kind, template) in &self.0 {
+            let mut rest = template.as_str();
+            while let Some(start) = rest.find('{') {
+                let Some(end) = rest[start..].find('}') else {
+                    break;
+                };
+                let name = &rest[start + 1..start + end];
+                if !kind.placeholders().contains(&name) && !COMMON_PLACEHOLDERS.contains(&name) {
+                    problems.push(format!(
+                        "Message `{kind:?}` refers to an unknown value `{{{name}}}`"
+                    ));
+                }
+                rest = &rest[start + end + 1..];
+            }
+        }
+        problems.sort();
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::PullRequestNumber;
+    use crate::messages::{MessageKind, MessageTemplates};
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn render_default() {
+        let templates = MessageTemplates::default();
+        assert_eq!(
+            templates.render(
+                MessageKind::InsufficientPrivileges,
+                &default_repo_name(),
+                PullRequestNumber(1),
+                &[("user", "foo"), ("role", "try")]
+            ),
+            "@foo: :key: Insufficient privileges: not in try users"
+        );
+    }
+
+    #[test]
+    fn render_custom() {
+        let templates: MessageTemplates =
+            toml::from_str(r#"approved = "Schválil {approver} ({sha}, {sha})""#).unwrap();
+        assert_eq!(
+            templates.render(
+                MessageKind::Approved,
+                &default_repo_name(),
+                PullRequestNumber(1),
+                &[("sha", "abc"), ("approver", "foo")]
+            ),
+            "Schválil foo (abc, abc)"
+        );
+    }
+
+    #[test]
+    fn render_common_values() {
+        let templates: MessageTemplates = toml::from_str(
+            r#"parse_error = "[{repo}#{pr}]({pr_url}): {error}, see the documentation""#,
+        )
+        .unwrap();
+        assert!(templates.unknown_placeholders().is_empty());
+        assert_eq!(
+            templates.render(
+                MessageKind::ParseError,
+                &default_repo_name(),
+                PullRequestNumber(3),
+                &[("error", "Unknown command {pr}")]
+            ),
+            "[owner/name#3](https://github.com/owner/name/pull/3): Unknown command {pr}, see the documentation"
+        );
+    }
+
+    #[test]
+    fn render_values_once() {
+        let templates: MessageTemplates =
+            toml::from_str(r#"insufficient_privileges = "{user} {role} {unknown}""#).unwrap();
+        assert_eq!(
+            templates.render(
+                MessageKind::InsufficientPrivileges,
+                &default_repo_name(),
+                PullRequestNumber(1),
+                &[("user", "{role}"), ("role", "try")]
+            ),
+            "{role} try {unknown}"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        let templates: MessageTemplates =
+            toml::from_str(r#"try_started = "Trying {commit}""#).unwrap();
+        assert_eq!(
+            templates.unknown_placeholders(),
+            vec!["Message `TryStarted` refers to an unknown value `{commit}`"]
+        );
+    }
+}
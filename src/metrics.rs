@@ -0,0 +1,318 @@
+//! Counters of the usage of the bot, exposed in the Prometheus text format at `/metrics`.
+//! They are kept in memory, so they are reset when the bot is restarted.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::github::GithubRepoName;
+
+/// What has happened with a command received by the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CommandOutcome {
+    Executed,
+    /// The author of the command does not have the permission to use it.
+    PermissionDenied,
+    /// The command could not be parsed.
+    ParseError,
+    /// The command has failed with an error.
+    Failed,
+}
+
+impl CommandOutcome {
+    fn name(self) -> &'static str {
+        match self {
+            CommandOutcome::Executed => "executed",
+            CommandOutcome::PermissionDenied => "permission_denied",
+            CommandOutcome::ParseError => "parse_error",
+            CommandOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Number of commands received by the bot, by repository, command and outcome.
+#[derive(Debug, Default)]
+pub struct CommandMetrics {
+    counts: Mutex<BTreeMap<(String, &'static str, CommandOutcome), u64>>,
+}
+
+impl CommandMetrics {
+    pub fn record(&self, repo: &GithubRepoName, command: &'static str, outcome: CommandOutcome) {
+        let mut counts = self.counts.lock().unwrap();
+        *counts
+            .entry((repo.to_string(), command, outcome))
+            .or_default() += 1;
+    }
+
+    /// Returns the number of commands with the given name and outcome received in the repository.
+    pub fn count(
+        &self,
+        repo: &GithubRepoName,
+        command: &'static str,
+        outcome: CommandOutcome,
+    ) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .get(&(repo.to_string(), command, outcome))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Renders the counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP bors_commands_total Number of commands received by the bot.\n# TYPE bors_commands_total counter\n",
+        );
+        for ((repo, command, outcome), count) in self.counts.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_commands_total{{repository="{}",command="{command}",outcome="{}"}} {count}"#,
+                escape_label(repo),
+                outcome.name()
+            );
+        }
+        text
+    }
+}
+
+/// Events in the queues of the event loops of the bot.
+pub static EVENT_QUEUES: EventQueueMetrics = EventQueueMetrics::new();
+
+#[derive(Debug, Default, Clone, Copy)]
+struct QueueCounters {
+    enqueued: u64,
+    processed: u64,
+    /// Webhooks that were rejected, because the queue was full.
+    rejected: u64,
+}
+
+/// Number of events sent to and processed by each event loop, by the name of its forge.
+#[derive(Debug)]
+pub struct EventQueueMetrics {
+    counts: Mutex<BTreeMap<&'static str, QueueCounters>>,
+}
+
+impl Default for EventQueueMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventQueueMetrics {
+    pub const fn new() -> Self {
+        Self {
+            counts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_enqueued(&self, queue: &'static str) {
+        self.counts
+            .lock()
+            .unwrap()
+            .entry(queue)
+            .or_default()
+            .enqueued += 1;
+    }
+
+    pub fn record_processed(&self, queue: &'static str) {
+        self.counts
+            .lock()
+            .unwrap()
+            .entry(queue)
+            .or_default()
+            .processed += 1;
+    }
+
+    pub fn record_rejected(&self, queue: &'static str) {
+        self.counts
+            .lock()
+            .unwrap()
+            .entry(queue)
+            .or_default()
+            .rejected += 1;
+    }
+
+    /// Number of events in the queue that have not been processed yet.
+    pub fn depth(&self, queue: &'static str) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .get(queue)
+            .map_or(0, |counts| counts.enqueued.saturating_sub(counts.processed))
+    }
+
+    /// Renders the counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        let mut text = String::from(
+            "# HELP bors_event_queue_depth Number of events waiting in the queue of an event loop.\n# TYPE bors_event_queue_depth gauge\n",
+        );
+        for (queue, counts) in counts.iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_event_queue_depth{{queue="{queue}"}} {}"#,
+                counts.enqueued.saturating_sub(counts.processed)
+            );
+        }
+        text.push_str(
+            "# HELP bors_events_rejected_total Number of webhooks rejected, because the queue of an event loop was full.\n# TYPE bors_events_rejected_total counter\n",
+        );
+        for (queue, counts) in counts.iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_events_rejected_total{{queue="{queue}"}} {}"#,
+                counts.rejected
+            );
+        }
+        text
+    }
+}
+
+/// Usage of the in-memory caches of the bot.
+pub static CACHES: CacheMetrics = CacheMetrics::new();
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// Number of hits, misses and evictions of the LRU caches, by the name of the cache.
+#[derive(Debug)]
+pub struct CacheMetrics {
+    counts: Mutex<BTreeMap<&'static str, CacheStats>>,
+}
+
+impl Default for CacheMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheMetrics {
+    pub const fn new() -> Self {
+        Self {
+            counts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_hit(&self, cache: &'static str) {
+        self.counts.lock().unwrap().entry(cache).or_default().hits += 1;
+    }
+
+    pub fn record_miss(&self, cache: &'static str) {
+        self.counts.lock().unwrap().entry(cache).or_default().misses += 1;
+    }
+
+    pub fn record_eviction(&self, cache: &'static str) {
+        self.counts
+            .lock()
+            .unwrap()
+            .entry(cache)
+            .or_default()
+            .evictions += 1;
+    }
+
+    pub fn stats(&self, cache: &'static str) -> CacheStats {
+        self.counts
+            .lock()
+            .unwrap()
+            .get(cache)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Renders the counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        let mut text = String::from(
+            "# HELP bors_cache_requests_total Number of lookups in an in-memory cache, by their result.\n# TYPE bors_cache_requests_total counter\n",
+        );
+        for (cache, stats) in counts.iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_cache_requests_total{{cache="{cache}",result="hit"}} {}"#,
+                stats.hits
+            );
+            let _ = writeln!(
+                text,
+                r#"bors_cache_requests_total{{cache="{cache}",result="miss"}} {}"#,
+                stats.misses
+            );
+        }
+        text.push_str(
+            "# HELP bors_cache_evictions_total Number of entries evicted from a full in-memory cache.\n# TYPE bors_cache_evictions_total counter\n",
+        );
+        for (cache, stats) in counts.iter() {
+            let _ = writeln!(
+                text,
+                r#"bors_cache_evictions_total{{cache="{cache}"}} {}"#,
+                stats.evictions
+            );
+        }
+        text
+    }
+}
+
+pub(crate) fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{CacheMetrics, CommandMetrics, CommandOutcome, EventQueueMetrics};
+    use crate::tests::state::default_repo_name;
+
+    #[test]
+    fn render_command_metrics() {
+        let metrics = CommandMetrics::default();
+        let repo = default_repo_name();
+        metrics.record(&repo, "approve", CommandOutcome::Executed);
+        metrics.record(&repo, "approve", CommandOutcome::Executed);
+        metrics.record(&repo, "try", CommandOutcome::PermissionDenied);
+        insta::assert_snapshot!(metrics.render(), @r###"
+        # HELP bors_commands_total Number of commands received by the bot.
+        # TYPE bors_commands_total counter
+        bors_commands_total{repository="owner/name",command="approve",outcome="executed"} 2
+        bors_commands_total{repository="owner/name",command="try",outcome="permission_denied"} 1
+        "###);
+    }
+
+    #[test]
+    fn render_event_queue_metrics() {
+        let metrics = EventQueueMetrics::new();
+        metrics.record_enqueued("github");
+        metrics.record_enqueued("github");
+        metrics.record_processed("github");
+        metrics.record_rejected("github");
+        assert_eq!(metrics.depth("github"), 1);
+        insta::assert_snapshot!(metrics.render(), @r###"
+        # HELP bors_event_queue_depth Number of events waiting in the queue of an event loop.
+        # TYPE bors_event_queue_depth gauge
+        bors_event_queue_depth{queue="github"} 1
+        # HELP bors_events_rejected_total Number of webhooks rejected, because the queue of an event loop was full.
+        # TYPE bors_events_rejected_total counter
+        bors_events_rejected_total{queue="github"} 1
+        "###);
+    }
+
+    #[test]
+    fn render_cache_metrics() {
+        let metrics = CacheMetrics::new();
+        metrics.record_hit("github_responses");
+        metrics.record_hit("github_responses");
+        metrics.record_miss("github_responses");
+        metrics.record_eviction("github_responses");
+        insta::assert_snapshot!(metrics.render(), @r###"
+        # HELP bors_cache_requests_total Number of lookups in an in-memory cache, by their result.
+        # TYPE bors_cache_requests_total counter
+        bors_cache_requests_total{cache="github_responses",result="hit"} 2
+        bors_cache_requests_total{cache="github_responses",result="miss"} 1
+        # HELP bors_cache_evictions_total Number of entries evicted from a full in-memory cache.
+        # TYPE bors_cache_evictions_total counter
+        bors_cache_evictions_total{cache="github_responses"} 1
+        "###);
+    }
+}
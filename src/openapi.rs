@@ -0,0 +1,578 @@
+//! OpenAPI description of the JSON API (see [`crate::api`]), served at `/api/v1/openapi.json`
+//! for client generators.
+//!
+//! The schemas are written by hand. Tests check that they describe all fields of the serialized
+//! API types, so that they do not get out of sync with the handlers.
+use serde_json::{json, Value};
+
+/// Returns the OpenAPI 3 document of the JSON API.
+pub fn openapi_document() -> Value {
+    let repo_params = json!([
+        path_param("owner", "string", "Owner of the repository"),
+        path_param("name", "string", "Name of the repository"),
+    ]);
+    let pr_params = json!([
+        path_param("owner", "string", "Owner of the repository"),
+        path_param("name", "string", "Name of the repository"),
+        path_param("number", "integer", "Number of the pull request"),
+    ]);
+    let page_param = json!({
+        "name": "page",
+        "in": "query",
+        "required": false,
+        "description": "Page of the history, starting at 1",
+        "schema": {"type": "integer", "minimum": 1, "default": 1}
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "bors API",
+            "description": "State of the merge queue and of the builds of repositories managed by bors.",
+            "version": "1"
+        },
+        "security": [{"bearer": []}],
+        "paths": {
+            "/api/v1/repos/{owner}/{name}/queue": {
+                "get": operation(
+                    "getQueue",
+                    "Merge queue of the repository: approved PRs in the order in which they will be merged, followed by PRs with a running try build",
+                    &repo_params,
+                    json!({"type": "array", "items": schema_ref("QueueEntry")}),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/prs/{number}": {
+                "get": operation(
+                    "getPullRequest",
+                    "State of a PR and of its try build",
+                    &pr_params,
+                    schema_ref("PullRequest"),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/builds/{id}": {
+                "get": operation(
+                    "getBuild",
+                    "A build with its workflows",
+                    &json!([
+                        path_param("owner", "string", "Owner of the repository"),
+                        path_param("name", "string", "Name of the repository"),
+                        path_param("id", "integer", "ID of the build"),
+                    ]),
+                    schema_ref("Build"),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/builds": {
+                "get": operation(
+                    "getBuildHistory",
+                    "Page of the build history of the repository, the newest builds first",
+                    &with_param(&repo_params, &page_param),
+                    schema_ref("HistoryPage"),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/prs/{number}/builds": {
+                "get": operation(
+                    "getPullRequestBuildHistory",
+                    "Page of the build history of a PR, the newest builds first",
+                    &with_param(&pr_params, &page_param),
+                    schema_ref("HistoryPage"),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/stats": {
+                "get": operation(
+                    "getStatistics",
+                    "Merge statistics of the repository, computed from its event log",
+                    &with_param(&repo_params, &json!({
+                        "name": "days",
+                        "in": "query",
+                        "required": false,
+                        "description": "Length of the period that ends now, in days",
+                        "schema": {"type": "integer", "minimum": 1, "default": 30}
+                    })),
+                    schema_ref("Statistics"),
+                )
+            },
+            "/api/v1/repos/{owner}/{name}/audit": {
+                "get": audit_operation(&repo_params)
+            },
+            "/api/v1/repos/{owner}/{name}/builds/{id}/result": {
+                "post": report_operation(&repo_params)
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "API token with the `read` scope (`report` for reporting build results)"
+                }
+            },
+            "schemas": schemas()
+        }
+    })
+}
+
+fn schemas() -> Value {
+    let build_status = json!({
+        "type": "string",
+        "enum": ["pending", "success", "failure", "cancelled", "timed out"]
+    });
+    let time = json!({"type": "string", "format": "date-time"});
+    let nullable_time = json!({"type": "string", "format": "date-time", "nullable": true});
+    let nullable_string = json!({"type": "string", "nullable": true});
+
+    json!({
+        "QueueEntry": {
+            "type": "object",
+            "required": ["number", "status", "priority", "approved_by", "approved_at", "try_build"],
+            "properties": {
+                "number": {"type": "integer"},
+                "status": {"type": "string", "enum": ["building", "approved", "testing"]},
+                "priority": {"type": "integer"},
+                "approved_by": nullable_string,
+                "approved_at": nullable_time,
+                "try_build": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "Status of the last try build of the PR",
+                    "enum": ["pending", "success", "failure", "cancelled", "timed out"]
+                }
+            }
+        },
+        "PullRequest": {
+            "type": "object",
+            "required": ["number", "approved_by", "approved_at", "priority", "delegated_to", "try_build"],
+            "properties": {
+                "number": {"type": "integer"},
+                "approved_by": nullable_string,
+                "approved_at": nullable_time,
+                "priority": {"type": "integer"},
+                "delegated_to": nullable_string,
+                "try_build": {
+                    "allOf": [schema_ref("Build")],
+                    "nullable": true
+                }
+            }
+        },
+        "Build": {
+            "type": "object",
+            "required": ["id", "branch", "commit_sha", "status", "created_at", "attempt", "workflows"],
+            "properties": {
+                "id": {"type": "integer"},
+                "branch": {"type": "string"},
+                "commit_sha": {"type": "string"},
+                "status": build_status,
+                "created_at": time,
+                "attempt": {
+                    "type": "integer",
+                    "description": "How many times has the build been retried automatically"
+                },
+                "workflows": {"type": "array", "items": schema_ref("Workflow")}
+            }
+        },
+        "Workflow": {
+            "type": "object",
+            "required": ["name", "url", "run_id", "status", "finished_at", "artifacts"],
+            "properties": {
+                "name": {"type": "string"},
+                "url": {"type": "string"},
+                "run_id": {"type": "integer"},
+                "status": {"type": "string", "enum": ["pending", "success", "failure"]},
+                "finished_at": nullable_time,
+                "artifacts": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "URLs of artifacts reported with the result of the workflow"
+                }
+            }
+        },
+        "BuildResult": {
+            "type": "object",
+            "required": ["name", "status", "url"],
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "Name of the reporting system, shown as the name of its workflow"
+                },
+                "status": {"type": "string", "enum": ["pending", "success", "failure"]},
+                "url": {"type": "string", "description": "Link to the build in the system"},
+                "artifacts": {"type": "array", "items": {"type": "string"}}
+            }
+        },
+        "HistoryPage": {
+            "type": "object",
+            "required": ["page", "has_next", "builds"],
+            "properties": {
+                "page": {"type": "integer"},
+                "has_next": {"type": "boolean"},
+                "builds": {"type": "array", "items": schema_ref("HistoryEntry")}
+            }
+        },
+        "HistoryEntry": {
+            "type": "object",
+            "required": [
+                "id", "pr", "branch", "commit_sha", "status", "approved_by", "created_at",
+                "duration_secs", "failed_checks"
+            ],
+            "properties": {
+                "id": {"type": "integer"},
+                "pr": {"type": "integer", "nullable": true},
+                "branch": {"type": "string"},
+                "commit_sha": {"type": "string"},
+                "status": build_status,
+                "approved_by": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "Who has approved the PR when the build was started"
+                },
+                "created_at": time,
+                "duration_secs": {"type": "integer", "nullable": true},
+                "failed_checks": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Names of workflows of the build that have failed"
+                }
+            }
+        },
+        "Statistics": {
+            "type": "object",
+            "required": [
+                "since", "until", "landed_prs", "median_time_to_merge_secs", "builds_per_day",
+                "retry_rate", "retries_per_pr", "rollup_share", "duration_regressions"
+            ],
+            "properties": {
+                "since": time,
+                "until": time,
+                "landed_prs": {"type": "integer", "description": "Number of PRs merged in the period"},
+                "median_time_to_merge_secs": {
+                    "type": "integer",
+                    "nullable": true,
+                    "description": "Median time between the approval of the merged PRs and their merge"
+                },
+                "builds_per_day": {"type": "number"},
+                "retry_rate": {
+                    "type": "number",
+                    "description": "Share of builds that have retried a commit that had already been built"
+                },
+                "retries_per_pr": {
+                    "type": "number",
+                    "description": "Average number of builds that have retried a commit that had already been built, over the PRs built in the period"
+                },
+                "rollup_share": {
+                    "type": "number",
+                    "description": "Share of merged PRs that were marked for a rollup (`always`, `maybe` or `iffy`) when they were merged"
+                },
+                "duration_regressions": {
+                    "type": "array",
+                    "items": schema_ref("DurationRegression"),
+                    "description": "Workflows whose median duration has increased significantly in the last week of the period"
+                }
+            }
+        },
+        "DurationRegression": {
+            "type": "object",
+            "required": ["workflow", "previous_median_secs", "current_median_secs", "increase"],
+            "properties": {
+                "workflow": {"type": "string"},
+                "previous_median_secs": {"type": "integer"},
+                "current_median_secs": {"type": "integer"},
+                "increase": {
+                    "type": "number",
+                    "description": "Relative increase of the median duration, e.g. 0.5 for 50%"
+                }
+            }
+        },
+        "AuditRecord": {
+            "type": "object",
+            "required": ["time", "repository", "pr", "action", "actor", "command", "approved_by"],
+            "properties": {
+                "time": time,
+                "repository": {"type": "string"},
+                "pr": {"type": "integer", "nullable": true},
+                "action": {
+                    "type": "string",
+                    "enum": [
+                        "command",
+                        "approved",
+                        "unapproved",
+                        "merged",
+                        "permission_granted",
+                        "permission_revoked",
+                        "delegation_revoked"
+                    ]
+                },
+                "actor": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "Who has sent the command or approved the PR, or whose permission or delegation has changed"
+                },
+                "command": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "The text of a command, or the changed permission (review or try)"
+                },
+                "approved_by": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "For merges, who has approved the merged PR"
+                }
+            }
+        }
+    })
+}
+
+fn operation(id: &str, summary: &str, parameters: &Value, response: Value) -> Value {
+    json!({
+        "operationId": id,
+        "summary": summary,
+        "parameters": parameters,
+        "responses": {
+            "200": {
+                "description": "OK",
+                "content": {"application/json": {"schema": response}}
+            },
+            "401": {"description": "The request is not authenticated"},
+            "403": {"description": "The API token does not have the `read` scope"},
+            "404": {"description": "Not found, or the API is disabled"}
+        }
+    })
+}
+
+/// The audit trail is exported as CSV or JSON lines rather than as a JSON document.
+fn audit_operation(repo_params: &Value) -> Value {
+    let date_param = |name: &str, description: &str| {
+        json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "description": description,
+            "schema": {"type": "string", "format": "date"}
+        })
+    };
+    let parameters = with_param(
+        &with_param(
+            &with_param(
+                repo_params,
+                &date_param(
+                    "since",
+                    "First day of the period [default: the first recorded event]",
+                ),
+            ),
+            &date_param("until", "Last day of the period [default: today]"),
+        ),
+        &json!({
+            "name": "format",
+            "in": "query",
+            "required": false,
+            "description": "Format of the export",
+            "schema": {"type": "string", "enum": ["csv", "jsonl"], "default": "csv"}
+        }),
+    );
+    let mut operation = operation(
+        "exportAuditTrail",
+        "Commands, approvals and merges of the repository in a period, the oldest first",
+        &parameters,
+        Value::Null,
+    );
+    operation["responses"]["200"]["content"] = json!({
+        "text/csv": {"schema": {"type": "string"}},
+        "application/x-ndjson": {"schema": schema_ref("AuditRecord")}
+    });
+    operation["responses"]["400"] = json!({"description": "Invalid date or format"});
+    operation
+}
+
+/// Reports are sent by external systems, so the operation has a request body and it does not
+/// return anything.
+fn report_operation(repo_params: &Value) -> Value {
+    json!({
+        "operationId": "reportBuildResult",
+        "summary": "Reports the result of a build of a candidate commit created by bors, which is stored as a workflow of the build",
+        "parameters": with_param(repo_params, &path_param("id", "integer", "ID of the build")),
+        "requestBody": {
+            "required": true,
+            "content": {"application/json": {"schema": schema_ref("BuildResult")}}
+        },
+        "responses": {
+            "202": {"description": "The result has been accepted"},
+            "400": {"description": "Invalid result"},
+            "401": {"description": "The request is not authenticated"},
+            "403": {"description": "The API token does not have the `report` scope or it cannot be used in the repository"},
+            "404": {"description": "Not found, or the API is disabled"},
+            "409": {"description": "The build has already finished"}
+        }
+    })
+}
+
+fn path_param(name: &str, kind: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": {"type": kind}
+    })
+}
+
+fn with_param(parameters: &Value, param: &Value) -> Value {
+    let mut parameters = parameters.as_array().cloned().unwrap_or_default();
+    parameters.push(param.clone());
+    Value::Array(parameters)
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{name}")})
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::Value;
+
+    use crate::api::{ApiBuild, ApiPullRequest, ApiQueueEntry, ApiWorkflow};
+    use crate::audit::AuditRecord;
+    use crate::history::{HistoryEntry, HistoryPage};
+    use crate::openapi::openapi_document;
+    use crate::stats::{DurationRegression, MergeStatistics};
+
+    /// Checks that the schema lists exactly the fields of the serialized value.
+    fn check_schema<T: serde::Serialize>(name: &str, value: &T) {
+        let document = openapi_document();
+        let schema = &document["components"]["schemas"][name];
+        let mut documented: Vec<&String> = schema["properties"]
+            .as_object()
+            .unwrap_or_else(|| panic!("Schema {name} does not exist"))
+            .keys()
+            .collect();
+        let value = serde_json::to_value(value).unwrap();
+        let mut fields: Vec<&String> = value.as_object().unwrap().keys().collect();
+        documented.sort();
+        fields.sort();
+        assert_eq!(documented, fields, "Schema {name} is out of date");
+
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(required.len(), fields.len(), "Schema {name} is out of date");
+    }
+
+    fn build() -> ApiBuild {
+        ApiBuild {
+            id: 1,
+            branch: "automation/bors/try".to_string(),
+            commit_sha: "sha".to_string(),
+            status: "success",
+            created_at: Utc::now(),
+            attempt: 0,
+            workflows: vec![],
+        }
+    }
+
+    #[test]
+    fn schemas_match_api_types() {
+        check_schema(
+            "QueueEntry",
+            &ApiQueueEntry {
+                number: 1,
+                status: "approved",
+                priority: 0,
+                approved_by: None,
+                approved_at: None,
+                try_build: None,
+            },
+        );
+        check_schema(
+            "PullRequest",
+            &ApiPullRequest {
+                number: 1,
+                approved_by: None,
+                approved_at: None,
+                priority: 0,
+                delegated_to: None,
+                try_build: None,
+            },
+        );
+        check_schema("Build", &build());
+        check_schema(
+            "Workflow",
+            &ApiWorkflow {
+                name: "CI".to_string(),
+                url: "https://ci".to_string(),
+                run_id: 1,
+                status: "success",
+                finished_at: None,
+                artifacts: vec![],
+            },
+        );
+        let entry = HistoryEntry {
+            id: 1,
+            pr: None,
+            branch: "automation/bors/try".to_string(),
+            commit_sha: "sha".to_string(),
+            status: "success",
+            approved_by: None,
+            created_at: Utc::now(),
+            duration_secs: None,
+            failed_checks: vec![],
+        };
+        check_schema("HistoryEntry", &entry);
+        check_schema(
+            "HistoryPage",
+            &HistoryPage {
+                page: 1,
+                has_next: false,
+                builds: vec![entry],
+            },
+        );
+        check_schema(
+            "Statistics",
+            &MergeStatistics {
+                since: Utc::now(),
+                until: Utc::now(),
+                landed_prs: 0,
+                median_time_to_merge_secs: None,
+                builds_per_day: 0.0,
+                retry_rate: 0.0,
+                retries_per_pr: 0.0,
+                rollup_share: 0.0,
+                duration_regressions: vec![],
+            },
+        );
+        check_schema(
+            "DurationRegression",
+            &DurationRegression {
+                workflow: "test".to_string(),
+                previous_median_secs: 100,
+                current_median_secs: 150,
+                increase: 0.5,
+            },
+        );
+        check_schema(
+            "AuditRecord",
+            &AuditRecord {
+                time: Utc::now(),
+                repository: "owner/name".to_string(),
+                pr: Some(1),
+                action: "merged",
+                actor: None,
+                command: None,
+                approved_by: Some("reviewer".to_string()),
+            },
+        );
+    }
+
+    #[test]
+    fn references_exist() {
+        let document = openapi_document();
+        let text = document.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(
+                document["components"]["schemas"][name].is_object(),
+                "Schema {name} is referenced, but it does not exist"
+            );
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use axum::async_trait;
 use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::time::{Duration, SystemTime};
-use tokio::sync::Mutex;
 
 use crate::github::GithubRepoName;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PermissionType {
     /// Can perform commands like r+.
     Review,
@@ -12,54 +13,138 @@ pub enum PermissionType {
     Try,
 }
 
+impl PermissionType {
+    pub fn name(self) -> &'static str {
+        match self {
+            PermissionType::Review => "review",
+            PermissionType::Try => "try",
+        }
+    }
+}
+
+/// Users that have gained or lost a permission since the previous load of the permissions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionChange {
+    pub permission: PermissionType,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Compares two sets of users that have the given permission. Returns `None` if they are the same.
+pub(crate) fn diff_users(
+    permission: PermissionType,
+    previous: &HashSet<String>,
+    current: &HashSet<String>,
+) -> Option<PermissionChange> {
+    let mut added: Vec<String> = current.difference(previous).cloned().collect();
+    let mut removed: Vec<String> = previous.difference(current).cloned().collect();
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+    added.sort();
+    removed.sort();
+    Some(PermissionChange {
+        permission,
+        added,
+        removed,
+    })
+}
+
 /// Decides if a GitHub user can perform various actions using the bot.
 #[async_trait]
 pub trait PermissionResolver {
     async fn has_permission(&self, username: &str, permission: PermissionType) -> bool;
+
+    /// Takes the changes of permissions found by the reloads since the last call.
+    fn take_changes(&self) -> Vec<PermissionChange> {
+        vec![]
+    }
 }
 
 /// For how long should the permissions be cached.
-const CACHE_DURATION: Duration = Duration::from_secs(60);
+pub(crate) const CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Permissions of a repository shared by its resolver and the background task that reloads them.
+struct SharedPermissions {
+    cached: RwLock<Arc<CachedUserPermissions>>,
+    changes: Mutex<Vec<PermissionChange>>,
+}
 
 /// Loads permission information from the Rust Team API.
+///
+/// Lookups only read the cached permissions, which are reloaded by a background task every
+/// [`CACHE_DURATION`], so concurrent lookups never wait for each other or for the reload.
 pub struct TeamApiPermissionResolver {
-    repo: GithubRepoName,
-    permissions: Mutex<CachedUserPermissions>,
+    permissions: Arc<SharedPermissions>,
 }
 
 impl TeamApiPermissionResolver {
     pub async fn load(repo: GithubRepoName) -> anyhow::Result<Self> {
         let permissions = load_permissions(&repo).await?;
+        let permissions = Arc::new(SharedPermissions {
+            cached: RwLock::new(Arc::new(CachedUserPermissions::new(permissions))),
+            changes: Mutex::new(vec![]),
+        });
+        tokio::spawn(refresh_permissions(repo, Arc::downgrade(&permissions)));
 
-        Ok(Self {
-            repo,
-            permissions: Mutex::new(CachedUserPermissions::new(permissions)),
-        })
+        Ok(Self { permissions })
     }
 
-    async fn reload_permissions(&self) {
-        let result = load_permissions(&self.repo).await;
-        match result {
-            Ok(perms) => *self.permissions.lock().await = CachedUserPermissions::new(perms),
-            Err(error) => {
-                tracing::error!("Cannot reload permissions for {}: {error:?}", self.repo);
-            }
-        }
+    fn cached(&self) -> Arc<CachedUserPermissions> {
+        self.permissions.cached.read().unwrap().clone()
     }
 }
 
 #[async_trait]
 impl PermissionResolver for TeamApiPermissionResolver {
     async fn has_permission(&self, username: &str, permission: PermissionType) -> bool {
-        if self.permissions.lock().await.is_stale() {
-            self.reload_permissions().await;
+        let cached = self.cached();
+        if cached.is_stale() {
+            tracing::warn!(
+                "Permissions have not been reloaded for {:?}",
+                cached.created_at.elapsed().unwrap_or_default()
+            );
         }
+        cached.permissions.has_permission(username, permission)
+    }
 
-        self.permissions
-            .lock()
-            .await
-            .permissions
-            .has_permission(username, permission)
+    fn take_changes(&self) -> Vec<PermissionChange> {
+        std::mem::take(&mut *self.permissions.changes.lock().unwrap())
+    }
+}
+
+/// Reloads the permissions of the repository periodically until its resolver is dropped (e.g.
+/// because the repositories of the bot have been reloaded). The previous permissions are kept
+/// when they cannot be loaded.
+async fn refresh_permissions(repo: GithubRepoName, permissions: Weak<SharedPermissions>) {
+    loop {
+        tokio::time::sleep(CACHE_DURATION).await;
+        if permissions.strong_count() == 0 {
+            return;
+        }
+        let result = load_permissions(&repo).await;
+        let Some(permissions) = permissions.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(perms) => {
+                let mut cached = permissions.cached.write().unwrap();
+                let changes = cached.permissions.diff(&perms);
+                for change in &changes {
+                    tracing::info!(
+                        "Permission `{}` of {repo} granted to {:?} and revoked from {:?}",
+                        change.permission.name(),
+                        change.added,
+                        change.removed
+                    );
+                }
+                *cached = Arc::new(CachedUserPermissions::new(perms));
+                permissions.changes.lock().unwrap().extend(changes);
+            }
+            Err(error) => {
+                tracing::error!("Cannot reload permissions for {repo}: {error:?}");
+            }
+        }
     }
 }
 
@@ -75,6 +160,21 @@ impl UserPermissions {
             PermissionType::Try => self.try_users.contains(username),
         }
     }
+
+    /// Finds the users that have gained or lost a permission in `current`.
+    fn diff(&self, current: &UserPermissions) -> Vec<PermissionChange> {
+        [
+            diff_users(
+                PermissionType::Review,
+                &self.review_users,
+                &current.review_users,
+            ),
+            diff_users(PermissionType::Try, &self.try_users, &current.try_users),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
 }
 
 struct CachedUserPermissions {
@@ -89,10 +189,11 @@ impl CachedUserPermissions {
         }
     }
 
+    /// Have the permissions not been reloaded for a long time, e.g. because the reloads fail?
     fn is_stale(&self) -> bool {
         self.created_at
             .elapsed()
-            .map(|duration| duration > CACHE_DURATION)
+            .map(|duration| duration > CACHE_DURATION * 10)
             .unwrap_or(true)
     }
 }
@@ -121,10 +222,7 @@ fn load_users_from_team_api(
     repository_name: &str,
     permission: PermissionType,
 ) -> anyhow::Result<HashSet<String>> {
-    let permission = match permission {
-        PermissionType::Review => "review",
-        PermissionType::Try => "try",
-    };
+    let permission = permission.name();
 
     let filename = format!(
         "{}/bors.{repository_name}.{permission}.json",
@@ -140,7 +238,46 @@ fn load_users_from_team_api(
 mod tests {
     use std::collections::HashSet;
 
-    use super::load_users_from_team_api;
+    use super::{
+        diff_users, load_users_from_team_api, PermissionResolver, PermissionType,
+        TeamApiPermissionResolver,
+    };
+    use crate::github::GithubRepoName;
+
+    #[test]
+    fn diff_permissions() {
+        let users = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let change = diff_users(
+            PermissionType::Review,
+            &users(&["alice", "bob"]),
+            &users(&["bob", "carol", "dave"]),
+        )
+        .unwrap();
+        assert_eq!(change.added, vec!["carol".to_string(), "dave".to_string()]);
+        assert_eq!(change.removed, vec!["alice".to_string()]);
+        assert_eq!(
+            diff_users(PermissionType::Try, &users(&["bob"]), &users(&["bob"])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_cached_permissions() {
+        let resolver =
+            TeamApiPermissionResolver::load(GithubRepoName::new("rust-lang", "__cargo-test"))
+                .await
+                .unwrap();
+        assert!(
+            resolver
+                .has_permission("some_user_name", PermissionType::Review)
+                .await
+        );
+        assert!(
+            !resolver
+                .has_permission("other_user_name", PermissionType::Try)
+                .await
+        );
+    }
 
     #[test]
     fn test_load_users_from_team_api_review() {
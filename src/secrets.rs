@@ -0,0 +1,147 @@
+//! Secrets of the bot (the webhook secret, the private key of the GitHub App and the database
+//! connection string) can be stored outside of its configuration and referenced from it:
+//! - `file:<path>` reads the secret from a file (e.g. one mounted by Kubernetes).
+//! - `env:<variable>` reads the secret from an environment variable.
+//! - `vault:<path>#<key>` reads the `key` field of a HashiCorp Vault secret. The server and its
+//!   token are configured using the `VAULT_ADDR` and `VAULT_TOKEN` environment variables.
+//!
+//! Any other value is the secret itself.
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::http::SendLimited;
+
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a secret is loaded from.
+#[derive(Clone, Debug)]
+pub enum SecretSource {
+    /// The secret is stored directly in the configuration.
+    Value(SecretString),
+    File(PathBuf),
+    Env(String),
+    Vault {
+        path: String,
+        key: String,
+    },
+}
+
+impl SecretSource {
+    /// Parses a secret reference, see the module documentation for the supported formats.
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        if let Some(path) = value.strip_prefix("file:") {
+            return Ok(Self::File(PathBuf::from(path)));
+        }
+        if let Some(variable) = value.strip_prefix("env:") {
+            return Ok(Self::Env(variable.to_string()));
+        }
+        if let Some(reference) = value.strip_prefix("vault:") {
+            let (path, key) = reference.split_once('#').ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Vault secret reference `{value}` must have the format `vault:<path>#<key>`"
+                )
+            })?;
+            return Ok(Self::Vault {
+                path: path.trim_matches('/').to_string(),
+                key: key.to_string(),
+            });
+        }
+        Ok(Self::Value(SecretString::new(value.to_string())))
+    }
+
+    /// Does the secret have to be loaded again to notice that it has been rotated?
+    pub fn can_rotate(&self) -> bool {
+        !matches!(self, Self::Value(_))
+    }
+
+    /// Loads the current value of the secret.
+    pub async fn load(&self) -> anyhow::Result<SecretString> {
+        let secret = match self {
+            Self::Value(secret) => secret.expose_secret().clone(),
+            Self::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Cannot read secret from {}", path.display()))?
+                .trim_end()
+                .to_string(),
+            Self::Env(variable) => std::env::var(variable).with_context(|| {
+                format!("Cannot read secret from environment variable {variable}")
+            })?,
+            Self::Vault { path, key } => load_vault_secret(path, key)
+                .await
+                .with_context(|| format!("Cannot read secret {path}#{key} from Vault"))?,
+        };
+        Ok(SecretString::new(secret))
+    }
+}
+
+/// Reads a field of a secret from Vault. Both the KV version 1 (`data.<key>`) and version 2
+/// (`data.data.<key>`) response formats are supported.
+async fn load_vault_secret(path: &str, key: &str) -> anyhow::Result<String> {
+    let address = std::env::var("VAULT_ADDR").context("VAULT_ADDR is not set")?;
+    let token = std::env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?;
+    let response = crate::http::client()
+        .get(format!("{}/v1/{path}", address.trim_end_matches('/')))
+        .header("X-Vault-Token", token)
+        .timeout(VAULT_TIMEOUT)
+        .send_limited()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: serde_json::Value = serde_json::from_str(&response)
+        .map_err(|error| anyhow::anyhow!("Cannot parse Vault response: {error:?}"))?;
+    vault_secret_field(&response, key)
+        .ok_or_else(|| anyhow::anyhow!("Vault secret does not contain the `{key}` field"))
+}
+
+fn vault_secret_field(response: &serde_json::Value, key: &str) -> Option<String> {
+    let data = &response["data"];
+    data["data"][key]
+        .as_str()
+        .or_else(|| data[key].as_str())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use crate::secrets::{vault_secret_field, SecretSource};
+
+    #[test]
+    fn parse_references() {
+        assert!(matches!(
+            SecretSource::parse("file:/run/secrets/webhook").unwrap(),
+            SecretSource::File(path) if path.to_str() == Some("/run/secrets/webhook")
+        ));
+        assert!(matches!(
+            SecretSource::parse("vault:/secret/data/bors#webhook_secret").unwrap(),
+            SecretSource::Vault { path, key } if path == "secret/data/bors" && key == "webhook_secret"
+        ));
+        assert!(SecretSource::parse("vault:secret/data/bors").is_err());
+        assert!(!SecretSource::parse("plain").unwrap().can_rotate());
+    }
+
+    #[tokio::test]
+    async fn load_from_env() {
+        std::env::set_var("BORS_TEST_SECRET", "hunter2");
+        let secret = SecretSource::parse("env:BORS_TEST_SECRET")
+            .unwrap()
+            .load()
+            .await
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "hunter2");
+    }
+
+    #[test]
+    fn vault_response_formats() {
+        let v1 = serde_json::json!({"data": {"token": "a"}});
+        let v2 = serde_json::json!({"data": {"data": {"token": "b"}, "metadata": {}}});
+        assert_eq!(vault_secret_field(&v1, "token").as_deref(), Some("a"));
+        assert_eq!(vault_secret_field(&v2, "token").as_deref(), Some("b"));
+        assert_eq!(vault_secret_field(&v2, "missing"), None);
+    }
+}
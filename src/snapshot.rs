@@ -0,0 +1,369 @@
+//! Export and import of all persistent state of the bot (repositories, PRs with their approvals
+//! and queue state, builds, workflows, the event log, API tokens, ...), so that a deployment can
+//! move to another database or host, e.g. during a blue/green deployment.
+//!
+//! A snapshot is a JSON document with the rows of all tables. It can only be imported into an
+//! empty database that has been migrated to the same schema as the exported database, and the
+//! primary keys are preserved, so that references between the tables stay valid.
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, EntityName,
+    EntityTrait, Insert, Statement, TransactionTrait,
+};
+
+use entity::{
+    api_token, build, event_log, job_log, pull_request, repository, webhook_delivery, workflow,
+};
+use migration::{MigrationName, Migrator, MigratorTrait};
+
+/// Version of the format of snapshots.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Number of rows inserted by a single statement, which keeps the number of bound parameters below
+/// the limit of SQLite.
+const IMPORT_BATCH_SIZE: usize = 50;
+
+/// Defines the row of a table stored in a snapshot and its conversions from and to the entity.
+macro_rules! snapshot_row {
+    ($row:ident, $entity:ident { $($field:ident: $ty:ty),* $(,)? }) => {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+        pub struct $row {
+            $(pub $field: $ty),*
+        }
+
+        impl From<$entity::Model> for $row {
+            fn from(model: $entity::Model) -> Self {
+                Self {
+                    $($field: model.$field),*
+                }
+            }
+        }
+
+        impl From<$row> for $entity::ActiveModel {
+            fn from(row: $row) -> Self {
+                Self {
+                    $($field: Set(row.$field)),*
+                }
+            }
+        }
+    };
+}
+
+snapshot_row! {
+    RepositoryRow, repository {
+        id: i32,
+        name: String,
+        tree_closed_priority: Option<i32>,
+        tree_closed_by: Option<String>,
+        tree_closed_reason: Option<String>,
+        tree_closed_at: Option<NaiveDateTime>,
+        paused_by: Option<String>,
+        pause_reason: Option<String>,
+        paused_at: Option<NaiveDateTime>,
+    }
+}
+
+snapshot_row! {
+    BuildRow, build {
+        id: i32,
+        repository: String,
+        branch: String,
+        commit_sha: String,
+        status: String,
+        created_at: NaiveDateTime,
+        attempt: i32,
+        retry_at: Option<NaiveDateTime>,
+        pr_head_sha: Option<String>,
+        parent_sha: Option<String>,
+        pr_commit_sha: Option<String>,
+        jobs: Option<String>,
+    }
+}
+
+snapshot_row! {
+    PullRequestRow, pull_request {
+        id: i32,
+        repository: String,
+        number: i32,
+        try_build: Option<i32>,
+        created_at: NaiveDateTime,
+        status_comment: Option<String>,
+        approved_by: Option<String>,
+        summary_comment: Option<String>,
+        approved_at: Option<NaiveDateTime>,
+        priority: i32,
+        rollup: Option<String>,
+        delegated_to: Option<String>,
+        updated_at: Option<NaiveDateTime>,
+        approved_sha: Option<String>,
+    }
+}
+
+snapshot_row! {
+    WorkflowRow, workflow {
+        id: i32,
+        build: i32,
+        name: String,
+        run_id: i64,
+        url: String,
+        status: String,
+        r#type: String,
+        created_at: NaiveDateTime,
+        finished_at: Option<NaiveDateTime>,
+        artifacts: Option<String>,
+    }
+}
+
+snapshot_row! {
+    EventRow, event_log {
+        id: i32,
+        repository: String,
+        pr_number: Option<i32>,
+        data: String,
+        created_at: NaiveDateTime,
+        kind: Option<String>,
+    }
+}
+
+snapshot_row! {
+    JobLogRow, job_log {
+        id: i32,
+        repository: String,
+        run_id: i64,
+        job_name: String,
+        excerpt: String,
+        created_at: NaiveDateTime,
+    }
+}
+
+snapshot_row! {
+    ApiTokenRow, api_token {
+        id: i32,
+        name: String,
+        token_hash: String,
+        scopes: String,
+        created_at: NaiveDateTime,
+        expires_at: Option<NaiveDateTime>,
+        revoked_at: Option<NaiveDateTime>,
+        repositories: Option<String>,
+    }
+}
+
+snapshot_row! {
+    WebhookDeliveryRow, webhook_delivery {
+        id: i32,
+        delivery_id: String,
+        event_type: String,
+        payload_hash: String,
+        payload: String,
+        result: String,
+        received_at: NaiveDateTime,
+    }
+}
+
+/// All persistent state of the bot.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Name of the last migration of the exported database.
+    pub schema: String,
+    pub created_at: DateTime<Utc>,
+    pub repositories: Vec<RepositoryRow>,
+    pub builds: Vec<BuildRow>,
+    pub pull_requests: Vec<PullRequestRow>,
+    pub workflows: Vec<WorkflowRow>,
+    pub events: Vec<EventRow>,
+    pub job_logs: Vec<JobLogRow>,
+    pub api_tokens: Vec<ApiTokenRow>,
+    pub webhook_deliveries: Vec<WebhookDeliveryRow>,
+}
+
+impl Snapshot {
+    /// Number of rows of each table in the snapshot.
+    pub fn row_counts(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("repositories", self.repositories.len()),
+            ("builds", self.builds.len()),
+            ("pull requests", self.pull_requests.len()),
+            ("workflows", self.workflows.len()),
+            ("events", self.events.len()),
+            ("job logs", self.job_logs.len()),
+            ("API tokens", self.api_tokens.len()),
+            ("webhook deliveries", self.webhook_deliveries.len()),
+        ]
+    }
+}
+
+/// Reads all tables of the database in a single transaction.
+pub async fn export_snapshot(db: &DatabaseConnection) -> anyhow::Result<Snapshot> {
+    let tx = db.begin().await?;
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        schema: current_schema(),
+        created_at: Utc::now(),
+        repositories: load_rows::<repository::Entity, _, _>(&tx).await?,
+        builds: load_rows::<build::Entity, _, _>(&tx).await?,
+        pull_requests: load_rows::<pull_request::Entity, _, _>(&tx).await?,
+        workflows: load_rows::<workflow::Entity, _, _>(&tx).await?,
+        events: load_rows::<event_log::Entity, _, _>(&tx).await?,
+        job_logs: load_rows::<job_log::Entity, _, _>(&tx).await?,
+        api_tokens: load_rows::<api_token::Entity, _, _>(&tx).await?,
+        webhook_deliveries: load_rows::<webhook_delivery::Entity, _, _>(&tx).await?,
+    };
+    tx.commit().await?;
+    Ok(snapshot)
+}
+
+/// Stores the content of the snapshot in an empty database. Either everything is imported, or
+/// nothing is.
+pub async fn import_snapshot(db: &DatabaseConnection, snapshot: Snapshot) -> anyhow::Result<()> {
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported snapshot version {}, expected {SNAPSHOT_VERSION}",
+            snapshot.version
+        ));
+    }
+    let schema = current_schema();
+    if snapshot.schema != schema {
+        return Err(anyhow::anyhow!(
+            "The snapshot was exported from a database with schema `{}`, but this version of bors \
+             uses `{schema}`. Export and import the snapshot with the same version of bors.",
+            snapshot.schema
+        ));
+    }
+
+    let tx = db.begin().await?;
+    // Parents are inserted before the rows that refer to them
+    insert_rows::<repository::ActiveModel, _>(&tx, snapshot.repositories).await?;
+    insert_rows::<build::ActiveModel, _>(&tx, snapshot.builds).await?;
+    insert_rows::<pull_request::ActiveModel, _>(&tx, snapshot.pull_requests).await?;
+    insert_rows::<workflow::ActiveModel, _>(&tx, snapshot.workflows).await?;
+    insert_rows::<event_log::ActiveModel, _>(&tx, snapshot.events).await?;
+    insert_rows::<job_log::ActiveModel, _>(&tx, snapshot.job_logs).await?;
+    insert_rows::<api_token::ActiveModel, _>(&tx, snapshot.api_tokens).await?;
+    insert_rows::<webhook_delivery::ActiveModel, _>(&tx, snapshot.webhook_deliveries).await?;
+
+    // Sequences of PostgreSQL are not advanced by inserting explicit primary keys
+    if tx.get_database_backend() == DatabaseBackend::Postgres {
+        for table in [
+            repository::Entity.table_name(),
+            build::Entity.table_name(),
+            pull_request::Entity.table_name(),
+            workflow::Entity.table_name(),
+            event_log::Entity.table_name(),
+            job_log::Entity.table_name(),
+            api_token::Entity.table_name(),
+            webhook_delivery::Entity.table_name(),
+        ] {
+            tx.execute(Statement::from_string(
+                DatabaseBackend::Postgres,
+                format!(
+                    "SELECT setval(pg_get_serial_sequence('{table}', 'id'), \
+                     COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM {table}"
+                ),
+            ))
+            .await
+            .with_context(|| format!("Cannot update the sequence of `{table}`"))?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn current_schema() -> String {
+    Migrator::migrations()
+        .last()
+        .map(|migration| migration.name().to_string())
+        .unwrap_or_default()
+}
+
+async fn load_rows<E, R, C>(db: &C) -> anyhow::Result<Vec<R>>
+where
+    E: EntityTrait,
+    R: From<E::Model>,
+    C: ConnectionTrait,
+{
+    let rows = E::find()
+        .all(db)
+        .await
+        .with_context(|| format!("Cannot read table `{}`", E::default().table_name()))?;
+    Ok(rows.into_iter().map(R::from).collect())
+}
+
+async fn insert_rows<A, R>(db: &impl ConnectionTrait, rows: Vec<R>) -> anyhow::Result<()>
+where
+    A: ActiveModelTrait + Send,
+    R: Into<A>,
+{
+    let table = A::Entity::default().table_name();
+    if <A::Entity as EntityTrait>::find().one(db).await?.is_some() {
+        return Err(anyhow::anyhow!(
+            "Table `{table}` is not empty, a snapshot can only be imported into an empty database"
+        ));
+    }
+    let mut rows = rows.into_iter().map(Into::into).peekable();
+    while rows.peek().is_some() {
+        let batch: Vec<A> = rows.by_ref().take(IMPORT_BATCH_SIZE).collect();
+        Insert::<A>::many(batch)
+            .exec(db)
+            .await
+            .with_context(|| format!("Cannot insert rows into `{table}`"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::DbClient;
+    use crate::github::PullRequestNumber;
+    use crate::snapshot::{export_snapshot, import_snapshot};
+    use crate::tests::database::create_test_db;
+    use crate::tests::state::default_repo_name;
+
+    #[tokio::test]
+    async fn export_and_import_snapshot() {
+        let mut source = create_test_db().await;
+        let repo = default_repo_name();
+        let pr = source
+            .get_or_create_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap();
+        source.approve(&pr, "alice", None).await.unwrap();
+        source.set_priority(&pr, 5).await.unwrap();
+        source
+            .close_tree(&repo, 10, Some("bob"), "Release")
+            .await
+            .unwrap();
+
+        let snapshot = export_snapshot(source.connection()).await.unwrap();
+        assert_eq!(snapshot.pull_requests.len(), 1);
+        let json = serde_json::to_string(&snapshot).unwrap();
+
+        let mut target = create_test_db().await;
+        import_snapshot(target.connection(), serde_json::from_str(&json).unwrap())
+            .await
+            .unwrap();
+        let imported = target
+            .find_pull_request(&repo, PullRequestNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported.approved_by.as_deref(), Some("alice"));
+        assert_eq!(imported.priority, 5);
+        assert!(target.get_tree_closure(&repo).await.unwrap().is_some());
+
+        // New rows do not collide with the imported primary keys
+        target
+            .get_or_create_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap();
+
+        // The database is no longer empty
+        let snapshot = export_snapshot(target.connection()).await.unwrap();
+        assert!(import_snapshot(target.connection(), snapshot)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,490 @@
+//! Statistics about the CI and merge health of a repository, computed from the builds, pull
+//! requests and the event log stored in the database.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::{DbClient, LoggedEvent, QueueStatus, WorkflowStatus};
+use crate::github::GithubRepoName;
+
+pub struct RepositoryStatistics {
+    pub repository: GithubRepoName,
+    /// Number of PRs that are currently approved.
+    pub queued_prs: usize,
+    /// How long have the currently approved PRs been waiting in the queue, on average.
+    pub average_queue_wait: Option<Duration>,
+    /// Average number of builds of a PR that have retried a commit that had already been built,
+    /// over the PRs that have been built.
+    pub retries_per_pr: f64,
+    /// Statistics of each workflow, sorted by the workflow name.
+    pub workflows: Vec<WorkflowStatistics>,
+}
+
+/// Statistics of the finished runs of a single workflow.
+#[derive(Debug, PartialEq)]
+pub struct WorkflowStatistics {
+    pub name: String,
+    pub runs: u64,
+    pub failures: u64,
+    /// Runs that finished before their completion time was stored are not included.
+    pub average_duration: Option<Duration>,
+}
+
+impl WorkflowStatistics {
+    pub fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.runs as f64
+        }
+    }
+}
+
+pub async fn repository_statistics(
+    db: &dyn DbClient,
+    repo: &GithubRepoName,
+) -> anyhow::Result<RepositoryStatistics> {
+    let now = Utc::now();
+    let queue_waits: Vec<Duration> = db
+        .get_approved_pull_requests(repo)
+        .await?
+        .into_iter()
+        .filter_map(|pr| match pr.queue_status() {
+            QueueStatus::Approved { approved_at, .. } => Some(now - approved_at),
+            QueueStatus::NotApproved => None,
+        })
+        .collect();
+
+    let mut workflows: BTreeMap<String, (WorkflowStatistics, Vec<Duration>)> = BTreeMap::new();
+    for workflow in db.get_workflows_for_repository(repo, None).await? {
+        if workflow.status == WorkflowStatus::Pending {
+            continue;
+        }
+        let duration = workflow.duration();
+        let (stats, durations) = workflows.entry(workflow.name.clone()).or_insert_with(|| {
+            (
+                WorkflowStatistics {
+                    name: workflow.name,
+                    runs: 0,
+                    failures: 0,
+                    average_duration: None,
+                },
+                vec![],
+            )
+        });
+        stats.runs += 1;
+        if workflow.status == WorkflowStatus::Failure {
+            stats.failures += 1;
+        }
+        durations.extend(duration);
+    }
+
+    let mut built_commits: HashSet<String> = HashSet::new();
+    let mut retries: HashMap<u64, usize> = HashMap::new();
+    for event in db
+        .get_events_of_kinds(repo, None, &["build_started"])
+        .await?
+    {
+        if let (LoggedEvent::BuildStarted { commit_sha, .. }, Some(pr)) = (event.event, event.pr) {
+            let retried = !built_commits.insert(commit_sha);
+            *retries.entry(pr.0).or_default() += usize::from(retried);
+        }
+    }
+
+    Ok(RepositoryStatistics {
+        repository: repo.clone(),
+        queued_prs: queue_waits.len(),
+        average_queue_wait: average(&queue_waits),
+        retries_per_pr: share(retries.values().sum(), retries.len()),
+        workflows: workflows
+            .into_values()
+            .map(|(stats, durations)| WorkflowStatistics {
+                average_duration: average(&durations),
+                ..stats
+            })
+            .collect(),
+    })
+}
+
+/// A workflow is considered to have slowed down if its median duration has increased by at least
+/// this share compared to the previous week.
+pub const REGRESSION_THRESHOLD: f64 = 0.3;
+
+/// Minimum number of successful runs of a workflow in each week for a slowdown to be reported, so
+/// that a single slow run does not cause a false alarm.
+pub const REGRESSION_MIN_RUNS: usize = 5;
+
+/// A significant increase of the median duration of a workflow in the last week, compared to the
+/// week before.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct DurationRegression {
+    pub workflow: String,
+    pub previous_median_secs: i64,
+    pub current_median_secs: i64,
+    /// Relative increase of the median duration, e.g. `0.5` for 50%.
+    pub increase: f64,
+}
+
+impl Display for DurationRegression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The median duration of workflow `{}` has increased by {:.0}% in the last week ({} -> {})",
+            self.workflow,
+            self.increase * 100.0,
+            format_duration(Duration::seconds(self.previous_median_secs)),
+            format_duration(Duration::seconds(self.current_median_secs))
+        )
+    }
+}
+
+/// Finds workflows whose median duration in the week before `now` has increased by at least
+/// [`REGRESSION_THRESHOLD`] compared to the week before it.
+///
+/// Only successful runs are compared, because failed runs often end early.
+pub async fn duration_regressions(
+    db: &dyn DbClient,
+    repo: &GithubRepoName,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<DurationRegression>> {
+    let week = Duration::days(7);
+    // Durations of runs in the previous and in the current week, by workflow name
+    let mut durations: BTreeMap<String, (Vec<i64>, Vec<i64>)> = BTreeMap::new();
+    let workflows = db
+        .get_workflows_for_repository(repo, Some(now - week * 2))
+        .await?;
+    for workflow in workflows {
+        if workflow.status != WorkflowStatus::Success || workflow.created_at >= now {
+            continue;
+        }
+        let Some(duration) = workflow.duration() else {
+            continue;
+        };
+        let (previous, current) = durations.entry(workflow.name).or_default();
+        if workflow.created_at >= now - week {
+            current.push(duration.num_seconds());
+        } else if workflow.created_at >= now - week * 2 {
+            previous.push(duration.num_seconds());
+        }
+    }
+
+    Ok(durations
+        .into_iter()
+        .filter_map(|(workflow, (previous, current))| {
+            detect_regression(workflow, previous, current)
+        })
+        .collect())
+}
+
+/// Compares the durations (in seconds) of the runs of a workflow in two weeks.
+fn detect_regression(
+    workflow: String,
+    mut previous: Vec<i64>,
+    mut current: Vec<i64>,
+) -> Option<DurationRegression> {
+    if previous.len() < REGRESSION_MIN_RUNS || current.len() < REGRESSION_MIN_RUNS {
+        return None;
+    }
+    previous.sort_unstable();
+    current.sort_unstable();
+    let previous = median(&previous)?;
+    let current = median(&current)?;
+    if previous <= 0 {
+        return None;
+    }
+    let increase = (current - previous) as f64 / previous as f64;
+    (increase >= REGRESSION_THRESHOLD).then_some(DurationRegression {
+        workflow,
+        previous_median_secs: previous,
+        current_median_secs: current,
+        increase,
+    })
+}
+
+/// Statistics of the merges and builds of a repository in a period, computed from its event log.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct MergeStatistics {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Number of PRs merged in the period.
+    pub landed_prs: usize,
+    /// Median time between the approval of the PRs merged in the period and their merge.
+    pub median_time_to_merge_secs: Option<i64>,
+    /// Number of builds started in the period, per day.
+    pub builds_per_day: f64,
+    /// Share of the builds started in the period that have retried a commit that had already
+    /// been built.
+    pub retry_rate: f64,
+    /// Average number of builds started in the period that have retried a commit that had already
+    /// been built, over the PRs built in the period.
+    pub retries_per_pr: f64,
+    /// Share of the PRs merged in the period that were marked for a rollup (`rollup=always`,
+    /// `maybe` or `iffy`) when they were merged.
+    pub rollup_share: f64,
+    /// Workflows that have slowed down in the last week of the period.
+    pub duration_regressions: Vec<DurationRegression>,
+}
+
+/// Computes the merge statistics of the repository for the period between `since` and `until`.
+pub async fn merge_statistics(
+    db: &dyn DbClient,
+    repo: &GithubRepoName,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<MergeStatistics> {
+    let in_period = |time: DateTime<Utc>| time >= since && time < until;
+
+    let mut approvals: HashMap<u64, DateTime<Utc>> = HashMap::new();
+    // Rollup modes of PRs, as recorded in the log
+    let mut rollup_modes: HashMap<u64, String> = HashMap::new();
+    let mut times_to_merge = vec![];
+    let mut landed = 0;
+    let mut rollups = 0;
+    // Commits that have been built, before or during the period
+    let mut built_commits: HashSet<String> = HashSet::new();
+    let mut started_builds = 0;
+    let mut retried_builds = 0;
+    // Number of retried builds started in the period, by PR
+    let mut retries: HashMap<u64, usize> = HashMap::new();
+    let kinds = ["approved", "rollup_changed", "merged", "build_started"];
+    for event in db.get_events_of_kinds(repo, None, &kinds).await? {
+        if event.created_at >= until {
+            break;
+        }
+        match event.event {
+            LoggedEvent::Approved { .. } => {
+                if let Some(pr) = event.pr {
+                    approvals.insert(pr.0, event.created_at);
+                }
+            }
+            LoggedEvent::RollupChanged { rollup } => {
+                if let Some(pr) = event.pr {
+                    match rollup {
+                        Some(rollup) => rollup_modes.insert(pr.0, rollup),
+                        None => rollup_modes.remove(&pr.0),
+                    };
+                }
+            }
+            LoggedEvent::Merged if in_period(event.created_at) => {
+                if let Some(pr) = event.pr {
+                    if let Some(approved_at) = approvals.get(&pr.0) {
+                        times_to_merge.push((event.created_at - *approved_at).num_seconds());
+                    }
+                    landed += 1;
+                    if rollup_modes
+                        .get(&pr.0)
+                        .map_or(false, |rollup| rollup != "never")
+                    {
+                        rollups += 1;
+                    }
+                }
+            }
+            LoggedEvent::BuildStarted { commit_sha, .. } => {
+                let retried = !built_commits.insert(commit_sha);
+                if in_period(event.created_at) {
+                    started_builds += 1;
+                    if retried {
+                        retried_builds += 1;
+                    }
+                    if let Some(pr) = event.pr {
+                        *retries.entry(pr.0).or_default() += usize::from(retried);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    times_to_merge.sort_unstable();
+    let days = (until - since).num_seconds() as f64 / 86400.0;
+    Ok(MergeStatistics {
+        since,
+        until,
+        landed_prs: landed,
+        median_time_to_merge_secs: median(&times_to_merge),
+        builds_per_day: if days > 0.0 {
+            started_builds as f64 / days
+        } else {
+            0.0
+        },
+        retry_rate: share(retried_builds, started_builds),
+        retries_per_pr: share(retries.values().sum(), retries.len()),
+        rollup_share: share(rollups, landed),
+        duration_regressions: duration_regressions(db, repo, until).await?,
+    })
+}
+
+/// Median of sorted values.
+fn median(values: &[i64]) -> Option<i64> {
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[len / 2 - 1] + values[len / 2]) / 2),
+        len => Some(values[len / 2]),
+    }
+}
+
+fn share(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+fn average(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    let total = durations
+        .iter()
+        .fold(Duration::zero(), |total, duration| total + *duration);
+    Some(total / durations.len() as i32)
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    match seconds {
+        s if s >= 3600 => format!("{}h {}m", s / 3600, (s % 3600) / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{s}s"),
+    }
+}
+
+impl Display for RepositoryStatistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Statistics of {}", self.repository)?;
+        write!(f, "Queued PRs: {}", self.queued_prs)?;
+        if let Some(wait) = self.average_queue_wait {
+            write!(f, " (average wait: {})", format_duration(wait))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Retries per PR: {:.2}", self.retries_per_pr)?;
+        writeln!(f, "Workflows:")?;
+        for workflow in &self.workflows {
+            write!(
+                f,
+                "- {}: {} run(s), {:.1}% failed",
+                workflow.name,
+                workflow.runs,
+                workflow.failure_rate() * 100.0
+            )?;
+            if let Some(duration) = workflow.average_duration {
+                write!(f, ", average duration: {}", format_duration(duration))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::database::{DbClient, LoggedEvent, RollupMode, WorkflowStatus};
+    use crate::github::PullRequestNumber;
+    use crate::stats::{detect_regression, median, merge_statistics, repository_statistics};
+    use crate::tests::database::create_test_db;
+    use crate::tests::event::suite_pending;
+    use crate::tests::state::{default_merge_sha, default_repo_name, ClientBuilder};
+
+    #[tokio::test]
+    async fn statistics_of_workflows() {
+        let mut state = ClientBuilder::default().create_state().await;
+        state
+            .client()
+            .set_checks(&default_merge_sha(), &[suite_pending()]);
+        state.comment("@bors try").await;
+        let branch = "automation/bors/try";
+        state
+            .perform_workflow_events(1, branch, &default_merge_sha(), WorkflowStatus::Success)
+            .await;
+        state
+            .perform_workflow_events(2, branch, &default_merge_sha(), WorkflowStatus::Failure)
+            .await;
+        state.comment("@bors r+").await;
+
+        let stats = repository_statistics(&state.db, &default_repo_name())
+            .await
+            .unwrap();
+        assert_eq!(stats.queued_prs, 1);
+        assert_eq!(stats.workflows.len(), 2);
+        assert_eq!(stats.workflows[0].name, "workflow-1");
+        assert_eq!(stats.workflows[0].failures, 0);
+        assert_eq!(stats.workflows[1].name, "workflow-2");
+        assert_eq!(stats.workflows[1].failure_rate(), 1.0);
+        assert!(stats.workflows[1].average_duration.is_some());
+        assert_eq!(stats.retries_per_pr, 0.0);
+    }
+
+    #[tokio::test]
+    async fn merge_statistics_of_period() {
+        let db = create_test_db().await;
+        let repo = default_repo_name();
+        for (number, rollup) in [(1, RollupMode::Iffy), (2, RollupMode::Never)] {
+            let pr = db
+                .get_or_create_pull_request(&repo, PullRequestNumber(number))
+                .await
+                .unwrap();
+            db.approve(&pr, "reviewer", None).await.unwrap();
+            db.set_rollup(&pr, Some(rollup)).await.unwrap();
+            let started = LoggedEvent::BuildStarted {
+                branch: "automation/bors/try".to_string(),
+                commit_sha: "sha".to_string(),
+            };
+            db.record_event(&repo, Some(pr.number), started)
+                .await
+                .unwrap();
+            db.record_event(&repo, Some(pr.number), LoggedEvent::Merged)
+                .await
+                .unwrap();
+        }
+        // Changes of the rollup mode after the merge are not counted
+        let pr = db
+            .get_or_create_pull_request(&repo, PullRequestNumber(2))
+            .await
+            .unwrap();
+        db.set_rollup(&pr, Some(RollupMode::Always)).await.unwrap();
+
+        let until = Utc::now() + Duration::minutes(1);
+        let stats = merge_statistics(&db, &repo, until - Duration::days(2), until)
+            .await
+            .unwrap();
+        assert_eq!(stats.landed_prs, 2);
+        assert!(stats.median_time_to_merge_secs.is_some());
+        assert_eq!(stats.builds_per_day, 1.0);
+        assert_eq!(stats.retry_rate, 0.5);
+        assert_eq!(stats.retries_per_pr, 0.5);
+        assert_eq!(stats.rollup_share, 0.5);
+        assert!(stats.duration_regressions.is_empty());
+    }
+
+    #[test]
+    fn detect_slowdown_of_workflow() {
+        let regression = detect_regression(
+            "test".to_string(),
+            vec![100, 110, 90, 100, 105],
+            vec![140, 150, 130, 160, 135],
+        )
+        .unwrap();
+        assert_eq!(regression.previous_median_secs, 100);
+        assert_eq!(regression.current_median_secs, 140);
+        assert_eq!(
+            regression.to_string(),
+            "The median duration of workflow `test` has increased by 40% in the last week (1m 40s -> 2m 20s)"
+        );
+
+        // Too small increase
+        assert!(detect_regression("test".to_string(), vec![100; 5], vec![120; 5]).is_none());
+        // Too few runs
+        assert!(detect_regression("test".to_string(), vec![100; 5], vec![200; 4]).is_none());
+    }
+
+    #[test]
+    fn median_of_values() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[1, 5, 7]), Some(5));
+        assert_eq!(median(&[1, 3, 5, 7]), Some(4));
+    }
+}
@@ -0,0 +1,13 @@
+use axum::async_trait;
+
+use crate::agreement::AgreementChecker;
+
+/// Only the given users have signed the agreement.
+pub struct SignedBy(pub Vec<String>);
+
+#[async_trait]
+impl AgreementChecker for SignedBy {
+    async fn has_signed(&self, username: &str) -> anyhow::Result<bool> {
+        Ok(self.0.iter().any(|user| user == username))
+    }
+}
@@ -0,0 +1,22 @@
+use std::path::Path;
+
+use crate::fixtures::load_fixtures;
+use crate::tests::state::TestBorsState;
+
+const ROOT_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+/// Sends the webhooks recorded in `tests/data/fixtures/<scenario>` through the whole pipeline
+/// (parsing of the payloads, handlers and the database) against the test forge, in the order in
+/// which they were received.
+pub async fn replay_scenario(state: &mut TestBorsState, scenario: &str) {
+    let directory = Path::new(ROOT_DIR)
+        .join("tests")
+        .join("data")
+        .join("fixtures")
+        .join(scenario);
+    for fixture in load_fixtures(&directory).unwrap() {
+        if let Some(event) = fixture.parse().unwrap() {
+            state.event(event).await;
+        }
+    }
+}
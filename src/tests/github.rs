@@ -17,6 +17,20 @@ pub struct PR {
     title: String,
     #[builder(default = "\"PR message\".to_string()")]
     message: String,
+    #[builder(default = "\"<user>\".to_string()")]
+    author: String,
+    #[builder(default)]
+    draft: bool,
+    #[builder(default = "true")]
+    open: bool,
+    #[builder(default)]
+    merged: bool,
+    #[builder(default)]
+    labels: Vec<String>,
+    #[builder(default)]
+    changed_lines: u64,
+    #[builder(default)]
+    changed_files: u64,
 }
 
 impl PRBuilder {
@@ -28,6 +42,13 @@ impl PRBuilder {
             base,
             title,
             message,
+            author,
+            draft,
+            open,
+            merged,
+            labels,
+            changed_lines,
+            changed_files,
         } = self.build().unwrap();
 
         PullRequest {
@@ -37,6 +58,13 @@ impl PRBuilder {
             base,
             title,
             message,
+            author,
+            draft,
+            open,
+            merged,
+            labels,
+            changed_lines,
+            changed_files,
         }
     }
 
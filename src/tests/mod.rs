@@ -1,6 +1,9 @@
+pub(crate) mod agreement;
 pub(crate) mod database;
 pub(crate) mod event;
+pub(crate) mod fixtures;
 pub(crate) mod github;
 pub(crate) mod io;
+pub(crate) mod notifications;
 pub(crate) mod permissions;
 pub(crate) mod state;
@@ -0,0 +1,30 @@
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+
+use crate::notifications::{Notification, NotificationEvent, Notifier};
+
+/// Records all received notifications. Clones share the recorded notifications.
+#[derive(Clone, Default)]
+pub struct RecordingNotifier(Arc<Mutex<Vec<Notification>>>);
+
+impl RecordingNotifier {
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn events(&self) -> Vec<NotificationEvent> {
+        self.notifications()
+            .iter()
+            .map(|notification| notification.event)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Notifier for RecordingNotifier {
+    async fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        self.0.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
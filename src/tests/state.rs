@@ -2,24 +2,44 @@ use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::pin::Pin;
 use std::string::ToString;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::config::RepositoryConfig;
+use crate::config::{
+    AgreementConfig, BranchProtectionConfig, CiConfig, DigestConfig, DraftPolicy, FeatureToggles,
+    LandingMode, LogExcerptsConfig, NotificationsConfig, OutdatedCommentsMode, PostMergeConfig,
+    PullRequestLimits, RepositoryConfig, RetryPolicy, StaleRemindersConfig, TreeConfig, TryTrigger,
+    Verbosity, CURRENT_SCHEMA_VERSION,
+};
+use crate::consistency::ConsistencyReports;
+use crate::messages::MessageTemplates;
+use crate::metrics::CommandMetrics;
 use axum::async_trait;
 use derive_builder::Builder;
 use octocrab::models::RunId;
 
 use super::permissions::AllPermissions;
+use crate::agreement::AgreementChecker;
 use crate::bors::event::{
-    BorsEvent, CheckSuiteCompleted, PullRequestComment, WorkflowCompleted, WorkflowStarted,
+    BorsEvent, BranchRewrite, BranchRewritten, CheckSuiteCompleted, PullRequestChanged,
+    PullRequestClosed, PullRequestComment, PullRequestLabelChanged, ReviewDismissed,
+    WorkflowCompleted, WorkflowStarted,
+};
+use crate::bors::shard::{RepoShard, ShardedState};
+use crate::bors::{
+    handle_bors_event, BorsContext, BranchProtection, CheckSuite, CommandParser,
+    DispatchedWorkflow, JobLog, RepositoryState, WorkflowRunDetails,
 };
-use crate::bors::{handle_bors_event, BorsContext, CheckSuite, CommandParser, RepositoryState};
 use crate::bors::{BorsState, RepositoryClient};
+use crate::ci::{CiBuildRequest, CiService, CiServices};
 use crate::database::{DbClient, SeaORMClient, WorkflowStatus};
 use crate::github::{
-    CommitSha, GithubRepoName, GithubUser, LabelModification, LabelTrigger, PullRequest,
+    BranchUpdateError, CheckAnnotation, Commit, MergeError, Milestone, PullRequestNumber,
+};
+use crate::github::{
+    CommentId, CommitSha, GithubRepoName, GithubUser, LabelModification, LabelTrigger, PullRequest,
 };
-use crate::github::{MergeError, PullRequestNumber};
+use crate::notifications::NotificationRoute;
 use crate::permissions::PermissionResolver;
 use crate::tests::database::create_test_db;
 use crate::tests::event::{
@@ -45,6 +65,9 @@ pub fn default_merge_sha() -> String {
 pub struct TestBorsState {
     repos: HashMap<GithubRepoName, RepositoryState<TestRepositoryClient>>,
     pub db: SeaORMClient,
+    pub metrics: Arc<CommandMetrics>,
+    pub ci: Arc<TestCiService>,
+    pub consistency: Arc<ConsistencyReports>,
 }
 
 impl TestBorsState {
@@ -53,12 +76,22 @@ impl TestBorsState {
         &mut self.repos.get_mut(&default_repo_name()).unwrap().client
     }
 
+    /// Returns the configuration of the default repository.
+    pub fn config(&self) -> &RepositoryConfig {
+        &self.repos[&default_repo_name()].config
+    }
+
     /// Execute an event.
     pub async fn event(&mut self, event: BorsEvent) {
         handle_bors_event(
             event,
             self,
-            &BorsContext::new(CommandParser::new("@bors".to_string())),
+            &BorsContext::new(CommandParser::new("@bors".to_string()))
+                .with_metrics(self.metrics.clone())
+                .with_consistency(self.consistency.clone())
+                .with_ci(Arc::new(
+                    CiServices::default().with_service(self.ci.clone()),
+                )),
         )
         .await
         .unwrap();
@@ -82,10 +115,105 @@ impl TestBorsState {
             .await;
     }
 
+    pub async fn review_approved<T: Into<PullRequestComment>>(&mut self, review: T) {
+        self.event(BorsEvent::ReviewApproved(review.into())).await;
+    }
+
+    pub async fn review_dismissed(&mut self, pr_number: u64, reviewer: GithubUser) {
+        self.event(BorsEvent::ReviewDismissed(ReviewDismissed {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+            reviewer,
+        }))
+        .await;
+    }
+
+    pub async fn protect_branches(&mut self) {
+        self.event(BorsEvent::ProtectBranches(default_repo_name()))
+            .await;
+    }
+
+    pub async fn branch_rewritten(
+        &mut self,
+        branch: &str,
+        default_branch: bool,
+        rewrite: BranchRewrite,
+    ) {
+        self.event(BorsEvent::BranchRewritten(BranchRewritten {
+            repository: default_repo_name(),
+            branch: branch.to_string(),
+            default_branch,
+            rewrite,
+        }))
+        .await;
+    }
+
+    pub async fn pull_request_labeled(&mut self, pr_number: u64, label: &str) {
+        self.event(BorsEvent::PullRequestLabeled(PullRequestLabelChanged {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+            label: label.to_string(),
+        }))
+        .await;
+    }
+
+    pub async fn pull_request_unlabeled(&mut self, pr_number: u64, label: &str) {
+        self.event(BorsEvent::PullRequestUnlabeled(PullRequestLabelChanged {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+            label: label.to_string(),
+        }))
+        .await;
+    }
+
+    pub async fn pull_request_closed(&mut self, pr_number: u64) {
+        self.event(BorsEvent::PullRequestClosed(PullRequestClosed {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+            merged: false,
+            base_branch: "main-branch".to_string(),
+        }))
+        .await;
+    }
+
+    pub async fn pull_request_merged(&mut self, pr_number: u64, base_branch: &str) {
+        self.event(BorsEvent::PullRequestClosed(PullRequestClosed {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+            merged: true,
+            base_branch: base_branch.to_string(),
+        }))
+        .await;
+    }
+
+    pub async fn converted_to_draft(&mut self, pr_number: u64) {
+        self.event(BorsEvent::PullRequestConvertedToDraft(PullRequestChanged {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+        }))
+        .await;
+    }
+
+    pub async fn force_dequeue(&mut self, pr_number: u64) {
+        self.event(BorsEvent::ForceDequeue(PullRequestChanged {
+            repository: default_repo_name(),
+            pr_number: pr_number.into(),
+        }))
+        .await;
+    }
+
     pub async fn refresh(&mut self) {
         self.event(BorsEvent::Refresh).await;
     }
 
+    pub async fn reconcile(&mut self) {
+        self.event(BorsEvent::Reconcile).await;
+    }
+
+    pub async fn sweep(&mut self) {
+        self.event(BorsEvent::Sweep).await;
+    }
+
     pub async fn perform_workflow_events(
         &mut self,
         run_id: u64,
@@ -154,6 +282,26 @@ impl BorsState<TestRepositoryClient> for TestBorsState {
     }
 }
 
+impl ShardedState<TestRepositoryClient> for TestBorsState {
+    fn repository_names(&self) -> Vec<GithubRepoName> {
+        self.repos.keys().cloned().collect()
+    }
+
+    fn take_repository(
+        &mut self,
+        repo: &GithubRepoName,
+    ) -> Option<RepoShard<TestRepositoryClient>> {
+        self.repos
+            .remove(repo)
+            .map(|repo| RepoShard::new(repo, Box::new(self.db.clone())))
+    }
+
+    fn return_repository(&mut self, shard: RepoShard<TestRepositoryClient>) {
+        self.repos
+            .insert(shard.repository().clone(), shard.into_repository_state());
+    }
+}
+
 #[derive(Builder)]
 #[builder(pattern = "owned")]
 pub struct RepoConfig {
@@ -161,6 +309,66 @@ pub struct RepoConfig {
     timeout: Duration,
     #[builder(field(type = "HashMap<LabelTrigger, Vec<LabelModification>>"))]
     labels: HashMap<LabelTrigger, Vec<LabelModification>>,
+    #[builder(default)]
+    outdated_comments: OutdatedCommentsMode,
+    #[builder(default)]
+    summary_comment: bool,
+    #[builder(default)]
+    summary_comment_delay: Duration,
+    #[builder(default)]
+    try_trigger: TryTrigger,
+    #[builder(default)]
+    drafts: DraftPolicy,
+    #[builder(default)]
+    landing: LandingMode,
+    #[builder(default)]
+    required_labels: Vec<String>,
+    #[builder(default)]
+    blocking_labels: Vec<String>,
+    #[builder(default)]
+    approved_failure_label: Option<String>,
+    #[builder(default)]
+    limits: PullRequestLimits,
+    #[builder(default)]
+    agreement: Option<AgreementConfig>,
+    #[builder(default)]
+    require_sign_off: bool,
+    #[builder(default)]
+    require_verified_commits: bool,
+    #[builder(default)]
+    review_approvals: bool,
+    #[builder(default)]
+    post_merge: PostMergeConfig,
+    #[builder(default)]
+    branch_protection: BranchProtectionConfig,
+    #[builder(default)]
+    log_excerpts: Option<LogExcerptsConfig>,
+    #[builder(default)]
+    operator_issue: Option<u64>,
+    #[builder(default)]
+    command_prefix: Option<String>,
+    #[builder(default)]
+    ci: CiConfig,
+    #[builder(default)]
+    retry: RetryPolicy,
+    #[builder(default)]
+    messages: MessageTemplates,
+    #[builder(default)]
+    features: FeatureToggles,
+    #[builder(default)]
+    notifications: NotificationsConfig,
+    #[builder(default)]
+    digest: Option<DigestConfig>,
+    #[builder(default)]
+    stale_reminders: StaleRemindersConfig,
+    #[builder(default)]
+    verbosity: Verbosity,
+    #[builder(default)]
+    tree: TreeConfig,
+    #[builder(default)]
+    dry_run: bool,
+    #[builder(default = "CURRENT_SCHEMA_VERSION")]
+    schema_version: u32,
 }
 
 impl RepoConfigBuilder {
@@ -181,8 +389,74 @@ impl RepoConfigBuilder {
     }
 
     pub fn create(self) -> RepositoryConfig {
-        let RepoConfig { timeout, labels } = self.build().unwrap();
-        RepositoryConfig { timeout, labels }
+        let RepoConfig {
+            timeout,
+            labels,
+            outdated_comments,
+            summary_comment,
+            summary_comment_delay,
+            try_trigger,
+            drafts,
+            landing,
+            required_labels,
+            blocking_labels,
+            approved_failure_label,
+            limits,
+            agreement,
+            require_sign_off,
+            require_verified_commits,
+            review_approvals,
+            post_merge,
+            branch_protection,
+            log_excerpts,
+            operator_issue,
+            command_prefix,
+            ci,
+            retry,
+            messages,
+            features,
+            notifications,
+            digest,
+            stale_reminders,
+            verbosity,
+            tree,
+            dry_run,
+            schema_version,
+        } = self.build().unwrap();
+        RepositoryConfig {
+            timeout,
+            labels,
+            outdated_comments,
+            summary_comment,
+            summary_comment_delay,
+            try_trigger,
+            drafts,
+            landing,
+            required_labels,
+            blocking_labels,
+            approved_failure_label,
+            limits,
+            agreement,
+            require_sign_off,
+            require_verified_commits,
+            review_approvals,
+            post_merge,
+            branch_protection,
+            log_excerpts,
+            operator_issue,
+            command_prefix,
+            ci,
+            retry,
+            messages,
+            features,
+            notifications,
+            digest,
+            stale_reminders,
+            verbosity,
+            tree,
+            dry_run,
+            schema_version,
+        }
     }
 }
 
@@ -194,6 +468,10 @@ pub struct Client {
     #[builder(default = "Box::new(AllPermissions)")]
     permission_resolver: Box<dyn PermissionResolver>,
     #[builder(default)]
+    agreement_checker: Option<Box<dyn AgreementChecker>>,
+    #[builder(default)]
+    notifications: Vec<NotificationRoute>,
+    #[builder(default)]
     config: RepoConfigBuilder,
 }
 
@@ -205,6 +483,8 @@ impl ClientBuilder {
         let Client {
             name,
             permission_resolver,
+            agreement_checker,
+            notifications,
             config,
         } = self.build().unwrap();
 
@@ -213,17 +493,39 @@ impl ClientBuilder {
             repository: name.clone(),
             client: TestRepositoryClient {
                 comments: Default::default(),
+                comment_ids: Default::default(),
+                minimized_comments: Default::default(),
                 name,
                 merge_branches_fn: Box::new(|| Ok(CommitSha(default_merge_sha()))),
+                set_branch_fn: Box::new(|_| Ok(())),
                 get_pr_fn: Box::new(move |pr| Ok(PRBuilder::default().number(pr.0).create())),
+                pr_files: Default::default(),
+                pr_commits: Default::default(),
                 check_suites: Default::default(),
                 cancelled_workflows: Default::default(),
                 added_labels: Default::default(),
                 removed_labels: Default::default(),
                 branch_history: Default::default(),
+                deleted_branches: Default::default(),
+                dispatched_workflows: Default::default(),
+                workflow_run_details: Default::default(),
+                job_logs: Default::default(),
+                milestones: Default::default(),
+                pr_milestones: Default::default(),
+                merge_queue: Default::default(),
+                protected_branches: Default::default(),
+                files: Default::default(),
+                commit_files: Default::default(),
+                commit_comments: Default::default(),
+                unverified_commits: Default::default(),
+                check_runs: Default::default(),
+                check_annotations: Default::default(),
             },
             permissions_resolver: permission_resolver,
+            agreement_checker,
+            notifications,
             config: config.create(),
+            deferred_updates: Default::default(),
         }
     }
 }
@@ -235,21 +537,94 @@ impl RepositoryState<TestRepositoryClient> {
         TestBorsState {
             repos,
             db: create_test_db().await,
+            metrics: Arc::default(),
+            ci: Arc::default(),
+            consistency: Arc::default(),
         }
     }
 }
 
+/// CI service registered as `buildkite`, which records the started builds.
+#[derive(Default)]
+pub struct TestCiService {
+    // (pipeline, commit SHA) of each started build
+    pub builds: Mutex<Vec<(String, String)>>,
+    // Results of finished builds returned when they are polled, by their URL
+    pub results: Mutex<HashMap<String, WorkflowStatus>>,
+}
+
+#[async_trait]
+impl CiService for TestCiService {
+    fn name(&self) -> &'static str {
+        "buildkite"
+    }
+
+    async fn start_build(
+        &self,
+        pipeline: &str,
+        request: &CiBuildRequest<'_>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        let mut builds = self.builds.lock().unwrap();
+        builds.push((pipeline.to_string(), request.commit_sha.to_string()));
+        let run_id = 2000 + builds.len() as u64;
+        Ok(DispatchedWorkflow {
+            run_id: RunId(run_id),
+            url: format!("https://buildkite.com/{pipeline}/builds/{run_id}"),
+        })
+    }
+
+    async fn poll_build(
+        &self,
+        _pipeline: &str,
+        url: &str,
+    ) -> anyhow::Result<Option<WorkflowStatus>> {
+        Ok(self.results.lock().unwrap().get(url).cloned())
+    }
+}
+
 pub struct TestRepositoryClient {
     pub name: GithubRepoName,
     comments: HashMap<u64, Vec<String>>,
+    comment_ids: HashMap<CommentId, (u64, usize)>,
+    minimized_comments: HashSet<(u64, usize)>,
     pub merge_branches_fn: Box<dyn Fn() -> Result<CommitSha, MergeError> + Send>,
+    pub set_branch_fn: Box<dyn Fn(&str) -> Result<(), BranchUpdateError> + Send>,
     pub get_pr_fn: Box<dyn Fn(PullRequestNumber) -> anyhow::Result<PullRequest> + Send>,
+    // PR number -> files modified by the PR
+    pub pr_files: HashMap<u64, Vec<String>>,
+    // PR number -> commits of the PR
+    pub pr_commits: HashMap<u64, Vec<Commit>>,
     pub check_suites: HashMap<String, Vec<CheckSuite>>,
     pub cancelled_workflows: HashSet<u64>,
     added_labels: HashMap<u64, Vec<String>>,
     removed_labels: HashMap<u64, Vec<String>>,
     // Branch name -> history of SHAs
     branch_history: HashMap<String, Vec<CommitSha>>,
+    deleted_branches: Vec<String>,
+    // (workflow, git ref, inputs) of each dispatched workflow run
+    pub dispatched_workflows: Vec<(String, String, HashMap<String, String>)>,
+    // Run ID -> failed jobs and artifacts of the run
+    pub workflow_run_details: HashMap<u64, WorkflowRunDetails>,
+    // Run ID -> logs of failed jobs of the run
+    pub job_logs: HashMap<u64, Vec<JobLog>>,
+    pub milestones: Vec<Milestone>,
+    // PR number -> milestone number
+    pr_milestones: HashMap<u64, u64>,
+    // PRs in the native merge queue of GitHub
+    pub merge_queue: Vec<u64>,
+    pub protected_branches: HashMap<String, BranchProtection>,
+    // Path -> content of files in the repository
+    pub files: HashMap<String, String>,
+    // (commit SHA, path) -> content of files that differ from `files` in the given commit
+    pub commit_files: HashMap<(String, String), String>,
+    // Commit SHA -> comments posted to the commit
+    pub commit_comments: HashMap<String, Vec<String>>,
+    // Commits without a verified signature, all other commits are verified
+    pub unverified_commits: HashSet<String>,
+    // (commit SHA, name, success, summary) of each created check run
+    pub check_runs: Vec<(String, String, bool, String)>,
+    /// Annotations of all created check runs.
+    pub check_annotations: Vec<CheckAnnotation>,
 }
 
 impl TestRepositoryClient {
@@ -282,6 +657,20 @@ impl TestRepositoryClient {
                 .collect::<Vec<_>>()
         );
     }
+    pub fn check_commit_comments(&self, sha: &str, comments: &[&str]) {
+        assert_eq!(
+            self.commit_comments.get(sha).cloned().unwrap_or_default(),
+            comments
+                .iter()
+                .map(|&s| String::from(s))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    pub fn check_merge_queue(&self, prs: &[u64]) {
+        assert_eq!(self.merge_queue, prs);
+    }
+
     pub fn check_comment_count(&self, pr_number: u64, count: usize) {
         assert_eq!(
             self.comments
@@ -293,6 +682,17 @@ impl TestRepositoryClient {
         );
     }
 
+    pub fn check_minimized_comments(&self, pr_number: u64, indices: &[usize]) {
+        let mut minimized = self
+            .minimized_comments
+            .iter()
+            .filter(|(pr, _)| *pr == pr_number)
+            .map(|(_, index)| *index)
+            .collect::<Vec<_>>();
+        minimized.sort();
+        assert_eq!(minimized, indices);
+    }
+
     pub fn check_added_labels(&self, pr: u64, added: &[&str]) -> &Self {
         assert_eq!(self.added_labels[&pr], added);
         self
@@ -320,6 +720,14 @@ impl TestRepositoryClient {
         );
     }
 
+    pub fn check_milestone(&self, pr_number: u64, milestone: Option<u64>) {
+        assert_eq!(self.pr_milestones.get(&pr_number).copied(), milestone);
+    }
+
+    pub fn check_deleted_branches(&self, branches: &[&str]) {
+        assert_eq!(self.deleted_branches, branches);
+    }
+
     fn add_branch_sha(&mut self, branch: &str, sha: &str) {
         self.branch_history
             .entry(branch.to_string())
@@ -338,19 +746,116 @@ impl RepositoryClient for TestRepositoryClient {
         (self.get_pr_fn)(pr)
     }
 
-    async fn post_comment(&mut self, pr: PullRequestNumber, text: &str) -> anyhow::Result<()> {
-        self.comments
-            .entry(pr.0)
+    async fn get_pull_request_files(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<String>> {
+        Ok(self.pr_files.get(&pr.0).cloned().unwrap_or_default())
+    }
+
+    async fn get_pull_request_commits(
+        &mut self,
+        pr: PullRequestNumber,
+    ) -> anyhow::Result<Vec<Commit>> {
+        Ok(self.pr_commits.get(&pr.0).cloned().unwrap_or_default())
+    }
+
+    async fn is_commit_verified(&mut self, sha: &CommitSha) -> anyhow::Result<bool> {
+        Ok(!self.unverified_commits.contains(&sha.0))
+    }
+
+    async fn post_comment(
+        &mut self,
+        pr: PullRequestNumber,
+        text: &str,
+    ) -> anyhow::Result<CommentId> {
+        let comments = self.comments.entry(pr.0).or_default();
+        comments.push(text.to_string());
+
+        let index = comments.len() - 1;
+        let id = CommentId(format!("comment-{}-{index}", pr.0));
+        self.comment_ids.insert(id.clone(), (pr.0, index));
+        Ok(id)
+    }
+
+    async fn edit_comment(&mut self, comment: &CommentId, text: &str) -> anyhow::Result<()> {
+        let (pr, index) = self.comment_ids[comment];
+        self.comments.get_mut(&pr).unwrap()[index] = text.to_string();
+        Ok(())
+    }
+
+    async fn minimize_comment(&mut self, comment: &CommentId) -> anyhow::Result<()> {
+        self.minimized_comments.insert(self.comment_ids[comment]);
+        Ok(())
+    }
+
+    async fn set_branch_to_sha(
+        &mut self,
+        branch: &str,
+        sha: &CommitSha,
+    ) -> Result<(), BranchUpdateError> {
+        (self.set_branch_fn)(branch)?;
+        self.add_branch_sha(branch, &sha.0);
+        Ok(())
+    }
+
+    async fn delete_branch(&mut self, branch: &str) -> anyhow::Result<()> {
+        self.deleted_branches.push(branch.to_string());
+        Ok(())
+    }
+
+    async fn post_commit_comment(&mut self, sha: &CommitSha, text: &str) -> anyhow::Result<()> {
+        self.commit_comments
+            .entry(sha.0.clone())
             .or_default()
             .push(text.to_string());
         Ok(())
     }
 
-    async fn set_branch_to_sha(&mut self, branch: &str, sha: &CommitSha) -> anyhow::Result<()> {
-        self.add_branch_sha(branch, &sha.0);
+    async fn create_check_run(
+        &mut self,
+        sha: &CommitSha,
+        name: &str,
+        success: bool,
+        summary: &str,
+        annotations: &[CheckAnnotation],
+    ) -> anyhow::Result<()> {
+        self.check_runs.push((
+            sha.0.clone(),
+            name.to_string(),
+            success,
+            summary.to_string(),
+        ));
+        self.check_annotations.extend_from_slice(annotations);
         Ok(())
     }
 
+    async fn get_file_content(
+        &mut self,
+        sha: &CommitSha,
+        path: &str,
+    ) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .commit_files
+            .get(&(sha.0.clone(), path.to_string()))
+            .or_else(|| self.files.get(path))
+            .cloned())
+    }
+
+    async fn get_branch_sha(&mut self, branch: &str) -> anyhow::Result<Option<CommitSha>> {
+        if self
+            .deleted_branches
+            .iter()
+            .any(|deleted| deleted == branch)
+        {
+            return Ok(None);
+        }
+        Ok(self
+            .branch_history
+            .get(branch)
+            .and_then(|history| history.last().cloned()))
+    }
+
     async fn merge_branches(
         &mut self,
         base: &str,
@@ -372,12 +877,46 @@ impl RepositoryClient for TestRepositoryClient {
         Ok(self.check_suites.get(&sha.0).cloned().unwrap_or_default())
     }
 
+    async fn get_workflow_run_details(
+        &mut self,
+        run_id: RunId,
+    ) -> anyhow::Result<WorkflowRunDetails> {
+        Ok(self
+            .workflow_run_details
+            .get(&run_id.0)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_failed_job_logs(
+        &mut self,
+        run_id: RunId,
+        _max_size: usize,
+    ) -> anyhow::Result<Vec<JobLog>> {
+        Ok(self.job_logs.get(&run_id.0).cloned().unwrap_or_default())
+    }
+
     async fn cancel_workflows(&mut self, run_ids: Vec<RunId>) -> anyhow::Result<()> {
         self.cancelled_workflows
             .extend(run_ids.into_iter().map(|id| id.0));
         Ok(())
     }
 
+    async fn dispatch_workflow(
+        &mut self,
+        workflow: &str,
+        git_ref: &str,
+        inputs: &HashMap<String, String>,
+    ) -> anyhow::Result<DispatchedWorkflow> {
+        self.dispatched_workflows
+            .push((workflow.to_string(), git_ref.to_string(), inputs.clone()));
+        let run_id = 1000 + self.dispatched_workflows.len() as u64;
+        Ok(DispatchedWorkflow {
+            run_id: RunId(run_id),
+            url: format!("https://{workflow}-{run_id}.com"),
+        })
+    }
+
     async fn add_labels(&mut self, pr: PullRequestNumber, labels: &[String]) -> anyhow::Result<()> {
         self.added_labels
             .entry(pr.0)
@@ -397,4 +936,35 @@ impl RepositoryClient for TestRepositoryClient {
             .extend(labels.to_vec());
         Ok(())
     }
+
+    async fn add_to_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()> {
+        if !self.merge_queue.contains(&pr.0) {
+            self.merge_queue.push(pr.0);
+        }
+        Ok(())
+    }
+
+    async fn remove_from_merge_queue(&mut self, pr: PullRequestNumber) -> anyhow::Result<()> {
+        self.merge_queue.retain(|queued| *queued != pr.0);
+        Ok(())
+    }
+
+    async fn protect_branch(
+        &mut self,
+        branch: &str,
+        protection: &BranchProtection,
+    ) -> anyhow::Result<()> {
+        self.protected_branches
+            .insert(branch.to_string(), protection.clone());
+        Ok(())
+    }
+
+    async fn get_open_milestones(&mut self) -> anyhow::Result<Vec<Milestone>> {
+        Ok(self.milestones.clone())
+    }
+
+    async fn set_milestone(&mut self, pr: PullRequestNumber, milestone: u64) -> anyhow::Result<()> {
+        self.pr_milestones.insert(pr.0, milestone);
+        Ok(())
+    }
 }
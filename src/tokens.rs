@@ -0,0 +1,290 @@
+//! API tokens, which let integrations access the API of the bot without sharing the credentials of
+//! the GitHub App. Each token has a set of scopes that limit what it can be used for.
+//!
+//! Tokens are created by an administrator using the command line (`--create-api-token`) and they
+//! can be rotated using `borsctl rotate-token`. The bot only stores a hash of each token, so its
+//! value is shown only when it is created.
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::database::{ApiTokenModel, DbClient, PrimaryKey, TokenScope};
+use crate::github::GithubRepoName;
+
+/// Prefix of tokens created by the bot, which makes them easier to find e.g. by secret scanners.
+const TOKEN_PREFIX: &str = "bors_";
+
+/// Result of checking a token presented by a request.
+#[derive(Debug, PartialEq)]
+pub enum TokenCheck {
+    /// The token is valid and it has the required scope.
+    Allowed,
+    /// The token is valid, but it does not have the required scope.
+    MissingScope,
+    /// The token is valid, but it cannot be used in the repository.
+    ForbiddenRepository,
+    /// The token is unknown, expired or revoked.
+    Invalid,
+}
+
+/// Creates a new token with the given scopes, which can be used only in the given repositories
+/// (`owner/name`), or in all repositories if there are none. Returns its ID and its value.
+pub async fn create_token(
+    db: &(dyn DbClient + Send + Sync),
+    name: &str,
+    scopes: &[TokenScope],
+    repositories: &[String],
+    expires_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<(PrimaryKey, String)> {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = format!("{TOKEN_PREFIX}{}", hex::encode(bytes));
+    let id = db
+        .create_api_token(name, &hash_token(&token), scopes, repositories, expires_at)
+        .await?;
+    Ok((id, token))
+}
+
+/// Replaces a token with a new token that has the same name, scopes and repositories, and the same
+/// lifetime starting `now`. The replaced token is revoked. Returns the ID and the value of the new
+/// token.
+pub async fn rotate_token(
+    db: &(dyn DbClient + Send + Sync),
+    id: PrimaryKey,
+    now: DateTime<Utc>,
+) -> anyhow::Result<(PrimaryKey, String)> {
+    let token = db
+        .get_api_tokens()
+        .await?
+        .into_iter()
+        .find(|token| token.id == id && token.revoked_at.is_none())
+        .ok_or_else(|| anyhow::anyhow!("API token {id} does not exist or it has been revoked"))?;
+    let expires_at = token
+        .expires_at
+        .map(|expires_at| now + (expires_at - token.created_at));
+    let rotated = create_token(
+        db,
+        &token.name,
+        &token.scopes,
+        &token.repositories,
+        expires_at,
+    )
+    .await?;
+    db.revoke_api_token(id).await?;
+    Ok(rotated)
+}
+
+/// Checks whether the token can be used for an action that requires the given scope, performed in
+/// the given repository (`None` if the action does not belong to a repository).
+pub async fn check_token(
+    db: &(dyn DbClient + Send + Sync),
+    token: &str,
+    scope: TokenScope,
+    repo: Option<&GithubRepoName>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<TokenCheck> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(TokenCheck::Invalid);
+    }
+    let Some(model) = db.find_api_token(&hash_token(token)).await? else {
+        return Ok(TokenCheck::Invalid);
+    };
+    Ok(check_model(&model, scope, repo, now))
+}
+
+/// Parses a comma-separated list of scopes, e.g. `read,admin`.
+pub fn parse_scopes(scopes: &str) -> anyhow::Result<Vec<TokenScope>> {
+    scopes
+        .split(',')
+        .map(|scope| {
+            TokenScope::parse(scope.trim()).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown token scope `{scope}`, expected `read`, `admin` or `report`"
+                )
+            })
+        })
+        .collect()
+}
+
+fn check_model(
+    model: &ApiTokenModel,
+    scope: TokenScope,
+    repo: Option<&GithubRepoName>,
+    now: DateTime<Utc>,
+) -> TokenCheck {
+    if model.revoked_at.is_some() || model.expires_at.map_or(false, |expires| expires <= now) {
+        return TokenCheck::Invalid;
+    }
+    if !model.scopes.contains(&scope) {
+        return TokenCheck::MissingScope;
+    }
+    let repo_allowed = model.repositories.is_empty()
+        || repo.map_or(false, |repo| {
+            let repo = repo.to_string();
+            model
+                .repositories
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&repo))
+        });
+    if repo_allowed {
+        TokenCheck::Allowed
+    } else {
+        TokenCheck::ForbiddenRepository
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::database::{DbClient, TokenScope};
+    use crate::github::GithubRepoName;
+    use crate::tests::database::create_test_db;
+    use crate::tokens::{check_token, create_token, parse_scopes, rotate_token, TokenCheck};
+
+    #[tokio::test]
+    async fn token_scopes() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+        let (_, token) = create_token(&db, "ci", &[TokenScope::Read], &[], None)
+            .await
+            .unwrap();
+        assert!(token.starts_with("bors_"));
+        assert_eq!(
+            check_token(&db, &token, TokenScope::Read, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::Allowed
+        );
+        assert_eq!(
+            check_token(&db, &token, TokenScope::Admin, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::MissingScope
+        );
+        assert_eq!(
+            check_token(&db, "bors_unknown", TokenScope::Read, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::Invalid
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_and_revoked_tokens() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+        let (_, expiring) = create_token(
+            &db,
+            "expiring",
+            &[TokenScope::Read],
+            &[],
+            Some(now + Duration::days(1)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            check_token(
+                &db,
+                &expiring,
+                TokenScope::Read,
+                None,
+                now + Duration::days(2)
+            )
+            .await
+            .unwrap(),
+            TokenCheck::Invalid
+        );
+
+        let (id, revoked) = create_token(&db, "revoked", &[TokenScope::Read], &[], None)
+            .await
+            .unwrap();
+        assert!(db.revoke_api_token(id).await.unwrap());
+        assert_eq!(
+            check_token(&db, &revoked, TokenScope::Read, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::Invalid
+        );
+        assert_eq!(db.get_api_tokens().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rotate_tokens() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+        let (id, old) = create_token(&db, "ci", &[TokenScope::Admin], &[], None)
+            .await
+            .unwrap();
+        let (new_id, new) = rotate_token(&db, id, now).await.unwrap();
+        assert_ne!(id, new_id);
+        assert_eq!(
+            check_token(&db, &old, TokenScope::Admin, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::Invalid
+        );
+        assert_eq!(
+            check_token(&db, &new, TokenScope::Admin, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::Allowed
+        );
+
+        // A revoked token cannot be rotated again
+        assert!(rotate_token(&db, id, now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn repository_tokens() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+        let repo = GithubRepoName::new("owner", "name");
+        let (_, token) = create_token(
+            &db,
+            "lab",
+            &[TokenScope::Report],
+            &["owner/name".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            check_token(&db, &token, TokenScope::Report, Some(&repo), now)
+                .await
+                .unwrap(),
+            TokenCheck::Allowed
+        );
+        assert_eq!(
+            check_token(
+                &db,
+                &token,
+                TokenScope::Report,
+                Some(&GithubRepoName::new("owner", "other")),
+                now
+            )
+            .await
+            .unwrap(),
+            TokenCheck::ForbiddenRepository
+        );
+        assert_eq!(
+            check_token(&db, &token, TokenScope::Report, None, now)
+                .await
+                .unwrap(),
+            TokenCheck::ForbiddenRepository
+        );
+    }
+
+    #[test]
+    fn parse_scope_list() {
+        assert_eq!(
+            parse_scopes("read, admin").unwrap(),
+            vec![TokenScope::Read, TokenScope::Admin]
+        );
+        assert!(parse_scopes("write").is_err());
+    }
+}
@@ -1,5 +1,10 @@
-use anyhow::Error;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::Mutex;
+
+use anyhow::{Context, Error};
 use tracing::span::Span;
+use tracing_subscriber::{reload, EnvFilter, Registry};
 
 pub trait LogError {
     fn log_error(&self, error: Error);
@@ -12,3 +17,101 @@ impl LogError for Span {
         });
     }
 }
+
+tokio::task_local! {
+    static CORRELATION_ID: CorrelationId;
+}
+
+/// Identifies everything that the bot does because of a single event. It is a field of the span of
+/// the event, so all log records of the event (including nested PR, build and API call spans) can
+/// be found by searching for it, and it is included in error comments so that users can refer to
+/// it when reporting a problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// Returns the ID of the event that is being handled by the current task, if any.
+    pub fn current() -> Option<Self> {
+        CORRELATION_ID.try_with(|id| *id).ok()
+    }
+
+    /// Runs `future` as a part of handling the event identified by this ID.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CORRELATION_ID.scope(self, future).await
+    }
+}
+
+impl Display for CorrelationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Filter of the log output that can be changed while the bot is running, e.g. to enable debug
+/// logs of a misbehaving repository without restarting the bot and losing its in-memory state.
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: Mutex<String>,
+}
+
+impl LogFilter {
+    /// Creates a filter with the given directives (in the `RUST_LOG` syntax). The returned layer
+    /// has to be used as the filter of the log output.
+    pub fn new(directives: &str) -> anyhow::Result<(Self, reload::Layer<EnvFilter, Registry>)> {
+        let (layer, handle) = reload::Layer::new(parse_directives(directives)?);
+        let filter = Self {
+            handle,
+            directives: Mutex::new(directives.to_string()),
+        };
+        Ok((filter, layer))
+    }
+
+    /// Returns the current directives of the filter.
+    pub fn directives(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    /// Replaces the directives of the filter.
+    pub fn set_directives(&self, directives: &str) -> anyhow::Result<()> {
+        let filter = parse_directives(directives)?;
+        let mut current = self.directives.lock().unwrap();
+        self.handle
+            .reload(filter)
+            .context("Cannot change the log filter")?;
+        tracing::info!("Log filter changed from `{current}` to `{directives}`");
+        *current = directives.to_string();
+        Ok(())
+    }
+}
+
+fn parse_directives(directives: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::try_new(directives)
+        .with_context(|| format!("Invalid log filter directives `{directives}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::logging::{CorrelationId, LogFilter};
+
+    #[tokio::test]
+    async fn current_correlation_id() {
+        assert_eq!(CorrelationId::current(), None);
+        let id = CorrelationId::generate();
+        assert_eq!(id.scope(async { CorrelationId::current() }).await, Some(id));
+        assert_eq!(id.to_string().len(), 16);
+    }
+
+    #[test]
+    fn change_log_filter() {
+        let (filter, _layer) = LogFilter::new("info").unwrap();
+        filter.set_directives("info,bors::github=debug").unwrap();
+        assert_eq!(filter.directives(), "info,bors::github=debug");
+
+        assert!(filter.set_directives("bors=loud").is_err());
+        assert_eq!(filter.directives(), "info,bors::github=debug");
+    }
+}
@@ -0,0 +1,103 @@
+//! A cache with a bounded number of entries, which evicts the least recently used entry when it
+//! is full, so that caches of long running bots do not grow with the number of PRs and users.
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::metrics::CACHES;
+
+/// Least recently used cache. Hits, misses and evictions are counted in [`CACHES`] under the
+/// name of the cache.
+pub struct LruCache<K, V> {
+    name: &'static str,
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by the time of their last use, the first one is evicted first.
+    usage: BTreeMap<u64, K>,
+    counter: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            counter: 0,
+        }
+    }
+
+    /// Returns the value of the given key and marks it as recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some((_, used_at)) = self.entries.get(key) else {
+            CACHES.record_miss(self.name);
+            return None;
+        };
+        CACHES.record_hit(self.name);
+        let used_at = *used_at;
+        let key = self.usage.remove(&used_at)?;
+        self.counter += 1;
+        let (value, used_at) = self.entries.get_mut(&key)?;
+        *used_at = self.counter;
+        self.usage.insert(self.counter, key);
+        Some(&*value)
+    }
+
+    /// Stores the value of the given key. If the cache is full, the least recently used entry is
+    /// evicted.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.counter += 1;
+        if let Some((_, used_at)) = self.entries.insert(key.clone(), (value, self.counter)) {
+            self.usage.remove(&used_at);
+        } else if self.entries.len() > self.capacity {
+            let oldest = self.usage.keys().next().copied();
+            if let Some(oldest) = oldest.and_then(|used_at| self.usage.remove(&used_at)) {
+                self.entries.remove(&oldest);
+                CACHES.record_eviction(self.name);
+            }
+        }
+        self.usage.insert(self.counter, key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::CACHES;
+    use crate::utils::lru::LruCache;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = LruCache::new("test_lru", 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(&1));
+
+        // `b` has not been used since `a` was read
+        cache.insert("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.get("c"), Some(&3));
+
+        // Replacing a value does not evict anything
+        cache.insert("c", 4);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.get("c"), Some(&4));
+
+        let stats = CACHES.stats("test_lru");
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (5, 1, 1));
+    }
+}